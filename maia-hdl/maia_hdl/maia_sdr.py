@@ -25,7 +25,7 @@ from .recorder import Recorder16IQ, RecorderMode
 from .spectrometer import Spectrometer
 
 # IP core version
-_version = '0.6.1'
+_version = '0.7.0'
 
 
 class MaiaSDR(Elaboratable):
@@ -47,6 +47,10 @@ class MaiaSDR(Elaboratable):
         self.clk2x = ClockDomain()
         self.clk3x = ClockDomain()
 
+        # Width of the sample counter. At 61.44 Msps it wraps around after
+        # more than 50 days.
+        self.sample_counter_width = 48
+
         self.axi4lite = Axi4LiteRegisterBridge(
             self.axi4_awidth, name='s_axi_lite')
         self.control_registers = Registers(
@@ -77,18 +81,30 @@ class MaiaSDR(Elaboratable):
         self.recorder_registers = Registers(
             'recorder',
             {
-                0b0: Register('recorder_control', [
+                0b00: Register('recorder_control', [
                     Field('start', Access.Wpulse, 1, 0),
                     Field('stop', Access.Wpulse, 1, 0),
                     Field('mode', Access.RW,
                           Shape.cast(RecorderMode).width, 0),
                     Field('dropped_samples', Access.R, 1, 0),
+                    Field('arm_trigger', Access.Wpulse, 1, 0),
+                    Field('disarm', Access.Wpulse, 1, 0),
+                    Field('armed', Access.R, 1, 0),
+                    Field('trigger_polarity', Access.RW, 1, 0),
+                    Field('trigger_stop', Access.RW, 1, 0),
                 ]),
-                0b1: Register('recorder_next_address', [
+                0b01: Register('recorder_next_address', [
                     Field('next_address', Access.R, 32, 0),
                 ]),
+                0b10: Register('recorder_start_sample_lo', [
+                    Field('start_sample_lo', Access.R, 32, 0),
+                ]),
+                0b11: Register('recorder_start_sample_hi', [
+                    Field('start_sample_hi', Access.R,
+                          self.sample_counter_width - 32, 0),
+                ]),
             },
-            1)
+            2)
         self.spectrometer = Spectrometer(
             config.spectrometer_address,
             config.spectrometer_buffers.bit_length() - 1,
@@ -202,6 +218,26 @@ class MaiaSDR(Elaboratable):
                               1,
                               0),
                     ]),
+                0b110: Register(
+                    'sample_counter_lo',
+                    [
+                        Field('counter_lo',
+                              Access.R,
+                              32,
+                              0),
+                    ]),
+                0b111: Register(
+                    'sample_counter_hi',
+                    [
+                        Field('counter_hi',
+                              Access.R,
+                              self.sample_counter_width - 32,
+                              0),
+                        Field('snapshot', Access.Wpulse, 1, 0),
+                        Field('pps_enable', Access.RW, 1, 0),
+                        Field('latch_count', Access.R, 4, 0),
+                        Field('latch_pps', Access.R, 1, 0),
+                    ]),
             }, 3)
         metadata = {
             'vendor': 'Daniel Estevez',
@@ -222,6 +258,8 @@ class MaiaSDR(Elaboratable):
         self.iq_in_width = 12
         self.re_in = Signal(self.iq_in_width)
         self.im_in = Signal(self.iq_in_width)
+        self.pps_in = Signal()
+        self.trigger_in = Signal()
         self.interrupt_out = Signal()
 
     def ports(self):
@@ -232,6 +270,8 @@ class MaiaSDR(Elaboratable):
             + [
                 self.re_in,
                 self.im_in,
+                self.pps_in,
+                self.trigger_in,
                 self.interrupt_out,
                 self.s_axi_lite.clk,
                 self.s_axi_lite.rst,
@@ -282,11 +322,25 @@ class MaiaSDR(Elaboratable):
         m.d.comb += [rxiq_cdc.re_in.eq(self.re_in),
                      rxiq_cdc.im_in.eq(self.im_in)]
 
+        # Sample counter (sync domain)
+        #
+        # This counts the RX IQ samples produced by the ADC since the SDR
+        # reset was removed.
+        sample_counter = Signal(self.sample_counter_width)
+        with m.If(rxiq_cdc.strobe_out):
+            m.d.sync += sample_counter.eq(sample_counter + 1)
+
         # Spectrometer (sync domain)
         spectrometer_re_in = Signal(
             self.spectrometer.width_in, reset_less=True)
         spectrometer_im_in = Signal(
             self.spectrometer.width_in, reset_less=True)
+        # Value of the sample counter corresponding to the spectrometer
+        # input. When the DDC output is used, this does not take into account
+        # the group delay of the DDC.
+        spectrometer_sample_counter = Signal(
+            self.sample_counter_width, reset_less=True)
+        m.d.sync += spectrometer_sample_counter.eq(sample_counter)
         assert len(spectrometer_re_in) == len(self.ddc.re_out)
         assert len(spectrometer_im_in) == len(self.ddc.im_out)
         spectrometer_strobe_in = Signal()
@@ -322,6 +376,45 @@ class MaiaSDR(Elaboratable):
                 self.spectrometer.last_buffer),
         ]
 
+        # Recorder trigger (s_axi_lite domain)
+        #
+        # When the recorder is armed, the recording is started on the active
+        # edge of the trigger input, which is the rising edge if
+        # trigger_polarity is 0 or the falling edge if trigger_polarity is
+        # 1. If trigger_stop is set, a running recording is stopped on the
+        # active edge of the trigger input.
+        trigger = Signal()
+        trigger_q = Signal()
+        m.submodules.sync_trigger = FFSynchronizer(
+            self.trigger_in, trigger, o_domain='s_axi_lite')
+        m.d.s_axi_lite += trigger_q.eq(trigger)
+        recorder_control = self.recorder_registers['recorder_control']
+        trigger_edge = Mux(recorder_control['trigger_polarity'],
+                           ~trigger & trigger_q,
+                           trigger & ~trigger_q)
+        recorder_armed = Signal()
+        trigger_start = recorder_armed & trigger_edge
+        with m.If(recorder_control['arm_trigger']):
+            m.d.s_axi_lite += recorder_armed.eq(1)
+        with m.If(recorder_control['disarm'] | trigger_start):
+            m.d.s_axi_lite += recorder_armed.eq(0)
+        m.d.comb += recorder_control['armed'].eq(recorder_armed)
+        # The recorder stop must only be pulsed while the recorder is running,
+        # and the trigger only pulses it once per recording.
+        recorder_running = Signal()
+        recorder_stopping = Signal()
+        with m.If(self.recorder.start):
+            m.d.s_axi_lite += [
+                recorder_running.eq(1),
+                recorder_stopping.eq(0),
+            ]
+        with m.If(self.recorder.stop):
+            m.d.s_axi_lite += recorder_stopping.eq(1)
+        with m.If(self.recorder.finished):
+            m.d.s_axi_lite += recorder_running.eq(0)
+        trigger_stop = (recorder_control['trigger_stop'] & recorder_running
+                        & ~recorder_stopping & trigger_edge)
+
         # Recorder
         m.d.comb += [
             # sync domain
@@ -332,15 +425,76 @@ class MaiaSDR(Elaboratable):
             self.recorder.mode.eq(
                 self.recorder_registers['recorder_control']['mode']),
             self.recorder.start.eq(
-                self.recorder_registers['recorder_control']['start']),
+                self.recorder_registers['recorder_control']['start']
+                | trigger_start),
             self.recorder.stop.eq(
-                self.recorder_registers['recorder_control']['stop']),
+                self.recorder_registers['recorder_control']['stop']
+                | trigger_stop),
             self.recorder_registers['recorder_control']['dropped_samples'].eq(
                 self.recorder.dropped_samples),
             (self.recorder_registers['recorder_next_address']
              ['next_address'].eq(self.recorder.next_address)),
         ]
 
+        # Recorder start sample
+        #
+        # The value of the sample counter is latched in the sync domain when
+        # the first sample of the recording arrives to the recorder. The
+        # latched value is transferred to the s_axi_lite domain after the
+        # pulse that indicates that it has been updated crosses the clock
+        # domains. The latched value is stable by then.
+        recorder_start_sample = Signal(
+            self.sample_counter_width, reset_less=True)
+        with m.If(self.recorder.first_sample):
+            m.d.sync += recorder_start_sample.eq(spectrometer_sample_counter)
+        m.submodules.sync_recorder_start_sample = \
+            sync_recorder_start_sample = PulseSynchronizer(
+                i_domain='sync', o_domain='s_axi_lite')
+        recorder_start_sample_dest = Signal(
+            self.sample_counter_width, reset_less=True)
+        m.d.comb += sync_recorder_start_sample.i.eq(
+            self.recorder.first_sample)
+        with m.If(sync_recorder_start_sample.o):
+            m.d.s_axi_lite += recorder_start_sample_dest.eq(
+                recorder_start_sample)
+        m.d.comb += [
+            (self.recorder_registers['recorder_start_sample_lo']
+             ['start_sample_lo'].eq(recorder_start_sample_dest[:32])),
+            (self.recorder_registers['recorder_start_sample_hi']
+             ['start_sample_hi'].eq(recorder_start_sample_dest[32:])),
+        ]
+
+        # Sample counter latch (sync domain)
+        #
+        # The sample counter is latched when the snapshot field is written or
+        # on the rising edge of the PPS input (if enabled). The latch count
+        # is incremented each time that the counter is latched, so that the
+        # software can detect new values and check that the two halves of
+        # the latched value have been read consistently.
+        pps = Signal()
+        pps_q = Signal()
+        m.submodules.sync_pps = FFSynchronizer(
+            self.pps_in, pps, o_domain='sync')
+        m.d.sync += pps_q.eq(pps)
+        counter_hi_reg = self.sdr_registers['sample_counter_hi']
+        latch_pps = counter_hi_reg['pps_enable'] & pps & ~pps_q
+        counter_latch = Signal(self.sample_counter_width)
+        latch_count = Signal(len(counter_hi_reg['latch_count']))
+        latch_from_pps = Signal()
+        with m.If(counter_hi_reg['snapshot'] | latch_pps):
+            m.d.sync += [
+                counter_latch.eq(sample_counter),
+                latch_count.eq(latch_count + 1),
+                latch_from_pps.eq(latch_pps),
+            ]
+        m.d.comb += [
+            self.sdr_registers['sample_counter_lo']['counter_lo'].eq(
+                counter_latch[:32]),
+            counter_hi_reg['counter_hi'].eq(counter_latch[32:]),
+            counter_hi_reg['latch_count'].eq(latch_count),
+            counter_hi_reg['latch_pps'].eq(latch_from_pps),
+        ]
+
         # DDC
         m.d.comb += [
             self.ddc.common_edge.eq(common_edge_3x.common_edge),
@@ -59,6 +59,11 @@ class Recorder16IQ(Elaboratable):
         Input real part.
     im_in : Signal(16), in
         Input imaginary part.
+    first_sample : Signal(), out
+        This signal is asserted for one cycle of ``domain_in`` when the first
+        sample of the recording is presented at the input (together with
+        ``strobe_in``). It can be used to timestamp the start of the
+        recording.
     mode : Signal(RecorderMode), in
         Controls the recording mode.
     start : Signal(), in
@@ -90,6 +95,7 @@ class Recorder16IQ(Elaboratable):
         self.strobe_in = Signal()
         self.re_in = Signal(16)
         self.im_in = Signal(16)
+        self.first_sample = Signal()
 
         # domain_dma
         self.mode = Signal(RecorderMode)
@@ -106,7 +112,7 @@ class Recorder16IQ(Elaboratable):
 
     def ports(self):
         return [
-            self.strobe_in, self.re_in, self.im_in,
+            self.strobe_in, self.re_in, self.im_in, self.first_sample,
             self.mode.as_value(), self.start, self.stop, self.finished,
             self.dropped_samples, self.next_address,
         ] + self.dma.axi.ports()
@@ -180,6 +186,15 @@ class Recorder16IQ(Elaboratable):
                     fifo.data_in.eq(pack8.out),
                     fifo.wren.eq(pack8.strobe_out),
                 ]
+        # The first sample of the recording is the first valid sample for
+        # which run_in is asserted.
+        started = Signal()
+        m.d.comb += self.first_sample.eq(self.strobe_in & run_in & ~started)
+        with m.If(self.first_sample):
+            m.d[self.domain_in] += started.eq(1)
+        with m.If(~run_in):
+            m.d[self.domain_in] += started.eq(0)
+
         dropped = Signal()
         run_in_q = Signal()
         m.d[self.domain_in] += run_in_q.eq(run_in)
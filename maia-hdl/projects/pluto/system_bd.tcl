@@ -259,6 +259,10 @@ ad_connect  axi_ad9361/adc_data_i0 adc_i_slice/Din
 ad_connect  axi_ad9361/adc_data_q0 adc_q_slice/Din
 ad_connect  adc_i_slice/Dout maia_sdr/re_in
 ad_connect  adc_q_slice/Dout maia_sdr/im_in
+# The Pluto does not have PPS or trigger inputs. The sample counter can still be
+# latched by software, and recordings can be started by software.
+ad_connect  maia_sdr/pps_in GND
+ad_connect  maia_sdr/trigger_in GND
 ad_connect  axi_ad9361/l_clk maia_sdr/sampling_clk
 ad_connect  sys_cpu_clk maia_sdr/s_axi_lite_clk
 ad_connect  sys_cpu_reset maia_sdr/s_axi_lite_rst
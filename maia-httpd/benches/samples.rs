@@ -0,0 +1,44 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use maia_httpd::samples;
+
+// Number of IQ samples in a chunk of a recording download.
+const CHUNK_ITEMS: usize = 1 << 16;
+
+fn test_data(len: usize) -> Vec<u8> {
+    (0..len).map(|j| (j.wrapping_mul(37) >> 3) as u8).collect()
+}
+
+fn unpack_12bit(c: &mut Criterion) {
+    let input = test_data(3 * CHUNK_ITEMS);
+    let mut output = vec![0; 4 * CHUNK_ITEMS];
+    let mut group = c.benchmark_group("unpack_12bit_to_16bit");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.bench_function(BenchmarkId::new("blocks", CHUNK_ITEMS), |b| {
+        b.iter(|| samples::unpack_12bit_to_16bit(&mut output, black_box(&input)))
+    });
+    group.bench_function(BenchmarkId::new("scalar", CHUNK_ITEMS), |b| {
+        b.iter(|| samples::unpack_12bit_to_16bit_scalar(&mut output, black_box(&input)))
+    });
+    group.finish();
+}
+
+fn to_complex(c: &mut Criterion) {
+    let mut group = c.benchmark_group("to_complex");
+    group.throughput(Throughput::Elements(CHUNK_ITEMS as u64));
+    let data = test_data(2 * CHUNK_ITEMS);
+    group.bench_function(BenchmarkId::new("ci8", CHUNK_ITEMS), |b| {
+        b.iter(|| samples::ci8_to_complex(black_box(&data)))
+    });
+    let data = test_data(3 * CHUNK_ITEMS);
+    group.bench_function(BenchmarkId::new("ci12", CHUNK_ITEMS), |b| {
+        b.iter(|| samples::ci12_to_complex(black_box(&data)))
+    });
+    let data = test_data(4 * CHUNK_ITEMS);
+    group.bench_function(BenchmarkId::new("ci16", CHUNK_ITEMS), |b| {
+        b.iter(|| samples::ci16_to_complex(black_box(&data), 32768.0))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, unpack_12bit, to_complex);
+criterion_main!(benches);
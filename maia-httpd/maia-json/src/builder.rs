@@ -0,0 +1,886 @@
+//! Builders and validation of the request schemas.
+//!
+//! The checks done by the `validate` methods mirror those done by maia-httpd,
+//! so that invalid requests can be detected before sending them. Some checks
+//! depend on the state of the device (for instance, the maximum sampling
+//! frequency supported by the DDC), so they can only be done by the server.
+//!
+//! The range, step and units of the numeric fields are declared once as
+//! [`FieldMetadata`]. The metadata is used by the `validate` methods, and
+//! clients can use it to configure their input elements, so that the limits
+//! are only defined in this module.
+
+use super::{
+    ColormapParameters, Decibels, Hertz, Limits, ListQuery, PatchAd9361, PatchDevice,
+    PatchRecorder, PatchScanner, PatchSpectrometer, PostRecordingAnnotation, PutRecorderHopping,
+    ScannerChannel, Seconds, SpectrometerEqualization, StartupProfile,
+};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::ops::RangeInclusive;
+
+/// Validation error.
+///
+/// This error is returned by the `validate` methods of the request schemas
+/// and by the `build` methods of their builders.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::{Decibels, Hertz, PatchAd9361};
+///
+/// let patch = PatchAd9361::builder()
+///     .rx_lo_frequency(Hertz::from_mhz(2400.0))
+///     .rx_gain(Decibels(30.0))
+///     .build()
+///     .unwrap();
+/// assert_eq!(patch.rx_lo_frequency, Some(Hertz(2.4e9)));
+///
+/// let err = PatchAd9361::builder()
+///     .rx_lo_frequency(Hertz::from_mhz(10.0))
+///     .build()
+///     .unwrap_err();
+/// assert_eq!(err.field(), "rx_lo_frequency");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    field: &'static str,
+    message: String,
+}
+
+impl ValidationError {
+    fn new(field: &'static str, message: impl Into<String>) -> ValidationError {
+        ValidationError {
+            field,
+            message: message.into(),
+        }
+    }
+
+    /// Returns the name of the field that is invalid.
+    pub fn field(&self) -> &str {
+        self.field
+    }
+
+    /// Returns the message describing why the field is invalid.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+        write!(f, "invalid {}: {}", self.field, self.message)
+    }
+}
+
+impl core::error::Error for ValidationError {}
+
+// Returns an error for the field if the condition does not hold.
+fn check(
+    condition: bool,
+    field: &'static str,
+    message: impl FnOnce() -> String,
+) -> Result<(), ValidationError> {
+    if condition {
+        Ok(())
+    } else {
+        Err(ValidationError::new(field, message()))
+    }
+}
+
+// Checks that the value of a field, if present, is within a range. The value
+// can be given in a unit type, such as Seconds, when the range is given in the
+// underlying numeric type.
+fn check_range<T: PartialOrd + core::fmt::Display, V: Into<T>>(
+    value: Option<V>,
+    range: &RangeInclusive<T>,
+    field: &'static str,
+) -> Result<(), ValidationError> {
+    match value.map(Into::into) {
+        Some(value) => check(range.contains(&value), field, || {
+            format!(
+                "{value} is outside of the range {}..={}",
+                range.start(),
+                range.end()
+            )
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Metadata of a numeric field of a request schema.
+///
+/// This gives the range of values that are valid for the field, the step
+/// between consecutive values and the units of the field. The range is checked
+/// by the `validate` methods of the request schemas. The step is only a hint
+/// for user interfaces, since it is enforced by the type of the field or by the
+/// hardware.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::PatchAd9361;
+///
+/// let metadata = PatchAd9361::RX_LO_FREQUENCY_METADATA;
+/// assert_eq!(metadata.name, "rx_lo_frequency");
+/// assert_eq!(metadata.min, 70e6);
+/// assert_eq!(metadata.max, 6e9);
+/// assert_eq!(metadata.unit, Some("Hz"));
+/// assert!(metadata.contains(2.4e9));
+///
+/// assert_eq!(PatchAd9361::field_metadata("tx_gain").unwrap().step, Some(0.25));
+/// assert!(PatchAd9361::field_metadata("rx_gain_mode").is_none());
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct FieldMetadata {
+    /// Name of the field.
+    pub name: &'static str,
+    /// Minimum value of the field.
+    pub min: f64,
+    /// Maximum value of the field.
+    pub max: f64,
+    /// Step between consecutive values.
+    ///
+    /// This is `None` if the field can take any value in its range.
+    pub step: Option<f64>,
+    /// Units of the field.
+    ///
+    /// This is `None` if the field is dimensionless.
+    pub unit: Option<&'static str>,
+}
+
+impl FieldMetadata {
+    /// Returns whether a value is within the range of the field.
+    pub fn contains(&self, value: f64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+// Declares the metadata of the numeric fields of a request schema. This
+// defines an associated constant with the metadata of each field, the list
+// FIELD_METADATA, and a validate_field_metadata method that checks the ranges
+// of the fields, which are given as RangeInclusive expressions.
+macro_rules! impl_field_metadata {
+    ($ty:ident, $($constant:ident => $field:ident: $range:expr, $step:expr, $unit:expr);*) => {
+        impl $ty {
+            $(
+                #[doc = concat!("Metadata of the `", stringify!($field), "` field.")]
+                pub const $constant: FieldMetadata = FieldMetadata {
+                    name: stringify!($field),
+                    min: *$range.start() as f64,
+                    max: *$range.end() as f64,
+                    step: $step,
+                    unit: $unit,
+                };
+            )*
+
+            #[doc = concat!("Metadata of the numeric fields of [`", stringify!($ty), "`].")]
+            pub const FIELD_METADATA: &'static [FieldMetadata] = &[$(Self::$constant),*];
+
+            /// Returns the metadata of a field.
+            ///
+            /// This returns `None` if the field is not a numeric field with
+            /// metadata.
+            pub fn field_metadata(field: &str) -> Option<&'static FieldMetadata> {
+                Self::FIELD_METADATA.iter().find(|metadata| metadata.name == field)
+            }
+
+            // Checks that the fields that are present are within their ranges.
+            fn validate_field_metadata(&self) -> Result<(), ValidationError> {
+                $(
+                    check_range(self.$field, &$range, stringify!($field))?;
+                )*
+                Ok(())
+            }
+        }
+    }
+}
+
+macro_rules! impl_builder {
+    ($ty:ident, $builder:ident, $($field:ident: $field_ty:ty),*) => {
+        impl $ty {
+            #[doc = concat!("Returns a builder for [`", stringify!($ty), "`].")]
+            ///
+            /// All the fields of the builder are initially unset.
+            pub fn builder() -> $builder {
+                $builder($ty::default())
+            }
+        }
+
+        #[doc = concat!("Builder for [`", stringify!($ty), "`].")]
+        ///
+        /// The fields that are not set are omitted from the request.
+        #[derive(Debug, Clone, Default)]
+        pub struct $builder($ty);
+
+        impl $builder {
+            $(
+                #[doc = concat!("Sets the `", stringify!($field), "` field.")]
+                pub fn $field(mut self, value: $field_ty) -> $builder {
+                    self.0.$field = Some(value);
+                    self
+                }
+            )*
+
+            #[doc = concat!("Validates and returns the [`", stringify!($ty), "`].")]
+            pub fn build(self) -> Result<$ty, ValidationError> {
+                self.0.validate()?;
+                Ok(self.0)
+            }
+        }
+    }
+}
+
+impl_builder!(PatchAd9361, PatchAd9361Builder,
+              sampling_frequency: Hertz,
+              rx_rf_bandwidth: Hertz,
+              rx_rf_bandwidth_mode: super::Ad9361RfBandwidthMode,
+              tx_rf_bandwidth: Hertz,
+              rx_lo_frequency: Hertz,
+              tx_lo_frequency: Hertz,
+              rx_gain: Decibels,
+              rx_gain_mode: super::Ad9361GainMode,
+              tx_gain: Decibels,
+              calibrate_rx_filter: bool);
+
+impl PatchAd9361 {
+    /// Range of the sampling frequency of the AD9361 (in samples per second).
+    pub const SAMPLING_FREQUENCY_RANGE: RangeInclusive<f64> = 520_833.0..=61_440_000.0;
+    /// Range of the RF bandwidth of the AD9361 (in Hz).
+    pub const RF_BANDWIDTH_RANGE: RangeInclusive<f64> = 200e3..=56e6;
+    /// Range of the receive LO frequency of the AD9361 (in Hz).
+    pub const RX_LO_FREQUENCY_RANGE: RangeInclusive<f64> = 70e6..=6e9;
+    /// Range of the transmit LO frequency of the AD9361 (in Hz).
+    pub const TX_LO_FREQUENCY_RANGE: RangeInclusive<f64> = 47e6..=6e9;
+    /// Range of the transmit gain of the AD9361 (in dB).
+    pub const TX_GAIN_RANGE: RangeInclusive<f64> = -89.75..=0.0;
+
+    /// Validates the request.
+    ///
+    /// The receive gain is only checked to be finite, since its range depends
+    /// on the receive LO frequency. The frequencies are rounded to an integer
+    /// number of Hz by the server.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        self.validate_field_metadata()?;
+        check(self.rx_gain.is_none_or(Decibels::is_finite), "rx_gain", || {
+            "the gain must be finite".to_string()
+        })
+    }
+}
+
+impl_field_metadata!(PatchAd9361,
+    SAMPLING_FREQUENCY_METADATA => sampling_frequency:
+        Self::SAMPLING_FREQUENCY_RANGE, Some(1.0), Some("Sps");
+    RX_RF_BANDWIDTH_METADATA => rx_rf_bandwidth:
+        Self::RF_BANDWIDTH_RANGE, Some(1.0), Some("Hz");
+    TX_RF_BANDWIDTH_METADATA => tx_rf_bandwidth:
+        Self::RF_BANDWIDTH_RANGE, Some(1.0), Some("Hz");
+    RX_LO_FREQUENCY_METADATA => rx_lo_frequency:
+        Self::RX_LO_FREQUENCY_RANGE, Some(1.0), Some("Hz");
+    TX_LO_FREQUENCY_METADATA => tx_lo_frequency:
+        Self::TX_LO_FREQUENCY_RANGE, Some(1.0), Some("Hz");
+    TX_GAIN_METADATA => tx_gain: Self::TX_GAIN_RANGE, Some(0.25), Some("dB"));
+
+impl_builder!(PatchSpectrometer, PatchSpectrometerBuilder,
+              input: super::SpectrometerInput,
+              output_sampling_frequency: Hertz,
+              number_integrations: u32,
+              mode: super::SpectrometerMode,
+              retune_mode: super::SpectrometerRetuneMode,
+              wall_clock_alignment: bool,
+              noise_floor_estimator: super::NoiseFloorEstimator,
+              noise_floor_averaging: u32,
+              dc_bin_removal: bool,
+              software_integrations: u32);
+
+impl PatchSpectrometer {
+    /// Maximum number of spectrum lines over which the noise floor is
+    /// smoothed.
+    pub const MAX_NOISE_FLOOR_AVERAGING: u32 = 1024;
+    /// Maximum number of spectra combined in software into each spectrum
+    /// line.
+    pub const MAX_SOFTWARE_INTEGRATIONS: u32 = 4096;
+
+    /// Validates the request.
+    ///
+    /// The maximum number of integrations depends on the FPGA IP core, so it
+    /// is only checked by the server.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check(
+            self.output_sampling_frequency
+                .is_none_or(|freq| freq.is_finite() && freq > Hertz(0.0)),
+            "output_sampling_frequency",
+            || "the output sampling frequency must be positive".to_string(),
+        )?;
+        check(
+            self.number_integrations.is_none_or(|n| n >= 1),
+            "number_integrations",
+            || "the number of integrations must be at least one".to_string(),
+        )?;
+        self.validate_field_metadata()
+    }
+}
+
+impl_field_metadata!(PatchSpectrometer,
+    NOISE_FLOOR_AVERAGING_METADATA => noise_floor_averaging:
+        1..=Self::MAX_NOISE_FLOOR_AVERAGING, Some(1.0), None;
+    SOFTWARE_INTEGRATIONS_METADATA => software_integrations:
+        1..=Self::MAX_SOFTWARE_INTEGRATIONS, Some(1.0), None);
+
+impl SpectrometerEqualization {
+    /// Maximum number of points of the equalization table.
+    pub const MAX_POINTS: usize = 4096;
+    /// Maximum absolute value of the gain of each point (in dB).
+    pub const MAX_GAIN: Decibels = Decibels(60.0);
+
+    /// Validates the request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{Decibels, Hertz, SpectrometerEqualization, SpectrometerEqualizationPoint};
+    ///
+    /// let point = |frequency, gain| SpectrometerEqualizationPoint {
+    ///     frequency: Hertz(frequency),
+    ///     gain: Decibels(gain),
+    /// };
+    /// let equalization = SpectrometerEqualization {
+    ///     enabled: true,
+    ///     points: vec![point(-30e6, 3.0), point(0.0, 0.0), point(30e6, 3.0)],
+    /// };
+    /// assert!(equalization.validate().is_ok());
+    ///
+    /// let unsorted = SpectrometerEqualization {
+    ///     enabled: true,
+    ///     points: vec![point(0.0, 0.0), point(-30e6, 3.0)],
+    /// };
+    /// assert_eq!(unsorted.validate().unwrap_err().field(), "points");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check(self.points.len() <= Self::MAX_POINTS, "points", || {
+            format!(
+                "the equalization table can have at most {} points",
+                Self::MAX_POINTS
+            )
+        })?;
+        for point in &self.points {
+            check(point.frequency.is_finite(), "points", || {
+                format!("point frequency {} is invalid", point.frequency)
+            })?;
+            check(point.gain.abs() <= Self::MAX_GAIN, "points", || {
+                format!(
+                    "point gain {} is outside of the range -{}..={}",
+                    point.gain,
+                    Self::MAX_GAIN,
+                    Self::MAX_GAIN
+                )
+            })?;
+        }
+        check(
+            self.points
+                .windows(2)
+                .all(|w| w[0].frequency < w[1].frequency),
+            "points",
+            || "the points must be sorted by increasing frequency".to_string(),
+        )
+    }
+}
+
+impl ColormapParameters {
+    /// Maximum difference between the start hue and the end hue (in degrees).
+    pub const MAX_HUE_SPAN: f64 = 720.0;
+    /// Range of the gamma.
+    pub const GAMMA_RANGE: RangeInclusive<f64> = 0.1..=10.0;
+
+    /// Validates the colormap parameters.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::ColormapParameters;
+    ///
+    /// let mut parameters = ColormapParameters {
+    ///     start_hue: 270.0,
+    ///     end_hue: 90.0,
+    ///     gamma: 1.5,
+    /// };
+    /// assert!(parameters.validate().is_ok());
+    ///
+    /// parameters.gamma = 0.0;
+    /// assert_eq!(parameters.validate().unwrap_err().field(), "gamma");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check(self.start_hue.is_finite(), "start_hue", || {
+            format!("{} is not a valid hue", self.start_hue)
+        })?;
+        check(
+            self.end_hue.is_finite() && (self.end_hue - self.start_hue).abs() <= Self::MAX_HUE_SPAN,
+            "end_hue",
+            || {
+                format!(
+                    "the end hue must be at most {} degrees away from the start hue",
+                    Self::MAX_HUE_SPAN
+                )
+            },
+        )?;
+        check_range(Some(self.gamma), &Self::GAMMA_RANGE, "gamma")
+    }
+}
+
+impl Limits {
+    /// Maximum number of ranges in each of the frequency lists.
+    pub const MAX_RANGES: usize = 256;
+
+    /// Validates the request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{Decibels, Hertz, Limits, LimitsFrequencyRange};
+    ///
+    /// let limits = Limits {
+    ///     max_tx_gain: Some(Decibels(-20.0)),
+    ///     frequency_allowlist: vec![LimitsFrequencyRange {
+    ///         start_frequency: Hertz::from_mhz(144.0),
+    ///         stop_frequency: Hertz::from_mhz(146.0),
+    ///     }],
+    ///     frequency_blocklist: vec![],
+    /// };
+    /// assert!(limits.validate().is_ok());
+    ///
+    /// let limits = Limits {
+    ///     max_tx_gain: Some(Decibels(10.0)),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(limits.validate().unwrap_err().field(), "max_tx_gain");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check_range(self.max_tx_gain, &PatchAd9361::TX_GAIN_RANGE, "max_tx_gain")?;
+        for (ranges, field) in [
+            (&self.frequency_allowlist, "frequency_allowlist"),
+            (&self.frequency_blocklist, "frequency_blocklist"),
+        ] {
+            check(ranges.len() <= Self::MAX_RANGES, field, || {
+                format!("the list can have at most {} ranges", Self::MAX_RANGES)
+            })?;
+            for range in ranges {
+                check(range.start_frequency <= range.stop_frequency, field, || {
+                    format!(
+                        "the start frequency {} is above the stop frequency {}",
+                        range.start_frequency, range.stop_frequency
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StartupProfile {
+    /// Validates the request.
+    ///
+    /// The AD9361 and spectrometer settings are validated as the corresponding
+    /// PATCH requests. The DDC configuration is only checked to have valid
+    /// decimation factors, since the rest of the checks depend on the FPGA IP
+    /// core.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(ad9361) = &self.ad9361 {
+            ad9361.validate()?;
+        }
+        if let Some(ddc) = &self.ddc {
+            for fir in core::iter::once(&ddc.fir1)
+                .chain(&ddc.fir2)
+                .chain(&ddc.fir3)
+            {
+                check(fir.decimation >= 1, "ddc", || {
+                    "the decimation factors must be at least one".to_string()
+                })?;
+            }
+        }
+        if let Some(spectrometer) = &self.spectrometer {
+            spectrometer.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl_builder!(ListQuery, ListQueryBuilder,
+              offset: usize,
+              limit: usize,
+              since: f64,
+              until: f64);
+
+impl ListQuery {
+    /// Validates the query.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::ListQuery;
+    ///
+    /// let query = ListQuery::builder().offset(20).limit(10).build().unwrap();
+    /// assert_eq!(query.limit, Some(10));
+    ///
+    /// let err = ListQuery::builder()
+    ///     .since(1_704_110_400_000.0)
+    ///     .until(1_704_024_000_000.0)
+    ///     .build()
+    ///     .unwrap_err();
+    /// assert_eq!(err.field(), "until");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check(self.since.is_none_or(f64::is_finite), "since", || {
+            "the time must be finite".to_string()
+        })?;
+        check(self.until.is_none_or(f64::is_finite), "until", || {
+            "the time must be finite".to_string()
+        })?;
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            check(since <= until, "until", || {
+                format!("{until} is earlier than since {since}")
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl PostRecordingAnnotation {
+    /// Maximum length of the label and the comment (in bytes).
+    pub const MAX_TEXT_LEN: usize = 1024;
+
+    /// Validates the request.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{Hertz, PostRecordingAnnotation};
+    ///
+    /// let mut annotation = PostRecordingAnnotation {
+    ///     start_time: 1_704_110_400_000.0,
+    ///     end_time: 1_704_110_401_500.0,
+    ///     frequency_min: Hertz(145.79e6),
+    ///     frequency_max: Hertz(145.81e6),
+    ///     label: "ISS APRS".to_string(),
+    ///     comment: String::new(),
+    /// };
+    /// assert!(annotation.validate().is_ok());
+    ///
+    /// annotation.frequency_max = Hertz(145.7e6);
+    /// assert_eq!(annotation.validate().unwrap_err().field(), "frequency_max");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        for (value, field) in [
+            (self.start_time, "start_time"),
+            (self.end_time, "end_time"),
+            (f64::from(self.frequency_min), "frequency_min"),
+            (f64::from(self.frequency_max), "frequency_max"),
+        ] {
+            check(value.is_finite(), field, || {
+                format!("{value} is not a finite number")
+            })?;
+        }
+        check(self.start_time <= self.end_time, "end_time", || {
+            format!(
+                "{} is earlier than start_time {}",
+                self.end_time, self.start_time
+            )
+        })?;
+        check(
+            self.frequency_min <= self.frequency_max,
+            "frequency_max",
+            || {
+                format!(
+                    "{} is below frequency_min {}",
+                    self.frequency_max, self.frequency_min
+                )
+            },
+        )?;
+        for (text, field) in [(&self.label, "label"), (&self.comment, "comment")] {
+            check(text.len() <= Self::MAX_TEXT_LEN, field, || {
+                format!("the text can have at most {} bytes", Self::MAX_TEXT_LEN)
+            })?;
+        }
+        Ok(())
+    }
+}
+
+impl_builder!(PatchRecorder, PatchRecorderBuilder,
+              state_change: super::RecorderStateChange,
+              mode: super::RecorderMode,
+              prepend_timestamp: bool,
+              maximum_duration: Seconds,
+              trigger_mode: super::RecorderTriggerMode,
+              trigger_polarity: super::RecorderTriggerPolarity,
+              clip_mode: bool,
+              clip_duration: Seconds);
+
+impl PatchRecorder {
+    /// Maximum duration of each clip in clip mode.
+    pub const MAX_CLIP_DURATION: Seconds = Seconds(10.0);
+
+    /// Validates the request.
+    ///
+    /// The clips must also fit in the recording buffer, but this depends on
+    /// the sample rate and the recorder mode, so it is only checked by the
+    /// server.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{PatchRecorder, Seconds};
+    ///
+    /// let mut patch = PatchRecorder {
+    ///     clip_duration: Some(Seconds(0.5)),
+    ///     ..Default::default()
+    /// };
+    /// assert!(patch.validate().is_ok());
+    ///
+    /// patch.clip_duration = Some(Seconds(60.0));
+    /// assert_eq!(patch.validate().unwrap_err().field(), "clip_duration");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check(
+            self.maximum_duration.is_none_or(|d| !d.0.is_nan()),
+            "maximum_duration",
+            || "the maximum duration must be a number".to_string(),
+        )?;
+        check(
+            self.clip_duration
+                .is_none_or(|d| d > Seconds(0.0) && d <= Self::MAX_CLIP_DURATION),
+            "clip_duration",
+            || {
+                format!(
+                    "the clip duration must be positive and at most {}",
+                    Self::MAX_CLIP_DURATION
+                )
+            },
+        )
+    }
+}
+
+impl PutRecorderHopping {
+    /// Maximum number of frequencies of a frequency-hopping recording.
+    pub const MAX_FREQUENCIES: usize = 256;
+    /// Maximum duration of each burst.
+    pub const MAX_BURST_DURATION: Seconds = Seconds(10.0);
+    /// Maximum settle time.
+    pub const MAX_SETTLE_TIME: Seconds = Seconds(1.0);
+    /// Maximum number of cycles.
+    pub const MAX_CYCLES: u32 = 1000;
+
+    /// Validates the request.
+    ///
+    /// The total size of the recording is also limited, but this depends on
+    /// the sample rate, so it is only checked by the server.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{Hertz, PutRecorderHopping, Seconds};
+    ///
+    /// let mut hopping = PutRecorderHopping {
+    ///     frequencies: vec![Hertz(433.1e6), Hertz(433.9e6), Hertz(434.7e6)],
+    ///     burst_duration: Seconds(0.05),
+    ///     settle_time: Seconds(0.01),
+    ///     cycles: 4,
+    /// };
+    /// assert!(hopping.validate().is_ok());
+    ///
+    /// hopping.cycles = 0;
+    /// assert_eq!(hopping.validate().unwrap_err().field(), "cycles");
+    /// ```
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        check(
+            !self.frequencies.is_empty() && self.frequencies.len() <= Self::MAX_FREQUENCIES,
+            "frequencies",
+            || {
+                format!(
+                    "there must be between 1 and {} frequencies",
+                    Self::MAX_FREQUENCIES
+                )
+            },
+        )?;
+        for &frequency in &self.frequencies {
+            check(
+                frequency.is_finite() && frequency > Hertz(0.0),
+                "frequencies",
+                || format!("frequency {frequency} is invalid"),
+            )?;
+        }
+        check(
+            self.burst_duration > Seconds(0.0) && self.burst_duration <= Self::MAX_BURST_DURATION,
+            "burst_duration",
+            || {
+                format!(
+                    "the burst duration must be positive and at most {}",
+                    Self::MAX_BURST_DURATION
+                )
+            },
+        )?;
+        check_range(
+            Some(self.settle_time),
+            &(Seconds(0.0)..=Self::MAX_SETTLE_TIME),
+            "settle_time",
+        )?;
+        check_range(Some(self.cycles), &(1..=Self::MAX_CYCLES), "cycles")
+    }
+}
+
+impl_builder!(PatchDevice, PatchDeviceBuilder,
+              name: String,
+              frequency_offset: Hertz);
+
+impl PatchDevice {
+    /// Maximum length of the device name (in characters).
+    pub const MAX_NAME_LENGTH: usize = 64;
+    /// Maximum absolute value of the frequency offset (in Hz).
+    ///
+    /// This is 1 THz, which is far beyond the frequency of any practical
+    /// converter.
+    pub const MAX_FREQUENCY_OFFSET: Hertz = Hertz(1e12);
+
+    /// Validates the request.
+    ///
+    /// Leading and trailing whitespace in the name is ignored, since the server
+    /// trims it.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(name) = &self.name {
+            let name = name.trim();
+            check(
+                name.chars().count() <= Self::MAX_NAME_LENGTH,
+                "name",
+                || {
+                    format!(
+                        "the name is longer than {} characters",
+                        Self::MAX_NAME_LENGTH
+                    )
+                },
+            )?;
+            check(!name.chars().any(char::is_control), "name", || {
+                "the name contains control characters".to_string()
+            })?;
+        }
+        self.validate_field_metadata()
+    }
+}
+
+impl_field_metadata!(PatchDevice,
+    FREQUENCY_OFFSET_METADATA => frequency_offset:
+        -Self::MAX_FREQUENCY_OFFSET.0..=Self::MAX_FREQUENCY_OFFSET.0, Some(1.0), Some("Hz"));
+
+impl_builder!(PatchScanner, PatchScannerBuilder,
+              enabled: bool,
+              channels: Vec<ScannerChannel>,
+              dwell: Seconds,
+              hold: Seconds,
+              threshold: Decibels,
+              record_hits: bool,
+              hit_duration: Seconds);
+
+impl PatchScanner {
+    /// Maximum number of channels of the scanner.
+    pub const MAX_CHANNELS: usize = 1024;
+    /// Maximum dwell time.
+    pub const MAX_DWELL: Seconds = Seconds(60.0);
+    /// Maximum hold time.
+    pub const MAX_HOLD: Seconds = Seconds(3600.0);
+    /// Maximum duration of the recordings of the scanner hits.
+    pub const MAX_HIT_DURATION: Seconds = Seconds(60.0);
+
+    /// Validates the request.
+    ///
+    /// Enabling the scanner also requires it to have at least one channel,
+    /// but this is only checked here if the channels are given in the request.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(channels) = &self.channels {
+            check(channels.len() <= Self::MAX_CHANNELS, "channels", || {
+                format!(
+                    "the scanner can have at most {} channels",
+                    Self::MAX_CHANNELS
+                )
+            })?;
+            for channel in channels {
+                check(
+                    channel.frequency.is_finite() && channel.frequency > Hertz(0.0),
+                    "channels",
+                    || format!("channel frequency {} is invalid", channel.frequency),
+                )?;
+                check(
+                    channel.bandwidth.is_finite() && channel.bandwidth > Hertz(0.0),
+                    "channels",
+                    || format!("channel bandwidth {} is invalid", channel.bandwidth),
+                )?;
+            }
+            check(
+                self.enabled != Some(true) || !channels.is_empty(),
+                "channels",
+                || "the scanner needs at least one channel".to_string(),
+            )?;
+        }
+        check(
+            self.dwell
+                .is_none_or(|d| d > Seconds(0.0) && d <= Self::MAX_DWELL),
+            "dwell",
+            || {
+                format!(
+                    "the dwell time must be positive and at most {}",
+                    Self::MAX_DWELL
+                )
+            },
+        )?;
+        self.validate_field_metadata()?;
+        check(
+            self.threshold.is_none_or(|t| t > Decibels(0.0)),
+            "threshold",
+            || "the threshold must be positive".to_string(),
+        )?;
+        check(
+            self.hit_duration
+                .is_none_or(|d| d > Seconds(0.0) && d <= Self::MAX_HIT_DURATION),
+            "hit_duration",
+            || {
+                format!(
+                    "the hit duration must be positive and at most {}",
+                    Self::MAX_HIT_DURATION
+                )
+            },
+        )
+    }
+}
+
+impl_field_metadata!(PatchScanner,
+    HOLD_METADATA => hold: 0.0..=Self::MAX_HOLD.0, None, Some("s"));
+
+impl ScannerChannel {
+    /// Creates a new scanner channel.
+    ///
+    /// The channel is not a priority channel and has no label.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{Hertz, PatchScanner, ScannerChannel, Seconds};
+    ///
+    /// let channel = ScannerChannel::new(Hertz::from_mhz(145.5), Hertz::from_khz(12.5));
+    /// let patch = PatchScanner::builder()
+    ///     .enabled(true)
+    ///     .channels(vec![channel])
+    ///     .dwell(Seconds(0.2))
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(patch.channels.unwrap()[0].frequency, Hertz(145.5e6));
+    ///
+    /// assert!(PatchScanner::builder().enabled(true).channels(vec![]).build().is_err());
+    /// ```
+    pub fn new(frequency: Hertz, bandwidth: Hertz) -> ScannerChannel {
+        ScannerChannel {
+            frequency,
+            bandwidth,
+            priority: false,
+            label: String::new(),
+        }
+    }
+}
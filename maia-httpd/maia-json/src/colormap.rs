@@ -0,0 +1,9 @@
+//! Colormaps.
+//!
+//! This module contains the look-up tables of the colormaps of the waterfall.
+//! They are used by maia-wasm to render the waterfall and by maia-httpd to
+//! render spectrograms, so that both look the same.
+
+pub mod inferno;
+pub mod turbo;
+pub mod viridis;
@@ -1,29 +1,134 @@
 //! maia-json contains the JSON schemas used by maia-httpd and maia-wasm.
+//!
+//! # API versioning
+//!
+//! The REST API is served under `/api/v1`. For compatibility with clients that
+//! predate API versioning, the same API is also served under `/api`. The
+//! version of the API implemented by a server is given by the `api_version`
+//! field of [`Api`].
+//!
+//! Fields added to the JSON schemas returned by the server after the first
+//! release of each schema have default values, so that responses from older
+//! servers, which do not include these fields, can still be deserialized.
+//!
+//! # Features
+//!
+//! The `std` feature, which is enabled by default, makes maia-json use the
+//! Rust standard library. Without it, maia-json is `no_std` and only requires
+//! the `alloc` crate, so the schemas can be used by embedded clients.
+//!
+//! # Units
+//!
+//! The frequency, gain and duration fields use the [`Hertz`], [`Decibels`] and
+//! [`Seconds`] types instead of an `f64`, so that the unit of the field is part
+//! of its type. These types are serialized as plain numbers.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(missing_docs)]
 
+extern crate alloc;
+
+use alloc::{string::String, vec::Vec};
 use serde::{Deserialize, Serialize};
 
+mod builder;
+pub mod colormap;
+pub use builder::{
+    FieldMetadata, PatchAd9361Builder, PatchDeviceBuilder, PatchRecorderBuilder,
+    PatchScannerBuilder, PatchSpectrometerBuilder, ValidationError,
+};
+mod units;
+pub use units::{Decibels, Hertz, Seconds};
+
+/// Version of the REST API.
+///
+/// The API is served under `/api/v{API_VERSION}`. This version is increased
+/// only when changes that break existing clients are made to the API. Adding
+/// new fields or endpoints does not change the version.
+pub const API_VERSION: u32 = 1;
+
 /// API JSON schema.
 ///
-/// This JSON schema corresponds to GET requests on `/api`. It contains the
-/// settings of the full Maia SDR system.
+/// This JSON schema corresponds to GET requests on `/api/v1` (or `/api`). It
+/// contains the settings of the full Maia SDR system.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Api {
+    /// Version of the REST API.
+    ///
+    /// See [`API_VERSION`]. Servers that predate API versioning do not include
+    /// this field, and it is deserialized as zero.
+    #[serde(default)]
+    pub api_version: u32,
     /// AD9361 settings.
     pub ad9361: Ad9361,
     /// DDC settings.
     pub ddc: DDCConfigSummary,
+    /// Device settings.
+    #[serde(default)]
+    pub device: Device,
+    /// Sections that could not be read.
+    ///
+    /// If reading a section fails, for instance because of an error reading
+    /// the AD9361, the section contains the value given in the previous
+    /// response, and the error is listed here. This is empty if all the
+    /// sections have been read successfully.
+    #[serde(default)]
+    pub errors: Vec<ApiSectionError>,
     /// Device geolocation.
     pub geolocation: DeviceGeolocation,
     /// IQ recorder settings.
     pub recorder: Recorder,
+    /// Annotations of the current recording.
+    #[serde(default)]
+    pub recording_annotations: RecordingAnnotations,
     /// Metadata for the current recording.
     pub recording_metadata: RecordingMetadata,
+    /// Frequency scanner.
+    #[serde(default)]
+    pub scanner: Scanner,
+    /// Sensor readings.
+    #[serde(default)]
+    pub sensors: Sensors,
     /// Spectrometer settings.
     pub spectrometer: Spectrometer,
     /// System time.
     pub time: Time,
+    /// Recording upload progress.
+    #[serde(default)]
+    pub upload: RecordingUpload,
+    /// Recording upload target.
+    #[serde(default)]
+    pub upload_target: UploadTarget,
+}
+
+/// API section.
+///
+/// This lists the sections of [`Api`] that can fail to be read.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ApiSection {
+    /// AD9361 settings.
+    Ad9361,
+    /// DDC settings.
+    DDC,
+    /// IQ recorder settings.
+    Recorder,
+    /// Sensor readings.
+    Sensors,
+    /// Spectrometer settings.
+    Spectrometer,
+    /// System time.
+    Time,
+}
+
+/// API section error.
+///
+/// This gives the error that happened when reading a section of [`Api`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ApiSectionError {
+    /// Section that could not be read.
+    pub section: ApiSection,
+    /// Error description.
+    pub error: String,
 }
 
 /// AD9361 JSON schema.
@@ -33,21 +138,30 @@ pub struct Api {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Ad9361 {
     /// Sampling frequency in samples per second.
-    pub sampling_frequency: u32,
+    pub sampling_frequency: Hertz,
     /// Receive RF bandwidth in Hz.
-    pub rx_rf_bandwidth: u32,
+    ///
+    /// This is the bandwidth of the receive analog filters of the AD9361. In
+    /// [`Ad9361RfBandwidthMode::Auto`] mode it follows the sampling frequency.
+    pub rx_rf_bandwidth: Hertz,
+    /// Receive RF bandwidth mode.
+    ///
+    /// Servers that predate this setting do not include this field, and it is
+    /// deserialized as [`Ad9361RfBandwidthMode::Manual`].
+    #[serde(default)]
+    pub rx_rf_bandwidth_mode: Ad9361RfBandwidthMode,
     /// Transmit RF bandwidth in Hz.
-    pub tx_rf_bandwidth: u32,
+    pub tx_rf_bandwidth: Hertz,
     /// Receive LO frequency in Hz.
-    pub rx_lo_frequency: u64,
+    pub rx_lo_frequency: Hertz,
     /// Transmit LO frequency in Hz.
-    pub tx_lo_frequency: u64,
+    pub tx_lo_frequency: Hertz,
     /// Receive gain in dB.
-    pub rx_gain: f64,
+    pub rx_gain: Decibels,
     /// Receive AGC mode.
     pub rx_gain_mode: Ad9361GainMode,
     /// Transmit gain in dB.
-    pub tx_gain: f64,
+    pub tx_gain: Decibels,
 }
 
 /// AD9361 PATCH JSON schema.
@@ -58,28 +172,44 @@ pub struct Ad9361 {
 pub struct PatchAd9361 {
     /// Sampling frequency in samples per second.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub sampling_frequency: Option<u32>,
+    pub sampling_frequency: Option<Hertz>,
     /// Receive RF bandwidth in Hz.
+    ///
+    /// Setting the bandwidth without setting `rx_rf_bandwidth_mode` changes
+    /// the mode to [`Ad9361RfBandwidthMode::Manual`]. The bandwidth is ignored
+    /// if `rx_rf_bandwidth_mode` is set to [`Ad9361RfBandwidthMode::Auto`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rx_rf_bandwidth: Option<Hertz>,
+    /// Receive RF bandwidth mode.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub rx_rf_bandwidth: Option<u32>,
+    pub rx_rf_bandwidth_mode: Option<Ad9361RfBandwidthMode>,
     /// Transmit RF bandwidth in Hz.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tx_rf_bandwidth: Option<u32>,
+    pub tx_rf_bandwidth: Option<Hertz>,
     /// Receive LO frequency in Hz.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub rx_lo_frequency: Option<u64>,
+    pub rx_lo_frequency: Option<Hertz>,
     /// Transmit LO frequency in Hz.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tx_lo_frequency: Option<u64>,
+    pub tx_lo_frequency: Option<Hertz>,
     /// Receive gain in dB.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub rx_gain: Option<f64>,
+    pub rx_gain: Option<Decibels>,
     /// Receive AGC mode.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub rx_gain_mode: Option<Ad9361GainMode>,
     /// Transmit gain in dB.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub tx_gain: Option<f64>,
+    pub tx_gain: Option<Decibels>,
+    /// Calibrate the receive analog filters.
+    ///
+    /// If this is `true`, the receive analog filters are tuned again for the
+    /// current receive RF bandwidth after applying the rest of the settings.
+    /// The AD9361 driver only tunes the filters when the bandwidth changes, so
+    /// this can be used to compensate for temperature drift. This field is not
+    /// part of the [`Ad9361`] settings.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibrate_rx_filter: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -97,9 +227,24 @@ pub enum Ad9361GainMode {
     Hybrid,
 }
 
+/// AD9361 RF bandwidth modes.
+///
+/// This enum lists how the receive RF bandwidth of the AD9361 is chosen.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum Ad9361RfBandwidthMode {
+    /// The RF bandwidth is set by maia-httpd to the sampling frequency
+    /// (clamped to the range supported by the AD9361) each time that the
+    /// sampling frequency changes.
+    Auto,
+    /// The RF bandwidth is only changed when it is set explicitly. This can be
+    /// used to narrow the analog filters to reject strong adjacent signals.
+    #[default]
+    Manual,
+}
+
 macro_rules! impl_str_conv {
     ($ty:ty, $($s:expr => $v:ident),*) => {
-        impl std::str::FromStr for $ty {
+        impl core::str::FromStr for $ty {
             type Err = ();
 
             fn from_str(s: &str) -> Result<Self, ()> {
@@ -112,8 +257,8 @@ macro_rules! impl_str_conv {
             }
         }
 
-        impl std::fmt::Display for $ty {
-            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        impl core::fmt::Display for $ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
                 write!(f, "{}", match self {
                     $(
                         <$ty>::$v => $s,
@@ -130,14 +275,26 @@ impl_str_conv!(Ad9361GainMode,
                "Slow attack" => SlowAttack,
                "Hybrid" => Hybrid);
 
+impl_str_conv!(Ad9361RfBandwidthMode,
+               "Auto" => Auto,
+               "Manual" => Manual);
+
+// The fields given after the optional semicolon are only present in the
+// $struct and are set to None.
 macro_rules! get_fields {
     ($struct:ident, $x:expr, $($field:ident),*) => {
+        get_fields!($struct, $x, $($field),*;)
+    };
+    ($struct:ident, $x:expr, $($field:ident),*; $($none:ident),*) => {
         $struct {
             $(
                 $field: Some($x.$field),
             )*
+            $(
+                $none: None,
+            )*
         }
-    }
+    };
 }
 
 impl From<Ad9361> for PatchAd9361 {
@@ -147,16 +304,252 @@ impl From<Ad9361> for PatchAd9361 {
             val,
             sampling_frequency,
             rx_rf_bandwidth,
+            rx_rf_bandwidth_mode,
             tx_rf_bandwidth,
             rx_lo_frequency,
             tx_lo_frequency,
             rx_gain,
             rx_gain_mode,
-            tx_gain
+            tx_gain;
+            calibrate_rx_filter
+        )
+    }
+}
+
+/// AD9361 AGC JSON schema.
+///
+/// This JSON schema corresponds to GET and PUT requests on `/api/ad9361/agc`. It
+/// contains the advanced parameters of the automatic gain control of the
+/// AD9361. The thresholds and steps are given in the units used by the AD9361
+/// driver. See the AD9361 Reference Manual (UG-570) for a detailed description
+/// of each parameter.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Ad9361Agc {
+    /// Outer high threshold in dB below full scale.
+    ///
+    /// When the signal exceeds this threshold, the slow attack and hybrid AGC
+    /// decrease the gain by `outer_thresh_high_dec_steps`.
+    pub outer_thresh_high: u32,
+    /// Gain decrease steps when the outer high threshold is exceeded.
+    pub outer_thresh_high_dec_steps: u32,
+    /// Inner high threshold in dB below full scale.
+    ///
+    /// When the signal exceeds this threshold, the slow attack and hybrid AGC
+    /// decrease the gain by `inner_thresh_high_dec_steps`.
+    pub inner_thresh_high: u32,
+    /// Gain decrease steps when the inner high threshold is exceeded.
+    pub inner_thresh_high_dec_steps: u32,
+    /// Inner low threshold in dB below full scale.
+    ///
+    /// When the signal falls below this threshold, the slow attack and hybrid
+    /// AGC increase the gain by `inner_thresh_low_inc_steps`.
+    pub inner_thresh_low: u32,
+    /// Gain increase steps when the signal is below the inner low threshold.
+    pub inner_thresh_low_inc_steps: u32,
+    /// Outer low threshold in dB below full scale.
+    ///
+    /// When the signal falls below this threshold, the slow attack and hybrid
+    /// AGC increase the gain by `outer_thresh_low_inc_steps`.
+    pub outer_thresh_low: u32,
+    /// Gain increase steps when the signal is below the outer low threshold.
+    pub outer_thresh_low_inc_steps: u32,
+    /// Extra delay in microseconds before the AGC starts after entering RX.
+    pub attack_delay_extra_margin_us: u32,
+    /// Gain update interval in microseconds.
+    ///
+    /// This is the interval at which the slow attack AGC updates the gain, and
+    /// thus controls how fast the gain decays after the signal weakens.
+    pub gain_update_interval_us: u32,
+    /// Fast attack AGC lock level in dB below full scale.
+    ///
+    /// This is the signal level that the fast attack AGC tries to achieve
+    /// before locking the gain.
+    pub fast_lock_level: u32,
+    /// Time in nanoseconds that the fast attack AGC waits in each state.
+    pub fast_state_wait_time_ns: u32,
+    /// Allow the fast attack AGC to increase the gain while it is not locked.
+    pub fast_allow_gain_increase: bool,
+    /// Allow the fast attack AGC to increase the gain after it is locked.
+    ///
+    /// When this is disabled, the gain is held after the AGC locks until one of
+    /// the unlock conditions happens.
+    pub fast_gain_increase_after_lock: bool,
+    /// Fast attack AGC unlock threshold for stronger signals.
+    ///
+    /// The gain is unlocked when the signal exceeds the lock level by this
+    /// number of dB.
+    pub fast_unlock_stronger_signal_thresh: u32,
+    /// Fast attack AGC unlock threshold for weaker signals.
+    ///
+    /// The gain is unlocked when the signal falls below the lock level by this
+    /// number of dB.
+    pub fast_unlock_energy_lost_thresh: u32,
+}
+
+/// AD9361 AGC PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/ad9361/agc`. It
+/// contains a subset of the advanced parameters of the automatic gain control
+/// of the AD9361.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PatchAd9361Agc {
+    /// Outer high threshold in dB below full scale.
+    ///
+    /// When the signal exceeds this threshold, the slow attack and hybrid AGC
+    /// decrease the gain by `outer_thresh_high_dec_steps`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outer_thresh_high: Option<u32>,
+    /// Gain decrease steps when the outer high threshold is exceeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outer_thresh_high_dec_steps: Option<u32>,
+    /// Inner high threshold in dB below full scale.
+    ///
+    /// When the signal exceeds this threshold, the slow attack and hybrid AGC
+    /// decrease the gain by `inner_thresh_high_dec_steps`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_thresh_high: Option<u32>,
+    /// Gain decrease steps when the inner high threshold is exceeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_thresh_high_dec_steps: Option<u32>,
+    /// Inner low threshold in dB below full scale.
+    ///
+    /// When the signal falls below this threshold, the slow attack and hybrid
+    /// AGC increase the gain by `inner_thresh_low_inc_steps`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_thresh_low: Option<u32>,
+    /// Gain increase steps when the signal is below the inner low threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_thresh_low_inc_steps: Option<u32>,
+    /// Outer low threshold in dB below full scale.
+    ///
+    /// When the signal falls below this threshold, the slow attack and hybrid
+    /// AGC increase the gain by `outer_thresh_low_inc_steps`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outer_thresh_low: Option<u32>,
+    /// Gain increase steps when the signal is below the outer low threshold.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub outer_thresh_low_inc_steps: Option<u32>,
+    /// Extra delay in microseconds before the AGC starts after entering RX.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attack_delay_extra_margin_us: Option<u32>,
+    /// Gain update interval in microseconds.
+    ///
+    /// This is the interval at which the slow attack AGC updates the gain, and
+    /// thus controls how fast the gain decays after the signal weakens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gain_update_interval_us: Option<u32>,
+    /// Fast attack AGC lock level in dB below full scale.
+    ///
+    /// This is the signal level that the fast attack AGC tries to achieve
+    /// before locking the gain.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_lock_level: Option<u32>,
+    /// Time in nanoseconds that the fast attack AGC waits in each state.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_state_wait_time_ns: Option<u32>,
+    /// Allow the fast attack AGC to increase the gain while it is not locked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_allow_gain_increase: Option<bool>,
+    /// Allow the fast attack AGC to increase the gain after it is locked.
+    ///
+    /// When this is disabled, the gain is held after the AGC locks until one of
+    /// the unlock conditions happens.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_gain_increase_after_lock: Option<bool>,
+    /// Fast attack AGC unlock threshold for stronger signals.
+    ///
+    /// The gain is unlocked when the signal exceeds the lock level by this
+    /// number of dB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_unlock_stronger_signal_thresh: Option<u32>,
+    /// Fast attack AGC unlock threshold for weaker signals.
+    ///
+    /// The gain is unlocked when the signal falls below the lock level by this
+    /// number of dB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fast_unlock_energy_lost_thresh: Option<u32>,
+}
+
+impl From<Ad9361Agc> for PatchAd9361Agc {
+    fn from(val: Ad9361Agc) -> PatchAd9361Agc {
+        get_fields!(
+            PatchAd9361Agc,
+            val,
+            outer_thresh_high,
+            outer_thresh_high_dec_steps,
+            inner_thresh_high,
+            inner_thresh_high_dec_steps,
+            inner_thresh_low,
+            inner_thresh_low_inc_steps,
+            outer_thresh_low,
+            outer_thresh_low_inc_steps,
+            attack_delay_extra_margin_us,
+            gain_update_interval_us,
+            fast_lock_level,
+            fast_state_wait_time_ns,
+            fast_allow_gain_increase,
+            fast_gain_increase_after_lock,
+            fast_unlock_stronger_signal_thresh,
+            fast_unlock_energy_lost_thresh
         )
     }
 }
 
+/// AD9361 IQ imbalance JSON schema.
+///
+/// This JSON schema corresponds to POST requests on `/api/ad9361/iq-imbalance`.
+/// It contains the residual IQ imbalance and DC offset of the AD9361 receiver,
+/// estimated from a short capture of IQ samples made with the recorder. This
+/// can be used to check the quadrature and DC offset calibrations of the
+/// AD9361, which depend on the frequency and gain, and to explain the mirror
+/// images that appear in the waterfall.
+///
+/// The IQ imbalance is estimated blindly from the statistics of the samples,
+/// assuming that the received signal is circular, which is the case for noise
+/// and for most signals. A strong signal that is not circular, such as a BPSK
+/// signal, biases the estimate. The amplitudes are normalized so that the full
+/// scale of the recorder corresponds to an amplitude of one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Ad9361IqImbalance {
+    /// Receive LO frequency at which the estimate was made, in Hz.
+    pub rx_lo_frequency: Hertz,
+    /// Sampling frequency at which the estimate was made, in samples per
+    /// second.
+    pub sampling_frequency: Hertz,
+    /// Receive gain at which the estimate was made, in dB.
+    pub rx_gain: Decibels,
+    /// Receive gain mode at which the estimate was made.
+    pub rx_gain_mode: Ad9361GainMode,
+    /// Number of IQ samples used in the estimate.
+    pub num_samples: usize,
+    /// Average power of the samples, in dBFS.
+    pub power_dbfs: Decibels,
+    /// DC offset of the I component.
+    pub dc_offset_i: f64,
+    /// DC offset of the Q component.
+    pub dc_offset_q: f64,
+    /// Power of the DC offset, in dBFS.
+    ///
+    /// This is `None` if the DC offset is zero.
+    pub dc_offset_dbfs: Option<Decibels>,
+    /// Image rejection ratio, in dB.
+    ///
+    /// This is the ratio between the power of a signal and the power of its
+    /// mirror image caused by the IQ imbalance. It is `None` if no IQ
+    /// imbalance can be measured.
+    pub image_rejection_db: Option<Decibels>,
+    /// Gain imbalance, in dB.
+    ///
+    /// This is the ratio between the power of the I component and the power of
+    /// the Q component.
+    pub gain_imbalance_db: Decibels,
+    /// Phase error, in degrees.
+    ///
+    /// This is the deviation from 90 degrees of the phase difference between
+    /// the I and Q components.
+    pub phase_error_deg: f64,
+}
+
 /// Spectrometer JSON schema.
 ///
 /// This JSON schema corresponds to GET requests on `/api/spectrometer`. It
@@ -166,15 +559,141 @@ pub struct Spectrometer {
     /// Input source.
     pub input: SpectrometerInput,
     /// Input sampling frequency in samples per second (read-only).
-    pub input_sampling_frequency: f64,
+    pub input_sampling_frequency: Hertz,
     /// Output sampling frequency in samples per second.
-    pub output_sampling_frequency: f64,
+    pub output_sampling_frequency: Hertz,
     /// Number of non-coherent integrations.
     pub number_integrations: u32,
     /// FFT size (read-only).
     pub fft_size: u32,
     /// Spectrometer mode.
     pub mode: SpectrometerMode,
+    /// Spectrometer behaviour when the receive frequency changes.
+    #[serde(default)]
+    pub retune_mode: SpectrometerRetuneMode,
+    /// Whether the spectrum lines are aligned to the wall clock.
+    ///
+    /// When enabled, the spectrometer integrations start at integer multiples
+    /// of the output sampling period counted from the Unix epoch (for
+    /// instance, on each second for an output sampling frequency of 1
+    /// Hz). The number of integrations is adjusted on each spectrum to keep
+    /// the alignment, and the spectrum lines sent through the `/waterfall`
+    /// WebSocket are preceded by a [`WaterfallTimestamp`].
+    #[serde(default)]
+    pub wall_clock_alignment: bool,
+    /// Noise floor estimator.
+    #[serde(default)]
+    pub noise_floor_estimator: NoiseFloorEstimator,
+    /// Number of spectrum lines over which the noise floor estimate is
+    /// smoothed.
+    ///
+    /// The estimate is smoothed with an exponential moving average whose time
+    /// constant is this number of lines. A value of 1 disables the smoothing.
+    #[serde(default)]
+    pub noise_floor_averaging: u32,
+    /// Whether the DC bin is replaced by the average of its neighbors.
+    ///
+    /// This removes the spike that the DC offset of the receiver produces at
+    /// the center of the spectrum, before the spectra are sent to the clients
+    /// and used by the server (for instance for the noise floor estimate or
+    /// the peak detection).
+    #[serde(default)]
+    pub dc_bin_removal: bool,
+    /// Number of spectra of the FPGA spectrometer that are combined in software
+    /// into each spectrum line.
+    ///
+    /// This gives integration times longer than those supported by the
+    /// number of integrations of the FPGA IP core. The spectra are averaged in
+    /// [`SpectrometerMode::Average`] and their maximum is taken in
+    /// [`SpectrometerMode::PeakDetect`]. The `output_sampling_frequency` is the
+    /// rate of the spectrum lines produced after the software integration. A
+    /// value of 1 disables the software integration.
+    #[serde(default)]
+    pub software_integrations: u32,
+    /// Total number of FFTs integrated into each spectrum line (read-only).
+    ///
+    /// This is the product of `number_integrations` and
+    /// `software_integrations`. It can be larger than the maximum number of
+    /// integrations supported by the FPGA IP core.
+    #[serde(default)]
+    pub total_integrations: u64,
+    /// Latest noise floor estimate in dB (read-only).
+    ///
+    /// This is `None` if the spectrometer has not produced any spectrum yet.
+    /// The estimate of each line is also sent through the `/waterfall`
+    /// WebSocket when the [`WATERFALL_PROTOCOL_V3`] subprotocol is used.
+    pub noise_floor: Option<Decibels>,
+}
+
+/// Spectrometer data format JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/spectrometer/format`.
+/// It describes the spectrum data sent through the `/waterfall` WebSocket (after
+/// the frame or message header, when a subprotocol that uses them is
+/// requested), so that the bins can be mapped to frequencies and powers. The
+/// frequency of the bin with index `k` is `center_frequency + (k - fft_size /
+/// 2) * bin_width` when the bins are in [`SpectrometerBinOrder::FftShifted`]
+/// order.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpectrometerFormat {
+    /// Number of bins of each spectrum line, which is the size of the FFT.
+    pub fft_size: u32,
+    /// Order of the bins in each spectrum line.
+    pub bin_order: SpectrometerBinOrder,
+    /// Format of each bin, using the notation of the SigMF datatypes.
+    ///
+    /// Currently this is always `"f32_le"`.
+    pub datatype: String,
+    /// Units of the bins.
+    pub units: SpectrometerUnits,
+    /// Scale factor applied to the bins.
+    ///
+    /// The bins are obtained by multiplying the power accumulated by the FPGA
+    /// spectrometer (in units of squared ADC counts) by this factor, which
+    /// normalizes by the number of integrations and the sample rate so that
+    /// the power in dB is positive. The factor changes when these settings
+    /// change.
+    pub scale: f64,
+    /// Whether the spectrometer equalization is applied to the bins.
+    ///
+    /// If so, each bin is also multiplied by its equalization gain (see
+    /// [`SpectrometerEqualization`]).
+    pub equalization: bool,
+    /// Whether the DC bin is replaced by the average of its neighbors (see
+    /// [`Spectrometer::dc_bin_removal`]).
+    #[serde(default)]
+    pub dc_bin_removal: bool,
+    /// Center frequency of the spectrum (in Hz).
+    ///
+    /// This includes the frequency offset of the device.
+    pub center_frequency: Hertz,
+    /// Frequency spacing between adjacent bins (in Hz).
+    pub bin_width: Hertz,
+}
+
+/// Spectrometer bin order.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SpectrometerBinOrder {
+    /// FFT-shifted order.
+    ///
+    /// The first bin corresponds to the frequency `-sample_rate / 2` relative
+    /// to the center frequency, and the bin with index `fft_size / 2`
+    /// corresponds to the center frequency.
+    FftShifted,
+    /// Natural FFT order.
+    ///
+    /// The first bin corresponds to the center frequency, and the bins with
+    /// negative frequencies follow those with positive frequencies.
+    Natural,
+}
+
+/// Spectrometer bin units.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SpectrometerUnits {
+    /// Linear power.
+    ///
+    /// The power in dB is obtained as `10 log10(x)`.
+    LinearPower,
 }
 
 /// Spectrometer PATCH JSON schema.
@@ -190,13 +709,200 @@ pub struct PatchSpectrometer {
     pub input: Option<SpectrometerInput>,
     /// Output sampling frequency in samples per second.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub output_sampling_frequency: Option<f64>,
+    pub output_sampling_frequency: Option<Hertz>,
     /// Number of non-coherent integrations.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub number_integrations: Option<u32>,
     /// Spectrometer mode.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub mode: Option<SpectrometerMode>,
+    /// Spectrometer behaviour when the receive frequency changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retune_mode: Option<SpectrometerRetuneMode>,
+    /// Whether the spectrum lines are aligned to the wall clock.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wall_clock_alignment: Option<bool>,
+    /// Noise floor estimator.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise_floor_estimator: Option<NoiseFloorEstimator>,
+    /// Number of spectrum lines over which the noise floor estimate is
+    /// smoothed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub noise_floor_averaging: Option<u32>,
+    /// Whether the DC bin is replaced by the average of its neighbors.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dc_bin_removal: Option<bool>,
+    /// Number of spectra of the FPGA spectrometer that are combined in software
+    /// into each spectrum line.
+    ///
+    /// When this is given together with the `output_sampling_frequency`, the
+    /// output sampling frequency applies to the spectrum lines produced with
+    /// the new number of software integrations. When the
+    /// `output_sampling_frequency` is given without this field, the server
+    /// uses the smallest number of software integrations for which the
+    /// integration fits in the number of integrations of the FPGA IP core, so
+    /// that long integrations can be requested with the output sampling
+    /// frequency alone.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub software_integrations: Option<u32>,
+}
+
+/// Spectrometer equalization JSON schema.
+///
+/// This JSON schema corresponds to GET and PUT requests on
+/// `/api/spectrometer/equalization`. It contains a table of gains that is
+/// applied to the spectra produced by the spectrometer before they are sent to
+/// the clients, for instance to compensate the droop of the AD9361 analog
+/// filters near the edges of the band. The gain of each spectrum bin is
+/// linearly interpolated between the points of the table, and the gains of the
+/// first and last points are used outside of the table.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SpectrometerEqualization {
+    /// Whether the equalization is applied.
+    pub enabled: bool,
+    /// Points of the equalization table, sorted by increasing frequency.
+    pub points: Vec<SpectrometerEqualizationPoint>,
+}
+
+/// Spectrometer equalization point JSON schema.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SpectrometerEqualizationPoint {
+    /// Frequency (in Hz) relative to the center of the spectrum.
+    pub frequency: Hertz,
+    /// Gain (in dB) applied at this frequency.
+    pub gain: Decibels,
+}
+
+/// Colormaps JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/colormaps`. It lists
+/// the custom colormaps that are stored in the device, so that they can be
+/// used by the waterfall of any browser.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Colormaps {
+    /// Custom colormaps, sorted by name.
+    pub colormaps: Vec<Colormap>,
+}
+
+/// Colormap JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/colormaps/generate`,
+/// which generate a colormap from the [`ColormapParameters`] given as query
+/// parameters, and to GET, PUT and DELETE requests on `/api/colormaps/{name}`,
+/// which access the custom colormaps stored in the device.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Colormap {
+    /// Name of the colormap.
+    ///
+    /// This is `None` for the colormaps returned by `/api/colormaps/generate`.
+    pub name: Option<String>,
+    /// Parameters from which the colormap is generated.
+    pub parameters: ColormapParameters,
+    /// Colors of the colormap, as 8-bit RGB values.
+    ///
+    /// The colormap has 256 colors, ordered from the lowest to the highest
+    /// power. This is the format used by the colormaps of the waterfall.
+    pub colors: Vec<[u8; 3]>,
+}
+
+/// Colormap parameters JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/colormaps/{name}` and
+/// to the query parameters of GET requests on `/api/colormaps/generate`. The
+/// colormap is a perceptually uniform ramp in the OKLCh color space, in which
+/// the lightness increases from dark to bright while the hue rotates from the
+/// start hue to the end hue.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ColormapParameters {
+    /// Hue of the first color of the colormap (in degrees).
+    pub start_hue: f64,
+    /// Hue of the last color of the colormap (in degrees).
+    ///
+    /// The hue goes through all the intermediate values between the start hue
+    /// and the end hue, so the end hue can be more than 360 degrees away from
+    /// the start hue to make the hue rotate more than once.
+    pub end_hue: f64,
+    /// Gamma of the colormap.
+    ///
+    /// The position along the ramp is raised to this power. Values larger than
+    /// one give more contrast to the strong signals, and values smaller than
+    /// one give more contrast to the weak signals.
+    pub gamma: f64,
+}
+
+/// Spectrometer peaks JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/spectrometer/peaks`.
+/// It contains the peaks detected in the spectra produced by the
+/// spectrometer. A peak is a group of adjacent spectrum bins whose power is
+/// above the noise floor by at least the detection threshold. Peaks are
+/// tracked across consecutive spectra, so that each peak represents a discrete
+/// signal event with a start time and a duration.
+///
+/// The start and end of each peak are also sent as
+/// [`SpectrometerPeakEvent`]'s through the `/peaks` WebSocket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpectrometerPeaks {
+    /// Detection threshold (in dB above the noise floor).
+    pub threshold: Decibels,
+    /// Peaks that are currently present in the spectrum.
+    pub active: Vec<SpectrometerPeak>,
+    /// Most recent peaks that have ended, sorted by increasing end time.
+    pub recent: Vec<SpectrometerPeak>,
+}
+
+/// Spectrometer peaks PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on
+/// `/api/spectrometer/peaks`. It is used to change the detection threshold.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PatchSpectrometerPeaks {
+    /// Detection threshold (in dB above the noise floor).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<Decibels>,
+}
+
+/// Spectrometer peak JSON schema.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SpectrometerPeak {
+    /// Identifier of the peak.
+    ///
+    /// The identifier is unique during the execution of maia-httpd.
+    pub id: u64,
+    /// Frequency of the peak (in Hz).
+    ///
+    /// This is the power-weighted centroid of the bins of the peak in the
+    /// spectrum in which the peak had its maximum power.
+    pub frequency: Hertz,
+    /// Bandwidth estimate of the peak (in Hz).
+    ///
+    /// This is the maximum width of the bins above the threshold across the
+    /// spectra in which the peak has been present.
+    pub bandwidth: Hertz,
+    /// Maximum power of the peak (in dB).
+    pub power: Decibels,
+    /// Signal-to-noise ratio of the maximum power of the peak (in dB).
+    pub snr: Decibels,
+    /// Start time of the peak.
+    ///
+    /// The start time is given in milliseconds since the UNIX epoch.
+    pub start_time: f64,
+    /// Duration of the peak (in seconds).
+    ///
+    /// For active peaks this is the duration up to the most recent spectrum.
+    pub duration: Seconds,
+}
+
+/// Spectrometer peak event JSON schema.
+///
+/// These events are sent as text messages through the `/peaks` WebSocket.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum SpectrometerPeakEvent {
+    /// A new peak has been detected.
+    Start(SpectrometerPeak),
+    /// A peak is no longer present in the spectrum.
+    End(SpectrometerPeak),
 }
 
 /// Spectrometer input source.
@@ -229,118 +935,644 @@ impl_str_conv!(SpectrometerMode,
                "Average" => Average,
                "Peak detect" => PeakDetect);
 
-/// DDC design PUT JSON schema.
+/// Noise floor estimator.
 ///
-/// This JSON schema corresponds to PUT requests on `/api/ddc/design`. It is
-/// used to define design constraints for the DDC and have maia-httpd calculate
-/// suitable FIR filters coefficients using pm-remez.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct PutDDCDesign {
-    /// Frequency for the mixer, in Hz.
-    pub frequency: f64,
-    /// Decimation factor for the DDC.
-    pub decimation: u32,
-    /// Transition bandwidth of the DDC output.
+/// The noise floor estimators compute the median of the bins of each spectrum
+/// line, which is robust to the presence of narrowband signals, and smooth it
+/// over time.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum NoiseFloorEstimator {
+    /// Smoothed median of the spectrum bins.
+    #[default]
+    Median,
+    /// Minimum statistics.
     ///
-    /// This is the fraction (in [0, 1]) of the total output bandwidth that gets
-    /// used as transition bands.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub transition_bandwidth: Option<f64>,
-    /// Passband ripple.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub passband_ripple: Option<f64>,
-    /// Stopband attenuation in dB.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub stopband_attenuation_db: Option<f64>,
-    /// Use 1/f response in the stopband.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub stopband_one_over_f: Option<bool>,
+    /// The estimate is the minimum of the smoothed median over the most recent
+    /// spectrum lines. This prevents signals that occupy a large part of the
+    /// spectrum during short periods of time from raising the estimate.
+    MinimumStatistics,
 }
 
-/// DDC configuration GET JSON schema.
+impl_str_conv!(NoiseFloorEstimator,
+               "Median" => Median,
+               "Minimum statistics" => MinimumStatistics);
+
+/// Spectrometer retune mode.
 ///
-/// This JSON schema corresponds to GET requests on `/api/ddc/config`. It lists
-/// the configuration of each FIR filter in the DDC, as well as some values
-/// calculated from this configuration.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct DDCConfig {
-    /// Indicates whether the DDC is currently enabled.
-    pub enabled: bool,
-    /// Frequency for the mixer, in Hz.
-    pub frequency: f64,
-    /// Total decimation of this DDC configuration.
-    pub decimation: u32,
-    /// Input sampling frequency in samples per second.
-    pub input_sampling_frequency: f64,
-    /// Output sampling frequency in samples per second.
-    pub output_sampling_frequency: f64,
-    /// Maximum input sampling frequency supported by this DDC configuration.
-    pub max_input_sampling_frequency: f64,
-    /// Configuration of the first FIR filter.
-    pub fir1: DDCFIRConfig,
-    /// Configuration of the second FIR filter.
+/// This enum lists what the spectrometer does when the receive frequency
+/// changes (either the AD9361 RX LO frequency or the DDC frequency, when the
+/// DDC is the spectrometer input). The integration that is in progress when
+/// the frequency changes mixes data from the old and new frequencies, so it
+/// produces a smeared line in the waterfall.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum SpectrometerRetuneMode {
+    /// Continue the current integration.
+    Continue,
+    /// Abort the current integration.
     ///
-    /// This has the value `None` if the second FIR filter is bypassed.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub fir2: Option<DDCFIRConfig>,
-    /// Configuration of the third FIR filter.
+    /// The current integration finishes at the end of the current FFT, so the
+    /// new frequency is shown as soon as possible. This is the default.
+    #[default]
+    Abort,
+    /// Abort the current integration and drop the spectrum it produces.
     ///
-    /// This has the value `None` if the third FIR filter is bypassed.
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub fir3: Option<DDCFIRConfig>,
+    /// This is similar to `Abort`, but the spectrum produced by the aborted
+    /// integration, which mixes data from the old and new frequencies, is not
+    /// sent to the clients.
+    AbortAndDrop,
 }
 
-/// DDC configuration summary GET JSON schema.
+impl_str_conv!(SpectrometerRetuneMode,
+               "Continue" => Continue,
+               "Abort" => Abort,
+               "Abort and drop" => AbortAndDrop);
+
+/// Waterfall dropped lines JSON schema.
 ///
-/// This JSON schema is similar to [`DDCConfig`], but it does not include the
-/// FIR coefficients. It is used for the DDC entry in `/api`.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct DDCConfigSummary {
-    /// Indicates whether the DDC is currently enabled.
-    pub enabled: bool,
-    /// Frequency for the mixer, in Hz.
-    pub frequency: f64,
-    /// Total decimation of this DDC configuration.
-    pub decimation: u32,
-    /// Input sampling frequency in samples per second.
-    pub input_sampling_frequency: f64,
-    /// Output sampling frequency in samples per second.
-    pub output_sampling_frequency: f64,
-    /// Maximum input sampling frequency supported by this DDC configuration.
-    pub max_input_sampling_frequency: f64,
+/// This JSON schema corresponds to the text messages sent through the
+/// `/waterfall` WebSocket, which otherwise only carries binary messages with
+/// the spectrum lines. A message is sent when some lines have not been sent to
+/// the client because it was not able to receive them fast enough.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct WaterfallDropped {
+    /// Number of spectrum lines that have been dropped.
+    pub dropped_lines: u64,
 }
 
-macro_rules! ddcconfig_from {
-    ($value:expr, $($field:ident),*) => {
-        DDCConfigSummary {
-            $($field: $value.$field),*
-        }
-    }
+/// Waterfall timestamp JSON schema.
+///
+/// This JSON schema corresponds to the text messages sent through the
+/// `/waterfall` WebSocket when the spectrometer wall clock alignment is
+/// enabled. Each of these messages precedes the binary message of the spectrum
+/// line to which it refers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WaterfallTimestamp {
+    /// Start time of the integration of the spectrum line.
+    ///
+    /// The time is given as the number of milliseconds since UNIX timestamp.
+    pub timestamp: f64,
 }
 
-impl From<DDCConfig> for DDCConfigSummary {
-    fn from(value: DDCConfig) -> DDCConfigSummary {
-        ddcconfig_from!(
-            value,
-            enabled,
-            frequency,
-            decimation,
-            input_sampling_frequency,
-            output_sampling_frequency,
-            max_input_sampling_frequency
-        )
-    }
-}
+/// WebSocket subprotocol for the `/waterfall` WebSocket with frame headers.
+///
+/// Clients that request this subprotocol when connecting to the `/waterfall`
+/// WebSocket receive each spectrum line as a binary message formed by a
+/// [`WaterfallFrameHeader`] followed by the spectrum data. In this case the
+/// [`WaterfallDropped`] and [`WaterfallTimestamp`] text messages are not sent,
+/// since the header carries the same information. Clients that do not request
+/// this subprotocol receive binary messages containing only the spectrum data.
+pub const WATERFALL_PROTOCOL_V2: &str = "maia-sdr-waterfall.v2";
 
-/// DDC configuration PUT JSON schema.
+/// WebSocket subprotocol for the `/waterfall` WebSocket with frame headers
+/// including the noise floor.
 ///
-/// This JSON schema corresponds to PUT requests on `/api/ddc/config`. It is
-/// used to set the coefficients for each FIR filter manually, as opposed to
-/// having maia-httpd design a filter satisfying some requirements.
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
-pub struct PutDDCConfig {
+/// This subprotocol is the same as [`WATERFALL_PROTOCOL_V2`], except that the
+/// [`WaterfallFrameHeader`] is serialized with
+/// [`WaterfallFrameHeader::to_bytes_v3`], so that it includes the noise floor
+/// estimate of each spectrum line.
+pub const WATERFALL_PROTOCOL_V3: &str = "maia-sdr-waterfall.v3";
+
+/// WebSocket subprotocol for the `/waterfall` WebSocket with typed messages.
+///
+/// Clients that request this subprotocol receive binary messages that start
+/// with a [`WaterfallMessageHeader`], which indicates the type and version of
+/// the message. Besides the spectrum lines, the messages notify about
+/// configuration changes and alerts. Clients should ignore the messages whose
+/// type they do not know, so that new types of messages can be added without
+/// breaking them.
+pub const WATERFALL_PROTOCOL_V4: &str = "maia-sdr-waterfall.v4";
+
+/// Waterfall message type.
+///
+/// This gives the type of the messages sent through the `/waterfall` WebSocket
+/// when the [`WATERFALL_PROTOCOL_V4`] subprotocol is used.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum WaterfallMessageType {
+    /// Spectrum line.
+    ///
+    /// The message header is followed by a [`WaterfallFrameHeader`] serialized
+    /// as in the v3 subprotocol, and by the spectrum data.
+    SpectrumLine,
+    /// Configuration change notification.
+    ///
+    /// The payload is a [`WaterfallConfigChange`] serialized as JSON.
+    ConfigChange,
+    /// Alert event.
+    ///
+    /// The payload is a [`WaterfallAlert`] serialized as JSON.
+    Alert,
+    /// Chunk of IQ samples.
+    ///
+    /// This type is reserved for streaming IQ samples through the waterfall
+    /// WebSocket. maia-httpd does not send these messages yet.
+    IqChunk,
+}
+
+impl WaterfallMessageType {
+    /// Returns the value used to encode the message type.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            WaterfallMessageType::SpectrumLine => 0,
+            WaterfallMessageType::ConfigChange => 1,
+            WaterfallMessageType::Alert => 2,
+            WaterfallMessageType::IqChunk => 3,
+        }
+    }
+
+    /// Returns the message type encoded by a value.
+    ///
+    /// Returns `None` if the value does not correspond to a known type.
+    pub fn from_u8(value: u8) -> Option<WaterfallMessageType> {
+        Some(match value {
+            0 => WaterfallMessageType::SpectrumLine,
+            1 => WaterfallMessageType::ConfigChange,
+            2 => WaterfallMessageType::Alert,
+            3 => WaterfallMessageType::IqChunk,
+            _ => return None,
+        })
+    }
+
+    /// Returns the current version of the messages of this type.
+    pub fn version(self) -> u8 {
+        1
+    }
+
+    /// Returns the size in bytes of the type-specific header that follows the
+    /// [`WaterfallMessageHeader`].
+    pub fn header_size(self) -> usize {
+        match self {
+            WaterfallMessageType::SpectrumLine => WaterfallFrameHeader::SIZE_V3,
+            _ => 0,
+        }
+    }
+}
+
+/// Waterfall message header.
+///
+/// This header is included at the beginning of each binary message of the
+/// `/waterfall` WebSocket when the [`WATERFALL_PROTOCOL_V4`] subprotocol is
+/// used. It is serialized as the message type, the message version, and the
+/// payload offset in little-endian format, occupying a total of 4 bytes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct WaterfallMessageHeader {
+    /// Type of the message, encoded as in [`WaterfallMessageType::to_u8`].
+    ///
+    /// This is kept as a number so that messages of unknown types can be
+    /// skipped.
+    pub message_type: u8,
+    /// Version of the message.
+    ///
+    /// Newer versions of a message type can only add fields at the end of the
+    /// type-specific header, so older clients can still parse the message.
+    pub version: u8,
+    /// Offset in bytes of the payload from the beginning of the message.
+    pub payload_offset: u16,
+}
+
+impl WaterfallMessageHeader {
+    /// Size of the serialized header in bytes.
+    pub const SIZE: usize = 4;
+
+    /// Creates the header for a message of the current version of a type.
+    pub fn new(message_type: WaterfallMessageType) -> WaterfallMessageHeader {
+        WaterfallMessageHeader {
+            message_type: message_type.to_u8(),
+            version: message_type.version(),
+            payload_offset: (Self::SIZE + message_type.header_size()) as u16,
+        }
+    }
+
+    /// Returns the type of the message.
+    ///
+    /// Returns `None` if the type is unknown.
+    pub fn message_type(&self) -> Option<WaterfallMessageType> {
+        WaterfallMessageType::from_u8(self.message_type)
+    }
+
+    /// Serializes the header.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[0] = self.message_type;
+        bytes[1] = self.version;
+        bytes[2..].copy_from_slice(&self.payload_offset.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes the header from the beginning of a message.
+    ///
+    /// Returns `None` if the message is shorter than the header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::{WaterfallMessageHeader, WaterfallMessageType};
+    ///
+    /// let header = WaterfallMessageHeader::new(WaterfallMessageType::SpectrumLine);
+    /// assert_eq!(header.payload_offset, 28);
+    /// let header = WaterfallMessageHeader::from_bytes(&header.to_bytes()).unwrap();
+    /// assert_eq!(
+    ///     header.message_type(),
+    ///     Some(WaterfallMessageType::SpectrumLine)
+    /// );
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Option<WaterfallMessageHeader> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        Some(WaterfallMessageHeader {
+            message_type: bytes[0],
+            version: bytes[1],
+            payload_offset: u16::from_le_bytes(bytes[2..].try_into().unwrap()),
+        })
+    }
+}
+
+/// Waterfall configuration change JSON schema.
+///
+/// This JSON schema corresponds to the payload of the configuration change
+/// messages of the `/waterfall` WebSocket in the [`WATERFALL_PROTOCOL_V4`]
+/// subprotocol. A message is sent each time that a request modifies the
+/// configuration through the REST API.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct WaterfallConfigChange {
+    /// Path of the REST API resource that has been modified.
+    ///
+    /// Clients can do a GET request on this path (or on `/api`) to obtain the
+    /// new configuration.
+    pub path: String,
+}
+
+/// Waterfall alert JSON schema.
+///
+/// This JSON schema corresponds to the payload of the alert messages of the
+/// `/waterfall` WebSocket in the [`WATERFALL_PROTOCOL_V4`] subprotocol. Alerts
+/// are sent when something that requires the attention of the user happens in
+/// maia-httpd, such as the failure of a task.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WaterfallAlert {
+    /// Time of the alert.
+    ///
+    /// This is given as the number of milliseconds since UNIX timestamp, using
+    /// the same format as JavaScript `Date.now()`.
+    pub time: f64,
+    /// Alert message.
+    pub message: String,
+}
+
+/// Waterfall frame header.
+///
+/// This header is included at the beginning of each binary message of the
+/// `/waterfall` WebSocket when the [`WATERFALL_PROTOCOL_V2`] or
+/// [`WATERFALL_PROTOCOL_V3`] subprotocols are used. In the v2 subprotocol, it
+/// is serialized as the sequence number and the timestamp, both in
+/// little-endian format. In the v3 subprotocol, the noise floor is serialized
+/// after these, also in little-endian format, with a NaN value if the noise
+/// floor is not available. In the [`WATERFALL_PROTOCOL_V4`] subprotocol, it is
+/// serialized as in the v3 subprotocol after the [`WaterfallMessageHeader`] of
+/// the spectrum line messages.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct WaterfallFrameHeader {
+    /// Sequence number of the spectrum line.
+    ///
+    /// The sequence number increases by one for each line produced by the
+    /// spectrometer. Gaps in the sequence numbers indicate lines that have not
+    /// been sent to the client.
+    pub sequence: u64,
+    /// Start time of the integration of the spectrum line.
+    ///
+    /// The time is given as the number of milliseconds since UNIX timestamp.
+    pub timestamp: f64,
+    /// Noise floor estimate of the spectrum line in dB.
+    ///
+    /// This is only serialized in the v3 subprotocol.
+    pub noise_floor: Option<f64>,
+}
+
+impl WaterfallFrameHeader {
+    /// Size of the serialized header in bytes.
+    pub const SIZE: usize = 16;
+    /// Size of the serialized header in bytes in the v3 subprotocol.
+    pub const SIZE_V3: usize = 24;
+
+    /// Serializes the header.
+    pub fn to_bytes(&self) -> [u8; Self::SIZE] {
+        let mut bytes = [0; Self::SIZE];
+        bytes[..8].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes the header from the beginning of a message.
+    ///
+    /// Returns `None` if the message is shorter than the header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::WaterfallFrameHeader;
+    ///
+    /// let header = WaterfallFrameHeader {
+    ///     sequence: 42,
+    ///     timestamp: 1704110400000.0,
+    ///     noise_floor: None,
+    /// };
+    /// assert_eq!(
+    ///     WaterfallFrameHeader::from_bytes(&header.to_bytes()),
+    ///     Some(header)
+    /// );
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Option<WaterfallFrameHeader> {
+        let bytes = bytes.get(..Self::SIZE)?;
+        Some(WaterfallFrameHeader {
+            sequence: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            timestamp: f64::from_le_bytes(bytes[8..].try_into().unwrap()),
+            noise_floor: None,
+        })
+    }
+
+    /// Serializes the header in the v3 subprotocol.
+    pub fn to_bytes_v3(&self) -> [u8; Self::SIZE_V3] {
+        let mut bytes = [0; Self::SIZE_V3];
+        bytes[..Self::SIZE].copy_from_slice(&self.to_bytes());
+        bytes[Self::SIZE..].copy_from_slice(&self.noise_floor.unwrap_or(f64::NAN).to_le_bytes());
+        bytes
+    }
+
+    /// Deserializes the header in the v3 subprotocol from the beginning of a
+    /// message.
+    ///
+    /// Returns `None` if the message is shorter than the header.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::WaterfallFrameHeader;
+    ///
+    /// let header = WaterfallFrameHeader {
+    ///     sequence: 42,
+    ///     timestamp: 1704110400000.0,
+    ///     noise_floor: Some(-3.5),
+    /// };
+    /// assert_eq!(
+    ///     WaterfallFrameHeader::from_bytes_v3(&header.to_bytes_v3()),
+    ///     Some(header)
+    /// );
+    /// ```
+    pub fn from_bytes_v3(bytes: &[u8]) -> Option<WaterfallFrameHeader> {
+        let bytes = bytes.get(..Self::SIZE_V3)?;
+        let noise_floor = f64::from_le_bytes(bytes[Self::SIZE..].try_into().unwrap());
+        Some(WaterfallFrameHeader {
+            noise_floor: (!noise_floor.is_nan()).then_some(noise_floor),
+            ..Self::from_bytes(bytes)?
+        })
+    }
+}
+
+/// DDC design PUT JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/ddc/design`. It is
+/// used to define design constraints for the DDC and have maia-httpd calculate
+/// suitable FIR filters coefficients using pm-remez.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PutDDCDesign {
+    /// Frequency for the mixer, in Hz.
+    pub frequency: Hertz,
+    /// Decimation factor for the DDC.
+    pub decimation: u32,
+    /// Transition bandwidth of the DDC output.
+    ///
+    /// This is the fraction (in [0, 1]) of the total output bandwidth that gets
+    /// used as transition bands.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transition_bandwidth: Option<f64>,
+    /// Passband ripple.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub passband_ripple: Option<f64>,
+    /// Stopband attenuation in dB.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopband_attenuation_db: Option<Decibels>,
+    /// Use 1/f response in the stopband.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopband_one_over_f: Option<bool>,
+}
+
+/// DDC configuration GET JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/ddc/config`. It lists
+/// the configuration of each FIR filter in the DDC, as well as some values
+/// calculated from this configuration.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DDCConfig {
+    /// Indicates whether the DDC is currently enabled.
+    pub enabled: bool,
+    /// Frequency for the mixer, in Hz.
+    pub frequency: Hertz,
+    /// Total decimation of this DDC configuration.
+    pub decimation: u32,
+    /// Input sampling frequency in samples per second.
+    pub input_sampling_frequency: Hertz,
+    /// Output sampling frequency in samples per second.
+    pub output_sampling_frequency: Hertz,
+    /// Maximum input sampling frequency supported by this DDC configuration.
+    pub max_input_sampling_frequency: Hertz,
+    /// Transition bandwidth of the DDC design.
+    ///
+    /// This is the fraction (in [0, 1]) of the total output bandwidth that is
+    /// used as transition bands by the FIR filters. It has the value `None` if
+    /// the FIR filters were not designed by maia-httpd.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition_bandwidth: Option<f64>,
+    /// Configuration of the first FIR filter.
+    pub fir1: DDCFIRConfig,
+    /// Configuration of the second FIR filter.
+    ///
+    /// This has the value `None` if the second FIR filter is bypassed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fir2: Option<DDCFIRConfig>,
+    /// Configuration of the third FIR filter.
+    ///
+    /// This has the value `None` if the third FIR filter is bypassed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fir3: Option<DDCFIRConfig>,
+}
+
+/// DDC configuration summary GET JSON schema.
+///
+/// This JSON schema is similar to [`DDCConfig`], but it does not include the
+/// FIR coefficients. It is used for the DDC entry in `/api`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DDCConfigSummary {
+    /// Indicates whether the DDC is currently enabled.
+    pub enabled: bool,
+    /// Frequency for the mixer, in Hz.
+    pub frequency: Hertz,
+    /// Total decimation of this DDC configuration.
+    pub decimation: u32,
+    /// Input sampling frequency in samples per second.
+    pub input_sampling_frequency: Hertz,
+    /// Output sampling frequency in samples per second.
+    pub output_sampling_frequency: Hertz,
+    /// Maximum input sampling frequency supported by this DDC configuration.
+    pub max_input_sampling_frequency: Hertz,
+    /// Transition bandwidth of the DDC design.
+    ///
+    /// This is the fraction (in [0, 1]) of the total output bandwidth that is
+    /// used as transition bands by the FIR filters. It has the value `None` if
+    /// the FIR filters were not designed by maia-httpd.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition_bandwidth: Option<f64>,
+}
+
+macro_rules! ddcconfig_from {
+    ($value:expr, $($field:ident),*) => {
+        DDCConfigSummary {
+            $($field: $value.$field),*
+        }
+    }
+}
+
+impl From<DDCConfig> for DDCConfigSummary {
+    fn from(value: DDCConfig) -> DDCConfigSummary {
+        ddcconfig_from!(
+            value,
+            enabled,
+            frequency,
+            decimation,
+            input_sampling_frequency,
+            output_sampling_frequency,
+            max_input_sampling_frequency,
+            transition_bandwidth
+        )
+    }
+}
+
+/// DDC design PUT response JSON schema.
+///
+/// This JSON schema corresponds to the responses of PUT requests on
+/// `/api/ddc/design`. It contains the DDC configuration that has been set, with
+/// the same fields as [`DDCConfig`], together with the error budget of the
+/// design.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DDCDesign {
+    /// DDC configuration.
+    #[serde(flatten)]
+    pub config: DDCConfig,
+    /// Error budget of the design.
+    pub error_budget: DDCErrorBudget,
+}
+
+/// Error budget of a DDC design.
+///
+/// This lists the passband ripple and stopband attenuation achieved by each of
+/// the FIR filters of a DDC design and by the complete DDC.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DDCErrorBudget {
+    /// Errors of the first FIR filter.
+    pub fir1: DDCFilterErrors,
+    /// Errors of the second FIR filter.
+    ///
+    /// This has the value `None` if the second FIR filter is bypassed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fir2: Option<DDCFilterErrors>,
+    /// Errors of the third FIR filter.
+    ///
+    /// This has the value `None` if the third FIR filter is bypassed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fir3: Option<DDCFilterErrors>,
+    /// Errors of the complete DDC.
+    ///
+    /// These are worst-case bounds computed from the errors of each FIR
+    /// filter.
+    pub composite: DDCFilterErrors,
+}
+
+/// Passband ripple and stopband attenuation achieved by a filter.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct DDCFilterErrors {
+    /// Passband ripple.
+    ///
+    /// This is the maximum deviation of the amplitude response from one in the
+    /// passband, as in [`PutDDCDesign::passband_ripple`].
+    pub passband_ripple: f64,
+    /// Stopband attenuation in dB.
+    ///
+    /// This is the minimum attenuation in the stopband.
+    pub stopband_attenuation_db: Decibels,
+}
+
+/// DDC SNR estimate GET JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/ddc/snr`. It contains
+/// an estimate of the SNR of the signal in the DDC channel, computed from the
+/// latest spectrum given by the spectrometer. The signal power is measured in
+/// the spectrometer bins that fall inside the DDC passband, and the noise floor
+/// is estimated as the median of the adjacent bins on both sides of the
+/// passband. Powers are given in dB, using the same (uncalibrated) units as the
+/// waterfall.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DDCSnr {
+    /// Estimated SNR, in dB.
+    ///
+    /// This is `None` if the power in the channel is not above the noise floor.
+    pub snr_db: Option<Decibels>,
+    /// Total power in the DDC channel, in dB.
+    pub channel_power_db: Decibels,
+    /// Estimated noise floor per spectrometer bin, in dB.
+    pub noise_floor_db: Decibels,
+}
+
+/// DDC noise blanker JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/ddc/noise-blanker`.
+/// The noise blanker is a software stage that detects impulse noise, such as
+/// the noise produced by car ignitions or power lines, and replaces the
+/// samples affected by it by zeros. A sample is detected as an impulse when
+/// its amplitude exceeds the average amplitude of the signal by a threshold.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DDCNoiseBlanker {
+    /// Apply the noise blanker to the live DDC output.
+    ///
+    /// If this is enabled, the noise blanker is applied to the IQ samples sent
+    /// by the IQ streams and to the input of the demodulators.
+    pub live: bool,
+    /// Apply the noise blanker to the processing jobs.
+    ///
+    /// If this is enabled, the noise blanker is applied to the recording before
+    /// it is processed by the software DDC.
+    pub processing: bool,
+    /// Detection threshold, in dB above the average amplitude.
+    pub threshold_db: Decibels,
+    /// Duration of the blanking after each impulse, in seconds.
+    pub blanking_duration: Seconds,
+}
+
+/// DDC noise blanker PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/ddc/noise-blanker`.
+/// It is used to modify some of the parameters of the noise blanker.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PatchDDCNoiseBlanker {
+    /// Apply the noise blanker to the live DDC output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub live: Option<bool>,
+    /// Apply the noise blanker to the processing jobs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub processing: Option<bool>,
+    /// Detection threshold, in dB above the average amplitude.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold_db: Option<Decibels>,
+    /// Duration of the blanking after each impulse, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blanking_duration: Option<Seconds>,
+}
+
+/// DDC configuration PUT JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/ddc/config`. It is
+/// used to set the coefficients for each FIR filter manually, as opposed to
+/// having maia-httpd design a filter satisfying some requirements.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PutDDCConfig {
     /// Frequency for the mixer, in Hz.
-    pub frequency: f64,
+    pub frequency: Hertz,
     /// Configuration of the first FIR filter.
     pub fir1: DDCFIRConfig,
     /// Configuration of the second FIR filter.
@@ -353,6 +1585,14 @@ pub struct PutDDCConfig {
     /// This has the value `None` if the third FIR filter is bypassed.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fir3: Option<DDCFIRConfig>,
+    /// Transition bandwidth of the DDC design that gave these FIR filters.
+    ///
+    /// This is only informative and does not change the configuration of the
+    /// DDC. It is given by maia-httpd in the configurations that it designs,
+    /// so that it is kept when they are stored and set again, as in a
+    /// [`StartupProfile`]. See [`DDCConfig::transition_bandwidth`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transition_bandwidth: Option<f64>,
 }
 
 /// DDC configuration PUT JSON schema.
@@ -363,7 +1603,7 @@ pub struct PutDDCConfig {
 pub struct PatchDDCConfig {
     /// Frequency for the mixer, in Hz.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub frequency: Option<f64>,
+    pub frequency: Option<Hertz>,
 }
 
 /// Configuration of a FIR filter in the DDC.
@@ -388,7 +1628,57 @@ pub struct Recorder {
     /// Automatically prepend timestamp to file name.
     pub prepend_timestamp: bool,
     /// Maximum recording duration (in seconds).
-    pub maximum_duration: f64,
+    pub maximum_duration: Seconds,
+    /// Remaining recording duration (in seconds).
+    ///
+    /// This is only present while a recording with a maximum duration is
+    /// running. It indicates the time left until the recording is stopped
+    /// automatically.
+    pub remaining_duration: Option<Seconds>,
+    /// Trigger input mode.
+    #[serde(default)]
+    pub trigger_mode: RecorderTriggerMode,
+    /// Trigger input polarity.
+    #[serde(default)]
+    pub trigger_polarity: RecorderTriggerPolarity,
+    /// Arm settings.
+    ///
+    /// This is only present while the IQ recorder is armed. It indicates the
+    /// condition that will start the recording.
+    pub armed: Option<RecorderArm>,
+    /// Percentage of clipped samples in the recording in progress or in the
+    /// last recording.
+    ///
+    /// A sample is considered clipped if its real or imaginary part is at the
+    /// full scale of the recorder. While the recording is running, this is
+    /// estimated periodically from blocks of the newest samples. After the
+    /// recording stops, it is computed in the background from the whole
+    /// recording. It is `None` until it is available.
+    pub clipping: Option<f64>,
+    /// Clip mode.
+    ///
+    /// In clip mode, arming the recorder on the trigger input captures a clip
+    /// of fixed duration each time that the trigger fires, re-arming the
+    /// recorder after each clip until it is stopped. The clips are listed in
+    /// `/api/recorder/clips`.
+    #[serde(default)]
+    pub clip_mode: bool,
+    /// Duration of each clip in clip mode (in seconds).
+    #[serde(default)]
+    pub clip_duration: Seconds,
+    /// Number of clips stored.
+    #[serde(default)]
+    pub num_clips: usize,
+    /// Size of the recording DMA buffer in bytes.
+    #[serde(default)]
+    pub buffer_size_bytes: u64,
+    /// Maximum duration of a recording with the current recorder mode and
+    /// sample rate (in seconds).
+    ///
+    /// This is the duration that fills the recording buffer. Recordings stop
+    /// when the buffer is full, even if they have a longer maximum duration.
+    #[serde(default)]
+    pub max_duration_at_current_settings: Seconds,
 }
 
 /// IQ recorder PATCH JSON schema.
@@ -408,31 +1698,227 @@ pub struct PatchRecorder {
     pub prepend_timestamp: Option<bool>,
     /// Maximum recording duration (in seconds).
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub maximum_duration: Option<f64>,
+    pub maximum_duration: Option<Seconds>,
+    /// Trigger input mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_mode: Option<RecorderTriggerMode>,
+    /// Trigger input polarity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trigger_polarity: Option<RecorderTriggerPolarity>,
+    /// Clip mode.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clip_mode: Option<bool>,
+    /// Duration of each clip in clip mode (in seconds).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clip_duration: Option<Seconds>,
 }
 
-/// Command to change the IQ recorder state.
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Hash)]
-pub enum RecorderStateChange {
-    /// Command the IQ recoder to start recording.
-    Start,
-    /// Command the IQ recorder to stop recording.
-    Stop,
+/// IQ recorder clips JSON schema.
+///
+/// This JSON schema corresponds to GET and DELETE requests on
+/// `/api/recorder/clips`. It lists the clips captured by the IQ recorder in
+/// clip mode. All the clips can be downloaded as a tar file containing a SigMF
+/// recording for each clip from `/recording/clips`. GET requests accept the
+/// query parameters of [`ListQuery`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RecorderClips {
+    /// List of clips, in the order in which they were captured.
+    pub clips: Vec<RecorderClip>,
+    /// Total number of clips that match the filters of the [`ListQuery`].
+    ///
+    /// This can be larger than the number of clips in this response if an
+    /// offset or a limit are given in the query.
+    #[serde(default)]
+    pub total: usize,
 }
 
-/// IQ recorder sampling mode.
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
-pub enum RecorderMode {
-    /// 8-bit sampling mode.
+/// IQ recorder clip JSON schema.
+///
+/// This JSON schema contains the information about a clip captured by the IQ
+/// recorder in clip mode.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecorderClip {
+    /// Clip identifier.
     ///
-    /// Only the 8 MSBs of the ADC data or the DDC output are recorded.
-    IQ8bit,
-    /// 12-bit sampling mode.
+    /// Identifiers are assigned in increasing order as clips are captured.
+    pub id: u64,
+    /// File name of the clip (without extension).
+    pub filename: String,
+    /// Start time of the clip.
     ///
-    /// All the 12 bits of the ADC data, or the 12 MSBs of the
-    /// 16-bit DDC output are recorded.
-    IQ12bit,
-    /// 16-bit sampling mode.
+    /// The start time is given in milliseconds since the UNIX timestamp, using
+    /// the same format as [`Time`].
+    pub start_time: f64,
+    /// Duration of the clip (in seconds).
+    pub duration: Seconds,
+    /// Center frequency of the clip (in Hz).
+    pub frequency: Hertz,
+    /// Sample rate of the clip (in samples per second).
+    pub sample_rate: Hertz,
+    /// Size of the SigMF data file of the clip (in bytes).
+    pub size: usize,
+}
+
+/// Frequency-hopping recording PUT JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/recorder/hopping`. It
+/// is used to start a frequency-hopping recording, in which the IQ recorder
+/// captures a short burst at each of the frequencies in turn, retuning the
+/// AD9361 RX LO between bursts. The bursts are concatenated into a single
+/// SigMF recording, which has a capture segment for each burst.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PutRecorderHopping {
+    /// Frequencies at which the bursts are captured (in Hz).
+    ///
+    /// Each frequency is the center frequency of the recorder input, including
+    /// the frequency offset of the device.
+    pub frequencies: Vec<Hertz>,
+    /// Duration of each burst (in seconds).
+    pub burst_duration: Seconds,
+    /// Time waited after each retuning before capturing the burst (in seconds).
+    pub settle_time: Seconds,
+    /// Number of times that the list of frequencies is visited.
+    pub cycles: u32,
+}
+
+/// Frequency-hopping recording JSON schema.
+///
+/// This JSON schema corresponds to GET, PUT and DELETE requests on
+/// `/api/recorder/hopping`. It contains the progress of the frequency-hopping
+/// recording. A DELETE request cancels the recording in progress. The finished
+/// recordings are listed in `/api/recorder/hopping/recordings` using the
+/// [`RecorderClips`] JSON schema, and can be downloaded as a tar file from
+/// `/recording/hopping`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RecorderHopping {
+    /// Whether a frequency-hopping recording is in progress.
+    pub running: bool,
+    /// Settings of the last frequency-hopping recording.
+    pub config: Option<PutRecorderHopping>,
+    /// Number of bursts that have been captured in the last recording.
+    pub segments: usize,
+    /// Total number of bursts of the last recording.
+    pub total_segments: usize,
+    /// Error that stopped the last recording, if any.
+    pub error: Option<String>,
+}
+
+/// IQ recorder transfers JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/recorder/transfers`.
+/// It lists the downloads of the current recording that are in progress. While
+/// a download is in progress, the recording buffer is locked, so a new
+/// recording cannot be started. GET requests accept the query parameters of
+/// [`ListQuery`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RecorderTransfers {
+    /// List of transfers, in the order in which they were started.
+    pub transfers: Vec<RecorderTransfer>,
+    /// Total number of transfers that match the filters of the [`ListQuery`].
+    ///
+    /// This can be larger than the number of transfers in this response if an
+    /// offset or a limit are given in the query.
+    #[serde(default)]
+    pub total: usize,
+}
+
+/// IQ recorder transfer JSON schema.
+///
+/// This JSON schema contains the progress of a download of the current
+/// recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecorderTransfer {
+    /// Transfer identifier.
+    pub id: u64,
+    /// File name of the download.
+    pub filename: String,
+    /// Address of the client.
+    ///
+    /// This is not present if the address of the client is not known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    /// Size of the download (in bytes).
+    pub size: u64,
+    /// Number of bytes sent so far.
+    pub bytes_sent: u64,
+    /// Average transfer rate since the start of the download (in bytes per
+    /// second).
+    pub rate: f64,
+    /// Time elapsed since the start of the download (in seconds).
+    pub duration: Seconds,
+}
+
+/// IQ recorder arm JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/recorder/arm`. It is
+/// used to arm the IQ recorder, so that a recording starts at a given time or
+/// on the rising edge of the trigger input of the FPGA IP core. Several devices
+/// can be armed in this way to start recordings nearly simultaneously.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+pub struct RecorderArm {
+    /// Start time of the recording.
+    ///
+    /// The start time is given in milliseconds since the UNIX timestamp, using
+    /// the same format as [`Time`]. If this is not present, the recording
+    /// starts on the trigger input.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<f64>,
+}
+
+/// IQ recorder trigger input mode.
+///
+/// The trigger input of the FPGA IP core is only available in some
+/// bitstreams. A recording is started by the trigger input by arming the
+/// recorder using `/api/recorder/arm` without a start time.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum RecorderTriggerMode {
+    /// The trigger input only starts recordings.
+    ///
+    /// The active edge of the trigger input starts an armed recording. The
+    /// recording is stopped by a stop command or by the maximum duration.
+    #[default]
+    Start,
+    /// The trigger input starts and stops recordings.
+    ///
+    /// The active edge of the trigger input starts an armed recording, and the
+    /// next active edge stops it.
+    StartStop,
+}
+
+/// IQ recorder trigger input polarity.
+///
+/// The polarity selects the active edge of the trigger input.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum RecorderTriggerPolarity {
+    /// The rising edge is active.
+    #[default]
+    Rising,
+    /// The falling edge is active.
+    Falling,
+}
+
+/// Command to change the IQ recorder state.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Hash)]
+pub enum RecorderStateChange {
+    /// Command the IQ recoder to start recording.
+    Start,
+    /// Command the IQ recorder to stop recording.
+    Stop,
+}
+
+/// IQ recorder sampling mode.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum RecorderMode {
+    /// 8-bit sampling mode.
+    ///
+    /// Only the 8 MSBs of the ADC data or the DDC output are recorded.
+    IQ8bit,
+    /// 12-bit sampling mode.
+    ///
+    /// All the 12 bits of the ADC data, or the 12 MSBs of the
+    /// 16-bit DDC output are recorded.
+    IQ12bit,
+    /// 16-bit sampling mode.
     ///
     /// All the 16 bits of the DDC output are recorded. 12-bit ADC data is
     /// placed on the 12 MSBs.
@@ -453,6 +1939,11 @@ pub enum RecorderState {
     Running,
     /// The IQ recoder is stopping.
     Stopping,
+    /// The IQ recorder is armed.
+    ///
+    /// The recording will start at the arm start time or on the trigger
+    /// input. A stop command disarms the recorder.
+    Armed,
 }
 
 /// Geolocation.
@@ -479,8 +1970,16 @@ pub struct Geolocation {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct RecordingMetadata {
     /// Recording file name.
+    ///
+    /// The file name can contain template variables, such as `{freq_mhz}`,
+    /// `{date}`, `{time}`, `{mode}` or `{gain}`, which are replaced by their
+    /// values when each recording starts. This field contains the template
+    /// without expanding the variables.
     pub filename: String,
     /// Recording description.
+    ///
+    /// The description can contain the same template variables as the file
+    /// name.
     pub description: String,
     /// Recording author.
     pub author: String,
@@ -491,6 +1990,199 @@ pub struct RecordingMetadata {
     pub geolocation: DeviceGeolocation,
 }
 
+/// Recording checksum JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/recording/checksum`.
+/// It contains the SHA512 checksum of the SigMF data file of the current
+/// recording, which is computed in the background after the recording stops.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecordingChecksum {
+    /// SHA512 checksum, as a lowercase hexadecimal string.
+    ///
+    /// This corresponds to the SigMF "core:sha512" key. It contains `None` if
+    /// the checksum is not available.
+    pub sha512: Option<String>,
+    /// Whether the checksum is being computed.
+    pub in_progress: bool,
+}
+
+/// Recording statistics JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/recording/stats`. It
+/// contains statistics of the IQ samples of the current recording, which are
+/// computed on demand. The samples are normalized so that the full scale of the
+/// recorder corresponds to an amplitude of one.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordingStats {
+    /// Number of IQ samples in the recording.
+    pub num_samples: u64,
+    /// DC offset of the in-phase component.
+    pub dc_offset_i: f64,
+    /// DC offset of the quadrature component.
+    pub dc_offset_q: f64,
+    /// Average power of the samples, in dB relative to full scale (dBFS).
+    ///
+    /// This contains `None` if the recording is empty or if all its samples are
+    /// zero.
+    pub power_dbfs: Option<Decibels>,
+    /// Number of samples in which the in-phase or quadrature component is at
+    /// full scale.
+    pub clipped_samples: u64,
+    /// Histogram of the values of the in-phase and quadrature components.
+    ///
+    /// The interval between -1 and 1 is divided into bins of equal width. Each
+    /// element gives the number of values in the corresponding bin, counting
+    /// both the in-phase and the quadrature component of each sample.
+    pub histogram: Vec<u64>,
+}
+
+/// Recording trim JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/recording/trim` and
+/// to the responses of PATCH and DELETE requests on the same URL. It gives the
+/// portion of the current recording that is included in the recording
+/// downloads and uploads. The SigMF metadata of the download is adjusted to
+/// describe only the samples inside this portion.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct RecordingTrim {
+    /// Index of the first sample included in the download.
+    pub start_sample: u64,
+    /// Index of the sample after the last sample included in the download.
+    ///
+    /// This contains `None` if the download extends to the end of the
+    /// recording.
+    pub end_sample: Option<u64>,
+}
+
+/// Recording trim PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/recording/trim`. It
+/// is used to restrict the portion of the current recording that is
+/// downloaded. Each bound can be given either as a sample index or as a time
+/// in seconds since the start of the recording, but not both. A DELETE request
+/// on `/api/recording/trim` removes the trim.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PatchRecordingTrim {
+    /// Index of the first sample included in the download.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_sample: Option<u64>,
+    /// Index of the sample after the last sample included in the download.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_sample: Option<u64>,
+    /// Start of the download, as a time in seconds since the start of the
+    /// recording.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_time: Option<f64>,
+    /// End of the download, as a time in seconds since the start of the
+    /// recording.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<f64>,
+}
+
+/// Recording annotations JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/recording/annotations`
+/// and to the responses of DELETE requests on the same URL. It contains the
+/// annotations of the current recording, which are included in the SigMF
+/// metadata of the recording. The annotations are removed when a new recording
+/// starts.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RecordingAnnotations {
+    /// List of annotations, sorted by increasing ID.
+    pub annotations: Vec<RecordingAnnotation>,
+}
+
+/// Recording annotation JSON schema.
+///
+/// This JSON schema corresponds to the responses of POST requests on
+/// `/api/recording/annotations` and of DELETE requests on
+/// `/api/recording/annotations/{id}`. An annotation marks a time and frequency
+/// region of the current recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordingAnnotation {
+    /// Annotation ID.
+    pub id: u64,
+    /// Start time of the annotation.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp, using the
+    /// same format as [`Time`].
+    pub start_time: f64,
+    /// End time of the annotation.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp.
+    pub end_time: f64,
+    /// Lowest frequency of the annotation (in Hz).
+    pub frequency_min: Hertz,
+    /// Highest frequency of the annotation (in Hz).
+    pub frequency_max: Hertz,
+    /// Short label of the annotation.
+    ///
+    /// This corresponds to the SigMF "core:label" key.
+    pub label: String,
+    /// Comment of the annotation.
+    ///
+    /// This corresponds to the SigMF "core:comment" key.
+    pub comment: String,
+}
+
+/// Recording annotation POST JSON schema.
+///
+/// This JSON schema corresponds to POST requests on
+/// `/api/recording/annotations`. It is used to add an annotation to the current
+/// recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PostRecordingAnnotation {
+    /// Start time of the annotation.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp.
+    pub start_time: f64,
+    /// End time of the annotation.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp.
+    pub end_time: f64,
+    /// Lowest frequency of the annotation (in Hz).
+    pub frequency_min: Hertz,
+    /// Highest frequency of the annotation (in Hz).
+    pub frequency_max: Hertz,
+    /// Short label of the annotation.
+    #[serde(default)]
+    pub label: String,
+    /// Comment of the annotation.
+    #[serde(default)]
+    pub comment: String,
+}
+
+/// Recording finished JSON schema.
+///
+/// This JSON schema is used by the recording hooks of maia-httpd, which are run
+/// when a recording finishes. It is written to the standard input of the hook
+/// command and sent as the body of a POST request to the hook URL.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RecordingFinished {
+    /// Recording metadata.
+    pub metadata: RecordingMetadata,
+    /// Recorder mode used for the recording.
+    pub mode: RecorderMode,
+    /// Start time of the recording.
+    ///
+    /// The start time is given in milliseconds since the UNIX timestamp, using
+    /// the same format as [`Time`].
+    pub start_time: f64,
+    /// Duration of the recording (in seconds).
+    pub duration: Seconds,
+    /// Center frequency of the recording (in Hz).
+    pub frequency: Hertz,
+    /// Sample rate of the recording (in samples per second).
+    pub sample_rate: Hertz,
+    /// Size of the SigMF data file of the recording (in bytes).
+    pub size: usize,
+    /// SHA512 checksum of the SigMF data file, as a lowercase hexadecimal
+    /// string.
+    pub sha512: String,
+    /// Percentage of clipped samples in the recording.
+    pub clipping: f64,
+}
+
 /// Recording metadata PATCH JSON schema.
 ///
 /// This JSON schema corresponds to PATCH and PUT requests on
@@ -529,6 +2221,243 @@ impl From<RecordingMetadata> for PatchRecordingMetadata {
     }
 }
 
+/// Upload target JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/upload-target`. It
+/// contains the remote server to which recordings are uploaded using
+/// `/api/recording/upload`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct UploadTarget {
+    /// Upload protocol.
+    pub protocol: UploadProtocol,
+    /// URL of the remote server.
+    ///
+    /// For S3, this is the path-style URL of the bucket, optionally followed
+    /// by a prefix, such as `https://s3.eu-west-1.amazonaws.com/bucket/prefix`.
+    /// For WebDAV, this is the URL of the collection in which the recordings
+    /// are stored. The upload target is disabled if the URL is empty.
+    pub url: String,
+    /// User name.
+    ///
+    /// For S3, this is the access key ID.
+    pub username: String,
+    /// Whether the password has been set.
+    ///
+    /// The password (the secret access key for S3) is never returned by the
+    /// API.
+    pub password_set: bool,
+    /// S3 region.
+    ///
+    /// This is not used by WebDAV.
+    pub region: String,
+}
+
+/// Upload target PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/upload-target`. It
+/// is used to modify the remote server to which recordings are uploaded.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct PatchUploadTarget {
+    /// Upload protocol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<UploadProtocol>,
+    /// URL of the remote server.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// User name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Password.
+    ///
+    /// For S3, this is the secret access key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// S3 region.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+impl From<UploadTarget> for PatchUploadTarget {
+    fn from(val: UploadTarget) -> PatchUploadTarget {
+        PatchUploadTarget {
+            protocol: Some(val.protocol),
+            url: Some(val.url),
+            username: Some(val.username),
+            // the password is not included in UploadTarget
+            password: None,
+            region: Some(val.region),
+        }
+    }
+}
+
+/// Upload protocol.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum UploadProtocol {
+    /// S3-compatible object storage.
+    ///
+    /// Requests are authenticated with AWS Signature Version 4.
+    #[default]
+    S3,
+    /// WebDAV server.
+    ///
+    /// Requests are authenticated with HTTP basic authentication.
+    WebDAV,
+}
+
+impl_str_conv!(UploadProtocol,
+               "S3" => S3,
+               "WebDAV" => WebDAV);
+
+/// Recording upload JSON schema.
+///
+/// This JSON schema corresponds to GET, PUT and DELETE requests on
+/// `/api/recording/upload`. A PUT request starts uploading the current
+/// recording as a SigMF archive to the upload target, and a DELETE request
+/// cancels the upload. The schema contains the progress of the upload.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RecordingUpload {
+    /// Upload state.
+    pub state: UploadState,
+    /// Name of the uploaded file.
+    ///
+    /// This is empty if no upload has been started.
+    pub filename: String,
+    /// Number of bytes uploaded in the current attempt.
+    pub bytes_uploaded: u64,
+    /// Size of the uploaded file (in bytes).
+    pub size: u64,
+    /// Number of the current attempt.
+    ///
+    /// Failed uploads are retried a few times. Attempts are numbered starting
+    /// by one.
+    pub attempt: u32,
+    /// Error of the last failed attempt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Recording upload state.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum UploadState {
+    /// No upload has been started.
+    #[default]
+    Idle,
+    /// The recording is being uploaded.
+    Uploading,
+    /// The upload has finished successfully.
+    Finished,
+    /// The upload has failed or has been cancelled.
+    Failed,
+}
+
+/// Processing jobs JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/processing`. It lists
+/// the post-capture processing jobs, which run the DDC in software over the
+/// current recording to produce narrowband recordings. GET requests accept the
+/// query parameters of [`ListQuery`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ProcessingJobs {
+    /// Processing jobs, in order of creation.
+    pub jobs: Vec<ProcessingJob>,
+    /// Total number of jobs that match the filters of the [`ListQuery`].
+    ///
+    /// This can be larger than the number of jobs in this response if an
+    /// offset or a limit are given in the query.
+    #[serde(default)]
+    pub total: usize,
+}
+
+/// Processing job JSON schema.
+///
+/// This JSON schema corresponds to GET and DELETE requests on
+/// `/api/processing/{id}`, and to the response of POST requests on
+/// `/api/processing`. A DELETE request cancels the job if it is running and
+/// removes it. The output of a finished job can be downloaded as a SigMF
+/// archive from `/processing/{id}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProcessingJob {
+    /// Job ID.
+    pub id: u64,
+    /// Job state.
+    pub state: ProcessingJobState,
+    /// Fraction of the recording that has been processed (between 0 and 1).
+    pub progress: f64,
+    /// File name of the output recording.
+    pub filename: String,
+    /// Center frequency of the output recording (in Hz).
+    pub frequency: Hertz,
+    /// Total decimation of the DDC.
+    pub decimation: u32,
+    /// Sample rate of the output recording (in samples per second).
+    pub output_sampling_frequency: Hertz,
+    /// Size of the SigMF data file of the output recording (in bytes).
+    ///
+    /// This grows as the job progresses.
+    pub size: usize,
+    /// Error message.
+    ///
+    /// This is only present if the job has failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Processing job state.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum ProcessingJobState {
+    /// The job is running.
+    Running,
+    /// The job has finished successfully.
+    Finished,
+    /// The job has failed.
+    Failed,
+}
+
+/// Processing job POST JSON schema.
+///
+/// This JSON schema corresponds to POST requests on `/api/processing`. It
+/// starts a job that runs the DDC in software over the current recording. The
+/// DDC can be given either as a configuration with the FIR filter coefficients,
+/// as in [`PutDDCConfig`], or as design constraints, as in [`PutDDCDesign`]. If
+/// neither is given, the current configuration of the FPGA DDC is used. In all
+/// cases, the DDC frequency is relative to the center frequency of the
+/// recording.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PostProcessingJob {
+    /// File name of the output recording.
+    ///
+    /// If this is not given, the file name of the recording with a `_ddc`
+    /// suffix is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filename: Option<String>,
+    /// DDC configuration.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<PutDDCConfig>,
+    /// DDC design constraints.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub design: Option<PutDDCDesign>,
+}
+
+/// Sensors JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/sensors`. It contains
+/// readings that change continuously and are provided by the hardware, so that
+/// they can be monitored.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Sensors {
+    /// Receive RSSI in dB.
+    ///
+    /// This is the received signal strength indicator reported by the
+    /// AD9361. It is given as a positive number of dB below the full scale of
+    /// the ADC, so larger values indicate weaker signals.
+    pub rx_rssi: Decibels,
+    /// Receive gain in dB.
+    ///
+    /// This is the gain currently applied by the AD9361. When the automatic
+    /// gain control is enabled, this is the gain that it has chosen.
+    pub rx_gain: Decibels,
+}
+
 /// System time JSON schema.
 ///
 /// This JSON schema corresponds to GET requests on `/api/time`. It contains the
@@ -560,6 +2489,136 @@ impl From<Time> for PatchTime {
     }
 }
 
+/// Device JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/device`. It contains
+/// settings that identify the device.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Device {
+    /// Device name.
+    ///
+    /// This is a user-configurable label that can be used to tell apart
+    /// several devices. It is empty if the name has never been set.
+    pub name: String,
+    /// Frequency offset of an external frequency converter (in Hz).
+    ///
+    /// This is the offset that is added to the frequencies at the device
+    /// input to obtain the frequencies at the converter input, as when using a
+    /// transverter or an LNB. It is used to display the frequencies in the
+    /// UI and it is stored in the SigMF metadata of the recordings as
+    /// `maia:frequency_offset`. It is zero if no converter is used.
+    pub frequency_offset: Hertz,
+}
+
+/// Device PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/device`. It is used
+/// to modify the settings of the device.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PatchDevice {
+    /// Device name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Frequency offset of an external frequency converter (in Hz).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_offset: Option<Hertz>,
+}
+
+/// Device identification JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/device/identify`. It
+/// is used to make the device blink its LED, so that it can be physically
+/// identified.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Identify {
+    /// Duration of the LED blinking in seconds.
+    pub duration: Seconds,
+}
+
+/// Power JSON schema.
+///
+/// This JSON schema is returned by PUT requests on `/api/shutdown` and
+/// `/api/reboot`, which power off or reboot the device. These requests take no
+/// body, but they must be authorized with the token given to maia-httpd in the
+/// `--power-token` argument, using an `Authorization: Bearer <token>` header.
+/// Before the device powers off or reboots, any recording in progress is
+/// stopped and the connections are closed.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct Power {
+    /// Action that the device is going to do.
+    pub action: PowerAction,
+}
+
+/// Power action.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum PowerAction {
+    /// The device powers off.
+    Shutdown,
+    /// The device reboots.
+    Reboot,
+}
+
+/// Safety limits JSON schema.
+///
+/// This JSON schema corresponds to GET and PUT requests on
+/// `/api/limits-admin`. It contains the safety limits that maia-httpd enforces
+/// on every change of the AD9361 settings, the scanner channels and the survey
+/// bands, so that shared or remote installations cannot be driven outside
+/// legal or safe parameters. PUT requests must be authorized with the token
+/// given to maia-httpd in the `--limits-token` argument, using an
+/// `Authorization: Bearer <token>` header.
+///
+/// The limits are only checked when the settings are changed, so setting new
+/// limits does not modify the current settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Limits {
+    /// Maximum transmit gain (in dB), or `None` if it is not limited.
+    pub max_tx_gain: Option<Decibels>,
+    /// Allowed frequency ranges.
+    ///
+    /// If this list is not empty, the LO frequencies, scanner channels and
+    /// survey bands must be inside one of these ranges.
+    pub frequency_allowlist: Vec<LimitsFrequencyRange>,
+    /// Blocked frequency ranges.
+    ///
+    /// The LO frequencies, scanner channels and survey bands cannot overlap
+    /// any of these ranges.
+    pub frequency_blocklist: Vec<LimitsFrequencyRange>,
+}
+
+/// Safety limits frequency range JSON schema.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct LimitsFrequencyRange {
+    /// Start frequency of the range (in Hz).
+    pub start_frequency: Hertz,
+    /// Stop frequency of the range (in Hz).
+    pub stop_frequency: Hertz,
+}
+
+/// Startup profile JSON schema.
+///
+/// This JSON schema corresponds to GET and PUT requests on
+/// `/api/startup-profile`. It contains the settings that maia-httpd applies
+/// when it starts, so that the device boots into the preferred configuration
+/// rather than the factory defaults. The settings that are omitted keep their
+/// default values.
+///
+/// The settings are applied in the order in which they appear in this
+/// struct, as PATCH requests to `/api/ad9361` and `/api/spectrometer` and a
+/// PUT request to `/api/ddc/config`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct StartupProfile {
+    /// AD9361 settings.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ad9361: Option<PatchAd9361>,
+    /// DDC configuration.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ddc: Option<PutDDCConfig>,
+    /// Spectrometer settings, including its input.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spectrometer: Option<PatchSpectrometer>,
+}
+
 /// Device geolocation JSON schema.
 ///
 /// This JSON schema corresponds to GET and PUT requests on
@@ -573,6 +2632,797 @@ pub struct DeviceGeolocation {
     pub point: Option<Geolocation>,
 }
 
+/// Audit log JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/audit`. It contains
+/// the most recent requests that have modified the state of the device, from
+/// oldest to newest. GET requests accept the query parameters of
+/// [`ListQuery`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Audit {
+    /// Audit log entries.
+    pub entries: Vec<AuditEntry>,
+    /// Total number of entries that match the filters of the [`ListQuery`].
+    ///
+    /// This can be larger than the number of entries in this response if an
+    /// offset or a limit are given in the query.
+    #[serde(default)]
+    pub total: usize,
+}
+
+/// Audit log entry.
+///
+/// An entry of the audit log describes a request that attempted to modify the
+/// state of the device.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AuditEntry {
+    /// Time at which the request was received.
+    ///
+    /// This is given as the number of milliseconds since UNIX timestamp, using
+    /// the same format as JavaScript `Date.now()`.
+    pub time: f64,
+    /// Address of the client that made the request.
+    pub client: String,
+    /// HTTP method of the request.
+    pub method: String,
+    /// Path of the request.
+    pub path: String,
+    /// Body of the request.
+    ///
+    /// The body is truncated if it is too long.
+    pub body: String,
+    /// HTTP status code of the response.
+    pub http_status_code: u16,
+}
+
+/// WebSocket clients JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/clients`. It lists
+/// the clients that are connected to the WebSockets of maia-httpd, in the
+/// order in which they connected. GET requests accept the query parameters of
+/// [`ListQuery`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct WebSocketClients {
+    /// List of clients.
+    pub clients: Vec<WebSocketClient>,
+    /// Total number of clients that match the filters of the [`ListQuery`].
+    ///
+    /// This can be larger than the number of clients in this response if an
+    /// offset or a limit are given in the query.
+    #[serde(default)]
+    pub total: usize,
+}
+
+/// WebSocket client JSON schema.
+///
+/// This JSON schema contains the information about a client connected to a
+/// WebSocket of maia-httpd. It also corresponds to the response of DELETE
+/// requests on `/api/clients/{id}`, which disconnect the client.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WebSocketClient {
+    /// Client identifier.
+    ///
+    /// Identifiers are assigned in increasing order as clients connect.
+    pub id: u64,
+    /// WebSocket to which the client is connected.
+    pub websocket: WebSocketKind,
+    /// Address of the client.
+    ///
+    /// This is not present if the address of the client is not known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    /// WebSocket subprotocol used by the client.
+    ///
+    /// This is not present if the client did not request a subprotocol.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    /// Time at which the client connected.
+    ///
+    /// This is given as the number of milliseconds since UNIX timestamp, using
+    /// the same format as JavaScript `Date.now()`.
+    pub connect_time: f64,
+    /// Time elapsed since the client connected (in seconds).
+    pub duration: Seconds,
+    /// Number of messages sent to the client.
+    pub messages_sent: u64,
+    /// Number of bytes sent to the client.
+    ///
+    /// This counts the payload of the WebSocket messages.
+    pub bytes_sent: u64,
+    /// Average rate at which data is sent to the client since it connected
+    /// (in bytes per second).
+    pub rate: f64,
+}
+
+/// WebSocket kind.
+///
+/// This enum lists the WebSockets of maia-httpd.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum WebSocketKind {
+    /// Waterfall WebSocket (`/waterfall`).
+    Waterfall,
+    /// Peak events WebSocket (`/peaks`).
+    Peaks,
+}
+
+/// System JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/system`. It contains
+/// the health status of maia-httpd and the incidents in which a task that
+/// handles the FPGA IP core has failed and has been restarted.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct System {
+    /// Health status.
+    pub status: SystemStatus,
+    /// Number of times that a failed task has been restarted.
+    pub restarts: u64,
+    /// Most recent incidents, sorted from oldest to newest.
+    pub incidents: Vec<SystemIncident>,
+}
+
+/// System clock JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/system/clock`. It
+/// contains a measurement of the actual sample rate, obtained from the rate at
+/// which the spectrometer produces spectra, which is compared to the nominal
+/// sample rate to estimate the error of the ADC clock. The measurement uses
+/// the system clock of the device as reference, so its accuracy depends on this
+/// clock being disciplined (for instance with NTP).
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SystemClock {
+    /// Nominal sample rate of the spectrometer input (in samples per second).
+    pub nominal_samp_rate: Hertz,
+    /// Measured sample rate of the spectrometer input (in samples per second).
+    ///
+    /// This is `None` if the measurement has not lasted long enough to give an
+    /// accurate value. The measurement is restarted when the sample rate or the
+    /// spectrometer settings change.
+    pub measured_samp_rate: Option<Hertz>,
+    /// Estimated error of the sample clock (in parts per million).
+    ///
+    /// This is positive if the sample clock is faster than its nominal
+    /// frequency, and `None` if the measured sample rate is not available.
+    pub clock_error: Option<f64>,
+    /// Duration of the measurement (in seconds).
+    pub measurement_duration: Seconds,
+}
+
+/// Benchmark JSON schema.
+///
+/// This JSON schema corresponds to POST requests on `/api/benchmark`. It
+/// contains the results of a synthetic benchmark that measures the performance
+/// of maia-httpd on the device, so that the effect of changes made to improve
+/// the performance can be validated. The benchmarks use synthetic data and do
+/// not include the network, so they give the rates that the CPU of the device
+/// can achieve.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct Benchmark {
+    /// Duration of each of the benchmarks (in seconds).
+    pub duration: Seconds,
+    /// Number of spectra processed per second by the spectrometer.
+    ///
+    /// This includes the conversion of the spectra read from the FPGA IP core
+    /// and the update of the noise floor estimate.
+    pub spectrometer_rate: f64,
+    /// Number of simulated waterfall WebSocket clients.
+    pub websocket_clients: usize,
+    /// Number of spectra per second sent to each of the simulated waterfall
+    /// WebSocket clients.
+    pub websocket_rate: f64,
+    /// Total throughput of the waterfall WebSocket (in bytes per second).
+    ///
+    /// This is the sum of the throughputs of all the simulated clients.
+    pub websocket_throughput: f64,
+    /// Recording download speed (in bytes per second).
+    ///
+    /// This is the speed at which the SigMF data file of a recording using the
+    /// current recorder mode is produced. It is `None` if the recording buffer
+    /// was in use.
+    pub recording_download_speed: Option<f64>,
+    /// Time needed to calculate a DDC design (in seconds).
+    pub ddc_design_time: Seconds,
+}
+
+/// System health status.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SystemStatus {
+    /// All the tasks are running normally.
+    Ok,
+    /// A task has failed and has been restarted recently.
+    Degraded,
+}
+
+/// System incident.
+///
+/// An incident describes the failure of a task of maia-httpd.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SystemIncident {
+    /// Time at which the task failed.
+    ///
+    /// This is given as the number of milliseconds since UNIX timestamp, using
+    /// the same format as JavaScript `Date.now()`.
+    pub time: f64,
+    /// Name of the task that failed.
+    pub task: String,
+    /// Description of the error.
+    pub error: String,
+}
+
+/// System LED JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/system/led`. The
+/// system LED shows the state of the device with a blinking pattern, so that
+/// it can be seen at a glance. The LED is the same one that blinks to identify
+/// the device.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SystemLed {
+    /// Whether the LED shows the state of the device.
+    ///
+    /// If this is `false`, the LED is left as configured by the operating
+    /// system.
+    pub enabled: bool,
+    /// Current state of the device.
+    pub state: SystemLedState,
+    /// Pattern shown in each state.
+    pub patterns: SystemLedPatterns,
+}
+
+/// System LED PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/system/led`. It is
+/// used to enable or disable the system LED and to change its patterns.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub struct PatchSystemLed {
+    /// Whether the LED shows the state of the device.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Pattern shown in each state.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patterns: Option<SystemLedPatterns>,
+}
+
+/// System LED state.
+///
+/// If several states apply, the one listed last takes precedence.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SystemLedState {
+    /// No clients are connected and no recording is in progress.
+    Idle,
+    /// Some waterfall clients are connected or some IQ streams are running.
+    Streaming,
+    /// A recording is in progress or the recorder is armed.
+    Recording,
+    /// A task has failed recently (the [`SystemStatus`] is degraded).
+    Error,
+}
+
+/// System LED patterns.
+///
+/// This gives the pattern shown by the system LED in each state.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SystemLedPatterns {
+    /// Pattern in the idle state.
+    pub idle: SystemLedPattern,
+    /// Pattern in the streaming state.
+    pub streaming: SystemLedPattern,
+    /// Pattern in the recording state.
+    pub recording: SystemLedPattern,
+    /// Pattern in the error state.
+    pub error: SystemLedPattern,
+}
+
+impl Default for SystemLedPatterns {
+    fn default() -> SystemLedPatterns {
+        SystemLedPatterns {
+            idle: SystemLedPattern::Heartbeat,
+            streaming: SystemLedPattern::SlowBlink,
+            recording: SystemLedPattern::On,
+            error: SystemLedPattern::FastBlink,
+        }
+    }
+}
+
+impl SystemLedPatterns {
+    /// Returns the pattern for a state.
+    pub fn pattern(&self, state: SystemLedState) -> SystemLedPattern {
+        match state {
+            SystemLedState::Idle => self.idle,
+            SystemLedState::Streaming => self.streaming,
+            SystemLedState::Recording => self.recording,
+            SystemLedState::Error => self.error,
+        }
+    }
+}
+
+/// System LED pattern.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SystemLedPattern {
+    /// The LED is off.
+    Off,
+    /// The LED is on.
+    On,
+    /// The LED blinks once per second.
+    SlowBlink,
+    /// The LED blinks five times per second.
+    FastBlink,
+    /// The LED blinks twice, as a heartbeat.
+    Heartbeat,
+}
+
+/// Demodulators JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/demodulators`. It
+/// lists the kinds of demodulators that can be started and the demodulator
+/// instances that are running.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Demodulators {
+    /// Kinds of demodulators that can be started.
+    pub kinds: Vec<String>,
+    /// Running demodulator instances, sorted by name.
+    pub instances: Vec<Demodulator>,
+}
+
+/// Demodulator instance JSON schema.
+///
+/// This JSON schema corresponds to GET and DELETE requests on
+/// `/api/demodulators/{name}`, and to the response of PUT requests on the same
+/// path.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Demodulator {
+    /// Name of the demodulator instance.
+    pub name: String,
+    /// Kind of demodulator.
+    pub kind: String,
+    /// UDP address to which the output of the demodulator is sent.
+    pub udp_address: String,
+    /// Number of blocks of IQ samples processed by the demodulator.
+    pub blocks: u64,
+}
+
+/// Demodulator instance PUT JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/demodulators/{name}`.
+/// It is used to start a demodulator instance with the given name, replacing
+/// the instance with the same name, if it exists.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PutDemodulator {
+    /// Kind of demodulator.
+    ///
+    /// This should be one of the kinds listed in [`Demodulators`].
+    pub kind: String,
+    /// UDP address to which the output of the demodulator is sent.
+    ///
+    /// The address is given as an IP address and port, such as
+    /// `192.168.2.10:7355`.
+    pub udp_address: String,
+}
+
+/// IQ streams JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/streams`. It lists
+/// the protocols that can be used to stream the DDC output IQ samples over the
+/// network and the streams that are running.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Streams {
+    /// Protocols that can be used by the streams.
+    pub protocols: Vec<StreamProtocol>,
+    /// Running streams, sorted by name.
+    pub instances: Vec<Stream>,
+}
+
+/// IQ stream JSON schema.
+///
+/// This JSON schema corresponds to GET and DELETE requests on
+/// `/api/streams/{name}`, and to the response of PUT requests on the same path.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Stream {
+    /// Name of the stream.
+    pub name: String,
+    /// Protocol used by the stream.
+    pub protocol: StreamProtocol,
+    /// Network address of the stream.
+    ///
+    /// For ZeroMQ streams, this is the endpoint to which the PUB socket has
+    /// been bound. If the port of the endpoint given in the [`PutStream`] is
+    /// zero, this contains the port assigned by the operating system.
+    pub address: String,
+    /// Number of packets sent by the stream.
+    ///
+    /// For UDP streams, this is the number of datagrams. For ZeroMQ streams,
+    /// this is the number of messages.
+    pub packets: u64,
+}
+
+/// IQ stream PUT JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/streams/{name}`. It
+/// is used to start a stream with the given name, replacing the stream with the
+/// same name, if it exists.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PutStream {
+    /// Protocol used by the stream.
+    pub protocol: StreamProtocol,
+    /// Network address of the stream.
+    ///
+    /// For UDP streams, this is the IP address and port to which the datagrams
+    /// are sent, such as `192.168.2.10:5000`. For ZeroMQ streams, this is the
+    /// endpoint to which the PUB socket is bound, such as
+    /// `tcp://0.0.0.0:5555`.
+    pub address: String,
+}
+
+/// IQ stream protocol.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum StreamProtocol {
+    /// UDP datagrams with a sequence header.
+    UDP,
+    /// ZeroMQ PUB socket.
+    ZMQ,
+}
+
+impl_str_conv!(StreamProtocol,
+               "UDP" => UDP,
+               "ZMQ" => ZMQ);
+
+/// Spectrum surveys JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/surveys`. It lists
+/// the spectrum surveys that have been configured.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Surveys {
+    /// Spectrum surveys, sorted by name.
+    pub surveys: Vec<Survey>,
+}
+
+/// Spectrum survey JSON schema.
+///
+/// This JSON schema corresponds to GET and DELETE requests on
+/// `/api/surveys/{name}`, and to the response of PUT requests on the same
+/// path and on `/api/surveys/{name}/run`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Survey {
+    /// Name of the survey.
+    pub name: String,
+    /// Configuration of the survey.
+    pub config: PutSurvey,
+    /// Time of the next scheduled run of the survey.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp, using the
+    /// same format as [`Time`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_run: Option<f64>,
+    /// Whether the survey is running or waiting to run.
+    pub running: bool,
+    /// Reports of the last runs of the survey, from oldest to newest.
+    pub reports: Vec<SurveyReportSummary>,
+}
+
+/// Spectrum survey PUT JSON schema.
+///
+/// This JSON schema corresponds to PUT requests on `/api/surveys/{name}`. It
+/// is used to configure a survey with the given name, replacing the survey
+/// with the same name, if it exists.
+///
+/// In each run of the survey, the AD9361 RX frequency is swept across each of
+/// the bands, and the spectrometer is used to measure the occupancy of each
+/// channel (the fraction of the spectrometer bins in the channel with a power
+/// above the noise floor plus the threshold), the maximum power of each
+/// channel, and the strongest peaks of each band.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PutSurvey {
+    /// Schedule of the survey.
+    ///
+    /// The schedule uses the cron format, with five fields for the minute,
+    /// hour, day of month, month and day of week, such as `*/15 * * * *` or
+    /// `0 8-20 * * 1-5`. Times are in UTC. An empty string means that the
+    /// survey is only run when requested with `/api/surveys/{name}/run`.
+    pub schedule: String,
+    /// Frequency bands swept by the survey.
+    pub bands: Vec<SurveyBand>,
+    /// Width of the channels in which the occupancy is measured (in Hz).
+    pub resolution: Hertz,
+    /// Threshold above the noise floor to consider a bin occupied (in dB).
+    pub threshold: Decibels,
+    /// Time during which spectra are measured at each frequency (in seconds).
+    pub dwell: Seconds,
+}
+
+/// Spectrum survey band JSON schema.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SurveyBand {
+    /// Start frequency of the band (in Hz).
+    pub start_frequency: Hertz,
+    /// Stop frequency of the band (in Hz).
+    pub stop_frequency: Hertz,
+}
+
+/// Spectrum survey report summary JSON schema.
+///
+/// This contains the information about a report listed in [`Survey`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SurveyReportSummary {
+    /// Report identifier.
+    ///
+    /// Identifiers are assigned in increasing order as reports are produced.
+    /// The report can be obtained from `/api/surveys/{name}/reports/{id}`.
+    pub id: u64,
+    /// Start time of the survey run.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp, using the
+    /// same format as [`Time`].
+    pub start_time: f64,
+    /// Duration of the survey run (in seconds).
+    pub duration: Seconds,
+    /// Error that stopped the survey run, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Spectrum survey report JSON schema.
+///
+/// This JSON schema corresponds to GET requests on
+/// `/api/surveys/{name}/reports/{id}`. The report can also be obtained in CSV
+/// format by adding the `format=csv` query parameter. The CSV file has a row
+/// for each channel, with its center frequency, occupancy and maximum power.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SurveyReport {
+    /// Name of the survey.
+    pub survey: String,
+    /// Summary of the report.
+    pub summary: SurveyReportSummary,
+    /// Resolution of the survey (in Hz).
+    ///
+    /// This is the width of each of the channels of the bands.
+    pub resolution: Hertz,
+    /// Results for each of the bands of the survey.
+    ///
+    /// This is empty if the survey run failed.
+    pub bands: Vec<SurveyBandReport>,
+}
+
+/// Spectrum survey band report JSON schema.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SurveyBandReport {
+    /// Band.
+    pub band: SurveyBand,
+    /// Noise floor of the band (in dB).
+    ///
+    /// The noise floor is the median of the noise floors estimated at each of
+    /// the frequencies to which the AD9361 has been tuned.
+    pub noise_floor: Decibels,
+    /// Occupancy of each channel, between 0 and 1.
+    ///
+    /// The first channel starts at the start frequency of the band, and each
+    /// channel has the width given by the survey resolution.
+    pub occupancy: Vec<f32>,
+    /// Maximum power of each channel (in dB).
+    pub max_power: Vec<f32>,
+    /// Strongest peaks of the band, sorted by decreasing power.
+    pub peaks: Vec<SurveyPeak>,
+}
+
+/// Spectrum survey peak JSON schema.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct SurveyPeak {
+    /// Frequency of the peak (in Hz).
+    pub frequency: Hertz,
+    /// Average power of the peak (in dB).
+    pub power: Decibels,
+    /// Power of the peak above the noise floor (in dB).
+    pub snr: Decibels,
+}
+
+/// Doppler correction JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/doppler`. It contains
+/// the settings and the status of the satellite Doppler correction, which
+/// adjusts the DDC frequency during the passes of the target satellite.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Doppler {
+    /// Whether the Doppler correction is enabled.
+    pub enabled: bool,
+    /// Target satellite.
+    ///
+    /// This is `None` if no target has been selected.
+    pub target: Option<DopplerTarget>,
+    /// Current Doppler status of the target satellite.
+    ///
+    /// This is `None` if there is no target, if the device geolocation is
+    /// unknown, or if the orbit of the target cannot be propagated.
+    pub status: Option<DopplerStatus>,
+}
+
+/// Doppler correction target JSON schema.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DopplerTarget {
+    /// Satellite name.
+    pub name: String,
+    /// First line of the satellite TLE.
+    pub tle_line1: String,
+    /// Second line of the satellite TLE.
+    pub tle_line2: String,
+    /// Transmit frequency of the satellite (in Hz).
+    ///
+    /// If an external frequency converter is used, this frequency is given at
+    /// the converter input.
+    pub frequency: Hertz,
+}
+
+/// Doppler correction status JSON schema.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub struct DopplerStatus {
+    /// Doppler shift of the satellite transmit frequency (in Hz).
+    pub correction: Hertz,
+    /// Range rate of the satellite (in m/s).
+    ///
+    /// The range rate is positive when the satellite is moving away from the
+    /// device.
+    pub range_rate: f64,
+    /// Elevation of the satellite (in degrees).
+    pub elevation: f64,
+    /// Whether the correction is being applied to the DDC frequency.
+    ///
+    /// The correction is only applied when it is enabled and the satellite is
+    /// above the horizon.
+    pub applied: bool,
+}
+
+/// Doppler correction PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/doppler`. It is
+/// used to enable or disable the Doppler correction and to select the target
+/// satellite.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PatchDoppler {
+    /// Enables or disables the Doppler correction.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Target satellite.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<DopplerTarget>,
+}
+
+/// Frequency scanner JSON schema.
+///
+/// This JSON schema corresponds to GET requests on `/api/scanner`. It contains
+/// the settings of the scanner, which cycles through a list of channels and
+/// stops on the channels where activity is detected, and its current state.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Scanner {
+    /// Whether the scanner is enabled.
+    pub enabled: bool,
+    /// Channels to scan.
+    pub channels: Vec<ScannerChannel>,
+    /// Time spent on each channel when there is no activity.
+    pub dwell: Seconds,
+    /// Time that the scanner stays on a channel after its activity ends.
+    pub hold: Seconds,
+    /// Activity detection threshold (above the noise floor).
+    pub threshold: Decibels,
+    /// Whether the channels with activity are recorded.
+    ///
+    /// When enabled, a recording is made each time that the scanner stops on
+    /// a channel with activity, if the recorder is not in use and the last
+    /// recording has been discarded with a DELETE request on
+    /// `/api/recording`. The recordings are stored in the library of scanner
+    /// hits, which is available in `/api/scanner/hits`.
+    pub record_hits: bool,
+    /// Duration of the recordings of the scanner hits.
+    pub hit_duration: Seconds,
+    /// Scanner state.
+    pub state: ScannerState,
+    /// Index of the current channel in the list of channels.
+    ///
+    /// This is `None` when the scanner is stopped.
+    pub channel: Option<usize>,
+    /// Error that stopped the scanner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Frequency scanner channel JSON schema.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ScannerChannel {
+    /// Channel frequency.
+    ///
+    /// If an external frequency converter is used, this frequency is given at
+    /// the converter input.
+    pub frequency: Hertz,
+    /// Channel bandwidth.
+    ///
+    /// The activity is detected using the power in this bandwidth.
+    pub bandwidth: Hertz,
+    /// Whether this is a priority channel.
+    ///
+    /// Priority channels are checked after each of the other channels.
+    #[serde(default)]
+    pub priority: bool,
+    /// Channel label.
+    #[serde(default)]
+    pub label: String,
+}
+
+/// Frequency scanner state.
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum ScannerState {
+    /// The scanner is stopped.
+    #[default]
+    Stopped,
+    /// The scanner is cycling through the channels.
+    Scanning,
+    /// The scanner has stopped on a channel with activity.
+    Active,
+    /// The activity of the current channel has ended, and the scanner waits
+    /// for the hold time before resuming.
+    Hold,
+}
+
+/// Frequency scanner PATCH JSON schema.
+///
+/// This JSON schema corresponds to PATCH requests on `/api/scanner`. It is
+/// used to enable or disable the scanner and to change its settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PatchScanner {
+    /// Enables or disables the scanner.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// Channels to scan.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channels: Option<Vec<ScannerChannel>>,
+    /// Time spent on each channel when there is no activity.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dwell: Option<Seconds>,
+    /// Time that the scanner stays on a channel after its activity ends.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold: Option<Seconds>,
+    /// Activity detection threshold (above the noise floor).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<Decibels>,
+    /// Whether the channels with activity are recorded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_hits: Option<bool>,
+    /// Duration of the recordings of the scanner hits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hit_duration: Option<Seconds>,
+}
+
+/// List query parameters.
+///
+/// These are the query parameters accepted by GET requests on the list
+/// resources, which are `/api/recorder/clips`, `/api/recorder/transfers`,
+/// `/api/recorder/hopping/recordings`, `/api/scanner/hits`, `/api/processing`,
+/// `/api/audit` and `/api/clients`. The items of the
+/// list are first filtered by time, and then the page given by the offset and
+/// the limit is returned. The `total` field of the response contains the
+/// number of items that match the filters, so a client can go through a long
+/// list by increasing the offset, or obtain only the number of items by using
+/// a limit of zero.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ListQuery {
+    /// Number of items to skip.
+    ///
+    /// If this is not given, no items are skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<usize>,
+    /// Maximum number of items to return.
+    ///
+    /// If this is not given, all the items after the offset are returned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// Only return items whose time is equal to or later than this.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp, using the
+    /// same format as [`Time`]. Items that do not have a time, such as
+    /// processing jobs, never match this filter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<f64>,
+    /// Only return items whose time is earlier than this.
+    ///
+    /// The time is given in the same format as `since`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until: Option<f64>,
+}
+
 /// Error.
 ///
 /// This JSON schema is used to report errors to the client. It is used whenever
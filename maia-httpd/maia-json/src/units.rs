@@ -0,0 +1,205 @@
+//! Physical units.
+//!
+//! The types in this module wrap an `f64` value given in a particular unit, so
+//! that values given in different units, such as Hz and MHz, or dB and linear
+//! power, cannot be mixed up when they are used. They are serialized as plain
+//! numbers, so a schema field that uses them has the same JSON representation
+//! as an `f64` field.
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+use core::time::Duration;
+use serde::{Deserialize, Serialize};
+
+// Implements the arithmetic operations and the conversions that are common to
+// all the units. Scaling by a number is only implemented for the linear units
+// (not for dB, where it has no physical meaning), by impl_scaling.
+macro_rules! impl_unit {
+    ($ty:ident, $symbol:expr) => {
+        impl $ty {
+            /// Returns whether the value is neither infinite nor NaN.
+            pub fn is_finite(self) -> bool {
+                self.0.is_finite()
+            }
+
+            /// Returns the absolute value.
+            pub fn abs(self) -> $ty {
+                $ty(self.0.abs())
+            }
+        }
+
+        impl From<$ty> for f64 {
+            fn from(value: $ty) -> f64 {
+                value.0
+            }
+        }
+
+        impl Add for $ty {
+            type Output = $ty;
+
+            fn add(self, rhs: $ty) -> $ty {
+                $ty(self.0 + rhs.0)
+            }
+        }
+
+        impl Sub for $ty {
+            type Output = $ty;
+
+            fn sub(self, rhs: $ty) -> $ty {
+                $ty(self.0 - rhs.0)
+            }
+        }
+
+        impl Neg for $ty {
+            type Output = $ty;
+
+            fn neg(self) -> $ty {
+                $ty(-self.0)
+            }
+        }
+
+        impl Div for $ty {
+            type Output = f64;
+
+            fn div(self, rhs: $ty) -> f64 {
+                self.0 / rhs.0
+            }
+        }
+
+        impl core::fmt::Display for $ty {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> Result<(), core::fmt::Error> {
+                self.0.fmt(f)?;
+                f.write_str(concat!(" ", $symbol))
+            }
+        }
+    };
+}
+
+// Implements the multiplication and division of a linear unit by a number.
+macro_rules! impl_scaling {
+    ($ty:ident) => {
+        impl Mul<f64> for $ty {
+            type Output = $ty;
+
+            fn mul(self, rhs: f64) -> $ty {
+                $ty(self.0 * rhs)
+            }
+        }
+
+        impl Div<f64> for $ty {
+            type Output = $ty;
+
+            fn div(self, rhs: f64) -> $ty {
+                $ty(self.0 / rhs)
+            }
+        }
+    };
+}
+
+/// Frequency in Hz.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::Hertz;
+///
+/// let frequency = Hertz::from_mhz(145.5);
+/// assert_eq!(frequency, Hertz(145.5e6));
+/// assert_eq!((frequency + Hertz::from_khz(12.5)).mhz(), 145.5125);
+/// assert_eq!(Hertz(12.5e3).to_string(), "12500 Hz");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, PartialOrd, Default)]
+#[serde(transparent)]
+pub struct Hertz(pub f64);
+
+impl Hertz {
+    /// Creates a frequency given in kHz.
+    pub fn from_khz(khz: f64) -> Hertz {
+        Hertz(khz * 1e3)
+    }
+
+    /// Creates a frequency given in MHz.
+    pub fn from_mhz(mhz: f64) -> Hertz {
+        Hertz(mhz * 1e6)
+    }
+
+    /// Returns the frequency in kHz.
+    pub fn khz(self) -> f64 {
+        self.0 * 1e-3
+    }
+
+    /// Returns the frequency in MHz.
+    pub fn mhz(self) -> f64 {
+        self.0 * 1e-6
+    }
+}
+
+impl_unit!(Hertz, "Hz");
+impl_scaling!(Hertz);
+
+/// Power ratio in dB.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::Decibels;
+///
+/// let snr = Decibels::from_power_ratio(100.0);
+/// assert_eq!(snr, Decibels(20.0));
+/// assert!((Decibels(3.0).power_ratio() - 2.0).abs() < 0.01);
+/// assert_eq!(snr.to_string(), "20 dB");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, PartialOrd, Default)]
+#[serde(transparent)]
+pub struct Decibels(pub f64);
+
+#[cfg(feature = "std")]
+impl Decibels {
+    /// Converts a power ratio in linear units to dB.
+    pub fn from_power_ratio(ratio: f64) -> Decibels {
+        Decibels(10.0 * ratio.log10())
+    }
+
+    /// Returns the power ratio in linear units.
+    pub fn power_ratio(self) -> f64 {
+        10.0_f64.powf(0.1 * self.0)
+    }
+}
+
+impl_unit!(Decibels, "dB");
+
+/// Time interval in seconds.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::Seconds;
+/// use std::time::Duration;
+///
+/// let dwell = Seconds::from(Duration::from_millis(200));
+/// assert_eq!(dwell, Seconds(0.2));
+/// assert_eq!(dwell.duration(), Some(Duration::from_millis(200)));
+/// assert_eq!(Seconds(-1.0).duration(), None);
+/// assert_eq!(Seconds(1.5).to_string(), "1.5 s");
+/// ```
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, PartialOrd, Default)]
+#[serde(transparent)]
+pub struct Seconds(pub f64);
+
+impl Seconds {
+    /// Returns the time interval as a [`Duration`].
+    ///
+    /// This returns `None` if the time interval is negative, not finite, or
+    /// overflows a [`Duration`].
+    pub fn duration(self) -> Option<Duration> {
+        Duration::try_from_secs_f64(self.0).ok()
+    }
+}
+
+impl From<Duration> for Seconds {
+    fn from(duration: Duration) -> Seconds {
+        Seconds(duration.as_secs_f64())
+    }
+}
+
+impl_unit!(Seconds, "s");
+impl_scaling!(Seconds);
@@ -0,0 +1,1130 @@
+//! Golden JSON serialization tests.
+//!
+//! Each schema is serialized and compared with a JSON file of the same name in
+//! `tests/golden`, to detect accidental changes of the wire format. The JSON
+//! files are also deserialized and compared with the original values.
+//!
+//! After an intentional change of the wire format, the JSON files can be
+//! regenerated by running the tests with `MAIA_JSON_UPDATE_GOLDEN=1`.
+
+use maia_json::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::fmt::Debug;
+use std::path::PathBuf;
+
+fn check<T: Serialize + DeserializeOwned + PartialEq + Debug>(name: &str, value: T) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{name}.json"));
+    let json = serde_json::to_string_pretty(&value).unwrap() + "\n";
+    if std::env::var_os("MAIA_JSON_UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &json).unwrap();
+    }
+    let golden = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("could not read {}: {err}", path.display()));
+    assert_eq!(json, golden, "serialization of {name} has changed");
+    let deserialized: T = serde_json::from_str(&golden).unwrap();
+    assert_eq!(deserialized, value, "deserialization of {name} has changed");
+}
+
+fn ad9361() -> Ad9361 {
+    Ad9361 {
+        sampling_frequency: Hertz(61_440_000.0),
+        rx_rf_bandwidth: Hertz(56_000_000.0),
+        rx_rf_bandwidth_mode: Ad9361RfBandwidthMode::Manual,
+        tx_rf_bandwidth: Hertz(56_000_000.0),
+        rx_lo_frequency: Hertz(2_400_000_000.0),
+        tx_lo_frequency: Hertz(2_450_000_000.0),
+        rx_gain: Decibels(70.0),
+        rx_gain_mode: Ad9361GainMode::SlowAttack,
+        tx_gain: Decibels(-10.0),
+    }
+}
+
+fn ad9361_agc() -> Ad9361Agc {
+    Ad9361Agc {
+        outer_thresh_high: 5,
+        outer_thresh_high_dec_steps: 2,
+        inner_thresh_high: 10,
+        inner_thresh_high_dec_steps: 1,
+        inner_thresh_low: 12,
+        inner_thresh_low_inc_steps: 1,
+        outer_thresh_low: 18,
+        outer_thresh_low_inc_steps: 2,
+        attack_delay_extra_margin_us: 1,
+        gain_update_interval_us: 1000,
+        fast_lock_level: 10,
+        fast_state_wait_time_ns: 260,
+        fast_allow_gain_increase: true,
+        fast_gain_increase_after_lock: false,
+        fast_unlock_stronger_signal_thresh: 0,
+        fast_unlock_energy_lost_thresh: 10,
+    }
+}
+
+fn spectrometer() -> Spectrometer {
+    Spectrometer {
+        input: SpectrometerInput::DDC,
+        input_sampling_frequency: Hertz(3_840_000.0),
+        output_sampling_frequency: Hertz(10.0),
+        number_integrations: 94,
+        fft_size: 4096,
+        mode: SpectrometerMode::Average,
+        retune_mode: SpectrometerRetuneMode::AbortAndDrop,
+        wall_clock_alignment: true,
+        noise_floor_estimator: NoiseFloorEstimator::MinimumStatistics,
+        noise_floor_averaging: 16,
+        dc_bin_removal: true,
+        software_integrations: 4,
+        total_integrations: 376,
+        noise_floor: Some(Decibels(-95.5)),
+    }
+}
+
+fn spectrometer_peak() -> SpectrometerPeak {
+    SpectrometerPeak {
+        id: 3,
+        frequency: Hertz(2_400_125_000.0),
+        bandwidth: Hertz(12_500.0),
+        power: Decibels(-42.25),
+        snr: Decibels(30.5),
+        start_time: 1_704_110_400_000.0,
+        duration: Seconds(2.5),
+    }
+}
+
+fn ddc_fir_config() -> DDCFIRConfig {
+    DDCFIRConfig {
+        coefficients: vec![-12, 0, 345, 1024, 345, 0, -12],
+        decimation: 2,
+    }
+}
+
+fn ddc_config_summary() -> DDCConfigSummary {
+    DDCConfigSummary {
+        enabled: true,
+        frequency: Hertz(125_000.0),
+        decimation: 16,
+        input_sampling_frequency: Hertz(61_440_000.0),
+        output_sampling_frequency: Hertz(3_840_000.0),
+        max_input_sampling_frequency: Hertz(61_440_000.0),
+        transition_bandwidth: Some(0.05),
+    }
+}
+
+fn recorder() -> Recorder {
+    Recorder {
+        state: RecorderState::Armed,
+        mode: RecorderMode::IQ12bit,
+        prepend_timestamp: false,
+        maximum_duration: Seconds(10.0),
+        remaining_duration: Some(Seconds(4.5)),
+        trigger_mode: RecorderTriggerMode::StartStop,
+        trigger_polarity: RecorderTriggerPolarity::Falling,
+        armed: Some(RecorderArm {
+            start_time: Some(1_704_110_400_000.0),
+        }),
+        clipping: Some(0.25),
+        clip_mode: true,
+        clip_duration: Seconds(1.0),
+        num_clips: 2,
+        buffer_size_bytes: 16 << 20,
+        max_duration_at_current_settings: Seconds(0.68),
+    }
+}
+
+fn geolocation() -> Geolocation {
+    Geolocation {
+        latitude: 40.5,
+        longitude: -3.75,
+        altitude: Some(650.0),
+    }
+}
+
+fn recording_metadata() -> RecordingMetadata {
+    RecordingMetadata {
+        filename: "recording".to_string(),
+        description: "Test recording".to_string(),
+        author: "Maia SDR".to_string(),
+        geolocation: DeviceGeolocation {
+            point: Some(geolocation()),
+        },
+    }
+}
+
+fn upload_target() -> UploadTarget {
+    UploadTarget {
+        protocol: UploadProtocol::WebDAV,
+        url: "https://example.com/recordings".to_string(),
+        username: "maia".to_string(),
+        password_set: true,
+        region: String::new(),
+    }
+}
+
+fn recording_upload() -> RecordingUpload {
+    RecordingUpload {
+        state: UploadState::Failed,
+        filename: "recording.sigmf".to_string(),
+        bytes_uploaded: 1024,
+        size: 4096,
+        attempt: 3,
+        error: Some("connection refused".to_string()),
+    }
+}
+
+fn device() -> Device {
+    Device {
+        name: "Pluto".to_string(),
+        frequency_offset: Hertz(-9_750_000_000.0),
+    }
+}
+
+fn put_survey() -> PutSurvey {
+    PutSurvey {
+        schedule: "0 * * * *".to_string(),
+        bands: vec![survey_band()],
+        resolution: Hertz(1000.0),
+        threshold: Decibels(10.0),
+        dwell: Seconds(0.5),
+    }
+}
+
+fn survey_band() -> SurveyBand {
+    SurveyBand {
+        start_frequency: Hertz(430_000_000.0),
+        stop_frequency: Hertz(440_000_000.0),
+    }
+}
+
+fn survey_report_summary() -> SurveyReportSummary {
+    SurveyReportSummary {
+        id: 7,
+        start_time: 1_704_110_400_000.0,
+        duration: Seconds(12.5),
+        error: None,
+    }
+}
+
+fn doppler_target() -> DopplerTarget {
+    DopplerTarget {
+        name: "VANGUARD 1".to_string(),
+        tle_line1: "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753"
+            .to_string(),
+        tle_line2: "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667"
+            .to_string(),
+        frequency: Hertz(108e6),
+    }
+}
+
+fn scanner_channel() -> ScannerChannel {
+    ScannerChannel {
+        frequency: Hertz(145_500_000.0),
+        bandwidth: Hertz(12_500.0),
+        priority: true,
+        label: "Calling".to_string(),
+    }
+}
+
+fn scanner() -> Scanner {
+    Scanner {
+        enabled: true,
+        channels: vec![
+            scanner_channel(),
+            ScannerChannel::new(Hertz(145_525_000.0), Hertz(12_500.0)),
+        ],
+        dwell: Seconds(0.5),
+        hold: Seconds(2.0),
+        threshold: Decibels(10.0),
+        record_hits: true,
+        hit_duration: Seconds(5.0),
+        state: ScannerState::Active,
+        channel: Some(0),
+        error: None,
+    }
+}
+
+#[test]
+fn api() {
+    check(
+        "Api",
+        Api {
+            api_version: API_VERSION,
+            ad9361: ad9361(),
+            ddc: ddc_config_summary(),
+            device: device(),
+            errors: vec![ApiSectionError {
+                section: ApiSection::Sensors,
+                error: "failed to read IIO attribute".to_string(),
+            }],
+            geolocation: DeviceGeolocation {
+                point: Some(geolocation()),
+            },
+            recorder: recorder(),
+            recording_annotations: RecordingAnnotations::default(),
+            recording_metadata: recording_metadata(),
+            scanner: scanner(),
+            sensors: Sensors {
+                rx_rssi: Decibels(-80.25),
+                rx_gain: Decibels(70.0),
+            },
+            spectrometer: spectrometer(),
+            time: Time {
+                time: 1_704_110_400_000.0,
+            },
+            upload: recording_upload(),
+            upload_target: upload_target(),
+        },
+    );
+    check(
+        "Error",
+        Error {
+            http_status_code: 400,
+            error_description: "invalid request".to_string(),
+            suggested_action: ErrorAction::Alert,
+        },
+    );
+    check(
+        "ErrorAction",
+        vec![ErrorAction::Alert, ErrorAction::Log, ErrorAction::Ignore],
+    );
+}
+
+#[test]
+fn ad9361_schemas() {
+    check("Ad9361", ad9361());
+    check(
+        "PatchAd9361",
+        PatchAd9361 {
+            rx_lo_frequency: Some(Hertz(433_920_000.0)),
+            rx_gain_mode: Some(Ad9361GainMode::Manual),
+            rx_gain: Some(Decibels(50.0)),
+            ..Default::default()
+        },
+    );
+    check(
+        "Ad9361RfBandwidthMode",
+        vec![Ad9361RfBandwidthMode::Auto, Ad9361RfBandwidthMode::Manual],
+    );
+    check(
+        "Ad9361GainMode",
+        vec![
+            Ad9361GainMode::Manual,
+            Ad9361GainMode::FastAttack,
+            Ad9361GainMode::SlowAttack,
+            Ad9361GainMode::Hybrid,
+        ],
+    );
+    check("Ad9361Agc", ad9361_agc());
+    check(
+        "PatchAd9361Agc",
+        PatchAd9361Agc {
+            inner_thresh_high: Some(8),
+            fast_allow_gain_increase: Some(false),
+            ..Default::default()
+        },
+    );
+    check(
+        "Ad9361IqImbalance",
+        Ad9361IqImbalance {
+            rx_lo_frequency: Hertz(2_400_000_000.0),
+            sampling_frequency: Hertz(61_440_000.0),
+            rx_gain: Decibels(40.0),
+            rx_gain_mode: Ad9361GainMode::Manual,
+            num_samples: 614_400,
+            power_dbfs: Decibels(-32.5),
+            dc_offset_i: 0.0012,
+            dc_offset_q: -0.0008,
+            dc_offset_dbfs: Some(Decibels(-56.8)),
+            image_rejection_db: Some(Decibels(48.2)),
+            gain_imbalance_db: Decibels(0.03),
+            phase_error_deg: -0.4,
+        },
+    );
+    check(
+        "Sensors",
+        Sensors {
+            rx_rssi: Decibels(-80.25),
+            rx_gain: Decibels(70.0),
+        },
+    );
+}
+
+#[test]
+fn spectrometer_schemas() {
+    check("Spectrometer", spectrometer());
+    check(
+        "SpectrometerFormat",
+        SpectrometerFormat {
+            fft_size: 4096,
+            bin_order: SpectrometerBinOrder::FftShifted,
+            datatype: "f32_le".to_string(),
+            units: SpectrometerUnits::LinearPower,
+            scale: 0.015625,
+            equalization: false,
+            dc_bin_removal: true,
+            center_frequency: Hertz(2_400_000_000.0),
+            bin_width: Hertz(15_000.0),
+        },
+    );
+    check(
+        "SpectrometerBinOrder",
+        vec![
+            SpectrometerBinOrder::FftShifted,
+            SpectrometerBinOrder::Natural,
+        ],
+    );
+    check(
+        "PatchSpectrometer",
+        PatchSpectrometer {
+            input: Some(SpectrometerInput::AD9361),
+            output_sampling_frequency: Some(Hertz(20.0)),
+            mode: Some(SpectrometerMode::PeakDetect),
+            noise_floor_averaging: Some(4),
+            ..Default::default()
+        },
+    );
+    check(
+        "SpectrometerInput",
+        vec![SpectrometerInput::AD9361, SpectrometerInput::DDC],
+    );
+    check(
+        "SpectrometerMode",
+        vec![SpectrometerMode::Average, SpectrometerMode::PeakDetect],
+    );
+    check(
+        "NoiseFloorEstimator",
+        vec![
+            NoiseFloorEstimator::Median,
+            NoiseFloorEstimator::MinimumStatistics,
+        ],
+    );
+    check(
+        "SpectrometerRetuneMode",
+        vec![
+            SpectrometerRetuneMode::Continue,
+            SpectrometerRetuneMode::Abort,
+            SpectrometerRetuneMode::AbortAndDrop,
+        ],
+    );
+    check(
+        "SpectrometerEqualization",
+        SpectrometerEqualization {
+            enabled: true,
+            points: vec![
+                SpectrometerEqualizationPoint {
+                    frequency: Hertz(-28e6),
+                    gain: Decibels(1.5),
+                },
+                SpectrometerEqualizationPoint {
+                    frequency: Hertz(28e6),
+                    gain: Decibels(1.25),
+                },
+            ],
+        },
+    );
+    check(
+        "SpectrometerPeaks",
+        SpectrometerPeaks {
+            threshold: Decibels(10.0),
+            active: vec![spectrometer_peak()],
+            recent: vec![],
+        },
+    );
+    check(
+        "PatchSpectrometerPeaks",
+        PatchSpectrometerPeaks {
+            threshold: Some(Decibels(6.0)),
+        },
+    );
+    check("SpectrometerPeak", spectrometer_peak());
+    check(
+        "SpectrometerPeakEvent",
+        vec![
+            SpectrometerPeakEvent::Start(spectrometer_peak()),
+            SpectrometerPeakEvent::End(spectrometer_peak()),
+        ],
+    );
+}
+
+#[test]
+fn waterfall_schemas() {
+    check("WaterfallDropped", WaterfallDropped { dropped_lines: 5 });
+    check(
+        "WaterfallTimestamp",
+        WaterfallTimestamp {
+            timestamp: 1_704_110_400_000.0,
+        },
+    );
+    check(
+        "WaterfallConfigChange",
+        WaterfallConfigChange {
+            path: "/api/v1/ad9361".to_string(),
+        },
+    );
+    check(
+        "WaterfallAlert",
+        WaterfallAlert {
+            time: 1_704_110_400_000.0,
+            message: "spectrometer failed".to_string(),
+        },
+    );
+}
+
+#[test]
+fn colormap_schemas() {
+    let parameters = ColormapParameters {
+        start_hue: 270.0,
+        end_hue: 450.0,
+        gamma: 1.5,
+    };
+    let colormap = Colormap {
+        name: Some("night".to_string()),
+        parameters: parameters.clone(),
+        colors: vec![[0, 0, 4], [120, 40, 160], [252, 255, 164]],
+    };
+    check(
+        "Colormaps",
+        Colormaps {
+            colormaps: vec![colormap.clone()],
+        },
+    );
+    check("Colormap", colormap);
+    check("ColormapParameters", parameters);
+}
+
+#[test]
+fn ddc_schemas() {
+    check(
+        "PutDDCDesign",
+        PutDDCDesign {
+            frequency: Hertz(125_000.0),
+            decimation: 16,
+            transition_bandwidth: Some(0.1),
+            passband_ripple: Some(0.01),
+            stopband_attenuation_db: None,
+            stopband_one_over_f: Some(true),
+        },
+    );
+    check(
+        "DDCConfig",
+        DDCConfig {
+            enabled: true,
+            frequency: Hertz(125_000.0),
+            decimation: 4,
+            input_sampling_frequency: Hertz(61_440_000.0),
+            output_sampling_frequency: Hertz(15_360_000.0),
+            max_input_sampling_frequency: Hertz(61_440_000.0),
+            transition_bandwidth: Some(0.05),
+            fir1: ddc_fir_config(),
+            fir2: Some(ddc_fir_config()),
+            fir3: None,
+        },
+    );
+    check("DDCConfigSummary", ddc_config_summary());
+    let errors = DDCFilterErrors {
+        passband_ripple: 0.005,
+        stopband_attenuation_db: Decibels(62.5),
+    };
+    check(
+        "DDCDesign",
+        DDCDesign {
+            config: DDCConfig {
+                enabled: true,
+                frequency: Hertz(125_000.0),
+                decimation: 16,
+                input_sampling_frequency: Hertz(61_440_000.0),
+                output_sampling_frequency: Hertz(3_840_000.0),
+                max_input_sampling_frequency: Hertz(61_440_000.0),
+                transition_bandwidth: Some(0.1),
+                fir1: ddc_fir_config(),
+                fir2: None,
+                fir3: Some(ddc_fir_config()),
+            },
+            error_budget: DDCErrorBudget {
+                fir1: errors,
+                fir2: None,
+                fir3: Some(errors),
+                composite: DDCFilterErrors {
+                    passband_ripple: 0.010025,
+                    stopband_attenuation_db: Decibels(62.45),
+                },
+            },
+        },
+    );
+    check(
+        "DDCSnr",
+        DDCSnr {
+            snr_db: Some(Decibels(20.5)),
+            channel_power_db: Decibels(-60.0),
+            noise_floor_db: Decibels(-80.5),
+        },
+    );
+    check(
+        "DDCNoiseBlanker",
+        DDCNoiseBlanker {
+            live: true,
+            processing: false,
+            threshold_db: Decibels(12.0),
+            blanking_duration: Seconds(100e-6),
+        },
+    );
+    check(
+        "PatchDDCNoiseBlanker",
+        PatchDDCNoiseBlanker {
+            processing: Some(true),
+            threshold_db: Some(Decibels(15.0)),
+            ..Default::default()
+        },
+    );
+    check(
+        "PutDDCConfig",
+        PutDDCConfig {
+            frequency: Hertz(-250_000.0),
+            fir1: ddc_fir_config(),
+            fir2: None,
+            fir3: Some(ddc_fir_config()),
+            transition_bandwidth: None,
+        },
+    );
+    check(
+        "PatchDDCConfig",
+        PatchDDCConfig {
+            frequency: Some(Hertz(500_000.0)),
+        },
+    );
+    check("DDCFIRConfig", ddc_fir_config());
+}
+
+#[test]
+fn recorder_schemas() {
+    check("Recorder", recorder());
+    check(
+        "PatchRecorder",
+        PatchRecorder {
+            state_change: Some(RecorderStateChange::Start),
+            mode: Some(RecorderMode::IQ16bit),
+            maximum_duration: Some(Seconds(0.0)),
+            clip_duration: Some(Seconds(0.5)),
+            ..Default::default()
+        },
+    );
+    check(
+        "RecorderClips",
+        RecorderClips {
+            clips: vec![RecorderClip {
+                id: 1,
+                filename: "recording_clip0001".to_string(),
+                start_time: 1_704_110_400_000.0,
+                duration: Seconds(1.0),
+                frequency: Hertz(2_400_000_000.0),
+                sample_rate: Hertz(3_840_000.0),
+                size: 15_360_000,
+            }],
+            total: 5,
+        },
+    );
+    check(
+        "RecorderTransfers",
+        RecorderTransfers {
+            transfers: vec![RecorderTransfer {
+                id: 0,
+                filename: "recording.sigmf".to_string(),
+                client: Some("192.0.2.1:40000".to_string()),
+                size: 15_361_536,
+                bytes_sent: 7_680_000,
+                rate: 3_840_000.0,
+                duration: Seconds(2.0),
+            }],
+            total: 1,
+        },
+    );
+    let hopping = PutRecorderHopping {
+        frequencies: vec![Hertz(433.1e6), Hertz(433.9e6), Hertz(434.7e6)],
+        burst_duration: Seconds(0.05),
+        settle_time: Seconds(0.01),
+        cycles: 4,
+    };
+    check("PutRecorderHopping", hopping.clone());
+    check(
+        "RecorderHopping",
+        RecorderHopping {
+            running: true,
+            config: Some(hopping),
+            segments: 5,
+            total_segments: 12,
+            error: None,
+        },
+    );
+    check("RecorderArm", RecorderArm { start_time: None });
+    check(
+        "RecorderTriggerMode",
+        vec![RecorderTriggerMode::Start, RecorderTriggerMode::StartStop],
+    );
+    check(
+        "RecorderTriggerPolarity",
+        vec![
+            RecorderTriggerPolarity::Rising,
+            RecorderTriggerPolarity::Falling,
+        ],
+    );
+    check(
+        "RecorderStateChange",
+        vec![RecorderStateChange::Start, RecorderStateChange::Stop],
+    );
+    check(
+        "RecorderMode",
+        vec![
+            RecorderMode::IQ8bit,
+            RecorderMode::IQ12bit,
+            RecorderMode::IQ16bit,
+        ],
+    );
+    check(
+        "RecorderState",
+        vec![
+            RecorderState::Stopped,
+            RecorderState::Running,
+            RecorderState::Stopping,
+            RecorderState::Armed,
+        ],
+    );
+}
+
+#[test]
+fn recording_schemas() {
+    check("Geolocation", geolocation());
+    check("RecordingMetadata", recording_metadata());
+    check(
+        "PatchRecordingMetadata",
+        PatchRecordingMetadata {
+            description: Some("New description".to_string()),
+            geolocation: Some(DeviceGeolocation { point: None }),
+            ..Default::default()
+        },
+    );
+    check(
+        "RecordingChecksum",
+        RecordingChecksum {
+            sha512: None,
+            in_progress: true,
+        },
+    );
+    check(
+        "RecordingStats",
+        RecordingStats {
+            num_samples: 1_000_000,
+            dc_offset_i: 0.0025,
+            dc_offset_q: -0.001,
+            power_dbfs: Some(Decibels(-23.5)),
+            clipped_samples: 12,
+            histogram: vec![0, 0, 1_000, 999_000, 998_794, 1_200, 6, 0],
+        },
+    );
+    check(
+        "RecordingAnnotations",
+        RecordingAnnotations {
+            annotations: vec![RecordingAnnotation {
+                id: 3,
+                start_time: 1_704_110_400_000.0,
+                end_time: 1_704_110_401_500.0,
+                frequency_min: Hertz(145_790_000.0),
+                frequency_max: Hertz(145_810_000.0),
+                label: "ISS APRS".to_string(),
+                comment: "Packet from the digipeater".to_string(),
+            }],
+        },
+    );
+    check(
+        "PostRecordingAnnotation",
+        PostRecordingAnnotation {
+            start_time: 1_704_110_400_000.0,
+            end_time: 1_704_110_401_500.0,
+            frequency_min: Hertz(145_790_000.0),
+            frequency_max: Hertz(145_810_000.0),
+            label: "ISS APRS".to_string(),
+            comment: String::new(),
+        },
+    );
+    check(
+        "RecordingTrim",
+        RecordingTrim {
+            start_sample: 1_000_000,
+            end_sample: Some(2_000_000),
+        },
+    );
+    check(
+        "PatchRecordingTrim",
+        PatchRecordingTrim {
+            start_time: Some(0.5),
+            end_sample: Some(2_000_000),
+            ..Default::default()
+        },
+    );
+    check(
+        "RecordingFinished",
+        RecordingFinished {
+            metadata: recording_metadata(),
+            mode: RecorderMode::IQ8bit,
+            start_time: 1_704_110_400_000.0,
+            duration: Seconds(2.5),
+            frequency: Hertz(2_400_000_000.0),
+            sample_rate: Hertz(3_840_000.0),
+            size: 19_200_000,
+            sha512: "cf83e1357eefb8bd".to_string(),
+            clipping: 0.0,
+        },
+    );
+    check("UploadTarget", upload_target());
+    check(
+        "PatchUploadTarget",
+        PatchUploadTarget {
+            protocol: Some(UploadProtocol::S3),
+            password: Some("secret".to_string()),
+            region: Some("eu-west-1".to_string()),
+            ..Default::default()
+        },
+    );
+    check(
+        "UploadProtocol",
+        vec![UploadProtocol::S3, UploadProtocol::WebDAV],
+    );
+    check("RecordingUpload", recording_upload());
+    check(
+        "UploadState",
+        vec![
+            UploadState::Idle,
+            UploadState::Uploading,
+            UploadState::Finished,
+            UploadState::Failed,
+        ],
+    );
+}
+
+#[test]
+fn processing_schemas() {
+    let job = ProcessingJob {
+        id: 3,
+        state: ProcessingJobState::Failed,
+        progress: 0.25,
+        filename: "recording_ddc".to_string(),
+        frequency: Hertz(2_400_100_000.0),
+        decimation: 20,
+        output_sampling_frequency: Hertz(192_000.0),
+        size: 1_536_000,
+        error: Some("job panicked".to_string()),
+    };
+    check(
+        "ProcessingJobs",
+        ProcessingJobs {
+            jobs: vec![job.clone()],
+            total: 1,
+        },
+    );
+    check("ProcessingJob", job);
+    check(
+        "ProcessingJobState",
+        vec![
+            ProcessingJobState::Running,
+            ProcessingJobState::Finished,
+            ProcessingJobState::Failed,
+        ],
+    );
+    check(
+        "PostProcessingJob",
+        PostProcessingJob {
+            filename: Some("narrowband".to_string()),
+            config: Some(PutDDCConfig {
+                frequency: Hertz(100_000.0),
+                fir1: ddc_fir_config(),
+                fir2: None,
+                fir3: None,
+                transition_bandwidth: Some(0.05),
+            }),
+            design: None,
+        },
+    );
+}
+
+#[test]
+fn device_schemas() {
+    check(
+        "Time",
+        Time {
+            time: 1_704_110_400_000.0,
+        },
+    );
+    check(
+        "PatchTime",
+        PatchTime {
+            time: Some(1_704_110_400_000.0),
+        },
+    );
+    check("Device", device());
+    check(
+        "PatchDevice",
+        PatchDevice {
+            name: Some("Pluto".to_string()),
+            frequency_offset: None,
+        },
+    );
+    check(
+        "Identify",
+        Identify {
+            duration: Seconds(5.0),
+        },
+    );
+    check(
+        "Limits",
+        Limits {
+            max_tx_gain: Some(Decibels(-20.0)),
+            frequency_allowlist: vec![LimitsFrequencyRange {
+                start_frequency: Hertz(144_000_000.0),
+                stop_frequency: Hertz(146_000_000.0),
+            }],
+            frequency_blocklist: vec![LimitsFrequencyRange {
+                start_frequency: Hertz(145_800_000.0),
+                stop_frequency: Hertz(146_000_000.0),
+            }],
+        },
+    );
+    check(
+        "StartupProfile",
+        StartupProfile {
+            ad9361: Some(PatchAd9361 {
+                sampling_frequency: Some(Hertz(30_720_000.0)),
+                rx_lo_frequency: Some(Hertz(2_400_000_000.0)),
+                rx_gain_mode: Some(Ad9361GainMode::SlowAttack),
+                ..Default::default()
+            }),
+            ddc: None,
+            spectrometer: Some(PatchSpectrometer {
+                input: Some(SpectrometerInput::AD9361),
+                output_sampling_frequency: Some(Hertz(20.0)),
+                ..Default::default()
+            }),
+        },
+    );
+    check(
+        "Power",
+        Power {
+            action: PowerAction::Reboot,
+        },
+    );
+    check("DeviceGeolocation", DeviceGeolocation { point: None });
+    check(
+        "Audit",
+        Audit {
+            entries: vec![AuditEntry {
+                time: 1_704_110_400_000.0,
+                client: "192.168.2.10:51234".to_string(),
+                method: "PATCH".to_string(),
+                path: "/api/v1/ad9361".to_string(),
+                body: r#"{"rx_gain":50.0}"#.to_string(),
+                http_status_code: 200,
+            }],
+            total: 1,
+        },
+    );
+    check(
+        "WebSocketClients",
+        WebSocketClients {
+            clients: vec![WebSocketClient {
+                id: 3,
+                websocket: WebSocketKind::Waterfall,
+                client: Some("192.168.2.10:51236".to_string()),
+                protocol: Some(WATERFALL_PROTOCOL_V4.to_string()),
+                connect_time: 1_704_110_400_000.0,
+                duration: Seconds(60.0),
+                messages_sent: 600,
+                bytes_sent: 9_830_400,
+                rate: 163_840.0,
+            }],
+            total: 1,
+        },
+    );
+    check(
+        "ListQuery",
+        ListQuery {
+            offset: Some(20),
+            limit: Some(10),
+            since: Some(1_704_110_400_000.0),
+            until: None,
+        },
+    );
+    check(
+        "System",
+        System {
+            status: SystemStatus::Degraded,
+            restarts: 1,
+            incidents: vec![SystemIncident {
+                time: 1_704_110_400_000.0,
+                task: "spectrometer".to_string(),
+                error: "no spectra received for 10 s".to_string(),
+            }],
+        },
+    );
+    check(
+        "SystemClock",
+        SystemClock {
+            nominal_samp_rate: Hertz(61.44e6),
+            measured_samp_rate: Some(Hertz(61_440_122.88)),
+            clock_error: Some(2.0),
+            measurement_duration: Seconds(300.0),
+        },
+    );
+    check(
+        "Benchmark",
+        Benchmark {
+            duration: Seconds(1.0),
+            spectrometer_rate: 5210.5,
+            websocket_clients: 4,
+            websocket_rate: 1302.6,
+            websocket_throughput: 85_390_540.8,
+            recording_download_speed: Some(96_468_992.0),
+            ddc_design_time: Seconds(0.35),
+        },
+    );
+    check(
+        "SystemLed",
+        SystemLed {
+            enabled: true,
+            state: SystemLedState::Recording,
+            patterns: SystemLedPatterns::default(),
+        },
+    );
+    check(
+        "PatchSystemLed",
+        PatchSystemLed {
+            enabled: Some(false),
+            patterns: None,
+        },
+    );
+}
+
+#[test]
+fn stream_schemas() {
+    check(
+        "Demodulators",
+        Demodulators {
+            kinds: vec!["FM".to_string(), "AM".to_string()],
+            instances: vec![Demodulator {
+                name: "fm".to_string(),
+                kind: "FM".to_string(),
+                udp_address: "192.168.2.10:7355".to_string(),
+                blocks: 100,
+            }],
+        },
+    );
+    check(
+        "PutDemodulator",
+        PutDemodulator {
+            kind: "AM".to_string(),
+            udp_address: "192.168.2.10:7356".to_string(),
+        },
+    );
+    check(
+        "Streams",
+        Streams {
+            protocols: vec![StreamProtocol::UDP, StreamProtocol::ZMQ],
+            instances: vec![Stream {
+                name: "iq".to_string(),
+                protocol: StreamProtocol::UDP,
+                address: "192.168.2.10:5000".to_string(),
+                packets: 1000,
+            }],
+        },
+    );
+    check(
+        "PutStream",
+        PutStream {
+            protocol: StreamProtocol::ZMQ,
+            address: "tcp://0.0.0.0:5555".to_string(),
+        },
+    );
+}
+
+#[test]
+fn survey_schemas() {
+    check(
+        "Surveys",
+        Surveys {
+            surveys: vec![Survey {
+                name: "uhf".to_string(),
+                config: put_survey(),
+                next_run: Some(1_704_114_000_000.0),
+                running: false,
+                reports: vec![survey_report_summary()],
+            }],
+        },
+    );
+    check("PutSurvey", put_survey());
+    check(
+        "SurveyReport",
+        SurveyReport {
+            survey: "uhf".to_string(),
+            summary: survey_report_summary(),
+            resolution: Hertz(1000.0),
+            bands: vec![SurveyBandReport {
+                band: survey_band(),
+                noise_floor: Decibels(-95.0),
+                occupancy: vec![0.0, 0.5, 1.0],
+                max_power: vec![-90.0, -60.5, -45.25],
+                peaks: vec![SurveyPeak {
+                    frequency: Hertz(433_920_000.0),
+                    power: Decibels(-45.25),
+                    snr: Decibels(49.75),
+                }],
+            }],
+        },
+    );
+}
+
+#[test]
+fn doppler_schemas() {
+    check(
+        "Doppler",
+        Doppler {
+            enabled: true,
+            target: Some(doppler_target()),
+            status: Some(DopplerStatus {
+                correction: Hertz(2_500.0),
+                range_rate: -6_939.5,
+                elevation: 35.25,
+                applied: true,
+            }),
+        },
+    );
+    check(
+        "PatchDoppler",
+        PatchDoppler {
+            enabled: Some(false),
+            target: Some(doppler_target()),
+        },
+    );
+}
+
+#[test]
+fn scanner_schemas() {
+    check("Scanner", scanner());
+    check(
+        "PatchScanner",
+        PatchScanner {
+            enabled: Some(true),
+            channels: Some(vec![scanner_channel()]),
+            threshold: Some(Decibels(6.0)),
+            record_hits: Some(false),
+            ..Default::default()
+        },
+    );
+    check(
+        "ScannerState",
+        vec![
+            ScannerState::Stopped,
+            ScannerState::Scanning,
+            ScannerState::Active,
+            ScannerState::Hold,
+        ],
+    );
+}
@@ -777,13 +777,16 @@ pub mod maia_sdr {
         interrupts: Interrupts,
         recorder_control: RecorderControl,
         recorder_next_address: RecorderNextAddress,
-        _reserved6: [u8; 0x08],
+        recorder_start_sample_lo: RecorderStartSampleLo,
+        recorder_start_sample_hi: RecorderStartSampleHi,
         spectrometer: Spectrometer,
         ddc_coeff_addr: DdcCoeffAddr,
         ddc_coeff: DdcCoeff,
         ddc_decimation: DdcDecimation,
         ddc_frequency: DdcFrequency,
         ddc_control: DdcControl,
+        sample_counter_lo: SampleCounterLo,
+        sample_counter_hi: SampleCounterHi,
     }
     impl RegisterBlock {
         #[doc = "0x00 - product_id"]
@@ -816,6 +819,16 @@ pub mod maia_sdr {
         pub const fn recorder_next_address(&self) -> &RecorderNextAddress {
             &self.recorder_next_address
         }
+        #[doc = "0x18 - recorder_start_sample_lo"]
+        #[inline(always)]
+        pub const fn recorder_start_sample_lo(&self) -> &RecorderStartSampleLo {
+            &self.recorder_start_sample_lo
+        }
+        #[doc = "0x1c - recorder_start_sample_hi"]
+        #[inline(always)]
+        pub const fn recorder_start_sample_hi(&self) -> &RecorderStartSampleHi {
+            &self.recorder_start_sample_hi
+        }
         #[doc = "0x20 - spectrometer"]
         #[inline(always)]
         pub const fn spectrometer(&self) -> &Spectrometer {
@@ -846,6 +859,16 @@ pub mod maia_sdr {
         pub const fn ddc_control(&self) -> &DdcControl {
             &self.ddc_control
         }
+        #[doc = "0x38 - sample_counter_lo"]
+        #[inline(always)]
+        pub const fn sample_counter_lo(&self) -> &SampleCounterLo {
+            &self.sample_counter_lo
+        }
+        #[doc = "0x3c - sample_counter_hi"]
+        #[inline(always)]
+        pub const fn sample_counter_hi(&self) -> &SampleCounterHi {
+            &self.sample_counter_hi
+        }
     }
     #[doc = "product_id (r) register accessor: product_id\n\nYou can [`read`](crate::Reg::read) this register and get [`product_id::R`]. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@product_id`]
 module"]
@@ -1013,6 +1036,20 @@ module"]
         pub type ModeW<'a, REG> = crate::FieldWriter<'a, REG, 2>;
         #[doc = "Field `dropped_samples` reader - dropped_samples"]
         pub type DroppedSamplesR = crate::BitReader;
+        #[doc = "Field `arm_trigger` writer - arm_trigger"]
+        pub type ArmTriggerW<'a, REG> = crate::BitWriter<'a, REG>;
+        #[doc = "Field `disarm` writer - disarm"]
+        pub type DisarmW<'a, REG> = crate::BitWriter<'a, REG>;
+        #[doc = "Field `armed` reader - armed"]
+        pub type ArmedR = crate::BitReader;
+        #[doc = "Field `trigger_polarity` reader - trigger_polarity"]
+        pub type TriggerPolarityR = crate::BitReader;
+        #[doc = "Field `trigger_polarity` writer - trigger_polarity"]
+        pub type TriggerPolarityW<'a, REG> = crate::BitWriter<'a, REG>;
+        #[doc = "Field `trigger_stop` reader - trigger_stop"]
+        pub type TriggerStopR = crate::BitReader;
+        #[doc = "Field `trigger_stop` writer - trigger_stop"]
+        pub type TriggerStopW<'a, REG> = crate::BitWriter<'a, REG>;
         impl R {
             #[doc = "Bits 2:3 - mode"]
             #[inline(always)]
@@ -1024,6 +1061,21 @@ module"]
             pub fn dropped_samples(&self) -> DroppedSamplesR {
                 DroppedSamplesR::new(((self.bits >> 4) & 1) != 0)
             }
+            #[doc = "Bit 7 - armed"]
+            #[inline(always)]
+            pub fn armed(&self) -> ArmedR {
+                ArmedR::new(((self.bits >> 7) & 1) != 0)
+            }
+            #[doc = "Bit 8 - trigger_polarity"]
+            #[inline(always)]
+            pub fn trigger_polarity(&self) -> TriggerPolarityR {
+                TriggerPolarityR::new(((self.bits >> 8) & 1) != 0)
+            }
+            #[doc = "Bit 9 - trigger_stop"]
+            #[inline(always)]
+            pub fn trigger_stop(&self) -> TriggerStopR {
+                TriggerStopR::new(((self.bits >> 9) & 1) != 0)
+            }
         }
         impl W {
             #[doc = "Bit 0 - start"]
@@ -1044,6 +1096,30 @@ module"]
             pub fn mode(&mut self) -> ModeW<RecorderControlSpec> {
                 ModeW::new(self, 2)
             }
+            #[doc = "Bit 5 - arm_trigger"]
+            #[inline(always)]
+            #[must_use]
+            pub fn arm_trigger(&mut self) -> ArmTriggerW<RecorderControlSpec> {
+                ArmTriggerW::new(self, 5)
+            }
+            #[doc = "Bit 6 - disarm"]
+            #[inline(always)]
+            #[must_use]
+            pub fn disarm(&mut self) -> DisarmW<RecorderControlSpec> {
+                DisarmW::new(self, 6)
+            }
+            #[doc = "Bit 8 - trigger_polarity"]
+            #[inline(always)]
+            #[must_use]
+            pub fn trigger_polarity(&mut self) -> TriggerPolarityW<RecorderControlSpec> {
+                TriggerPolarityW::new(self, 8)
+            }
+            #[doc = "Bit 9 - trigger_stop"]
+            #[inline(always)]
+            #[must_use]
+            pub fn trigger_stop(&mut self) -> TriggerStopW<RecorderControlSpec> {
+                TriggerStopW::new(self, 9)
+            }
         }
         #[doc = "recorder_control\n\nYou can [`read`](crate::Reg::read) this register and get [`recorder_control::R`](R). You can [`write_with_zero`](crate::Reg::write_with_zero) this register using [`recorder_control::W`](W). You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
         pub struct RecorderControlSpec;
@@ -1084,6 +1160,58 @@ module"]
         #[doc = "`read()` method returns [`recorder_next_address::R`](R) reader structure"]
         impl crate::Readable for RecorderNextAddressSpec {}
     }
+    #[doc = "recorder_start_sample_lo (r) register accessor: recorder_start_sample_lo\n\nYou can [`read`](crate::Reg::read) this register and get [`recorder_start_sample_lo::R`]. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@recorder_start_sample_lo`]
+module"]
+    #[doc(alias = "recorder_start_sample_lo")]
+    pub type RecorderStartSampleLo =
+        crate::Reg<recorder_start_sample_lo::RecorderStartSampleLoSpec>;
+    #[doc = "recorder_start_sample_lo"]
+    pub mod recorder_start_sample_lo {
+        #[doc = "Register `recorder_start_sample_lo` reader"]
+        pub type R = crate::R<RecorderStartSampleLoSpec>;
+        #[doc = "Field `start_sample_lo` reader - start_sample_lo"]
+        pub type StartSampleLoR = crate::FieldReader<u32>;
+        impl R {
+            #[doc = "Bits 0:31 - start_sample_lo"]
+            #[inline(always)]
+            pub fn start_sample_lo(&self) -> StartSampleLoR {
+                StartSampleLoR::new(self.bits)
+            }
+        }
+        #[doc = "recorder_start_sample_lo\n\nYou can [`read`](crate::Reg::read) this register and get [`recorder_start_sample_lo::R`](R). See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
+        pub struct RecorderStartSampleLoSpec;
+        impl crate::RegisterSpec for RecorderStartSampleLoSpec {
+            type Ux = u32;
+        }
+        #[doc = "`read()` method returns [`recorder_start_sample_lo::R`](R) reader structure"]
+        impl crate::Readable for RecorderStartSampleLoSpec {}
+    }
+    #[doc = "recorder_start_sample_hi (r) register accessor: recorder_start_sample_hi\n\nYou can [`read`](crate::Reg::read) this register and get [`recorder_start_sample_hi::R`]. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@recorder_start_sample_hi`]
+module"]
+    #[doc(alias = "recorder_start_sample_hi")]
+    pub type RecorderStartSampleHi =
+        crate::Reg<recorder_start_sample_hi::RecorderStartSampleHiSpec>;
+    #[doc = "recorder_start_sample_hi"]
+    pub mod recorder_start_sample_hi {
+        #[doc = "Register `recorder_start_sample_hi` reader"]
+        pub type R = crate::R<RecorderStartSampleHiSpec>;
+        #[doc = "Field `start_sample_hi` reader - start_sample_hi"]
+        pub type StartSampleHiR = crate::FieldReader<u16>;
+        impl R {
+            #[doc = "Bits 0:15 - start_sample_hi"]
+            #[inline(always)]
+            pub fn start_sample_hi(&self) -> StartSampleHiR {
+                StartSampleHiR::new((self.bits & 0xffff) as u16)
+            }
+        }
+        #[doc = "recorder_start_sample_hi\n\nYou can [`read`](crate::Reg::read) this register and get [`recorder_start_sample_hi::R`](R). See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
+        pub struct RecorderStartSampleHiSpec;
+        impl crate::RegisterSpec for RecorderStartSampleHiSpec {
+            type Ux = u32;
+        }
+        #[doc = "`read()` method returns [`recorder_start_sample_hi::R`](R) reader structure"]
+        impl crate::Readable for RecorderStartSampleHiSpec {}
+    }
     #[doc = "spectrometer (rw) register accessor: spectrometer\n\nYou can [`read`](crate::Reg::read) this register and get [`spectrometer::R`]. You can [`write_with_zero`](crate::Reg::write_with_zero) this register using [`spectrometer::W`]. You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@spectrometer`]
 module"]
     #[doc(alias = "spectrometer")]
@@ -1530,6 +1658,103 @@ module"]
             const ONE_TO_MODIFY_FIELDS_BITMAP: u32 = 0;
         }
     }
+    #[doc = "sample_counter_lo (r) register accessor: sample_counter_lo\n\nYou can [`read`](crate::Reg::read) this register and get [`sample_counter_lo::R`]. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@sample_counter_lo`]
+module"]
+    #[doc(alias = "sample_counter_lo")]
+    pub type SampleCounterLo = crate::Reg<sample_counter_lo::SampleCounterLoSpec>;
+    #[doc = "sample_counter_lo"]
+    pub mod sample_counter_lo {
+        #[doc = "Register `sample_counter_lo` reader"]
+        pub type R = crate::R<SampleCounterLoSpec>;
+        #[doc = "Field `counter_lo` reader - counter_lo"]
+        pub type CounterLoR = crate::FieldReader<u32>;
+        impl R {
+            #[doc = "Bits 0:31 - counter_lo"]
+            #[inline(always)]
+            pub fn counter_lo(&self) -> CounterLoR {
+                CounterLoR::new(self.bits)
+            }
+        }
+        #[doc = "sample_counter_lo\n\nYou can [`read`](crate::Reg::read) this register and get [`sample_counter_lo::R`](R). See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
+        pub struct SampleCounterLoSpec;
+        impl crate::RegisterSpec for SampleCounterLoSpec {
+            type Ux = u32;
+        }
+        #[doc = "`read()` method returns [`sample_counter_lo::R`](R) reader structure"]
+        impl crate::Readable for SampleCounterLoSpec {}
+    }
+    #[doc = "sample_counter_hi (rw) register accessor: sample_counter_hi\n\nYou can [`read`](crate::Reg::read) this register and get [`sample_counter_hi::R`]. You can [`write_with_zero`](crate::Reg::write_with_zero) this register using [`sample_counter_hi::W`]. You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@sample_counter_hi`]
+module"]
+    #[doc(alias = "sample_counter_hi")]
+    pub type SampleCounterHi = crate::Reg<sample_counter_hi::SampleCounterHiSpec>;
+    #[doc = "sample_counter_hi"]
+    pub mod sample_counter_hi {
+        #[doc = "Register `sample_counter_hi` reader"]
+        pub type R = crate::R<SampleCounterHiSpec>;
+        #[doc = "Register `sample_counter_hi` writer"]
+        pub type W = crate::W<SampleCounterHiSpec>;
+        #[doc = "Field `counter_hi` reader - counter_hi"]
+        pub type CounterHiR = crate::FieldReader<u16>;
+        #[doc = "Field `snapshot` writer - snapshot"]
+        pub type SnapshotW<'a, REG> = crate::BitWriter<'a, REG>;
+        #[doc = "Field `pps_enable` reader - pps_enable"]
+        pub type PpsEnableR = crate::BitReader;
+        #[doc = "Field `pps_enable` writer - pps_enable"]
+        pub type PpsEnableW<'a, REG> = crate::BitWriter<'a, REG>;
+        #[doc = "Field `latch_count` reader - latch_count"]
+        pub type LatchCountR = crate::FieldReader;
+        #[doc = "Field `latch_pps` reader - latch_pps"]
+        pub type LatchPpsR = crate::BitReader;
+        impl R {
+            #[doc = "Bits 0:15 - counter_hi"]
+            #[inline(always)]
+            pub fn counter_hi(&self) -> CounterHiR {
+                CounterHiR::new((self.bits & 0xffff) as u16)
+            }
+            #[doc = "Bit 17 - pps_enable"]
+            #[inline(always)]
+            pub fn pps_enable(&self) -> PpsEnableR {
+                PpsEnableR::new(((self.bits >> 17) & 1) != 0)
+            }
+            #[doc = "Bits 18:21 - latch_count"]
+            #[inline(always)]
+            pub fn latch_count(&self) -> LatchCountR {
+                LatchCountR::new(((self.bits >> 18) & 0x0f) as u8)
+            }
+            #[doc = "Bit 22 - latch_pps"]
+            #[inline(always)]
+            pub fn latch_pps(&self) -> LatchPpsR {
+                LatchPpsR::new(((self.bits >> 22) & 1) != 0)
+            }
+        }
+        impl W {
+            #[doc = "Bit 16 - snapshot"]
+            #[inline(always)]
+            #[must_use]
+            pub fn snapshot(&mut self) -> SnapshotW<SampleCounterHiSpec> {
+                SnapshotW::new(self, 16)
+            }
+            #[doc = "Bit 17 - pps_enable"]
+            #[inline(always)]
+            #[must_use]
+            pub fn pps_enable(&mut self) -> PpsEnableW<SampleCounterHiSpec> {
+                PpsEnableW::new(self, 17)
+            }
+        }
+        #[doc = "sample_counter_hi\n\nYou can [`read`](crate::Reg::read) this register and get [`sample_counter_hi::R`](R). You can [`write_with_zero`](crate::Reg::write_with_zero) this register using [`sample_counter_hi::W`](W). You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
+        pub struct SampleCounterHiSpec;
+        impl crate::RegisterSpec for SampleCounterHiSpec {
+            type Ux = u32;
+        }
+        #[doc = "`read()` method returns [`sample_counter_hi::R`](R) reader structure"]
+        impl crate::Readable for SampleCounterHiSpec {}
+        #[doc = "`write(|w| ..)` method takes [`sample_counter_hi::W`](W) writer structure"]
+        impl crate::Writable for SampleCounterHiSpec {
+            type Safety = crate::Unsafe;
+            const ZERO_TO_MODIFY_FIELDS_BITMAP: u32 = 0;
+            const ONE_TO_MODIFY_FIELDS_BITMAP: u32 = 0;
+        }
+    }
 }
 #[no_mangle]
 static mut DEVICE_PERIPHERALS: bool = false;
@@ -6,14 +6,53 @@
 
 use crate::{
     args::Args,
+    capture::CaptureRunner,
+    colormap::custom::CustomColormaps,
+    demodulator::Demodulators,
+    doppler::{Doppler, DopplerCorrector},
     fpga::{InterruptHandler, IpCore},
-    httpd::{self, RecorderFinishWaiter, RecorderState},
+    httpd::{
+        self, AuditLog, ButtonRunner, DeviceState, FileStorage, RateLimiter, RecorderFinishWaiter,
+        RecorderState, RecordingHooks, StartupProfile, StatusLed, StatusLedRunner,
+        WebSocketClients,
+    },
     iio::Ad9361,
-    spectrometer::{Spectrometer, SpectrometerConfig},
+    limits::Limits,
+    mock::{MockFpga, MockRfic},
+    notifications::Notifications,
+    peaks::{PeakDetector, Peaks},
+    sample_time::{SampleTimeReference, SampleTimeTracker},
+    scanner::{Scanner, ScannerRunner},
+    shutdown::{Shutdown, ShutdownHandler},
+    spectrometer::{
+        ClockMeasurement, Equalization, Spectrometer, SpectrometerConfig, SpectrumHistory,
+        SpectrumLine, FFT_SIZE,
+    },
+    stream::Streams,
+    survey::{SurveyRunner, Surveys},
+    system::System,
+    tuner::Tuner,
 };
-use anyhow::Result;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use maia_json::Hertz;
 use std::sync::{Arc, Mutex};
-use tokio::sync::broadcast;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+// Delay before restarting the FPGA tasks after a failure. It is doubled after
+// each failure, up to MAX_RESTART_DELAY.
+const RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+// The restart delay is reset if the FPGA tasks run for this long without
+// failing.
+const HEALTHY_RUN: Duration = Duration::from_secs(60);
+// Minimum time without spectra after which the watchdog restarts the FPGA
+// tasks.
+const WATCHDOG_MIN_TIMEOUT: Duration = Duration::from_secs(10);
+// Number of spectrum periods without spectra after which the watchdog restarts
+// the FPGA tasks, if this is longer than WATCHDOG_MIN_TIMEOUT.
+const WATCHDOG_PERIODS: f64 = 4.0;
 
 /// maia-httpd application.
 ///
@@ -21,10 +60,32 @@ use tokio::sync::broadcast;
 /// objects of which the application is formed, and runs them concurrently.
 #[derive(Debug)]
 pub struct App {
+    button: ButtonRunner,
+    capture: CaptureRunner,
+    doppler: DopplerCorrector,
+    fpga: FpgaSupervisor,
     httpd: httpd::Server,
-    interrupt_handler: InterruptHandler,
+    peak_detector: PeakDetector,
     recorder_finish: RecorderFinishWaiter,
+    sample_time: SampleTimeTracker,
+    scanner: ScannerRunner,
+    shutdown: ShutdownHandler,
+    status_led: StatusLedRunner,
+    survey: SurveyRunner,
+}
+
+/// FPGA task supervisor.
+///
+/// This runs the [`InterruptHandler`] and the [`Spectrometer`]. If any of them
+/// fails, or if a watchdog detects that the spectrometer has stopped producing
+/// spectra, the failure is recorded in the [`System`] health, the spectrometer
+/// DMA is re-initialized, and both tasks are restarted.
+#[derive(Debug)]
+struct FpgaSupervisor {
+    state: AppState,
+    interrupt_handler: InterruptHandler,
     spectrometer: Spectrometer,
+    spectra: broadcast::Receiver<SpectrumLine>,
 }
 
 impl App {
@@ -32,22 +93,62 @@ impl App {
     #[tracing::instrument(name = "App::new", level = "debug")]
     pub async fn new(args: &Args) -> Result<App> {
         // Initialize and build application state
-        let (ip_core, interrupt_handler) = IpCore::take().await?;
-        let ip_core = std::sync::Mutex::new(ip_core);
-        let ad9361 = tokio::sync::Mutex::new(Ad9361::new().await?);
-        let recorder = RecorderState::new(&ad9361, &ip_core).await?;
-        let state = AppState(Arc::new(State {
-            ad9361,
-            ip_core,
-            geolocation: std::sync::Mutex::new(None),
-            recorder,
-            spectrometer_config: Default::default(),
-        }));
-        // Initialize spectrometer sample rate and mode
-        state.spectrometer_config().set_samp_rate_mode(
-            state.ad9361().lock().await.get_sampling_frequency().await? as f32,
-            state.ip_core().lock().unwrap().spectrometer_mode(),
-        );
+        let ((ip_core, interrupt_handler), ad9361) = if args.simulate {
+            tracing::info!("running in simulation mode");
+            (
+                IpCore::with_backend(Arc::new(MockFpga::new())).await?,
+                Ad9361::with_backend(MockRfic::new()),
+            )
+        } else {
+            (IpCore::take().await?, Ad9361::new().await?)
+        };
+        let device = DeviceState::new(args).await?;
+        let limits = Limits::new(args).await?;
+        let state = AppState::new(ad9361, ip_core, device, limits).await?;
+        state
+            .spectrometer_equalization()
+            .load(args.spectrometer_equalization_file.clone())
+            .await?;
+        state
+            .custom_colormaps()
+            .load(args.colormaps_file.clone())
+            .await?;
+        if let Some(file) = &args.recording_file {
+            let backend = Arc::clone(state.ip_core().lock().unwrap().backend());
+            let storage = FileStorage::open(file, backend).await?;
+            state.recorder().set_storage(Box::new(storage)).await?;
+        }
+        if let Some(clips_dir) = &args.clips_dir {
+            state.recorder().set_clips_dir(clips_dir);
+        }
+        // The recording session is recovered before applying the startup
+        // profile, which could change the settings of the recording.
+        if let Err(err) = state
+            .recorder()
+            .recover_session(&state, args.recording_session_file.clone())
+            .await
+        {
+            tracing::error!("failed to recover recording: {err:#}");
+        }
+        if let Some(profile) = &args.startup_profile {
+            let profile = profile.profile();
+            profile
+                .validate()
+                .context("invalid startup profile in the configuration")?;
+            state.startup_profile().set(profile).await?;
+        }
+        // The startup profile saved to the file, if any, replaces the one in
+        // the configuration.
+        state
+            .startup_profile()
+            .load(args.startup_profile_file.clone())
+            .await?;
+        // A startup profile that cannot be applied should not prevent
+        // maia-httpd from starting, since the profile can be fixed through the
+        // API.
+        if let Err(err) = StartupProfile::apply(&state).await {
+            tracing::error!("{err:#}");
+        }
 
         // Build application objects
 
@@ -58,42 +159,182 @@ impl App {
             waterfall_sender.clone(),
         );
 
-        let recorder_finish =
-            RecorderFinishWaiter::new(state.clone(), interrupt_handler.waiter_recorder());
+        let peak_detector = PeakDetector::new(state.clone(), waterfall_sender.subscribe());
+
+        let recording_hooks = RecordingHooks::new(
+            args.recording_hook.clone(),
+            args.recording_hook_url.as_deref(),
+        )?;
+        let recorder_finish = RecorderFinishWaiter::new(
+            state.clone(),
+            interrupt_handler.waiter_recorder(),
+            recording_hooks,
+        );
+
+        let sample_time = SampleTimeTracker::new(state.clone(), args.pps);
+
+        let capture = CaptureRunner::new(state.clone());
+
+        let button = ButtonRunner::new(state.clone(), args);
+
+        let doppler = DopplerCorrector::new(state.clone());
+
+        let scanner = ScannerRunner::new(state.clone());
 
+        let shutdown = ShutdownHandler::new(state.clone(), args.simulate);
+
+        state.status_led().set_enabled(args.status_led);
+        let status_led = StatusLedRunner::new(state.clone(), args.identify_led.clone());
+
+        let fpga = FpgaSupervisor {
+            state: state.clone(),
+            interrupt_handler,
+            spectrometer,
+            spectra: waterfall_sender.subscribe(),
+        };
+
+        let survey = SurveyRunner::new(state.clone(), args.survey_dir.clone()).await?;
+
+        let audit_log = AuditLog::new(args.audit_log_length);
+        let rate_limiter = args
+            .rate_limit
+            .map(|rate| RateLimiter::new(f64::from(rate) / 60.0, args.rate_limit_burst))
+            .transpose()?;
         let httpd = httpd::Server::new(
             args.listen,
             args.listen_https,
             args.ssl_cert.as_ref(),
             args.ssl_key.as_ref(),
             args.ca_cert.as_ref(),
+            &args.cors_allow_origin,
+            audit_log,
+            rate_limiter,
             state,
             waterfall_sender,
         )
         .await?;
 
         Ok(App {
+            button,
+            capture,
+            doppler,
+            fpga,
             httpd,
-            interrupt_handler,
+            peak_detector,
             recorder_finish,
-            spectrometer,
+            sample_time,
+            scanner,
+            shutdown,
+            status_led,
+            survey,
         })
     }
 
     /// Runs the application.
     ///
-    /// This only returns if one of the objects that form the application fails.
+    /// This only returns if one of the objects that form the application fails,
+    /// or once the application has shut down.
     #[tracing::instrument(name = "App::run", level = "debug", skip_all)]
     pub async fn run(self) -> Result<()> {
         tokio::select! {
+            ret = self.button.run() => ret,
+            ret = self.capture.run() => ret,
+            ret = self.doppler.run() => ret,
+            ret = self.fpga.run() => ret,
             ret = self.httpd.run() => ret,
-            ret = self.interrupt_handler.run() => ret,
+            ret = self.peak_detector.run() => ret,
             ret = self.recorder_finish.run() => ret,
-            ret = self.spectrometer.run() => ret,
+            ret = self.sample_time.run() => ret,
+            ret = self.scanner.run() => ret,
+            ret = self.shutdown.run() => ret,
+            ret = self.status_led.run() => ret,
+            ret = self.survey.run() => ret,
+        }
+    }
+}
+
+impl FpgaSupervisor {
+    #[tracing::instrument(name = "fpga_supervisor", skip_all)]
+    async fn run(mut self) -> Result<()> {
+        let mut delay = RESTART_DELAY;
+        loop {
+            let start = Instant::now();
+            let (task, err) = tokio::select! {
+                ret = self.interrupt_handler.clone().run() => ("interrupt handler", task_error(ret)),
+                ret = self.spectrometer.clone().run() => ("spectrometer", task_error(ret)),
+                err = watchdog(&self.state, &mut self.spectra) => ("spectrometer watchdog", err),
+            };
+            let system = self.state.system();
+            system.record_incident(task, &err);
+            self.state
+                .notifications()
+                .alert(format!("{task} failed: {err:#}; restarting"));
+            if start.elapsed() >= HEALTHY_RUN {
+                delay = RESTART_DELAY;
+            }
+            tokio::time::sleep(delay).await;
+            delay = (2 * delay).min(MAX_RESTART_DELAY);
+            if let Err(err) = self
+                .state
+                .ip_core()
+                .lock()
+                .unwrap()
+                .reset_spectrometer_dma()
+            {
+                system.record_incident("spectrometer DMA", &err);
+            }
+            tracing::info!("restarting FPGA tasks");
+            system.record_restart();
+        }
+    }
+}
+
+// Converts the return value of a task that should run forever into an error.
+fn task_error(ret: Result<()>) -> anyhow::Error {
+    match ret {
+        Ok(()) => anyhow::anyhow!("task exited unexpectedly"),
+        Err(err) => err,
+    }
+}
+
+// Waits until the spectrometer has not produced any spectra for longer than
+// the watchdog timeout, which depends on the expected spectrum period.
+pub(crate) async fn watchdog(
+    state: &AppState,
+    spectra: &mut broadcast::Receiver<SpectrumLine>,
+) -> anyhow::Error {
+    loop {
+        let timeout = watchdog_timeout(state);
+        match tokio::time::timeout(timeout, spectra.recv()).await {
+            Ok(Ok(_) | Err(RecvError::Lagged(_))) => {}
+            Ok(Err(RecvError::Closed)) => return anyhow::anyhow!("spectrum channel closed"),
+            Err(_) => {
+                return anyhow::anyhow!("no spectra received for {:.0} s", timeout.as_secs_f64())
+            }
         }
     }
 }
 
+fn watchdog_timeout(state: &AppState) -> Duration {
+    let samp_rate = f64::from(state.spectrometer_config().samp_rate());
+    let fpga_period = match state.spectrometer_config().alignment_period() {
+        Some(period) => period,
+        None if samp_rate > 0.0 => {
+            let num_integrations = state
+                .ip_core()
+                .lock()
+                .unwrap()
+                .spectrometer_number_integrations();
+            f64::from(num_integrations) * f64::from(FFT_SIZE) / samp_rate
+        }
+        None => 0.0,
+    };
+    // The spectrum lines are sent after the software integration, so their
+    // period is a multiple of the FPGA spectrometer period.
+    let period = fpga_period * f64::from(state.spectrometer_config().software_integrations());
+    WATCHDOG_MIN_TIMEOUT.max(Duration::from_secs_f64(WATCHDOG_PERIODS * period))
+}
+
 /// Application state.
 ///
 /// This struct contains the application state that needs to be shared between
@@ -107,12 +348,93 @@ pub struct AppState(Arc<State>);
 struct State {
     ad9361: tokio::sync::Mutex<Ad9361>,
     ip_core: Mutex<IpCore>,
+    device: DeviceState,
+    limits: Limits,
     geolocation: Mutex<Option<maia_json::Geolocation>>,
+    doppler: Doppler,
     recorder: RecorderState,
     spectrometer_config: SpectrometerConfig,
+    spectrometer_equalization: Equalization,
+    custom_colormaps: CustomColormaps,
+    startup_profile: StartupProfile,
+    clock_measurement: ClockMeasurement,
+    last_spectrum: Mutex<Option<Bytes>>,
+    last_api: Mutex<Option<maia_json::Api>>,
+    spectrum_history: SpectrumHistory,
+    peaks: Peaks,
+    sample_time_reference: Mutex<Option<SampleTimeReference>>,
+    demodulators: Demodulators,
+    streams: Streams,
+    noise_blanker: Mutex<maia_json::DDCNoiseBlanker>,
+    surveys: Surveys,
+    scanner: Scanner,
+    tuner: Tuner,
+    shutdown: Shutdown,
+    system: System,
+    status_led: StatusLed,
+    notifications: Notifications,
+    websocket_clients: WebSocketClients,
 }
 
 impl AppState {
+    /// Creates a new application state.
+    ///
+    /// The state is built from the objects that control the hardware, the
+    /// device state and the safety limits. The recorder state and the
+    /// spectrometer configuration are initialized from the hardware.
+    pub async fn new(
+        ad9361: Ad9361,
+        ip_core: IpCore,
+        device: DeviceState,
+        limits: Limits,
+    ) -> Result<AppState> {
+        let ip_core = Mutex::new(ip_core);
+        let ad9361 = tokio::sync::Mutex::new(ad9361);
+        let recorder = RecorderState::new(&ad9361, &ip_core).await?;
+        let state = AppState(Arc::new(State {
+            ad9361,
+            ip_core,
+            device,
+            limits,
+            geolocation: Mutex::new(None),
+            doppler: Doppler::new(),
+            recorder,
+            spectrometer_config: Default::default(),
+            spectrometer_equalization: Equalization::new(),
+            custom_colormaps: CustomColormaps::new(),
+            startup_profile: StartupProfile::new(),
+            clock_measurement: ClockMeasurement::new(),
+            last_spectrum: Mutex::new(None),
+            last_api: Mutex::new(None),
+            spectrum_history: SpectrumHistory::new(),
+            peaks: Peaks::new(),
+            sample_time_reference: Mutex::new(None),
+            demodulators: Default::default(),
+            streams: Streams::new(),
+            noise_blanker: Mutex::new(crate::ddc::noise_blanker::default_config()),
+            surveys: Surveys::new(),
+            scanner: Scanner::new(),
+            tuner: Tuner::new(),
+            shutdown: Shutdown::default(),
+            system: System::new(),
+            status_led: StatusLed::new(),
+            notifications: Notifications::new(),
+            websocket_clients: WebSocketClients::new(),
+        }));
+        // Initialize spectrometer sample rate and mode
+        state.spectrometer_config().set_samp_rate_mode(
+            state
+                .ad9361()
+                .lock()
+                .await
+                .get_sampling_frequency()
+                .await?
+                .0 as f32,
+            state.ip_core().lock().unwrap().spectrometer_mode(),
+        );
+        Ok(state)
+    }
+
     /// Gives access to the [`Ad9361`] object of the application.
     pub fn ad9361(&self) -> &tokio::sync::Mutex<Ad9361> {
         &self.0.ad9361
@@ -123,6 +445,16 @@ impl AppState {
         &self.0.ip_core
     }
 
+    /// Gives access to the [`DeviceState`] object of the application.
+    pub fn device(&self) -> &DeviceState {
+        &self.0.device
+    }
+
+    /// Gives access to the safety [`Limits`] of the application.
+    pub fn limits(&self) -> &Limits {
+        &self.0.limits
+    }
+
     /// Gives access to the current geolocation of the device.
     ///
     /// The geolocation is `None` if it has never been set or if it has been
@@ -131,6 +463,11 @@ impl AppState {
         &self.0.geolocation
     }
 
+    /// Gives access to the [`Doppler`] correction of the application.
+    pub fn doppler(&self) -> &Doppler {
+        &self.0.doppler
+    }
+
     /// Gives access to the [`RecorderState`] object of the application.
     pub fn recorder(&self) -> &RecorderState {
         &self.0.recorder
@@ -141,8 +478,164 @@ impl AppState {
         &self.0.spectrometer_config
     }
 
+    /// Gives access to the [`Equalization`] of the spectrometer.
+    pub fn spectrometer_equalization(&self) -> &Equalization {
+        &self.0.spectrometer_equalization
+    }
+
+    /// Gives access to the [`CustomColormaps`] of the application.
+    pub fn custom_colormaps(&self) -> &CustomColormaps {
+        &self.0.custom_colormaps
+    }
+
+    /// Gives access to the [`StartupProfile`] of the application.
+    pub fn startup_profile(&self) -> &StartupProfile {
+        &self.0.startup_profile
+    }
+
+    /// Gives access to the [`ClockMeasurement`] of the sample clock.
+    pub fn clock_measurement(&self) -> &ClockMeasurement {
+        &self.0.clock_measurement
+    }
+
+    /// Gives access to the latest spectrum produced by the spectrometer.
+    ///
+    /// The spectrum is serialized as `f32` values in native endianness, in the
+    /// same format that is sent to the waterfall websocket. It is `None` if
+    /// the spectrometer has not produced any spectrum yet.
+    pub fn last_spectrum(&self) -> &Mutex<Option<Bytes>> {
+        &self.0.last_spectrum
+    }
+
+    /// Gives access to the last response to GET `/api`.
+    ///
+    /// The sections of the response that cannot be read are taken from this
+    /// response. It is `None` if no response has been given yet.
+    pub fn last_api(&self) -> &Mutex<Option<maia_json::Api>> {
+        &self.0.last_api
+    }
+
+    /// Gives access to the [`SpectrumHistory`] object of the application.
+    ///
+    /// The history contains the most recent spectra produced by the
+    /// spectrometer.
+    pub fn spectrum_history(&self) -> &SpectrumHistory {
+        &self.0.spectrum_history
+    }
+
+    /// Gives access to the [`Peaks`] detected in the spectra produced by the
+    /// spectrometer.
+    pub fn peaks(&self) -> &Peaks {
+        &self.0.peaks
+    }
+
+    /// Gives access to the latest sample time reference.
+    ///
+    /// The sample time reference maps the sample counter of the FPGA IP core
+    /// to UTC. It is `None` if it has not been obtained yet or if the IP core
+    /// does not have a sample counter.
+    pub fn sample_time_reference(&self) -> &Mutex<Option<SampleTimeReference>> {
+        &self.0.sample_time_reference
+    }
+
+    /// Gives access to the [`Demodulators`] object of the application.
+    ///
+    /// Initially, the demodulators in the
+    /// [built-in registry](crate::demodulator::DemodulatorRegistry::with_builtin)
+    /// can be started.
+    pub fn demodulators(&self) -> &Demodulators {
+        &self.0.demodulators
+    }
+
+    /// Gives access to the [`Streams`] object of the application.
+    pub fn streams(&self) -> &Streams {
+        &self.0.streams
+    }
+
+    /// Gives access to the configuration of the DDC
+    /// [noise blanker](crate::ddc::noise_blanker).
+    pub fn noise_blanker(&self) -> &Mutex<maia_json::DDCNoiseBlanker> {
+        &self.0.noise_blanker
+    }
+
+    /// Gives access to the [`Surveys`] object of the application.
+    pub fn surveys(&self) -> &Surveys {
+        &self.0.surveys
+    }
+
+    /// Gives access to the [`Scanner`] object of the application.
+    pub fn scanner(&self) -> &Scanner {
+        &self.0.scanner
+    }
+
+    /// Gives access to the [`Tuner`] that controls the ownership of the
+    /// AD9361 RX LO.
+    pub fn tuner(&self) -> &Tuner {
+        &self.0.tuner
+    }
+
+    /// Gives access to the [`Shutdown`] state of the application.
+    pub fn shutdown(&self) -> &Shutdown {
+        &self.0.shutdown
+    }
+
+    /// Gives access to the [`System`] health of the application.
+    pub fn system(&self) -> &System {
+        &self.0.system
+    }
+
+    /// Gives access to the system [`StatusLed`].
+    pub fn status_led(&self) -> &StatusLed {
+        &self.0.status_led
+    }
+
+    /// Gives access to the [`Notifications`] sent to the WebSocket clients.
+    pub fn notifications(&self) -> &Notifications {
+        &self.0.notifications
+    }
+
+    /// Gives access to the [`WebSocketClients`] of the application.
+    pub fn websocket_clients(&self) -> &WebSocketClients {
+        &self.0.websocket_clients
+    }
+
     /// Returns the AD9361 sampling frequency.
-    pub async fn ad9361_samp_rate(&self) -> Result<f64> {
-        Ok(self.ad9361().lock().await.get_sampling_frequency().await? as f64)
+    pub async fn ad9361_samp_rate(&self) -> Result<Hertz> {
+        self.ad9361().lock().await.get_sampling_frequency().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn watchdog_timeout_software_integration() {
+        let (state, _) = crate::mock::app_state().await;
+        state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .set_spectrometer_number_integrations(1000)
+            .unwrap();
+        assert_eq!(watchdog_timeout(&state), WATCHDOG_MIN_TIMEOUT);
+        // 200 spectra of the FPGA spectrometer, which last 1/15 s each at
+        // 61.44 Msps, give a line period of 13.3 s
+        state
+            .spectrometer_config()
+            .set_software_integrations(200)
+            .unwrap();
+        let line_period = 200.0 * 1000.0 * f64::from(FFT_SIZE) / 61.44e6;
+        assert!(Duration::from_secs_f64(line_period) > WATCHDOG_MIN_TIMEOUT);
+        let timeout = watchdog_timeout(&state).as_secs_f64();
+        assert!((timeout - WATCHDOG_PERIODS * line_period).abs() < 1e-6);
+        // with wall clock alignment the period is given by the alignment
+        // period
+        state
+            .spectrometer_config()
+            .set_alignment_period(Some(0.5))
+            .unwrap();
+        let timeout = watchdog_timeout(&state).as_secs_f64();
+        assert!((timeout - WATCHDOG_PERIODS * 100.0).abs() < 1e-6);
     }
 }
@@ -1,15 +1,44 @@
 //! maia-httpd CLI arguments.
 //!
 //! This module contains the definition of the CLI arguments for the maia-httpd
-//! application.
+//! application. The arguments can also be given in a TOML or JSON
+//! configuration file and in environment variables, which is the only way to
+//! configure maia-httpd in uclibc builds, since they cannot parse the command
+//! line.
 
-use clap::Parser;
+use anyhow::{Context, Result};
+#[cfg(not(feature = "uclibc"))]
+use clap::CommandFactory;
+use clap::{parser::ValueSource, ArgMatches, FromArgMatches, Parser};
+use serde::{Deserialize, Serialize};
 use std::{net::SocketAddr, path::PathBuf};
 
+// Prefix of the environment variables that set the arguments. The rest of the
+// name of each variable is the name of the argument in upper case, with
+// underscores instead of dashes.
+const ENV_PREFIX: &str = "MAIA_HTTPD_";
+
 /// maia-httpd CLI arguments.
-#[derive(Parser, Debug, Clone, Eq, PartialEq, Hash)]
+///
+/// The fields of the configuration file have the names of the arguments, with
+/// underscores instead of dashes. The configuration file can also contain a
+/// `startup_profile` field, which cannot be given in the command line.
+#[derive(Parser, Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
 #[clap(author, version, about, long_about = None)]
+#[serde(default, deny_unknown_fields)]
 pub struct Args {
+    /// Path to a TOML or JSON configuration file
+    ///
+    /// The configuration file can set any of the arguments, plus the initial
+    /// startup profile. It is parsed as TOML if its extension is .toml, and as
+    /// JSON otherwise. The arguments can also be set with environment
+    /// variables, such as MAIA_HTTPD_LISTEN for --listen. Environment
+    /// variables take precedence over the configuration file, and the
+    /// command line takes precedence over both. The configuration file can
+    /// also be given in the MAIA_HTTPD_CONFIG environment variable.
+    #[clap(long)]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
     /// Listen address for the HTTP server
     #[clap(long, default_value = "0.0.0.0:8000")]
     pub listen: SocketAddr,
@@ -34,17 +63,466 @@ pub struct Args {
     /// option is provided.
     #[clap(long)]
     pub ca_cert: Option<PathBuf>,
+    /// Allow cross-origin requests from this origin
+    ///
+    /// This option can be given multiple times to allow several origins. The
+    /// value `*` allows requests from any origin. This is needed to control
+    /// this device from a web page served from another origin, such as a
+    /// multi-device dashboard. By default cross-origin requests are not
+    /// allowed.
+    #[clap(long)]
+    pub cors_allow_origin: Vec<String>,
+    /// Path to the file that stores the device name
+    ///
+    /// The device name is read from this file on startup, and written to it
+    /// when it is changed through the API. If this option is not given, the
+    /// device name is not persisted across restarts.
+    #[clap(long)]
+    pub device_name_file: Option<PathBuf>,
+    /// Path to the Avahi service file advertising the HTTP server
+    ///
+    /// If this option is given, an Avahi service file that advertises the
+    /// HTTP server using mDNS with the device name is written to this path
+    /// (for instance `/etc/avahi/services/maia-sdr.service`).
+    #[clap(long)]
+    pub avahi_service_file: Option<PathBuf>,
+    /// Maximum rate of requests that modify settings, per client
+    ///
+    /// The rate is given in requests per minute. Clients that exceed it get
+    /// an HTTP 429 error. By default, requests are not rate limited.
+    #[clap(long)]
+    pub rate_limit: Option<u32>,
+    /// Maximum burst of requests that modify settings, per client
+    ///
+    /// This is only used if --rate-limit is given.
+    #[clap(long, default_value_t = 20)]
+    pub rate_limit_burst: u32,
+    /// Number of requests that modify settings to keep in the audit log
+    ///
+    /// The audit log is available in /api/audit. A value of zero disables the
+    /// audit log.
+    #[clap(long, default_value_t = 256)]
+    pub audit_log_length: usize,
+    /// Path to the sysfs directory of the LED used to identify the device
+    #[clap(long, default_value = "/sys/class/leds/led0:green")]
+    pub identify_led: PathBuf,
+    /// Show the state of the device with the identify LED
+    ///
+    /// The LED blinks with a different pattern when the device is idle, when
+    /// clients are connected, when it is recording and after an error. The
+    /// status LED can also be enabled and configured through /api/system/led.
+    #[clap(long)]
+    pub status_led: bool,
+    /// Path to the sysfs value file of the GPIO of a push button
+    ///
+    /// A short press of the button starts or stops a recording, and a long
+    /// press (2 seconds) applies the startup profile. The GPIO must be
+    /// exported and configured as an input, and this is the path to its value
+    /// file (for instance `/sys/class/gpio/gpio906/value`).
+    #[clap(long)]
+    pub button_gpio: Option<PathBuf>,
+    /// The button GPIO is low when the button is pressed
+    #[clap(long)]
+    pub button_active_low: bool,
+    /// Path to the sysfs directory of the LED that shows the recorder state
+    ///
+    /// The LED is lit while a recording is in progress or armed, and it
+    /// flashes when a long press of the button applies the startup profile.
+    /// This is only used if --button-gpio is given.
+    #[clap(long)]
+    pub button_led: Option<PathBuf>,
+    /// Use simulated hardware with a synthetic signal
+    ///
+    /// The FPGA IP core and the AD9361 are replaced by simulations. The
+    /// spectrometer and the recorder produce data from a synthetic signal
+    /// formed by noise and a few moving carriers. This allows running
+    /// maia-httpd on a machine without Maia SDR hardware, for instance to
+    /// develop or demonstrate the web UI.
+    #[clap(long, alias = "demo")]
+    pub simulate: bool,
+    /// Use the PPS input to map the sample counter to UTC
+    ///
+    /// When this is given, the FPGA sample counter is latched on each rising
+    /// edge of the PPS input, and it is assumed that the system clock is
+    /// synchronized to within a fraction of a second (for instance, by GPS).
+    /// Otherwise, the sample counter is mapped to the system clock.
+    #[clap(long)]
+    pub pps: bool,
+    /// Command to run when a recording finishes
+    ///
+    /// The command is run without arguments, and it receives information about
+    /// the recording as JSON in its standard input. It can download the
+    /// recording from the HTTP server, for instance to upload it to a NAS or
+    /// to S3.
+    #[clap(long)]
+    pub recording_hook: Option<PathBuf>,
+    /// URL to send a POST request to when a recording finishes
+    ///
+    /// The body of the request contains information about the recording as
+    /// JSON. Both http:// and https:// URLs are supported.
+    #[clap(long)]
+    pub recording_hook_url: Option<String>,
+    /// File to store the state of the recording in progress
+    ///
+    /// If maia-httpd restarts while a recording is in progress, the recording
+    /// is stopped and its metadata is recovered from this file, so that the
+    /// recording can be downloaded.
+    #[clap(long)]
+    pub recording_session_file: Option<PathBuf>,
+    /// File to store the latest recording
+    ///
+    /// When a recording finishes, it is copied from the recording DMA buffer
+    /// to this file, and it is accessed from the file afterwards, so that it
+    /// persists across reboots. This file can be in an SD card or in a network
+    /// filesystem. By default, the recording is only kept in the DMA buffer.
+    #[clap(long)]
+    pub recording_file: Option<PathBuf>,
+    /// Directory to store the clips and the recordings of scanner hits
+    ///
+    /// The clips, the scanner hits and the frequency-hopping recordings are
+    /// copied from the recording buffer to files in this directory, so that
+    /// they do not take up memory. The files are removed when the clips are
+    /// discarded and when maia-httpd restarts. By default a directory in the
+    /// system temporary directory is used.
+    #[clap(long)]
+    pub clips_dir: Option<PathBuf>,
+    /// Directory to store spectrum surveys and their reports
+    ///
+    /// The configured surveys are saved to this directory so that they persist
+    /// across restarts, and the report of each survey run is written to it in
+    /// JSON and CSV format.
+    #[clap(long)]
+    pub survey_dir: Option<PathBuf>,
+    /// File to store the spectrometer equalization table
+    ///
+    /// The equalization table configured through
+    /// /api/spectrometer/equalization is saved to this file so that it
+    /// persists across restarts.
+    #[clap(long)]
+    pub spectrometer_equalization_file: Option<PathBuf>,
+    /// File to store the custom colormaps
+    ///
+    /// The custom colormaps configured through /api/colormaps are saved to
+    /// this file so that they persist across restarts.
+    #[clap(long)]
+    pub colormaps_file: Option<PathBuf>,
+    /// Token that authorizes shutting down and rebooting the device
+    ///
+    /// Requests to /api/shutdown and /api/reboot must include this token in an
+    /// `Authorization: Bearer <token>` header. If this option is not given,
+    /// the device cannot be shut down or rebooted through the API.
+    #[clap(long)]
+    pub power_token: Option<String>,
+    /// Token that authorizes administrative requests
+    ///
+    /// POST requests to /api/benchmark must include this token in an
+    /// `Authorization: Bearer <token>` header. If this option is not given,
+    /// the benchmarks cannot be run through the API.
+    #[clap(long)]
+    pub admin_token: Option<String>,
+    /// Token that authorizes changing the safety limits
+    ///
+    /// PUT requests to /api/limits-admin must include this token in an
+    /// `Authorization: Bearer <token>` header. If this option is not given,
+    /// the safety limits cannot be changed through the API.
+    #[clap(long)]
+    pub limits_token: Option<String>,
+    /// File to store the safety limits
+    ///
+    /// The safety limits configured through /api/limits-admin are saved to
+    /// this file so that they persist across restarts.
+    #[clap(long)]
+    pub limits_file: Option<PathBuf>,
+    /// File to store the startup profile
+    ///
+    /// The AD9361, DDC and spectrometer settings in the startup profile
+    /// configured through /api/startup-profile are applied when maia-httpd
+    /// starts. The profile is saved to this file so that it persists across
+    /// restarts.
+    #[clap(long)]
+    pub startup_profile_file: Option<PathBuf>,
+    /// Initial startup profile.
+    ///
+    /// This can only be given in the configuration file. It is used when no
+    /// startup profile has been saved to the `startup_profile_file`.
+    #[clap(skip)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub startup_profile: Option<InitialStartupProfile>,
+}
+
+/// Initial startup profile given in the configuration file.
+///
+/// The profile is stored as a JSON value, so that [`Args`] can implement `Eq`
+/// and `Hash`, which [`maia_json::StartupProfile`] does not implement because
+/// it contains floating point values. It is serialized and deserialized as a
+/// [`maia_json::StartupProfile`].
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq, Hash)]
+#[serde(from = "maia_json::StartupProfile", into = "maia_json::StartupProfile")]
+pub struct InitialStartupProfile(serde_json::Value);
+
+impl InitialStartupProfile {
+    /// Returns the startup profile.
+    pub fn profile(&self) -> maia_json::StartupProfile {
+        self.clone().into()
+    }
+}
+
+impl From<maia_json::StartupProfile> for InitialStartupProfile {
+    fn from(profile: maia_json::StartupProfile) -> InitialStartupProfile {
+        InitialStartupProfile(serde_json::to_value(profile).unwrap())
+    }
+}
+
+impl From<InitialStartupProfile> for maia_json::StartupProfile {
+    fn from(profile: InitialStartupProfile) -> maia_json::StartupProfile {
+        // The value has been obtained by serializing a StartupProfile, so this
+        // cannot fail.
+        serde_json::from_value(profile.0).unwrap()
+    }
+}
+
+impl Args {
+    /// Returns the arguments of the application.
+    ///
+    /// The arguments are taken, in increasing order of precedence, from their
+    /// default values, from the configuration file, from the environment
+    /// variables and from the command line. The command line is not used in
+    /// uclibc builds.
+    pub fn load() -> Result<Args> {
+        // workaround for https://github.com/rust-lang/rust/issues/112488
+        #[cfg(feature = "uclibc")]
+        let matches = None;
+        #[cfg(not(feature = "uclibc"))]
+        let matches = Some(Args::command().get_matches());
+        Args::from_sources(matches.as_ref(), std::env::vars())
+    }
+
+    fn from_sources(
+        matches: Option<&ArgMatches>,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Args> {
+        let cli = matches.map(Args::from_arg_matches).transpose()?;
+        let env = env
+            .into_iter()
+            .filter_map(|(name, value)| {
+                Some((name.strip_prefix(ENV_PREFIX)?.to_lowercase(), value))
+            })
+            .collect::<Vec<_>>();
+        let config = cli.as_ref().and_then(|cli| cli.config.clone()).or_else(|| {
+            env.iter()
+                .find(|(name, _)| name == "config")
+                .map(|(_, value)| PathBuf::from(value))
+        });
+
+        let mut json = serde_json::to_value(Args::default())?;
+        if let Some(file) = &config {
+            let contents = std::fs::read_to_string(file)
+                .with_context(|| format!("failed to read {file:?}"))?;
+            let fields = if file.extension().is_some_and(|ext| ext == "toml") {
+                toml::from_str(&contents).map_err(anyhow::Error::from)
+            } else {
+                serde_json::from_str(&contents).map_err(anyhow::Error::from)
+            }
+            .with_context(|| format!("failed to parse {file:?}"))?;
+            let serde_json::Value::Object(fields) = fields else {
+                anyhow::bail!("{file:?} does not contain an object");
+            };
+            json.as_object_mut().unwrap().extend(fields);
+            Args::deserialize(&json)
+                .with_context(|| format!("invalid configuration in {file:?}"))?;
+        }
+        for (name, value) in env.into_iter().filter(|(name, _)| name != "config") {
+            // Other programs could use environment variables with the same
+            // prefix, so unknown variables are not an error.
+            if json.get(&name).is_none() && name != "startup_profile" {
+                tracing::warn!(
+                    "ignoring unknown environment variable {ENV_PREFIX}{}",
+                    name.to_uppercase()
+                );
+                continue;
+            }
+            // The value is used as a string if possible. Otherwise it is
+            // parsed as JSON, so that numbers, booleans and lists can be
+            // given.
+            json[&name] = serde_json::Value::String(value.clone());
+            if Args::deserialize(&json).is_err() {
+                if let Ok(parsed) = serde_json::from_str(&value) {
+                    json[&name] = parsed;
+                }
+            }
+            Args::deserialize(&json).with_context(|| {
+                format!(
+                    "invalid value in environment variable {ENV_PREFIX}{}",
+                    name.to_uppercase()
+                )
+            })?;
+        }
+        if let (Some(cli), Some(matches)) = (cli, matches) {
+            let cli = serde_json::to_value(cli)?;
+            // The IDs also include the argument group of the struct, which is
+            // not a field. The config field is not serialized.
+            for id in matches.ids().map(|id| id.as_str()) {
+                if let Some(value) = cli.get(id) {
+                    if matches.value_source(id) == Some(ValueSource::CommandLine) {
+                        json[id] = value.clone();
+                    }
+                }
+            }
+        }
+
+        let mut args = Args::deserialize(&json)?;
+        args.config = config;
+        Ok(args)
+    }
 }
 
-#[cfg(feature = "uclibc")]
 impl Default for Args {
     fn default() -> Args {
         Args {
+            config: None,
             listen: "0.0.0.0:8000".parse().unwrap(),
             listen_https: "0.0.0.0:443".parse().unwrap(),
             ssl_cert: None,
             ssl_key: None,
             ca_cert: None,
+            cors_allow_origin: Vec::new(),
+            device_name_file: None,
+            avahi_service_file: None,
+            rate_limit: None,
+            rate_limit_burst: 20,
+            audit_log_length: 256,
+            identify_led: "/sys/class/leds/led0:green".into(),
+            status_led: false,
+            button_gpio: None,
+            button_active_low: false,
+            button_led: None,
+            simulate: false,
+            pps: false,
+            recording_hook: None,
+            recording_hook_url: None,
+            recording_session_file: None,
+            recording_file: None,
+            clips_dir: None,
+            survey_dir: None,
+            spectrometer_equalization_file: None,
+            colormaps_file: None,
+            power_token: None,
+            admin_token: None,
+            limits_token: None,
+            limits_file: None,
+            startup_profile_file: None,
+            startup_profile: None,
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn env(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn default_matches_cli() {
+        assert_eq!(Args::default(), Args::parse_from(["maia-httpd"]));
+    }
+
+    #[test]
+    fn sources() {
+        let file =
+            std::env::temp_dir().join(format!("maia-httpd-config-{}.json", std::process::id()));
+        std::fs::write(
+            &file,
+            r#"{"listen": "0.0.0.0:80", "rate_limit": 60, "power_token": "file",
+                "startup_profile": {"ad9361": {"rx_lo_frequency": 433920000}}}"#,
+        )
+        .unwrap();
+        let matches = Args::command().get_matches_from(["maia-httpd", "--power-token", "cli"]);
+        let args = Args::from_sources(
+            Some(&matches),
+            env(&[
+                ("MAIA_HTTPD_CONFIG", file.to_str().unwrap()),
+                ("MAIA_HTTPD_RATE_LIMIT", "30"),
+                ("MAIA_HTTPD_LIMITS_TOKEN", "1234"),
+                ("MAIA_HTTPD_SIMULATE", "true"),
+                ("HOME", "/root"),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(args.config.as_ref(), Some(&file));
+        assert_eq!(args.listen, "0.0.0.0:80".parse().unwrap());
+        assert_eq!(args.rate_limit, Some(30));
+        assert_eq!(args.limits_token.as_deref(), Some("1234"));
+        assert!(args.simulate);
+        assert_eq!(args.power_token.as_deref(), Some("cli"));
+        assert_eq!(
+            args.startup_profile
+                .unwrap()
+                .profile()
+                .ad9361
+                .unwrap()
+                .rx_lo_frequency,
+            Some(maia_json::Hertz(433_920_000.0))
+        );
+
+        // Without the command line, as in uclibc builds.
+        let args = Args::from_sources(None, env(&[("MAIA_HTTPD_CONFIG", file.to_str().unwrap())]))
+            .unwrap();
+        assert_eq!(args.power_token.as_deref(), Some("file"));
+
+        assert!(Args::from_sources(None, env(&[("MAIA_HTTPD_RATE_LIMIT", "fast")])).is_err());
+        assert_eq!(
+            Args::from_sources(None, env(&[("MAIA_HTTPD_UNKNOWN", "1")])).unwrap(),
+            Args::default()
+        );
+        std::fs::write(&file, r#"{"unknown": 1}"#).unwrap();
+        assert!(
+            Args::from_sources(None, env(&[("MAIA_HTTPD_CONFIG", file.to_str().unwrap())]))
+                .is_err()
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn toml_config() {
+        let file =
+            std::env::temp_dir().join(format!("maia-httpd-config-{}.toml", std::process::id()));
+        std::fs::write(
+            &file,
+            r#"
+listen = "0.0.0.0:80"
+cors_allow_origin = ["https://example.com"]
+rate_limit = 60
+
+[startup_profile.ad9361]
+rx_lo_frequency = 433920000
+"#,
+        )
+        .unwrap();
+        let args = Args::from_sources(None, env(&[("MAIA_HTTPD_CONFIG", file.to_str().unwrap())]))
+            .unwrap();
+        assert_eq!(args.listen, "0.0.0.0:80".parse().unwrap());
+        assert_eq!(args.cors_allow_origin, ["https://example.com"]);
+        assert_eq!(args.rate_limit, Some(60));
+        assert_eq!(
+            args.startup_profile
+                .unwrap()
+                .profile()
+                .ad9361
+                .unwrap()
+                .rx_lo_frequency,
+            Some(maia_json::Hertz(433_920_000.0))
+        );
+        std::fs::write(&file, "listen = ").unwrap();
+        assert!(
+            Args::from_sources(None, env(&[("MAIA_HTTPD_CONFIG", file.to_str().unwrap())]))
+                .is_err()
+        );
+        std::fs::remove_file(file).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+//! Capture of IQ samples.
+//!
+//! The FPGA IP core does not have a continuous stream of IQ samples to the CPU.
+//! This module obtains blocks of IQ samples produced by the DDC by making short
+//! recordings with the IQ recorder, and passes them to the
+//! [demodulators](crate::demodulator) and the [streams](crate::stream) that are
+//! running. Therefore, there are gaps between consecutive blocks of samples,
+//! the contents of the recording buffer are overwritten while demodulators or
+//! streams are running, and these are paused while a recording is in progress
+//! or armed.
+//!
+//! If the live [noise blanker](crate::ddc::noise_blanker) is enabled, it is
+//! applied to the blocks of samples before they are passed to the demodulators
+//! and streams.
+
+use crate::{app::AppState, ddc::noise_blanker::NoiseBlanker};
+use anyhow::Result;
+use num_complex::Complex32;
+use std::time::Duration;
+
+// Duration of each of the recordings used to obtain blocks of IQ samples.
+const BLOCK_DURATION: Duration = Duration::from_millis(100);
+// Period with which the capture of a block is retried when the recorder is in
+// use or its input is not the DDC.
+const RETRY_PERIOD: Duration = Duration::from_millis(100);
+
+/// Block of IQ samples.
+///
+/// This contains a block of consecutive IQ samples produced by the DDC,
+/// together with the parameters required to interpret them.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct IqBlock<'a> {
+    /// IQ samples.
+    ///
+    /// The samples are normalized so that the full scale of the recorder
+    /// corresponds to an amplitude of one.
+    pub samples: &'a [Complex32],
+    /// Sample rate in samples per second.
+    pub sample_rate: f64,
+    /// Center frequency in Hz.
+    pub frequency: f64,
+    /// Value of the sample counter of the FPGA IP core for the first sample.
+    ///
+    /// This is `None` if the IP core does not have a sample counter.
+    pub sample_counter: Option<u64>,
+    /// Decimation factor of the samples.
+    ///
+    /// This is the ratio between the AD9361 sample rate, at which the sample
+    /// counter increments, and the sample rate of the block.
+    pub decimation: usize,
+}
+
+/// Capture runner.
+///
+/// This struct captures blocks of IQ samples with the recorder while there are
+/// demodulators or streams running, and passes the blocks to them. It
+/// implements a [`run`](CaptureRunner::run) async method that should be run
+/// concurrently with the rest of the application.
+#[derive(Debug)]
+pub struct CaptureRunner {
+    state: AppState,
+}
+
+impl CaptureRunner {
+    /// Creates a new capture runner.
+    pub fn new(state: AppState) -> CaptureRunner {
+        CaptureRunner { state }
+    }
+
+    /// Runs the capture runner.
+    ///
+    /// This function loops forever. Errors when capturing blocks of IQ samples
+    /// are logged. The function only returns if there is an error accessing
+    /// the AD9361.
+    #[tracing::instrument(name = "capture", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let mut paused = false;
+        // Noise blanker, together with the configuration and sample rate with
+        // which it was created.
+        let mut noise_blanker: Option<(maia_json::DDCNoiseBlanker, f64, NoiseBlanker)> = None;
+        loop {
+            if self.state.demodulators().is_empty() && self.state.streams().is_empty() {
+                tokio::select! {
+                    _ = self.state.demodulators().changed().notified() => {}
+                    _ = self.state.streams().changed().notified() => {}
+                };
+                continue;
+            }
+            let (input, frequency_offset, decimation) = {
+                let ip_core = self.state.ip_core().lock().unwrap();
+                (
+                    ip_core.spectrometer_input(),
+                    ip_core.recorder_input_frequency_offset(),
+                    ip_core.recorder_input_decimation(),
+                )
+            };
+            if input != maia_json::SpectrometerInput::DDC {
+                if !paused {
+                    tracing::warn!("capture paused: recorder input is not the DDC");
+                    paused = true;
+                }
+                tokio::time::sleep(RETRY_PERIOD).await;
+                continue;
+            }
+            paused = false;
+            let (sample_rate, frequency) = {
+                let ad9361 = self.state.ad9361().lock().await;
+                (
+                    (ad9361.get_sampling_frequency().await? / decimation as f64).0,
+                    (ad9361.get_rx_lo_frequency().await? + frequency_offset).0,
+                )
+            };
+            let mut capture = match self
+                .state
+                .recorder()
+                .capture_iq(self.state.ip_core(), BLOCK_DURATION)
+                .await
+            {
+                Ok(Ok(capture)) => capture,
+                Ok(Err(_)) => {
+                    // The recorder is in use, or the recording buffer contains
+                    // a recording that must not be overwritten.
+                    tokio::time::sleep(RETRY_PERIOD).await;
+                    continue;
+                }
+                Err(err) => {
+                    tracing::error!("failed to capture IQ samples: {err:#}");
+                    tokio::time::sleep(RETRY_PERIOD).await;
+                    continue;
+                }
+            };
+            let noise_blanker_config = self.state.noise_blanker().lock().unwrap().clone();
+            if noise_blanker_config.live {
+                if !matches!(&noise_blanker, Some((config, rate, _))
+                             if *config == noise_blanker_config && *rate == sample_rate)
+                {
+                    noise_blanker = match NoiseBlanker::new(&noise_blanker_config, sample_rate) {
+                        Ok(blanker) => Some((noise_blanker_config, sample_rate, blanker)),
+                        Err(err) => {
+                            tracing::error!("failed to create noise blanker: {err:#}");
+                            None
+                        }
+                    };
+                }
+                if let Some((_, _, blanker)) = noise_blanker.as_mut() {
+                    blanker.process(&mut capture.samples);
+                }
+            } else {
+                noise_blanker = None;
+            }
+            let block = IqBlock {
+                samples: &capture.samples,
+                sample_rate,
+                frequency,
+                sample_counter: capture.start_sample,
+                decimation,
+            };
+            self.state.demodulators().process(&block);
+            self.state.streams().process(&block);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Colormaps.
+//!
+//! This module contains the colormaps that can be used to render spectrograms.
+//! The look-up tables are shared with the maia-wasm waterfall through
+//! [`maia_json::colormap`]. The [`custom`] module generates additional
+//! colormaps from user parameters.
+
+use serde::{Deserialize, Serialize};
+
+pub mod custom;
+pub use maia_json::colormap::{inferno, turbo, viridis};
+
+/// Colormap.
+///
+/// This enum lists the supported colormaps.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum Colormap {
+    /// Turbo colormap.
+    #[default]
+    Turbo,
+    /// Viridis colormap.
+    Viridis,
+    /// Inferno colormap.
+    Inferno,
+}
+
+impl Colormap {
+    /// Returns the colormap as a slice.
+    ///
+    /// The format of the slice is 8-bit RGB as a flattened array of 256 RGB
+    /// pixels.
+    pub fn colormap_as_slice(&self) -> &'static [u8] {
+        match self {
+            Colormap::Turbo => &turbo::COLORMAP,
+            Colormap::Viridis => &viridis::COLORMAP,
+            Colormap::Inferno => &inferno::COLORMAP,
+        }
+    }
+}
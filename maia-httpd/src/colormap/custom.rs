@@ -0,0 +1,354 @@
+//! Custom colormaps.
+//!
+//! This module generates colormaps from a few parameters (see
+//! [`ColormapParameters`]), and stores the custom colormaps that users define
+//! so that they can be used by the waterfall of any browser.
+//!
+//! The colormaps are ramps in the OKLCh color space, which is perceptually
+//! uniform. The lightness increases linearly with the position along the ramp
+//! (after applying the gamma), and the hue rotates from the start hue to the
+//! end hue. The chroma is constant, except where it is reduced so that the
+//! color fits in the sRGB gamut.
+
+use anyhow::{Context, Result};
+use maia_json::ColormapParameters;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Number of colors of the colormaps.
+pub const NUM_COLORS: usize = 256;
+/// Maximum number of custom colormaps that can be stored.
+pub const MAX_COLORMAPS: usize = 64;
+
+// Lightness of the first and last colors of the colormaps.
+const MIN_LIGHTNESS: f64 = 0.15;
+const MAX_LIGHTNESS: f64 = 0.95;
+// Chroma of the colors, before reducing it to fit in the sRGB gamut.
+const CHROMA: f64 = 0.15;
+// Number of bisection iterations used to fit the chroma in the sRGB gamut.
+const GAMUT_ITERATIONS: usize = 16;
+
+/// Generates a colormap.
+///
+/// The colormap has [`NUM_COLORS`] 8-bit RGB colors, ordered from the lowest
+/// to the highest power. The `parameters` are assumed to be valid.
+pub fn generate(parameters: &ColormapParameters) -> Vec<[u8; 3]> {
+    (0..NUM_COLORS)
+        .map(|j| {
+            let t = (j as f64 / (NUM_COLORS - 1) as f64).powf(parameters.gamma);
+            let lightness = MIN_LIGHTNESS + (MAX_LIGHTNESS - MIN_LIGHTNESS) * t;
+            let hue = parameters.start_hue + (parameters.end_hue - parameters.start_hue) * t;
+            oklch_to_srgb(lightness, CHROMA, hue.to_radians())
+        })
+        .collect()
+}
+
+// Converts an OKLCh color to 8-bit sRGB, reducing the chroma if the color is
+// outside of the sRGB gamut.
+fn oklch_to_srgb(lightness: f64, chroma: f64, hue: f64) -> [u8; 3] {
+    let rgb = |chroma: f64| oklab_to_linear_srgb(lightness, chroma * hue.cos(), chroma * hue.sin());
+    let in_gamut = |rgb: &[f64; 3]| rgb.iter().all(|x| (0.0..=1.0).contains(x));
+    let mut linear = rgb(chroma);
+    if !in_gamut(&linear) {
+        let (mut low, mut high) = (0.0, chroma);
+        for _ in 0..GAMUT_ITERATIONS {
+            let mid = 0.5 * (low + high);
+            if in_gamut(&rgb(mid)) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        linear = rgb(low);
+    }
+    linear.map(|x| (255.0 * srgb_transfer(x.clamp(0.0, 1.0))).round() as u8)
+}
+
+// Converts an OKLab color to linear sRGB.
+fn oklab_to_linear_srgb(l: f64, a: f64, b: f64) -> [f64; 3] {
+    let l_ = l + 0.3963377774 * a + 0.2158037573 * b;
+    let m_ = l - 0.1055613458 * a - 0.0638541728 * b;
+    let s_ = l - 0.0894841775 * a - 1.2914855480 * b;
+    let (l, m, s) = (l_.powi(3), m_.powi(3), s_.powi(3));
+    [
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    ]
+}
+
+// sRGB transfer function (gamma encoding).
+fn srgb_transfer(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Custom colormaps.
+///
+/// This struct is part of the [`AppState`](crate::app::AppState). It contains
+/// the custom colormaps, indexed by name. If a file is given with
+/// [`CustomColormaps::load`], the colormaps are read from this file and saved
+/// to it each time that they are changed.
+#[derive(Debug, Default)]
+pub struct CustomColormaps {
+    inner: Mutex<Inner>,
+    // Serializes the changes, which are saved to the file before they are
+    // applied.
+    updates: tokio::sync::Mutex<()>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    colormaps: BTreeMap<String, ColormapParameters>,
+    file: Option<PathBuf>,
+}
+
+impl CustomColormaps {
+    /// Creates an empty set of custom colormaps.
+    pub fn new() -> CustomColormaps {
+        CustomColormaps::default()
+    }
+
+    /// Loads the custom colormaps from a file.
+    ///
+    /// The `file` is remembered so that the colormaps are saved to it when
+    /// they are changed. If the file does not exist, the current colormaps are
+    /// kept.
+    pub async fn load(&self, file: Option<PathBuf>) -> Result<()> {
+        if let Some(file) = &file {
+            match tokio::fs::read(file).await {
+                Ok(json) => {
+                    let colormaps: BTreeMap<String, ColormapParameters> =
+                        serde_json::from_slice(&json)
+                            .with_context(|| format!("failed to parse {file:?}"))?;
+                    for (name, parameters) in &colormaps {
+                        check(name, parameters)
+                            .with_context(|| format!("invalid colormap in {file:?}"))?;
+                    }
+                    self.inner.lock().unwrap().colormaps = colormaps;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("failed to read {file:?}")),
+            }
+        }
+        self.inner.lock().unwrap().file = file;
+        Ok(())
+    }
+
+    /// Returns the JSON representation of the custom colormaps.
+    pub fn json(&self) -> maia_json::Colormaps {
+        maia_json::Colormaps {
+            colormaps: self
+                .inner
+                .lock()
+                .unwrap()
+                .colormaps
+                .iter()
+                .map(|(name, parameters)| colormap_json(Some(name), parameters))
+                .collect(),
+        }
+    }
+
+    /// Returns the JSON representation of a custom colormap.
+    ///
+    /// This returns `None` if there is no colormap with this name.
+    pub fn colormap_json(&self, name: &str) -> Option<maia_json::Colormap> {
+        self.inner
+            .lock()
+            .unwrap()
+            .colormaps
+            .get(name)
+            .map(|parameters| colormap_json(Some(name), parameters))
+    }
+
+    /// Checks that a custom colormap can be added or replaced.
+    ///
+    /// The function fails if the name or the parameters are invalid, or if the
+    /// maximum number of colormaps has been reached.
+    pub fn check(&self, name: &str, parameters: &ColormapParameters) -> Result<()> {
+        check(name, parameters)?;
+        let inner = self.inner.lock().unwrap();
+        anyhow::ensure!(
+            inner.colormaps.len() < MAX_COLORMAPS || inner.colormaps.contains_key(name),
+            "at most {MAX_COLORMAPS} custom colormaps can be stored"
+        );
+        Ok(())
+    }
+
+    /// Adds or replaces a custom colormap.
+    ///
+    /// The colormap must have been checked with
+    /// [`check`](CustomColormaps::check). The colormaps are saved to the
+    /// colormaps file before the colormap is added, so the function fails and
+    /// keeps the current colormaps if they cannot be saved.
+    pub async fn set(
+        &self,
+        name: &str,
+        parameters: ColormapParameters,
+    ) -> Result<maia_json::Colormap> {
+        let _updates = self.updates.lock().await;
+        self.check(name, &parameters)?;
+        let mut colormaps = self.inner.lock().unwrap().colormaps.clone();
+        colormaps.insert(name.to_string(), parameters.clone());
+        self.save(colormaps).await?;
+        Ok(colormap_json(Some(name), &parameters))
+    }
+
+    /// Removes a custom colormap.
+    ///
+    /// Returns the colormap that has been removed, or `None` if there is no
+    /// colormap with this name. The colormaps are saved to the colormaps file
+    /// before the colormap is removed, so the function fails and keeps the
+    /// current colormaps if they cannot be saved.
+    pub async fn remove(&self, name: &str) -> Result<Option<maia_json::Colormap>> {
+        let _updates = self.updates.lock().await;
+        let mut colormaps = self.inner.lock().unwrap().colormaps.clone();
+        let Some(parameters) = colormaps.remove(name) else {
+            return Ok(None);
+        };
+        self.save(colormaps).await?;
+        Ok(Some(colormap_json(Some(name), &parameters)))
+    }
+
+    // Saves the colormaps to the colormaps file, if there is one, and then
+    // replaces the current colormaps.
+    async fn save(&self, colormaps: BTreeMap<String, ColormapParameters>) -> Result<()> {
+        let file = self.inner.lock().unwrap().file.clone();
+        if let Some(file) = file {
+            crate::fs::write_atomic(&file, serde_json::to_vec_pretty(&colormaps)?)
+                .await
+                .context("failed to write colormaps file")?;
+        }
+        self.inner.lock().unwrap().colormaps = colormaps;
+        Ok(())
+    }
+}
+
+/// Returns the JSON representation of a colormap.
+///
+/// The colors of the colormap are generated from the `parameters`.
+pub fn colormap_json(name: Option<&str>, parameters: &ColormapParameters) -> maia_json::Colormap {
+    maia_json::Colormap {
+        name: name.map(|name| name.to_string()),
+        parameters: parameters.clone(),
+        colors: generate(parameters),
+    }
+}
+
+// Checks that the name and parameters of a custom colormap are valid.
+fn check(name: &str, parameters: &ColormapParameters) -> Result<()> {
+    anyhow::ensure!(
+        !name.is_empty()
+            && name.len() <= 64
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+        "colormap name must be formed by up to 64 letters, digits, '_' and '-'"
+    );
+    // This name is used by the /api/colormaps/generate endpoint.
+    anyhow::ensure!(name != "generate", "colormap name cannot be \"generate\"");
+    parameters.validate()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parameters() -> ColormapParameters {
+        ColormapParameters {
+            start_hue: 270.0,
+            end_hue: 450.0,
+            gamma: 1.0,
+        }
+    }
+
+    // Relative luminance of an 8-bit sRGB color.
+    fn luminance(color: [u8; 3]) -> f64 {
+        let linear = color.map(|x| {
+            let x = f64::from(x) / 255.0;
+            if x <= 0.04045 {
+                x / 12.92
+            } else {
+                ((x + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2]
+    }
+
+    #[test]
+    fn generation() {
+        for gamma in [0.5, 1.0, 2.0] {
+            let colors = generate(&ColormapParameters {
+                gamma,
+                ..parameters()
+            });
+            assert_eq!(colors.len(), NUM_COLORS);
+            // the luminance increases monotonically, with some tolerance for
+            // the 8-bit rounding
+            for pair in colors.windows(2) {
+                assert!(luminance(pair[1]) >= luminance(pair[0]) - 2e-3);
+            }
+            assert!(luminance(colors[0]) < 0.01);
+            assert!(luminance(colors[NUM_COLORS - 1]) > 0.8);
+        }
+        // a larger gamma keeps the colormap darker in the middle
+        let dark = generate(&ColormapParameters {
+            gamma: 2.0,
+            ..parameters()
+        });
+        let bright = generate(&ColormapParameters {
+            gamma: 0.5,
+            ..parameters()
+        });
+        assert!(luminance(dark[NUM_COLORS / 2]) < luminance(bright[NUM_COLORS / 2]));
+    }
+
+    #[tokio::test]
+    async fn set_and_remove() {
+        let colormaps = CustomColormaps::new();
+        let colormap = colormaps.set("night", parameters()).await.unwrap();
+        assert_eq!(colormap.name.as_deref(), Some("night"));
+        assert_eq!(colormap.colors, generate(&parameters()));
+        assert_eq!(colormaps.colormap_json("night"), Some(colormap.clone()));
+        assert!(colormaps.check("generate", &parameters()).is_err());
+        assert!(colormaps.set("a b", parameters()).await.is_err());
+        let invalid = ColormapParameters {
+            gamma: f64::NAN,
+            ..parameters()
+        };
+        assert!(colormaps.check("invalid", &invalid).is_err());
+        assert_eq!(colormaps.json().colormaps, vec![colormap.clone()]);
+        assert_eq!(colormaps.remove("night").await.unwrap(), Some(colormap));
+        assert_eq!(colormaps.remove("night").await.unwrap(), None);
+        assert!(colormaps.json().colormaps.is_empty());
+    }
+
+    #[tokio::test]
+    async fn persistence() {
+        let file =
+            std::env::temp_dir().join(format!("maia-httpd-colormaps-{}.json", std::process::id()));
+        let colormaps = CustomColormaps::new();
+        colormaps.load(Some(file.clone())).await.unwrap();
+        colormaps.set("night", parameters()).await.unwrap();
+        let loaded = CustomColormaps::new();
+        loaded.load(Some(file.clone())).await.unwrap();
+        assert_eq!(loaded.json(), colormaps.json());
+        std::fs::remove_file(file).unwrap();
+
+        // The colormaps are kept if they cannot be saved
+        let colormaps = CustomColormaps::new();
+        colormaps
+            .load(Some(
+                std::env::temp_dir().join("maia-httpd-missing/colormaps.json"),
+            ))
+            .await
+            .unwrap();
+        assert!(colormaps.set("night", parameters()).await.is_err());
+        assert!(colormaps.json().colormaps.is_empty());
+    }
+}
@@ -2,14 +2,24 @@
 //!
 //! This module contains code used to design FIR filters for the DDC. The design
 //! is done using the Parks-McClellan algorithm with the [pm-remez](mod@pm_remez)
-//! crate.
+//! crate. The [`software`] module contains a software implementation of the
+//! DDC, and the [`noise_blanker`] module contains a software noise blanker.
 
 use anyhow::Result;
+use maia_json::{Decibels, Hertz};
 use pm_remez::{
     constant, linear, order_estimates::ichige, pm_parameters, pm_remez, BandSetting, PMDesign,
 };
 
 pub mod constants;
+pub mod noise_blanker;
+pub mod software;
+
+/// Transition bandwidth used by the DDC designs that do not specify it.
+///
+/// This is the fraction of the output bandwidth used as transition bands. See
+/// [`maia_json::PutDDCDesign::transition_bandwidth`].
+pub const DEFAULT_TRANSITION_BANDWIDTH: f64 = 0.05;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 struct Config {
@@ -26,11 +36,13 @@ struct Config {
 impl Config {
     fn from_ddc_design(design: &maia_json::PutDDCDesign) -> Config {
         Config {
-            delta_f: design.transition_bandwidth.unwrap_or(0.05),
+            delta_f: design
+                .transition_bandwidth
+                .unwrap_or(DEFAULT_TRANSITION_BANDWIDTH),
             delta_p: design.passband_ripple.unwrap_or(0.01),
             delta_s: design
                 .stopband_attenuation_db
-                .map_or(0.001, |db| 10.0f64.powf(-db / 20.0)),
+                .map_or(0.001, |db| 10.0f64.powf(-db.0 / 20.0)),
             one_over_f: design.stopband_one_over_f.unwrap_or(true),
         }
     }
@@ -126,12 +138,13 @@ impl DecimatorConfig<f64> {
 }
 
 impl DecimatorConfig<i32> {
-    fn into_json(self, frequency: f64) -> maia_json::PutDDCConfig {
+    fn into_json(self, frequency: Hertz, transition_bandwidth: f64) -> maia_json::PutDDCConfig {
         maia_json::PutDDCConfig {
             frequency,
             fir1: self.fir1.into(),
             fir2: self.fir2.map(|f| f.into()),
             fir3: self.fir3.map(|f| f.into()),
+            transition_bandwidth: Some(transition_bandwidth),
         }
     }
 }
@@ -163,18 +176,38 @@ impl From<FIRConfig<i32>> for maia_json::DDCFIRConfig {
 /// - Stopband 1/f response: enabled.
 pub fn make_design(
     design: &maia_json::PutDDCDesign,
-    input_samp_rate: f64,
+    input_samp_rate: Hertz,
 ) -> Result<maia_json::PutDDCConfig> {
-    Ok(stages_design(
+    Ok(make_design_with_error_budget(design, input_samp_rate)?.0)
+}
+
+/// Calculates a DDC design and its error budget.
+///
+/// This works as [`make_design`], but it also returns the passband ripple and
+/// stopband attenuation achieved by each FIR filter and by the complete DDC.
+pub fn make_design_with_error_budget(
+    design: &maia_json::PutDDCDesign,
+    input_samp_rate: Hertz,
+) -> Result<(maia_json::PutDDCConfig, maia_json::DDCErrorBudget)> {
+    let config = Config::from_ddc_design(design);
+    let (decimator, error_budget) = stages_design(
         usize::try_from(design.decimation).unwrap(),
-        input_samp_rate,
-        &Config::from_ddc_design(design),
-    )?
-    .quantize()
-    .into_json(design.frequency))
+        input_samp_rate.0,
+        &config,
+    )?;
+    Ok((
+        decimator
+            .quantize()
+            .into_json(design.frequency, config.delta_f),
+        error_budget,
+    ))
 }
 
-fn stages_design(d: usize, input_samp_rate: f64, config: &Config) -> Result<DecimatorConfig<f64>> {
+fn stages_design(
+    d: usize,
+    input_samp_rate: f64,
+    config: &Config,
+) -> Result<(DecimatorConfig<f64>, maia_json::DDCErrorBudget)> {
     // Iterator that splits decimation factor d in vectors of up to 3 factors in
     // non-increasing order. Also impose FPGA implementation constraint on max
     // decimation factor per stage.
@@ -267,58 +300,123 @@ fn split_design(
     d: usize,
     input_samp_rate: f64,
     config: &Config,
-) -> Result<DecimatorConfig<f64>> {
+) -> Result<(DecimatorConfig<f64>, maia_json::DDCErrorBudget)> {
     assert!((1..=3).contains(&split.len()));
     let fp = 0.5 * (1.0 - config.delta_f);
 
     let d1 = split[0];
     let n1_max = stage_max_coefficients(input_samp_rate, d1, true);
     let design1 = pm_design(d as f64, fp, d1, config, n1_max)?;
+    let errors1 = filter_errors(design1.weighted_error, config);
     let fir1 = FIRConfig {
         coefficients: design1.impulse_response,
         decimation: d1,
     };
 
     if split.len() < 2 {
-        return Ok(DecimatorConfig {
-            fir1,
-            fir2: None,
-            fir3: None,
-        });
+        return Ok((
+            DecimatorConfig {
+                fir1,
+                fir2: None,
+                fir3: None,
+            },
+            error_budget(errors1, None, None),
+        ));
     }
 
     let d2 = split[1];
     let four_dsp = split.len() == 2;
     let n2_max = stage_max_coefficients(input_samp_rate / d1 as f64, d2, four_dsp);
     let design2 = pm_design((d / d1) as f64, fp, d2, config, n2_max)?;
+    let errors2 = filter_errors(design2.weighted_error, config);
     let fir2 = FIRConfig {
         coefficients: design2.impulse_response,
         decimation: d2,
     };
 
     if split.len() < 3 {
-        return Ok(DecimatorConfig {
-            fir1,
-            // with only 2 stages, the 2nd stage is bypassed and the third stage
-            // is used as the second
-            fir2: None,
-            fir3: Some(fir2),
-        });
+        // with only 2 stages, the 2nd stage is bypassed and the third stage is
+        // used as the second
+        return Ok((
+            DecimatorConfig {
+                fir1,
+                fir2: None,
+                fir3: Some(fir2),
+            },
+            error_budget(errors1, None, Some(errors2)),
+        ));
     }
 
     let d3 = split[2];
     let n3_max = stage_max_coefficients(input_samp_rate / (d1 * d2) as f64, d3, true);
     let design3 = pm_design(d3 as f64, fp, d3, config, n3_max)?;
+    let errors3 = filter_errors(design3.weighted_error, config);
     let fir3 = FIRConfig {
         coefficients: design3.impulse_response,
         decimation: d3,
     };
 
-    Ok(DecimatorConfig {
+    Ok((
+        DecimatorConfig {
+            fir1,
+            fir2: Some(fir2),
+            fir3: Some(fir3),
+        },
+        error_budget(errors1, Some(errors2), Some(errors3)),
+    ))
+}
+
+// Passband ripple and stopband attenuation of a design made by pm_design, given
+// its weighted error.
+fn filter_errors(weighted_error: f64, config: &Config) -> maia_json::DDCFilterErrors {
+    // The passband has unit weight, so the weighted error is the passband
+    // ripple. The stopband weight is smallest at the start of the stopband,
+    // where it is delta_p / delta_s, so the stopband ripple is largest there.
+    let passband_ripple = weighted_error;
+    let stopband_ripple = weighted_error * config.delta_s / config.delta_p;
+    maia_json::DDCFilterErrors {
+        passband_ripple,
+        stopband_attenuation_db: Decibels(-20.0 * stopband_ripple.log10()),
+    }
+}
+
+// Error budget of the complete DDC.
+//
+// The composite errors are worst-case bounds. The passband gains of the
+// filters multiply, so their ripples add up as (1 + d1)(1 + d2)(1 + d3) - 1.
+// In the stopband of each filter, the other filters have a gain of at most one
+// plus their passband ripple, so the composite stopband attenuation is the
+// smallest of the filter attenuations reduced by the passband gain of the other
+// filters.
+fn error_budget(
+    fir1: maia_json::DDCFilterErrors,
+    fir2: Option<maia_json::DDCFilterErrors>,
+    fir3: Option<maia_json::DDCFilterErrors>,
+) -> maia_json::DDCErrorBudget {
+    let filters = [Some(fir1), fir2, fir3]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    let max_gain_db = |f: &maia_json::DDCFilterErrors| 20.0 * (1.0 + f.passband_ripple).log10();
+    let total_gain_db = filters.iter().map(max_gain_db).sum::<f64>();
+    let composite = maia_json::DDCFilterErrors {
+        passband_ripple: filters
+            .iter()
+            .map(|f| 1.0 + f.passband_ripple)
+            .product::<f64>()
+            - 1.0,
+        stopband_attenuation_db: filters
+            .iter()
+            .map(|f| f.stopband_attenuation_db - Decibels(total_gain_db - max_gain_db(f)))
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+            .unwrap(),
+    };
+    maia_json::DDCErrorBudget {
         fir1,
-        fir2: Some(fir2),
-        fir3: Some(fir3),
-    })
+        fir2,
+        fir3,
+        composite,
+    }
 }
 
 fn stage_max_coefficients(input_samp_rate: f64, decimation: usize, four_dsp: bool) -> usize {
@@ -453,7 +551,7 @@ mod test {
     #[test]
     fn stages() {
         let config = example_config();
-        let stages = stages_design(1280, 61.44e6, &config).unwrap();
+        let (stages, _) = stages_design(1280, 61.44e6, &config).unwrap();
         let stages_quant = stages.quantize();
         assert_eq!(stages_quant.fir1.decimation, 32);
         assert_eq!(stages_quant.fir2.unwrap().decimation, 20);
@@ -481,6 +579,34 @@ mod test {
         assert_eq!(super::ichige(0.01, 0.01, 0.01, 0.001), 271);
     }
 
+    #[test]
+    fn error_budget() {
+        let config = example_config();
+        let errors = filter_errors(0.005, &config);
+        assert_eq!(errors.passband_ripple, 0.005);
+        assert!((errors.stopband_attenuation_db - Decibels(66.0206)).abs() < Decibels(1e-4));
+
+        let budget = super::error_budget(errors, None, None);
+        assert!((budget.composite.passband_ripple - errors.passband_ripple).abs() < 1e-12);
+        assert_eq!(
+            budget.composite.stopband_attenuation_db,
+            errors.stopband_attenuation_db
+        );
+
+        let other = maia_json::DDCFilterErrors {
+            passband_ripple: 0.01,
+            stopband_attenuation_db: Decibels(60.0),
+        };
+        let budget = super::error_budget(errors, None, Some(other));
+        assert!(budget.fir2.is_none());
+        assert_eq!(budget.fir3, Some(other));
+        assert!((budget.composite.passband_ripple - 0.01505).abs() < 1e-12);
+        // The attenuation of the second filter is reduced by the passband gain
+        // of the first filter.
+        let expected = Decibels(60.0 - 20.0 * 1.005f64.log10());
+        assert!((budget.composite.stopband_attenuation_db - expected).abs() < Decibels(1e-9));
+    }
+
     #[test]
     fn zero_pack() {
         assert_eq!(
@@ -0,0 +1,169 @@
+//! Noise blanker.
+//!
+//! This module contains a software noise blanker, which removes impulse noise,
+//! such as the noise produced by car ignitions or power lines, from IQ
+//! samples. The noise blanker tracks the average amplitude of the signal with
+//! an exponential moving average. When the amplitude of a sample exceeds the
+//! average by the detection threshold, this sample and the samples in the
+//! following blanking interval are replaced by zeros. The blanked samples are
+//! not included in the average, so that the impulses do not raise it.
+
+use anyhow::Result;
+use maia_json::{Decibels, Seconds};
+use num_complex::Complex32;
+
+// Time constant of the average amplitude (in seconds).
+const AVERAGE_TIME: f64 = 1e-3;
+// Minimum time constant of the average amplitude (in samples).
+const MIN_AVERAGE_SAMPLES: f64 = 16.0;
+// Maximum duration of the blanking after each impulse (in seconds).
+const MAX_BLANKING_DURATION: Seconds = Seconds(0.1);
+
+/// Noise blanker.
+///
+/// The noise blanker processes the samples in blocks of arbitrary size,
+/// keeping its state between blocks.
+#[derive(Debug, Clone)]
+pub struct NoiseBlanker {
+    // Amplitude threshold relative to the average amplitude
+    threshold: f32,
+    blanking_samples: usize,
+    // Weight of each new sample in the average amplitude
+    alpha: f32,
+    // Average amplitude, or None if no samples have been processed
+    average: Option<f32>,
+    // Number of samples remaining to be blanked
+    remaining: usize,
+}
+
+impl NoiseBlanker {
+    /// Creates a new noise blanker.
+    ///
+    /// The detection threshold and blanking duration are taken from `config`.
+    /// The samples that are processed have a sample rate of `sample_rate`.
+    pub fn new(config: &maia_json::DDCNoiseBlanker, sample_rate: f64) -> Result<NoiseBlanker> {
+        check_config(config)?;
+        anyhow::ensure!(sample_rate > 0.0, "invalid sample rate");
+        Ok(NoiseBlanker {
+            threshold: config.threshold_db.power_ratio().sqrt() as f32,
+            blanking_samples: (config.blanking_duration.0 * sample_rate).round() as usize,
+            alpha: (AVERAGE_TIME * sample_rate)
+                .max(MIN_AVERAGE_SAMPLES)
+                .recip() as f32,
+            average: None,
+            remaining: 0,
+        })
+    }
+
+    /// Blanks the impulses in a block of samples.
+    ///
+    /// The samples are modified in place. Returns the number of samples that
+    /// have been blanked.
+    pub fn process(&mut self, samples: &mut [Complex32]) -> usize {
+        if samples.is_empty() {
+            return 0;
+        }
+        // The average is initialized with the first block, since there is no
+        // better estimate of the signal amplitude.
+        let mut average = self.average.unwrap_or_else(|| {
+            samples.iter().map(|x| x.norm()).sum::<f32>() / samples.len() as f32
+        });
+        let mut blanked = 0;
+        for x in samples.iter_mut() {
+            let amplitude = x.norm();
+            if amplitude > self.threshold * average {
+                self.remaining = self.blanking_samples + 1;
+            }
+            if self.remaining > 0 {
+                self.remaining -= 1;
+                *x = Complex32::default();
+                blanked += 1;
+            } else {
+                average += self.alpha * (amplitude - average);
+            }
+        }
+        self.average = Some(average);
+        blanked
+    }
+}
+
+/// Returns the default noise blanker configuration.
+///
+/// The noise blanker is not applied by default.
+pub fn default_config() -> maia_json::DDCNoiseBlanker {
+    maia_json::DDCNoiseBlanker {
+        live: false,
+        processing: false,
+        threshold_db: Decibels(12.0),
+        blanking_duration: Seconds(100e-6),
+    }
+}
+
+/// Checks that a noise blanker configuration is valid.
+pub fn check_config(config: &maia_json::DDCNoiseBlanker) -> Result<()> {
+    anyhow::ensure!(
+        config.threshold_db.is_finite() && config.threshold_db >= Decibels(0.0),
+        "the noise blanker threshold must be non-negative"
+    );
+    anyhow::ensure!(
+        (Seconds(0.0)..=MAX_BLANKING_DURATION).contains(&config.blanking_duration),
+        "the noise blanker blanking duration must be between 0 and {MAX_BLANKING_DURATION}"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> maia_json::DDCNoiseBlanker {
+        maia_json::DDCNoiseBlanker {
+            live: true,
+            processing: true,
+            threshold_db: Decibels(12.0),
+            blanking_duration: Seconds(3e-6),
+        }
+    }
+
+    #[test]
+    fn blanks_impulses() {
+        let mut blanker = NoiseBlanker::new(&config(), 1e6).unwrap();
+        let mut samples = (0..1000)
+            .map(|n| Complex32::from_polar(0.01, 0.1 * n as f32))
+            .collect::<Vec<_>>();
+        samples[500] = Complex32::new(0.5, 0.0);
+        let expected = samples
+            .iter()
+            .enumerate()
+            .map(|(n, &x)| {
+                if (500..504).contains(&n) {
+                    Complex32::default()
+                } else {
+                    x
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(blanker.process(&mut samples[..400]), 0);
+        // the blanking continues across block boundaries
+        assert_eq!(blanker.process(&mut samples[400..502]), 2);
+        assert_eq!(blanker.process(&mut samples[502..]), 2);
+        assert_eq!(samples, expected);
+        // the average amplitude is not raised by the impulse
+        assert!((blanker.average.unwrap() - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn invalid_config() {
+        let negative_threshold = maia_json::DDCNoiseBlanker {
+            threshold_db: Decibels(-1.0),
+            ..config()
+        };
+        assert!(NoiseBlanker::new(&negative_threshold, 1e6).is_err());
+        let long_blanking = maia_json::DDCNoiseBlanker {
+            blanking_duration: Seconds(1.0),
+            ..config()
+        };
+        assert!(NoiseBlanker::new(&long_blanking, 1e6).is_err());
+        assert!(NoiseBlanker::new(&config(), 0.0).is_err());
+    }
+}
@@ -0,0 +1,235 @@
+//! Software DDC.
+//!
+//! This module contains a software implementation of the DDC, which runs the
+//! same chain as the FPGA DDC (a mixer followed by up to three FIR decimator
+//! stages) on IQ samples stored in memory. It is used to channelize recordings
+//! after they have been captured.
+//!
+//! The FIR filters are computed in single precision floating point, with the
+//! coefficients of each stage normalized to unit gain at DC. The in-phase and
+//! quadrature components are stored in separate buffers and the inner products
+//! use several accumulators, so that the compiler can vectorize them. On ARM
+//! targets that support NEON, this produces NEON code.
+
+use anyhow::Result;
+use num_complex::Complex32;
+use std::f64::consts::TAU;
+
+// Number of accumulators used in the inner products.
+const LANES: usize = 8;
+
+/// Software DDC.
+///
+/// The DDC processes the samples in blocks of arbitrary size, keeping its state
+/// between blocks, so that the output is the same as if all the samples had
+/// been processed at once.
+#[derive(Debug, Clone)]
+pub struct SoftwareDdc {
+    // Phase increment of the mixer per sample (in radians).
+    phase_step: f64,
+    phase: f64,
+    stages: Vec<FirDecimator>,
+}
+
+impl SoftwareDdc {
+    /// Creates a new software DDC.
+    ///
+    /// The DDC uses the mixer frequency and FIR filters given in `config`,
+    /// which has the same format as the configuration of the FPGA DDC. The
+    /// mixer frequency is relative to the center frequency of the input, whose
+    /// sample rate is `sample_rate`.
+    pub fn new(config: &maia_json::PutDDCConfig, sample_rate: f64) -> Result<SoftwareDdc> {
+        anyhow::ensure!(
+            config.frequency.is_finite() && sample_rate > 0.0,
+            "invalid DDC frequency or sample rate"
+        );
+        let stages = std::iter::once(&config.fir1)
+            .chain(config.fir2.as_ref())
+            .chain(config.fir3.as_ref())
+            .map(FirDecimator::new)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(SoftwareDdc {
+            phase_step: -TAU * config.frequency.0 / sample_rate,
+            phase: 0.0,
+            stages,
+        })
+    }
+
+    /// Returns the total decimation of the DDC.
+    pub fn decimation(&self) -> usize {
+        self.stages.iter().map(|stage| stage.decimation).product()
+    }
+
+    /// Processes a block of samples.
+    ///
+    /// Returns the output samples that can be computed with the samples
+    /// processed so far.
+    pub fn process(&mut self, input: &[Complex32]) -> Vec<Complex32> {
+        let mut samples = input
+            .iter()
+            .map(|&x| {
+                let (sin, cos) = self.phase.sin_cos();
+                self.phase = (self.phase + self.phase_step).rem_euclid(TAU);
+                x * Complex32::new(cos as f32, sin as f32)
+            })
+            .collect::<Vec<_>>();
+        for stage in self.stages.iter_mut() {
+            samples = stage.process(&samples);
+        }
+        samples
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FirDecimator {
+    // Coefficients in reverse order, so that the inner products run forward
+    // over the input buffers.
+    taps: Vec<f32>,
+    decimation: usize,
+    // Input samples that have not been consumed yet, including the filter
+    // history.
+    re: Vec<f32>,
+    im: Vec<f32>,
+}
+
+impl FirDecimator {
+    fn new(config: &maia_json::DDCFIRConfig) -> Result<FirDecimator> {
+        anyhow::ensure!(
+            !config.coefficients.is_empty(),
+            "the FIR filter has no coefficients"
+        );
+        anyhow::ensure!(
+            config.decimation >= 1,
+            "the FIR decimation must be at least 1"
+        );
+        let gain = config
+            .coefficients
+            .iter()
+            .map(|&h| f64::from(h))
+            .sum::<f64>();
+        anyhow::ensure!(gain != 0.0, "the FIR filter has zero gain at DC");
+        let taps = config
+            .coefficients
+            .iter()
+            .rev()
+            .map(|&h| (f64::from(h) / gain) as f32)
+            .collect::<Vec<_>>();
+        // The filter history starts with zeros.
+        let history = vec![0.0; taps.len() - 1];
+        Ok(FirDecimator {
+            taps,
+            decimation: usize::try_from(config.decimation)?,
+            re: history.clone(),
+            im: history,
+        })
+    }
+
+    fn process(&mut self, input: &[Complex32]) -> Vec<Complex32> {
+        self.re.extend(input.iter().map(|x| x.re));
+        self.im.extend(input.iter().map(|x| x.im));
+        let num_taps = self.taps.len();
+        let mut output = Vec::with_capacity(input.len() / self.decimation + 1);
+        let mut start = 0;
+        while start + num_taps <= self.re.len() {
+            let end = start + num_taps;
+            output.push(Complex32::new(
+                dot(&self.taps, &self.re[start..end]),
+                dot(&self.taps, &self.im[start..end]),
+            ));
+            start += self.decimation;
+        }
+        let consumed = start.min(self.re.len());
+        self.re.drain(..consumed);
+        self.im.drain(..consumed);
+        output
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut acc = [0.0; LANES];
+    let a_chunks = a.chunks_exact(LANES);
+    let b_chunks = b.chunks_exact(LANES);
+    let tail = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum::<f32>();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for ((acc, x), y) in acc.iter_mut().zip(x).zip(y) {
+            *acc += x * y;
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config(frequency: f64) -> maia_json::PutDDCConfig {
+        maia_json::PutDDCConfig {
+            frequency: maia_json::Hertz(frequency),
+            fir1: maia_json::DDCFIRConfig {
+                coefficients: vec![1; 20],
+                decimation: 5,
+            },
+            fir2: Some(maia_json::DDCFIRConfig {
+                coefficients: vec![1, 2, 3, 2, 1],
+                decimation: 2,
+            }),
+            fir3: None,
+            transition_bandwidth: None,
+        }
+    }
+
+    fn tone(frequency: f64, sample_rate: f64, len: usize) -> Vec<Complex32> {
+        (0..len)
+            .map(|n| {
+                let phase = TAU * frequency * n as f64 / sample_rate;
+                Complex32::new(phase.cos() as f32, phase.sin() as f32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn mixer_and_gain() {
+        let sample_rate = 1e6;
+        let mut ddc = SoftwareDdc::new(&config(100e3), sample_rate).unwrap();
+        assert_eq!(ddc.decimation(), 10);
+        let output = ddc.process(&tone(100e3, sample_rate, 10000));
+        assert_eq!(output.len(), 1000);
+        // after the filter transient, the tone is at DC with unit amplitude
+        for x in &output[10..] {
+            assert!((x - Complex32::new(1.0, 0.0)).norm() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn blocks() {
+        let sample_rate = 1e6;
+        let input = tone(123e3, sample_rate, 10007);
+        let mut ddc = SoftwareDdc::new(&config(100e3), sample_rate).unwrap();
+        let expected = ddc.process(&input);
+        let mut ddc = SoftwareDdc::new(&config(100e3), sample_rate).unwrap();
+        let output = input
+            .chunks(333)
+            .flat_map(|block| ddc.process(block))
+            .collect::<Vec<_>>();
+        assert_eq!(output.len(), expected.len());
+        for (x, y) in output.iter().zip(&expected) {
+            assert!((x - y).norm() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn invalid_config() {
+        let mut bad = config(0.0);
+        bad.fir1.decimation = 0;
+        assert!(SoftwareDdc::new(&bad, 1e6).is_err());
+        let mut bad = config(0.0);
+        bad.fir1.coefficients = vec![1, -1];
+        assert!(SoftwareDdc::new(&bad, 1e6).is_err());
+        assert!(SoftwareDdc::new(&config(f64::NAN), 1e6).is_err());
+    }
+}
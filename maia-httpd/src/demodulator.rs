@@ -0,0 +1,455 @@
+//! Server-side demodulators.
+//!
+//! This module contains a framework to run demodulators and decoders in
+//! maia-httpd, so that the device can work as a standalone decoder node. A
+//! demodulator implements the [`Demodulator`] trait, which processes blocks of
+//! IQ samples produced by the DDC, and sends its output over UDP. The kinds of
+//! demodulators that can be started through the API are listed in a
+//! [`DemodulatorRegistry`]. Some simple AM and FM audio demodulators are built
+//! in, and other demodulators can be registered by users of this crate.
+//!
+//! The blocks of IQ samples are obtained by the
+//! [`CaptureRunner`](crate::capture::CaptureRunner), so there are gaps between
+//! consecutive blocks.
+
+use crate::capture::IqBlock;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::f32::consts::PI;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Sample rate of the audio produced by the built-in demodulators.
+pub const AUDIO_SAMPLE_RATE: u32 = 48_000;
+// Number of audio samples sent in each UDP datagram.
+const AUDIO_SAMPLES_PER_DATAGRAM: usize = 512;
+
+/// Demodulator.
+///
+/// This trait is implemented by the demodulators that can be run by
+/// maia-httpd. Demodulators are constructed by a [`DemodulatorConstructor`],
+/// which receives the [`UdpOutput`] to which the demodulator should send its
+/// output.
+pub trait Demodulator: std::fmt::Debug + Send {
+    /// Processes a block of IQ samples.
+    ///
+    /// There is a gap of unknown length between the block and the previous
+    /// block that was processed, so any state that depends on the continuity
+    /// of the samples should be reset.
+    fn process(&mut self, block: &IqBlock<'_>) -> Result<()>;
+}
+
+/// Demodulator constructor.
+///
+/// A function that constructs a new demodulator that sends its output to a
+/// [`UdpOutput`].
+pub type DemodulatorConstructor = fn(UdpOutput) -> Box<dyn Demodulator>;
+
+/// UDP output of a demodulator.
+///
+/// This is a UDP socket that sends datagrams to the address given when the
+/// demodulator was started.
+#[derive(Debug)]
+pub struct UdpOutput {
+    socket: UdpSocket,
+    address: SocketAddr,
+}
+
+impl UdpOutput {
+    /// Creates a new UDP output that sends datagrams to `address`.
+    pub fn new(address: SocketAddr) -> Result<UdpOutput> {
+        let bind_address = match address {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = UdpSocket::bind(bind_address)?;
+        socket.connect(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpOutput { socket, address })
+    }
+
+    /// Returns the address to which datagrams are sent.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Sends a datagram.
+    ///
+    /// Errors caused by the destination not listening, or by the socket
+    /// buffer being full, are ignored, since UDP does not guarantee delivery
+    /// anyway.
+    pub fn send(&self, datagram: &[u8]) -> Result<()> {
+        match self.socket.send(datagram) {
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::WouldBlock
+                ) =>
+            {
+                Ok(())
+            }
+            ret => ret.map(|_| ()).map_err(Into::into),
+        }
+    }
+}
+
+/// Demodulator registry.
+///
+/// The registry lists the kinds of demodulators that can be started, together
+/// with the function that constructs each of them.
+#[derive(Debug, Clone)]
+pub struct DemodulatorRegistry {
+    kinds: BTreeMap<String, DemodulatorConstructor>,
+}
+
+impl DemodulatorRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> DemodulatorRegistry {
+        DemodulatorRegistry {
+            kinds: BTreeMap::new(),
+        }
+    }
+
+    /// Creates a registry containing the built-in demodulators.
+    ///
+    /// The built-in demodulators are `am`, `nfm` (narrowband FM, with 5 kHz
+    /// deviation) and `wfm` (wideband FM, with 75 kHz deviation). They send
+    /// mono audio with a sample rate of [`AUDIO_SAMPLE_RATE`] as 16-bit signed
+    /// little-endian integers.
+    pub fn with_builtin() -> DemodulatorRegistry {
+        let mut registry = DemodulatorRegistry::new();
+        registry.register("am", |output| {
+            Box::new(AudioDemodulator::new(Modulation::Am, output))
+        });
+        registry.register("nfm", |output| {
+            Box::new(AudioDemodulator::new(
+                Modulation::Fm { deviation: 5e3 },
+                output,
+            ))
+        });
+        registry.register("wfm", |output| {
+            Box::new(AudioDemodulator::new(
+                Modulation::Fm { deviation: 75e3 },
+                output,
+            ))
+        });
+        registry
+    }
+
+    /// Registers a kind of demodulator.
+    ///
+    /// If there was already a demodulator with the same kind, it is replaced.
+    pub fn register(&mut self, kind: &str, constructor: DemodulatorConstructor) {
+        self.kinds.insert(kind.to_string(), constructor);
+    }
+
+    /// Returns an iterator over the kinds of demodulators in the registry.
+    pub fn kinds(&self) -> impl Iterator<Item = &str> {
+        self.kinds.keys().map(|kind| kind.as_str())
+    }
+
+    /// Constructs a demodulator of a given kind.
+    ///
+    /// Returns `None` if the kind is not in the registry.
+    pub fn create(&self, kind: &str, output: UdpOutput) -> Option<Box<dyn Demodulator>> {
+        self.kinds.get(kind).map(|constructor| constructor(output))
+    }
+}
+
+impl Default for DemodulatorRegistry {
+    fn default() -> DemodulatorRegistry {
+        DemodulatorRegistry::with_builtin()
+    }
+}
+
+/// Demodulators.
+///
+/// This struct contains the [`DemodulatorRegistry`] and the demodulator
+/// instances that are running. It is used by the REST API and by the
+/// [`CaptureRunner`](crate::capture::CaptureRunner).
+#[derive(Debug, Default)]
+pub struct Demodulators {
+    inner: Mutex<DemodulatorsInner>,
+    changed: Notify,
+}
+
+#[derive(Debug, Default)]
+struct DemodulatorsInner {
+    registry: DemodulatorRegistry,
+    instances: BTreeMap<String, Instance>,
+}
+
+#[derive(Debug)]
+struct Instance {
+    kind: String,
+    udp_address: SocketAddr,
+    demodulator: Box<dyn Demodulator>,
+    blocks: u64,
+}
+
+impl Instance {
+    fn json(&self, name: &str) -> maia_json::Demodulator {
+        maia_json::Demodulator {
+            name: name.to_string(),
+            kind: self.kind.clone(),
+            udp_address: self.udp_address.to_string(),
+            blocks: self.blocks,
+        }
+    }
+}
+
+impl Demodulators {
+    /// Creates a new object using a given registry.
+    ///
+    /// Initially there are no demodulator instances running.
+    pub fn new(registry: DemodulatorRegistry) -> Demodulators {
+        Demodulators {
+            inner: Mutex::new(DemodulatorsInner {
+                registry,
+                instances: BTreeMap::new(),
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Registers a kind of demodulator.
+    ///
+    /// See [`DemodulatorRegistry::register`].
+    pub fn register(&self, kind: &str, constructor: DemodulatorConstructor) {
+        self.inner
+            .lock()
+            .unwrap()
+            .registry
+            .register(kind, constructor);
+    }
+
+    /// Starts a demodulator instance.
+    ///
+    /// The instance is identified by its `name`. If there is already an
+    /// instance with the same name, it is replaced.
+    pub fn start(
+        &self,
+        name: &str,
+        kind: &str,
+        udp_address: SocketAddr,
+    ) -> Result<maia_json::Demodulator> {
+        let mut inner = self.inner.lock().unwrap();
+        anyhow::ensure!(
+            inner.registry.kinds.contains_key(kind),
+            "unknown demodulator kind {kind}"
+        );
+        let output = UdpOutput::new(udp_address)?;
+        let demodulator = inner.registry.create(kind, output).unwrap();
+        let instance = Instance {
+            kind: kind.to_string(),
+            udp_address,
+            demodulator,
+            blocks: 0,
+        };
+        let json = instance.json(name);
+        tracing::info!(name, kind, %udp_address, "starting demodulator");
+        inner.instances.insert(name.to_string(), instance);
+        self.changed.notify_one();
+        Ok(json)
+    }
+
+    /// Stops a demodulator instance.
+    ///
+    /// Returns `None` if there is no instance with this name.
+    pub fn stop(&self, name: &str) -> Option<maia_json::Demodulator> {
+        let instance = self.inner.lock().unwrap().instances.remove(name)?;
+        tracing::info!(name, "stopped demodulator");
+        Some(instance.json(name))
+    }
+
+    /// Returns the JSON description of a demodulator instance.
+    ///
+    /// Returns `None` if there is no instance with this name.
+    pub fn instance_json(&self, name: &str) -> Option<maia_json::Demodulator> {
+        self.inner
+            .lock()
+            .unwrap()
+            .instances
+            .get(name)
+            .map(|instance| instance.json(name))
+    }
+
+    /// Returns the JSON description of the demodulators.
+    pub fn json(&self) -> maia_json::Demodulators {
+        let inner = self.inner.lock().unwrap();
+        maia_json::Demodulators {
+            kinds: inner.registry.kinds().map(String::from).collect(),
+            instances: inner
+                .instances
+                .iter()
+                .map(|(name, instance)| instance.json(name))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().instances.is_empty()
+    }
+
+    pub(crate) fn changed(&self) -> &Notify {
+        &self.changed
+    }
+
+    pub(crate) fn process(&self, block: &IqBlock<'_>) {
+        let mut inner = self.inner.lock().unwrap();
+        for (name, instance) in inner.instances.iter_mut() {
+            if let Err(err) = instance.demodulator.process(block) {
+                tracing::warn!(name, "demodulator failed to process block: {err:#}");
+            }
+            instance.blocks += 1;
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Modulation {
+    Am,
+    // The deviation is given in Hz and corresponds to the full scale of the
+    // audio output.
+    Fm { deviation: f32 },
+}
+
+// Built-in audio demodulator. The demodulator is tuned to the center frequency
+// of the DDC.
+#[derive(Debug)]
+struct AudioDemodulator {
+    modulation: Modulation,
+    output: UdpOutput,
+}
+
+impl AudioDemodulator {
+    fn new(modulation: Modulation, output: UdpOutput) -> AudioDemodulator {
+        AudioDemodulator { modulation, output }
+    }
+
+    fn demodulate(&self, block: &IqBlock<'_>) -> Vec<f32> {
+        match self.modulation {
+            Modulation::Am => {
+                // Envelope detector. The DC component of the envelope is used
+                // to normalize the audio, which acts as an AGC.
+                let envelope = block.samples.iter().map(|x| x.norm()).collect::<Vec<_>>();
+                let dc = envelope.iter().sum::<f32>() / envelope.len().max(1) as f32;
+                if dc == 0.0 {
+                    return vec![0.0; envelope.len()];
+                }
+                envelope.into_iter().map(|x| (x - dc) / dc).collect()
+            }
+            Modulation::Fm { deviation } => {
+                // Quadrature demodulator
+                let scale = block.sample_rate as f32 / (2.0 * PI * deviation);
+                block
+                    .samples
+                    .windows(2)
+                    .map(|x| (x[1] * x[0].conj()).arg() * scale)
+                    .collect()
+            }
+        }
+    }
+}
+
+impl Demodulator for AudioDemodulator {
+    fn process(&mut self, block: &IqBlock<'_>) -> Result<()> {
+        let audio = resample(&self.demodulate(block), block.sample_rate);
+        for chunk in audio.chunks(AUDIO_SAMPLES_PER_DATAGRAM) {
+            let datagram = chunk
+                .iter()
+                .flat_map(|&x| ((x.clamp(-1.0, 1.0) * 32767.0).round() as i16).to_le_bytes())
+                .collect::<Vec<u8>>();
+            self.output.send(&datagram)?;
+        }
+        Ok(())
+    }
+}
+
+// Resamples audio to AUDIO_SAMPLE_RATE by averaging the input samples that fall
+// in each output sample period. This acts as a simple anti-aliasing filter when
+// decimating.
+fn resample(input: &[f32], sample_rate: f64) -> Vec<f32> {
+    let ratio = sample_rate / f64::from(AUDIO_SAMPLE_RATE);
+    let num_output = (input.len() as f64 / ratio) as usize;
+    (0..num_output)
+        .map(|k| {
+            let start = (k as f64 * ratio) as usize;
+            let end = (((k + 1) as f64 * ratio) as usize).clamp(start + 1, input.len());
+            input[start..end].iter().sum::<f32>() / (end - start) as f32
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_complex::Complex32;
+
+    fn tone(frequency: f32, sample_rate: f32, len: usize) -> impl Iterator<Item = f32> {
+        (0..len).map(move |n| (2.0 * PI * frequency * n as f32 / sample_rate).sin())
+    }
+
+    #[test]
+    fn resample_average() {
+        let input = (0..96).map(|x| x as f32).collect::<Vec<_>>();
+        let output = resample(&input, 96e3);
+        assert_eq!(output.len(), 48);
+        assert_eq!(output[0], 0.5);
+        assert_eq!(output[47], 94.5);
+        // Upsampling repeats samples
+        let output = resample(&[1.0, 2.0], 24e3);
+        assert_eq!(output, [1.0, 1.0, 2.0, 2.0]);
+    }
+
+    #[test]
+    fn demodulate() {
+        let sample_rate = 240e3;
+        let output = UdpOutput::new("127.0.0.1:9".parse().unwrap()).unwrap();
+        let mut demodulator = AudioDemodulator::new(Modulation::Am, output);
+        let samples = tone(1e3, sample_rate as f32, 2400)
+            .map(|x| Complex32::new(2.0 + x, 0.0))
+            .collect::<Vec<_>>();
+        let block = IqBlock {
+            samples: &samples,
+            sample_rate,
+            frequency: 100e6,
+            sample_counter: None,
+            decimation: 1,
+        };
+        let audio = demodulator.demodulate(&block);
+        for (x, y) in audio.iter().zip(tone(1e3, sample_rate as f32, 2400)) {
+            assert!((x - 0.5 * y).abs() < 1e-3);
+        }
+        demodulator.process(&block).unwrap();
+
+        demodulator.modulation = Modulation::Fm { deviation: 5e3 };
+        // FM signal with a constant frequency of 2.5 kHz
+        let samples = (0..2400)
+            .map(|n| Complex32::from_polar(1.0, 2.0 * PI * 2.5e3 * n as f32 / sample_rate as f32))
+            .collect::<Vec<_>>();
+        let audio = demodulator.demodulate(&IqBlock {
+            samples: &samples,
+            ..block
+        });
+        assert_eq!(audio.len(), 2399);
+        assert!(audio.iter().all(|x| (x - 0.5).abs() < 1e-3));
+    }
+
+    #[test]
+    fn start_stop() {
+        let demodulators = Demodulators::default();
+        assert_eq!(demodulators.json().kinds, ["am", "nfm", "wfm"]);
+        let address = "127.0.0.1:7355".parse().unwrap();
+        assert!(demodulators.start("radio", "ssb", address).is_err());
+        let instance = demodulators.start("radio", "nfm", address).unwrap();
+        assert_eq!(instance.udp_address, "127.0.0.1:7355");
+        demodulators.start("radio", "am", address).unwrap();
+        assert_eq!(demodulators.json().instances.len(), 1);
+        assert_eq!(demodulators.instance_json("radio").unwrap().kind, "am");
+        assert_eq!(demodulators.stop("radio").unwrap().name, "radio");
+        assert!(demodulators.stop("radio").is_none());
+        assert!(demodulators.is_empty());
+    }
+}
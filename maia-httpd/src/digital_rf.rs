@@ -0,0 +1,329 @@
+//! Digital RF format.
+//!
+//! This module contains the functions required to write recordings in the
+//! [Digital RF](https://github.com/MITHaystack/digital_rf) format. A Digital RF
+//! channel is formed by a `drf_properties.h5` file, which contains the
+//! properties of the channel, and a series of HDF5 files containing the
+//! samples. These files are stored in subdirectories that cover a fixed time
+//! interval, and each file covers a fixed time interval within its
+//! subdirectory. Samples are identified by their global index, which is the
+//! number of samples since the UNIX epoch.
+//!
+//! Only the Digital RF channel is written. Digital Metadata, which is used to
+//! store other parameters such as the center frequency, is not supported.
+
+use crate::hdf5;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::hash::BuildHasher;
+
+/// Duration of the subdirectories in seconds.
+pub const SUBDIR_CADENCE_SECS: u64 = 3600;
+/// Duration of the files in milliseconds.
+pub const FILE_CADENCE_MILLISECS: u64 = 1000;
+/// Version of Digital RF with which the files are compatible.
+pub const DIGITAL_RF_VERSION: &str = "2.6.0";
+
+const EPOCH: &str = "1970-01-01T00:00:00Z";
+const TIME_DESCRIPTION: &str = "All times in this format are in number of samples since the \
+                                epoch in the epoch attribute. The first sample time will be \
+                                sample_rate * UTC time at first sample. Attribute \
+                                init_utc_timestamp records this init UTC time so that a \
+                                conversion to any other time is possible given the number of \
+                                leapseconds between epoch and init_utc_timestamp. Leap seconds \
+                                are not included in the global sample index.";
+
+/// Digital RF channel.
+///
+/// This struct describes a channel formed by complex samples with a single
+/// subchannel.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Channel {
+    sample_rate_numerator: u64,
+    sample_rate_denominator: u64,
+    component_size: usize,
+}
+
+/// Digital RF data file.
+///
+/// This describes one of the files that contain the samples of a recording.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct RfFile {
+    /// Path of the file, relative to the channel directory.
+    pub path: String,
+    /// Global index of the first sample in the file.
+    pub global_index: u64,
+    /// Index of the first sample in the file relative to the start of the
+    /// recording.
+    pub recording_index: u64,
+    /// Number of samples in the file.
+    pub num_samples: u64,
+}
+
+impl Channel {
+    /// Creates a new channel.
+    ///
+    /// The sample rate is given in samples per second. It is represented as a
+    /// rational number, which is exact if the sample rate is an integer divided
+    /// by a decimation factor smaller than 10000. The `component_size` is the
+    /// size in bytes of the real and imaginary parts of each sample, which are
+    /// stored as signed integers.
+    pub fn new(sample_rate: f64, component_size: usize) -> Result<Channel> {
+        anyhow::ensure!(
+            sample_rate.is_finite() && sample_rate >= 1.0,
+            "invalid sample rate {sample_rate}"
+        );
+        anyhow::ensure!(
+            matches!(component_size, 1 | 2 | 4 | 8),
+            "invalid component size {component_size}"
+        );
+        const MAX_DENOMINATOR: u64 = 10000;
+        let (numerator, denominator) = (1..MAX_DENOMINATOR)
+            .map(|den| ((sample_rate * den as f64).round() as u64, den))
+            .find(|&(num, den)| (num as f64 / den as f64 - sample_rate).abs() <= 1e-9 * sample_rate)
+            .unwrap_or((sample_rate.round() as u64, 1));
+        let gcd = gcd(numerator, denominator);
+        Ok(Channel {
+            sample_rate_numerator: numerator / gcd,
+            sample_rate_denominator: denominator / gcd,
+            component_size,
+        })
+    }
+
+    /// Returns the sample rate as a rational number.
+    ///
+    /// The numerator and denominator are returned, in this order.
+    pub fn sample_rate(&self) -> (u64, u64) {
+        (self.sample_rate_numerator, self.sample_rate_denominator)
+    }
+
+    /// Returns the global index of the sample closest to a given time.
+    pub fn global_index(&self, datetime: DateTime<Utc>) -> Result<u64> {
+        let nanos = i128::from(datetime.timestamp()) * 1_000_000_000
+            + i128::from(datetime.timestamp_subsec_nanos());
+        let den = i128::from(self.sample_rate_denominator) * 1_000_000_000;
+        let index = (nanos * i128::from(self.sample_rate_numerator) + den / 2) / den;
+        Ok(u64::try_from(index)?)
+    }
+
+    /// Returns the files used to store a recording.
+    ///
+    /// The recording starts at the sample with global index `global_index` and
+    /// contains `num_samples` samples.
+    pub fn rf_files(&self, global_index: u64, num_samples: u64) -> Vec<RfFile> {
+        let mut files = Vec::new();
+        let end = global_index + num_samples;
+        let mut index = global_index;
+        while index < end {
+            let millis = self.index_to_millis(index);
+            let file_millis = millis - millis % FILE_CADENCE_MILLISECS;
+            let next_index = self.millis_to_index(file_millis + FILE_CADENCE_MILLISECS);
+            let file_secs = file_millis / 1000;
+            let subdir_secs = file_secs - file_secs % SUBDIR_CADENCE_SECS;
+            let subdir = DateTime::from_timestamp(subdir_secs as i64, 0)
+                .unwrap()
+                .format("%Y-%m-%dT%H-%M-%S");
+            files.push(RfFile {
+                path: format!("{subdir}/rf@{file_secs}.{:03}.h5", file_millis % 1000),
+                global_index: index,
+                recording_index: index - global_index,
+                num_samples: next_index.min(end) - index,
+            });
+            index = next_index;
+        }
+        files
+    }
+
+    // Time of a sample in milliseconds since the epoch, rounded down.
+    fn index_to_millis(&self, index: u64) -> u64 {
+        (u128::from(index) * u128::from(self.sample_rate_denominator) * 1000
+            / u128::from(self.sample_rate_numerator)) as u64
+    }
+
+    // Index of the first sample at or after a time in milliseconds since the
+    // epoch.
+    fn millis_to_index(&self, millis: u64) -> u64 {
+        let den = u128::from(self.sample_rate_denominator) * 1000;
+        (u128::from(millis) * u128::from(self.sample_rate_numerator)).div_ceil(den) as u64
+    }
+
+    fn properties(&self) -> Vec<(String, hdf5::Value)> {
+        let component_bits = 8 * self.component_size as i32;
+        [
+            ("H5Tget_class", hdf5::Value::I32(0)),
+            ("H5Tget_size", hdf5::Value::I32(self.component_size as i32)),
+            ("H5Tget_order", hdf5::Value::I32(0)),
+            ("H5Tget_precision", hdf5::Value::I32(component_bits)),
+            ("H5Tget_offset", hdf5::Value::I32(0)),
+            ("subdir_cadence_secs", hdf5::Value::U64(SUBDIR_CADENCE_SECS)),
+            (
+                "file_cadence_millisecs",
+                hdf5::Value::U64(FILE_CADENCE_MILLISECS),
+            ),
+            (
+                "sample_rate_numerator",
+                hdf5::Value::U64(self.sample_rate_numerator),
+            ),
+            (
+                "sample_rate_denominator",
+                hdf5::Value::U64(self.sample_rate_denominator),
+            ),
+            ("is_complex", hdf5::Value::I32(1)),
+            ("num_subchannels", hdf5::Value::I32(1)),
+            ("is_continuous", hdf5::Value::I32(1)),
+            ("epoch", hdf5::Value::String(EPOCH.to_string())),
+            (
+                "digital_rf_time_description",
+                hdf5::Value::String(TIME_DESCRIPTION.to_string()),
+            ),
+            (
+                "digital_rf_version",
+                hdf5::Value::String(DIGITAL_RF_VERSION.to_string()),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+    }
+
+    /// Returns the contents of the `drf_properties.h5` file of the channel.
+    pub fn properties_file(&self) -> Result<Vec<u8>> {
+        hdf5::File {
+            attributes: self.properties(),
+            datasets: Vec::new(),
+        }
+        .to_bytes()
+    }
+
+    /// Returns the contents of a data file, excluding its samples.
+    ///
+    /// The samples, given as interleaved little-endian real and imaginary
+    /// parts, need to be appended to the returned bytes to form the file.
+    ///
+    /// The `sequence_num` is the index of the file within the recording. The
+    /// `init_utc_timestamp` is the UNIX timestamp of the start of the
+    /// recording.
+    pub fn rf_file_header(
+        &self,
+        file: &RfFile,
+        sequence_num: usize,
+        init_utc_timestamp: u64,
+    ) -> Result<Vec<u8>> {
+        let component = hdf5::Datatype::Integer {
+            size: self.component_size,
+            signed: true,
+        };
+        let mut attributes = vec![
+            (
+                "sequence_num".to_string(),
+                hdf5::Value::I32(i32::try_from(sequence_num)?),
+            ),
+            (
+                "init_utc_timestamp".to_string(),
+                hdf5::Value::U64(init_utc_timestamp),
+            ),
+            (
+                "computer_time".to_string(),
+                hdf5::Value::U64(u64::try_from(Utc::now().timestamp())?),
+            ),
+            ("uuid_str".to_string(), hdf5::Value::String(uuid())),
+        ];
+        attributes.extend(self.properties());
+        let index_data = [file.global_index, 0]
+            .iter()
+            .flat_map(|x| x.to_le_bytes())
+            .collect();
+        hdf5::File {
+            attributes: Vec::new(),
+            datasets: vec![
+                hdf5::Dataset {
+                    name: "rf_data_index".to_string(),
+                    datatype: hdf5::Datatype::Integer {
+                        size: 8,
+                        signed: false,
+                    },
+                    dimensions: vec![1, 2],
+                    attributes: Vec::new(),
+                    data: Some(index_data),
+                },
+                hdf5::Dataset {
+                    name: "rf_data".to_string(),
+                    datatype: hdf5::Datatype::Compound(vec![
+                        ("r".to_string(), component.clone()),
+                        ("i".to_string(), component),
+                    ]),
+                    dimensions: vec![file.num_samples, 1],
+                    attributes,
+                    data: None,
+                },
+            ],
+        }
+        .to_bytes()
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Generates a random hexadecimal identifier of 128 bits.
+fn uuid() -> String {
+    let state = std::collections::hash_map::RandomState::new();
+    format!(
+        "{:016x}{:016x}",
+        state.hash_one(0),
+        state.hash_one(Utc::now().timestamp_nanos_opt())
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sample_rate() {
+        assert_eq!(
+            Channel::new(61.44e6, 2).unwrap().sample_rate(),
+            (61440000, 1)
+        );
+        assert_eq!(
+            Channel::new(61.44e6 / 7.0, 2).unwrap().sample_rate(),
+            (61440000, 7)
+        );
+        assert_eq!(
+            Channel::new(30.72e6 / 20.0, 1).unwrap().sample_rate(),
+            (1536000, 1)
+        );
+        assert!(Channel::new(1e6, 3).is_err());
+    }
+
+    #[test]
+    fn rf_files() {
+        let channel = Channel::new(1000.0 / 3.0, 2).unwrap();
+        let datetime = DateTime::from_timestamp(1704070799, 500_000_000).unwrap();
+        let start = channel.global_index(datetime).unwrap();
+        assert_eq!(start, 568023599833);
+        let files = channel.rf_files(start, 400);
+        assert_eq!(
+            files,
+            [
+                RfFile {
+                    path: "2024-01-01T00-00-00/rf@1704070799.000.h5".to_string(),
+                    global_index: 568023599833,
+                    recording_index: 0,
+                    num_samples: 167,
+                },
+                RfFile {
+                    path: "2024-01-01T01-00-00/rf@1704070800.000.h5".to_string(),
+                    global_index: 568023600000,
+                    recording_index: 167,
+                    num_samples: 233,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Satellite Doppler correction.
+//!
+//! This module implements a Doppler correction engine for satellite downlinks.
+//! The orbit of the target satellite is propagated from its TLE with SGP4, and
+//! the Doppler shift seen at the device geolocation is computed periodically.
+//! During the passes of the satellite, the DDC frequency is adjusted whenever
+//! the correction changes significantly, so that the satellite signal stays
+//! centred in the DDC output. Retuning the DDC through the API disables the
+//! correction.
+
+use crate::{app::AppState, sigmf};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use maia_json::{DopplerStatus, DopplerTarget, Geolocation, Hertz, SpectrometerInput};
+use sgp4::{Sgp4, Tle};
+use std::sync::Mutex;
+use std::time::Duration;
+
+mod sgp4;
+
+// Period with which the Doppler correction is updated.
+const UPDATE_PERIOD: Duration = Duration::from_millis(500);
+// Minimum change of the correction (in Hz) that retunes the DDC. This avoids
+// retuning the DDC on every update when the correction changes slowly.
+const RETUNE_THRESHOLD: Hertz = Hertz(10.0);
+// Speed of light (in m/s).
+const SPEED_OF_LIGHT: f64 = 299_792_458.0;
+// Earth rotation rate (in rad/s), as used in the TEME to Earth-fixed
+// conversion.
+const EARTH_ROTATION_RATE: f64 = 7.292115146706979e-5;
+// WGS84 ellipsoid semi-major axis (in km) and flattening.
+const WGS84_A: f64 = 6378.137;
+const WGS84_F: f64 = 1.0 / 298.257223563;
+
+/// Doppler correction.
+///
+/// This struct contains the settings of the Doppler correction, and the
+/// status computed by the [`DopplerCorrector`] for the target satellite.
+#[derive(Debug, Default)]
+pub struct Doppler(Mutex<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    enabled: bool,
+    target: Option<Target>,
+    status: Option<DopplerStatus>,
+}
+
+#[derive(Debug)]
+struct Target {
+    json: DopplerTarget,
+    sgp4: Sgp4,
+}
+
+impl Doppler {
+    /// Creates a new Doppler correction object.
+    ///
+    /// The Doppler correction is initially disabled and has no target.
+    pub fn new() -> Doppler {
+        Doppler::default()
+    }
+
+    /// Returns the JSON representation of the Doppler correction.
+    pub fn json(&self) -> maia_json::Doppler {
+        let inner = self.0.lock().unwrap();
+        maia_json::Doppler {
+            enabled: inner.enabled,
+            target: inner.target.as_ref().map(|target| target.json.clone()),
+            status: inner.status,
+        }
+    }
+
+    /// Enables or disables the Doppler correction.
+    pub fn set_enabled(&self, enabled: bool) {
+        let mut inner = self.0.lock().unwrap();
+        inner.enabled = enabled;
+        if let Some(status) = &mut inner.status {
+            status.applied &= enabled;
+        }
+    }
+
+    /// Sets the target satellite.
+    ///
+    /// An error is returned if the TLE of the target cannot be parsed or if
+    /// its orbit is not supported.
+    pub fn set_target(&self, target: DopplerTarget) -> Result<()> {
+        anyhow::ensure!(
+            target.frequency > Hertz(0.0),
+            "the target frequency must be positive"
+        );
+        let tle = Tle::parse(&target.tle_line1, &target.tle_line2)?;
+        let sgp4 = Sgp4::new(&tle)?;
+        let mut inner = self.0.lock().unwrap();
+        inner.target = Some(Target { json: target, sgp4 });
+        inner.status = None;
+        Ok(())
+    }
+
+    /// Returns the Doppler correction that is being applied to the DDC.
+    ///
+    /// This is `None` if the correction is not being applied.
+    pub fn applied_correction(&self) -> Option<sigmf::DopplerCorrection> {
+        let inner = self.0.lock().unwrap();
+        let status = inner.status.filter(|status| status.applied)?;
+        let target = inner.target.as_ref()?;
+        Some(sigmf::DopplerCorrection {
+            satellite: target.json.name.clone(),
+            frequency: target.json.frequency.0,
+            correction: status.correction.0,
+        })
+    }
+
+    // Computes the status of the target satellite at the given time. If the
+    // correction should be applied, returns the corrected satellite frequency.
+    fn update_status(
+        &self,
+        geolocation: Option<&Geolocation>,
+        time: DateTime<Utc>,
+    ) -> Option<Hertz> {
+        let mut inner = self.0.lock().unwrap();
+        let status = match (&inner.target, geolocation) {
+            (Some(target), Some(geolocation)) => match look(&target.sgp4, geolocation, time) {
+                Ok(look) => Some(DopplerStatus {
+                    correction: target.json.frequency * (-look.range_rate / SPEED_OF_LIGHT),
+                    range_rate: look.range_rate,
+                    elevation: look.elevation,
+                    applied: false,
+                }),
+                Err(err) => {
+                    if inner.status.is_some() {
+                        tracing::warn!(%err, "could not propagate the orbit of the target");
+                    }
+                    None
+                }
+            },
+            _ => None,
+        };
+        inner.status = status;
+        let status = status.filter(|status| inner.enabled && status.elevation >= 0.0)?;
+        Some(inner.target.as_ref().unwrap().json.frequency + status.correction)
+    }
+
+    fn set_applied(&self, applied: bool) {
+        if let Some(status) = &mut self.0.lock().unwrap().status {
+            status.applied = applied;
+        }
+    }
+}
+
+// Range rate (in m/s) and elevation (in degrees) of a satellite as seen from
+// an observer.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Look {
+    range_rate: f64,
+    elevation: f64,
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a.iter().zip(b.iter()).map(|(x, y)| x * y).sum()
+}
+
+// Computes the position (in km) and velocity (in km/s) of a satellite in the
+// Earth-fixed frame. Polar motion is neglected.
+fn earth_fixed_state(sgp4: &Sgp4, time: DateTime<Utc>) -> Result<([f64; 3], [f64; 3])> {
+    let state = sgp4.state_at(time)?;
+    let (sin_g, cos_g) = sgp4::gmst(time).sin_cos();
+    let [x, y, z] = state.position;
+    let [vx, vy, vz] = state.velocity;
+    let position = [cos_g * x + sin_g * y, -sin_g * x + cos_g * y, z];
+    let velocity = [
+        cos_g * vx + sin_g * vy + EARTH_ROTATION_RATE * position[1],
+        -sin_g * vx + cos_g * vy - EARTH_ROTATION_RATE * position[0],
+        vz,
+    ];
+    Ok((position, velocity))
+}
+
+// Computes the Earth-fixed position (in km) and the local vertical of an
+// observer.
+fn observer_position(geolocation: &Geolocation) -> ([f64; 3], [f64; 3]) {
+    let (sin_lat, cos_lat) = geolocation.latitude.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = geolocation.longitude.to_radians().sin_cos();
+    let altitude = geolocation.altitude.unwrap_or(0.0) * 1e-3;
+    let e2 = WGS84_F * (2.0 - WGS84_F);
+    let n = WGS84_A / (1.0 - e2 * sin_lat * sin_lat).sqrt();
+    let position = [
+        (n + altitude) * cos_lat * cos_lon,
+        (n + altitude) * cos_lat * sin_lon,
+        (n * (1.0 - e2) + altitude) * sin_lat,
+    ];
+    let up = [cos_lat * cos_lon, cos_lat * sin_lon, sin_lat];
+    (position, up)
+}
+
+fn look(sgp4: &Sgp4, geolocation: &Geolocation, time: DateTime<Utc>) -> Result<Look> {
+    let (position, velocity) = earth_fixed_state(sgp4, time)?;
+    let (observer, up) = observer_position(geolocation);
+    let range: [f64; 3] = std::array::from_fn(|j| position[j] - observer[j]);
+    let distance = dot(range, range).sqrt();
+    Ok(Look {
+        range_rate: dot(range, velocity) / distance * 1e3,
+        elevation: (dot(range, up) / distance).asin().to_degrees(),
+    })
+}
+
+// Action taken by the Doppler corrector on each update.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Retune {
+    // Set the DDC to the new frequency.
+    Set,
+    // Keep the DDC frequency, because the correction has changed less than
+    // the threshold.
+    Keep,
+    // The DDC has been retuned by the user since the last correction.
+    UserRetuned,
+}
+
+impl Retune {
+    // Decides how to apply a new DDC frequency, given the DDC frequency set by
+    // the previous correction (if any) and the current DDC frequency.
+    fn new(applied: Option<Hertz>, current: Hertz, frequency: Hertz) -> Retune {
+        match applied {
+            None => Retune::Set,
+            Some(applied) if applied != current => Retune::UserRetuned,
+            Some(applied) if (frequency - applied).abs() < RETUNE_THRESHOLD => Retune::Keep,
+            Some(_) => Retune::Set,
+        }
+    }
+}
+
+/// Doppler corrector.
+///
+/// The Doppler corrector periodically computes the Doppler status of the
+/// target satellite and, if the correction is enabled and the satellite is
+/// above the horizon, sets the DDC frequency accordingly. The DDC is only
+/// retuned when the correction has changed significantly. If the user retunes
+/// the DDC while the correction is applied, the correction is disabled, so
+/// that the corrector does not override the user.
+#[derive(Debug)]
+pub struct DopplerCorrector {
+    state: AppState,
+}
+
+impl DopplerCorrector {
+    /// Creates a new Doppler corrector.
+    pub fn new(state: AppState) -> DopplerCorrector {
+        DopplerCorrector { state }
+    }
+
+    /// Runs the Doppler corrector.
+    ///
+    /// This only returns if there is an error accessing the AD9361.
+    #[tracing::instrument(name = "doppler", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let mut interval = tokio::time::interval(UPDATE_PERIOD);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        // Used to avoid logging the same error on every update
+        let mut out_of_range = false;
+        // DDC frequency set by the last correction, which is used to detect
+        // whether the DDC has been retuned by the user.
+        let mut applied_frequency = None;
+        loop {
+            interval.tick().await;
+            let geolocation = self.state.geolocation().lock().unwrap().clone();
+            let Some(frequency) = self
+                .state
+                .doppler()
+                .update_status(geolocation.as_ref(), Utc::now())
+            else {
+                applied_frequency = None;
+                continue;
+            };
+            let (lo_frequency, samp_rate) = {
+                let ad9361 = self.state.ad9361().lock().await;
+                (
+                    ad9361.get_rx_lo_frequency().await?,
+                    ad9361.get_sampling_frequency().await?,
+                )
+            };
+            let ddc_frequency = frequency - self.state.device().frequency_offset() - lo_frequency;
+            let result = {
+                let mut ip_core = self.state.ip_core().lock().unwrap();
+                match Retune::new(applied_frequency, ip_core.ddc_frequency(), ddc_frequency) {
+                    Retune::Keep => Ok(()),
+                    Retune::UserRetuned => {
+                        drop(ip_core);
+                        tracing::info!("DDC retuned by the user: disabling Doppler correction");
+                        self.state.doppler().set_enabled(false);
+                        applied_frequency = None;
+                        continue;
+                    }
+                    Retune::Set => {
+                        let result = ip_core.set_ddc_frequency(ddc_frequency, samp_rate);
+                        if result.is_ok() {
+                            applied_frequency = Some(ddc_frequency);
+                            if matches!(ip_core.spectrometer_input(), SpectrometerInput::DDC) {
+                                self.state.spectrometer_config().retune(&ip_core);
+                            }
+                        }
+                        result
+                    }
+                }
+            };
+            match result {
+                Ok(()) => out_of_range = false,
+                Err(err) => {
+                    applied_frequency = None;
+                    if !out_of_range {
+                        tracing::warn!(%err, "could not apply Doppler correction");
+                    }
+                    out_of_range = true;
+                }
+            }
+            self.state.doppler().set_applied(!out_of_range);
+        }
+    }
+}
+
+/// Returns a target that can be used in tests.
+///
+/// The target uses the TLE of Vanguard 1 that is used for the SGP4 test
+/// vectors in "Revisiting Spacetrack Report #3".
+#[cfg(test)]
+pub(crate) fn test_target() -> DopplerTarget {
+    DopplerTarget {
+        name: "VANGUARD 1".to_string(),
+        tle_line1: "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753"
+            .to_string(),
+        tle_line2: "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667"
+            .to_string(),
+        frequency: Hertz(108e6),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn geolocation() -> Geolocation {
+        Geolocation {
+            latitude: 40.4,
+            longitude: -3.7,
+            altitude: Some(650.0),
+        }
+    }
+
+    #[test]
+    fn range_rate() {
+        let target = test_target();
+        let sgp4 = Sgp4::new(&Tle::parse(&target.tle_line1, &target.tle_line2).unwrap()).unwrap();
+        let geolocation = geolocation();
+        let time = Utc.with_ymd_and_hms(2000, 6, 28, 0, 0, 0).unwrap();
+        // The range rate must match the numerical derivative of the range
+        let distance = |time| {
+            let (position, _) = earth_fixed_state(&sgp4, time).unwrap();
+            let (observer, _) = observer_position(&geolocation);
+            let range: [f64; 3] = std::array::from_fn(|j| position[j] - observer[j]);
+            dot(range, range).sqrt() * 1e3
+        };
+        let dt = chrono::Duration::milliseconds(500);
+        // The two evaluations are separated by 1 second
+        let derivative = distance(time + dt) - distance(time - dt);
+        let look = look(&sgp4, &geolocation, time).unwrap();
+        assert!(
+            (look.range_rate - derivative).abs() < 0.1,
+            "{} != {derivative}",
+            look.range_rate
+        );
+        assert!((-90.0..=90.0).contains(&look.elevation));
+    }
+
+    #[test]
+    fn retune() {
+        let khz = Hertz::from_khz;
+        assert_eq!(Retune::new(None, Hertz(0.0), khz(1.0)), Retune::Set);
+        assert_eq!(
+            Retune::new(Some(khz(1.0)), khz(1.0), khz(1.0) + Hertz(1.0)),
+            Retune::Keep
+        );
+        assert_eq!(
+            Retune::new(Some(khz(1.0)), khz(1.0), khz(1.0) + RETUNE_THRESHOLD * 2.0),
+            Retune::Set
+        );
+        assert_eq!(
+            Retune::new(Some(khz(1.0)), khz(2.0), khz(1.0)),
+            Retune::UserRetuned
+        );
+    }
+
+    #[test]
+    fn status() {
+        let doppler = Doppler::new();
+        let time = Utc.with_ymd_and_hms(2000, 6, 28, 0, 0, 0).unwrap();
+        let mut target = test_target();
+        target.frequency = Hertz(0.0);
+        assert!(doppler.set_target(target).is_err());
+        let mut target = test_target();
+        target.tle_line2.replace_range(68.., "0");
+        assert!(doppler.set_target(target).is_err());
+        assert_eq!(doppler.update_status(Some(&geolocation()), time), None);
+        assert_eq!(doppler.json().status, None);
+
+        doppler.set_target(test_target()).unwrap();
+        assert_eq!(doppler.update_status(None, time), None);
+        assert_eq!(doppler.json().status, None);
+        // Find a time during a pass
+        let time = (0..24 * 60)
+            .map(|minute| time + chrono::Duration::minutes(minute))
+            .find(|&time| {
+                doppler.update_status(Some(&geolocation()), time);
+                doppler.json().status.unwrap().elevation > 10.0
+            })
+            .unwrap();
+        let status = doppler.json().status.unwrap();
+        assert_eq!(
+            status.correction,
+            Hertz(-status.range_rate / SPEED_OF_LIGHT * 108e6)
+        );
+        assert!(!status.applied);
+        // The correction is only applied when enabled
+        assert_eq!(doppler.update_status(Some(&geolocation()), time), None);
+        doppler.set_enabled(true);
+        assert_eq!(
+            doppler.update_status(Some(&geolocation()), time),
+            Some(Hertz(108e6) + status.correction)
+        );
+        assert_eq!(doppler.applied_correction(), None);
+        doppler.set_applied(true);
+        assert_eq!(
+            doppler.applied_correction(),
+            Some(sigmf::DopplerCorrection {
+                satellite: "VANGUARD 1".to_string(),
+                frequency: 108e6,
+                correction: status.correction.0,
+            })
+        );
+        doppler.set_enabled(false);
+        assert_eq!(doppler.applied_correction(), None);
+    }
+}
@@ -0,0 +1,624 @@
+//! SGP4 orbit propagator.
+//!
+//! This is an implementation of the near-earth part of the SGP4 propagator, as
+//! described in "Revisiting Spacetrack Report #3" by Vallado, Crawford,
+//! Hujsak and Kelso, using the WGS72 constants. Deep-space orbits (with a
+//! period of 225 minutes or more) are not supported, since they are not needed
+//! for the LEO satellites for which Doppler correction is most useful.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use std::f64::consts::{PI, TAU};
+
+// WGS72 constants
+const MU: f64 = 398600.8; // km^3 / s^2
+const RADIUS_EARTH: f64 = 6378.135; // km
+const J2: f64 = 0.001082616;
+const J3: f64 = -0.00000253881;
+const J4: f64 = -0.00000165597;
+const J3OJ2: f64 = J3 / J2;
+const X2O3: f64 = 2.0 / 3.0;
+const TEMP4: f64 = 1.5e-12;
+
+// Square root of the Earth gravitational constant in units of Earth radii and
+// minutes.
+fn xke() -> f64 {
+    60.0 / (RADIUS_EARTH * RADIUS_EARTH * RADIUS_EARTH / MU).sqrt()
+}
+
+/// Two-line element set.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tle {
+    /// Epoch of the elements.
+    pub epoch: DateTime<Utc>,
+    /// Drag term (in inverse Earth radii).
+    pub bstar: f64,
+    /// Inclination (radians).
+    pub inclination: f64,
+    /// Right ascension of the ascending node (radians).
+    pub raan: f64,
+    /// Eccentricity.
+    pub eccentricity: f64,
+    /// Argument of perigee (radians).
+    pub arg_perigee: f64,
+    /// Mean anomaly (radians).
+    pub mean_anomaly: f64,
+    /// Mean motion (radians per minute).
+    pub mean_motion: f64,
+}
+
+// Parses a field of a TLE line, given its 1-based column range.
+fn field(line: &str, first: usize, last: usize) -> Result<&str> {
+    line.get(first - 1..last)
+        .map(str::trim)
+        .with_context(|| format!("TLE line is too short: {line}"))
+}
+
+fn parse_field(line: &str, first: usize, last: usize) -> Result<f64> {
+    let value = field(line, first, last)?;
+    value
+        .parse()
+        .with_context(|| format!("invalid TLE field: {value}"))
+}
+
+// Parses a field in the TLE exponential notation with an assumed leading
+// decimal point, such as " 28098-4", which means 0.28098e-4.
+fn parse_exponential(line: &str, first: usize, last: usize) -> Result<f64> {
+    let value = field(line, first, last)?;
+    let invalid = || format!("invalid TLE field: {value}");
+    let (mantissa, exponent) = value.split_at(value.len().saturating_sub(2));
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => (-1.0, mantissa),
+        None => (1.0, mantissa.trim_start_matches('+')),
+    };
+    let mantissa: f64 = format!("0.{mantissa}").parse().with_context(invalid)?;
+    let exponent: i32 = exponent.parse().with_context(invalid)?;
+    Ok(sign * mantissa * 10f64.powi(exponent))
+}
+
+fn checksum(line: &str) -> u32 {
+    line.chars()
+        .take(68)
+        .map(|c| match c {
+            '-' => 1,
+            _ => c.to_digit(10).unwrap_or(0),
+        })
+        .sum::<u32>()
+        % 10
+}
+
+fn check_line(line: &str, number: char) -> Result<()> {
+    anyhow::ensure!(
+        line.len() == 69 && line.starts_with(number),
+        "TLE line {number} is malformed: {line}"
+    );
+    let expected = line[68..].parse::<u32>().ok();
+    anyhow::ensure!(
+        expected == Some(checksum(line)),
+        "TLE line {number} has a wrong checksum: {line}"
+    );
+    Ok(())
+}
+
+impl Tle {
+    /// Parses a TLE from its two lines.
+    pub fn parse(line1: &str, line2: &str) -> Result<Tle> {
+        let (line1, line2) = (line1.trim_end(), line2.trim_end());
+        check_line(line1, '1')?;
+        check_line(line2, '2')?;
+        anyhow::ensure!(
+            field(line1, 3, 7)? == field(line2, 3, 7)?,
+            "the catalog numbers of the TLE lines do not match"
+        );
+        let year = parse_field(line1, 19, 20)? as i32;
+        let year = if year < 57 { 2000 + year } else { 1900 + year };
+        let day = parse_field(line1, 21, 32)?;
+        let epoch = Utc.with_ymd_and_hms(year, 1, 1, 0, 0, 0).unwrap()
+            + Duration::microseconds(((day - 1.0) * 86400e6).round() as i64);
+        Ok(Tle {
+            epoch,
+            bstar: parse_exponential(line1, 54, 61)?,
+            inclination: parse_field(line2, 9, 16)?.to_radians(),
+            raan: parse_field(line2, 18, 25)?.to_radians(),
+            eccentricity: format!("0.{}", field(line2, 27, 33)?)
+                .parse()
+                .context("invalid TLE eccentricity")?,
+            arg_perigee: parse_field(line2, 35, 42)?.to_radians(),
+            mean_anomaly: parse_field(line2, 44, 51)?.to_radians(),
+            mean_motion: parse_field(line2, 53, 63)? * TAU / 1440.0,
+        })
+    }
+}
+
+/// Satellite state vector.
+///
+/// The state vector is given in the TEME (true equator, mean equinox)
+/// reference frame.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StateVector {
+    /// Position (km).
+    pub position: [f64; 3],
+    /// Velocity (km/s).
+    pub velocity: [f64; 3],
+}
+
+/// SGP4 propagator.
+///
+/// The propagator is initialized from a [`Tle`], and it can compute the state
+/// vector of the satellite at any time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sgp4 {
+    epoch: DateTime<Utc>,
+    bstar: f64,
+    ecco: f64,
+    inclo: f64,
+    nodeo: f64,
+    argpo: f64,
+    mo: f64,
+    no: f64,
+    isimp: bool,
+    aycof: f64,
+    con41: f64,
+    cc1: f64,
+    cc4: f64,
+    cc5: f64,
+    d2: f64,
+    d3: f64,
+    d4: f64,
+    delmo: f64,
+    eta: f64,
+    argpdot: f64,
+    omgcof: f64,
+    sinmao: f64,
+    t2cof: f64,
+    t3cof: f64,
+    t4cof: f64,
+    t5cof: f64,
+    x1mth2: f64,
+    x7thm1: f64,
+    mdot: f64,
+    nodedot: f64,
+    xlcof: f64,
+    xmcof: f64,
+    nodecf: f64,
+}
+
+impl Sgp4 {
+    /// Initializes the propagator from a TLE.
+    ///
+    /// An error is returned if the orbit is a deep-space orbit or if the
+    /// elements are invalid.
+    pub fn new(tle: &Tle) -> Result<Sgp4> {
+        let xke = xke();
+        let ecco = tle.eccentricity;
+        let inclo = tle.inclination;
+        let argpo = tle.arg_perigee;
+        let bstar = tle.bstar;
+        anyhow::ensure!(
+            (0.0..1.0).contains(&ecco),
+            "the TLE eccentricity is out of range"
+        );
+        anyhow::ensure!(tle.mean_motion > 0.0, "the TLE mean motion is not positive");
+
+        // Recover the original mean motion and semi-major axis from the
+        // Kozai mean motion in the TLE
+        let eccsq = ecco * ecco;
+        let omeosq = 1.0 - eccsq;
+        let rteosq = omeosq.sqrt();
+        let cosio = inclo.cos();
+        let cosio2 = cosio * cosio;
+        let ak = (xke / tle.mean_motion).powf(X2O3);
+        let d1 = 0.75 * J2 * (3.0 * cosio2 - 1.0) / (rteosq * omeosq);
+        let del = d1 / (ak * ak);
+        let adel = ak * (1.0 - del * del - del * (1.0 / 3.0 + 134.0 * del * del / 81.0));
+        let del = d1 / (adel * adel);
+        let no = tle.mean_motion / (1.0 + del);
+        anyhow::ensure!(TAU / no < 225.0, "deep-space orbits are not supported");
+        let ao = (xke / no).powf(X2O3);
+        let sinio = inclo.sin();
+        let po = ao * omeosq;
+        let con42 = 1.0 - 5.0 * cosio2;
+        let con41 = -con42 - cosio2 - cosio2;
+        let posq = po * po;
+        let rp = ao * (1.0 - ecco);
+
+        // For perigees below 220 km, the equations are truncated to a linear
+        // variation in the square root of a and quadratic variation in mean
+        // anomaly
+        let isimp = rp < 220.0 / RADIUS_EARTH + 1.0;
+        let mut sfour = 78.0 / RADIUS_EARTH + 1.0;
+        let mut qzms24 = ((120.0 - 78.0) / RADIUS_EARTH).powi(4);
+        let perige = (rp - 1.0) * RADIUS_EARTH;
+        if perige < 156.0 {
+            sfour = if perige < 98.0 { 20.0 } else { perige - 78.0 };
+            qzms24 = ((120.0 - sfour) / RADIUS_EARTH).powi(4);
+            sfour = sfour / RADIUS_EARTH + 1.0;
+        }
+        let pinvsq = 1.0 / posq;
+        let tsi = 1.0 / (ao - sfour);
+        let eta = ao * ecco * tsi;
+        let etasq = eta * eta;
+        let eeta = ecco * eta;
+        let psisq = (1.0 - etasq).abs();
+        let coef = qzms24 * tsi.powi(4);
+        let coef1 = coef / psisq.powf(3.5);
+        let cc2 = coef1
+            * no
+            * (ao * (1.0 + 1.5 * etasq + eeta * (4.0 + etasq))
+                + 0.375 * J2 * tsi / psisq * con41 * (8.0 + 3.0 * etasq * (8.0 + etasq)));
+        let cc1 = bstar * cc2;
+        let cc3 = if ecco > 1.0e-4 {
+            -2.0 * coef * tsi * J3OJ2 * no * sinio / ecco
+        } else {
+            0.0
+        };
+        let x1mth2 = 1.0 - cosio2;
+        let cc4 = 2.0
+            * no
+            * coef1
+            * ao
+            * omeosq
+            * (eta * (2.0 + 0.5 * etasq) + ecco * (0.5 + 2.0 * etasq)
+                - J2 * tsi / (ao * psisq)
+                    * (-3.0 * con41 * (1.0 - 2.0 * eeta + etasq * (1.5 - 0.5 * eeta))
+                        + 0.75
+                            * x1mth2
+                            * (2.0 * etasq - eeta * (1.0 + etasq))
+                            * (2.0 * argpo).cos()));
+        let cc5 = 2.0 * coef1 * ao * omeosq * (1.0 + 2.75 * (etasq + eeta) + eeta * etasq);
+        let cosio4 = cosio2 * cosio2;
+        let temp1 = 1.5 * J2 * pinvsq * no;
+        let temp2 = 0.5 * temp1 * J2 * pinvsq;
+        let temp3 = -0.46875 * J4 * pinvsq * pinvsq * no;
+        let mdot = no
+            + 0.5 * temp1 * rteosq * con41
+            + 0.0625 * temp2 * rteosq * (13.0 - 78.0 * cosio2 + 137.0 * cosio4);
+        let argpdot = -0.5 * temp1 * con42
+            + 0.0625 * temp2 * (7.0 - 114.0 * cosio2 + 395.0 * cosio4)
+            + temp3 * (3.0 - 36.0 * cosio2 + 49.0 * cosio4);
+        let xhdot1 = -temp1 * cosio;
+        let nodedot = xhdot1
+            + (0.5 * temp2 * (4.0 - 19.0 * cosio2) + 2.0 * temp3 * (3.0 - 7.0 * cosio2)) * cosio;
+        let omgcof = bstar * cc3 * argpo.cos();
+        let xmcof = if ecco > 1.0e-4 {
+            -X2O3 * coef * bstar / eeta
+        } else {
+            0.0
+        };
+        let nodecf = 3.5 * omeosq * xhdot1 * cc1;
+        let t2cof = 1.5 * cc1;
+        let xlcof_den = if (cosio + 1.0).abs() > TEMP4 {
+            1.0 + cosio
+        } else {
+            TEMP4
+        };
+        let xlcof = -0.25 * J3OJ2 * sinio * (3.0 + 5.0 * cosio) / xlcof_den;
+        let aycof = -0.5 * J3OJ2 * sinio;
+        let delmo = (1.0 + eta * tle.mean_anomaly.cos()).powi(3);
+        let sinmao = tle.mean_anomaly.sin();
+        let x7thm1 = 7.0 * cosio2 - 1.0;
+
+        let (d2, d3, d4, t3cof, t4cof, t5cof) = if isimp {
+            Default::default()
+        } else {
+            let cc1sq = cc1 * cc1;
+            let d2 = 4.0 * ao * tsi * cc1sq;
+            let temp = d2 * tsi * cc1 / 3.0;
+            let d3 = (17.0 * ao + sfour) * temp;
+            let d4 = 0.5 * temp * ao * tsi * (221.0 * ao + 31.0 * sfour) * cc1;
+            let t3cof = d2 + 2.0 * cc1sq;
+            let t4cof = 0.25 * (3.0 * d3 + cc1 * (12.0 * d2 + 10.0 * cc1sq));
+            let t5cof = 0.2
+                * (3.0 * d4 + 12.0 * cc1 * d3 + 6.0 * d2 * d2 + 15.0 * cc1sq * (2.0 * d2 + cc1sq));
+            (d2, d3, d4, t3cof, t4cof, t5cof)
+        };
+
+        Ok(Sgp4 {
+            epoch: tle.epoch,
+            bstar,
+            ecco,
+            inclo,
+            nodeo: tle.raan,
+            argpo,
+            mo: tle.mean_anomaly,
+            no,
+            isimp,
+            aycof,
+            con41,
+            cc1,
+            cc4,
+            cc5,
+            d2,
+            d3,
+            d4,
+            delmo,
+            eta,
+            argpdot,
+            omgcof,
+            sinmao,
+            t2cof,
+            t3cof,
+            t4cof,
+            t5cof,
+            x1mth2,
+            x7thm1,
+            mdot,
+            nodedot,
+            xlcof,
+            xmcof,
+            nodecf,
+        })
+    }
+
+    /// Computes the state vector of the satellite at a given time.
+    pub fn state_at(&self, time: DateTime<Utc>) -> Result<StateVector> {
+        let minutes = (time - self.epoch).num_microseconds().unwrap_or(i64::MAX) as f64 / 60e6;
+        self.propagate(minutes)
+    }
+
+    /// Computes the state vector of the satellite a given number of minutes
+    /// after the epoch of the TLE.
+    ///
+    /// An error is returned if the orbit has decayed or the propagation fails
+    /// because the elements are no longer valid.
+    pub fn propagate(&self, t: f64) -> Result<StateVector> {
+        let xke = xke();
+        let vkmpersec = RADIUS_EARTH * xke / 60.0;
+
+        // Secular gravity and atmospheric drag
+        let xmdf = self.mo + self.mdot * t;
+        let argpdf = self.argpo + self.argpdot * t;
+        let nodedf = self.nodeo + self.nodedot * t;
+        let mut argpm = argpdf;
+        let mut mm = xmdf;
+        let t2 = t * t;
+        let mut nodem = nodedf + self.nodecf * t2;
+        let mut tempa = 1.0 - self.cc1 * t;
+        let mut tempe = self.bstar * self.cc4 * t;
+        let mut templ = self.t2cof * t2;
+        if !self.isimp {
+            let delomg = self.omgcof * t;
+            let delm = self.xmcof * ((1.0 + self.eta * xmdf.cos()).powi(3) - self.delmo);
+            let temp = delomg + delm;
+            mm = xmdf + temp;
+            argpm = argpdf - temp;
+            let t3 = t2 * t;
+            let t4 = t3 * t;
+            tempa -= self.d2 * t2 + self.d3 * t3 + self.d4 * t4;
+            tempe += self.bstar * self.cc5 * (mm.sin() - self.sinmao);
+            templ += self.t3cof * t3 + t4 * (self.t4cof + t * self.t5cof);
+        }
+        let am = (xke / self.no).powf(X2O3) * tempa * tempa;
+        let nm = xke / am.powf(1.5);
+        let mut em = self.ecco - tempe;
+        anyhow::ensure!(
+            (-0.001..1.0).contains(&em),
+            "the propagated eccentricity is out of range"
+        );
+        em = em.max(1.0e-6);
+        mm += self.no * templ;
+        let xlm = (mm + argpm + nodem) % TAU;
+        nodem %= TAU;
+        argpm %= TAU;
+        let mp = (xlm - argpm - nodem) % TAU;
+        let ep = em;
+        let (sinip, cosip) = self.inclo.sin_cos();
+
+        // Long period periodics
+        let axnl = ep * argpm.cos();
+        let temp = 1.0 / (am * (1.0 - ep * ep));
+        let aynl = ep * argpm.sin() + temp * self.aycof;
+        let xl = mp + argpm + nodem + temp * self.xlcof * axnl;
+
+        // Solve Kepler's equation
+        let u = (xl - nodem) % TAU;
+        let mut eo1 = u;
+        let (mut sineo1, mut coseo1) = (0.0, 0.0);
+        let mut tem5: f64 = 9999.9;
+        let mut ktr = 1;
+        while tem5.abs() >= 1.0e-12 && ktr <= 10 {
+            (sineo1, coseo1) = eo1.sin_cos();
+            tem5 = 1.0 - coseo1 * axnl - sineo1 * aynl;
+            tem5 = (u - aynl * coseo1 + axnl * sineo1 - eo1) / tem5;
+            tem5 = tem5.clamp(-0.95, 0.95);
+            eo1 += tem5;
+            ktr += 1;
+        }
+
+        // Short period preliminary quantities
+        let ecose = axnl * coseo1 + aynl * sineo1;
+        let esine = axnl * sineo1 - aynl * coseo1;
+        let el2 = axnl * axnl + aynl * aynl;
+        let pl = am * (1.0 - el2);
+        anyhow::ensure!(pl >= 0.0, "the propagated semi-latus rectum is negative");
+        let rl = am * (1.0 - ecose);
+        let rdotl = am.sqrt() * esine / rl;
+        let rvdotl = pl.sqrt() / rl;
+        let betal = (1.0 - el2).sqrt();
+        let temp = esine / (1.0 + betal);
+        let sinu = am / rl * (sineo1 - aynl - axnl * temp);
+        let cosu = am / rl * (coseo1 - axnl + aynl * temp);
+        let su = sinu.atan2(cosu);
+        let sin2u = (cosu + cosu) * sinu;
+        let cos2u = 1.0 - 2.0 * sinu * sinu;
+        let temp = 1.0 / pl;
+        let temp1 = 0.5 * J2 * temp;
+        let temp2 = temp1 * temp;
+
+        // Update for short period periodics
+        let mrt = rl * (1.0 - 1.5 * temp2 * betal * self.con41) + 0.5 * temp1 * self.x1mth2 * cos2u;
+        anyhow::ensure!(mrt >= 1.0, "the satellite has decayed");
+        let su = su - 0.25 * temp2 * self.x7thm1 * sin2u;
+        let xnode = nodem + 1.5 * temp2 * cosip * sin2u;
+        let xinc = self.inclo + 1.5 * temp2 * cosip * sinip * cos2u;
+        let mvt = rdotl - nm * temp1 * self.x1mth2 * sin2u / xke;
+        let rvdot = rvdotl + nm * temp1 * (self.x1mth2 * cos2u + 1.5 * self.con41) / xke;
+
+        // Orientation vectors
+        let (sinsu, cossu) = su.sin_cos();
+        let (snod, cnod) = xnode.sin_cos();
+        let (sini, cosi) = xinc.sin_cos();
+        let xmx = -snod * cosi;
+        let xmy = cnod * cosi;
+        let ux = [
+            xmx * sinsu + cnod * cossu,
+            xmy * sinsu + snod * cossu,
+            sini * sinsu,
+        ];
+        let vx = [
+            xmx * cossu - cnod * sinsu,
+            xmy * cossu - snod * sinsu,
+            sini * cossu,
+        ];
+        Ok(StateVector {
+            position: std::array::from_fn(|j| mrt * ux[j] * RADIUS_EARTH),
+            velocity: std::array::from_fn(|j| (mvt * ux[j] + rvdot * vx[j]) * vkmpersec),
+        })
+    }
+}
+
+/// Computes the Greenwich mean sidereal time.
+///
+/// The GMST is returned in radians, in the range `[0, 2π)`. It is computed
+/// using the IAU-82 model, which is the one that is used to convert from the
+/// TEME frame to the Earth-fixed frame.
+pub fn gmst(time: DateTime<Utc>) -> f64 {
+    const J2000: f64 = 946_728_000.0; // 2000-01-01 12:00:00 UTC (Unix time)
+    let seconds = time.timestamp() as f64 + 1e-9 * f64::from(time.timestamp_subsec_nanos());
+    let tut1 = (seconds - J2000) / (86400.0 * 36525.0);
+    let gmst = -6.2e-6 * tut1 * tut1 * tut1
+        + 0.093104 * tut1 * tut1
+        + (876600.0 * 3600.0 + 8640184.812866) * tut1
+        + 67310.54841;
+    // 360 degrees correspond to 86400 seconds
+    (gmst * PI / 43200.0).rem_euclid(TAU)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const LINE1: &str = "1 00005U 58002B   00179.78495062  .00000023  00000-0  28098-4 0  4753";
+    const LINE2: &str = "2 00005  34.2682 348.7242 1859667 331.7664  19.3264 10.82419157413667";
+
+    fn assert_close(actual: [f64; 3], expected: [f64; 3], tolerance: f64) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < tolerance, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn parse_tle() {
+        let tle = Tle::parse(LINE1, LINE2).unwrap();
+        assert_eq!(
+            tle.epoch,
+            Utc.with_ymd_and_hms(2000, 6, 27, 18, 50, 19).unwrap() + Duration::microseconds(733568)
+        );
+        assert!((tle.bstar - 2.8098e-5).abs() < 1e-12);
+        assert!((tle.eccentricity - 0.1859667).abs() < 1e-12);
+        assert!((tle.inclination.to_degrees() - 34.2682).abs() < 1e-9);
+        let mut bad_checksum = LINE1.to_string();
+        bad_checksum.replace_range(68.., "4");
+        assert!(Tle::parse(&bad_checksum, LINE2).is_err());
+        assert!(Tle::parse(LINE1, &LINE2[..60]).is_err());
+    }
+
+    // Test vectors from "Revisiting Spacetrack Report #3"
+    #[test]
+    fn propagate() {
+        let sgp4 = Sgp4::new(&Tle::parse(LINE1, LINE2).unwrap()).unwrap();
+        let state = sgp4.propagate(0.0).unwrap();
+        assert_close(
+            state.position,
+            [7022.46529266, -1400.08296755, 0.03995155],
+            1e-4,
+        );
+        assert_close(
+            state.velocity,
+            [1.893841015, 6.405893759, 4.534807250],
+            1e-7,
+        );
+        let state = sgp4.propagate(360.0).unwrap();
+        assert_close(
+            state.position,
+            [-7154.03120202, -3783.17682504, -3536.19412294],
+            1e-4,
+        );
+        assert_close(
+            state.velocity,
+            [4.741887409, -4.151817765, -2.093935425],
+            1e-7,
+        );
+        // Remaining test vectors of this satellite, which cover 3 days
+        let vectors = [
+            (
+                720.0,
+                [-7134.59340119, 6531.68641334, 3260.27186483],
+                [-4.113793027, -2.911922039, -2.557327851],
+            ),
+            (
+                1080.0,
+                [5568.53901181, 4492.06992591, 3863.87641983],
+                [-4.209106476, 5.159719888, 2.744852980],
+            ),
+            (
+                1440.0,
+                [-938.55923943, -6268.18748831, -4294.02924751],
+                [7.536105209, -0.427127707, 0.989878080],
+            ),
+            (
+                1800.0,
+                [-9680.56121728, 2802.47771354, 124.10688038],
+                [-0.905874102, -4.659467970, -3.227347517],
+            ),
+            (
+                2160.0,
+                [190.19796988, 7746.96653614, 5110.00675412],
+                [-6.112325142, 1.527008184, -0.139152358],
+            ),
+            (
+                2520.0,
+                [5579.55640116, -3995.61396789, -1518.82108966],
+                [4.767927483, 5.123185301, 4.276837355],
+            ),
+            (
+                2880.0,
+                [-8650.73082219, -1914.93811525, -3007.03603443],
+                [3.067165127, -4.828384068, -2.515322836],
+            ),
+            (
+                3240.0,
+                [-5429.79204164, 7574.36493792, 3747.39305236],
+                [-4.999442110, -1.800561422, -2.229392830],
+            ),
+            (
+                3600.0,
+                [6759.04583722, 2001.58198220, 2783.55192533],
+                [-2.180993947, 6.402085603, 3.644723952],
+            ),
+            (
+                3960.0,
+                [-3791.44531559, -5712.95617894, -4533.48630714],
+                [6.668817493, -2.516382327, -0.082384354],
+            ),
+            (
+                4320.0,
+                [-9060.47373569, 4658.70952502, 813.68673153],
+                [-2.232832783, -4.110453490, -3.157345433],
+            ),
+        ];
+        for (t, position, velocity) in vectors {
+            let state = sgp4.propagate(t).unwrap();
+            assert_close(state.position, position, 1e-4);
+            assert_close(state.velocity, velocity, 1e-7);
+        }
+    }
+
+    #[test]
+    fn greenwich_sidereal_time() {
+        // Example 3-5 in Vallado, "Fundamentals of Astrodynamics and
+        // Applications": 1992-08-20 12:14 UT1 gives GMST = 152.578788 deg
+        let time = Utc.with_ymd_and_hms(1992, 8, 20, 12, 14, 0).unwrap();
+        assert!((gmst(time).to_degrees() - 152.578788).abs() < 1e-5);
+    }
+}
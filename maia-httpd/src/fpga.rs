@@ -6,17 +6,19 @@
 use crate::rxbuffer::RxBuffer;
 use crate::uio::{Mapping, Uio};
 use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use maia_json::Hertz;
+use std::os::unix::io::AsRawFd;
 use std::sync::Arc;
-use tokio::sync::Notify;
+use tokio::{fs, sync::Notify};
 
 /// Maia SDR FPGA IP core.
 ///
 /// This struct represents the FPGA IP core and gives access to its registers
-/// and DMA buffers.
+/// and DMA buffers. The hardware is accessed through an [`FpgaBackend`].
 #[derive(Debug)]
 pub struct IpCore {
     registers: Registers,
-    phys_addr: usize,
     spectrometer: Dma,
     // RAM-based cache for the number of spectrometer integrations and
     // mode. These are used to speed up IpCore::spectrometer_number_integrations
@@ -32,8 +34,9 @@ pub struct IpCore {
 /// Interrupt waiter.
 ///
 /// This is associated with an interrupt of a particular type and can be used by
-/// a future to await until such an interrupt happens.
-#[derive(Debug)]
+/// a future to await until such an interrupt happens. Clones of a waiter
+/// refer to the same interrupt, and each interrupt only wakes up one of them.
+#[derive(Debug, Clone)]
 pub struct InterruptWaiter {
     notify: Arc<Notify>,
 }
@@ -44,6 +47,10 @@ pub struct InterruptWaiter {
 /// to the [`InterruptWaiter`]s. It is necessary to call
 /// [`InterruptHandler::run`] in order to receive and process interrupts.
 ///
+/// Clones of the handler send notifications to the same waiters. Only one
+/// clone should be run at a time. Cloning is used to run the handler again
+/// after it has failed.
+///
 /// # Examples
 ///
 /// This shows how to create an `InterruptHandler`, obtain an `InterruptWaiter`,
@@ -62,17 +69,110 @@ pub struct InterruptWaiter {
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct InterruptHandler {
-    uio: Uio,
     registers: Registers, // should only access registers.interrupts
     notify_spectrometer: Arc<Notify>,
     notify_recorder: Arc<Notify>,
 }
 
+/// FPGA IP core backend.
+///
+/// This trait abstracts the hardware resources that are used by [`IpCore`]:
+/// the registers of the IP core, the spectrometer and recording DMA buffers,
+/// and the interrupts. This allows using [`IpCore`] with the real hardware,
+/// through [`UioFpga`], or with a simulation of the IP core, such as
+/// [`MockFpga`](crate::mock::MockFpga).
+pub trait FpgaBackend: std::fmt::Debug + Send + Sync {
+    /// Returns the virtual address of the registers of the IP core.
+    ///
+    /// The address must point to a region of memory at least as large as the
+    /// register block of the IP core that remains valid for as long as the
+    /// backend exists.
+    fn registers(&self) -> *mut libc::c_void;
+
+    /// Returns the physical address of the registers of the IP core.
+    ///
+    /// This is only used for informative purposes.
+    fn phys_addr(&self) -> usize;
+
+    /// Returns the number of buffers in the spectrometer DMA ring.
+    fn spectrometer_num_buffers(&self) -> usize;
+
+    /// Returns one of the buffers of the spectrometer DMA ring.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `num_buffer` is greater or equal to the number
+    /// of buffers in the ring.
+    fn spectrometer_buffer(&self, num_buffer: usize) -> &[u8];
+
+    /// Invalidates the cache of one of the buffers of the spectrometer DMA
+    /// ring.
+    ///
+    /// See [`RxBuffer::cache_invalidate`].
+    fn spectrometer_cache_invalidate(&self, num_buffer: usize) -> Result<()>;
+
+    /// Gives access to the recording DMA buffer.
+    ///
+    /// The contents of the recording buffer are only guaranteed to be up to
+    /// date with the latest recording if this function is called after the
+    /// recording has finished.
+    fn recording_buffer(&self) -> BoxFuture<'_, Result<Box<dyn RecordingMemory>>>;
+
+    /// Returns the physical address of the recording DMA buffer.
+    ///
+    /// This is used together with [`IpCore::recorder_next_address`] to
+    /// calculate the size of a recording.
+    fn recording_base_address(&self) -> BoxFuture<'_, Result<usize>>;
+
+    /// Waits for an interrupt of the IP core.
+    ///
+    /// When the returned future completes, the interrupts register of the IP
+    /// core indicates which interrupts have happened.
+    fn wait_interrupt(&self) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Recording DMA buffer memory.
+///
+/// This trait gives read access to the memory to which the recorder writes
+/// the samples.
+pub trait RecordingMemory: std::fmt::Debug + Send + Sync {
+    /// Returns the contents of the recording buffer.
+    fn as_slice(&self) -> &[u8];
+}
+
+/// UIO-based FPGA IP core backend.
+///
+/// This is the [`FpgaBackend`] that is used with the Maia SDR hardware. It
+/// accesses the registers and interrupts of the IP core through the maia-sdr
+/// UIO device, and the DMA buffers through the devices provided by the
+/// maia-sdr kernel module.
+#[derive(Debug)]
+pub struct UioFpga {
+    uio: tokio::sync::Mutex<Uio>,
+    mapping: Mapping,
+    phys_addr: usize,
+    spectrometer: RxBuffer,
+}
+
+// The raw pointers in the UIO mapping and the DMA buffer are only used to
+// access device memory, so UioFpga can be shared between threads.
+unsafe impl Send for UioFpga {}
+unsafe impl Sync for UioFpga {}
+
+#[derive(Debug)]
+struct RecordingMapping {
+    base: *const u8,
+    size: usize,
+}
+
+unsafe impl Send for RecordingMapping {}
+unsafe impl Sync for RecordingMapping {}
+
 #[derive(Debug)]
 struct Dma {
-    buffer: RxBuffer,
+    backend: Arc<dyn FpgaBackend>,
     last_written: Option<usize>,
     num_buffers_mask: usize,
 }
@@ -90,25 +190,30 @@ impl std::fmt::Display for Version {
     }
 }
 
-#[derive(Debug)]
-struct Registers(Mapping);
+// First IP core version that has a sample counter and a recorder trigger input.
+const VERSION_0_7_0: Version = Version {
+    major: 0,
+    minor: 7,
+    bugfix: 0,
+};
+
+#[derive(Debug, Clone)]
+struct Registers(Arc<dyn FpgaBackend>);
 
 impl std::ops::Deref for Registers {
     type Target = maia_pac::maia_sdr::RegisterBlock;
     fn deref(&self) -> &Self::Target {
-        unsafe { &*(self.0.addr() as *const maia_pac::maia_sdr::RegisterBlock) }
+        unsafe { &*(self.0.registers() as *const maia_pac::maia_sdr::RegisterBlock) }
     }
 }
 
-unsafe impl Send for Registers {}
-
 fn default_ddc_config() -> maia_json::PutDDCConfig {
     // this design can be calculated quickly and it is good for sample rates
     // above 61.44 Msps
-    let input_samp_rate = 61.44e6;
+    let input_samp_rate = Hertz(61.44e6);
     crate::ddc::make_design(
         &maia_json::PutDDCDesign {
-            frequency: 0.0,
+            frequency: Hertz(0.0),
             decimation: 20,
             transition_bandwidth: None,
             passband_ripple: None,
@@ -229,21 +334,19 @@ impl IpCore {
     /// On success, the `IpCore` and the corresponding [`InterruptHandler`] are
     /// returned.
     pub async fn take() -> Result<(IpCore, InterruptHandler)> {
-        let uio = Uio::from_name("maia-sdr")
-            .await
-            .context("failed to open maia-sdr UIO")?;
-        let mapping = uio
-            .map_mapping(0)
-            .await
-            .context("failed to map maia-sdr UIO")?;
-        let phys_addr = uio.map_addr(0).await?;
-        let spectrometer = Dma::new("maia-sdr-spectrometer")
-            .await
-            .context("failed to open maia-sdr-spectrometer DMA buffer")?;
-        let interrupt_registers = Registers(mapping.clone());
+        Self::with_backend(Arc::new(UioFpga::new().await?)).await
+    }
+
+    /// Opens the FPGA IP core using a custom backend.
+    ///
+    /// This function is mainly used to run maia-httpd with a simulated IP core
+    /// (see [`MockFpga`](crate::mock::MockFpga)). It initializes the IP core
+    /// in the same way as [`IpCore::take`].
+    pub async fn with_backend(backend: Arc<dyn FpgaBackend>) -> Result<(IpCore, InterruptHandler)> {
+        let spectrometer = Dma::new(Arc::clone(&backend))?;
+        let interrupt_registers = Registers(Arc::clone(&backend));
         let mut ip_core = IpCore {
-            registers: Registers(mapping),
-            phys_addr,
+            registers: Registers(backend),
             spectrometer,
             // These are initialized to the correct value below, after removing
             // the SDR reset.
@@ -273,7 +376,7 @@ impl IpCore {
                     maia_json::SpectrometerInput::AD9361
                 },
                 // fake the input sample rate so that this never fails
-                0.0,
+                Hertz(0.0),
             )
             .unwrap();
         ip_core.spectrometer_mode = if ip_core.registers.spectrometer().read().peak_detect().bit() {
@@ -281,11 +384,18 @@ impl IpCore {
         } else {
             maia_json::SpectrometerMode::Average
         };
-        ip_core.set_ddc_config(&default_ddc_config(), 0.0).unwrap();
-        let interrupt_handler = InterruptHandler::new(uio, interrupt_registers);
+        ip_core
+            .set_ddc_config(&default_ddc_config(), Hertz(0.0))
+            .unwrap();
+        let interrupt_handler = InterruptHandler::new(interrupt_registers);
         Ok((ip_core, interrupt_handler))
     }
 
+    /// Returns the backend used to access the IP core.
+    pub fn backend(&self) -> &Arc<dyn FpgaBackend> {
+        &self.registers.0
+    }
+
     fn version_struct(&self) -> Version {
         let version = self.registers.version().read();
         Version {
@@ -303,7 +413,7 @@ impl IpCore {
     fn check_product_id(&self) -> Result<()> {
         const PRODUCT_ID: &[u8; 4] = b"maia";
         let product_id = unsafe {
-            std::slice::from_raw_parts(self.registers.0.addr() as *const u8, PRODUCT_ID.len())
+            std::slice::from_raw_parts(self.registers.0.registers() as *const u8, PRODUCT_ID.len())
         };
         if product_id != PRODUCT_ID {
             anyhow::bail!("wrong product ID {:#02x?}", product_id);
@@ -321,7 +431,7 @@ impl IpCore {
         tracing::info!(
             "opened Maia SDR IP core version {} at physical address {:#08x}",
             self.version_struct(),
-            self.phys_addr
+            self.registers.0.phys_addr()
         );
         Ok(())
     }
@@ -350,9 +460,9 @@ impl IpCore {
     /// This offset is relative to the AD9361 RX LO frequency. The offset is
     /// zero if the input is the AD9361, or the DDC frequency if the input is
     /// the DDC.
-    pub fn spectrometer_input_frequency_offset(&self) -> f64 {
+    pub fn spectrometer_input_frequency_offset(&self) -> Hertz {
         match self.spectrometer_input() {
-            maia_json::SpectrometerInput::AD9361 => 0.0,
+            maia_json::SpectrometerInput::AD9361 => Hertz(0.0),
             maia_json::SpectrometerInput::DDC => self.ddc_frequency(),
         }
     }
@@ -403,7 +513,7 @@ impl IpCore {
     pub fn set_spectrometer_input(
         &mut self,
         input: maia_json::SpectrometerInput,
-        input_samp_freq: f64,
+        input_samp_freq: Hertz,
     ) -> Result<()> {
         let use_ddc = matches!(input, maia_json::SpectrometerInput::DDC);
         if use_ddc {
@@ -430,12 +540,7 @@ impl IpCore {
     ///
     /// See [`IpCore::spectrometer_number_integrations`].
     pub fn set_spectrometer_number_integrations(&mut self, value: u32) -> Result<()> {
-        const WIDTH: u8 = maia_pac::maia_sdr::spectrometer::NumIntegrationsW::<
-            maia_pac::maia_sdr::spectrometer::SpectrometerSpec,
-        >::WIDTH;
-        if !(1..1 << WIDTH).contains(&value) {
-            anyhow::bail!("invalid number of integrations: {}", value);
-        }
+        Self::check_spectrometer_number_integrations(value)?;
         unsafe {
             self.registers.spectrometer().modify(|r, w| {
                 // if reducing the number of integrations, use the abort bit
@@ -448,6 +553,52 @@ impl IpCore {
         Ok(())
     }
 
+    /// Sets the number of integrations of the next spectrometer integration.
+    ///
+    /// Unlike [`IpCore::set_spectrometer_number_integrations`], this never
+    /// aborts the integration in progress, which keeps using the number of
+    /// integrations that was set when it started. The new value is used from
+    /// the next integration onwards.
+    pub fn set_spectrometer_next_number_integrations(&mut self, value: u32) -> Result<()> {
+        Self::check_spectrometer_number_integrations(value)?;
+        unsafe {
+            self.registers
+                .spectrometer()
+                .modify(|_, w| w.num_integrations().bits(value as _))
+        };
+        self.spectrometer_integrations = value;
+        Ok(())
+    }
+
+    /// Returns the maximum number of integrations of the spectrometer.
+    ///
+    /// This is given by the width of the number of integrations register.
+    pub fn spectrometer_max_number_integrations() -> u32 {
+        const WIDTH: u8 = maia_pac::maia_sdr::spectrometer::NumIntegrationsW::<
+            maia_pac::maia_sdr::spectrometer::SpectrometerSpec,
+        >::WIDTH;
+        (1 << WIDTH) - 1
+    }
+
+    fn check_spectrometer_number_integrations(value: u32) -> Result<()> {
+        if !(1..=Self::spectrometer_max_number_integrations()).contains(&value) {
+            anyhow::bail!("invalid number of integrations: {}", value);
+        }
+        Ok(())
+    }
+
+    /// Aborts the current spectrometer integration.
+    ///
+    /// The integration that is in progress finishes at the end of the current
+    /// FFT, instead of when the number of integrations is reached. The
+    /// spectrum produced by this integration contains fewer integrations than
+    /// usual.
+    pub fn spectrometer_abort(&self) {
+        self.registers
+            .spectrometer()
+            .modify(|_, w| w.abort().set_bit());
+    }
+
     /// Sets the spectrometer mode.
     ///
     /// See [`IpCore::spectrometer_mode`].
@@ -478,6 +629,17 @@ impl IpCore {
             })
     }
 
+    /// Re-initializes the spectrometer DMA ring.
+    ///
+    /// The last buffer that has been read from the ring is forgotten, so the
+    /// buffers written before this call are not returned by
+    /// [`IpCore::get_spectrometer_buffers`]. This is used to resynchronize with
+    /// the spectrometer after a failure of the interrupt handler.
+    pub fn reset_spectrometer_dma(&mut self) -> Result<()> {
+        self.spectrometer = Dma::new(Arc::clone(&self.spectrometer.backend))?;
+        Ok(())
+    }
+
     fn set_ddc_enable(&mut self, enable: bool) {
         self.registers
             .ddc_control()
@@ -490,7 +652,7 @@ impl IpCore {
     /// The `input_sampling_frequency` parameter indicates the sampling
     /// frequency of the source connected to the DDC input (typically the
     /// AD9361).
-    pub fn ddc_config(&self, input_sampling_frequency: f64) -> maia_json::DDCConfig {
+    pub fn ddc_config(&self, input_sampling_frequency: Hertz) -> maia_json::DDCConfig {
         let summary = self.ddc_config_summary(input_sampling_frequency);
         maia_json::DDCConfig {
             enabled: summary.enabled,
@@ -499,6 +661,7 @@ impl IpCore {
             input_sampling_frequency: summary.input_sampling_frequency,
             output_sampling_frequency: summary.output_sampling_frequency,
             max_input_sampling_frequency: summary.max_input_sampling_frequency,
+            transition_bandwidth: summary.transition_bandwidth,
             fir1: self.ddc_config.fir1.clone(),
             fir2: self.ddc_config.fir2.clone(),
             fir3: self.ddc_config.fir3.clone(),
@@ -512,7 +675,10 @@ impl IpCore {
     /// The `input_sampling_frequency` parameter indicates the sampling
     /// frequency of the source connected to the DDC input (typically the
     /// AD9361).
-    pub fn ddc_config_summary(&self, input_sampling_frequency: f64) -> maia_json::DDCConfigSummary {
+    pub fn ddc_config_summary(
+        &self,
+        input_sampling_frequency: Hertz,
+    ) -> maia_json::DDCConfigSummary {
         use crate::ddc::constants;
 
         let n = self.ddc_config.fir1.coefficients.len();
@@ -544,7 +710,8 @@ impl IpCore {
             decimation: u32::try_from(decimation).unwrap(),
             input_sampling_frequency,
             output_sampling_frequency: input_sampling_frequency / decimation as f64,
-            max_input_sampling_frequency,
+            max_input_sampling_frequency: Hertz(max_input_sampling_frequency),
+            transition_bandwidth: self.ddc_config.transition_bandwidth,
         }
     }
 
@@ -562,7 +729,7 @@ impl IpCore {
     pub fn set_ddc_config(
         &mut self,
         config: &maia_json::PutDDCConfig,
-        input_samp_rate: f64,
+        input_samp_rate: Hertz,
     ) -> Result<()> {
         if let Err(e) = self.try_set_ddc_config(config, input_samp_rate) {
             // revert DDC config; this should not fail, since the
@@ -581,11 +748,11 @@ impl IpCore {
     fn try_set_ddc_config(
         &self,
         config: &maia_json::PutDDCConfig,
-        input_samp_rate: f64,
+        input_samp_rate: Hertz,
     ) -> Result<()> {
-        let mut input_samp_rate = input_samp_rate;
         self.try_set_ddc_frequency(config.frequency, input_samp_rate)
             .context("failed to configure DDC frequency")?;
+        let mut input_samp_rate = input_samp_rate.0;
         self.set_ddc_fir1(
             &config.fir1.coefficients,
             usize::try_from(config.fir1.decimation).unwrap(),
@@ -622,22 +789,22 @@ impl IpCore {
     /// Gets the mixer frequency of the DDC.
     ///
     /// The frequency is given in units of Hz.
-    pub fn ddc_frequency(&self) -> f64 {
+    pub fn ddc_frequency(&self) -> Hertz {
         self.ddc_config.frequency
     }
 
     /// Sets the mixer frequency of the DDC.
     ///
     /// The `frequency` is given in units of Hz.
-    pub fn set_ddc_frequency(&mut self, frequency: f64, input_samp_rate: f64) -> Result<()> {
+    pub fn set_ddc_frequency(&mut self, frequency: Hertz, input_samp_rate: Hertz) -> Result<()> {
         self.try_set_ddc_frequency(frequency, input_samp_rate)?;
         // update configuration cache if we succeeded
         self.ddc_config.frequency = frequency;
         Ok(())
     }
 
-    fn try_set_ddc_frequency(&self, frequency: f64, input_samp_rate: f64) -> Result<()> {
-        if !(-0.5 * input_samp_rate..=0.5 * input_samp_rate).contains(&frequency) {
+    fn try_set_ddc_frequency(&self, frequency: Hertz, input_samp_rate: Hertz) -> Result<()> {
+        if !(input_samp_rate * -0.5..=input_samp_rate * 0.5).contains(&frequency) {
             anyhow::bail!(
                 "frequency {frequency} is out of range with input sample rate {input_samp_rate}"
             );
@@ -699,7 +866,7 @@ impl IpCore {
     /// This offset is relative to the AD9361 RX LO frequency. The offset is
     /// zero if the input is the AD9361, or the DDC frequency if the input is
     /// the DDC.
-    pub fn recorder_input_frequency_offset(&self) -> f64 {
+    pub fn recorder_input_frequency_offset(&self) -> Hertz {
         // currently the recorder shares the same input as the spectrometer
         self.spectrometer_input_frequency_offset()
     }
@@ -764,6 +931,87 @@ impl IpCore {
             .modify(|_, w| w.stop().set_bit());
     }
 
+    /// Arms the recorder to start on the trigger input.
+    ///
+    /// The recording will start on the next rising edge of the trigger input,
+    /// unless the recorder is disarmed with [`IpCore::recorder_disarm`]
+    /// before. This should only be called when the recorder is stopped.
+    pub fn recorder_arm_trigger(&self) {
+        tracing::info!("arming recorder trigger");
+        self.registers
+            .recorder_control()
+            .modify(|_, w| w.arm_trigger().set_bit());
+    }
+
+    /// Disarms the recorder.
+    ///
+    /// See [`IpCore::recorder_arm_trigger`].
+    pub fn recorder_disarm(&self) {
+        tracing::info!("disarming recorder trigger");
+        self.registers
+            .recorder_control()
+            .modify(|_, w| w.disarm().set_bit());
+    }
+
+    /// Returns `true` if the recorder is armed to start on the trigger input.
+    ///
+    /// The recorder stops being armed when it is disarmed or when the trigger
+    /// starts the recording.
+    pub fn recorder_armed(&self) -> bool {
+        self.registers.recorder_control().read().armed().bit()
+    }
+
+    /// Gives the trigger input mode of the recorder.
+    pub fn recorder_trigger_mode(&self) -> maia_json::RecorderTriggerMode {
+        if self
+            .registers
+            .recorder_control()
+            .read()
+            .trigger_stop()
+            .bit()
+        {
+            maia_json::RecorderTriggerMode::StartStop
+        } else {
+            maia_json::RecorderTriggerMode::Start
+        }
+    }
+
+    /// Sets the trigger input mode of the recorder.
+    ///
+    /// In [`StartStop`](maia_json::RecorderTriggerMode::StartStop) mode, the
+    /// active edge of the trigger input stops a running recording.
+    pub fn set_recorder_trigger_mode(&self, mode: maia_json::RecorderTriggerMode) {
+        let stop = matches!(mode, maia_json::RecorderTriggerMode::StartStop);
+        self.registers
+            .recorder_control()
+            .modify(|_, w| w.trigger_stop().bit(stop));
+    }
+
+    /// Gives the trigger input polarity of the recorder.
+    pub fn recorder_trigger_polarity(&self) -> maia_json::RecorderTriggerPolarity {
+        if self
+            .registers
+            .recorder_control()
+            .read()
+            .trigger_polarity()
+            .bit()
+        {
+            maia_json::RecorderTriggerPolarity::Falling
+        } else {
+            maia_json::RecorderTriggerPolarity::Rising
+        }
+    }
+
+    /// Sets the trigger input polarity of the recorder.
+    ///
+    /// The polarity selects which edge of the trigger input is active.
+    pub fn set_recorder_trigger_polarity(&self, polarity: maia_json::RecorderTriggerPolarity) {
+        let falling = matches!(polarity, maia_json::RecorderTriggerPolarity::Falling);
+        self.registers
+            .recorder_control()
+            .modify(|_, w| w.trigger_polarity().bit(falling));
+    }
+
     /// Gives the value of the next address register of the recorder.
     ///
     /// This register indicates the next physical address to which the recorder
@@ -772,14 +1020,104 @@ impl IpCore {
     pub fn recorder_next_address(&self) -> usize {
         usize::try_from(self.registers.recorder_next_address().read().bits()).unwrap()
     }
+
+    /// Returns `true` if the IP core has a sample counter.
+    ///
+    /// The sample counter and the associated registers are only present in IP
+    /// core versions 0.7.0 and later.
+    pub fn has_sample_counter(&self) -> bool {
+        self.version_struct() >= VERSION_0_7_0
+    }
+
+    /// Returns `true` if the IP core has a recorder trigger input.
+    ///
+    /// The trigger input is only present in IP core versions 0.7.0 and later.
+    pub fn has_recorder_trigger(&self) -> bool {
+        self.version_struct() >= VERSION_0_7_0
+    }
+
+    /// Gives the value of the sample counter at the start of the last
+    /// recording.
+    ///
+    /// The sample counter counts the samples produced by the AD9361. Its value
+    /// is latched when the first sample of a recording arrives to the
+    /// recorder. This returns `None` if the IP core does not have a sample
+    /// counter.
+    pub fn recorder_start_sample(&self) -> Option<u64> {
+        if !self.has_sample_counter() {
+            return None;
+        }
+        let lo = self.registers.recorder_start_sample_lo().read().bits();
+        let hi = self
+            .registers
+            .recorder_start_sample_hi()
+            .read()
+            .start_sample_hi()
+            .bits();
+        Some((u64::from(hi) << 32) | u64::from(lo))
+    }
+
+    /// Latches the current value of the sample counter.
+    ///
+    /// The latched value can be read with [`IpCore::sample_counter_latch`]
+    /// once its latch count has changed.
+    pub fn sample_counter_snapshot(&self) {
+        self.registers
+            .sample_counter_hi()
+            .modify(|_, w| w.snapshot().set_bit());
+    }
+
+    /// Gives the latched value of the sample counter.
+    ///
+    /// The sample counter is latched by [`IpCore::sample_counter_snapshot`]
+    /// and, if enabled with [`IpCore::set_pps_enable`], on each rising edge of
+    /// the PPS input.
+    pub fn sample_counter_latch(&self) -> SampleCounterLatch {
+        loop {
+            // The latch count is used to check that the two halves of the
+            // latched value belong to the same latch event.
+            let hi = self.registers.sample_counter_hi().read();
+            let lo = self.registers.sample_counter_lo().read().bits();
+            let hi_check = self.registers.sample_counter_hi().read();
+            if hi.latch_count().bits() == hi_check.latch_count().bits() {
+                return SampleCounterLatch {
+                    counter: (u64::from(hi.counter_hi().bits()) << 32) | u64::from(lo),
+                    count: hi.latch_count().bits(),
+                    pps: hi.latch_pps().bit(),
+                };
+            }
+        }
+    }
+
+    /// Enables or disables latching the sample counter on the PPS input.
+    pub fn set_pps_enable(&self, enable: bool) {
+        self.registers
+            .sample_counter_hi()
+            .modify(|_, w| w.pps_enable().bit(enable));
+    }
+}
+
+/// Latched value of the sample counter.
+///
+/// See [`IpCore::sample_counter_latch`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct SampleCounterLatch {
+    /// Value of the sample counter.
+    pub counter: u64,
+    /// Latch count.
+    ///
+    /// This 4-bit counter is incremented each time that the sample counter is
+    /// latched. It can be used to detect when a new value has been latched.
+    pub count: u8,
+    /// Indicates whether the value was latched by the PPS input.
+    pub pps: bool,
 }
 
 macro_rules! impl_interrupt_handler {
     ($($interrupt:ident),*) => {
         paste::paste! {
-            fn new(uio: Uio, registers: Registers) -> InterruptHandler {
+            fn new(registers: Registers) -> InterruptHandler {
                 InterruptHandler {
-                    uio,
                     registers,
                     $(
                         [<notify_ $interrupt>]: Arc::new(Notify::new()),
@@ -788,8 +1126,7 @@ macro_rules! impl_interrupt_handler {
             }
 
             async fn wait_and_notify(&mut self) -> Result<()> {
-                self.uio.irq_enable().await?;
-                self.uio.irq_wait().await?;
+                self.registers.0.wait_interrupt().await?;
                 let interrupts = self.registers.interrupts().read();
                 $(
                     if interrupts.$interrupt().bit() {
@@ -838,17 +1175,138 @@ impl InterruptWaiter {
     }
 }
 
-impl Dma {
-    async fn new(name: &str) -> Result<Dma> {
-        let buffer = RxBuffer::new(name)
+impl UioFpga {
+    /// Opens the UIO device and DMA buffers of the IP core.
+    pub async fn new() -> Result<UioFpga> {
+        let uio = Uio::from_name("maia-sdr")
             .await
-            .context("failed to open rxbuffer DMA buffer")?;
-        let num_buffers = buffer.num_buffers();
+            .context("failed to open maia-sdr UIO")?;
+        let mapping = uio
+            .map_mapping(0)
+            .await
+            .context("failed to map maia-sdr UIO")?;
+        let phys_addr = uio.map_addr(0).await?;
+        let spectrometer = RxBuffer::new("maia-sdr-spectrometer")
+            .await
+            .context("failed to open maia-sdr-spectrometer DMA buffer")?;
+        Ok(UioFpga {
+            uio: tokio::sync::Mutex::new(uio),
+            mapping,
+            phys_addr,
+            spectrometer,
+        })
+    }
+}
+
+impl FpgaBackend for UioFpga {
+    fn registers(&self) -> *mut libc::c_void {
+        self.mapping.addr()
+    }
+
+    fn phys_addr(&self) -> usize {
+        self.phys_addr
+    }
+
+    fn spectrometer_num_buffers(&self) -> usize {
+        self.spectrometer.num_buffers()
+    }
+
+    fn spectrometer_buffer(&self, num_buffer: usize) -> &[u8] {
+        self.spectrometer.buffer_as_slice(num_buffer)
+    }
+
+    fn spectrometer_cache_invalidate(&self, num_buffer: usize) -> Result<()> {
+        self.spectrometer.cache_invalidate(num_buffer)
+    }
+
+    fn recording_buffer(&self) -> BoxFuture<'_, Result<Box<dyn RecordingMemory>>> {
+        Box::pin(async move {
+            let mapping = RecordingMapping::new().await?;
+            Ok(Box::new(mapping) as Box<dyn RecordingMemory>)
+        })
+    }
+
+    fn recording_base_address(&self) -> BoxFuture<'_, Result<usize>> {
+        Box::pin(async move {
+            Ok(usize::from_str_radix(
+                fs::read_to_string(
+                    "/sys/class/maia-sdr/maia-sdr-recording/device/recording_base_address",
+                )
+                .await?
+                .trim_end()
+                .trim_start_matches("0x"),
+                16,
+            )?)
+        })
+    }
+
+    fn wait_interrupt(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            let mut uio = self.uio.lock().await;
+            uio.irq_enable().await?;
+            uio.irq_wait().await?;
+            Ok(())
+        })
+    }
+}
+
+impl RecordingMapping {
+    async fn new() -> Result<RecordingMapping> {
+        let size = usize::from_str_radix(
+            fs::read_to_string("/sys/class/maia-sdr/maia-sdr-recording/device/recording_size")
+                .await?
+                .trim_end()
+                .trim_start_matches("0x"),
+            16,
+        )?;
+        let mem = fs::OpenOptions::new()
+            .read(true)
+            .open("/dev/maia-sdr-recording")
+            .await?;
+        // mmap()'ing the buffer can be quite expensive, because the cache is invalidated.
+        // We run it with spawn_blocking.
+        tokio::task::spawn_blocking(move || unsafe {
+            match libc::mmap(
+                std::ptr::null_mut::<libc::c_void>(),
+                size,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                mem.as_raw_fd(),
+                0,
+            ) {
+                libc::MAP_FAILED => Err(anyhow::anyhow!("mmap /dev/maia-sdr-recording failed")),
+                x => Ok(RecordingMapping {
+                    base: x as *const u8,
+                    size,
+                }),
+            }
+        })
+        .await?
+    }
+}
+
+impl RecordingMemory for RecordingMapping {
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.base, self.size) }
+    }
+}
+
+impl Drop for RecordingMapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.size);
+        }
+    }
+}
+
+impl Dma {
+    fn new(backend: Arc<dyn FpgaBackend>) -> Result<Dma> {
+        let num_buffers = backend.spectrometer_num_buffers();
         if !num_buffers.is_power_of_two() {
             anyhow::bail!("num_buffers is not a power of 2");
         }
         Ok(Dma {
-            buffer,
+            backend,
             last_written: None,
             num_buffers_mask: num_buffers - 1,
         })
@@ -866,8 +1324,8 @@ impl Dma {
             .map(|n| n & self.num_buffers_mask)
             .take_while(move |&n| n != end)
             .map(|n| {
-                self.buffer.cache_invalidate(n).unwrap();
-                self.buffer.buffer_as_slice(n)
+                self.backend.spectrometer_cache_invalidate(n).unwrap();
+                self.backend.spectrometer_buffer(n)
             })
     }
 }
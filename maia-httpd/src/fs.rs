@@ -0,0 +1,80 @@
+//! Filesystem utilities.
+//!
+//! This module contains helpers to persist the configuration files that
+//! maia-httpd writes to disk.
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Writes a file atomically.
+///
+/// The contents are written to a temporary file in the same directory, which
+/// is then renamed to `path`. This makes sure that the file is not left
+/// corrupted if maia-httpd stops while writing it. Each call uses a different
+/// temporary file, so concurrent writes to the same file do not interfere with
+/// each other. The last rename wins.
+pub async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let tmp_path = tmp_path(path);
+    let result = async {
+        tokio::fs::write(&tmp_path, contents)
+            .await
+            .with_context(|| format!("failed to write {tmp_path:?}"))?;
+        tokio::fs::rename(&tmp_path, path)
+            .await
+            .with_context(|| format!("failed to rename {tmp_path:?} to {path:?}"))
+    }
+    .await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&tmp_path).await;
+    }
+    result
+}
+
+// Returns a temporary path in the same directory as path that is unique
+// within this process.
+fn tmp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.{count}.tmp", std::process::id()));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_writes() {
+        let directory = std::env::temp_dir().join(format!("maia-httpd-fs-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("config.json");
+        let writes = (0..16)
+            .map(|n| write_atomic(&path, format!("{n:0>8}")))
+            .collect::<Vec<_>>();
+        for result in futures::future::join_all(writes).await {
+            result.unwrap();
+        }
+        // One of the writes wins, and no temporary files are left behind
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.len(), 8);
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn unique_tmp_paths() {
+        let path = Path::new("/var/lib/maia-sdr/limits.json");
+        let a = tmp_path(path);
+        let b = tmp_path(path);
+        assert_ne!(a, b);
+        assert_eq!(a.parent(), path.parent());
+        assert!(a
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("limits.json."));
+    }
+}
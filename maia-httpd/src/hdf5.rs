@@ -0,0 +1,526 @@
+//! HDF5 format.
+//!
+//! This module contains a minimal writer for the [HDF5 file
+//! format](https://docs.hdfgroup.org/hdf5/develop/_f_m_t3.html). It only
+//! supports files formed by a root group with scalar attributes and a few
+//! datasets with contiguous storage, which is enough to write the files used by
+//! [Digital RF](crate::digital_rf). The files use the oldest versions of the
+//! format structures (superblock version 0 and object header version 1), so
+//! that they can be read by any version of the HDF5 library.
+//!
+//! The data of the last dataset of the file can be supplied separately, so that
+//! large datasets can be streamed after the rest of the file.
+
+use anyhow::Result;
+
+const SIGNATURE: &[u8; 8] = b"\x89HDF\r\n\x1a\n";
+const UNDEFINED_ADDRESS: u64 = u64::MAX;
+const GROUP_LEAF_NODE_K: usize = 4;
+const GROUP_INTERNAL_NODE_K: usize = 16;
+const SUPERBLOCK_SIZE: usize = 96;
+const LOCAL_HEAP_HEADER_SIZE: usize = 32;
+const SYMBOL_TABLE_ENTRY_SIZE: usize = 40;
+const BTREE_NODE_SIZE: usize =
+    24 + (2 * GROUP_INTERNAL_NODE_K + 1) * 8 + 2 * GROUP_INTERNAL_NODE_K * 8;
+const SYMBOL_TABLE_NODE_SIZE: usize = 8 + 2 * GROUP_LEAF_NODE_K * SYMBOL_TABLE_ENTRY_SIZE;
+
+// Header message types
+const MSG_DATASPACE: u16 = 0x0001;
+const MSG_DATATYPE: u16 = 0x0003;
+const MSG_FILL_VALUE: u16 = 0x0005;
+const MSG_LAYOUT: u16 = 0x0008;
+const MSG_ATTRIBUTE: u16 = 0x000c;
+const MSG_SYMBOL_TABLE: u16 = 0x0011;
+
+/// HDF5 datatype.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Datatype {
+    /// Little-endian integer.
+    Integer {
+        /// Size in bytes.
+        size: usize,
+        /// Whether the integer is signed.
+        signed: bool,
+    },
+    /// Fixed-length null-terminated ASCII string.
+    String {
+        /// Size in bytes, including the null terminator.
+        size: usize,
+    },
+    /// Compound datatype.
+    ///
+    /// The members are given by their name and datatype, and are packed
+    /// without padding.
+    Compound(Vec<(String, Datatype)>),
+}
+
+/// HDF5 attribute value.
+///
+/// Only scalar attributes are supported.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Value {
+    /// 32-bit signed integer.
+    I32(i32),
+    /// 64-bit unsigned integer.
+    U64(u64),
+    /// String.
+    String(String),
+}
+
+/// HDF5 dataset.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Dataset {
+    /// Name of the dataset in the root group.
+    pub name: String,
+    /// Datatype of the elements of the dataset.
+    pub datatype: Datatype,
+    /// Dimensions of the dataset.
+    pub dimensions: Vec<u64>,
+    /// Attributes of the dataset.
+    pub attributes: Vec<(String, Value)>,
+    /// Data of the dataset.
+    ///
+    /// The data is given as the little-endian representation of the elements,
+    /// in row-major order. If this is `None`, the data needs to be appended
+    /// after the bytes returned by [`File::to_bytes`]. Only the last dataset
+    /// of the file can have its data given in this way.
+    pub data: Option<Vec<u8>>,
+}
+
+/// HDF5 file.
+///
+/// The file contains a root group with attributes and datasets.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Default)]
+pub struct File {
+    /// Attributes of the root group.
+    pub attributes: Vec<(String, Value)>,
+    /// Datasets in the root group.
+    pub datasets: Vec<Dataset>,
+}
+
+impl Datatype {
+    /// Returns the size in bytes of an element with this datatype.
+    pub fn size(&self) -> usize {
+        match self {
+            Datatype::Integer { size, .. } | Datatype::String { size } => *size,
+            Datatype::Compound(members) => members.iter().map(|(_, m)| m.size()).sum(),
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Datatype::Integer { size, signed } => {
+                // class 0 (fixed-point), version 1
+                out.push(0x10);
+                out.extend_from_slice(&[if *signed { 0x08 } else { 0x00 }, 0, 0]);
+                put_u32(out, *size);
+                // bit offset and bit precision
+                out.extend_from_slice(&0u16.to_le_bytes());
+                out.extend_from_slice(&u16::try_from(8 * size).unwrap().to_le_bytes());
+            }
+            Datatype::String { size } => {
+                // class 3 (string), version 1, null terminated ASCII
+                out.push(0x13);
+                out.extend_from_slice(&[0, 0, 0]);
+                put_u32(out, *size);
+            }
+            Datatype::Compound(members) => {
+                // class 6 (compound), version 1
+                out.push(0x16);
+                out.extend_from_slice(&u16::try_from(members.len()).unwrap().to_le_bytes());
+                out.push(0);
+                put_u32(out, self.size());
+                let mut offset = 0;
+                for (name, member) in members {
+                    put_padded_name(out, name);
+                    put_u32(out, offset);
+                    // dimensionality, reserved, dimension permutation,
+                    // reserved, and 4 dimension sizes
+                    out.extend_from_slice(&[0; 28]);
+                    member.encode(out);
+                    offset += member.size();
+                }
+            }
+        }
+    }
+}
+
+impl Value {
+    fn datatype(&self) -> Datatype {
+        match self {
+            Value::I32(_) => Datatype::Integer {
+                size: 4,
+                signed: true,
+            },
+            Value::U64(_) => Datatype::Integer {
+                size: 8,
+                signed: false,
+            },
+            Value::String(s) => Datatype::String { size: s.len() + 1 },
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::I32(x) => out.extend_from_slice(&x.to_le_bytes()),
+            Value::U64(x) => out.extend_from_slice(&x.to_le_bytes()),
+            Value::String(s) => {
+                out.extend_from_slice(s.as_bytes());
+                out.push(0);
+            }
+        }
+    }
+}
+
+impl Dataset {
+    /// Returns the size in bytes of the data of the dataset.
+    pub fn data_size(&self) -> usize {
+        self.dimensions.iter().product::<u64>() as usize * self.datatype.size()
+    }
+
+    fn object_header(&self, data_address: u64) -> Vec<u8> {
+        let mut messages = Vec::new();
+        let mut dataspace = Vec::new();
+        encode_dataspace(&mut dataspace, &self.dimensions);
+        messages.push((MSG_DATASPACE, dataspace));
+        let mut datatype = Vec::new();
+        self.datatype.encode(&mut datatype);
+        messages.push((MSG_DATATYPE, datatype));
+        // version 2, early space allocation, write fill value if set by the
+        // user, fill value undefined
+        messages.push((MSG_FILL_VALUE, vec![2, 1, 2, 0]));
+        let mut layout = vec![3, 1];
+        put_u64(&mut layout, data_address);
+        put_u64(&mut layout, self.data_size() as u64);
+        messages.push((MSG_LAYOUT, layout));
+        messages.extend(encode_attributes(&self.attributes));
+        object_header(&messages)
+    }
+}
+
+impl File {
+    /// Creates a new empty file.
+    pub fn new() -> File {
+        File::default()
+    }
+
+    /// Serializes the file.
+    ///
+    /// If the last dataset does not have its data, the data needs to be
+    /// appended to the bytes returned by this function to form the file.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        if let Some((_, datasets)) = self.datasets.split_last() {
+            anyhow::ensure!(
+                datasets.iter().all(|dataset| dataset.data.is_some()),
+                "only the last dataset can be appended"
+            );
+        }
+        // Entries of the root group, which need to be sorted by name.
+        let mut entries = self.datasets.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        anyhow::ensure!(entries.len() <= 2 * GROUP_LEAF_NODE_K, "too many datasets");
+
+        // Local heap data segment, which contains the names of the entries.
+        // The first name is the empty string.
+        let mut heap = vec![0; 8];
+        let mut name_offsets = Vec::new();
+        for entry in &entries {
+            name_offsets.push(heap.len() as u64);
+            put_padded_name(&mut heap, &entry.name);
+        }
+
+        // Layout of the file
+        let root_header_size = self.root_object_header(0, 0).len();
+        let root_header_address = SUPERBLOCK_SIZE;
+        let heap_address = root_header_address + root_header_size;
+        let btree_address = heap_address + LOCAL_HEAP_HEADER_SIZE + heap.len();
+        let snod_address = btree_address + BTREE_NODE_SIZE;
+        let mut address = snod_address + SYMBOL_TABLE_NODE_SIZE;
+        let mut header_addresses = Vec::new();
+        for dataset in &self.datasets {
+            header_addresses.push(address);
+            address += dataset.object_header(0).len();
+        }
+        let mut data_addresses = Vec::new();
+        for dataset in &self.datasets {
+            data_addresses.push(address);
+            address += dataset.data_size();
+        }
+        let eof_address = address as u64;
+
+        let mut out = Vec::with_capacity(data_addresses.last().copied().unwrap_or(address));
+        // Superblock
+        out.extend_from_slice(SIGNATURE);
+        // superblock version, free-space version, root group symbol table
+        // entry version, reserved, shared header message version, size of
+        // offsets, size of lengths, reserved
+        out.extend_from_slice(&[0, 0, 0, 0, 0, 8, 8, 0]);
+        out.extend_from_slice(&(GROUP_LEAF_NODE_K as u16).to_le_bytes());
+        out.extend_from_slice(&(GROUP_INTERNAL_NODE_K as u16).to_le_bytes());
+        // file consistency flags
+        put_u32(&mut out, 0);
+        // base address, free-space info address, end of file address, driver
+        // information block address
+        put_u64(&mut out, 0);
+        put_u64(&mut out, UNDEFINED_ADDRESS);
+        put_u64(&mut out, eof_address);
+        put_u64(&mut out, UNDEFINED_ADDRESS);
+        // root group symbol table entry, which caches the symbol table
+        put_symbol_table_entry(&mut out, 0, root_header_address as u64, 1);
+        put_u64(&mut out, btree_address as u64);
+        put_u64(&mut out, heap_address as u64);
+        assert_eq!(out.len(), SUPERBLOCK_SIZE);
+
+        out.extend(self.root_object_header(btree_address as u64, heap_address as u64));
+
+        // Local heap
+        out.extend_from_slice(b"HEAP");
+        out.extend_from_slice(&[0, 0, 0, 0]);
+        put_u64(&mut out, heap.len() as u64);
+        // no free list, which the HDF5 library encodes as an offset of 1
+        put_u64(&mut out, 1);
+        put_u64(&mut out, (heap_address + LOCAL_HEAP_HEADER_SIZE) as u64);
+        out.extend_from_slice(&heap);
+
+        // B-tree with a single leaf node, which points to the symbol table
+        // node if the group is not empty
+        out.extend_from_slice(b"TREE");
+        // node type (group), node level
+        out.extend_from_slice(&[0, 0]);
+        let children = u16::from(!entries.is_empty());
+        out.extend_from_slice(&children.to_le_bytes());
+        put_u64(&mut out, UNDEFINED_ADDRESS);
+        put_u64(&mut out, UNDEFINED_ADDRESS);
+        if !entries.is_empty() {
+            put_u64(&mut out, 0);
+            put_u64(&mut out, snod_address as u64);
+            put_u64(&mut out, *name_offsets.last().unwrap());
+        }
+        out.resize(snod_address, 0);
+
+        // Symbol table node
+        out.extend_from_slice(b"SNOD");
+        out.extend_from_slice(&[1, 0]);
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        for (entry, &name_offset) in entries.iter().zip(&name_offsets) {
+            let index = self
+                .datasets
+                .iter()
+                .position(|dataset| std::ptr::eq(dataset, *entry))
+                .unwrap();
+            put_symbol_table_entry(&mut out, name_offset, header_addresses[index] as u64, 0);
+            put_u64(&mut out, 0);
+            put_u64(&mut out, 0);
+        }
+        out.resize(snod_address + SYMBOL_TABLE_NODE_SIZE, 0);
+
+        // Datasets
+        for (dataset, &data_address) in self.datasets.iter().zip(&data_addresses) {
+            out.extend(dataset.object_header(data_address as u64));
+        }
+        for dataset in &self.datasets {
+            if let Some(data) = &dataset.data {
+                anyhow::ensure!(
+                    data.len() == dataset.data_size(),
+                    "data of dataset {} has wrong size",
+                    dataset.name
+                );
+                out.extend_from_slice(data);
+            }
+        }
+        Ok(out)
+    }
+
+    fn root_object_header(&self, btree_address: u64, heap_address: u64) -> Vec<u8> {
+        let mut symbol_table = Vec::new();
+        put_u64(&mut symbol_table, btree_address);
+        put_u64(&mut symbol_table, heap_address);
+        let mut messages = vec![(MSG_SYMBOL_TABLE, symbol_table)];
+        messages.extend(encode_attributes(&self.attributes));
+        object_header(&messages)
+    }
+}
+
+fn put_u32(out: &mut Vec<u8>, x: usize) {
+    out.extend_from_slice(&u32::try_from(x).unwrap().to_le_bytes());
+}
+
+fn put_u64(out: &mut Vec<u8>, x: u64) {
+    out.extend_from_slice(&x.to_le_bytes());
+}
+
+// Appends a null-terminated name padded with nulls to a multiple of 8 bytes.
+fn put_padded_name(out: &mut Vec<u8>, name: &str) {
+    out.extend_from_slice(name.as_bytes());
+    out.resize(out.len() + 8 - name.len() % 8, 0);
+}
+
+// Appends a symbol table entry without its scratch-pad space.
+fn put_symbol_table_entry(
+    out: &mut Vec<u8>,
+    name_offset: u64,
+    object_header_address: u64,
+    cache_type: u32,
+) {
+    put_u64(out, name_offset);
+    put_u64(out, object_header_address);
+    out.extend_from_slice(&cache_type.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+}
+
+fn encode_dataspace(out: &mut Vec<u8>, dimensions: &[u64]) {
+    // version 1, dimensionality, flags (no maximum dimensions), reserved
+    out.extend_from_slice(&[1, u8::try_from(dimensions.len()).unwrap(), 0, 0, 0, 0, 0, 0]);
+    for &dimension in dimensions {
+        put_u64(out, dimension);
+    }
+}
+
+fn encode_attributes(attributes: &[(String, Value)]) -> Vec<(u16, Vec<u8>)> {
+    attributes
+        .iter()
+        .map(|(name, value)| {
+            let mut datatype = Vec::new();
+            value.datatype().encode(&mut datatype);
+            // scalar dataspace
+            let mut dataspace = Vec::new();
+            encode_dataspace(&mut dataspace, &[]);
+            let mut message = vec![1, 0];
+            // sizes without padding
+            message.extend_from_slice(&u16::try_from(name.len() + 1).unwrap().to_le_bytes());
+            message.extend_from_slice(&u16::try_from(datatype.len()).unwrap().to_le_bytes());
+            message.extend_from_slice(&u16::try_from(dataspace.len()).unwrap().to_le_bytes());
+            put_padded_name(&mut message, name);
+            datatype.resize(datatype.len().next_multiple_of(8), 0);
+            message.extend_from_slice(&datatype);
+            message.extend_from_slice(&dataspace);
+            value.encode(&mut message);
+            (MSG_ATTRIBUTE, message)
+        })
+        .collect()
+}
+
+// Encodes a version 1 object header.
+fn object_header(messages: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (message_type, data) in messages {
+        let size = data.len().next_multiple_of(8);
+        body.extend_from_slice(&message_type.to_le_bytes());
+        body.extend_from_slice(&u16::try_from(size).unwrap().to_le_bytes());
+        // flags and reserved
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(data);
+        body.resize(body.len() + size - data.len(), 0);
+    }
+    // version, reserved, number of messages
+    let mut header = vec![1, 0];
+    header.extend_from_slice(&u16::try_from(messages.len()).unwrap().to_le_bytes());
+    // object reference count
+    put_u32(&mut header, 1);
+    put_u32(&mut header, body.len());
+    // padding to align the messages to 8 bytes
+    put_u32(&mut header, 0);
+    header.extend(body);
+    header
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn read_u64(data: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn datatypes() {
+        let mut out = Vec::new();
+        Datatype::Integer {
+            size: 2,
+            signed: true,
+        }
+        .encode(&mut out);
+        assert_eq!(out, [0x10, 0x08, 0, 0, 2, 0, 0, 0, 0, 0, 16, 0]);
+        let complex = Datatype::Compound(vec![
+            (
+                "r".to_string(),
+                Datatype::Integer {
+                    size: 2,
+                    signed: true,
+                },
+            ),
+            (
+                "i".to_string(),
+                Datatype::Integer {
+                    size: 2,
+                    signed: true,
+                },
+            ),
+        ]);
+        assert_eq!(complex.size(), 4);
+        let mut out = Vec::new();
+        complex.encode(&mut out);
+        assert_eq!(out.len(), 8 + 2 * (8 + 4 + 28 + 12));
+        assert_eq!(out[..8], [0x16, 2, 0, 0, 4, 0, 0, 0]);
+        // offset of the second member
+        assert_eq!(out[8 + 52 + 8..8 + 52 + 12], [2, 0, 0, 0]);
+    }
+
+    #[test]
+    fn file_layout() {
+        let file = File {
+            attributes: vec![("version".to_string(), Value::String("1.0".to_string()))],
+            datasets: vec![
+                Dataset {
+                    name: "index".to_string(),
+                    datatype: Datatype::Integer {
+                        size: 8,
+                        signed: true,
+                    },
+                    dimensions: vec![1, 2],
+                    attributes: vec![],
+                    data: Some(vec![0xaa; 16]),
+                },
+                Dataset {
+                    name: "data".to_string(),
+                    datatype: Datatype::Integer {
+                        size: 1,
+                        signed: true,
+                    },
+                    dimensions: vec![100],
+                    attributes: vec![("count".to_string(), Value::U64(100))],
+                    data: None,
+                },
+            ],
+        };
+        let bytes = file.to_bytes().unwrap();
+        assert_eq!(&bytes[..8], SIGNATURE);
+        // end of file address includes the appended data
+        assert_eq!(read_u64(&bytes, 40), bytes.len() as u64 + 100);
+        // the root symbol table entry points to the B-tree and local heap
+        let btree = read_u64(&bytes, 80) as usize;
+        let heap = read_u64(&bytes, 88) as usize;
+        assert_eq!(&bytes[btree..btree + 4], b"TREE");
+        assert_eq!(&bytes[heap..heap + 4], b"HEAP");
+        // the symbol table node contains the datasets sorted by name
+        let snod = read_u64(&bytes, btree + 32) as usize;
+        assert_eq!(&bytes[snod..snod + 4], b"SNOD");
+        assert_eq!(bytes[snod + 6], 2);
+        let heap_data = read_u64(&bytes, heap + 24) as usize;
+        let name = |entry: usize| {
+            let offset = read_u64(&bytes, snod + 8 + 40 * entry) as usize;
+            let name = &bytes[heap_data + offset..];
+            String::from_utf8(name[..name.iter().position(|&c| c == 0).unwrap()].to_vec()).unwrap()
+        };
+        assert_eq!(name(0), "data");
+        assert_eq!(name(1), "index");
+        // the B-tree key is the name of the last entry
+        assert_eq!(read_u64(&bytes, btree + 40), read_u64(&bytes, snod + 48));
+        // the inline data is at the end of the bytes
+        assert_eq!(bytes[bytes.len() - 16..], [0xaa; 16]);
+
+        let mut file = file;
+        file.datasets.reverse();
+        assert!(file.to_bytes().is_err());
+    }
+}
@@ -0,0 +1,248 @@
+//! Minimal HTTP client.
+//!
+//! This module contains a minimal HTTP/1.1 client, which is used to send
+//! recordings and notifications to other servers. It supports `http://` and
+//! `https://` URLs. For `https://` URLs, the server certificate is verified
+//! using the CA certificates of the system.
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use http::{header, HeaderMap, Method, StatusCode, Uri};
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio_rustls::rustls;
+
+// Timeout for each of the network operations of a request.
+const IO_TIMEOUT: Duration = Duration::from_secs(30);
+// Maximum size of the headers of a response.
+const MAX_HEADERS_SIZE: usize = 16 << 10;
+// Maximum size of the body of a response that is read. The rest of the body is
+// discarded.
+const MAX_BODY_SIZE: usize = 16 << 10;
+
+/// HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// Status code.
+    pub status: StatusCode,
+    /// Body of the response.
+    ///
+    /// Only the first 16 KiB of the body are read.
+    pub body: Bytes,
+}
+
+impl Response {
+    /// Returns an error if the status code is not a success.
+    ///
+    /// The error contains the body of the response, since it usually describes
+    /// the problem.
+    pub fn error_for_status(self) -> Result<Response> {
+        anyhow::ensure!(
+            self.status.is_success(),
+            "server returned {}: {}",
+            self.status,
+            String::from_utf8_lossy(&self.body).trim()
+        );
+        Ok(self)
+    }
+}
+
+/// Checks that a URL can be used with this client.
+pub fn check_url(url: &Uri) -> Result<()> {
+    anyhow::ensure!(
+        matches!(url.scheme_str(), Some("http" | "https")) && url.host().is_some(),
+        "URL must be an http:// or https:// URL"
+    );
+    Ok(())
+}
+
+/// Sends an HTTP request.
+///
+/// The request is sent to `url` with the given `method`, `headers` and
+/// `body`. The `Host`, `Content-Length` and `Connection` headers are added
+/// by this function. The body is given as a stream, so that large bodies can
+/// be sent without storing them in memory, and `content_length` is its size in
+/// bytes.
+pub async fn send<S>(
+    method: &Method,
+    url: &Uri,
+    headers: &HeaderMap,
+    body: S,
+    content_length: usize,
+) -> Result<Response>
+where
+    S: Stream<Item = Result<Bytes, std::io::Error>>,
+{
+    check_url(url)?;
+    let host = url.host().unwrap();
+    let https = url.scheme_str() == Some("https");
+    let port = url.port_u16().unwrap_or(if https { 443 } else { 80 });
+    let tcp = io_timeout(tokio::net::TcpStream::connect((host, port)))
+        .await
+        .with_context(|| format!("failed to connect to {host}:{port}"))?;
+    let mut stream: Box<dyn Connection> = if https {
+        let server_name = rustls::pki_types::ServerName::try_from(host.to_string())?;
+        let connector = tokio_rustls::TlsConnector::from(tls_config()?);
+        Box::new(
+            io_timeout(connector.connect(server_name, tcp))
+                .await
+                .context("TLS handshake failed")?,
+        )
+    } else {
+        Box::new(tcp)
+    };
+
+    let path = url.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+    let mut request = format!(
+        "{method} {path} HTTP/1.1\r\n\
+         Host: {}\r\n\
+         Content-Length: {content_length}\r\n\
+         Connection: close\r\n",
+        url.authority().unwrap()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{name}: {}\r\n", value.to_str()?));
+    }
+    request.push_str("\r\n");
+    io_timeout(stream.write_all(request.as_bytes())).await?;
+    let mut body = std::pin::pin!(body);
+    while let Some(chunk) = body.next().await {
+        io_timeout(stream.write_all(&chunk?)).await?;
+    }
+    io_timeout(stream.flush()).await?;
+    read_response(&mut stream).await
+}
+
+async fn read_response(stream: &mut Box<dyn Connection>) -> Result<Response> {
+    let mut data = Vec::new();
+    let mut buf = [0; 4096];
+    let headers_end = loop {
+        let n = io_timeout(stream.read(&mut buf)).await?;
+        anyhow::ensure!(n > 0, "connection closed before receiving a response");
+        data.extend_from_slice(&buf[..n]);
+        if let Some(pos) = data.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4;
+        }
+        anyhow::ensure!(data.len() <= MAX_HEADERS_SIZE, "response headers too long");
+    };
+    let headers = std::str::from_utf8(&data[..headers_end]).context("invalid response")?;
+    let mut lines = headers.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<StatusCode>().ok())
+        .with_context(|| format!("invalid HTTP response: {status_line:?}"))?;
+    let content_length = lines.find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        name.eq_ignore_ascii_case(header::CONTENT_LENGTH.as_str())
+            .then(|| value.trim().parse::<usize>().ok())?
+    });
+    let body_size = content_length.unwrap_or(usize::MAX).min(MAX_BODY_SIZE);
+    let mut body = data.split_off(headers_end);
+    // The server closes the connection after the response, so the body ends
+    // on EOF if the Content-Length header is missing.
+    while body.len() < body_size {
+        let n = io_timeout(stream.read(&mut buf)).await?;
+        if n == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..n]);
+    }
+    body.truncate(body_size);
+    Ok(Response {
+        status,
+        body: body.into(),
+    })
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+async fn io_timeout<T, E>(future: impl Future<Output = Result<T, E>>) -> Result<T>
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    Ok(tokio::time::timeout(IO_TIMEOUT, future)
+        .await
+        .context("operation timed out")??)
+}
+
+fn tls_config() -> Result<Arc<rustls::ClientConfig>> {
+    static CONFIG: OnceLock<Arc<rustls::ClientConfig>> = OnceLock::new();
+    if let Some(config) = CONFIG.get() {
+        return Ok(Arc::clone(config));
+    }
+    let mut roots = rustls::RootCertStore::empty();
+    let certs =
+        rustls_native_certs::load_native_certs().context("failed to load CA certificates")?;
+    let (_, ignored) = roots.add_parsable_certificates(certs);
+    if ignored != 0 {
+        tracing::warn!("ignored {ignored} invalid CA certificates");
+    }
+    let config = Arc::new(
+        rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    );
+    Ok(Arc::clone(CONFIG.get_or_init(|| config)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert!(check_url(&"http://localhost:8080/hook".parse().unwrap()).is_ok());
+        assert!(check_url(&"https://example.com/hook".parse().unwrap()).is_ok());
+        assert!(check_url(&"ftp://example.com/hook".parse().unwrap()).is_err());
+        assert!(check_url(&"/hook".parse().unwrap()).is_err());
+    }
+
+    #[tokio::test]
+    async fn post() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook?a=1", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            // Read until the end of the body, which is the JSON object.
+            while !request.ends_with(b"}") {
+                let n = stream.read(&mut buf).await.unwrap();
+                assert!(n > 0);
+                request.extend_from_slice(&buf[..n]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 5\r\n\r\nerror")
+                .await
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+        let body = br#"{"filename":"recording"}"#;
+        let response = send(
+            &Method::POST,
+            &url.parse().unwrap(),
+            &headers,
+            futures::stream::iter([Ok(Bytes::from_static(body))]),
+            body.len(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status, StatusCode::BAD_REQUEST);
+        assert_eq!(response.body, "error");
+        assert!(response.error_for_status().is_err());
+        let request = server.await.unwrap();
+        assert!(request.starts_with("POST /hook?a=1 HTTP/1.1\r\n"));
+        assert!(request.contains("Content-Length: 24\r\n"));
+        assert!(request.contains("content-type: application/json\r\n"));
+        assert!(request.ends_with("\r\n\r\n{\"filename\":\"recording\"}"));
+    }
+}
@@ -3,34 +3,64 @@
 //! This module contains the HTTP server of maia-httpd, which is a web server
 //! implemented using [`axum`].
 
-use crate::app::AppState;
+use crate::{app::AppState, shutdown::CONNECTIONS_CLOSE_TIMEOUT, spectrometer::SpectrumLine};
 use anyhow::Result;
 use axum::{
-    routing::{get, put},
+    http::{header, HeaderValue, Method},
+    middleware,
+    routing::{delete, get, post, put},
     Router,
 };
 use axum_server::tls_rustls::RustlsConfig;
-use bytes::Bytes;
 use std::{net::SocketAddr, path::Path};
 use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
 use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
     services::{ServeDir, ServeFile},
     trace::TraceLayer,
 };
 
 mod ad9361;
 mod api;
+mod audit;
+mod benchmark;
+mod button;
+mod clients;
+mod colormaps;
 mod ddc;
+mod demodulators;
+mod device;
+mod doppler;
 mod geolocation;
 mod iqengine;
+mod limits;
+mod list;
+mod peaks;
 mod recording;
+mod scanner;
+mod sensors;
+mod service_worker;
 mod spectrometer;
+mod startup_profile;
+mod static_files;
+mod status_led;
+mod streams;
+mod surveys;
+mod system;
 mod time;
 mod version;
 mod websocket;
 mod zeros;
 
-pub use recording::{RecorderFinishWaiter, RecorderState};
+pub use audit::{AuditLog, RateLimiter};
+pub use button::ButtonRunner;
+pub use clients::WebSocketClients;
+pub use device::DeviceState;
+pub use recording::storage::{DmaStorage, FileStorage, RecordingStorage};
+pub use recording::{IqCapture, RecorderFinishWaiter, RecorderState, RecordingHooks, ScannerHit};
+pub use startup_profile::StartupProfile;
+pub use status_led::{StatusLed, StatusLedRunner};
 
 /// HTTP server.
 ///
@@ -42,6 +72,7 @@ pub struct Server {
     http_server: axum_server::Server,
     https_server: Option<axum_server::Server<axum_server::tls_rustls::RustlsAcceptor>>,
     app: Router,
+    closing: CancellationToken,
 }
 
 impl Server {
@@ -55,54 +86,48 @@ impl Server {
     /// shared access to update the sample rate of the spectrometer. The
     /// `waiter_recorder` is the interrupt waiter for the IQ recorder, which is
     /// contolled by the HTTP server. The `waterfall_sender` is used to obtain
-    /// waterfall channel receivers for the websocket server.
+    /// waterfall channel receivers for the websocket server. The
+    /// `cors_allow_origin` parameter lists the origins from which cross-origin
+    /// requests are allowed (`*` allows any origin). If it is empty,
+    /// cross-origin requests are not allowed. The `audit_log` records the
+    /// requests that modify the state of the device, and the `rate_limiter`,
+    /// if present, limits the rate of such requests.
     ///
     /// After calling this function, the server needs to be run by calling
     /// [`Server::run`].
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         http_address: SocketAddr,
         https_address: SocketAddr,
         ssl_cert: Option<impl AsRef<Path>>,
         ssl_key: Option<impl AsRef<Path>>,
         ca_cert: Option<impl AsRef<Path>>,
+        cors_allow_origin: &[String],
+        audit_log: AuditLog,
+        rate_limiter: Option<RateLimiter>,
         state: AppState,
-        waterfall_sender: broadcast::Sender<Bytes>,
+        waterfall_sender: broadcast::Sender<SpectrumLine>,
     ) -> Result<Server> {
+        let closing = state.shutdown().closing().clone();
         let mut app = Router::new()
+            .merge(api_routes(
+                &format!("/api/v{}", maia_json::API_VERSION),
+                audit_log.clone(),
+            ))
+            .merge(api_routes("/api", audit_log.clone()))
             // all the following routes have .with_state(state)
-            .route("/api", get(api::get_api))
-            .route(
-                "/api/ad9361",
-                get(ad9361::get_ad9361)
-                    .put(ad9361::put_ad9361)
-                    .patch(ad9361::patch_ad9361),
-            )
-            .route(
-                "/api/spectrometer",
-                get(spectrometer::get_spectrometer).patch(spectrometer::patch_spectrometer),
-            )
-            .route(
-                "/api/ddc/config",
-                get(ddc::get_ddc_config)
-                    .put(ddc::put_ddc_config)
-                    .patch(ddc::patch_ddc_config),
-            )
-            .route("/api/ddc/design", put(ddc::put_ddc_design))
-            .route(
-                "/api/geolocation",
-                get(geolocation::get_geolocation).put(geolocation::put_geolocation),
-            )
+            .route("/peaks", get(peaks::websocket_handler))
+            .route("/recording", get(recording::get_recording))
+            .route("/recording/clips", get(recording::clips::get_clips_archive))
             .route(
-                "/api/recorder",
-                get(recording::get_recorder).patch(recording::patch_recorder),
+                "/processing/:id",
+                get(recording::processing::get_processing_output),
             )
+            .route("/scanner/hits", get(recording::clips::get_hits_archive))
             .route(
-                "/api/recording/metadata",
-                get(recording::get_recording_metadata)
-                    .put(recording::put_recording_metadata)
-                    .patch(recording::patch_recording_metadata),
+                "/recording/hopping",
+                get(recording::clips::get_hopping_archive),
             )
-            .route("/recording", get(recording::get_recording))
             .route("/version", get(version::get_version))
             // IQEngine viewer for IQ recording
             .route(
@@ -117,32 +142,46 @@ impl Server {
                 "/api/datasources/maiasdr/maiasdr/recording/minimap-data",
                 get(recording::iqengine::minimap_data),
             )
-            .with_state(state)
+            .with_state(state.clone())
             // the following routes have another (or no) state
-            .route(
-                "/api/time",
-                get(time::get_time)
-                    .put(time::put_time)
-                    .patch(time::patch_time),
-            )
             .route(
                 "/waterfall",
-                get(websocket::handler).with_state(waterfall_sender),
+                get(websocket::handler).with_state((waterfall_sender, state.clone())),
             )
             .route("/zeros", get(zeros::get_zeros)); // used for benchmarking
         if let Some(ca_cert) = &ca_cert {
             // Maia SDR CA certificate
             app = app.route_service("/ca.crt", ServeFile::new(ca_cert));
         }
-        let app = app
+        let mut app = app
             // IQEngine viewer for IQ recording
             .route_service(
                 "/view/api/maiasdr/maiasdr/recording",
                 ServeFile::new("iqengine/index.html"),
             )
             .route("/assets/:filename", get(iqengine::serve_assets))
-            .fallback_service(ServeDir::new("."))
+            // Service worker of maia-wasm
+            .route("/sw.js", get(service_worker::get_service_worker))
+            .fallback_service(
+                // Static files (maia-wasm), serving the precompressed variants
+                // (.br and .gz files) when they exist
+                Router::new()
+                    .fallback_service(ServeDir::new(".").precompressed_br().precompressed_gzip())
+                    .layer(middleware::from_fn(static_files::cache_headers)),
+            );
+        if let Some(rate_limiter) = rate_limiter {
+            app = app.layer(middleware::from_fn_with_state(
+                rate_limiter,
+                audit::rate_limit,
+            ));
+        }
+        app = app
+            .layer(middleware::from_fn_with_state(state, audit::notify_changes))
+            .layer(middleware::from_fn_with_state(audit_log, audit::audit))
             .layer(TraceLayer::new_for_http());
+        if let Some(cors) = cors_layer(cors_allow_origin)? {
+            app = app.layer(cors);
+        }
         tracing::info!(%http_address, "starting HTTP server");
         let http_server = axum_server::bind(http_address);
         tracing::info!(%https_address, "starting HTTPS server");
@@ -157,34 +196,347 @@ impl Server {
             http_server,
             https_server,
             app,
+            closing,
         })
     }
 
     /// Runs the HTTP server.
     ///
-    /// This only returns if there is a fatal error.
+    /// This only returns if there is a fatal error. During the shutdown of the
+    /// application, the server stops accepting connections and waits for the
+    /// existing connections to close.
     pub async fn run(self) -> Result<()> {
-        let http_server = self.http_server.serve(self.app.clone().into_make_service());
+        let handle = axum_server::Handle::new();
+        {
+            let handle = handle.clone();
+            let closing = self.closing.clone();
+            tokio::spawn(async move {
+                closing.cancelled().await;
+                handle.graceful_shutdown(Some(CONNECTIONS_CLOSE_TIMEOUT));
+            });
+        }
+        let http_server = self.http_server.handle(handle.clone()).serve(
+            self.app
+                .clone()
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        );
         if let Some(https_server) = self.https_server {
-            let https_server = https_server.serve(self.app.into_make_service());
-            Ok(tokio::select! {
-                ret = http_server => ret,
-                ret = https_server => ret,
-            }?)
+            let https_server = https_server
+                .handle(handle)
+                .serve(self.app.into_make_service_with_connect_info::<SocketAddr>());
+            tokio::try_join!(http_server, https_server)?;
         } else {
-            Ok(http_server.await?)
+            http_server.await?;
         }
+        // The servers only return without an error after a graceful
+        // shutdown. The shutdown handler finishes the application.
+        std::future::pending().await
+    }
+}
+
+// Routes of the REST API under the given path prefix.
+//
+// The REST API is served under /api/v1 and, for compatibility with clients that
+// predate API versioning, also under /api.
+fn api_routes(prefix: &str, audit_log: AuditLog) -> Router<AppState> {
+    Router::new()
+        .route(prefix, get(api::get_api))
+        .route(
+            &format!("{prefix}/ad9361"),
+            get(ad9361::get_ad9361)
+                .put(ad9361::put_ad9361)
+                .patch(ad9361::patch_ad9361),
+        )
+        .route(
+            &format!("{prefix}/ad9361/iq-imbalance"),
+            post(ad9361::post_ad9361_iq_imbalance),
+        )
+        .route(
+            &format!("{prefix}/ad9361/agc"),
+            get(ad9361::get_ad9361_agc)
+                .put(ad9361::put_ad9361_agc)
+                .patch(ad9361::patch_ad9361_agc),
+        )
+        .route(
+            &format!("{prefix}/spectrometer"),
+            get(spectrometer::get_spectrometer).patch(spectrometer::patch_spectrometer),
+        )
+        .route(
+            &format!("{prefix}/spectrometer/format"),
+            get(spectrometer::get_spectrometer_format),
+        )
+        .route(
+            &format!("{prefix}/spectrometer/spectrogram.png"),
+            get(spectrometer::get_spectrogram),
+        )
+        .route(
+            &format!("{prefix}/spectrometer/equalization"),
+            get(spectrometer::get_spectrometer_equalization)
+                .put(spectrometer::put_spectrometer_equalization),
+        )
+        .route(&format!("{prefix}/clients"), get(clients::get_clients))
+        .route(
+            &format!("{prefix}/clients/:id"),
+            delete(clients::delete_client),
+        )
+        .route(
+            &format!("{prefix}/colormaps"),
+            get(colormaps::get_colormaps),
+        )
+        .route(
+            &format!("{prefix}/colormaps/generate"),
+            get(colormaps::get_generate_colormap),
+        )
+        .route(
+            &format!("{prefix}/colormaps/:name"),
+            get(colormaps::get_colormap)
+                .put(colormaps::put_colormap)
+                .delete(colormaps::delete_colormap),
+        )
+        .route(
+            &format!("{prefix}/spectrometer/peaks"),
+            get(peaks::get_peaks).patch(peaks::patch_peaks),
+        )
+        .route(
+            &format!("{prefix}/ddc/config"),
+            get(ddc::get_ddc_config)
+                .put(ddc::put_ddc_config)
+                .patch(ddc::patch_ddc_config),
+        )
+        .route(&format!("{prefix}/ddc/design"), put(ddc::put_ddc_design))
+        .route(&format!("{prefix}/ddc/snr"), get(ddc::get_ddc_snr))
+        .route(
+            &format!("{prefix}/ddc/noise-blanker"),
+            get(ddc::get_noise_blanker).patch(ddc::patch_noise_blanker),
+        )
+        .route(
+            &format!("{prefix}/demodulators"),
+            get(demodulators::get_demodulators),
+        )
+        .route(
+            &format!("{prefix}/demodulators/:name"),
+            get(demodulators::get_demodulator)
+                .put(demodulators::put_demodulator)
+                .delete(demodulators::delete_demodulator),
+        )
+        .route(
+            &format!("{prefix}/device"),
+            get(device::get_device).patch(device::patch_device),
+        )
+        .route(
+            &format!("{prefix}/device/identify"),
+            put(device::put_identify),
+        )
+        .route(&format!("{prefix}/shutdown"), put(device::put_shutdown))
+        .route(&format!("{prefix}/reboot"), put(device::put_reboot))
+        .route(&format!("{prefix}/system"), get(system::get_system))
+        .route(
+            &format!("{prefix}/system/clock"),
+            get(system::get_system_clock),
+        )
+        .route(
+            &format!("{prefix}/system/led"),
+            get(status_led::get_system_led).patch(status_led::patch_system_led),
+        )
+        .route(
+            &format!("{prefix}/benchmark"),
+            post(benchmark::post_benchmark),
+        )
+        .route(
+            &format!("{prefix}/limits-admin"),
+            get(limits::get_limits_admin).put(limits::put_limits_admin),
+        )
+        .route(
+            &format!("{prefix}/startup-profile"),
+            get(startup_profile::get_startup_profile)
+                .put(startup_profile::put_startup_profile)
+                .delete(startup_profile::delete_startup_profile),
+        )
+        .route(
+            &format!("{prefix}/startup-profile/current"),
+            put(startup_profile::put_startup_profile_current),
+        )
+        .route(
+            &format!("{prefix}/doppler"),
+            get(doppler::get_doppler).patch(doppler::patch_doppler),
+        )
+        .route(
+            &format!("{prefix}/geolocation"),
+            get(geolocation::get_geolocation).put(geolocation::put_geolocation),
+        )
+        .route(
+            &format!("{prefix}/scanner"),
+            get(scanner::get_scanner).patch(scanner::patch_scanner),
+        )
+        .route(
+            &format!("{prefix}/scanner/hits"),
+            get(recording::clips::get_hits).delete(recording::clips::delete_hits),
+        )
+        .route(
+            &format!("{prefix}/recorder"),
+            get(recording::get_recorder).patch(recording::patch_recorder),
+        )
+        .route(
+            &format!("{prefix}/recorder/transfers"),
+            get(recording::transfers::get_recorder_transfers),
+        )
+        .route(
+            &format!("{prefix}/recorder/arm"),
+            put(recording::put_recorder_arm),
+        )
+        .route(
+            &format!("{prefix}/recorder/clips"),
+            get(recording::clips::get_clips).delete(recording::clips::delete_clips),
+        )
+        .route(
+            &format!("{prefix}/recorder/hopping"),
+            get(recording::hopping::get_hopping)
+                .put(recording::hopping::put_hopping)
+                .delete(recording::hopping::delete_hopping),
+        )
+        .route(
+            &format!("{prefix}/recorder/hopping/recordings"),
+            get(recording::clips::get_hopping_recordings)
+                .delete(recording::clips::delete_hopping_recordings),
+        )
+        .route(
+            &format!("{prefix}/recording"),
+            delete(recording::delete_recording),
+        )
+        .route(
+            &format!("{prefix}/recording/metadata"),
+            get(recording::get_recording_metadata)
+                .put(recording::put_recording_metadata)
+                .patch(recording::patch_recording_metadata),
+        )
+        .route(
+            &format!("{prefix}/recording/trim"),
+            get(recording::get_recording_trim)
+                .patch(recording::patch_recording_trim)
+                .delete(recording::delete_recording_trim),
+        )
+        .route(
+            &format!("{prefix}/recording/annotations"),
+            get(recording::annotations::get_recording_annotations)
+                .post(recording::annotations::post_recording_annotation)
+                .delete(recording::annotations::delete_recording_annotations),
+        )
+        .route(
+            &format!("{prefix}/recording/annotations/:id"),
+            delete(recording::annotations::delete_recording_annotation),
+        )
+        .route(
+            &format!("{prefix}/recording/checksum"),
+            get(recording::get_recording_checksum),
+        )
+        .route(
+            &format!("{prefix}/recording/preview"),
+            get(recording::preview::get_preview),
+        )
+        .route(
+            &format!("{prefix}/recording/region"),
+            get(recording::region::get_region),
+        )
+        .route(
+            &format!("{prefix}/recording/stats"),
+            get(recording::stats::get_stats),
+        )
+        .route(
+            &format!("{prefix}/recording/upload"),
+            get(recording::upload::get_recording_upload)
+                .put(recording::upload::put_recording_upload)
+                .delete(recording::upload::delete_recording_upload),
+        )
+        .route(
+            &format!("{prefix}/processing"),
+            get(recording::processing::get_processing_jobs)
+                .post(recording::processing::post_processing_job),
+        )
+        .route(
+            &format!("{prefix}/processing/:id"),
+            get(recording::processing::get_processing_job)
+                .delete(recording::processing::delete_processing_job),
+        )
+        .route(&format!("{prefix}/sensors"), get(sensors::get_sensors))
+        .route(&format!("{prefix}/streams"), get(streams::get_streams))
+        .route(
+            &format!("{prefix}/upload-target"),
+            get(recording::upload::get_upload_target).patch(recording::upload::patch_upload_target),
+        )
+        .route(
+            &format!("{prefix}/streams/:name"),
+            get(streams::get_stream)
+                .put(streams::put_stream)
+                .delete(streams::delete_stream),
+        )
+        .route(&format!("{prefix}/surveys"), get(surveys::get_surveys))
+        .route(
+            &format!("{prefix}/surveys/:name"),
+            get(surveys::get_survey)
+                .put(surveys::put_survey)
+                .delete(surveys::delete_survey),
+        )
+        .route(
+            &format!("{prefix}/surveys/:name/run"),
+            put(surveys::put_survey_run),
+        )
+        .route(
+            &format!("{prefix}/surveys/:name/reports/:id"),
+            get(surveys::get_survey_report),
+        )
+        .route(
+            &format!("{prefix}/time"),
+            get(time::get_time)
+                .put(time::put_time)
+                .patch(time::patch_time),
+        )
+        .route(
+            &format!("{prefix}/audit"),
+            get(audit::get_audit).with_state(audit_log),
+        )
+}
+
+fn cors_layer(allow_origin: &[String]) -> Result<Option<CorsLayer>> {
+    if allow_origin.is_empty() {
+        return Ok(None);
     }
+    let origin = if allow_origin.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            allow_origin
+                .iter()
+                .map(|origin| HeaderValue::from_str(origin))
+                .collect::<Result<Vec<_>, _>>()?,
+        )
+    };
+    tracing::info!(?allow_origin, "allowing cross-origin requests");
+    Ok(Some(
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods([
+                Method::GET,
+                Method::POST,
+                Method::PUT,
+                Method::PATCH,
+                Method::DELETE,
+            ])
+            .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]),
+    ))
 }
 
 mod json_error {
     use anyhow::Error;
     use axum::{
-        http::StatusCode,
+        http::{header, StatusCode},
         response::{IntoResponse, Response},
     };
     use serde::Serialize;
 
+    // Seconds after which the client should retry a request that failed with
+    // service unavailable.
+    const RETRY_AFTER: &str = "1";
+
     #[derive(Serialize, Debug, Clone, Eq, PartialEq)]
     pub struct JsonError(maia_json::Error);
 
@@ -214,6 +566,22 @@ mod json_error {
             JsonError::from_error(error, StatusCode::BAD_REQUEST, maia_json::ErrorAction::Log)
         }
 
+        pub fn unauthorized<E: Into<Error>>(error: E) -> JsonError {
+            JsonError::from_error(
+                error,
+                StatusCode::UNAUTHORIZED,
+                maia_json::ErrorAction::Alert,
+            )
+        }
+
+        pub fn conflict<E: Into<Error>>(error: E) -> JsonError {
+            JsonError::from_error(error, StatusCode::CONFLICT, maia_json::ErrorAction::Alert)
+        }
+
+        pub fn not_found<E: Into<Error>>(error: E) -> JsonError {
+            JsonError::from_error(error, StatusCode::NOT_FOUND, maia_json::ErrorAction::Log)
+        }
+
         pub fn server_error<E: Into<Error>>(error: E) -> JsonError {
             JsonError::from_error(
                 error,
@@ -221,13 +589,83 @@ mod json_error {
                 maia_json::ErrorAction::Log,
             )
         }
+
+        // The response includes a Retry-After header, since the request can
+        // succeed if it is retried later.
+        pub fn service_unavailable<E: Into<Error>>(error: E) -> JsonError {
+            JsonError::from_error(
+                error,
+                StatusCode::SERVICE_UNAVAILABLE,
+                maia_json::ErrorAction::Log,
+            )
+        }
+    }
+
+    impl std::fmt::Display for JsonError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.write_str(&self.0.error_description)
+        }
     }
 
+    impl std::error::Error for JsonError {}
+
     impl IntoResponse for JsonError {
         fn into_response(self) -> Response {
             let status_code = StatusCode::from_u16(self.0.http_status_code).unwrap();
             let json = serde_json::to_string(&self.0).unwrap();
-            (status_code, json).into_response()
+            if status_code == StatusCode::SERVICE_UNAVAILABLE {
+                (status_code, [(header::RETRY_AFTER, RETRY_AFTER)], json).into_response()
+            } else {
+                (status_code, json).into_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn cors_preflight() {
+        assert!(cors_layer(&[]).unwrap().is_none());
+        let app = Router::new()
+            .route("/api/test", get(|| async {}).post(|| async {}))
+            .layer(
+                cors_layer(&["http://example.com".to_string()])
+                    .unwrap()
+                    .unwrap(),
+            );
+        for (method, headers) in [("POST", "content-type"), ("PUT", "authorization")] {
+            let request = Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/api/test")
+                .header(header::ORIGIN, "http://example.com")
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+                .header(header::ACCESS_CONTROL_REQUEST_HEADERS, headers)
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            assert!(response.status().is_success());
+            let response_headers = response.headers();
+            assert_eq!(
+                response_headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+                "http://example.com"
+            );
+            let allowed = |name| {
+                response_headers[name]
+                    .to_str()
+                    .unwrap()
+                    .to_ascii_lowercase()
+                    .split(',')
+                    .map(|x| x.trim().to_string())
+                    .collect::<Vec<_>>()
+            };
+            assert!(allowed(header::ACCESS_CONTROL_ALLOW_METHODS)
+                .contains(&method.to_ascii_lowercase()));
+            assert!(allowed(header::ACCESS_CONTROL_ALLOW_HEADERS).contains(&headers.to_string()));
         }
     }
 }
@@ -1,22 +1,51 @@
 use super::json_error::JsonError;
-use crate::{app::AppState, iio};
+use crate::{app::AppState, iio, iq_imbalance};
 use anyhow::Result;
 use axum::{extract::State, Json};
-use maia_json::{Ad9361, PatchAd9361};
+use maia_json::{
+    Ad9361, Ad9361Agc, Ad9361GainMode, Ad9361IqImbalance, Ad9361RfBandwidthMode, Decibels, Hertz,
+    PatchAd9361, PatchAd9361Agc, SpectrometerInput,
+};
+use std::time::Duration;
 
+// Duration of the capture used to estimate the IQ imbalance.
+const IQ_IMBALANCE_CAPTURE_DURATION: Duration = Duration::from_millis(20);
+
+// The optional prefix is prepended to the attribute names to obtain the names
+// of the iio::Ad9361 methods.
 macro_rules! get_attributes {
-    ($iio:expr, $($attribute:ident),*) => {
+    ($struct:ident, $iio:expr, $prefix:ident; $($attribute:ident),*) => {
+        paste::paste! {
+            $struct {
+                $(
+                    $attribute: $iio.[<get_ $prefix $attribute>]().await?.into(),
+                )*
+            }
+        }
+    };
+    ($struct:ident, $iio:expr, $($attribute:ident),*) => {
         paste::paste! {
-            Ad9361 {
+            $struct {
                 $(
                     $attribute: $iio.[<get_ $attribute>]().await?.into(),
                 )*
             }
         }
-    }
+    };
 }
 
 macro_rules! try_set_attributes {
+    ($iio:expr, $json:expr, $prefix:ident; $($attribute:ident),*) => {
+        paste::paste! {
+            $(
+                if let Some(value) = $json.$attribute {
+                    $iio.[<set_ $prefix $attribute>](value.into())
+                        .await
+                        .map_err(JsonError::server_error)?;
+                }
+            )*
+        }
+    };
     ($iio:expr, $json:expr, $($attribute:ident),*) => {
         paste::paste! {
             $(
@@ -25,14 +54,16 @@ macro_rules! try_set_attributes {
                 }
             )*
         }
-    }
+    };
 }
 
 pub async fn ad9361_json(iio: &iio::Ad9361) -> Result<Ad9361> {
     Ok(get_attributes!(
+        Ad9361,
         iio,
         sampling_frequency,
         rx_rf_bandwidth,
+        rx_rf_bandwidth_mode,
         tx_rf_bandwidth,
         rx_lo_frequency,
         tx_lo_frequency,
@@ -42,7 +73,14 @@ pub async fn ad9361_json(iio: &iio::Ad9361) -> Result<Ad9361> {
     ))
 }
 
-async fn ad9361_update(
+// Returns the receive RF bandwidth used in automatic mode for a sampling
+// frequency.
+fn auto_rx_rf_bandwidth(sampling_frequency: Hertz) -> Hertz {
+    let range = PatchAd9361::RF_BANDWIDTH_RANGE;
+    Hertz(sampling_frequency.0.clamp(*range.start(), *range.end()))
+}
+
+pub(super) async fn ad9361_update(
     state: &AppState,
     iio: &iio::Ad9361,
     json: &PatchAd9361,
@@ -50,9 +88,13 @@ async fn ad9361_update(
     if let Some(freq) = json.sampling_frequency {
         // here the input sample rate to the DDC does not matter, because we only
         // need its config to check the maximum input sampling frequency and the enable
-        let ddc_config = state.ip_core().lock().unwrap().ddc_config_summary(0.0);
+        let ddc_config = state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .ddc_config_summary(Hertz(0.0));
         // check that DDC can support this input frequency if it is enabled
-        if ddc_config.enabled && f64::from(freq) > ddc_config.max_input_sampling_frequency {
+        if ddc_config.enabled && freq > ddc_config.max_input_sampling_frequency {
             return Err(JsonError::client_error_alert(anyhow::anyhow!(
                 "tried to set AD9361 sampling rate to {freq}, \
                            but DDC is enabled and its maximum input sampling frequency is {}",
@@ -68,13 +110,39 @@ async fn ad9361_update(
             .ip_core()
             .lock()
             .unwrap()
-            .set_ddc_frequency(ddc_config.frequency, f64::from(freq))
+            .set_ddc_frequency(ddc_config.frequency, freq)
             .map_err(JsonError::client_error_alert)?;
     }
+    // Setting the receive RF bandwidth explicitly selects the manual mode.
+    let rx_rf_bandwidth_mode = json
+        .rx_rf_bandwidth_mode
+        .or(json.rx_rf_bandwidth.map(|_| Ad9361RfBandwidthMode::Manual));
+    if let Some(mode) = rx_rf_bandwidth_mode {
+        iio.set_rx_rf_bandwidth_mode(mode);
+    }
+    match iio
+        .get_rx_rf_bandwidth_mode()
+        .await
+        .map_err(JsonError::server_error)?
+    {
+        Ad9361RfBandwidthMode::Auto => {
+            if json.sampling_frequency.is_some()
+                || rx_rf_bandwidth_mode == Some(Ad9361RfBandwidthMode::Auto)
+            {
+                let freq = iio
+                    .get_sampling_frequency()
+                    .await
+                    .map_err(JsonError::server_error)?;
+                iio.set_rx_rf_bandwidth(auto_rx_rf_bandwidth(freq))
+                    .await
+                    .map_err(JsonError::server_error)?;
+            }
+        }
+        Ad9361RfBandwidthMode::Manual => try_set_attributes!(iio, json, rx_rf_bandwidth),
+    }
     try_set_attributes!(
         iio,
         json,
-        rx_rf_bandwidth,
         tx_rf_bandwidth,
         rx_lo_frequency,
         tx_lo_frequency,
@@ -83,6 +151,35 @@ async fn ad9361_update(
         rx_gain,
         tx_gain
     );
+    if json.calibrate_rx_filter == Some(true) {
+        iio.calibrate_rx_filter()
+            .await
+            .map_err(JsonError::server_error)?;
+    }
+    if json.rx_lo_frequency.is_some() {
+        state
+            .spectrometer_config()
+            .retune(&state.ip_core().lock().unwrap());
+    }
+    Ok(())
+}
+
+// Checks that the settings of a request are within the safety limits.
+pub(super) fn check_limits(state: &AppState, json: &PatchAd9361) -> Result<(), JsonError> {
+    let limits = state.limits();
+    for freq in [json.rx_lo_frequency, json.tx_lo_frequency]
+        .into_iter()
+        .flatten()
+    {
+        limits
+            .check_frequency(freq)
+            .map_err(JsonError::client_error_alert)?;
+    }
+    if let Some(gain) = json.tx_gain {
+        limits
+            .check_tx_gain(gain)
+            .map_err(JsonError::client_error_alert)?;
+    }
     Ok(())
 }
 
@@ -102,6 +199,11 @@ async fn patch_ad9361_json(
     State(state): State<AppState>,
     patch: &PatchAd9361,
 ) -> Result<Json<Ad9361>, JsonError> {
+    patch.validate().map_err(JsonError::client_error_alert)?;
+    check_limits(&state, patch)?;
+    if patch.rx_lo_frequency.is_some() {
+        state.tuner().check_free().map_err(JsonError::conflict)?;
+    }
     let iio = state.ad9361().lock().await;
     ad9361_update(&state, &iio, patch).await?;
     get_ad9361_json(&iio).await
@@ -121,3 +223,385 @@ pub async fn patch_ad9361(
 ) -> Result<Json<Ad9361>, JsonError> {
     patch_ad9361_json(state, &patch).await
 }
+
+macro_rules! agc_attributes {
+    ($macro:ident!($($args:tt)*)) => {
+        $macro!(
+            $($args)*
+            outer_thresh_high,
+            outer_thresh_high_dec_steps,
+            inner_thresh_high,
+            inner_thresh_high_dec_steps,
+            inner_thresh_low,
+            inner_thresh_low_inc_steps,
+            outer_thresh_low,
+            outer_thresh_low_inc_steps,
+            attack_delay_extra_margin_us,
+            gain_update_interval_us,
+            fast_lock_level,
+            fast_state_wait_time_ns,
+            fast_allow_gain_increase,
+            fast_gain_increase_after_lock,
+            fast_unlock_stronger_signal_thresh,
+            fast_unlock_energy_lost_thresh
+        )
+    };
+}
+
+macro_rules! apply_patch {
+    ($x:expr, $patch:expr, $($field:ident),*) => {
+        $(
+            if let Some(value) = $patch.$field {
+                $x.$field = value;
+            }
+        )*
+    };
+}
+
+pub async fn ad9361_agc_json(iio: &iio::Ad9361) -> Result<Ad9361Agc> {
+    Ok(agc_attributes!(get_attributes!(Ad9361Agc, iio, agc_;)))
+}
+
+fn check_agc(agc: &Ad9361Agc) -> Result<(), JsonError> {
+    // The thresholds are given in dB below full scale, so the high thresholds
+    // have smaller values.
+    if !(agc.outer_thresh_high <= agc.inner_thresh_high
+        && agc.inner_thresh_high <= agc.inner_thresh_low
+        && agc.inner_thresh_low <= agc.outer_thresh_low)
+    {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "AGC thresholds must satisfy outer_thresh_high <= inner_thresh_high \
+             <= inner_thresh_low <= outer_thresh_low"
+        )));
+    }
+    Ok(())
+}
+
+async fn ad9361_agc_update(
+    state: &AppState,
+    iio: &iio::Ad9361,
+    json: &PatchAd9361Agc,
+) -> Result<(), JsonError> {
+    if *json == PatchAd9361Agc::default() {
+        return Ok(());
+    }
+    let mut agc = ad9361_agc_json(iio)
+        .await
+        .map_err(JsonError::server_error)?;
+    agc_attributes!(apply_patch!(agc, json,));
+    check_agc(&agc)?;
+    // The AD9361 is initialized again to apply the new AGC settings, so the
+    // current settings are read first to restore them afterwards.
+    let settings = ad9361_json(iio).await.map_err(JsonError::server_error)?;
+    agc_attributes!(try_set_attributes!(iio, json, agc_;));
+    iio.initialize().await.map_err(JsonError::server_error)?;
+    let mut restore = PatchAd9361::from(settings);
+    if restore.rx_gain_mode != Some(Ad9361GainMode::Manual) {
+        // the gain cannot be set when the AGC is enabled
+        restore.rx_gain = None;
+    }
+    ad9361_update(state, iio, &restore).await
+}
+
+async fn get_ad9361_agc_json(iio: &iio::Ad9361) -> Result<Json<Ad9361Agc>, JsonError> {
+    ad9361_agc_json(iio)
+        .await
+        .map_err(JsonError::server_error)
+        .map(Json)
+}
+
+pub async fn get_ad9361_agc(State(state): State<AppState>) -> Result<Json<Ad9361Agc>, JsonError> {
+    let iio = state.ad9361().lock().await;
+    get_ad9361_agc_json(&iio).await
+}
+
+async fn patch_ad9361_agc_json(
+    State(state): State<AppState>,
+    patch: &PatchAd9361Agc,
+) -> Result<Json<Ad9361Agc>, JsonError> {
+    let iio = state.ad9361().lock().await;
+    ad9361_agc_update(&state, &iio, patch).await?;
+    get_ad9361_agc_json(&iio).await
+}
+
+pub async fn put_ad9361_agc(
+    state: State<AppState>,
+    Json(put): Json<Ad9361Agc>,
+) -> Result<Json<Ad9361Agc>, JsonError> {
+    let patch = PatchAd9361Agc::from(put);
+    patch_ad9361_agc_json(state, &patch).await
+}
+
+pub async fn patch_ad9361_agc(
+    state: State<AppState>,
+    Json(patch): Json<PatchAd9361Agc>,
+) -> Result<Json<Ad9361Agc>, JsonError> {
+    patch_ad9361_agc_json(state, &patch).await
+}
+
+/// Estimates the residual IQ imbalance and DC offset of the AD9361.
+///
+/// The estimate is made from a short capture of IQ samples made with the
+/// recorder, so the recorder must not be in use, and its input, which is the
+/// same as the spectrometer input, must be the AD9361. The capture overwrites
+/// the recording buffer, so it is refused while the buffer contains a
+/// recording that has not been discarded.
+pub async fn post_ad9361_iq_imbalance(
+    State(state): State<AppState>,
+) -> Result<Json<Ad9361IqImbalance>, JsonError> {
+    let input = state.ip_core().lock().unwrap().spectrometer_input();
+    if !matches!(input, SpectrometerInput::AD9361) {
+        return Err(JsonError::client_error(anyhow::anyhow!(
+            "the spectrometer input must be the AD9361 to estimate the IQ imbalance"
+        )));
+    }
+    // The AD9361 lock is held during the capture, so that the settings
+    // reported correspond to the captured samples.
+    let (ad9361, capture) = {
+        let iio = state.ad9361().lock().await;
+        let ad9361 = ad9361_json(&iio).await.map_err(JsonError::server_error)?;
+        let capture = state
+            .recorder()
+            .capture_iq(state.ip_core(), IQ_IMBALANCE_CAPTURE_DURATION)
+            .await
+            .map_err(JsonError::server_error)?
+            .map_err(JsonError::conflict)?;
+        (ad9361, capture)
+    };
+    let estimate = iq_imbalance::estimate(&capture.samples).ok_or_else(|| {
+        JsonError::server_error(anyhow::anyhow!(
+            "the IQ imbalance cannot be estimated from the captured samples"
+        ))
+    })?;
+    let db = Decibels::from_power_ratio;
+    let dc_offset_power = estimate.dc_offset.norm_sqr();
+    Ok(Json(Ad9361IqImbalance {
+        rx_lo_frequency: ad9361.rx_lo_frequency,
+        sampling_frequency: ad9361.sampling_frequency,
+        rx_gain: ad9361.rx_gain,
+        rx_gain_mode: ad9361.rx_gain_mode,
+        num_samples: capture.samples.len(),
+        power_dbfs: db(estimate.power),
+        dc_offset_i: estimate.dc_offset.re,
+        dc_offset_q: estimate.dc_offset.im,
+        dc_offset_dbfs: (dc_offset_power > 0.0).then(|| db(dc_offset_power)),
+        image_rejection_db: estimate.image_rejection.map(db),
+        gain_imbalance_db: db(estimate.gain_imbalance),
+        phase_error_deg: estimate.phase_error.to_degrees(),
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse};
+
+    #[tokio::test]
+    async fn rx_rf_bandwidth_mode() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(ad9361) = get_ad9361(State(state.clone())).await.unwrap();
+        assert_eq!(ad9361.rx_rf_bandwidth_mode, Ad9361RfBandwidthMode::Manual);
+
+        let patch = PatchAd9361 {
+            rx_rf_bandwidth_mode: Some(Ad9361RfBandwidthMode::Auto),
+            sampling_frequency: Some(Hertz(30_720_000.0)),
+            ..Default::default()
+        };
+        let Json(ad9361) = patch_ad9361(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        assert_eq!(ad9361.rx_rf_bandwidth_mode, Ad9361RfBandwidthMode::Auto);
+        assert_eq!(ad9361.rx_rf_bandwidth, Hertz(30_720_000.0));
+        // the bandwidth follows the sampling frequency
+        let patch = PatchAd9361 {
+            sampling_frequency: Some(Hertz(10_000_000.0)),
+            ..Default::default()
+        };
+        let Json(ad9361) = patch_ad9361(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        assert_eq!(ad9361.rx_rf_bandwidth, Hertz(10_000_000.0));
+        // a PUT of the current settings keeps the automatic mode
+        let Json(ad9361) = put_ad9361(State(state.clone()), Json(ad9361.clone()))
+            .await
+            .unwrap();
+        assert_eq!(ad9361.rx_rf_bandwidth_mode, Ad9361RfBandwidthMode::Auto);
+
+        // setting the bandwidth selects the manual mode
+        let patch = PatchAd9361 {
+            rx_rf_bandwidth: Some(Hertz(2_000_000.0)),
+            calibrate_rx_filter: Some(true),
+            ..Default::default()
+        };
+        let Json(ad9361) = patch_ad9361(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        assert_eq!(ad9361.rx_rf_bandwidth_mode, Ad9361RfBandwidthMode::Manual);
+        assert_eq!(ad9361.rx_rf_bandwidth, Hertz(2_000_000.0));
+        let patch = PatchAd9361 {
+            sampling_frequency: Some(Hertz(20_000_000.0)),
+            ..Default::default()
+        };
+        let Json(ad9361) = patch_ad9361(State(state), Json(patch)).await.unwrap();
+        assert_eq!(ad9361.rx_rf_bandwidth, Hertz(2_000_000.0));
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let (state, _) = crate::mock::app_state().await;
+        state
+            .limits()
+            .set(maia_json::Limits {
+                max_tx_gain: Some(Decibels(-30.0)),
+                frequency_blocklist: vec![maia_json::LimitsFrequencyRange {
+                    start_frequency: Hertz(430_000_000.0),
+                    stop_frequency: Hertz(440_000_000.0),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        for patch in [
+            PatchAd9361 {
+                tx_gain: Some(Decibels(-10.0)),
+                ..Default::default()
+            },
+            PatchAd9361 {
+                rx_lo_frequency: Some(Hertz(433_920_000.0)),
+                ..Default::default()
+            },
+        ] {
+            assert!(patch_ad9361(State(state.clone()), Json(patch))
+                .await
+                .is_err());
+        }
+        let patch = PatchAd9361 {
+            rx_lo_frequency: Some(Hertz(446_000_000.0)),
+            tx_gain: Some(Decibels(-40.0)),
+            ..Default::default()
+        };
+        let Json(ad9361) = patch_ad9361(State(state), Json(patch)).await.unwrap();
+        assert_eq!(ad9361.rx_lo_frequency, Hertz(446_000_000.0));
+        assert_eq!(ad9361.tx_gain, Decibels(-40.0));
+    }
+
+    #[tokio::test]
+    async fn out_of_range() {
+        let (state, _) = crate::mock::app_state().await;
+        for patch in [
+            PatchAd9361 {
+                rx_lo_frequency: Some(Hertz(10e6)),
+                ..Default::default()
+            },
+            PatchAd9361 {
+                sampling_frequency: Some(Hertz(100e6)),
+                ..Default::default()
+            },
+            PatchAd9361 {
+                tx_gain: Some(Decibels(10.0)),
+                ..Default::default()
+            },
+        ] {
+            assert_eq!(
+                patch_ad9361(State(state.clone()), Json(patch))
+                    .await
+                    .unwrap_err()
+                    .into_response()
+                    .status(),
+                StatusCode::BAD_REQUEST
+            );
+        }
+        let Json(ad9361) = get_ad9361(State(state)).await.unwrap();
+        assert_ne!(ad9361.rx_lo_frequency, Hertz(10e6));
+    }
+
+    #[tokio::test]
+    async fn agc() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(agc) = get_ad9361_agc(State(state.clone())).await.unwrap();
+        assert_eq!(agc.fast_lock_level, 10);
+        assert!(!agc.fast_gain_increase_after_lock);
+
+        let patch = PatchAd9361Agc {
+            fast_lock_level: Some(15),
+            fast_gain_increase_after_lock: Some(true),
+            ..Default::default()
+        };
+        let Json(agc) = patch_ad9361_agc(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        assert_eq!(agc.fast_lock_level, 15);
+        assert!(agc.fast_gain_increase_after_lock);
+        // the AD9361 settings are preserved
+        let Json(ad9361) = get_ad9361(State(state.clone())).await.unwrap();
+        assert_eq!(ad9361.rx_gain_mode, Ad9361GainMode::SlowAttack);
+
+        // thresholds in the wrong order
+        let patch = PatchAd9361Agc {
+            inner_thresh_low: Some(20),
+            ..Default::default()
+        };
+        assert!(patch_ad9361_agc(State(state.clone()), Json(patch))
+            .await
+            .is_err());
+        let Json(agc) = get_ad9361_agc(State(state)).await.unwrap();
+        assert_eq!(agc.inner_thresh_low, 12);
+    }
+
+    #[tokio::test]
+    async fn iq_imbalance() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        let recorder_finish = crate::httpd::RecorderFinishWaiter::new(
+            state.clone(),
+            interrupt_handler.waiter_recorder(),
+            crate::httpd::RecordingHooks::default(),
+        );
+        tokio::spawn(interrupt_handler.run());
+        tokio::spawn(recorder_finish.run());
+        let Json(ad9361) = get_ad9361(State(state.clone())).await.unwrap();
+        let Json(json) = post_ad9361_iq_imbalance(State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(json.rx_lo_frequency, ad9361.rx_lo_frequency);
+        assert_eq!(json.rx_gain_mode, ad9361.rx_gain_mode);
+        assert!(json.num_samples > 0);
+        assert!(json.power_dbfs < Decibels(0.0));
+        assert!(json.gain_imbalance_db.is_finite() && json.phase_error_deg.is_finite());
+
+        state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .set_spectrometer_input(SpectrometerInput::DDC, Hertz(1e6))
+            .unwrap();
+        assert!(post_ad9361_iq_imbalance(State(state.clone()))
+            .await
+            .is_err());
+
+        // the capture is refused while there is a recording
+        state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .set_spectrometer_input(SpectrometerInput::AD9361, Hertz(1e6))
+            .unwrap();
+        let start = maia_json::PatchRecorder {
+            state_change: Some(maia_json::RecorderStateChange::Start),
+            ..Default::default()
+        };
+        let _ = crate::httpd::recording::patch_recorder(State(state.clone()), Json(start))
+            .await
+            .unwrap();
+        assert!(!state.recorder().is_stopped().await);
+        state
+            .recorder()
+            .stop(&state, Duration::from_secs(5))
+            .await
+            .unwrap();
+        let err = post_ad9361_iq_imbalance(State(state)).await.unwrap_err();
+        assert_eq!(
+            axum::response::IntoResponse::into_response(err).status(),
+            http::StatusCode::CONFLICT
+        );
+    }
+}
@@ -1,9 +1,15 @@
 use super::{
     ad9361::ad9361_json,
     ddc::ddc_json,
+    device::device_json,
     geolocation::device_geolocation,
     json_error::JsonError,
-    recording::{recorder_json, recording_metadata_json},
+    recording::{
+        annotations::recording_annotations_json,
+        recorder_json, recording_metadata_json,
+        upload::{upload_json, upload_target_json},
+    },
+    sensors::sensors_json,
     spectrometer::spectrometer_json,
     time::time_json,
 };
@@ -11,31 +17,216 @@ use crate::app::AppState;
 use anyhow::Result;
 use axum::{extract::State, Json};
 
-async fn api_json(state: &AppState) -> Result<maia_json::Api> {
-    let ad9361 = {
+async fn api_json(state: &AppState) -> Result<maia_json::Api, JsonError> {
+    use maia_json::ApiSection;
+
+    let mut sections = Sections {
+        last: state.last_api().lock().unwrap().clone(),
+        errors: Vec::new(),
+    };
+    let (ad9361, sensors) = {
         let ad9361 = state.ad9361().lock().await;
-        ad9361_json(&ad9361).await
-    }?;
-    let ddc = ddc_json(state).await?;
-    let spectrometer = spectrometer_json(state).await?;
-    let recorder = recorder_json(state).await?;
+        (ad9361_json(&ad9361).await, sensors_json(&ad9361).await)
+    };
+    let ad9361 = sections.get(ApiSection::Ad9361, ad9361, |api| &api.ad9361)?;
+    let sensors = sections.get(ApiSection::Sensors, sensors, |api| &api.sensors)?;
+    let ddc = sections.get(ApiSection::DDC, ddc_json(state).await, |api| &api.ddc)?;
+    let spectrometer = sections.get(
+        ApiSection::Spectrometer,
+        spectrometer_json(state).await,
+        |api| &api.spectrometer,
+    )?;
+    let recorder = sections.get(ApiSection::Recorder, recorder_json(state).await, |api| {
+        &api.recorder
+    })?;
+    let time = sections.get(ApiSection::Time, time_json(), |api| &api.time)?;
+    let recording_annotations = recording_annotations_json(state).await;
     let recording_metadata = recording_metadata_json(state).await;
+    let device = device_json(state);
     let geolocation = device_geolocation(state);
-    let time = time_json()?;
-    Ok(maia_json::Api {
+    let scanner = state.scanner().json();
+    let upload = upload_json(state);
+    let upload_target = upload_target_json(state);
+    let api = maia_json::Api {
+        api_version: maia_json::API_VERSION,
         ad9361,
         ddc,
+        device,
+        errors: sections.errors,
         geolocation,
         spectrometer,
         recorder,
+        recording_annotations,
         recording_metadata,
+        scanner,
+        sensors,
         time,
-    })
+        upload,
+        upload_target,
+    };
+    state.last_api().lock().unwrap().replace(api.clone());
+    Ok(api)
+}
+
+// Sections of the API that can fail to be read.
+struct Sections {
+    // Last response, which is used for the sections that cannot be read
+    last: Option<maia_json::Api>,
+    errors: Vec<maia_json::ApiSectionError>,
+}
+
+impl Sections {
+    // Returns the value of a section of the API. If the section could not be
+    // read, the error is added to the list of errors and the value of the
+    // section in the last response is returned instead. If there is no last
+    // response, which happens if the AD9361 cannot be read after maia-httpd
+    // starts, a service unavailable error is returned, since the error is
+    // usually transient and the client can retry.
+    fn get<T: Clone>(
+        &mut self,
+        section: maia_json::ApiSection,
+        result: Result<T>,
+        get_last: impl FnOnce(&maia_json::Api) -> &T,
+    ) -> Result<T, JsonError> {
+        match (result, &self.last) {
+            (Ok(value), _) => Ok(value),
+            (Err(err), None) => Err(JsonError::service_unavailable(err.context(format!(
+                "the {section:?} section of the API is not available yet"
+            )))),
+            (Err(err), Some(last)) => {
+                tracing::warn!("failed to read {section:?} section of the API: {err:#}");
+                self.errors.push(maia_json::ApiSectionError {
+                    section,
+                    error: format!("{err:#}"),
+                });
+                Ok(get_last(last).clone())
+            }
+        }
+    }
 }
 
 pub async fn get_api(State(state): State<AppState>) -> Result<Json<maia_json::Api>, JsonError> {
-    api_json(&state)
-        .await
-        .map_err(JsonError::server_error)
-        .map(Json)
+    api_json(&state).await.map(Json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::future::BoxFuture;
+
+    // AD9361 backend whose attributes cannot be read or written.
+    #[derive(Debug)]
+    struct FailingRfic;
+
+    impl crate::iio::RficBackend for FailingRfic {
+        fn read_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>> {
+            Box::pin(async move { anyhow::bail!("failed to read IIO attribute {attribute}") })
+        }
+
+        fn write_attribute<'a>(
+            &'a self,
+            attribute: &'a str,
+            _value: String,
+        ) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async move { anyhow::bail!("failed to write IIO attribute {attribute}") })
+        }
+    }
+
+    #[tokio::test]
+    async fn section_errors() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let Json(api) = get_api(State(state.clone())).await.unwrap();
+        assert!(api.errors.is_empty());
+
+        // The sections that depend on the AD9361 are taken from the last
+        // response, and the rest of the response is still given.
+        *state.ad9361().lock().await = crate::iio::Ad9361::with_backend(FailingRfic);
+        let Json(partial) = get_api(State(state.clone())).await.unwrap();
+        let sections = partial
+            .errors
+            .iter()
+            .map(|error| error.section)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            [
+                maia_json::ApiSection::Ad9361,
+                maia_json::ApiSection::Sensors,
+                maia_json::ApiSection::DDC,
+                maia_json::ApiSection::Spectrometer,
+            ]
+        );
+        assert!(partial.errors[0]
+            .error
+            .contains("failed to read IIO attribute"));
+        assert_eq!(partial.ad9361, api.ad9361);
+        assert_eq!(partial.recorder, api.recorder);
+    }
+
+    #[tokio::test]
+    async fn cold_start_error() {
+        use axum::{
+            http::{header, StatusCode},
+            response::IntoResponse,
+        };
+
+        // Without a last response, the client is asked to retry.
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        *state.ad9361().lock().await = crate::iio::Ad9361::with_backend(FailingRfic);
+        let response = get_api(State(state)).await.unwrap_err().into_response();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+    }
+
+    #[tokio::test]
+    async fn api_version() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let Json(api) = get_api(State(state)).await.unwrap();
+        assert_eq!(api.api_version, maia_json::API_VERSION);
+
+        // The response of a server that predates API versioning and the fields
+        // added afterwards can still be deserialized
+        let mut json = serde_json::to_value(&api).unwrap();
+        for field in [
+            "api_version",
+            "device",
+            "recording_annotations",
+            "scanner",
+            "sensors",
+            "upload",
+            "upload_target",
+        ] {
+            json.as_object_mut().unwrap().remove(field);
+        }
+        for field in [
+            "retune_mode",
+            "wall_clock_alignment",
+            "noise_floor_estimator",
+            "dc_bin_removal",
+            "software_integrations",
+            "total_integrations",
+        ] {
+            json["spectrometer"].as_object_mut().unwrap().remove(field);
+        }
+        for field in [
+            "trigger_mode",
+            "clip_mode",
+            "num_clips",
+            "buffer_size_bytes",
+            "max_duration_at_current_settings",
+        ] {
+            json["recorder"].as_object_mut().unwrap().remove(field);
+        }
+        let old: maia_json::Api = serde_json::from_value(json).unwrap();
+        assert_eq!(old.api_version, 0);
+        assert_eq!(old.ad9361, api.ad9361);
+        assert_eq!(
+            old.spectrometer.retune_mode,
+            maia_json::SpectrometerRetuneMode::Abort
+        );
+        assert_eq!(
+            old.recorder.trigger_mode,
+            maia_json::RecorderTriggerMode::Start
+        );
+    }
 }
@@ -0,0 +1,377 @@
+use super::json_error::JsonError;
+use super::list;
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Query, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use maia_json::{Audit, AuditEntry, ListQuery};
+use std::{
+    collections::{HashMap, VecDeque},
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Instant, UNIX_EPOCH},
+};
+
+// Request bodies larger than this are rejected by the audit middleware.
+const MAX_BODY_SIZE: usize = 1 << 20;
+// Request bodies longer than this are truncated in the audit log.
+const MAX_LOGGED_BODY_LENGTH: usize = 1024;
+// Maximum number of clients tracked by the rate limiter.
+const MAX_RATE_LIMITER_CLIENTS: usize = 1024;
+// Replacement for the values of secret fields in the audit log.
+const REDACTED: &str = "***";
+
+fn is_mutating(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Audit log.
+///
+/// The audit log keeps a record of the most recent requests that attempted to
+/// modify the state of the device (any request that does not use the GET, HEAD
+/// or OPTIONS methods). It is shared between the audit middleware, which
+/// records the requests, and the `/api/audit` handler. The object is cheaply
+/// clonable and clones represent a reference to a shared object.
+#[derive(Debug, Clone)]
+pub struct AuditLog(Arc<AuditLogInner>);
+
+#[derive(Debug)]
+struct AuditLogInner {
+    entries: Mutex<VecDeque<AuditEntry>>,
+    capacity: usize,
+}
+
+impl AuditLog {
+    /// Creates a new audit log.
+    ///
+    /// The log keeps at most `capacity` entries. Older entries are
+    /// discarded. If `capacity` is zero, no requests are recorded.
+    pub fn new(capacity: usize) -> AuditLog {
+        AuditLog(Arc::new(AuditLogInner {
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+        }))
+    }
+
+    fn push(&self, entry: AuditEntry) {
+        if self.0.capacity == 0 {
+            return;
+        }
+        let mut entries = self.0.entries.lock().unwrap();
+        if entries.len() == self.0.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    fn json(&self) -> Audit {
+        let entries = self.0.entries.lock().unwrap();
+        Audit {
+            entries: entries.iter().cloned().collect(),
+            total: entries.len(),
+        }
+    }
+}
+
+pub async fn get_audit(
+    State(audit_log): State<AuditLog>,
+    Query(query): Query<ListQuery>,
+) -> Result<Json<Audit>, JsonError> {
+    let mut json = audit_log.json();
+    json.total = list::paginate(&mut json.entries, &query)?;
+    Ok(Json(json))
+}
+
+pub async fn audit(
+    State(audit_log): State<AuditLog>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if audit_log.0.capacity == 0 || !is_mutating(request.method()) {
+        return next.run(request).await;
+    }
+    let time = UNIX_EPOCH
+        .elapsed()
+        .map(|t| t.as_secs_f64() * 1e3)
+        .unwrap_or_default();
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_BODY_SIZE).await {
+        Ok(body) => body,
+        Err(err) => {
+            return JsonError::from_error(
+                err,
+                StatusCode::PAYLOAD_TOO_LARGE,
+                maia_json::ErrorAction::Log,
+            )
+            .into_response()
+        }
+    };
+    let logged_body = redact_body(&body)
+        .chars()
+        .take(MAX_LOGGED_BODY_LENGTH)
+        .collect();
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let http_status_code = response.status().as_u16();
+    tracing::info!(%client, method, path, http_status_code, "audit");
+    audit_log.push(AuditEntry {
+        time,
+        client: client.to_string(),
+        method,
+        path,
+        body: logged_body,
+        http_status_code,
+    });
+    response
+}
+
+// Returns the request body as it is stored in the audit log.
+//
+// The audit log can be read without authentication, so the values of the
+// fields of JSON bodies that contain secrets, such as the password of the
+// upload target, are replaced by a placeholder. The secrets are always
+// redacted before truncating the body, so that a partial secret is never
+// logged.
+fn redact_body(body: &[u8]) -> String {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(mut json) => {
+            redact_json(&mut json);
+            json.to_string()
+        }
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    }
+}
+
+fn redact_json(json: &mut serde_json::Value) {
+    match json {
+        serde_json::Value::Object(fields) => {
+            for (name, value) in fields.iter_mut() {
+                if is_secret_field(name) {
+                    *value = REDACTED.into();
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        serde_json::Value::Array(values) => values.iter_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+fn is_secret_field(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["password", "token", "secret"]
+        .iter()
+        .any(|secret| name.contains(secret))
+}
+
+/// Per-client rate limiter.
+///
+/// The rate limiter limits the rate at which each client (identified by its IP
+/// address) can make requests that modify the state of the device. It uses a
+/// token bucket algorithm, so short bursts of requests are allowed. The object
+/// is cheaply clonable and clones represent a reference to a shared object.
+#[derive(Debug, Clone)]
+pub struct RateLimiter(Arc<RateLimiterInner>);
+
+#[derive(Debug)]
+struct RateLimiterInner {
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+    rate: f64,
+    burst: f64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Bucket {
+    tokens: f64,
+    last_update: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter.
+    ///
+    /// Each client is allowed to make `rate` mutating requests per second on
+    /// average, with bursts of up to `burst` requests.
+    pub fn new(rate: f64, burst: u32) -> Result<RateLimiter> {
+        anyhow::ensure!(rate > 0.0, "rate limit must be positive");
+        anyhow::ensure!(burst > 0, "rate limit burst must be positive");
+        Ok(RateLimiter(Arc::new(RateLimiterInner {
+            buckets: Mutex::new(HashMap::new()),
+            rate,
+            burst: f64::from(burst),
+        })))
+    }
+
+    fn allow(&self, client: IpAddr, now: Instant) -> bool {
+        let inner = &self.0;
+        let mut buckets = inner.buckets.lock().unwrap();
+        let refill = |bucket: &Bucket| {
+            let elapsed = now.saturating_duration_since(bucket.last_update);
+            (bucket.tokens + elapsed.as_secs_f64() * inner.rate).min(inner.burst)
+        };
+        if buckets.len() >= MAX_RATE_LIMITER_CLIENTS && !buckets.contains_key(&client) {
+            // Forget about clients whose bucket has been refilled completely,
+            // since they are equivalent to new clients.
+            buckets.retain(|_, bucket| refill(bucket) < inner.burst);
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: inner.burst,
+            last_update: now,
+        });
+        bucket.tokens = refill(bucket);
+        bucket.last_update = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub async fn rate_limit(
+    State(rate_limiter): State<RateLimiter>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_mutating(request.method()) && !rate_limiter.allow(client.ip(), Instant::now()) {
+        tracing::warn!(%client, "rate limit exceeded");
+        return JsonError::from_error(
+            anyhow::anyhow!("rate limit exceeded; try again later"),
+            StatusCode::TOO_MANY_REQUESTS,
+            maia_json::ErrorAction::Alert,
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+pub async fn notify_changes(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = (is_mutating(request.method()) && request.uri().path().starts_with("/api"))
+        .then(|| request.uri().path().to_string());
+    let response = next.run(request).await;
+    if let Some(path) = path {
+        if response.status().is_success() {
+            state.notifications().config_change(&path);
+        }
+    }
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::routing::{get, patch};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    #[test]
+    fn rate_limiter() {
+        let limiter = RateLimiter::new(2.0, 3).unwrap();
+        let client: IpAddr = "192.168.2.10".parse().unwrap();
+        let other: IpAddr = "192.168.2.11".parse().unwrap();
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.allow(client, now));
+        }
+        assert!(!limiter.allow(client, now));
+        // other clients are not affected
+        assert!(limiter.allow(other, now));
+        // a token is refilled after 1 / rate seconds
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.allow(client, later));
+        assert!(!limiter.allow(client, later));
+    }
+
+    #[test]
+    fn audit_log_capacity() {
+        let log = AuditLog::new(2);
+        for n in 0..3 {
+            log.push(AuditEntry {
+                time: 0.0,
+                client: "192.168.2.10:1234".to_string(),
+                method: "PATCH".to_string(),
+                path: format!("/api/{n}"),
+                body: String::new(),
+                http_status_code: 200,
+            });
+        }
+        let paths = log
+            .json()
+            .entries
+            .into_iter()
+            .map(|e| e.path)
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["/api/1", "/api/2"]);
+    }
+
+    #[test]
+    fn redacted_body() {
+        assert_eq!(
+            redact_body(br#"{"url":"https://example.com","password":"hunter2"}"#),
+            r#"{"password":"***","url":"https://example.com"}"#
+        );
+        assert_eq!(
+            redact_body(br#"{"admin_token":"abc","nested":[{"Secret":1}]}"#),
+            r#"{"admin_token":"***","nested":[{"Secret":"***"}]}"#
+        );
+        assert_eq!(redact_body(b"not json"), "not json");
+    }
+
+    #[tokio::test]
+    async fn upload_target_password() {
+        let (state, _) = crate::mock::app_state().await;
+        let audit_log = AuditLog::new(16);
+        let app = axum::Router::new()
+            .route(
+                "/api/upload-target",
+                patch(super::super::recording::upload::patch_upload_target),
+            )
+            .with_state(state)
+            .route("/api/audit", get(get_audit).with_state(audit_log.clone()))
+            .layer(axum::middleware::from_fn_with_state(
+                audit_log.clone(),
+                audit,
+            ));
+        let client: SocketAddr = "192.168.2.10:1234".parse().unwrap();
+        let mut request = Request::builder()
+            .method(Method::PATCH)
+            .uri("/api/upload-target")
+            .header(axum::http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                r#"{"protocol":"WebDAV","url":"https://example.com","password":"hunter2"}"#,
+            ))
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(client));
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let mut request = Request::builder()
+            .uri("/api/audit")
+            .body(Body::empty())
+            .unwrap();
+        request.extensions_mut().insert(ConnectInfo(client));
+        let response = app.oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let audit: Audit = serde_json::from_slice(&body).unwrap();
+        assert_eq!(audit.entries.len(), 1);
+        assert_eq!(audit.entries[0].path, "/api/upload-target");
+        assert!(!audit.entries[0].body.contains("hunter2"));
+        assert!(audit.entries[0].body.contains(r#""password":"***""#));
+    }
+}
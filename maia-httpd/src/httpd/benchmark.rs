@@ -0,0 +1,185 @@
+use super::{json_error::JsonError, recording, websocket};
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use http::header::HeaderMap;
+use maia_json::{Hertz, Seconds};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+// Held while a benchmark runs, so that only one benchmark runs at a time.
+static RUNNING: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+// Maximum duration of each of the benchmarks, in seconds.
+const MAX_DURATION: f64 = 10.0;
+// Maximum number of simulated WebSocket clients.
+const MAX_CLIENTS: usize = 64;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct BenchmarkQuery {
+    #[serde(default = "default_duration")]
+    duration: f64,
+    #[serde(default = "default_clients")]
+    clients: usize,
+}
+
+fn default_duration() -> f64 {
+    1.0
+}
+
+fn default_clients() -> usize {
+    4
+}
+
+/// Runs a synthetic benchmark of maia-httpd.
+///
+/// The benchmarks measure the spectrometer processing rate, the throughput of
+/// the waterfall WebSocket with a number of simulated clients, the recording
+/// download speed and the DDC design time. Each benchmark runs for the
+/// `duration` given in the query (in seconds), and the number of simulated
+/// WebSocket clients is given by `clients`. The benchmarks run one after the
+/// other and they load the CPU, so they can slow down the rest of maia-httpd
+/// while they run. For this reason, the request must carry the admin token,
+/// and only one benchmark can run at a time.
+pub async fn post_benchmark(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<BenchmarkQuery>,
+) -> Result<Json<maia_json::Benchmark>, JsonError> {
+    state.device().authorize_admin(&headers)?;
+    let Ok(_running) = RUNNING.try_lock() else {
+        return Err(JsonError::conflict(anyhow::anyhow!(
+            "a benchmark is already running"
+        )));
+    };
+    if !(query.duration > 0.0 && query.duration <= MAX_DURATION) {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "duration must be positive and at most {MAX_DURATION} seconds"
+        )));
+    }
+    if !(1..=MAX_CLIENTS).contains(&query.clients) {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "clients must be between 1 and {MAX_CLIENTS}"
+        )));
+    }
+    let duration = Duration::from_secs_f64(query.duration);
+    tracing::info!(?duration, clients = query.clients, "running benchmark");
+    let spectrometer_rate =
+        tokio::task::spawn_blocking(move || crate::spectrometer::processing_rate(duration))
+            .await
+            .map_err(JsonError::server_error)?;
+    let websocket = websocket::throughput(query.clients, duration).await;
+    let recording_download_speed = recording::download_speed(&state, duration)
+        .await
+        .map_err(JsonError::server_error)?;
+    let ddc_design_time = tokio::task::spawn_blocking(ddc_design_time)
+        .await
+        .map_err(JsonError::server_error)?
+        .map_err(JsonError::server_error)?;
+    Ok(Json(maia_json::Benchmark {
+        duration: Seconds(query.duration),
+        spectrometer_rate,
+        websocket_clients: query.clients,
+        websocket_rate: websocket.rate,
+        websocket_throughput: websocket.bytes_per_second,
+        recording_download_speed,
+        ddc_design_time: Seconds(ddc_design_time),
+    }))
+}
+
+// Returns the time needed to calculate the default DDC design, in seconds.
+fn ddc_design_time() -> Result<f64> {
+    let start = Instant::now();
+    crate::ddc::make_design(
+        &maia_json::PutDDCDesign {
+            frequency: Hertz(0.0),
+            decimation: 20,
+            transition_bandwidth: None,
+            passband_ripple: None,
+            stopband_attenuation_db: None,
+            stopband_one_over_f: None,
+        },
+        Hertz(61.44e6),
+    )?;
+    Ok(start.elapsed().as_secs_f64())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse};
+    use http::header::AUTHORIZATION;
+
+    #[tokio::test]
+    async fn benchmark() {
+        let query = BenchmarkQuery {
+            duration: 0.05,
+            clients: 2,
+        };
+        // no token given in the command line
+        let (state, _) = crate::mock::app_state().await;
+        assert!(
+            post_benchmark(State(state), HeaderMap::new(), Query(query.clone()))
+                .await
+                .is_err()
+        );
+
+        let (state, _) = crate::mock::app_state_with_args(&["--admin-token", "secret"]).await;
+        assert_eq!(
+            post_benchmark(State(state.clone()), HeaderMap::new(), Query(query.clone()))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        // only one benchmark can run at a time
+        let running = RUNNING.try_lock().unwrap();
+        assert_eq!(
+            post_benchmark(State(state.clone()), headers.clone(), Query(query.clone()))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::CONFLICT
+        );
+        drop(running);
+        let Json(json) =
+            post_benchmark(State(state.clone()), headers.clone(), Query(query.clone()))
+                .await
+                .unwrap();
+        assert_eq!(json.duration, Seconds(0.05));
+        assert!(json.spectrometer_rate > 0.0);
+        assert_eq!(json.websocket_clients, 2);
+        assert!(json.websocket_rate > 0.0);
+        assert!(json.websocket_throughput > 0.0);
+        assert!(json.recording_download_speed.unwrap() > 0.0);
+        assert!(json.ddc_design_time > Seconds(0.0));
+
+        for query in [
+            BenchmarkQuery {
+                duration: 0.0,
+                ..query.clone()
+            },
+            BenchmarkQuery {
+                duration: 60.0,
+                ..query.clone()
+            },
+            BenchmarkQuery {
+                clients: 0,
+                ..query.clone()
+            },
+        ] {
+            assert!(
+                post_benchmark(State(state.clone()), headers.clone(), Query(query))
+                    .await
+                    .is_err()
+            );
+        }
+    }
+}
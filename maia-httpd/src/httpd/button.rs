@@ -0,0 +1,316 @@
+use super::{recording, StartupProfile};
+use crate::{app::AppState, args::Args};
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Period with which the button GPIO is polled.
+const POLL_PERIOD: Duration = Duration::from_millis(20);
+// Number of consecutive polls with the same value required to accept a change
+// of the button state.
+const DEBOUNCE_POLLS: u32 = 3;
+// Number of polls that the button must be held to make a long press (2 s).
+const LONG_PRESS_POLLS: u32 = 100;
+// Number of times that the LED flashes to acknowledge a long press, and
+// duration of each flash.
+const LED_FLASHES: usize = 3;
+const LED_FLASH_DURATION: Duration = Duration::from_millis(100);
+
+/// Push button runner.
+///
+/// This struct polls a push button connected to a GPIO. A short press of the
+/// button starts or stops a recording, and a long press applies the
+/// [`StartupProfile`]. Optionally, an LED shows the state of the recorder. It
+/// implements a [`run`](ButtonRunner::run) async method that should be run
+/// concurrently with the rest of the application.
+#[derive(Debug)]
+pub struct ButtonRunner {
+    state: AppState,
+    gpio: Option<PathBuf>,
+    active_low: bool,
+    led: Option<PathBuf>,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum Press {
+    Short,
+    Long,
+}
+
+// Debounces the button and detects short and long presses.
+#[derive(Debug, Default)]
+struct PressDetector {
+    pressed: bool,
+    changed_polls: u32,
+    held_polls: u32,
+    long_press: bool,
+}
+
+impl PressDetector {
+    // Updates the detector with a new value of the button, which is `true` if
+    // the button is pressed. A short press is reported when the button is
+    // released, and a long press is reported as soon as the button has been
+    // held for long enough.
+    fn update(&mut self, pressed: bool) -> Option<Press> {
+        if pressed == self.pressed {
+            self.changed_polls = 0;
+            if self.pressed && !self.long_press {
+                self.held_polls += 1;
+                if self.held_polls >= LONG_PRESS_POLLS {
+                    self.long_press = true;
+                    return Some(Press::Long);
+                }
+            }
+            return None;
+        }
+        self.changed_polls += 1;
+        if self.changed_polls < DEBOUNCE_POLLS {
+            return None;
+        }
+        self.pressed = pressed;
+        self.changed_polls = 0;
+        self.held_polls = 0;
+        let long_press = std::mem::take(&mut self.long_press);
+        (!pressed && !long_press).then_some(Press::Short)
+    }
+}
+
+impl ButtonRunner {
+    /// Creates a new push button runner.
+    ///
+    /// The GPIO, its polarity and the LED are given in the `args`. This
+    /// function only creates the object. The [`run`](ButtonRunner::run)
+    /// method needs to be called afterwards.
+    pub fn new(state: AppState, args: &Args) -> ButtonRunner {
+        ButtonRunner {
+            state,
+            gpio: args.button_gpio.clone(),
+            active_low: args.button_active_low,
+            led: args.button_led.clone(),
+        }
+    }
+
+    /// Runs the push button runner.
+    ///
+    /// This function only returns if the GPIO cannot be read when it starts.
+    /// Errors reading the GPIO or setting the LED afterwards are logged, and the
+    /// GPIO keeps being polled. If no GPIO has been given, the function never
+    /// returns.
+    #[tracing::instrument(name = "button", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let Some(gpio) = &self.gpio else {
+            return std::future::pending().await;
+        };
+        tokio::fs::read_to_string(gpio)
+            .await
+            .context("failed to read button GPIO")?;
+        let mut led_errors = ErrorLog::new("button LED");
+        if let Some(led) = &self.led {
+            // The brightness of the LED is controlled manually.
+            led_errors.log(
+                tokio::fs::write(led.join("trigger"), "none")
+                    .await
+                    .context("failed to set button LED trigger"),
+            );
+        }
+        let mut gpio_errors = ErrorLog::new("button GPIO");
+        let mut detector = PressDetector::default();
+        let mut led_on = None;
+        let mut interval = tokio::time::interval(POLL_PERIOD);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            let Some(value) = gpio_errors.log(
+                tokio::fs::read_to_string(gpio)
+                    .await
+                    .context("failed to read button GPIO"),
+            ) else {
+                continue;
+            };
+            let pressed = (value.trim() == "1") != self.active_low;
+            match detector.update(pressed) {
+                Some(Press::Short) => {
+                    if let Err(err) = toggle_recording(&self.state).await {
+                        tracing::error!("failed to start or stop recording: {err:#}");
+                    }
+                }
+                Some(Press::Long) => {
+                    tracing::info!("applying startup profile");
+                    if let Err(err) = StartupProfile::apply(&self.state).await {
+                        tracing::error!("{err:#}");
+                    }
+                    if let Some(led) = &self.led {
+                        led_errors.log(flash_led(led).await);
+                        led_on = None;
+                    }
+                }
+                None => (),
+            }
+            if let Some(led) = &self.led {
+                let recording = !self.state.recorder().is_stopped().await;
+                if led_on != Some(recording)
+                    && led_errors.log(set_led(led, recording).await).is_some()
+                {
+                    led_on = Some(recording);
+                }
+            }
+        }
+    }
+}
+
+// Logs the errors of a GPIO or LED that is accessed on each poll. Only the
+// first of a series of consecutive errors is logged, so that a missing sysfs
+// file does not flood the log.
+#[derive(Debug)]
+struct ErrorLog {
+    name: &'static str,
+    failing: bool,
+}
+
+impl ErrorLog {
+    fn new(name: &'static str) -> ErrorLog {
+        ErrorLog {
+            name,
+            failing: false,
+        }
+    }
+
+    // Logs the error of a result, returning its value if there was no error.
+    fn log<T>(&mut self, result: Result<T>) -> Option<T> {
+        match result {
+            Ok(value) => {
+                if std::mem::take(&mut self.failing) {
+                    tracing::info!("{} working again", self.name);
+                }
+                Some(value)
+            }
+            Err(err) => {
+                if !std::mem::replace(&mut self.failing, true) {
+                    tracing::error!("{err:#}");
+                }
+                None
+            }
+        }
+    }
+}
+
+// Starts a recording if the recorder is stopped, or stops it otherwise.
+async fn toggle_recording(state: &AppState) -> Result<()> {
+    let state_change = if state.recorder().is_stopped().await {
+        tracing::info!("starting recording");
+        maia_json::RecorderStateChange::Start
+    } else {
+        tracing::info!("stopping recording");
+        maia_json::RecorderStateChange::Stop
+    };
+    let Json(_) = recording::patch_recorder(
+        State(state.clone()),
+        Json(maia_json::PatchRecorder {
+            state_change: Some(state_change),
+            ..Default::default()
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn set_led(led: &Path, on: bool) -> Result<()> {
+    tokio::fs::write(led.join("brightness"), if on { "1" } else { "0" })
+        .await
+        .context("failed to set button LED")
+}
+
+async fn flash_led(led: &Path) -> Result<()> {
+    for _ in 0..LED_FLASHES {
+        for on in [true, false] {
+            set_led(led, on).await?;
+            tokio::time::sleep(LED_FLASH_DURATION).await;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn press_detector() {
+        let mut detector = PressDetector::default();
+        let mut update = |pressed, polls| {
+            (0..polls)
+                .filter_map(|_| detector.update(pressed))
+                .collect::<Vec<_>>()
+        };
+        // A glitch shorter than the debounce time is ignored.
+        assert!(update(true, DEBOUNCE_POLLS - 1).is_empty());
+        assert!(update(false, 10).is_empty());
+        // Short press
+        assert!(update(true, 10).is_empty());
+        assert_eq!(update(false, 10), [Press::Short]);
+        // Long press, which is reported only once and not followed by a
+        // short press on release.
+        assert_eq!(update(true, 2 * LONG_PRESS_POLLS), [Press::Long]);
+        assert!(update(false, 10).is_empty());
+    }
+
+    #[test]
+    fn error_log() {
+        let mut log = ErrorLog::new("test");
+        assert_eq!(log.log(Ok(1)), Some(1));
+        assert!(!log.failing);
+        assert_eq!(log.log::<()>(Err(anyhow::anyhow!("error"))), None);
+        assert!(log.failing);
+        assert_eq!(log.log(Ok(2)), Some(2));
+        assert!(!log.failing);
+    }
+
+    #[tokio::test]
+    async fn gpio_errors() {
+        let (state, _) = crate::mock::app_state().await;
+        let gpio =
+            std::env::temp_dir().join(format!("maia-httpd-button-gpio-{}", std::process::id()));
+        let runner = |state: &AppState| {
+            ButtonRunner::new(
+                state.clone(),
+                &Args {
+                    button_gpio: Some(gpio.clone()),
+                    ..Default::default()
+                },
+            )
+        };
+        // the GPIO must be readable when the runner starts
+        assert!(runner(&state).run().await.is_err());
+        tokio::fs::write(&gpio, "0\n").await.unwrap();
+        let task = tokio::spawn(runner(&state).run());
+        tokio::time::sleep(4 * POLL_PERIOD).await;
+        // read errors after the start do not stop the runner
+        tokio::fs::remove_file(&gpio).await.unwrap();
+        tokio::time::sleep(4 * POLL_PERIOD).await;
+        assert!(!task.is_finished());
+        task.abort();
+    }
+
+    #[tokio::test]
+    async fn toggle() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        let recorder_finish = recording::RecorderFinishWaiter::new(
+            state.clone(),
+            interrupt_handler.waiter_recorder(),
+            Default::default(),
+        );
+        tokio::spawn(interrupt_handler.run());
+        tokio::spawn(recorder_finish.run());
+        toggle_recording(&state).await.unwrap();
+        assert!(!state.recorder().is_stopped().await);
+        toggle_recording(&state).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !state.recorder().is_stopped().await {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+}
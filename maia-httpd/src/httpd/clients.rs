@@ -0,0 +1,275 @@
+use super::{json_error::JsonError, list};
+use crate::app::AppState;
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message},
+        Path, Query, State,
+    },
+    Json,
+};
+use maia_json::Seconds;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Instant, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+/// WebSocket clients.
+///
+/// This struct is part of the [`AppState`]. It keeps track of the clients
+/// connected to the WebSockets, so that they can be listed and disconnected
+/// through `/api/clients`.
+#[derive(Debug, Default)]
+pub struct WebSocketClients(Mutex<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    clients: BTreeMap<u64, Client>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct Client {
+    websocket: maia_json::WebSocketKind,
+    address: Option<SocketAddr>,
+    protocol: Option<String>,
+    connect_time: f64,
+    start: Instant,
+    counters: Arc<Counters>,
+    disconnect: CancellationToken,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    messages: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Client {
+    fn json(&self, id: u64) -> maia_json::WebSocketClient {
+        let bytes_sent = self.counters.bytes.load(Ordering::Relaxed);
+        let duration = self.start.elapsed().as_secs_f64();
+        maia_json::WebSocketClient {
+            id,
+            websocket: self.websocket,
+            client: self.address.map(|address| address.to_string()),
+            protocol: self.protocol.clone(),
+            connect_time: self.connect_time,
+            duration: Seconds(duration),
+            messages_sent: self.counters.messages.load(Ordering::Relaxed),
+            bytes_sent,
+            rate: if duration > 0.0 {
+                bytes_sent as f64 / duration
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl WebSocketClients {
+    /// Creates an empty list of WebSocket clients.
+    pub fn new() -> WebSocketClients {
+        WebSocketClients::default()
+    }
+
+    fn json(&self) -> maia_json::WebSocketClients {
+        let inner = self.0.lock().unwrap();
+        maia_json::WebSocketClients {
+            clients: inner
+                .clients
+                .iter()
+                .map(|(&id, client)| client.json(id))
+                .collect(),
+            total: inner.clients.len(),
+        }
+    }
+
+    // Requests a client to disconnect. Returns the client, or None if there is
+    // no client with this id.
+    fn disconnect(&self, id: u64) -> Option<maia_json::WebSocketClient> {
+        let inner = self.0.lock().unwrap();
+        let client = inner.clients.get(&id)?;
+        client.disconnect.cancel();
+        Some(client.json(id))
+    }
+}
+
+/// Registration of a client connected to a WebSocket.
+///
+/// The client is listed in `/api/clients` until the registration is dropped.
+/// The WebSocket handler counts the messages that it sends with
+/// [`ClientRegistration::count`], and it closes the WebSocket when the
+/// [`ClientRegistration::disconnect`] token is cancelled, which happens when
+/// the client is disconnected through the API or when maia-httpd shuts down.
+#[derive(Debug)]
+pub struct ClientRegistration {
+    state: AppState,
+    id: u64,
+    counters: Arc<Counters>,
+    disconnect: CancellationToken,
+}
+
+impl ClientRegistration {
+    /// Counts a message sent to the client.
+    pub fn count(&self, message: &Message) {
+        let len = match message {
+            Message::Text(text) => text.len(),
+            Message::Binary(data) => data.len(),
+            _ => 0,
+        };
+        self.counters.messages.fetch_add(1, Ordering::Relaxed);
+        self.counters.bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the token that is cancelled when the client must be
+    /// disconnected.
+    pub fn disconnect(&self) -> &CancellationToken {
+        &self.disconnect
+    }
+}
+
+impl Drop for ClientRegistration {
+    fn drop(&mut self) {
+        self.state
+            .websocket_clients()
+            .0
+            .lock()
+            .unwrap()
+            .clients
+            .remove(&self.id);
+    }
+}
+
+/// Registers a client that has connected to a WebSocket.
+///
+/// The `address` of the client is `None` if it is not known. The `protocol` is
+/// the WebSocket subprotocol used by the client, if any.
+pub fn register(
+    state: &AppState,
+    websocket: maia_json::WebSocketKind,
+    address: Option<SocketAddr>,
+    protocol: Option<&str>,
+) -> ClientRegistration {
+    let counters = Arc::new(Counters::default());
+    // The disconnect token is a child of the closing token, so that it is also
+    // cancelled when maia-httpd shuts down.
+    let disconnect = state.shutdown().closing().child_token();
+    let mut inner = state.websocket_clients().0.lock().unwrap();
+    let id = inner.next_id;
+    inner.next_id += 1;
+    inner.clients.insert(
+        id,
+        Client {
+            websocket,
+            address,
+            protocol: protocol.map(|protocol| protocol.to_string()),
+            connect_time: UNIX_EPOCH.elapsed().map_or(0.0, |t| t.as_secs_f64() * 1e3),
+            start: Instant::now(),
+            counters: Arc::clone(&counters),
+            disconnect: disconnect.clone(),
+        },
+    );
+    ClientRegistration {
+        state: state.clone(),
+        id,
+        counters,
+        disconnect,
+    }
+}
+
+/// Returns the close message sent to the WebSocket clients that are
+/// disconnected through the API.
+pub fn disconnect_close_message() -> Message {
+    Message::Close(Some(CloseFrame {
+        code: close_code::POLICY,
+        reason: "disconnected by the operator".into(),
+    }))
+}
+
+pub async fn get_clients(
+    State(state): State<AppState>,
+    Query(query): Query<maia_json::ListQuery>,
+) -> Result<Json<maia_json::WebSocketClients>, JsonError> {
+    let mut json = state.websocket_clients().json();
+    json.total = list::paginate(&mut json.clients, &query)?;
+    Ok(Json(json))
+}
+
+/// Disconnects a WebSocket client.
+///
+/// The WebSocket is closed with a close message once the message that is being
+/// sent to the client, if any, has been sent.
+pub async fn delete_client(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<maia_json::WebSocketClient>, JsonError> {
+    let client = state
+        .websocket_clients()
+        .disconnect(id)
+        .ok_or_else(|| JsonError::not_found(anyhow::anyhow!("client {id} does not exist")))?;
+    tracing::info!(
+        id,
+        client = client.client.as_deref().unwrap_or("unknown"),
+        "disconnecting websocket client"
+    );
+    Ok(Json(client))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn clients() {
+        let (state, _) = crate::mock::app_state().await;
+        let address = "192.168.2.10:51236".parse().unwrap();
+        let waterfall = register(
+            &state,
+            maia_json::WebSocketKind::Waterfall,
+            Some(address),
+            Some(maia_json::WATERFALL_PROTOCOL_V4),
+        );
+        let peaks = register(&state, maia_json::WebSocketKind::Peaks, None, None);
+        waterfall.count(&Message::Binary(vec![0; 100]));
+        waterfall.count(&Message::Text("{}".to_string()));
+
+        let Json(json) = get_clients(State(state.clone()), Query(Default::default()))
+            .await
+            .unwrap();
+        assert_eq!(json.total, 2);
+        let client = &json.clients[0];
+        assert_eq!(client.websocket, maia_json::WebSocketKind::Waterfall);
+        assert_eq!(client.client.as_deref(), Some("192.168.2.10:51236"));
+        assert_eq!(
+            client.protocol.as_deref(),
+            Some(maia_json::WATERFALL_PROTOCOL_V4)
+        );
+        assert_eq!(client.messages_sent, 2);
+        assert_eq!(client.bytes_sent, 102);
+        assert_eq!(json.clients[1].client, None);
+
+        let Json(deleted) = delete_client(State(state.clone()), Path(json.clients[1].id))
+            .await
+            .unwrap();
+        assert_eq!(deleted.websocket, maia_json::WebSocketKind::Peaks);
+        assert!(peaks.disconnect().is_cancelled());
+        assert!(!waterfall.disconnect().is_cancelled());
+        assert!(delete_client(State(state.clone()), Path(100))
+            .await
+            .is_err());
+
+        // the client is removed from the list when it disconnects
+        drop(peaks);
+        let Json(json) = get_clients(State(state.clone()), Query(Default::default()))
+            .await
+            .unwrap();
+        assert_eq!(json.total, 1);
+        // shutting down disconnects all the clients
+        state.shutdown().closing().cancel();
+        assert!(waterfall.disconnect().is_cancelled());
+    }
+}
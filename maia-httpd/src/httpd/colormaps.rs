@@ -0,0 +1,116 @@
+use super::json_error::JsonError;
+use crate::{app::AppState, colormap::custom};
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use maia_json::ColormapParameters;
+
+fn not_found(name: &str) -> JsonError {
+    JsonError::not_found(anyhow::anyhow!("colormap {name} does not exist"))
+}
+
+pub async fn get_colormaps(State(state): State<AppState>) -> Json<maia_json::Colormaps> {
+    Json(state.custom_colormaps().json())
+}
+
+/// Generates a colormap from the parameters given in the query.
+///
+/// The colormap is not stored. It can be used to preview the parameters
+/// before storing them as a custom colormap.
+pub async fn get_generate_colormap(
+    Query(parameters): Query<ColormapParameters>,
+) -> Result<Json<maia_json::Colormap>, JsonError> {
+    parameters
+        .validate()
+        .map_err(JsonError::client_error_alert)?;
+    Ok(Json(custom::colormap_json(None, &parameters)))
+}
+
+pub async fn get_colormap(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Colormap>, JsonError> {
+    state
+        .custom_colormaps()
+        .colormap_json(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+pub async fn put_colormap(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(put): Json<ColormapParameters>,
+) -> Result<Json<maia_json::Colormap>, JsonError> {
+    let colormaps = state.custom_colormaps();
+    colormaps
+        .check(&name, &put)
+        .map_err(JsonError::client_error_alert)?;
+    colormaps
+        .set(&name, put)
+        .await
+        .map(Json)
+        .map_err(JsonError::server_error)
+}
+
+pub async fn delete_colormap(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Colormap>, JsonError> {
+    state
+        .custom_colormaps()
+        .remove(&name)
+        .await
+        .map_err(JsonError::server_error)?
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn colormaps() {
+        let (state, _) = crate::mock::app_state().await;
+        let parameters = ColormapParameters {
+            start_hue: 30.0,
+            end_hue: -90.0,
+            gamma: 0.8,
+        };
+        let Json(generated) = get_generate_colormap(Query(parameters.clone()))
+            .await
+            .unwrap();
+        assert_eq!(generated.name, None);
+        assert_eq!(generated.colors.len(), custom::NUM_COLORS);
+        assert!(get_generate_colormap(Query(ColormapParameters {
+            gamma: 100.0,
+            ..parameters.clone()
+        }))
+        .await
+        .is_err());
+
+        assert!(get_colormap(State(state.clone()), Path("warm".to_string()))
+            .await
+            .is_err());
+        let Json(colormap) = put_colormap(
+            State(state.clone()),
+            Path("warm".to_string()),
+            Json(parameters),
+        )
+        .await
+        .unwrap();
+        assert_eq!(colormap.name.as_deref(), Some("warm"));
+        assert_eq!(colormap.colors, generated.colors);
+        let Json(colormaps) = get_colormaps(State(state.clone())).await;
+        assert_eq!(colormaps.colormaps, vec![colormap.clone()]);
+        let Json(deleted) = delete_colormap(State(state.clone()), Path("warm".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(deleted, colormap);
+        assert!(delete_colormap(State(state), Path("warm".to_string()))
+            .await
+            .is_err());
+    }
+}
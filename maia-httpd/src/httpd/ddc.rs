@@ -1,8 +1,11 @@
 use super::json_error::JsonError;
-use crate::{app::AppState, ddc};
+use crate::{app::AppState, ddc, spectrometer};
 use anyhow::Result;
 use axum::{extract::State, Json};
-use maia_json::{DDCConfig, DDCConfigSummary, PatchDDCConfig, PutDDCConfig, PutDDCDesign};
+use maia_json::{
+    DDCConfig, DDCConfigSummary, DDCDesign, DDCErrorBudget, DDCNoiseBlanker, DDCSnr,
+    PatchDDCConfig, PatchDDCNoiseBlanker, PutDDCConfig, PutDDCDesign, SpectrometerInput,
+};
 
 async fn ddc_config(state: &AppState) -> Result<Json<DDCConfig>, JsonError> {
     let samp_rate = state
@@ -25,7 +28,10 @@ pub async fn get_ddc_config(State(state): State<AppState>) -> Result<Json<DDCCon
     ddc_config(&state).await
 }
 
-async fn set_ddc_config(state: &AppState, config: PutDDCConfig) -> Result<(), JsonError> {
+pub(super) async fn set_ddc_config(
+    state: &AppState,
+    config: PutDDCConfig,
+) -> Result<(), JsonError> {
     let samp_rate = state
         .ad9361_samp_rate()
         .await
@@ -55,27 +61,35 @@ pub async fn patch_ddc_config(
             .ad9361_samp_rate()
             .await
             .map_err(JsonError::server_error)?;
-        state
-            .ip_core()
-            .lock()
-            .unwrap()
+        let mut ip_core = state.ip_core().lock().unwrap();
+        ip_core
             .set_ddc_frequency(frequency, samp_rate)
             .map_err(JsonError::client_error_alert)?;
+        if matches!(
+            ip_core.spectrometer_input(),
+            maia_json::SpectrometerInput::DDC
+        ) {
+            state.spectrometer_config().retune(&ip_core);
+        }
     }
     ddc_config(&state).await
 }
 
-async fn set_ddc_design(state: &AppState, design: PutDDCDesign) -> Result<(), JsonError> {
+async fn set_ddc_design(
+    state: &AppState,
+    design: PutDDCDesign,
+) -> Result<DDCErrorBudget, JsonError> {
     let samp_rate = state
         .ad9361_samp_rate()
         .await
         .map_err(JsonError::server_error)?;
     // The DDC design can take a couple seconds to calculate, so it is run in a
     // blocking thread.
-    let config = tokio::task::spawn_blocking(move || ddc::make_design(&design, samp_rate))
-        .await
-        .map_err(JsonError::server_error)?
-        .map_err(JsonError::client_error_alert)?;
+    let (config, error_budget) =
+        tokio::task::spawn_blocking(move || ddc::make_design_with_error_budget(&design, samp_rate))
+            .await
+            .map_err(JsonError::server_error)?
+            .map_err(JsonError::client_error_alert)?;
     state
         .ip_core()
         .lock()
@@ -83,13 +97,194 @@ async fn set_ddc_design(state: &AppState, design: PutDDCDesign) -> Result<(), Js
         .set_ddc_config(&config, samp_rate)
         // If the design was successful, this call should succeed. If it doesn't
         // it's a server error, not a client parameters error.
-        .map_err(JsonError::server_error)
+        .map_err(JsonError::server_error)?;
+    Ok(error_budget)
 }
 
 pub async fn put_ddc_design(
     State(state): State<AppState>,
     Json(put): Json<PutDDCDesign>,
-) -> Result<Json<DDCConfig>, JsonError> {
-    set_ddc_design(&state, put).await?;
-    ddc_config(&state).await
+) -> Result<Json<DDCDesign>, JsonError> {
+    let error_budget = set_ddc_design(&state, put).await?;
+    let Json(config) = ddc_config(&state).await?;
+    Ok(Json(DDCDesign {
+        config,
+        error_budget,
+    }))
+}
+
+pub async fn get_ddc_snr(State(state): State<AppState>) -> Result<Json<DDCSnr>, JsonError> {
+    let samp_rate = state
+        .ad9361_samp_rate()
+        .await
+        .map_err(JsonError::server_error)?;
+    let (input, ddc) = {
+        let ip_core = state.ip_core().lock().unwrap();
+        (
+            ip_core.spectrometer_input(),
+            ip_core.ddc_config_summary(samp_rate),
+        )
+    };
+    if !matches!(input, SpectrometerInput::AD9361) {
+        return Err(JsonError::client_error(anyhow::anyhow!(
+            "the spectrometer input must be the AD9361 to estimate the DDC SNR"
+        )));
+    }
+    let spectrum = state
+        .last_spectrum()
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| {
+            JsonError::service_unavailable(anyhow::anyhow!("no spectrum available yet"))
+        })?;
+    let spectrum = spectrometer::spectrum_from_bytes(&spectrum);
+    spectrometer::estimate_snr(
+        &spectrum,
+        samp_rate.0,
+        ddc.frequency.0,
+        ddc.output_sampling_frequency.0,
+    )
+    .map(Json)
+    .ok_or_else(|| {
+        JsonError::client_error(anyhow::anyhow!(
+            "the DDC channel does not fit in the spectrometer band"
+        ))
+    })
+}
+
+pub async fn get_noise_blanker(State(state): State<AppState>) -> Json<DDCNoiseBlanker> {
+    Json(state.noise_blanker().lock().unwrap().clone())
+}
+
+pub async fn patch_noise_blanker(
+    State(state): State<AppState>,
+    Json(patch): Json<PatchDDCNoiseBlanker>,
+) -> Result<Json<DDCNoiseBlanker>, JsonError> {
+    let mut noise_blanker = state.noise_blanker().lock().unwrap();
+    let mut config = noise_blanker.clone();
+    if let Some(live) = patch.live {
+        config.live = live;
+    }
+    if let Some(processing) = patch.processing {
+        config.processing = processing;
+    }
+    if let Some(threshold_db) = patch.threshold_db {
+        config.threshold_db = threshold_db;
+    }
+    if let Some(blanking_duration) = patch.blanking_duration {
+        config.blanking_duration = blanking_duration;
+    }
+    ddc::noise_blanker::check_config(&config).map_err(JsonError::client_error_alert)?;
+    *noise_blanker = config.clone();
+    Ok(Json(config))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::{Decibels, Hertz, Seconds};
+
+    #[tokio::test]
+    async fn ddc_frequency() {
+        let (state, _) = crate::mock::app_state().await;
+        let patch = |frequency| {
+            patch_ddc_config(
+                State(state.clone()),
+                Json(PatchDDCConfig {
+                    frequency: Some(frequency),
+                }),
+            )
+        };
+        let Json(config) = patch(Hertz(1e6)).await.unwrap();
+        assert_eq!(config.frequency, Hertz(1e6));
+        // out of range for the AD9361 sample rate
+        assert!(patch(Hertz(40e6)).await.is_err());
+        let Json(config) = get_ddc_config(State(state.clone())).await.unwrap();
+        assert_eq!(config.frequency, Hertz(1e6));
+    }
+
+    #[tokio::test]
+    async fn snr_without_spectrum() {
+        use axum::{http::StatusCode, response::IntoResponse};
+
+        let (state, _) = crate::mock::app_state().await;
+        let err = get_ddc_snr(State(state.clone())).await.unwrap_err();
+        assert_eq!(
+            err.into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let spectrum = std::iter::repeat_n(1.0f32, 4096)
+            .flat_map(|x| x.to_ne_bytes())
+            .collect();
+        *state.last_spectrum().lock().unwrap() = Some(spectrum);
+        let Json(snr) = get_ddc_snr(State(state)).await.unwrap();
+        assert_eq!(snr.snr_db, None);
+    }
+
+    #[tokio::test]
+    async fn transition_bandwidth() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(config) = get_ddc_config(State(state.clone())).await.unwrap();
+        assert_eq!(
+            config.transition_bandwidth,
+            Some(ddc::DEFAULT_TRANSITION_BANDWIDTH)
+        );
+        let Json(api) = crate::httpd::api::get_api(State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(api.ddc.transition_bandwidth, config.transition_bandwidth);
+        // FIR filters that are not designed by maia-httpd
+        let put = PutDDCConfig {
+            frequency: config.frequency,
+            fir1: config.fir1,
+            fir2: config.fir2,
+            fir3: config.fir3,
+            transition_bandwidth: None,
+        };
+        let Json(config) = put_ddc_config(State(state.clone()), Json(put.clone()))
+            .await
+            .unwrap();
+        assert_eq!(config.transition_bandwidth, None);
+        // a stored design keeps its transition bandwidth
+        let put = PutDDCConfig {
+            transition_bandwidth: Some(0.1),
+            ..put
+        };
+        let Json(config) = put_ddc_config(State(state), Json(put)).await.unwrap();
+        assert_eq!(config.transition_bandwidth, Some(0.1));
+    }
+
+    #[tokio::test]
+    async fn noise_blanker() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(config) = get_noise_blanker(State(state.clone())).await;
+        assert!(!config.live && !config.processing);
+        let Json(config) = patch_noise_blanker(
+            State(state.clone()),
+            Json(PatchDDCNoiseBlanker {
+                live: Some(true),
+                threshold_db: Some(Decibels(20.0)),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(config.live);
+        assert_eq!(config.threshold_db, Decibels(20.0));
+        assert!(patch_noise_blanker(
+            State(state.clone()),
+            Json(PatchDDCNoiseBlanker {
+                processing: Some(true),
+                blanking_duration: Some(Seconds(-1.0)),
+                ..Default::default()
+            }),
+        )
+        .await
+        .is_err());
+        // an invalid patch does not modify the configuration
+        let Json(config) = get_noise_blanker(State(state.clone())).await;
+        assert!(!config.processing);
+        assert_eq!(config.blanking_duration, Seconds(100e-6));
+    }
 }
@@ -0,0 +1,90 @@
+use super::json_error::JsonError;
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use std::net::SocketAddr;
+
+fn not_found(name: &str) -> JsonError {
+    JsonError::not_found(anyhow::anyhow!("demodulator {name} does not exist"))
+}
+
+pub async fn get_demodulators(State(state): State<AppState>) -> Json<maia_json::Demodulators> {
+    Json(state.demodulators().json())
+}
+
+pub async fn get_demodulator(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Demodulator>, JsonError> {
+    state
+        .demodulators()
+        .instance_json(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+pub async fn put_demodulator(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(put): Json<maia_json::PutDemodulator>,
+) -> Result<Json<maia_json::Demodulator>, JsonError> {
+    let udp_address: SocketAddr = put.udp_address.parse().map_err(|_| {
+        JsonError::client_error_alert(anyhow::anyhow!("invalid UDP address {}", put.udp_address))
+    })?;
+    state
+        .demodulators()
+        .start(&name, &put.kind, udp_address)
+        .map(Json)
+        .map_err(JsonError::client_error_alert)
+}
+
+pub async fn delete_demodulator(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Demodulator>, JsonError> {
+    state
+        .demodulators()
+        .stop(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn put_delete() {
+        let (state, _) = crate::mock::app_state().await;
+        let put = |kind: &str, udp_address: &str| {
+            put_demodulator(
+                State(state.clone()),
+                Path("radio".to_string()),
+                Json(maia_json::PutDemodulator {
+                    kind: kind.to_string(),
+                    udp_address: udp_address.to_string(),
+                }),
+            )
+        };
+        assert!(put("am", "localhost").await.is_err());
+        assert!(put("ssb", "127.0.0.1:7355").await.is_err());
+        let Json(demodulator) = put("wfm", "127.0.0.1:7355").await.unwrap();
+        assert_eq!(demodulator.kind, "wfm");
+        let Json(demodulators) = get_demodulators(State(state.clone())).await;
+        assert_eq!(demodulators.instances, std::slice::from_ref(&demodulator));
+        let Json(deleted) = delete_demodulator(State(state.clone()), Path("radio".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(deleted, demodulator);
+        let err = get_demodulator(State(state.clone()), Path("radio".to_string()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            axum::response::IntoResponse::into_response(err).status(),
+            http::StatusCode::NOT_FOUND
+        );
+    }
+}
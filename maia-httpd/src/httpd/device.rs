@@ -0,0 +1,415 @@
+use super::json_error::JsonError;
+use crate::{app::AppState, args::Args, shutdown::PowerAction};
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use http::header::{HeaderMap, AUTHORIZATION};
+use maia_json::{Device, Hertz, Identify, PatchDevice, Power, Seconds};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+const MAX_IDENTIFY_DURATION: Seconds = Seconds(60.0);
+const IDENTIFY_BLINK_PERIOD_MS: u32 = 100;
+
+/// Device state.
+///
+/// This struct contains the settings that identify the device, such as its
+/// name and the frequency offset of an external converter, the state needed
+/// to make the device blink its LED to identify it, and the tokens that
+/// authorize powering off or rebooting the device and administrative requests.
+#[derive(Debug)]
+pub struct DeviceState {
+    name: Mutex<String>,
+    frequency_offset: Mutex<Hertz>,
+    name_file: Option<PathBuf>,
+    avahi_service_file: Option<PathBuf>,
+    http_port: u16,
+    identify_led: PathBuf,
+    identifying: AtomicBool,
+    power_token: Option<String>,
+    admin_token: Option<String>,
+}
+
+impl DeviceState {
+    /// Creates a new device state.
+    ///
+    /// If a device name file is given in the `args`, the device name is read
+    /// from this file (if it exists). If an Avahi service file is given, the
+    /// service file advertising the HTTP server is written.
+    pub async fn new(args: &Args) -> Result<DeviceState> {
+        let name = match &args.device_name_file {
+            Some(file) => match tokio::fs::read_to_string(file).await {
+                Ok(name) => name.trim().to_string(),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(err) => {
+                    return Err(err).context("failed to read device name file");
+                }
+            },
+            None => String::new(),
+        };
+        let state = DeviceState {
+            name: Mutex::new(name),
+            frequency_offset: Mutex::new(Hertz(0.0)),
+            name_file: args.device_name_file.clone(),
+            avahi_service_file: args.avahi_service_file.clone(),
+            http_port: args.listen.port(),
+            identify_led: args.identify_led.clone(),
+            identifying: AtomicBool::new(false),
+            power_token: args.power_token.clone(),
+            admin_token: args.admin_token.clone(),
+        };
+        state.write_avahi_service(&state.name()).await?;
+        Ok(state)
+    }
+
+    /// Returns the device name.
+    ///
+    /// The name is empty if it has never been set.
+    pub fn name(&self) -> String {
+        self.name.lock().unwrap().clone()
+    }
+
+    /// Returns `true` while the identify LED is blinking to identify the
+    /// device.
+    pub fn is_identifying(&self) -> bool {
+        self.identifying.load(Ordering::Relaxed)
+    }
+
+    /// Returns the frequency offset of the external frequency converter.
+    ///
+    /// The offset is zero if no converter is used.
+    pub fn frequency_offset(&self) -> Hertz {
+        *self.frequency_offset.lock().unwrap()
+    }
+
+    fn set_frequency_offset(&self, offset: Hertz) {
+        *self.frequency_offset.lock().unwrap() = offset;
+    }
+
+    // Sets the device name, which must have been validated with
+    // PatchDevice::validate. The name is saved to the device name file before
+    // it is changed, so the current name is kept if it cannot be saved.
+    async fn set_name(&self, name: &str) -> Result<()> {
+        let name = name.trim();
+        if let Some(file) = &self.name_file {
+            crate::fs::write_atomic(file, format!("{name}\n"))
+                .await
+                .context("failed to write device name file")?;
+        }
+        self.name.lock().unwrap().replace_range(.., name);
+        self.write_avahi_service(name).await
+    }
+
+    async fn write_avahi_service(&self, name: &str) -> Result<()> {
+        if let Some(file) = &self.avahi_service_file {
+            // avahi-daemon watches its services directory, so the
+            // advertisement is updated as soon as the file is written.
+            crate::fs::write_atomic(file, avahi_service(name, self.http_port))
+                .await
+                .context("failed to write Avahi service file")?;
+        }
+        Ok(())
+    }
+
+    // Checks that a power request carries the bearer token given in the
+    // command line.
+    fn authorize_power(&self, headers: &HeaderMap) -> Result<(), JsonError> {
+        let Some(token) = &self.power_token else {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "shutdown and reboot are disabled (no --power-token given)"
+            )));
+        };
+        if !bearer_token_matches(headers, token) {
+            return Err(JsonError::unauthorized(anyhow::anyhow!(
+                "invalid power token"
+            )));
+        }
+        Ok(())
+    }
+
+    // Checks that an administrative request, such as running the benchmarks,
+    // carries the bearer token given in the command line.
+    pub(super) fn authorize_admin(&self, headers: &HeaderMap) -> Result<(), JsonError> {
+        let Some(token) = &self.admin_token else {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "administrative requests are disabled (no --admin-token given)"
+            )));
+        };
+        if !bearer_token_matches(headers, token) {
+            return Err(JsonError::unauthorized(anyhow::anyhow!(
+                "invalid admin token"
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Checks whether a request carries a token in an `Authorization: Bearer`
+// header.
+pub(super) fn bearer_token_matches(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|given| tokens_match(given, token))
+}
+
+// Compares two tokens in a time that does not depend on the position of the
+// first mismatch.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+fn avahi_service(name: &str, port: u16) -> String {
+    // Avahi replaces %h by the host name when replace-wildcards is enabled,
+    // but it has no escape for a literal %, so the wildcards are only enabled
+    // for the default name.
+    let (name, replace_wildcards) = if name.is_empty() {
+        ("Maia SDR on %h".to_string(), "yes")
+    } else {
+        (
+            name.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;"),
+            "no",
+        )
+    };
+    format!(
+        r#"<?xml version="1.0" standalone="no"?>
+<!DOCTYPE service-group SYSTEM "avahi-service.dtd">
+<service-group>
+  <name replace-wildcards="{replace_wildcards}">{name}</name>
+  <service>
+    <type>_http._tcp</type>
+    <port>{port}</port>
+  </service>
+</service-group>
+"#
+    )
+}
+
+pub fn device_json(state: &AppState) -> Device {
+    Device {
+        name: state.device().name(),
+        frequency_offset: state.device().frequency_offset(),
+    }
+}
+
+pub async fn get_device(State(state): State<AppState>) -> Json<Device> {
+    Json(device_json(&state))
+}
+
+pub async fn patch_device(
+    State(state): State<AppState>,
+    Json(patch): Json<PatchDevice>,
+) -> Result<Json<Device>, JsonError> {
+    patch.validate().map_err(JsonError::client_error_alert)?;
+    if let Some(name) = &patch.name {
+        state
+            .device()
+            .set_name(name)
+            .await
+            .map_err(JsonError::server_error)?;
+    }
+    if let Some(offset) = patch.frequency_offset {
+        state.device().set_frequency_offset(offset);
+    }
+    Ok(Json(device_json(&state)))
+}
+
+pub(super) async fn led_trigger(led: &Path) -> Result<String> {
+    // The trigger file lists all the available triggers, with the current one
+    // in square brackets.
+    let triggers = tokio::fs::read_to_string(led.join("trigger")).await?;
+    triggers
+        .split_whitespace()
+        .find_map(|t| t.strip_prefix('[')?.strip_suffix(']'))
+        .map(|t| t.to_string())
+        .ok_or_else(|| anyhow::anyhow!("could not parse LED trigger"))
+}
+
+async fn start_blinking(led: &Path) -> Result<String> {
+    let previous = led_trigger(led).await?;
+    tokio::fs::write(led.join("trigger"), "timer").await?;
+    for file in ["delay_on", "delay_off"] {
+        tokio::fs::write(led.join(file), IDENTIFY_BLINK_PERIOD_MS.to_string()).await?;
+    }
+    Ok(previous)
+}
+
+pub async fn put_identify(
+    State(state): State<AppState>,
+    Json(put): Json<Identify>,
+) -> Result<Json<Identify>, JsonError> {
+    let duration = match put.duration.duration() {
+        Some(duration) if put.duration > Seconds(0.0) && put.duration <= MAX_IDENTIFY_DURATION => {
+            duration
+        }
+        _ => {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "identify duration must be between 0 and {MAX_IDENTIFY_DURATION}"
+            )))
+        }
+    };
+    let device = state.device();
+    if device.identifying.swap(true, Ordering::Relaxed) {
+        // The LED is already blinking
+        return Ok(Json(put));
+    }
+    let led = device.identify_led.clone();
+    let previous = match start_blinking(&led).await {
+        Ok(previous) => previous,
+        Err(err) => {
+            device.identifying.store(false, Ordering::Relaxed);
+            return Err(JsonError::server_error(
+                err.context("failed to set identify LED"),
+            ));
+        }
+    };
+    tracing::info!(duration = put.duration.0, "identifying device");
+    tokio::spawn(async move {
+        tokio::time::sleep(duration).await;
+        if let Err(err) = tokio::fs::write(led.join("trigger"), previous).await {
+            tracing::error!("failed to restore identify LED trigger: {err}");
+        }
+        state.device().identifying.store(false, Ordering::Relaxed);
+    });
+    Ok(Json(put))
+}
+
+async fn power(
+    state: AppState,
+    headers: HeaderMap,
+    action: PowerAction,
+) -> Result<Json<Power>, JsonError> {
+    state.device().authorize_power(&headers)?;
+    tracing::info!(?action, "power action requested");
+    state.shutdown().request(action);
+    Ok(Json(Power {
+        action: match action {
+            PowerAction::Reboot => maia_json::PowerAction::Reboot,
+            _ => maia_json::PowerAction::Shutdown,
+        },
+    }))
+}
+
+pub async fn put_shutdown(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Power>, JsonError> {
+    power(state, headers, PowerAction::Poweroff).await
+}
+
+pub async fn put_reboot(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Power>, JsonError> {
+    power(state, headers, PowerAction::Reboot).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse};
+    use clap::Parser;
+
+    #[test]
+    fn avahi_service_escaping() {
+        let service = avahi_service("Pluto <1> & 100%", 8000);
+        assert!(
+            service.contains("<name replace-wildcards=\"no\">Pluto &lt;1&gt; &amp; 100%</name>")
+        );
+        assert!(service.contains("<port>8000</port>"));
+        let service = avahi_service("", 80);
+        assert!(service.contains("<name replace-wildcards=\"yes\">Maia SDR on %h</name>"));
+    }
+
+    #[tokio::test]
+    async fn frequency_offset() {
+        let (state, _) = crate::mock::app_state().await;
+        let patch = |frequency_offset| {
+            patch_device(
+                State(state.clone()),
+                Json(PatchDevice {
+                    name: None,
+                    frequency_offset: Some(frequency_offset),
+                }),
+            )
+        };
+        assert_eq!(device_json(&state).frequency_offset, Hertz(0.0));
+        let Json(device) = patch(Hertz(9.75e9)).await.unwrap();
+        assert_eq!(device.frequency_offset, Hertz(9.75e9));
+        let Json(device) = patch(Hertz(-125e6)).await.unwrap();
+        assert_eq!(device.frequency_offset, Hertz(-125e6));
+        assert_eq!(
+            patch(PatchDevice::MAX_FREQUENCY_OFFSET * 2.0)
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(state.device().frequency_offset(), Hertz(-125e6));
+    }
+
+    #[tokio::test]
+    async fn unsaved_name() {
+        let file = std::env::temp_dir().join("maia-httpd-missing/device-name");
+        let (state, _) =
+            crate::mock::app_state_with_args(&["--device-name-file", file.to_str().unwrap()]).await;
+        let patch = PatchDevice {
+            name: Some("Pluto 1".to_string()),
+            frequency_offset: Some(Hertz(9.75e9)),
+        };
+        // The name is kept if it cannot be saved, and the failure is a
+        // server error
+        assert_eq!(
+            patch_device(State(state.clone()), Json(patch))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(device_json(&state).name, "");
+        assert_eq!(device_json(&state).frequency_offset, Hertz(0.0));
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(tokens_match("secret", "secret"));
+        assert!(!tokens_match("secreT", "secret"));
+        assert!(!tokens_match("secret1", "secret"));
+        assert!(!tokens_match("", "secret"));
+    }
+
+    #[tokio::test]
+    async fn power_authorization() {
+        let (state, _) = crate::mock::app_state().await;
+        let bearer = |token: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, format!("Bearer {token}").parse().unwrap());
+            headers
+        };
+        // Disabled when no token is configured
+        assert!(put_reboot(State(state.clone()), bearer("secret"))
+            .await
+            .is_err());
+        assert!(!state.shutdown().is_requested());
+
+        let device = DeviceState::new(&Args::parse_from(["maia-httpd", "--power-token", "secret"]))
+            .await
+            .unwrap();
+        assert!(device.authorize_power(&HeaderMap::new()).is_err());
+        assert!(device.authorize_power(&bearer("wrong")).is_err());
+        assert!(device.authorize_power(&bearer("secret")).is_ok());
+    }
+}
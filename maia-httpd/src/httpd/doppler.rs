@@ -0,0 +1,74 @@
+use super::json_error::JsonError;
+use crate::app::AppState;
+use axum::{extract::State, Json};
+use maia_json::{Doppler, PatchDoppler};
+
+pub async fn get_doppler(State(state): State<AppState>) -> Json<Doppler> {
+    Json(state.doppler().json())
+}
+
+pub async fn patch_doppler(
+    State(state): State<AppState>,
+    Json(patch): Json<PatchDoppler>,
+) -> Result<Json<Doppler>, JsonError> {
+    if let Some(target) = patch.target {
+        state
+            .doppler()
+            .set_target(target)
+            .map_err(JsonError::client_error_alert)?;
+    }
+    if let Some(enabled) = patch.enabled {
+        if enabled && state.doppler().json().target.is_none() {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "a target must be selected to enable the Doppler correction"
+            )));
+        }
+        state.doppler().set_enabled(enabled);
+    }
+    Ok(Json(state.doppler().json()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::doppler::test_target;
+
+    #[tokio::test]
+    async fn doppler() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(doppler) = get_doppler(State(state.clone())).await;
+        assert!(!doppler.enabled);
+        assert_eq!(doppler.target, None);
+        assert_eq!(doppler.status, None);
+        let enable = PatchDoppler {
+            enabled: Some(true),
+            ..Default::default()
+        };
+        assert!(patch_doppler(State(state.clone()), Json(enable.clone()))
+            .await
+            .is_err());
+        let mut target = test_target();
+        target.tle_line1.truncate(40);
+        let patch = |target| PatchDoppler {
+            enabled: Some(true),
+            target: Some(target),
+        };
+        assert!(patch_doppler(State(state.clone()), Json(patch(target)))
+            .await
+            .is_err());
+        let Json(doppler) = patch_doppler(State(state.clone()), Json(patch(test_target())))
+            .await
+            .unwrap();
+        assert!(doppler.enabled);
+        assert_eq!(doppler.target, Some(test_target()));
+        let disable = PatchDoppler {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        let Json(doppler) = patch_doppler(State(state.clone()), Json(disable))
+            .await
+            .unwrap();
+        assert!(!doppler.enabled);
+        assert_eq!(get_doppler(State(state)).await.target, Some(test_target()));
+    }
+}
@@ -0,0 +1,76 @@
+use super::{device::bearer_token_matches, json_error::JsonError};
+use crate::app::AppState;
+use axum::{extract::State, Json};
+use http::header::HeaderMap;
+
+pub async fn get_limits_admin(State(state): State<AppState>) -> Json<maia_json::Limits> {
+    Json(state.limits().json())
+}
+
+pub async fn put_limits_admin(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(put): Json<maia_json::Limits>,
+) -> Result<Json<maia_json::Limits>, JsonError> {
+    let Some(token) = state.limits().token() else {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "the safety limits cannot be changed (no --limits-token given)"
+        )));
+    };
+    if !bearer_token_matches(&headers, token) {
+        return Err(JsonError::unauthorized(anyhow::anyhow!(
+            "invalid limits token"
+        )));
+    }
+    put.validate().map_err(JsonError::client_error_alert)?;
+    state
+        .limits()
+        .set(put)
+        .await
+        .map_err(JsonError::server_error)?;
+    tracing::info!("safety limits changed");
+    Ok(Json(state.limits().json()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse};
+    use http::header::AUTHORIZATION;
+    use maia_json::Decibels;
+
+    #[tokio::test]
+    async fn limits_admin() {
+        let (state, _) = crate::mock::app_state().await;
+        let put = maia_json::Limits {
+            max_tx_gain: Some(Decibels(-30.0)),
+            ..Default::default()
+        };
+        // no token given in the command line
+        assert!(
+            put_limits_admin(State(state.clone()), HeaderMap::new(), Json(put.clone()))
+                .await
+                .is_err()
+        );
+
+        let (state, _) = crate::mock::app_state_with_args(&["--limits-token", "secret"]).await;
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert_eq!(
+            put_limits_admin(State(state.clone()), headers, Json(put.clone()))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        let Json(limits) = put_limits_admin(State(state.clone()), headers, Json(put.clone()))
+            .await
+            .unwrap();
+        assert_eq!(limits, put);
+        let Json(limits) = get_limits_admin(State(state)).await;
+        assert_eq!(limits, put);
+    }
+}
@@ -0,0 +1,128 @@
+use super::json_error::JsonError;
+use maia_json::ListQuery;
+
+/// Item of a list resource.
+///
+/// This trait is implemented by the items of the list resources, so that they
+/// can be filtered and paginated with [`paginate`].
+pub trait ListItem {
+    /// Returns the time of the item.
+    ///
+    /// The time is given in milliseconds since the UNIX timestamp. Items that
+    /// do not have a time return `None`, and they never match the time filters.
+    fn time(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl ListItem for maia_json::RecorderClip {
+    fn time(&self) -> Option<f64> {
+        Some(self.start_time)
+    }
+}
+
+impl ListItem for maia_json::RecorderTransfer {}
+
+impl ListItem for maia_json::ProcessingJob {}
+
+impl ListItem for maia_json::WebSocketClient {
+    fn time(&self) -> Option<f64> {
+        Some(self.connect_time)
+    }
+}
+
+impl ListItem for maia_json::AuditEntry {
+    fn time(&self) -> Option<f64> {
+        Some(self.time)
+    }
+}
+
+/// Filters and paginates the items of a list resource.
+///
+/// The items that do not match the time filters of the `query` are removed,
+/// and then only the page given by the offset and the limit of the `query` is
+/// kept. The function returns the number of items that match the filters, which
+/// is used as the total count of the list.
+pub fn paginate<T: ListItem>(items: &mut Vec<T>, query: &ListQuery) -> Result<usize, JsonError> {
+    query.validate().map_err(JsonError::client_error)?;
+    if query.since.is_some() || query.until.is_some() {
+        items.retain(|item| {
+            item.time().is_some_and(|time| {
+                query.since.is_none_or(|since| time >= since)
+                    && query.until.is_none_or(|until| time < until)
+            })
+        });
+    }
+    let total = items.len();
+    let offset = query.offset.unwrap_or(0).min(total);
+    let end = match query.limit {
+        Some(limit) => offset.saturating_add(limit).min(total),
+        None => total,
+    };
+    items.truncate(end);
+    items.drain(..offset);
+    Ok(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entries(times: &[f64]) -> Vec<maia_json::AuditEntry> {
+        times
+            .iter()
+            .map(|&time| maia_json::AuditEntry {
+                time,
+                client: "127.0.0.1:8000".to_string(),
+                method: "PATCH".to_string(),
+                path: "/api/ad9361".to_string(),
+                body: String::new(),
+                http_status_code: 200,
+            })
+            .collect()
+    }
+
+    fn times(entries: &[maia_json::AuditEntry]) -> Vec<f64> {
+        entries.iter().map(|entry| entry.time).collect()
+    }
+
+    #[test]
+    fn pagination() {
+        let all = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let mut items = entries(&all);
+        assert_eq!(paginate(&mut items, &ListQuery::default()).unwrap(), 5);
+        assert_eq!(times(&items), all);
+
+        let query = ListQuery::builder().offset(1).limit(2).build().unwrap();
+        let mut items = entries(&all);
+        assert_eq!(paginate(&mut items, &query).unwrap(), 5);
+        assert_eq!(times(&items), [2.0, 3.0]);
+
+        let query = ListQuery::builder().offset(10).build().unwrap();
+        let mut items = entries(&all);
+        assert_eq!(paginate(&mut items, &query).unwrap(), 5);
+        assert!(items.is_empty());
+
+        let query = ListQuery::builder()
+            .since(2.0)
+            .until(5.0)
+            .limit(0)
+            .build()
+            .unwrap();
+        let mut items = entries(&all);
+        assert_eq!(paginate(&mut items, &query).unwrap(), 3);
+        assert!(items.is_empty());
+
+        let query = ListQuery::builder().since(2.0).offset(2).build().unwrap();
+        let mut items = entries(&all);
+        assert_eq!(paginate(&mut items, &query).unwrap(), 4);
+        assert_eq!(times(&items), [4.0, 5.0]);
+
+        let query = ListQuery {
+            since: Some(3.0),
+            until: Some(2.0),
+            ..Default::default()
+        };
+        assert!(paginate(&mut entries(&all), &query).is_err());
+    }
+}
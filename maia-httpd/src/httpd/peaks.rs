@@ -0,0 +1,131 @@
+use super::{
+    clients::{self, disconnect_close_message},
+    json_error::JsonError,
+    websocket::shutdown_close_message,
+};
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, State,
+    },
+    response::Response,
+    Json,
+};
+use futures::{stream::StreamExt, SinkExt};
+use maia_json::{PatchSpectrometerPeaks, SpectrometerPeakEvent, SpectrometerPeaks};
+use std::net::SocketAddr;
+use tokio::sync::broadcast;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
+use tracing::Instrument;
+
+pub async fn get_peaks(State(state): State<AppState>) -> Json<SpectrometerPeaks> {
+    Json(state.peaks().json())
+}
+
+pub async fn patch_peaks(
+    State(state): State<AppState>,
+    Json(patch): Json<PatchSpectrometerPeaks>,
+) -> Result<Json<SpectrometerPeaks>, JsonError> {
+    if let Some(threshold) = patch.threshold {
+        state
+            .peaks()
+            .set_threshold(threshold.0)
+            .map_err(JsonError::client_error_alert)?;
+    }
+    Ok(Json(state.peaks().json()))
+}
+
+pub async fn websocket_handler(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let span = tracing::debug_span!("peaks websocket");
+    let receiver = state.peaks().subscribe();
+    let client = connect_info.map(|ConnectInfo(client)| client);
+    ws.on_upgrade(move |socket| handle(socket, state, client, receiver).instrument(span))
+}
+
+async fn handle(
+    socket: WebSocket,
+    state: AppState,
+    client: Option<SocketAddr>,
+    receiver: broadcast::Receiver<SpectrometerPeakEvent>,
+) {
+    if let Err(error) = handle_socket(socket, state, client, receiver).await {
+        tracing::error!(%error, "client error");
+    }
+}
+
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    client: Option<SocketAddr>,
+    receiver: broadcast::Receiver<SpectrometerPeakEvent>,
+) -> Result<()> {
+    let registration = clients::register(&state, maia_json::WebSocketKind::Peaks, client, None);
+    let (mut ws_send, ws_recv) = socket.split();
+    // Future to forward the peak events to the websocket.
+    let send = BroadcastStream::new(receiver)
+        .filter_map(|x| {
+            futures::future::ready(match x {
+                Ok(event) => {
+                    let message = Message::Text(serde_json::to_string(&event).unwrap());
+                    registration.count(&message);
+                    Some(Ok(message))
+                }
+                Err(BroadcastStreamRecvError::Lagged(lagged)) => {
+                    tracing::info!("client lagged {} peak events", lagged);
+                    None
+                }
+            })
+        })
+        .take_until(registration.disconnect().cancelled())
+        .forward(&mut ws_send);
+    // Future to receive messages form the websocket and ignore them. This
+    // is needed to make the lower layers reply to ping messages automatically.
+    let mut receive = ws_recv.skip_while(|r| futures::future::ready(r.is_ok()));
+    tokio::select! {
+        ret = send => ret?,
+        ret = receive.next() => match ret {
+            None => anyhow::bail!("no more websocket messages to receive"),
+            Some(Ok(_)) => unreachable!(), // we've skipped all the Ok messages
+            Some(Err(e)) => Err(e)?,
+        },
+    };
+    if state.shutdown().closing().is_cancelled() {
+        ws_send.send(shutdown_close_message()).await?;
+    } else if registration.disconnect().is_cancelled() {
+        ws_send.send(disconnect_close_message()).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::Decibels;
+
+    #[tokio::test]
+    async fn threshold() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(peaks) = get_peaks(State(state.clone())).await;
+        assert!(peaks.active.is_empty());
+        assert!(peaks.recent.is_empty());
+        let patch = |threshold| PatchSpectrometerPeaks {
+            threshold: Some(threshold),
+        };
+        let Json(peaks) = patch_peaks(State(state.clone()), Json(patch(Decibels(6.0))))
+            .await
+            .unwrap();
+        assert_eq!(peaks.threshold, Decibels(6.0));
+        assert!(
+            patch_peaks(State(state.clone()), Json(patch(Decibels(-1.0))))
+                .await
+                .is_err()
+        );
+        assert_eq!(get_peaks(State(state)).await.threshold, Decibels(6.0));
+    }
+}
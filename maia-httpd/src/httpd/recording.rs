@@ -2,24 +2,53 @@ use super::json_error::JsonError;
 use crate::app::AppState;
 use crate::fpga::{InterruptWaiter, IpCore};
 use crate::iio::Ad9361;
+use crate::sample_time::SampleTimeReference;
+use crate::samples;
 use crate::sigmf;
+use crate::tuner::TunerOwner;
 use anyhow::Result;
-use axum::{body::Body, extract::State, Json};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Query, State},
+    Json,
+};
 use bytes::{Bytes, BytesMut};
-use futures::Stream;
+use futures::{Stream, StreamExt};
 use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH};
-use maia_json::RecorderMode;
-use std::os::unix::io::AsRawFd;
+use maia_json::{Hertz, RecorderMode, Seconds};
+use num_complex::Complex32;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
-use tokio::fs;
+use std::time::{Duration, Instant, UNIX_EPOCH};
 use tokio::io::DuplexStream;
 use tokio::sync::{OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock};
 use tokio_util::{io::ReaderStream, sync::CancellationToken};
 
+pub mod annotations;
+pub mod clips;
+mod digital_rf;
+mod hooks;
+pub mod hopping;
 pub mod iqengine;
+pub mod preview;
+pub mod processing;
+pub mod region;
+mod session;
+pub mod stats;
+pub mod storage;
+pub mod template;
+pub mod transfers;
+pub mod upload;
+mod wav;
+
+pub use hooks::RecordingHooks;
+use storage::RecordingStorage;
 
 type InProgress = tokio::sync::Mutex<Option<OwnedRwLockWriteGuard<RecordingBuffer>>>;
 
@@ -31,6 +60,82 @@ pub struct RecorderState {
     metadata: tokio::sync::Mutex<RecordingMeta>,
     buffer: Arc<RwLock<RecordingBuffer>>,
     recording_in_progress: InProgress,
+    // Cancellation token of the analysis of the recording, which is Some while
+    // the recording is being analyzed.
+    analysis_cancellation: std::sync::Mutex<Option<CancellationToken>>,
+    clips: std::sync::Mutex<clips::Clips>,
+    hits: std::sync::Mutex<clips::Clips>,
+    hopping: std::sync::Mutex<hopping::Hopping>,
+    hopping_recordings: std::sync::Mutex<clips::Clips>,
+    upload: std::sync::Mutex<upload::Upload>,
+    processing: std::sync::Mutex<processing::Jobs>,
+    transfers: std::sync::Mutex<transfers::Transfers>,
+    // File where the recording in progress is saved, so that it can be
+    // recovered if maia-httpd restarts.
+    session_file: std::sync::Mutex<Option<PathBuf>>,
+    // Whether a recording has been recovered from the session file and the
+    // RecorderFinishWaiter has not finished it yet.
+    session_recovered: AtomicBool,
+    // Size of the recording buffer in bytes. It is updated each time that the
+    // recording storage is refreshed.
+    buffer_size: AtomicUsize,
+}
+
+/// IQ samples captured by [`RecorderState::capture_iq`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct IqCapture {
+    /// IQ samples.
+    ///
+    /// The samples are normalized so that the full scale of the recorder
+    /// corresponds to an amplitude of one.
+    pub samples: Vec<Complex32>,
+    /// Value of the sample counter of the FPGA IP core for the first sample.
+    ///
+    /// This is `None` if the IP core does not have a sample counter.
+    pub start_sample: Option<u64>,
+}
+
+/// Reason why the recorder cannot make a capture.
+///
+/// This is returned by [`RecorderState::capture_iq`] and
+/// [`RecorderState::record_hit`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RecorderBusy {
+    /// A recording is in progress or armed, or the recording buffer is being
+    /// accessed.
+    InUse,
+    /// The recording buffer contains the last recording, which has not been
+    /// discarded with [`delete_recording`].
+    RecordingStored,
+}
+
+impl std::fmt::Display for RecorderBusy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RecorderBusy::InUse => "the recorder is in use",
+            RecorderBusy::RecordingStored => {
+                "the recording buffer contains a recording that has not been discarded"
+            }
+        })
+    }
+}
+
+impl std::error::Error for RecorderBusy {}
+
+/// Recording of a scanner hit.
+///
+/// This is used by [`RecorderState::record_hit`] to make a short recording
+/// when the [scanner](crate::scanner) stops on an active channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScannerHit {
+    /// File name of the recording (without extension).
+    pub filename: String,
+    /// Description of the recording.
+    ///
+    /// This is stored in the SigMF metadata of the recording.
+    pub description: String,
+    /// Duration of the recording.
+    pub duration: Duration,
 }
 
 /// Recorder finish waiter.
@@ -43,33 +148,364 @@ pub struct RecorderState {
 pub struct RecorderFinishWaiter {
     state: AppState,
     waiter: InterruptWaiter,
+    hooks: RecordingHooks,
 }
 
 impl RecorderState {
     /// Creates a new recorder state.
+    ///
+    /// The recordings are accessed in the recording DMA buffer of the IP core.
     pub async fn new(
         ad9361: &tokio::sync::Mutex<Ad9361>,
         ip_core: &std::sync::Mutex<IpCore>,
+    ) -> Result<RecorderState> {
+        let backend = Arc::clone(ip_core.lock().unwrap().backend());
+        let storage = storage::DmaStorage::new(backend).await?;
+        RecorderState::with_storage(ad9361, ip_core, Box::new(storage)).await
+    }
+
+    /// Creates a new recorder state that uses a custom recording storage.
+    ///
+    /// The recorded data is accessed through `storage`, which must contain the
+    /// data written by the recorder after it has been
+    /// [refreshed](RecordingStorage::refresh).
+    pub async fn with_storage(
+        ad9361: &tokio::sync::Mutex<Ad9361>,
+        ip_core: &std::sync::Mutex<IpCore>,
+        storage: Box<dyn RecordingStorage>,
     ) -> Result<RecorderState> {
         let metadata = tokio::sync::Mutex::new(RecordingMeta::new(ad9361, ip_core).await?);
-        let buffer = Arc::new(RwLock::new(RecordingBuffer::new().await?));
+        let buffer = RecordingBuffer(storage);
+        let buffer_size = AtomicUsize::new(buffer.0.size());
+        let buffer = Arc::new(RwLock::new(buffer));
         let recording_in_progress = tokio::sync::Mutex::new(None);
         Ok(RecorderState {
             metadata,
             buffer,
             recording_in_progress,
+            analysis_cancellation: std::sync::Mutex::new(None),
+            clips: std::sync::Mutex::new(clips::Clips::new("clip", clips::MAX_CLIPS_SIZE)),
+            hits: std::sync::Mutex::new(clips::Clips::new("hit", clips::MAX_HITS_SIZE)),
+            hopping: std::sync::Mutex::new(hopping::Hopping::default()),
+            hopping_recordings: std::sync::Mutex::new(clips::Clips::new(
+                "hopping",
+                clips::MAX_HOPPING_SIZE,
+            )),
+            upload: std::sync::Mutex::new(upload::Upload::default()),
+            processing: std::sync::Mutex::new(processing::Jobs::default()),
+            transfers: std::sync::Mutex::new(transfers::Transfers::default()),
+            session_file: std::sync::Mutex::new(None),
+            session_recovered: AtomicBool::new(false),
+            buffer_size,
+        })
+    }
+
+    /// Sets the directory where the clips are stored.
+    ///
+    /// The SigMF data of the clips, the scanner hits and the frequency-hopping
+    /// recordings is written to subdirectories of `directory`. If this is not
+    /// called, a directory in the temporary directory is used.
+    pub fn set_clips_dir(&self, directory: &Path) {
+        for clips in [&self.clips, &self.hits, &self.hopping_recordings] {
+            clips.lock().unwrap().set_directory(directory);
+        }
+    }
+
+    /// Returns the size of the recording buffer in bytes.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// Replaces the recording storage.
+    ///
+    /// This is used to store the recordings somewhere other than the recording
+    /// DMA buffer, such as in a [`FileStorage`](storage::FileStorage). It fails
+    /// if the recording buffer is in use.
+    pub async fn set_storage(&self, storage: Box<dyn RecordingStorage>) -> Result<()> {
+        let mut buffer = self
+            .buffer
+            .try_write()
+            .map_err(|_| anyhow::anyhow!("recording buffer is being accessed"))?;
+        self.buffer_size.store(storage.size(), Ordering::Relaxed);
+        buffer.0 = storage;
+        Ok(())
+    }
+
+    // Refreshes the recording storage after a recording has finished, and
+    // updates the size of the recording buffer, since the size of some storages
+    // can change when they are refreshed.
+    async fn refresh_buffer(
+        &self,
+        buffer: &mut RecordingBuffer,
+        ip_core: &std::sync::Mutex<IpCore>,
+    ) -> Result<()> {
+        let (backend, next_address) = {
+            let ip_core = ip_core.lock().unwrap();
+            (
+                Arc::clone(ip_core.backend()),
+                ip_core.recorder_next_address(),
+            )
+        };
+        let base_address = backend.recording_base_address().await?;
+        let result = buffer
+            .0
+            .refresh(next_address.saturating_sub(base_address))
+            .await;
+        self.buffer_size.store(buffer.0.size(), Ordering::Relaxed);
+        result
+    }
+
+    /// Returns `true` if the recorder is stopped.
+    ///
+    /// The recorder is not stopped while a recording is in progress or armed.
+    pub async fn is_stopped(&self) -> bool {
+        self.metadata.lock().await.recorder_state == maia_json::RecorderState::Stopped
+    }
+
+    /// Stops the recorder.
+    ///
+    /// If a recording is in progress, it is stopped, and if the recorder is
+    /// armed, it is disarmed. This waits for the recording to finish and its
+    /// metadata to be updated, for at most `timeout`.
+    pub async fn stop(&self, state: &AppState, timeout: Duration) -> Result<()> {
+        {
+            let mut metadata = self.metadata.lock().await;
+            match metadata.recorder_state {
+                maia_json::RecorderState::Running => {
+                    tracing::info!("stopping recording");
+                    state.ip_core().lock().unwrap().recorder_stop();
+                    metadata.recorder_state = maia_json::RecorderState::Stopping;
+                    metadata.clip_capture = false;
+                }
+                maia_json::RecorderState::Armed => disarm(state, &mut metadata).await?,
+                _ => (),
+            }
+        }
+        tokio::time::timeout(timeout, async {
+            while !self.is_stopped().await {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
         })
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out waiting for the recording to finish"))
+    }
+
+    /// Recovers a recording that was in progress when maia-httpd stopped.
+    ///
+    /// The recording in progress is saved to the session `file` each time
+    /// that a recording starts. If the file exists when this function is
+    /// called, the recorder in the FPGA IP core is stopped and the metadata of
+    /// the recording is restored, so that the [`RecorderFinishWaiter`]
+    /// finishes the recording and it can be downloaded. This should be called
+    /// before any recording is started. Returns `true` if a recording has
+    /// been recovered.
+    ///
+    /// If the session file cannot be restored, it is renamed with a `.bad`
+    /// extension, so that the next start does not fail in the same way, and
+    /// the recorder is left stopped. The same is done without returning an
+    /// error if the session is stale, because the recording buffer cannot
+    /// contain its recording anymore (for instance after a power cycle).
+    pub async fn recover_session(&self, state: &AppState, file: Option<PathBuf>) -> Result<bool> {
+        self.session_file.lock().unwrap().clone_from(&file);
+        let Some(file) = file else {
+            return Ok(false);
+        };
+        let result = self.restore_session(state, &file).await;
+        if result.is_err() {
+            if let Err(err) = session::discard(&file).await {
+                tracing::error!("{err:#}");
+            }
+        }
+        result
+    }
+
+    async fn restore_session(&self, state: &AppState, file: &Path) -> Result<bool> {
+        let Some(session) = session::load(file).await? else {
+            return Ok(false);
+        };
+        let mode = state.ip_core().lock().unwrap().recorder_mode()?;
+        if let Err(err) = session.check(self.buffer_size(), mode).await {
+            // A stale session is expected after a power cycle, so it is not
+            // an error.
+            tracing::warn!("not recovering recording {}: {err:#}", session.filename());
+            session::discard(file).await?;
+            return Ok(false);
+        }
+        tracing::info!("recovering recording {}", session.filename());
+        // The IP core might still be recording. If the recording has already
+        // finished, this does nothing.
+        state.ip_core().lock().unwrap().recorder_stop();
+        let mut metadata = self.metadata.lock().await;
+        // The session is restored into a copy of the metadata, so that the
+        // metadata is not modified if the session is invalid.
+        let mut restored = metadata.clone();
+        session
+            .restore(&mut restored)
+            .map_err(|err| err.context("invalid recording session"))?;
+        lock_recording_buffer(state).await?;
+        *metadata = restored;
+        metadata.recorder_state = maia_json::RecorderState::Stopping;
+        metadata.clip = false;
+        metadata.hit = None;
+        metadata.recording_stored = true;
+        self.session_recovered.store(true, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    // Saves the recording in progress to the session file, if there is one.
+    // Errors are only logged, since they should not prevent recording.
+    async fn save_session(&self, metadata: &RecordingMeta) {
+        let Some(file) = self.session_file.lock().unwrap().clone() else {
+            return;
+        };
+        let session = session::Session::new(metadata, self.buffer_size()).await;
+        if let Err(err) = session::save(&file, &session).await {
+            tracing::error!("failed to save recording session to {file:?}: {err:#}");
+        }
+    }
+
+    // Removes the session file once the recording has finished.
+    async fn remove_session(&self) {
+        let Some(file) = self.session_file.lock().unwrap().clone() else {
+            return;
+        };
+        if let Err(err) = session::remove(&file).await {
+            tracing::error!("{err:#}");
+        }
+    }
+
+    /// Records a scanner hit.
+    ///
+    /// This starts a recording of the duration given in the `hit`. When the
+    /// recording finishes, it is stored in the library of scanner hits, in the
+    /// same way as the clips captured in clip mode. Since the recording
+    /// buffer is overwritten, hits are not recorded while the buffer contains
+    /// a recording that has not been discarded with [`delete_recording`].
+    ///
+    /// Returns a [`RecorderBusy`] error if the recorder cannot record the hit.
+    pub async fn record_hit(
+        &self,
+        state: &AppState,
+        hit: ScannerHit,
+    ) -> Result<Result<(), RecorderBusy>> {
+        let mut metadata = self.metadata.lock().await;
+        if let Err(busy) = metadata.check_capture() {
+            return Ok(Err(busy));
+        }
+        if lock_recording_buffer(state).await.is_err() {
+            return Ok(Err(RecorderBusy::InUse));
+        }
+        metadata.recorder_state = maia_json::RecorderState::Running;
+        metadata.clip = false;
+        metadata.hit = Some(hit);
+        state.ip_core().lock().unwrap().recorder_start();
+        metadata.update_for_new_recording(state).await?;
+        Ok(Ok(()))
+    }
+
+    /// Captures a block of IQ samples with the recorder.
+    ///
+    /// This makes a recording of the given `duration` and returns its IQ
+    /// samples, normalized so that the full scale of the recorder corresponds
+    /// to an amplitude of one. The contents of the recording buffer are
+    /// overwritten, so the capture is refused while the buffer contains a
+    /// recording that has not been discarded with [`delete_recording`].
+    ///
+    /// Returns a [`RecorderBusy`] error if the recorder cannot make the
+    /// capture.
+    pub async fn capture_iq(
+        &self,
+        ip_core: &std::sync::Mutex<IpCore>,
+        duration: Duration,
+    ) -> Result<Result<IqCapture, RecorderBusy>> {
+        Ok(self
+            .capture(ip_core, duration, |mode, data| mode.iq_samples(data))
+            .await?
+            .map(|(samples, start_sample)| IqCapture {
+                samples,
+                start_sample,
+            }))
+    }
+
+    // Checks that the recorder can make a capture, without making it.
+    async fn check_capture(&self) -> Result<(), RecorderBusy> {
+        self.metadata.lock().await.check_capture()
+    }
+
+    // Makes a recording of the given duration and converts the recorded data
+    // with `convert`. Returns the converted data and the value of the sample
+    // counter for the first sample, or the reason why the recorder cannot make
+    // the capture. This is used by capture_iq and by the frequency-hopping
+    // recordings.
+    async fn capture<T>(
+        &self,
+        ip_core: &std::sync::Mutex<IpCore>,
+        duration: Duration,
+        convert: impl FnOnce(Mode, &[u8]) -> T,
+    ) -> Result<Result<(T, Option<u64>), RecorderBusy>> {
+        {
+            let metadata = self.metadata.lock().await;
+            if let Err(busy) = metadata.check_capture() {
+                return Ok(Err(busy));
+            }
+            let Ok(lock) = self.buffer.clone().try_write_owned() else {
+                return Ok(Err(RecorderBusy::InUse));
+            };
+            self.recording_in_progress.lock().await.replace(lock);
+            ip_core.lock().unwrap().recorder_start();
+        }
+        tokio::time::sleep(duration).await;
+        ip_core.lock().unwrap().recorder_stop();
+        // Wait for the RecorderFinishWaiter to release the recording buffer.
+        let mut buffer = None;
+        for _ in 0..CAPTURE_FINISH_MAX_POLLS {
+            tokio::time::sleep(CAPTURE_FINISH_POLL_PERIOD).await;
+            if self.recording_in_progress.lock().await.is_none() {
+                // This fails if a new recording has been started in the
+                // meantime.
+                buffer = self.buffer.clone().try_read_owned().ok();
+                break;
+            }
+        }
+        let Some(buffer) = buffer else {
+            return Ok(Err(RecorderBusy::InUse));
+        };
+        let (mode, start_sample) = {
+            let ip_core = ip_core.lock().unwrap();
+            (ip_core.recorder_mode()?, ip_core.recorder_start_sample())
+        };
+        let data = &buffer.0.as_slice()[..buffer.0.recording_size()];
+        Ok(Ok((convert(Mode(mode), data), start_sample)))
     }
 }
 
+// Maximum time to wait for the interrupt of a recording recovered from the
+// session file. There is no interrupt if the recording finished while
+// maia-httpd was not running.
+const RECOVERED_FINISH_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Maximum number of polls waiting for a capture to finish, and period between
+// polls.
+const CAPTURE_FINISH_MAX_POLLS: usize = 1000;
+const CAPTURE_FINISH_POLL_PERIOD: Duration = Duration::from_millis(1);
+
 impl RecorderFinishWaiter {
     /// Creates a new recorder finish waiter.
     ///
     /// The `waiter` is the [`InterruptWaiter`] corresponding to the recorder
-    /// interrupt. This function only creates the object. The
-    /// [`run`](RecorderFinishWaiter) method needs to be called afterwards.
-    pub fn new(state: AppState, waiter: InterruptWaiter) -> RecorderFinishWaiter {
-        RecorderFinishWaiter { state, waiter }
+    /// interrupt. The `hooks` are run after each recording finishes (but not
+    /// after each clip in clip mode). This function only creates the object.
+    /// The [`run`](RecorderFinishWaiter) method needs to be called afterwards.
+    pub fn new(
+        state: AppState,
+        waiter: InterruptWaiter,
+        hooks: RecordingHooks,
+    ) -> RecorderFinishWaiter {
+        RecorderFinishWaiter {
+            state,
+            waiter,
+            hooks,
+        }
     }
 
     /// Runs the recorder finish waiter.
@@ -77,25 +513,113 @@ impl RecorderFinishWaiter {
     /// This function loops forever, waiting for interrupts and updating the
     /// state of the recorder. The function only returns if there is an error.
     pub async fn run(self) -> Result<()> {
+        if self
+            .state
+            .recorder()
+            .session_recovered
+            .swap(false, Ordering::Relaxed)
+        {
+            // The recording recovered by RecorderState::recover_session has
+            // been stopped. Wait for its interrupt, if there is any.
+            let _ = tokio::time::timeout(RECOVERED_FINISH_TIMEOUT, self.waiter.wait()).await;
+            self.finish().await?;
+        }
         loop {
             self.waiter.wait().await;
-            tracing::info!("recorder finished");
-            {
-                let mut in_progress = self.state.recorder().recording_in_progress.lock().await;
-                if let Some(buffer) = in_progress.as_mut() {
-                    // mmap() the buffer again to invalidate the cache
-                    **buffer = RecordingBuffer::new().await?;
+            self.finish().await?;
+        }
+    }
+
+    // Updates the state of the recorder after a recording has finished.
+    async fn finish(&self) -> Result<()> {
+        tracing::info!("recorder finished");
+        {
+            let mut in_progress = self.state.recorder().recording_in_progress.lock().await;
+            if let Some(buffer) = in_progress.as_mut() {
+                // The recorder can keep working if the storage fails, so the
+                // error is only logged.
+                if let Err(err) = self
+                    .state
+                    .recorder()
+                    .refresh_buffer(buffer, self.state.ip_core())
+                    .await
+                {
+                    tracing::error!("failed to refresh recording storage: {err:#}");
                 }
-                *in_progress = None;
             }
-            let mut metadata = self.state.recorder().metadata.lock().await;
-            // Cancel the stop timer (perhaps it has already expired, but this
-            // doesn't matter).
-            if let Some(token) = metadata.stop_timer_cancellation.take() {
-                token.cancel()
+            *in_progress = None;
+        }
+        let mut metadata = self.state.recorder().metadata.lock().await;
+        // Cancel the stop timer (perhaps it has already expired, but this
+        // doesn't matter).
+        if let Some(token) = metadata.stop_timer_cancellation.take() {
+            token.cancel()
+        }
+        if metadata.recorder_state == maia_json::RecorderState::Stopped {
+            // This was a capture made by RecorderState::capture_iq, which
+            // does not modify the metadata of the last recording.
+            return Ok(());
+        }
+        metadata.recorder_state = maia_json::RecorderState::Stopped;
+        let start_sample = self.state.ip_core().lock().unwrap().recorder_start_sample();
+        let reference = *self.state.sample_time_reference().lock().unwrap();
+        metadata.set_sample_time(start_sample, reference);
+        if !metadata.clip && metadata.hit.is_none() {
+            self.state.recorder().remove_session().await;
+            drop(metadata);
+            start_analysis(&self.state, &self.hooks);
+            return Ok(());
+        }
+        if let Err(err) = self.store_clip(&metadata).await {
+            tracing::error!("failed to store clip: {err:#}");
+        }
+        if metadata.clip_capture {
+            // Re-arm the recorder to capture the next clip.
+            metadata.clip_capture = false;
+            if let Err(err) = arm(&self.state, &mut metadata, Default::default()).await {
+                tracing::error!("failed to re-arm recorder for the next clip: {err:?}");
             }
-            metadata.recorder_state = maia_json::RecorderState::Stopped;
         }
+        Ok(())
+    }
+
+    // Copies the clip or scanner hit that has just finished from the recording
+    // buffer to the clips or hits storage.
+    async fn store_clip(&self, metadata: &RecordingMeta) -> Result<()> {
+        let buffer = self
+            .state
+            .recorder()
+            .buffer
+            .clone()
+            .try_read_owned()
+            .map_err(|_| anyhow::anyhow!("recording buffer is being accessed"))?;
+        let mut stream = RecordingStream::new(buffer, metadata);
+        let num_items = stream.info.num_items();
+        let recorder = self.state.recorder();
+        let clips = if metadata.hit.is_some() {
+            &recorder.hits
+        } else {
+            &recorder.clips
+        };
+        let mut writer = clips::ClipWriter::create(clips).await?;
+        while let Some(chunk) = stream.next().await {
+            writer.write(&chunk?).await?;
+        }
+        let mut sigmf_meta = metadata.sigmf_meta.clone();
+        sigmf_meta.set_sha512(None);
+        if let Some(hit) = &metadata.hit {
+            sigmf_meta.set_description(&hit.description);
+            writer
+                .finish(clips, &hit.filename, sigmf_meta, num_items)
+                .await?;
+            tracing::info!("stored scanner hit");
+        } else {
+            writer
+                .finish(clips, &metadata.filename, sigmf_meta, num_items)
+                .await?;
+            tracing::info!("stored clip");
+        }
+        Ok(())
     }
 }
 
@@ -103,14 +627,54 @@ impl RecorderFinishWaiter {
 struct RecordingMeta {
     sigmf_meta: sigmf::Metadata,
     mode: RecorderMode,
+    decimation: usize,
+    // File name of the current recording, with the template variables
+    // expanded.
     filename: String,
+    // File name and description given through the API, which may contain
+    // template variables.
+    filename_template: String,
+    description_template: String,
+    // Values of the template variables for the current recording
+    template_variables: Option<template::TemplateVariables>,
     prepend_timestamp: bool,
     maximum_duration: Option<Duration>,
     stop_timer_cancellation: Option<CancellationToken>,
+    recording_start: Option<Instant>,
     recorder_state: maia_json::RecorderState,
+    armed: Option<maia_json::RecorderArm>,
+    arm_cancellation: Option<CancellationToken>,
+    clipping: Option<f64>,
+    clip_mode: bool,
+    clip_duration: Duration,
+    // Whether the recorder is capturing clips, so that it should be re-armed
+    // after the current clip
+    clip_capture: bool,
+    // Whether the last recording is a clip
+    clip: bool,
+    // Scanner hit, if the last recording is a scanner hit
+    hit: Option<ScannerHit>,
+    // Whether the recording buffer contains a recording started by the user
+    // that has not been discarded. Captures must not overwrite it.
+    recording_stored: bool,
+    // Portion of the recording that is included in downloads and uploads
+    trim: maia_json::RecordingTrim,
+    annotations: annotations::Annotations,
 }
 
 impl RecordingMeta {
+    // Checks that the recorder can make a capture, which overwrites the
+    // recording buffer.
+    fn check_capture(&self) -> Result<(), RecorderBusy> {
+        if self.recorder_state != maia_json::RecorderState::Stopped {
+            Err(RecorderBusy::InUse)
+        } else if self.recording_stored {
+            Err(RecorderBusy::RecordingStored)
+        } else {
+            Ok(())
+        }
+    }
+
     async fn new(
         ad9361: &tokio::sync::Mutex<Ad9361>,
         ip_core: &std::sync::Mutex<IpCore>,
@@ -127,20 +691,37 @@ impl RecordingMeta {
         let frequency;
         {
             let ad9361 = ad9361.lock().await;
-            sample_rate = ad9361.get_sampling_frequency().await? as f64 / decimation as f64;
-            frequency = ad9361.get_rx_lo_frequency().await? as f64;
+            sample_rate = (ad9361.get_sampling_frequency().await? / decimation as f64).0;
+            frequency = ad9361.get_rx_lo_frequency().await?.0;
         }
         let sigmf_meta = sigmf::Metadata::new(datatype, sample_rate, frequency);
         let filename = "recording".to_string();
+        let description_template = sigmf_meta.description().to_string();
         let recorder_state = maia_json::RecorderState::Stopped;
         Ok(RecordingMeta {
             sigmf_meta,
             mode,
+            decimation,
+            filename_template: filename.clone(),
             filename,
+            description_template,
+            template_variables: None,
             prepend_timestamp: false,
             maximum_duration: None,
             stop_timer_cancellation: None,
+            recording_start: None,
             recorder_state,
+            armed: None,
+            arm_cancellation: None,
+            clipping: None,
+            clip_mode: false,
+            clip_duration: clips::DEFAULT_CLIP_DURATION,
+            clip_capture: false,
+            clip: false,
+            hit: None,
+            recording_stored: false,
+            trim: maia_json::RecordingTrim::default(),
+            annotations: annotations::Annotations::default(),
         })
     }
 
@@ -153,9 +734,24 @@ impl RecordingMeta {
         } else {
             self.sigmf_meta.remove_geolocation();
         }
+        let frequency_offset = state.device().frequency_offset();
+        self.sigmf_meta
+            .set_frequency_offset((frequency_offset != Hertz(0.0)).then_some(frequency_offset.0));
+        self.sigmf_meta
+            .set_clock_error(state.clock_measurement().clock_error());
         self.sigmf_meta.set_datetime_now();
+        self.sigmf_meta.set_sample_time(None);
+        self.sigmf_meta.set_sha512(None);
+        self.clipping = None;
+        self.trim = maia_json::RecordingTrim::default();
+        self.annotations.clear();
+        self.update_sigmf_annotations();
+        self.recording_start = Some(Instant::now());
+        // Clips and scanner hits are copied to their libraries when they
+        // finish, so the recording buffer can be overwritten afterwards.
+        self.recording_stored = !self.clip && self.hit.is_none();
 
-        if let Some(duration) = self.maximum_duration {
+        if let Some(duration) = self.recording_duration() {
             // set up timer task to automatically stop the recording
             let token = CancellationToken::new();
             // stop_timer_cancellation should always be None in the Stopped
@@ -176,32 +772,105 @@ impl RecordingMeta {
             }
         }
 
-        if self.prepend_timestamp {
-            self.prepend_timestamp_to_filename();
-        }
-        let (offset, decimation) = {
+        let (offset, decimation, input) = {
             let ip_core = state.ip_core().lock().unwrap();
             self.mode = ip_core.recorder_mode()?;
             (
                 ip_core.recorder_input_frequency_offset(),
                 ip_core.recorder_input_decimation(),
+                ip_core.spectrometer_input(),
             )
         };
+        self.decimation = decimation;
+        // The Doppler correction is applied to the DDC frequency, so it only
+        // affects the recording if the recorder input is the DDC (the recorder
+        // shares the same input as the spectrometer)
+        self.sigmf_meta.set_doppler_correction(
+            matches!(input, maia_json::SpectrometerInput::DDC)
+                .then(|| state.doppler().applied_correction())
+                .flatten(),
+        );
         self.sigmf_meta.set_datatype(self.mode.into());
+        let gain;
         {
             let ad9361 = state.ad9361().lock().await;
             self.sigmf_meta
-                .set_sample_rate(ad9361.get_sampling_frequency().await? as f64 / decimation as f64);
+                .set_sample_rate((ad9361.get_sampling_frequency().await? / decimation as f64).0);
             self.sigmf_meta
-                .set_frequency(ad9361.get_rx_lo_frequency().await? as f64 + offset);
+                .set_frequency((ad9361.get_rx_lo_frequency().await? + offset).0);
+            gain = ad9361.get_rx_gain().await?.0;
+        }
+        if self.prepend_timestamp {
+            self.prepend_timestamp_to_filename();
+        }
+        let variables = template::TemplateVariables {
+            frequency: self.sigmf_meta.frequency(),
+            samp_rate: self.sigmf_meta.sample_rate(),
+            datetime: self.sigmf_meta.datetime(),
+            mode: self.mode,
+            gain,
+        };
+        self.template_variables = Some(variables);
+        self.expand_templates();
+        // recording_start has been set above
+        start_clipping_monitor(state, self.recording_start.unwrap());
+        if !self.clip && self.hit.is_none() {
+            state.recorder().save_session(self).await;
         }
         Ok(())
     }
 
+    // Sets the file name and description of the current recording by expanding
+    // the templates.
+    fn expand_templates(&mut self) {
+        let expand = |template: &str| match &self.template_variables {
+            Some(variables) => variables.expand(template),
+            None => template.to_string(),
+        };
+        self.filename = expand(&self.filename_template);
+        let description = expand(&self.description_template);
+        self.sigmf_meta.set_description(&description);
+    }
+
+    // Sets the sample time of the SigMF metadata after the recording has
+    // finished. If there is a sample time reference, the datetime of the
+    // recording is also set using the reference.
+    fn set_sample_time(
+        &mut self,
+        start_sample: Option<u64>,
+        reference: Option<SampleTimeReference>,
+    ) {
+        let Some(start_sample) = start_sample else {
+            return;
+        };
+        let counter_rate = self.sigmf_meta.sample_rate() * self.decimation as f64;
+        if let Some(reference) = &reference {
+            self.sigmf_meta
+                .set_datetime(reference.datetime_at(start_sample, counter_rate));
+        }
+        self.sigmf_meta.set_sample_time(Some(sigmf::SampleTime {
+            start_sample,
+            counter_rate,
+            decimation: u32::try_from(self.decimation).unwrap(),
+            reference,
+        }));
+        // The datetime of the recording might have changed
+        self.update_sigmf_annotations();
+    }
+
+    // Sets the annotations of the SigMF metadata from the annotations of the
+    // recording.
+    fn update_sigmf_annotations(&mut self) {
+        let recording_start = self.sigmf_meta.datetime().timestamp_micros() as f64 * 1e-3;
+        let sample_rate = self.sigmf_meta.sample_rate();
+        self.sigmf_meta
+            .set_annotations(self.annotations.sigmf(recording_start, sample_rate));
+    }
+
     fn json(&self) -> maia_json::RecordingMetadata {
         maia_json::RecordingMetadata {
-            filename: self.filename.clone(),
-            description: self.sigmf_meta.description().to_string(),
+            filename: self.filename_template.clone(),
+            description: self.description_template.clone(),
             author: self.sigmf_meta.author().to_string(),
             geolocation: maia_json::DeviceGeolocation {
                 point: self.sigmf_meta.geolocation().map(|g| g.into()),
@@ -209,25 +878,57 @@ impl RecordingMeta {
         }
     }
 
-    fn recorder_json(&self, ip_core: &std::sync::Mutex<IpCore>) -> Result<maia_json::Recorder> {
+    // The samp_rate is the current sample rate of the recorder input, which is
+    // used to compute the duration that fits in the recording buffer.
+    fn recorder_json(
+        &self,
+        ip_core: &std::sync::Mutex<IpCore>,
+        num_clips: usize,
+        buffer_size: usize,
+        samp_rate: f64,
+    ) -> Result<maia_json::Recorder> {
+        let ip_core = ip_core.lock().unwrap();
+        let mode = ip_core.recorder_mode()?;
+        let buffer_items = buffer_size / Mode(mode).input_bytes_per_item();
         Ok(maia_json::Recorder {
             state: self.recorder_state,
-            mode: ip_core.lock().unwrap().recorder_mode()?,
+            mode,
             prepend_timestamp: self.prepend_timestamp,
-            maximum_duration: self
-                .maximum_duration
-                .map(|d| d.as_secs_f64())
-                .unwrap_or(0.0),
+            maximum_duration: self.maximum_duration.map_or(Seconds(0.0), Seconds::from),
+            remaining_duration: match (
+                self.recorder_state,
+                self.recording_duration(),
+                self.recording_start,
+            ) {
+                (maia_json::RecorderState::Running, Some(duration), Some(start)) => {
+                    Some(duration.saturating_sub(start.elapsed()).into())
+                }
+                _ => None,
+            },
+            trigger_mode: ip_core.recorder_trigger_mode(),
+            trigger_polarity: ip_core.recorder_trigger_polarity(),
+            armed: self.armed,
+            clipping: self.clipping,
+            clip_mode: self.clip_mode,
+            clip_duration: self.clip_duration.into(),
+            num_clips,
+            buffer_size_bytes: buffer_size as u64,
+            max_duration_at_current_settings: if samp_rate > 0.0 {
+                Seconds(buffer_items as f64 / samp_rate)
+            } else {
+                Seconds(0.0)
+            },
         })
     }
 
     fn patch_json(&mut self, patch: maia_json::PatchRecordingMetadata) -> Result<()> {
         if let Some(filename) = patch.filename {
-            self.filename = filename;
+            self.filename_template = filename;
         }
         if let Some(description) = patch.description {
-            self.sigmf_meta.set_description(&description);
+            self.description_template = description;
         }
+        self.expand_templates();
         if let Some(author) = patch.author {
             self.sigmf_meta.set_author(&author);
         }
@@ -243,12 +944,12 @@ impl RecordingMeta {
         // corresponding to the recording start.
         let datetime = self.sigmf_meta.datetime();
         // Remove previous timestamp if there is already one
-        let filename = if Self::begins_with_timestamp(&self.filename) {
-            &self.filename[Self::TIMESTAMP_LEN..]
+        let filename = if Self::begins_with_timestamp(&self.filename_template) {
+            &self.filename_template[Self::TIMESTAMP_LEN..]
         } else {
-            &self.filename
+            &self.filename_template
         };
-        self.filename = format!("{}_{}", datetime.format("%Y-%m-%d-%H-%M-%S"), filename);
+        self.filename_template = format!("{}_{}", datetime.format("%Y-%m-%d-%H-%M-%S"), filename);
     }
 
     // Timestamp format XXXX-XX-XX-XX-XX-XX_
@@ -274,8 +975,79 @@ impl RecordingMeta {
         true
     }
 
+    // Returns the duration after which the recording is stopped
+    // automatically. For clips, this is the clip duration, and for scanner
+    // hits, the hit duration.
+    fn recording_duration(&self) -> Option<Duration> {
+        if let Some(hit) = &self.hit {
+            Some(hit.duration)
+        } else if self.clip {
+            Some(self.clip_duration)
+        } else {
+            self.maximum_duration
+        }
+    }
+
+    // Modifies the trim of the recording. The num_items is the number of
+    // samples in the recording, which is used to validate the trim.
+    fn patch_trim(&mut self, patch: maia_json::PatchRecordingTrim, num_items: usize) -> Result<()> {
+        let sample_rate = self.sigmf_meta.sample_rate();
+        let bound = |sample: Option<u64>, time: Option<f64>, name| match (sample, time) {
+            (Some(_), Some(_)) => Err(anyhow::anyhow!(
+                "{name} cannot be given both as a sample and as a time"
+            )),
+            (None, Some(time)) => {
+                anyhow::ensure!(time.is_finite() && time >= 0.0, "invalid {name} time");
+                Ok(Some((time * sample_rate).round() as u64))
+            }
+            (sample, None) => Ok(sample),
+        };
+        let start =
+            bound(patch.start_sample, patch.start_time, "start")?.unwrap_or(self.trim.start_sample);
+        let end = bound(patch.end_sample, patch.end_time, "end")?.or(self.trim.end_sample);
+        let num_items = num_items as u64;
+        anyhow::ensure!(
+            start < num_items,
+            "start is beyond the end of the recording ({num_items} samples)"
+        );
+        if let Some(end) = end {
+            anyhow::ensure!(end > start, "end must be greater than start");
+        }
+        self.trim = maia_json::RecordingTrim {
+            start_sample: start,
+            // An end beyond the end of the recording is the same as no end
+            end_sample: end.filter(|&end| end < num_items),
+        };
+        Ok(())
+    }
+
+    // Returns a copy of the metadata in which the SigMF metadata describes
+    // only the trimmed portion of the recording. This is used for downloads
+    // and uploads.
+    fn trimmed(&self) -> RecordingMeta {
+        let mut metadata = self.clone();
+        if metadata.trim == maia_json::RecordingTrim::default() {
+            return metadata;
+        }
+        // The checksum is only valid for the whole recording
+        metadata.sigmf_meta.set_sha512(None);
+        let start = metadata.trim.start_sample;
+        let nanoseconds = (start as f64 * 1e9 / metadata.sigmf_meta.sample_rate()).round() as i64;
+        metadata.sigmf_meta.set_datetime(
+            metadata.sigmf_meta.datetime() + chrono::Duration::nanoseconds(nanoseconds),
+        );
+        if let Some(mut sample_time) = metadata.sigmf_meta.sample_time() {
+            sample_time.start_sample += start * u64::from(sample_time.decimation);
+            metadata.sigmf_meta.set_sample_time(Some(sample_time));
+        }
+        metadata
+            .sigmf_meta
+            .restrict_annotations(start, metadata.trim.end_sample, 1);
+        metadata
+    }
+
     fn max_samples(&self) -> Option<usize> {
-        self.maximum_duration.map(|duration| {
+        self.recording_duration().map(|duration| {
             let samp_rate = self.sigmf_meta.sample_rate();
             (duration.as_secs_f64() * samp_rate).round() as usize
         })
@@ -283,12 +1055,24 @@ impl RecordingMeta {
 }
 
 pub async fn recorder_json(state: &AppState) -> Result<maia_json::Recorder> {
-    state
-        .recorder()
-        .metadata
-        .lock()
-        .await
-        .recorder_json(state.ip_core())
+    let metadata = state.recorder().metadata.lock().await;
+    metadata_recorder_json(state, &metadata)
+}
+
+fn metadata_recorder_json(
+    state: &AppState,
+    metadata: &RecordingMeta,
+) -> Result<maia_json::Recorder> {
+    let num_clips = state.recorder().clips.lock().unwrap().len();
+    // The recorder shares the same input as the spectrometer, so its sample
+    // rate is the spectrometer sample rate.
+    let samp_rate = f64::from(state.spectrometer_config().samp_rate());
+    metadata.recorder_json(
+        state.ip_core(),
+        num_clips,
+        state.recorder().buffer_size(),
+        samp_rate,
+    )
 }
 
 pub async fn get_recorder(
@@ -304,6 +1088,21 @@ pub async fn patch_recorder(
     State(state): State<AppState>,
     Json(patch): Json<maia_json::PatchRecorder>,
 ) -> Result<Json<maia_json::Recorder>, JsonError> {
+    patch.validate().map_err(JsonError::client_error_alert)?;
+    if patch.trigger_mode.is_some() || patch.trigger_polarity.is_some() {
+        let ip_core = state.ip_core().lock().unwrap();
+        if !ip_core.has_recorder_trigger() {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "FPGA IP core does not have a trigger input"
+            )));
+        }
+        if let Some(mode) = patch.trigger_mode {
+            ip_core.set_recorder_trigger_mode(mode);
+        }
+        if let Some(polarity) = patch.trigger_polarity {
+            ip_core.set_recorder_trigger_polarity(polarity);
+        }
+    }
     if let Some(mode) = patch.mode {
         state.ip_core().lock().unwrap().set_recorder_mode(mode);
     }
@@ -311,35 +1110,36 @@ pub async fn patch_recorder(
     if let Some(prepend) = patch.prepend_timestamp {
         metadata.prepend_timestamp = prepend;
     }
+    if let Some(duration) = patch.clip_duration {
+        // The clip duration has been validated, so it is positive and finite.
+        metadata.clip_duration = Duration::from_secs_f64(duration.0);
+    }
+    if let Some(clip_mode) = patch.clip_mode {
+        metadata.clip_mode = clip_mode;
+    }
     if let Some(duration) = patch.maximum_duration {
-        if duration <= 0.0 {
+        if duration <= Seconds(0.0) {
             // Unlimited duration
             metadata.maximum_duration = None;
         } else {
-            // Use try_from_secs_f64 to avoid panics when duration overflows
-            // Duration or is infinite.
-            metadata.maximum_duration = Duration::try_from_secs_f64(duration).ok();
+            // The duration is None if it overflows Duration or is infinite.
+            metadata.maximum_duration = duration.duration();
         }
     }
     match (patch.state_change, metadata.recorder_state) {
         (Some(maia_json::RecorderStateChange::Start), maia_json::RecorderState::Stopped) => {
-            let lock = state
-                .recorder()
-                .buffer
-                .clone()
-                .try_write_owned()
-                .map_err(|_| {
-                    JsonError::client_error_alert(anyhow::anyhow!(
-                        "cannot start new recording: current recording is begin accessed"
-                    ))
-                })?;
-            state
-                .recorder()
-                .recording_in_progress
-                .lock()
-                .await
-                .replace(lock);
+            // A survey retunes the RX LO and expects the recorder to be idle
+            // while it runs.
+            if state.tuner().owner() == Some(TunerOwner::Survey) {
+                return Err(JsonError::conflict(anyhow::anyhow!(
+                    "the RX frequency is being controlled by {}",
+                    TunerOwner::Survey
+                )));
+            }
+            lock_recording_buffer(&state).await?;
             metadata.recorder_state = maia_json::RecorderState::Running;
+            metadata.clip = false;
+            metadata.hit = None;
             state.ip_core().lock().unwrap().recorder_start();
             metadata
                 .update_for_new_recording(&state)
@@ -349,81 +1149,646 @@ pub async fn patch_recorder(
         (Some(maia_json::RecorderStateChange::Stop), maia_json::RecorderState::Running) => {
             state.ip_core().lock().unwrap().recorder_stop();
             metadata.recorder_state = maia_json::RecorderState::Stopping;
+            metadata.clip_capture = false;
+        }
+        (Some(maia_json::RecorderStateChange::Stop), maia_json::RecorderState::Armed) => {
+            disarm(&state, &mut metadata)
+                .await
+                .map_err(JsonError::server_error)?;
         }
         (_, _) => (),
     }
-    metadata
-        .recorder_json(state.ip_core())
+    metadata_recorder_json(&state, &metadata)
         .map(Json)
         .map_err(JsonError::server_error)
 }
 
-pub async fn recording_metadata_json(state: &AppState) -> maia_json::RecordingMetadata {
-    state.recorder().metadata.lock().await.json()
-}
-
-pub async fn get_recording_metadata(
-    State(state): State<AppState>,
-) -> Json<maia_json::RecordingMetadata> {
-    Json(recording_metadata_json(&state).await)
+/// Discards the last recording.
+///
+/// The recording buffer is kept, so the recording can still be downloaded, but
+/// it can be overwritten by IQ captures, frequency-hopping recordings and
+/// scanner hits, which are refused while there is a recording that has not
+/// been discarded.
+pub async fn delete_recording(State(state): State<AppState>) -> Result<(), JsonError> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    if metadata.recorder_state != maia_json::RecorderState::Stopped {
+        return Err(JsonError::conflict(RecorderBusy::InUse));
+    }
+    metadata.recording_stored = false;
+    Ok(())
 }
 
-async fn set_recording_metadata(
-    state: &AppState,
-    patch: maia_json::PatchRecordingMetadata,
-) -> Result<Json<maia_json::RecordingMetadata>, JsonError> {
-    let mut metadata = state.recorder().metadata.lock().await;
-    metadata
-        .patch_json(patch)
-        .map_err(JsonError::client_error_alert)?;
-    Ok(Json(metadata.json()))
+// Takes the lock of the recording buffer for a new recording. The lock is held
+// in RecorderState::recording_in_progress until the recording finishes.
+async fn lock_recording_buffer(state: &AppState) -> Result<(), JsonError> {
+    let buffer = state.recorder().buffer.clone();
+    let analysis_cancellation = state
+        .recorder()
+        .analysis_cancellation
+        .lock()
+        .unwrap()
+        .take();
+    let lock = if let Some(token) = analysis_cancellation {
+        // Cancel the analysis of the previous recording and wait for it to
+        // release the buffer.
+        token.cancel();
+        tokio::time::timeout(ANALYSIS_CANCEL_TIMEOUT, buffer.write_owned())
+            .await
+            .ok()
+    } else {
+        buffer.try_write_owned().ok()
+    }
+    .ok_or_else(|| {
+        let users = transfers::buffer_users(state);
+        if users.is_empty() {
+            JsonError::client_error_alert(anyhow::anyhow!(
+                "cannot start new recording: current recording is being accessed"
+            ))
+        } else {
+            JsonError::client_error_alert(anyhow::anyhow!(
+                "cannot start new recording: current recording is being accessed by {}",
+                users.join(", ")
+            ))
+        }
+    })?;
+    state
+        .recorder()
+        .recording_in_progress
+        .lock()
+        .await
+        .replace(lock);
+    Ok(())
 }
 
-pub async fn put_recording_metadata(
+pub async fn put_recorder_arm(
     State(state): State<AppState>,
-    Json(put): Json<maia_json::RecordingMetadata>,
-) -> Result<Json<maia_json::RecordingMetadata>, JsonError> {
-    set_recording_metadata(&state, put.into()).await
+    Json(arm_settings): Json<maia_json::RecorderArm>,
+) -> Result<Json<maia_json::Recorder>, JsonError> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    arm(&state, &mut metadata, arm_settings).await?;
+    metadata_recorder_json(&state, &metadata)
+        .map(Json)
+        .map_err(JsonError::server_error)
 }
 
-pub async fn patch_recording_metadata(
-    State(state): State<AppState>,
-    Json(patch): Json<maia_json::PatchRecordingMetadata>,
-) -> Result<Json<maia_json::RecordingMetadata>, JsonError> {
-    set_recording_metadata(&state, patch).await
+// Arms the recorder, going from the Stopped state to the Armed state.
+async fn arm(
+    state: &AppState,
+    metadata: &mut RecordingMeta,
+    arm: maia_json::RecorderArm,
+) -> Result<(), JsonError> {
+    if metadata.recorder_state != maia_json::RecorderState::Stopped {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "cannot arm recorder: recorder is not stopped"
+        )));
+    }
+    let delay = match arm.start_time {
+        Some(_) if metadata.clip_mode => {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "cannot arm recorder: clip mode requires arming on the trigger input"
+            )));
+        }
+        Some(start_time) => {
+            let now = UNIX_EPOCH.elapsed().map_err(JsonError::server_error)?;
+            let delay = Duration::try_from_secs_f64(start_time * 1e-3)
+                .ok()
+                .and_then(|start_time| start_time.checked_sub(now))
+                .ok_or_else(|| {
+                    JsonError::client_error_alert(anyhow::anyhow!(
+                        "cannot arm recorder: start time is not in the future"
+                    ))
+                })?;
+            Some(delay)
+        }
+        None => {
+            if !state.ip_core().lock().unwrap().has_recorder_trigger() {
+                return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                    "cannot arm recorder: FPGA IP core does not have a trigger input"
+                )));
+            }
+            None
+        }
+    };
+    lock_recording_buffer(state).await?;
+    // The deadline is computed before arming, so that it does not depend on
+    // when the timer task is first polled.
+    let deadline = delay.map(|delay| tokio::time::Instant::now() + delay);
+    let token = CancellationToken::new();
+    metadata.arm_cancellation = Some(token.clone());
+    metadata.armed = Some(arm);
+    metadata.recorder_state = maia_json::RecorderState::Armed;
+    metadata.clip_capture = metadata.clip_mode;
+    if delay.is_none() {
+        state.ip_core().lock().unwrap().recorder_arm_trigger();
+    }
+    {
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Some(deadline) = deadline {
+                tokio::select! {
+                    _ = token.cancelled() => return,
+                    _ = tokio::time::sleep_until(deadline) => {}
+                };
+            } else {
+                // The recording is started by the FPGA IP core. Poll the armed
+                // bit to detect when this has happened.
+                while state.ip_core().lock().unwrap().recorder_armed() {
+                    tokio::select! {
+                        _ = token.cancelled() => return,
+                        _ = tokio::time::sleep(ARM_POLL_PERIOD) => {}
+                    };
+                }
+            }
+            if let Err(err) = start_armed_recording(&state, &token, deadline.is_some()).await {
+                tracing::error!("failed to start armed recording: {err:#}");
+            }
+        });
+    }
+    Ok(())
 }
 
-pub type SigmfStream = ReaderStream<DuplexStream>;
+// Period with which the armed bit of the recorder is polled while the recorder
+// is armed to start on the trigger input.
+const ARM_POLL_PERIOD: Duration = Duration::from_millis(10);
 
-pub async fn get_recording(State(state): State<AppState>) -> Result<(HeaderMap, Body), JsonError> {
-    let buffer = state
+// Transitions the recorder from the Armed state to the Running state. If
+// start_hardware is true, the recorder in the FPGA IP core is started.
+// Otherwise, it is assumed that the trigger input has started it.
+//
+// The token is the arm cancellation token of the task that calls this
+// function. It is checked with the metadata locked, since disarm() cancels it
+// with the metadata locked.
+async fn start_armed_recording(
+    state: &AppState,
+    token: &CancellationToken,
+    start_hardware: bool,
+) -> Result<()> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    if token.is_cancelled() {
+        // The recorder has been disarmed in the meantime (and it might have
+        // been armed again).
+        return Ok(());
+    }
+    if start_hardware {
+        state.ip_core().lock().unwrap().recorder_start();
+    }
+    tracing::info!("armed recording started");
+    metadata.recorder_state = maia_json::RecorderState::Running;
+    metadata.armed = None;
+    metadata.arm_cancellation = None;
+    metadata.clip = metadata.clip_capture;
+    metadata.hit = None;
+    metadata.update_for_new_recording(state).await
+}
+
+// Disarms the recorder, going back to the Stopped state. If the trigger input
+// has already started the recording, the recording is stopped instead.
+async fn disarm(state: &AppState, metadata: &mut RecordingMeta) -> Result<()> {
+    if let Some(token) = metadata.arm_cancellation.take() {
+        token.cancel();
+    }
+    let clip_capture = std::mem::take(&mut metadata.clip_capture);
+    let trigger = metadata
+        .armed
+        .take()
+        .is_some_and(|arm| arm.start_time.is_none());
+    if trigger {
+        let triggered = {
+            let ip_core = state.ip_core().lock().unwrap();
+            // If the armed bit is already clear, the trigger has started the
+            // recording but the polling task has not noticed it yet. There is
+            // a race condition if the trigger happens between reading the
+            // armed bit and disarming, but the window is very short.
+            let triggered = !ip_core.recorder_armed();
+            if !triggered {
+                ip_core.recorder_disarm();
+            }
+            triggered
+        };
+        if triggered {
+            metadata.recorder_state = maia_json::RecorderState::Stopping;
+            metadata.clip = clip_capture;
+            metadata.hit = None;
+            metadata.update_for_new_recording(state).await?;
+            state.ip_core().lock().unwrap().recorder_stop();
+            return Ok(());
+        }
+    }
+    *state.recorder().recording_in_progress.lock().await = None;
+    metadata.recorder_state = maia_json::RecorderState::Stopped;
+    Ok(())
+}
+
+// Interval at which the clipping of the recording in progress is updated.
+const CLIPPING_MONITOR_INTERVAL: Duration = Duration::from_millis(100);
+
+// Maximum size of the block of the newest samples that is checked for clipping
+// in each update. Checking all the samples of the recording in progress would
+// use too much CPU at high sample rates.
+const CLIPPING_MONITOR_BLOCK_SIZE: usize = 1 << 16;
+
+// Starts a task that estimates the percentage of clipped samples of the
+// recording in progress, so that it can be shown in the recorder status. The
+// task finishes when the recording stops. The exact percentage is then
+// computed by the analysis of the whole recording.
+fn start_clipping_monitor(state: &AppState, recording_start: Instant) {
+    let state = state.clone();
+    tokio::spawn(async move {
+        if let Err(err) = monitor_clipping(&state, recording_start).await {
+            tracing::error!("failed to monitor clipping: {err:#}");
+        }
+    });
+}
+
+async fn monitor_clipping(state: &AppState, recording_start: Instant) -> Result<()> {
+    let is_running = |metadata: &RecordingMeta| {
+        metadata.recording_start == Some(recording_start)
+            && metadata.recorder_state == maia_json::RecorderState::Running
+    };
+    let backend = Arc::clone(state.ip_core().lock().unwrap().backend());
+    let base_address = backend.recording_base_address().await?;
+    // Bytes of the recording that have already been considered
+    let mut checked = 0;
+    let mut clipped = 0;
+    let mut items = 0;
+    loop {
+        tokio::time::sleep(CLIPPING_MONITOR_INTERVAL).await;
+        let mode = {
+            let metadata = state.recorder().metadata.lock().await;
+            if !is_running(&metadata) {
+                return Ok(());
+            }
+            Mode(metadata.mode)
+        };
+        // The recorder has written the data below its next address, so this
+        // region can be read while the recording is in progress.
+        let written = state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .recorder_next_address()
+            .saturating_sub(base_address);
+        let bytes_per_item = mode.input_bytes_per_item();
+        let end = written / bytes_per_item * bytes_per_item;
+        if end <= checked {
+            continue;
+        }
+        let block_size =
+            (end - checked).min(CLIPPING_MONITOR_BLOCK_SIZE / bytes_per_item * bytes_per_item);
+        // mmap() the buffer again to invalidate the cache
+        let memory = backend.recording_buffer().await?;
+        let Some(block) = memory.as_slice().get(end - block_size..end) else {
+            anyhow::bail!("recorder next address is out of the recording buffer");
+        };
+        clipped += mode.clipped_samples(&mode.sigmf_data(block));
+        items += block_size / bytes_per_item;
+        checked = end;
+        let mut metadata = state.recorder().metadata.lock().await;
+        if !is_running(&metadata) {
+            return Ok(());
+        }
+        metadata.clipping = Some(100.0 * clipped as f64 / items as f64);
+    }
+}
+
+// Maximum time to wait for the analysis of the recording to release the
+// recording buffer after it has been cancelled.
+const ANALYSIS_CANCEL_TIMEOUT: Duration = Duration::from_millis(100);
+
+// Starts the analysis of the recording in the background. The analysis computes
+// the SHA512 checksum of the recording, which is added to the SigMF metadata,
+// and the percentage of clipped samples. The analysis is cancelled if a new
+// recording is started. The recording hooks are run when the analysis
+// finishes, so they are not run if it is cancelled (in this case the recording
+// is being overwritten anyway).
+fn start_analysis(state: &AppState, hooks: &RecordingHooks) {
+    let token = CancellationToken::new();
+    state
+        .recorder()
+        .analysis_cancellation
+        .lock()
+        .unwrap()
+        .replace(token.clone());
+    let state = state.clone();
+    let hooks = hooks.clone();
+    tokio::spawn(async move {
+        let finished = match analyze_recording(&state, token.clone()).await {
+            Ok(finished) => finished,
+            Err(err) => {
+                tracing::error!("failed to analyze recording: {err:#}");
+                None
+            }
+        };
+        if !token.is_cancelled() {
+            state
+                .recorder()
+                .analysis_cancellation
+                .lock()
+                .unwrap()
+                .take();
+        }
+        if let (false, Some(finished)) = (hooks.is_empty(), finished) {
+            hooks.run(&finished).await;
+        }
+    });
+}
+
+// Returns the information about the finished recording for the recording
+// hooks, or None if the analysis has been cancelled.
+async fn analyze_recording(
+    state: &AppState,
+    token: CancellationToken,
+) -> Result<Option<maia_json::RecordingFinished>> {
+    use sha2::Digest;
+
+    let mut metadata = state.recorder().metadata.lock().await.clone();
+    // The analysis is done on the whole recording, regardless of the trim.
+    metadata.trim = maia_json::RecordingTrim::default();
+    let Ok(buffer) = state.recorder().buffer.clone().try_read_owned() else {
+        // A new recording has been started in the meantime.
+        return Ok(None);
+    };
+    let stream = RecordingStream::new(buffer, &metadata);
+    let num_items = stream.info.num_items();
+    let size = stream.info.output_size();
+    let mode = stream.info.mode;
+    let cancelled = token.clone();
+    // The analysis is done on the contents of the SigMF data file.
+    let analysis = tokio::task::spawn_blocking(move || -> Result<Option<(String, usize)>> {
+        let mut hasher = sha2::Sha512::new();
+        let mut clipped = 0;
+        for chunk in futures::executor::block_on_stream(stream) {
+            if cancelled.is_cancelled() {
+                return Ok(None);
+            }
+            let chunk = chunk?;
+            clipped += mode.clipped_samples(&chunk);
+            hasher.update(chunk);
+        }
+        let sha512 = hasher
+            .finalize()
+            .iter()
+            .map(|x| format!("{x:02x}"))
+            .collect();
+        Ok(Some((sha512, clipped)))
+    })
+    .await??;
+    let mut metadata = state.recorder().metadata.lock().await;
+    let (false, Some((sha512, clipped))) = (token.is_cancelled(), analysis) else {
+        return Ok(None);
+    };
+    let clipping = if num_items == 0 {
+        0.0
+    } else {
+        100.0 * clipped as f64 / num_items as f64
+    };
+    let sample_rate = metadata.sigmf_meta.sample_rate();
+    let finished = maia_json::RecordingFinished {
+        metadata: metadata.json(),
+        mode: metadata.mode,
+        start_time: metadata.sigmf_meta.datetime().timestamp_micros() as f64 * 1e-3,
+        duration: Seconds(num_items as f64 / sample_rate),
+        frequency: Hertz(metadata.sigmf_meta.frequency()),
+        sample_rate: Hertz(sample_rate),
+        size,
+        sha512: sha512.clone(),
+        clipping,
+    };
+    metadata.sigmf_meta.set_sha512(Some(sha512));
+    metadata.clipping = Some(clipping);
+    Ok(Some(finished))
+}
+
+pub async fn get_recording_checksum(
+    State(state): State<AppState>,
+) -> Json<maia_json::RecordingChecksum> {
+    let sha512 = state
+        .recorder()
+        .metadata
+        .lock()
+        .await
+        .sigmf_meta
+        .sha512()
+        .map(String::from);
+    let in_progress = state
+        .recorder()
+        .analysis_cancellation
+        .lock()
+        .unwrap()
+        .is_some();
+    Json(maia_json::RecordingChecksum {
+        sha512,
+        in_progress,
+    })
+}
+
+pub async fn recording_metadata_json(state: &AppState) -> maia_json::RecordingMetadata {
+    state.recorder().metadata.lock().await.json()
+}
+
+pub async fn get_recording_metadata(
+    State(state): State<AppState>,
+) -> Json<maia_json::RecordingMetadata> {
+    Json(recording_metadata_json(&state).await)
+}
+
+async fn set_recording_metadata(
+    state: &AppState,
+    patch: maia_json::PatchRecordingMetadata,
+) -> Result<Json<maia_json::RecordingMetadata>, JsonError> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    metadata
+        .patch_json(patch)
+        .map_err(JsonError::client_error_alert)?;
+    if metadata.recorder_state == maia_json::RecorderState::Running
+        && !metadata.clip
+        && metadata.hit.is_none()
+    {
+        state.recorder().save_session(&metadata).await;
+    }
+    Ok(Json(metadata.json()))
+}
+
+pub async fn put_recording_metadata(
+    State(state): State<AppState>,
+    Json(put): Json<maia_json::RecordingMetadata>,
+) -> Result<Json<maia_json::RecordingMetadata>, JsonError> {
+    set_recording_metadata(&state, put.into()).await
+}
+
+pub async fn patch_recording_metadata(
+    State(state): State<AppState>,
+    Json(patch): Json<maia_json::PatchRecordingMetadata>,
+) -> Result<Json<maia_json::RecordingMetadata>, JsonError> {
+    set_recording_metadata(&state, patch).await
+}
+
+pub async fn get_recording_trim(State(state): State<AppState>) -> Json<maia_json::RecordingTrim> {
+    Json(state.recorder().metadata.lock().await.trim.clone())
+}
+
+pub async fn patch_recording_trim(
+    State(state): State<AppState>,
+    Json(patch): Json<maia_json::PatchRecordingTrim>,
+) -> Result<Json<maia_json::RecordingTrim>, JsonError> {
+    // The trim can only be modified when there is no recording in progress
+    let buffer = state
+        .recorder()
+        .buffer
+        .try_read()
+        .map_err(|_| JsonError::client_error_alert(anyhow::anyhow!("recording in progress")))?;
+    let mut metadata = state.recorder().metadata.lock().await;
+    let mut untrimmed = metadata.clone();
+    untrimmed.trim = maia_json::RecordingTrim::default();
+    let num_items = RecordingBufferInfo::new(&untrimmed, &buffer).num_items();
+    metadata
+        .patch_trim(patch, num_items)
+        .map_err(JsonError::client_error_alert)?;
+    Ok(Json(metadata.trim.clone()))
+}
+
+pub async fn delete_recording_trim(
+    State(state): State<AppState>,
+) -> Json<maia_json::RecordingTrim> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    metadata.trim = maia_json::RecordingTrim::default();
+    Json(metadata.trim.clone())
+}
+
+pub type SigmfStream = ReaderStream<DuplexStream>;
+
+/// Query parameters of the recording download.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RecordingQuery {
+    #[serde(default)]
+    format: RecordingFormat,
+}
+
+/// Format of the recording download.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum RecordingFormat {
+    /// SigMF archive.
+    #[default]
+    Sigmf,
+    /// Digital RF channel in a tar file.
+    DigitalRf,
+    /// IQ WAV file.
+    Wav,
+}
+
+pub async fn get_recording(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Query(query): Query<RecordingQuery>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    let buffer = state
         .recorder()
         .buffer
         .clone()
         .try_read_owned()
         .map_err(|_| JsonError::client_error_alert(anyhow::anyhow!("recording in progress")))?;
-    let metadata = state.recorder().metadata.lock().await.clone();
-    let (recording, size) = recording_stream(buffer, &metadata, state.ip_core())
-        .await
-        .map_err(JsonError::server_error)?;
+    let metadata = state.recorder().metadata.lock().await.trimmed();
+    let extension = match query.format {
+        RecordingFormat::Sigmf => "sigmf",
+        RecordingFormat::DigitalRf => "tar",
+        RecordingFormat::Wav => "wav",
+    };
+    let filename = format!("{}.{extension}", metadata.filename);
+    let client = connect_info.map(|ConnectInfo(client)| client);
+    let recording = match query.format {
+        RecordingFormat::Sigmf => {
+            recording_stream(buffer, &metadata)
+                .await
+                .map(|(stream, size)| {
+                    let stream = transfers::track(&state, filename.clone(), client, size, stream);
+                    (Body::from_stream(stream), size)
+                })
+        }
+        RecordingFormat::DigitalRf => {
+            digital_rf::recording_stream(buffer, &metadata)
+                .await
+                .map(|(stream, size)| {
+                    let stream = transfers::track(&state, filename.clone(), client, size, stream);
+                    (Body::from_stream(stream), size)
+                })
+        }
+        RecordingFormat::Wav => {
+            wav::recording_stream(buffer, &metadata)
+                .await
+                .map(|(stream, size)| {
+                    let stream = transfers::track(&state, filename.clone(), client, size, stream);
+                    (Body::from_stream(stream), size)
+                })
+        }
+    };
+    let (recording, size) = recording.map_err(JsonError::server_error)?;
     let mut headers = HeaderMap::new();
     headers.insert(
         CONTENT_DISPOSITION,
-        format!("attachment; filename=\"{}.sigmf\"", metadata.filename)
+        format!("attachment; filename=\"{filename}\"")
             .parse()
             .unwrap(),
     );
     headers.insert(CONTENT_LENGTH, size.to_string().parse().unwrap());
-    Ok::<_, JsonError>((headers, Body::from_stream(recording)))
+    Ok::<_, JsonError>((headers, recording))
+}
+
+/// Measures the recording download speed.
+///
+/// The whole recording buffer is read repeatedly during `duration`, converting
+/// it to the format of the SigMF data file for the current recorder mode, as
+/// the recording downloads do. The recording buffer is locked only while each
+/// chunk is converted, so that a recording can be started during the
+/// measurement, which then stops. Returns the speed in bytes per second of the
+/// data file, or `None` if the recording buffer is in use.
+pub async fn download_speed(state: &AppState, duration: Duration) -> Result<Option<f64>> {
+    let buffer = state.recorder().buffer.clone();
+    let Ok(size) = buffer.try_read().map(|buffer| buffer.0.size()) else {
+        return Ok(None);
+    };
+    let mode = Mode(state.recorder().metadata.lock().await.mode);
+    let input_bytes_per_item = mode.input_bytes_per_item();
+    let info = RecordingBufferInfo {
+        size: size / input_bytes_per_item * input_bytes_per_item,
+        mode,
+        input_bytes_per_item,
+        chunk_bytes: input_bytes_per_item * RecordingBufferInfo::CHUNK_ITEMS,
+    };
+    tokio::task::spawn_blocking(move || {
+        let start = Instant::now();
+        let mut bytes = 0;
+        let mut offset = 0;
+        while start.elapsed() < duration {
+            let Ok(buffer) = buffer.clone().try_read_owned() else {
+                break;
+            };
+            if offset >= info.size {
+                offset = 0;
+            }
+            let end = (offset + info.chunk_bytes).min(info.size);
+            let stream = RecordingStream {
+                buffer: Arc::new(buffer),
+                offset,
+                end,
+                info: info.clone(),
+            };
+            for chunk in futures::executor::block_on_stream(stream) {
+                bytes += chunk?.len();
+            }
+            offset = end;
+        }
+        Ok((bytes > 0).then(|| bytes as f64 / start.elapsed().as_secs_f64()))
+    })
+    .await?
 }
 
 async fn recording_stream(
     buffer: OwnedRwLockReadGuard<RecordingBuffer>,
     metadata: &RecordingMeta,
-    ip_core: &std::sync::Mutex<IpCore>,
 ) -> Result<(SigmfStream, usize)> {
     const DUPLEX_SIZE: usize = 1 << 20;
-    let buffer = RecordingStream::new(buffer, metadata, ip_core).await?;
+    let buffer = RecordingStream::new(buffer, metadata);
     let (duplex_write, duplex_read) = tokio::io::duplex(DUPLEX_SIZE);
     let stream = tokio_util::io::ReaderStream::new(duplex_read);
 
@@ -433,29 +1798,17 @@ async fn recording_stream(
     let timestamp = u64::try_from(metadata.sigmf_meta.datetime().timestamp())?;
 
     // Set up tar headers
-    let mut dir_header = tokio_tar::Header::new_ustar();
-    dir_header.set_path(format!("{filename}/"))?;
-    dir_header.set_size(0);
-    dir_header.set_mode(0o0755);
-    dir_header.set_entry_type(tokio_tar::EntryType::Directory);
-    dir_header.set_mtime(timestamp);
-    dir_header.set_cksum();
-
-    let mut meta_header = tokio_tar::Header::new_ustar();
-    meta_header.set_path(format!("{filename}/{filename}.sigmf-meta"))?;
-    meta_header.set_size(sigmf_meta.len().try_into().unwrap());
-    meta_header.set_mode(0o0444);
-    meta_header.set_entry_type(tokio_tar::EntryType::Regular);
-    meta_header.set_mtime(timestamp);
-    meta_header.set_cksum();
-
-    let mut data_header = tokio_tar::Header::new_ustar();
-    data_header.set_path(format!("{filename}/{filename}.sigmf-data"))?;
-    data_header.set_size(buffer.info.output_size().try_into().unwrap());
-    data_header.set_mode(0o0444);
-    data_header.set_entry_type(tokio_tar::EntryType::Regular);
-    data_header.set_mtime(timestamp);
-    data_header.set_cksum();
+    let dir_header = tar_header(&format!("{filename}/"), 0, timestamp)?;
+    let meta_header = tar_header(
+        &format!("{filename}/{filename}.sigmf-meta"),
+        sigmf_meta.len(),
+        timestamp,
+    )?;
+    let data_header = tar_header(
+        &format!("{filename}/{filename}.sigmf-data"),
+        buffer.info.output_size(),
+        timestamp,
+    )?;
 
     let tar_header_size = 512;
     let num_headers = 3;
@@ -479,6 +1832,23 @@ async fn recording_stream(
     Ok((stream, tar_size))
 }
 
+// Creates the tar header for a file, or for a directory if the path ends in '/'.
+fn tar_header(path: &str, size: usize, mtime: u64) -> Result<tokio_tar::Header> {
+    let mut header = tokio_tar::Header::new_ustar();
+    header.set_path(path)?;
+    header.set_size(size.try_into().unwrap());
+    if path.ends_with('/') {
+        header.set_mode(0o0755);
+        header.set_entry_type(tokio_tar::EntryType::Directory);
+    } else {
+        header.set_mode(0o0444);
+        header.set_entry_type(tokio_tar::EntryType::Regular);
+    }
+    header.set_mtime(mtime);
+    header.set_cksum();
+    Ok(header)
+}
+
 fn round_up_multiple_512(n: usize) -> usize {
     if n & 0x1ff != 0 {
         ((n >> 9) + 1) << 9
@@ -488,81 +1858,54 @@ fn round_up_multiple_512(n: usize) -> usize {
 }
 
 #[derive(Debug)]
-struct RecordingBuffer {
-    base: *const u8,
-    size: usize,
-}
-
-unsafe impl Send for RecordingBuffer {}
-unsafe impl Sync for RecordingBuffer {}
-
-impl RecordingBuffer {
-    async fn new() -> Result<RecordingBuffer> {
-        let size = usize::from_str_radix(
-            fs::read_to_string("/sys/class/maia-sdr/maia-sdr-recording/device/recording_size")
-                .await?
-                .trim_end()
-                .trim_start_matches("0x"),
-            16,
-        )?;
-        let mem = fs::OpenOptions::new()
-            .read(true)
-            .open("/dev/maia-sdr-recording")
-            .await?;
-        // mmap()'ing the buffer can be quite expensive, because the cache is invalidated.
-        // We run it with spawn_blocking.
-        tokio::task::spawn_blocking(move || unsafe {
-            match libc::mmap(
-                std::ptr::null_mut::<libc::c_void>(),
-                size,
-                libc::PROT_READ,
-                libc::MAP_SHARED,
-                mem.as_raw_fd(),
-                0,
-            ) {
-                libc::MAP_FAILED => Err(anyhow::anyhow!("mmap /dev/maia-sdr-recording failed")),
-                x => Ok(RecordingBuffer {
-                    base: x as *const u8,
-                    size,
-                }),
-            }
-        })
-        .await?
-    }
-}
-
-impl Drop for RecordingBuffer {
-    fn drop(&mut self) {
-        unsafe {
-            libc::munmap(self.base as *mut libc::c_void, self.size);
-        }
-    }
-}
+struct RecordingBuffer(Box<dyn RecordingStorage>);
 
 #[derive(Debug)]
 struct RecordingStream {
-    buffer: OwnedRwLockReadGuard<RecordingBuffer>,
-    chunk: *const u8,
+    buffer: Arc<OwnedRwLockReadGuard<RecordingBuffer>>,
+    offset: usize,
+    end: usize,
     info: RecordingBufferInfo,
 }
 
-unsafe impl Send for RecordingStream {}
-
 impl RecordingStream {
-    async fn new(
+    fn new(
         buffer: OwnedRwLockReadGuard<RecordingBuffer>,
         metadata: &RecordingMeta,
-        ip_core: &std::sync::Mutex<IpCore>,
-    ) -> Result<RecordingStream> {
-        let info = RecordingBufferInfo::new(metadata, ip_core).await?;
-        // chunk is a *const u8, which is not Send, so it must not be held
-        // accross an await point.
-        let chunk = buffer.base;
-        Ok(RecordingStream {
-            buffer,
-            chunk,
+    ) -> RecordingStream {
+        let mut info = RecordingBufferInfo::new(metadata, &buffer);
+        // Restrict the stream to the trimmed portion of the recording
+        let bytes_per_item = info.input_bytes_per_item;
+        let trim_bytes = |sample: u64| {
+            usize::try_from(sample)
+                .unwrap_or(usize::MAX)
+                .saturating_mul(bytes_per_item)
+                .min(info.size)
+        };
+        let offset = trim_bytes(metadata.trim.start_sample);
+        let end = metadata
+            .trim
+            .end_sample
+            .map_or(info.size, trim_bytes)
+            .max(offset);
+        info.size = end - offset;
+        RecordingStream {
+            buffer: Arc::new(buffer),
+            offset,
+            end,
             info,
-        })
+        }
+    }
+
+    // Returns a stream that contains a range of the items of this stream.
+    fn items(&self, items: Range<usize>) -> RecordingStream {
+        let bytes_per_item = self.info.input_bytes_per_item;
+        RecordingStream {
+            buffer: Arc::clone(&self.buffer),
+            offset: self.offset + (items.start * bytes_per_item).min(self.info.size),
+            end: self.offset + (items.end * bytes_per_item).min(self.info.size),
+            info: self.info.clone(),
+        }
     }
 }
 
@@ -570,8 +1913,8 @@ impl Stream for RecordingStream {
     type Item = Result<Bytes, std::io::Error>;
 
     fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let offset = unsafe { self.chunk.offset_from(self.buffer.base) as usize };
-        let remaining = self.info.size - offset;
+        let offset = self.offset;
+        let remaining = self.end - offset;
         if remaining < self.info.input_bytes_per_item {
             return Poll::Ready(None);
         }
@@ -584,22 +1927,43 @@ impl Stream for RecordingStream {
                 (chunk_items * self.info.input_bytes_per_item, chunk_items)
             }
         };
-        let data = unsafe { std::slice::from_raw_parts(self.chunk, chunk_bytes) };
+        let data = &self.buffer.0.as_slice()[offset..offset + chunk_bytes];
         let bytes = match self.info.mode.0 {
-            RecorderMode::IQ8bit | RecorderMode::IQ16bit => Bytes::copy_from_slice(data),
+            // These modes do not need any conversion, so the chunk is handed
+            // out without copying it from the recording buffer.
+            RecorderMode::IQ8bit | RecorderMode::IQ16bit => Bytes::from_owner(RecordingChunk {
+                buffer: Arc::clone(&self.buffer),
+                range: offset..offset + chunk_bytes,
+            }),
             RecorderMode::IQ12bit => {
                 let mut bytes =
                     BytesMut::zeroed(self.info.mode.output_bytes_per_item() * chunk_items);
-                unpack_12bit_to_16bit(&mut bytes[..], data);
+                samples::unpack_12bit_to_16bit(&mut bytes[..], data);
                 Bytes::from(bytes)
             }
         };
-        self.chunk = unsafe { self.chunk.add(chunk_bytes) };
+        self.offset += chunk_bytes;
         Poll::Ready(Some(Ok(bytes)))
     }
 }
 
+// Chunk of the recording buffer.
+//
+// The chunk holds a read lock on the recording buffer, so that the recording
+// buffer cannot be overwritten while the chunk is alive.
 #[derive(Debug)]
+struct RecordingChunk {
+    buffer: Arc<OwnedRwLockReadGuard<RecordingBuffer>>,
+    range: Range<usize>,
+}
+
+impl AsRef<[u8]> for RecordingChunk {
+    fn as_ref(&self) -> &[u8] {
+        &self.buffer.0.as_slice()[self.range.clone()]
+    }
+}
+
+#[derive(Debug, Clone)]
 struct RecordingBufferInfo {
     size: usize,
     mode: Mode,
@@ -608,36 +1972,24 @@ struct RecordingBufferInfo {
 }
 
 impl RecordingBufferInfo {
-    async fn new(
-        metadata: &RecordingMeta,
-        ip_core: &std::sync::Mutex<IpCore>,
-    ) -> Result<RecordingBufferInfo> {
-        let base_address = usize::from_str_radix(
-            fs::read_to_string(
-                "/sys/class/maia-sdr/maia-sdr-recording/device/recording_base_address",
-            )
-            .await?
-            .trim_end()
-            .trim_start_matches("0x"),
-            16,
-        )?;
-        let next_address = ip_core.lock().unwrap().recorder_next_address();
-
+    // The size of the recording is the size of the latest recording in the
+    // recording storage.
+    fn new(metadata: &RecordingMeta, buffer: &RecordingBuffer) -> RecordingBufferInfo {
         let mode = Mode(metadata.mode);
         let input_bytes_per_item = mode.input_bytes_per_item();
         let max_size = metadata
             .max_samples()
             .map(|items| items * input_bytes_per_item);
-        let size = next_address - base_address;
+        let size = buffer.0.recording_size();
         // Constrain size <= max_size if max_size.is_some()
         let size = max_size.map(|x| x.min(size)).unwrap_or(size);
 
-        Ok(RecordingBufferInfo {
+        RecordingBufferInfo {
             size,
             mode,
             input_bytes_per_item,
             chunk_bytes: input_bytes_per_item * Self::CHUNK_ITEMS,
-        })
+        }
     }
 
     fn output_size(&self) -> usize {
@@ -669,13 +2021,1428 @@ impl Mode {
             RecorderMode::IQ12bit | RecorderMode::IQ16bit => 4,
         }
     }
+
+    // Converts recorded data to IQ samples normalized to full scale.
+    fn iq_samples(&self, data: &[u8]) -> Vec<Complex32> {
+        match self.0 {
+            RecorderMode::IQ8bit => samples::ci8_to_complex(data),
+            RecorderMode::IQ12bit => samples::ci12_to_complex(data),
+            RecorderMode::IQ16bit => samples::ci16_to_complex(data, 32768.0),
+        }
+    }
+
+    // Converts recorded data to the format of the SigMF data file.
+    fn sigmf_data(&self, data: &[u8]) -> Vec<u8> {
+        match self.0 {
+            RecorderMode::IQ8bit | RecorderMode::IQ16bit => data.to_vec(),
+            RecorderMode::IQ12bit => {
+                let num_items = data.len() / self.input_bytes_per_item();
+                let mut bytes = vec![0; num_items * self.output_bytes_per_item()];
+                samples::unpack_12bit_to_16bit(&mut bytes, data);
+                bytes
+            }
+        }
+    }
+
+    // Counts the number of samples in which the real or imaginary part is at
+    // full scale. The data is given in the format of the SigMF data file.
+    fn clipped_samples(&self, data: &[u8]) -> usize {
+        match self.0 {
+            RecorderMode::IQ8bit => data
+                .chunks_exact(2)
+                .filter(|x| x.iter().any(|&y| matches!(y as i8, i8::MIN | i8::MAX)))
+                .count(),
+            RecorderMode::IQ12bit => Self::clipped_samples_16bit(data, -2048, 2047),
+            RecorderMode::IQ16bit => Self::clipped_samples_16bit(data, i16::MIN, i16::MAX),
+        }
+    }
+
+    fn clipped_samples_16bit(data: &[u8], min: i16, max: i16) -> usize {
+        data.chunks_exact(4)
+            .filter(|x| {
+                x.chunks_exact(2).any(|y| {
+                    let y = i16::from_le_bytes([y[0], y[1]]);
+                    y <= min || y >= max
+                })
+            })
+            .count()
+    }
 }
 
-fn unpack_12bit_to_16bit(output: &mut [u8], input: &[u8]) {
-    for (j, x) in input.chunks_exact(3).enumerate() {
-        output[4 * j] = (x[0] << 4) | (x[1] >> 4);
-        output[4 * j + 1] = ((x[0] & 0xf0) as i8 >> 4) as u8;
-        output[4 * j + 2] = x[2];
-        output[4 * j + 3] = ((x[1] << 4) as i8 >> 4) as u8;
+#[cfg(test)]
+mod test {
+    use super::clips::{
+        delete_clips, delete_hits, delete_hopping_recordings, get_clips, get_clips_archive,
+        get_hits, get_hits_archive, get_hopping_archive, get_hopping_recordings,
+    };
+    use super::hopping::{delete_hopping, get_hopping, put_hopping};
+    use super::*;
+
+    // Spawns the interrupt handler and the task that finishes the recordings,
+    // which are needed for the recorder to work.
+    fn spawn_recorder(state: &AppState, interrupt_handler: crate::fpga::InterruptHandler) {
+        let recorder_finish = RecorderFinishWaiter::new(
+            state.clone(),
+            interrupt_handler.waiter_recorder(),
+            RecordingHooks::default(),
+        );
+        tokio::spawn(interrupt_handler.run());
+        tokio::spawn(recorder_finish.run());
+    }
+
+    // Changes the state of the recorder, using the 8-bit IQ mode.
+    async fn patch_state(
+        state: &AppState,
+        state_change: maia_json::RecorderStateChange,
+    ) -> Result<Json<maia_json::Recorder>, JsonError> {
+        patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                state_change: Some(state_change),
+                mode: Some(RecorderMode::IQ8bit),
+                ..Default::default()
+            }),
+        )
+        .await
+    }
+
+    // Makes an 8-bit IQ recording of the given duration and waits for the
+    // recorder to stop.
+    async fn record_for(state: &AppState, duration: Duration) {
+        let Json(recorder) = patch_state(state, maia_json::RecorderStateChange::Start)
+            .await
+            .unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Running);
+        tokio::time::sleep(duration).await;
+        let Json(recorder) = patch_state(state, maia_json::RecorderStateChange::Stop)
+            .await
+            .unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Stopping);
+        wait_stopped(state).await;
+    }
+
+    // Waits for the recorder to reach the stopped state.
+    async fn wait_stopped(state: &AppState) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !state.recorder().is_stopped().await {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn start_stop_recording() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        let patch = |state_change| patch_state(&state, state_change);
+
+        // recordings cannot be started while a survey runs
+        let tuner = state.tuner().acquire(TunerOwner::Survey).unwrap();
+        assert!(patch(maia_json::RecorderStateChange::Start).await.is_err());
+        drop(tuner);
+
+        let Json(recorder) = patch(maia_json::RecorderStateChange::Start).await.unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Running);
+        // the recording cannot be downloaded while it is in progress
+        assert!(
+            get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+                .await
+                .is_err()
+        );
+        let Json(recorder) = patch(maia_json::RecorderStateChange::Stop).await.unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Stopping);
+        wait_stopped(&state).await;
+
+        let (headers, _) =
+            get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+                .await
+                .unwrap();
+        let size: usize = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        assert!(size > 0);
+        let (headers, body) = get_recording(
+            State(state.clone()),
+            None,
+            Query(RecordingQuery {
+                format: RecordingFormat::DigitalRf,
+            }),
+        )
+        .await
+        .unwrap();
+        let size: usize = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        let tar = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(tar.len(), size);
+        let path = b"/ch0/drf_properties.h5";
+        assert!(tar.windows(path.len()).any(|w| w == path));
+        let (headers, body) = get_recording(
+            State(state.clone()),
+            None,
+            Query(RecordingQuery {
+                format: RecordingFormat::Wav,
+            }),
+        )
+        .await
+        .unwrap();
+        let size: usize = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        let wav = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(wav.len(), size);
+        assert_eq!(&wav[..4], b"RIFF");
+
+        let preview = |decimation| {
+            preview::get_preview(
+                State(state.clone()),
+                Query(
+                    serde_json::from_value(serde_json::json!({ "decimation": decimation }))
+                        .unwrap(),
+                ),
+            )
+        };
+        assert!(preview(0).await.is_err());
+        let (headers, body) = preview(100).await.unwrap();
+        let size: usize = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        let tar = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(tar.len(), size);
+        let datatype = b"\"core:datatype\": \"cf32_le\"";
+        assert!(tar.windows(datatype.len()).any(|w| w == datatype));
+        // without decimation, the preview contains all the samples
+        let (_, body) = preview(1).await.unwrap();
+        let (_, data) = sigmf_archive_contents(body).await;
+        let num_items = {
+            let buffer = state.recorder().buffer.read().await;
+            let metadata = state.recorder().metadata.lock().await;
+            RecordingBufferInfo::new(&metadata, &buffer).num_items()
+        };
+        assert_eq!(data.len(), 8 * num_items);
+
+        let stats = |bins| {
+            stats::get_stats(
+                State(state.clone()),
+                Query(serde_json::from_value(serde_json::json!({ "bins": bins })).unwrap()),
+            )
+        };
+        assert!(stats(0).await.is_err());
+        let Json(stats) = stats(16).await.unwrap();
+        assert_eq!(stats.histogram.len(), 16);
+        assert_eq!(stats.histogram.iter().sum::<u64>(), 2 * stats.num_samples);
+        assert!(stats.clipped_samples <= stats.num_samples);
+
+        // the checksum is computed in the background
+        let checksum = tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let Json(checksum) = get_recording_checksum(State(state.clone())).await;
+                if !checksum.in_progress {
+                    break checksum;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let sha512 = checksum.sha512.unwrap();
+        let clipping = recorder_json(&state).await.unwrap().clipping.unwrap();
+        assert!((0.0..=100.0).contains(&clipping));
+        // the statistics and the analysis count the same clipped samples
+        let stats_clipping = if stats.num_samples == 0 {
+            0.0
+        } else {
+            100.0 * stats.clipped_samples as f64 / stats.num_samples as f64
+        };
+        assert!((clipping - stats_clipping).abs() < 1e-9);
+        let (_, body) = get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+            .await
+            .unwrap();
+        let tar = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let mut archive = tokio_tar::Archive::new(&tar[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut num_checked = 0;
+        while let Some(entry) = futures::StreamExt::next(&mut entries).await {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().to_string();
+            let mut contents = Vec::new();
+            tokio::io::AsyncReadExt::read_to_end(&mut entry, &mut contents)
+                .await
+                .unwrap();
+            if path.ends_with(".sigmf-meta") {
+                let meta: serde_json::Value = serde_json::from_slice(&contents).unwrap();
+                assert_eq!(meta["global"]["core:sha512"], sha512.as_str());
+                num_checked += 1;
+            } else if path.ends_with(".sigmf-data") {
+                use sha2::Digest;
+                let hash = sha2::Sha512::digest(&contents);
+                let hash = hash.iter().map(|x| format!("{x:02x}")).collect::<String>();
+                assert_eq!(hash, sha512);
+                num_checked += 1;
+            }
+        }
+        assert_eq!(num_checked, 2);
+        // the mock IP core has a sample counter
+        let sample_time = state
+            .recorder()
+            .metadata
+            .lock()
+            .await
+            .sigmf_meta
+            .sample_time()
+            .unwrap();
+        assert_eq!(sample_time.decimation, 1);
+        assert!(sample_time.start_sample > 0);
+    }
+
+    // Returns the contents of the SigMF metadata and data files of a SigMF
+    // archive.
+    async fn sigmf_archive_contents(body: Body) -> (serde_json::Value, Vec<u8>) {
+        let tar = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let mut archive = tokio_tar::Archive::new(&tar[..]);
+        let mut entries = archive.entries().unwrap();
+        let mut meta = None;
+        let mut data = None;
+        while let Some(entry) = futures::StreamExt::next(&mut entries).await {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_str().unwrap().to_string();
+            let mut contents = Vec::new();
+            tokio::io::AsyncReadExt::read_to_end(&mut entry, &mut contents)
+                .await
+                .unwrap();
+            if path.ends_with(".sigmf-meta") {
+                meta = Some(serde_json::from_slice(&contents).unwrap());
+            } else if path.ends_with(".sigmf-data") {
+                data = Some(contents);
+            }
+        }
+        (meta.unwrap(), data.unwrap())
+    }
+
+    #[tokio::test]
+    async fn export_region() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        record_for(&state, Duration::from_millis(10)).await;
+
+        let (sample_rate, frequency) = {
+            let metadata = state.recorder().metadata.lock().await;
+            (
+                metadata.sigmf_meta.sample_rate(),
+                metadata.sigmf_meta.frequency(),
+            )
+        };
+        let region = |frequency_min: f64, frequency_max: f64| {
+            region::get_region(
+                State(state.clone()),
+                Query(
+                    serde_json::from_value(serde_json::json!({
+                        "frequency_min": frequency_min,
+                        "frequency_max": frequency_max,
+                    }))
+                    .unwrap(),
+                ),
+            )
+        };
+        // the frequency range must overlap the recording
+        assert!(
+            region(frequency + sample_rate, frequency + 2.0 * sample_rate)
+                .await
+                .is_err()
+        );
+        let (headers, body) = region(frequency, frequency + 0.125 * sample_rate)
+            .await
+            .unwrap();
+        let size: usize = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        let (meta, data) = sigmf_archive_contents(body).await;
+        assert!(size > data.len());
+        assert_eq!(
+            meta["global"]["core:sample_rate"].as_f64().unwrap(),
+            sample_rate / 8.0
+        );
+        let region_frequency = meta["captures"][0]["core:frequency"].as_f64().unwrap();
+        assert!((region_frequency - (frequency + 0.0625 * sample_rate)).abs() < 1e-3);
+        // the region can be restricted in time
+        let start = state
+            .recorder()
+            .metadata
+            .lock()
+            .await
+            .sigmf_meta
+            .datetime()
+            .timestamp_micros() as f64
+            * 1e-3;
+        let (_, body) = region::get_region(
+            State(state.clone()),
+            Query(
+                serde_json::from_value(serde_json::json!({
+                    "start_time": start,
+                    "end_time": start + 1.0,
+                    "frequency_min": frequency,
+                    "frequency_max": frequency + 0.125 * sample_rate,
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        let (_, time_data) = sigmf_archive_contents(body).await;
+        assert!(time_data.len() < data.len());
+        // cf32 samples use 8 bytes
+        let expected_samples = ((1e-3 * sample_rate).round() as usize).div_ceil(8);
+        assert_eq!(time_data.len() / 8, expected_samples);
+    }
+
+    #[tokio::test]
+    async fn processing_job() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        record_for(&state, Duration::from_millis(10)).await;
+
+        let (sample_rate, frequency) = {
+            let metadata = state.recorder().metadata.lock().await;
+            (
+                metadata.sigmf_meta.sample_rate(),
+                metadata.sigmf_meta.frequency(),
+            )
+        };
+        let config = maia_json::PutDDCConfig {
+            frequency: Hertz(100e3),
+            fir1: maia_json::DDCFIRConfig {
+                coefficients: vec![1; 16],
+                decimation: 4,
+            },
+            fir2: Some(maia_json::DDCFIRConfig {
+                coefficients: vec![1; 8],
+                decimation: 2,
+            }),
+            fir3: None,
+            transition_bandwidth: None,
+        };
+        // the configuration and the design cannot be given at the same time
+        let post = maia_json::PostProcessingJob {
+            config: Some(config.clone()),
+            design: Some(maia_json::PutDDCDesign {
+                frequency: Hertz(0.0),
+                decimation: 8,
+                transition_bandwidth: None,
+                passband_ripple: None,
+                stopband_attenuation_db: None,
+                stopband_one_over_f: None,
+            }),
+            ..Default::default()
+        };
+        assert!(
+            processing::post_processing_job(State(state.clone()), Json(post))
+                .await
+                .is_err()
+        );
+        let post = maia_json::PostProcessingJob {
+            filename: Some("narrowband".to_string()),
+            config: Some(config),
+            ..Default::default()
+        };
+        let Json(job) = processing::post_processing_job(State(state.clone()), Json(post))
+            .await
+            .unwrap();
+        assert_eq!(job.decimation, 8);
+        assert_eq!(job.output_sampling_frequency, Hertz(sample_rate / 8.0));
+        assert_eq!(job.frequency, Hertz(frequency + 100e3));
+        let id = job.id;
+        let job = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let Json(job) =
+                    processing::get_processing_job(State(state.clone()), axum::extract::Path(id))
+                        .await
+                        .unwrap();
+                if job.state != maia_json::ProcessingJobState::Running {
+                    break job;
+                }
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(job.state, maia_json::ProcessingJobState::Finished);
+        assert_eq!(job.progress, 1.0);
+        let Json(jobs) =
+            processing::get_processing_jobs(State(state.clone()), Query(Default::default()))
+                .await
+                .unwrap();
+        assert_eq!(jobs.jobs, vec![job.clone()]);
+
+        let (headers, body) =
+            processing::get_processing_output(State(state.clone()), axum::extract::Path(id))
+                .await
+                .unwrap();
+        assert!(headers[CONTENT_DISPOSITION]
+            .to_str()
+            .unwrap()
+            .contains("narrowband.sigmf"));
+        let (meta, data) = sigmf_archive_contents(body).await;
+        assert_eq!(data.len(), job.size);
+        assert_eq!(meta["global"]["core:datatype"], "cf32_le");
+        assert_eq!(
+            meta["global"]["core:sample_rate"].as_f64().unwrap(),
+            sample_rate / 8.0
+        );
+
+        let Json(deleted) =
+            processing::delete_processing_job(State(state.clone()), axum::extract::Path(id))
+                .await
+                .unwrap();
+        assert_eq!(deleted.id, id);
+        assert!(
+            processing::get_processing_job(State(state.clone()), axum::extract::Path(id))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_zero_copy() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        record_for(&state, Duration::from_millis(10)).await;
+        // wait for the analysis of the recording to release the buffer
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while state.recorder().buffer.try_write().is_err() {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        let buffer = state.recorder().buffer.clone().try_read_owned().unwrap();
+        let metadata = state.recorder().metadata.lock().await.clone();
+        let mut stream = RecordingStream::new(buffer, &metadata);
+        let data = stream.buffer.0.as_slice();
+        let data = data[..stream.info.size].as_ptr_range();
+        let chunk = stream.next().await.unwrap().unwrap();
+        assert!(!chunk.is_empty());
+        // the chunk points into the recording buffer
+        assert_eq!(chunk.as_ptr(), data.start);
+        drop(stream);
+        // the chunk keeps the recording buffer locked
+        assert!(state.recorder().buffer.try_write().is_err());
+        drop(chunk);
+        assert!(state.recorder().buffer.try_write().is_ok());
+    }
+
+    #[tokio::test]
+    async fn recording_transfers() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        // The recording must be larger than the download duplex, so that the
+        // download keeps the recording buffer locked until it is read.
+        record_for(&state, Duration::from_millis(50)).await;
+
+        let client: SocketAddr = "192.0.2.1:40000".parse().unwrap();
+        let (headers, body) = get_recording(
+            State(state.clone()),
+            Some(ConnectInfo(client)),
+            Query(RecordingQuery::default()),
+        )
+        .await
+        .unwrap();
+        let size: u64 = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        let Json(transfers) =
+            transfers::get_recorder_transfers(State(state.clone()), Query(Default::default()))
+                .await
+                .unwrap();
+        assert_eq!(transfers.transfers.len(), 1);
+        let transfer = &transfers.transfers[0];
+        assert_eq!(transfer.client.as_deref(), Some("192.0.2.1:40000"));
+        assert_eq!(transfer.size, size);
+        assert!(transfer.filename.ends_with(".sigmf"));
+
+        // a new recording cannot be started during the download, and the error
+        // tells who is using the recording
+        let err = patch_state(&state, maia_json::RecorderStateChange::Start)
+            .await
+            .unwrap_err();
+        let err = serde_json::to_value(err).unwrap();
+        let description = err["error_description"].as_str().unwrap();
+        assert!(description.contains("download of"));
+        assert!(description.contains("192.0.2.1"));
+
+        let data = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(data.len() as u64, size);
+        let Json(transfers) =
+            transfers::get_recorder_transfers(State(state.clone()), Query(Default::default()))
+                .await
+                .unwrap();
+        assert!(transfers.transfers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn trim_recording() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        let Json(recorder) = patch_state(&state, maia_json::RecorderStateChange::Start)
+            .await
+            .unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Running);
+        // the trim cannot be modified while recording
+        assert!(patch_recording_trim(
+            State(state.clone()),
+            Json(maia_json::PatchRecordingTrim::default())
+        )
+        .await
+        .is_err());
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        let Json(recorder) = patch_state(&state, maia_json::RecorderStateChange::Stop)
+            .await
+            .unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Stopping);
+        wait_stopped(&state).await;
+
+        let (_, body) = get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+            .await
+            .unwrap();
+        let (meta, data) = sigmf_archive_contents(body).await;
+        // 8-bit IQ samples use 2 bytes
+        let num_items = data.len() / 2;
+        assert!(num_items >= 4);
+        let (start, end) = (num_items / 4, num_items / 2);
+
+        let trim = |patch| patch_recording_trim(State(state.clone()), Json(patch));
+        assert!(trim(maia_json::PatchRecordingTrim {
+            start_sample: Some(0),
+            start_time: Some(0.0),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+        assert!(trim(maia_json::PatchRecordingTrim {
+            start_sample: Some(num_items as u64),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+        let Json(recording_trim) = trim(maia_json::PatchRecordingTrim {
+            start_sample: Some(start as u64),
+            end_sample: Some(end as u64),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            recording_trim,
+            maia_json::RecordingTrim {
+                start_sample: start as u64,
+                end_sample: Some(end as u64),
+            }
+        );
+        assert!(trim(maia_json::PatchRecordingTrim {
+            end_sample: Some(start as u64),
+            ..Default::default()
+        })
+        .await
+        .is_err());
+
+        let (headers, body) =
+            get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+                .await
+                .unwrap();
+        let size: usize = headers[CONTENT_LENGTH].to_str().unwrap().parse().unwrap();
+        let tar = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        assert_eq!(tar.len(), size);
+        let (trimmed_meta, trimmed_data) = sigmf_archive_contents(Body::from(tar)).await;
+        assert_eq!(trimmed_data, data[2 * start..2 * end]);
+        assert!(trimmed_meta["global"].get("core:sha512").is_none());
+        // the mock IP core has a sample counter
+        let sample_counter =
+            |meta: &serde_json::Value| meta["global"]["maia:sample_counter"].as_u64().unwrap();
+        assert_eq!(
+            sample_counter(&trimmed_meta),
+            sample_counter(&meta) + start as u64
+        );
+
+        // the trim can also be given in seconds
+        let samp_rate = meta["global"]["core:sample_rate"].as_f64().unwrap();
+        let Json(recording_trim) = trim(maia_json::PatchRecordingTrim {
+            start_time: Some(0.0),
+            end_time: Some(start as f64 / samp_rate),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            recording_trim,
+            maia_json::RecordingTrim {
+                start_sample: 0,
+                end_sample: Some(start as u64),
+            }
+        );
+
+        let Json(recording_trim) = delete_recording_trim(State(state.clone())).await;
+        assert_eq!(recording_trim, maia_json::RecordingTrim::default());
+        let (_, body) = get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+            .await
+            .unwrap();
+        let (_, untrimmed_data) = sigmf_archive_contents(body).await;
+        assert_eq!(untrimmed_data, data);
+    }
+
+    // The clock is paused so that the clipping is estimated before the mock
+    // recorder fills the recording buffer, which only takes a fraction of a
+    // second.
+    #[tokio::test(start_paused = true)]
+    async fn clipping_in_progress() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        let Json(recorder) = patch_state(&state, maia_json::RecorderStateChange::Start)
+            .await
+            .unwrap();
+        assert!(recorder.clipping.is_none());
+        // the clipping is estimated while the recording is running
+        let clipping = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                let recorder = recorder_json(&state).await.unwrap();
+                assert_eq!(recorder.state, maia_json::RecorderState::Running);
+                if let Some(clipping) = recorder.clipping {
+                    return clipping;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!((0.0..=100.0).contains(&clipping));
+        let Json(recorder) = patch_state(&state, maia_json::RecorderStateChange::Stop)
+            .await
+            .unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Stopping);
+        wait_stopped(&state).await;
+    }
+
+    #[test]
+    fn clipped_samples() {
+        let data = [0, 0, 127, 0, 0, 0x80, 5, 7];
+        assert_eq!(Mode(RecorderMode::IQ8bit).clipped_samples(&data), 2);
+        let data = [
+            0xff, 0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf8, 0x00, 0x00, 0xfe, 0x07,
+        ];
+        assert_eq!(Mode(RecorderMode::IQ12bit).clipped_samples(&data), 2);
+        assert_eq!(Mode(RecorderMode::IQ16bit).clipped_samples(&data), 0);
+    }
+
+    #[tokio::test]
+    async fn capture_iq() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        for mode in [
+            RecorderMode::IQ8bit,
+            RecorderMode::IQ12bit,
+            RecorderMode::IQ16bit,
+        ] {
+            state.ip_core().lock().unwrap().set_recorder_mode(mode);
+            let capture = state
+                .recorder()
+                // the duration must be longer than the tick of the mock IP core
+                .capture_iq(state.ip_core(), Duration::from_millis(10))
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(capture.start_sample.is_some());
+            let samples = capture.samples;
+            assert!(!samples.is_empty());
+            // the synthetic signal is well below full scale
+            assert!(samples.iter().all(|x| x.norm() < 1.0));
+            let power = samples.iter().map(|x| x.norm_sqr()).sum::<f32>() / samples.len() as f32;
+            assert!(power > 1e-4);
+        }
+        // the capture does not modify the metadata of the last recording
+        let metadata = state.recorder().metadata.lock().await;
+        assert_eq!(metadata.recorder_state, maia_json::RecorderState::Stopped);
+        assert!(metadata.sigmf_meta.sample_time().is_none());
+    }
+
+    #[tokio::test]
+    async fn capture_keeps_recording() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        record_for(&state, Duration::from_millis(10)).await;
+
+        let capture = || {
+            state
+                .recorder()
+                .capture_iq(state.ip_core(), Duration::from_millis(10))
+        };
+        assert_eq!(
+            capture().await.unwrap().unwrap_err(),
+            RecorderBusy::RecordingStored
+        );
+        // the capture can be made once the recording is discarded and its
+        // analysis has finished
+        delete_recording(State(state.clone())).await.unwrap();
+        while get_recording_checksum(State(state.clone()))
+            .await
+            .in_progress
+        {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        assert!(capture().await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn arm_recording() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        let arm = |start_time| {
+            put_recorder_arm(
+                State(state.clone()),
+                Json(maia_json::RecorderArm { start_time }),
+            )
+        };
+        let wait_for_state = |recorder_state| {
+            let state = state.clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(2), async {
+                    while recorder_json(&state).await.unwrap().state != recorder_state {
+                        tokio::time::sleep(Duration::from_millis(1)).await;
+                    }
+                })
+                .await
+                .unwrap()
+            }
+        };
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs_f64() * 1e3;
+
+        // start time in the past
+        assert!(arm(Some(now - 1000.0)).await.is_err());
+
+        // arm to start at a given time
+        let Json(recorder) = arm(Some(now + 100.0)).await.unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Armed);
+        assert_eq!(recorder.armed.unwrap().start_time, Some(now + 100.0));
+        // the recorder cannot be armed again
+        assert!(arm(Some(now + 200.0)).await.is_err());
+        wait_for_state(maia_json::RecorderState::Running).await;
+        assert!(recorder_json(&state).await.unwrap().armed.is_none());
+        state.ip_core().lock().unwrap().recorder_stop();
+        wait_stopped(&state).await;
+
+        // arm to start on the trigger input and disarm
+        let Json(recorder) = arm(None).await.unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Armed);
+        assert!(
+            get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+                .await
+                .is_err()
+        );
+        let Json(recorder) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                state_change: Some(maia_json::RecorderStateChange::Stop),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert_ne!(recorder.state, maia_json::RecorderState::Armed);
+        wait_stopped(&state).await;
+        assert!(
+            get_recording(State(state.clone()), None, Query(RecordingQuery::default()))
+                .await
+                .is_ok()
+        );
+
+        // disarm and arm again before the first start time: the recording
+        // does not start at the first start time
+        let disarm = || {
+            patch_recorder(
+                State(state.clone()),
+                Json(maia_json::PatchRecorder {
+                    state_change: Some(maia_json::RecorderStateChange::Stop),
+                    ..Default::default()
+                }),
+            )
+        };
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs_f64() * 1e3;
+        assert!(arm(Some(now + 50.0)).await.is_ok());
+        assert!(disarm().await.is_ok());
+        assert!(arm(Some(now + 2000.0)).await.is_ok());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(
+            recorder_json(&state).await.unwrap().state,
+            maia_json::RecorderState::Armed
+        );
+        assert!(disarm().await.is_ok());
+        wait_stopped(&state).await;
+    }
+
+    #[tokio::test]
+    async fn clip_mode() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        let Json(recorder) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                mode: Some(RecorderMode::IQ8bit),
+                clip_mode: Some(true),
+                clip_duration: Some(Seconds(0.05)),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(recorder.clip_mode);
+        assert_eq!(recorder.clip_duration, Seconds(0.05));
+        // the clip duration is bounded
+        assert!(patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                clip_duration: Some(maia_json::PatchRecorder::MAX_CLIP_DURATION * 2.0),
+                ..Default::default()
+            }),
+        )
+        .await
+        .is_err());
+        // clip mode cannot be armed at a given time
+        let now = UNIX_EPOCH.elapsed().unwrap().as_secs_f64() * 1e3;
+        let arm = |start_time| {
+            put_recorder_arm(
+                State(state.clone()),
+                Json(maia_json::RecorderArm { start_time }),
+            )
+        };
+        assert!(arm(Some(now + 100.0)).await.is_err());
+        assert!(get_clips_archive(State(state.clone())).await.is_err());
+
+        // the mock trigger input fires once per second
+        assert!(arm(None).await.is_ok());
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while recorder_json(&state).await.unwrap().num_clips < 2 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        let Json(recorder) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                state_change: Some(maia_json::RecorderStateChange::Stop),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(recorder.num_clips >= 2);
+
+        let Json(clips) = get_clips(State(state.clone()), Query(Default::default()))
+            .await
+            .unwrap();
+        assert_eq!(clips.clips[0].id, 0);
+        assert_eq!(clips.clips[1].id, 1);
+        assert_eq!(clips.clips[0].filename, "recording_clip0000");
+        for clip in &clips.clips {
+            assert!((clip.duration - Seconds(0.05)).abs() < Seconds(1e-3));
+            assert_eq!(clip.size, (0.05 * clip.sample_rate.0).round() as usize * 2);
+        }
+        assert!(get_clips_archive(State(state.clone())).await.is_ok());
+        let Json(clips) = delete_clips(State(state.clone())).await;
+        assert!(clips.clips.is_empty());
+    }
+
+    #[tokio::test]
+    async fn hopping_recording() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        assert!(get_hopping_archive(State(state.clone())).await.is_err());
+        let original_frequency = state
+            .ad9361()
+            .lock()
+            .await
+            .get_rx_lo_frequency()
+            .await
+            .unwrap();
+        let put = maia_json::PutRecorderHopping {
+            frequencies: vec![Hertz(433e6), Hertz(868e6)],
+            // the duration must be longer than the tick of the mock IP core
+            burst_duration: Seconds(0.02),
+            settle_time: Seconds(0.0),
+            cycles: 2,
+        };
+        let Json(hopping) = put_hopping(State(state.clone()), Json(put.clone()))
+            .await
+            .unwrap();
+        assert!(hopping.running);
+        assert_eq!(hopping.total_segments, 4);
+        // only one frequency-hopping recording can be in progress
+        assert!(put_hopping(State(state.clone()), Json(put.clone()))
+            .await
+            .is_err());
+        // the RX frequency cannot be changed during the recording
+        let patch = maia_json::PatchAd9361 {
+            rx_lo_frequency: Some(Hertz(100_000_000.0)),
+            ..Default::default()
+        };
+        assert!(
+            crate::httpd::ad9361::patch_ad9361(State(state.clone()), Json(patch))
+                .await
+                .is_err()
+        );
+        let hopping = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let Json(hopping) = get_hopping(State(state.clone())).await;
+                if !hopping.running {
+                    break hopping;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(hopping.error, None);
+        assert_eq!(hopping.segments, 4);
+        assert_eq!(
+            state
+                .ad9361()
+                .lock()
+                .await
+                .get_rx_lo_frequency()
+                .await
+                .unwrap(),
+            original_frequency
+        );
+
+        let Json(recordings) =
+            get_hopping_recordings(State(state.clone()), Query(Default::default()))
+                .await
+                .unwrap();
+        assert_eq!(recordings.clips.len(), 1);
+        assert_eq!(recordings.clips[0].filename, "recording_hopping0000");
+        assert_eq!(recordings.clips[0].frequency, Hertz(433e6));
+        let (_, body) = get_hopping_archive(State(state.clone())).await.unwrap();
+        let (meta, data) = sigmf_archive_contents(body).await;
+        let captures = meta["captures"].as_array().unwrap();
+        assert_eq!(captures.len(), 4);
+        for (capture, frequency) in captures.iter().zip([433e6, 868e6, 433e6, 868e6]) {
+            assert_eq!(capture["core:frequency"], frequency);
+        }
+        assert_eq!(captures[0]["core:sample_start"], 0);
+        let last_start = captures[3]["core:sample_start"].as_u64().unwrap() as usize;
+        assert!(last_start > 0 && last_start < data.len() / 2);
+
+        // invalid recordings are rejected
+        assert!(put_hopping(
+            State(state.clone()),
+            Json(maia_json::PutRecorderHopping {
+                frequencies: Vec::new(),
+                ..put.clone()
+            }),
+        )
+        .await
+        .is_err());
+        assert!(put_hopping(
+            State(state.clone()),
+            Json(maia_json::PutRecorderHopping {
+                burst_duration: Seconds(10.0),
+                cycles: 1000,
+                ..put
+            }),
+        )
+        .await
+        .is_err());
+        let Json(hopping) = delete_hopping(State(state.clone())).await;
+        assert!(!hopping.running);
+        let Json(recordings) = delete_hopping_recordings(State(state.clone())).await;
+        assert!(recordings.clips.is_empty());
+    }
+
+    #[tokio::test]
+    async fn file_storage() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        let path = std::env::temp_dir().join(format!(
+            "maia-httpd-recording-file-{}.bin",
+            std::process::id()
+        ));
+        let backend = Arc::clone(state.ip_core().lock().unwrap().backend());
+        let recorder = state.recorder();
+        recorder
+            .set_storage(Box::new(
+                storage::FileStorage::open(&path, backend).await.unwrap(),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(recorder.buffer_size(), crate::mock::RECORDING_SIZE);
+        spawn_recorder(&state, interrupt_handler);
+        record_for(&state, Duration::from_millis(20)).await;
+        while get_recording_checksum(State(state.clone()))
+            .await
+            .in_progress
+        {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+
+        // the recording has been copied to the file, and its size is taken
+        // from the file
+        let metadata = recorder.metadata.lock().await.clone();
+        let buffer = recorder.buffer.clone().try_read_owned().unwrap();
+        let info = RecordingBufferInfo::new(&metadata, &buffer);
+        assert!(info.size > 0);
+        assert_eq!(
+            tokio::fs::metadata(&path).await.unwrap().len(),
+            info.size as u64
+        );
+        let data = RecordingStream::new(buffer, &metadata)
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat();
+        assert_eq!(data.len(), info.output_size());
+
+        // the storage is empty if the recording cannot be copied to the file
+        tokio::fs::remove_file(&path).await.unwrap();
+        tokio::fs::create_dir(&path).await.unwrap();
+        let mut buffer = recorder.buffer.clone().try_write_owned().unwrap();
+        assert!(recorder
+            .refresh_buffer(&mut buffer, state.ip_core())
+            .await
+            .is_err());
+        assert_eq!(RecordingBufferInfo::new(&metadata, &buffer).size, 0);
+        tokio::fs::remove_dir(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn hopping_limits() {
+        let (state, _) = crate::mock::app_state().await;
+        state
+            .limits()
+            .set(maia_json::Limits {
+                frequency_allowlist: vec![maia_json::LimitsFrequencyRange {
+                    start_frequency: Hertz(400_000_000.0),
+                    stop_frequency: Hertz(500_000_000.0),
+                }],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let put = maia_json::PutRecorderHopping {
+            frequencies: vec![Hertz(433e6), Hertz(868e6)],
+            burst_duration: Seconds(0.02),
+            settle_time: Seconds(0.0),
+            cycles: 1,
+        };
+        assert!(put_hopping(State(state.clone()), Json(put.clone()))
+            .await
+            .is_err());
+        let Json(hopping) = get_hopping(State(state.clone())).await;
+        assert!(!hopping.running);
+        assert!(hopping.config.is_none());
+        // the limits apply to the RX LO frequency, which includes the
+        // frequency offset of the device
+        let patch = maia_json::PatchDevice {
+            frequency_offset: Some(Hertz(-50_000_000.0)),
+            ..Default::default()
+        };
+        let _ = crate::httpd::device::patch_device(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        let put = maia_json::PutRecorderHopping {
+            frequencies: vec![Hertz(433e6), Hertz(480e6)],
+            ..put
+        };
+        assert!(put_hopping(State(state.clone()), Json(put.clone()))
+            .await
+            .is_err());
+        let patch = maia_json::PatchDevice {
+            frequency_offset: Some(Hertz(0.0)),
+            ..Default::default()
+        };
+        let _ = crate::httpd::device::patch_device(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        let Json(hopping) = put_hopping(State(state.clone()), Json(put)).await.unwrap();
+        assert!(hopping.running);
+        let _ = delete_hopping(State(state.clone())).await;
+    }
+
+    #[tokio::test]
+    async fn hopping_keeps_recording() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        record_for(&state, Duration::from_millis(10)).await;
+        let put = maia_json::PutRecorderHopping {
+            frequencies: vec![Hertz(433e6)],
+            burst_duration: Seconds(0.02),
+            settle_time: Seconds(0.0),
+            cycles: 1,
+        };
+        let err = put_hopping(State(state.clone()), Json(put))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            axum::response::IntoResponse::into_response(err).status(),
+            http::StatusCode::CONFLICT
+        );
+    }
+
+    #[tokio::test]
+    async fn scanner_hit() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        spawn_recorder(&state, interrupt_handler);
+        assert!(get_hits_archive(State(state.clone())).await.is_err());
+        let hit = ScannerHit {
+            filename: "scanner_100000000Hz".to_string(),
+            description: "Scanner hit at 100000000 Hz".to_string(),
+            duration: Duration::from_millis(50),
+        };
+        let recorder = state.recorder();
+        assert!(recorder
+            .record_hit(&state, hit.clone())
+            .await
+            .unwrap()
+            .is_ok());
+        // the recorder is in use until the hit finishes
+        assert_eq!(
+            recorder.record_hit(&state, hit.clone()).await.unwrap(),
+            Err(RecorderBusy::InUse)
+        );
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while get_hits(State(state.clone()), Query(Default::default()))
+                .await
+                .unwrap()
+                .clips
+                .is_empty()
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            recorder_json(&state).await.unwrap().state,
+            maia_json::RecorderState::Stopped
+        );
+        let Json(hits) = get_hits(State(state.clone()), Query(Default::default()))
+            .await
+            .unwrap();
+        assert_eq!(hits.clips[0].filename, "scanner_100000000Hz_hit0000");
+        assert!((hits.clips[0].duration - Seconds(0.05)).abs() < Seconds(1e-3));
+        // scanner hits are not stored as clips
+        assert!(get_clips(State(state.clone()), Query(Default::default()))
+            .await
+            .unwrap()
+            .clips
+            .is_empty());
+        assert!(get_hits_archive(State(state.clone())).await.is_ok());
+        let Json(hits) = delete_hits(State(state.clone())).await;
+        assert!(hits.clips.is_empty());
+
+        // hits do not overwrite a recording that has not been discarded
+        record_for(&state, Duration::from_millis(10)).await;
+        assert_eq!(
+            recorder.record_hit(&state, hit).await.unwrap(),
+            Err(RecorderBusy::RecordingStored)
+        );
+    }
+
+    #[tokio::test]
+    async fn metadata_templates() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let patch = maia_json::PatchRecordingMetadata {
+            filename: Some("rec_{freq_mhz}MHz_{mode}".to_string()),
+            description: Some("Recorded on {date} with {gain} dB gain".to_string()),
+            ..Default::default()
+        };
+        let Json(json) = patch_recording_metadata(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        assert_eq!(json.filename, "rec_{freq_mhz}MHz_{mode}");
+        let mut metadata = state.recorder().metadata.lock().await;
+        metadata.update_for_new_recording(&state).await.unwrap();
+        let frequency = metadata.sigmf_meta.frequency();
+        assert_eq!(
+            metadata.filename,
+            format!("rec_{}MHz_{:?}", frequency * 1e-6, metadata.mode)
+        );
+        let date = metadata.sigmf_meta.datetime().format("%Y-%m-%d");
+        assert_eq!(
+            metadata.sigmf_meta.description(),
+            format!("Recorded on {date} with 70 dB gain")
+        );
+        // The templates are kept for the next recording
+        let json = metadata.json();
+        assert_eq!(json.filename, "rec_{freq_mhz}MHz_{mode}");
+        assert_eq!(json.description, "Recorded on {date} with {gain} dB gain");
+    }
+
+    #[tokio::test]
+    async fn trigger_settings() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let Json(recorder) = get_recorder(State(state.clone())).await.unwrap();
+        assert_eq!(recorder.trigger_mode, maia_json::RecorderTriggerMode::Start);
+        assert_eq!(
+            recorder.trigger_polarity,
+            maia_json::RecorderTriggerPolarity::Rising
+        );
+        let Json(recorder) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                trigger_mode: Some(maia_json::RecorderTriggerMode::StartStop),
+                trigger_polarity: Some(maia_json::RecorderTriggerPolarity::Falling),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            recorder.trigger_mode,
+            maia_json::RecorderTriggerMode::StartStop
+        );
+        assert_eq!(
+            recorder.trigger_polarity,
+            maia_json::RecorderTriggerPolarity::Falling
+        );
+        // the other settings are not modified
+        assert_eq!(recorder.mode, RecorderMode::IQ16bit);
+    }
+
+    #[tokio::test]
+    async fn buffer_duration() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let samp_rate = f64::from(state.spectrometer_config().samp_rate());
+        let Json(recorder) = get_recorder(State(state.clone())).await.unwrap();
+        let buffer_size = crate::mock::RECORDING_SIZE as u64;
+        assert_eq!(recorder.buffer_size_bytes, buffer_size);
+        assert_eq!(
+            recorder.max_duration_at_current_settings,
+            Seconds((buffer_size / 4) as f64 / samp_rate)
+        );
+        // the duration gets longer with 8-bit samples
+        let Json(recorder) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                mode: Some(RecorderMode::IQ8bit),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(recorder.buffer_size_bytes, buffer_size);
+        assert_eq!(
+            recorder.max_duration_at_current_settings,
+            Seconds((buffer_size / 2) as f64 / samp_rate)
+        );
+    }
+
+    #[tokio::test]
+    async fn session_recovery() {
+        let file = std::env::temp_dir().join(format!(
+            "maia-httpd-recording-session-{}.json",
+            std::process::id()
+        ));
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        assert!(!state
+            .recorder()
+            .recover_session(&state, Some(file.clone()))
+            .await
+            .unwrap());
+        let Json(_) = patch_recording_metadata(
+            State(state.clone()),
+            Json(maia_json::PatchRecordingMetadata {
+                filename: Some("session".to_string()),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        let Json(_) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                state_change: Some(maia_json::RecorderStateChange::Start),
+                mode: Some(RecorderMode::IQ8bit),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(file.exists());
+
+        // A new instance recovers the recording and finishes it. The IP core
+        // keeps its registers when maia-httpd restarts.
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .set_recorder_mode(RecorderMode::IQ8bit);
+        assert!(state
+            .recorder()
+            .recover_session(&state, Some(file.clone()))
+            .await
+            .unwrap());
+        let recorder = recorder_json(&state).await.unwrap();
+        assert_eq!(recorder.state, maia_json::RecorderState::Stopping);
+        assert_eq!(recording_metadata_json(&state).await.filename, "session");
+        spawn_recorder(&state, interrupt_handler);
+        wait_stopped(&state).await;
+        assert_eq!(
+            state.recorder().metadata.lock().await.sigmf_meta.datatype(),
+            RecorderMode::IQ8bit.into()
+        );
+        assert!(!file.exists());
+    }
+
+    #[tokio::test]
+    async fn corrupt_session() {
+        let file = std::env::temp_dir().join(format!(
+            "maia-httpd-corrupt-session-{}.json",
+            std::process::id()
+        ));
+        let bad_file = file.with_extension("json.bad");
+        tokio::fs::write(&file, b"{\"filename\": ").await.unwrap();
+        let (state, _) = crate::mock::app_state().await;
+        assert!(state
+            .recorder()
+            .recover_session(&state, Some(file.clone()))
+            .await
+            .is_err());
+        assert!(state.recorder().is_stopped().await);
+        // the session file is put aside, so that the next start does not fail
+        assert!(!file.exists());
+        assert!(bad_file.exists());
+        assert!(!state
+            .recorder()
+            .recover_session(&state, Some(file.clone()))
+            .await
+            .unwrap());
+        tokio::fs::remove_file(&bad_file).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn stale_session() {
+        let file = std::env::temp_dir().join(format!(
+            "maia-httpd-stale-session-{}.json",
+            std::process::id()
+        ));
+        let bad_file = file.with_extension("json.bad");
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        state
+            .recorder()
+            .recover_session(&state, Some(file.clone()))
+            .await
+            .unwrap();
+        let Json(_) = patch_recorder(
+            State(state.clone()),
+            Json(maia_json::PatchRecorder {
+                state_change: Some(maia_json::RecorderStateChange::Start),
+                mode: Some(RecorderMode::IQ8bit),
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(file.exists());
+
+        // After a power cycle, the IP core registers have their reset values,
+        // so the session is discarded.
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        assert!(!state
+            .recorder()
+            .recover_session(&state, Some(file.clone()))
+            .await
+            .unwrap());
+        assert!(state.recorder().is_stopped().await);
+        assert!(!file.exists());
+        assert!(bad_file.exists());
+        tokio::fs::remove_file(&bad_file).await.unwrap();
     }
 }
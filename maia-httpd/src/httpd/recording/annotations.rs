@@ -0,0 +1,215 @@
+use super::super::json_error::JsonError;
+use crate::app::AppState;
+use crate::sigmf;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+// Maximum number of annotations of a recording.
+const MAX_ANNOTATIONS: usize = 1024;
+
+/// Annotations of the current recording.
+///
+/// The annotations are given by their time and frequency, so that they can be
+/// drawn on the waterfall. They are converted to SigMF annotations, which are
+/// given in samples, using the start time and sample rate of the recording.
+#[derive(Debug, Clone, Default)]
+pub struct Annotations {
+    annotations: Vec<maia_json::RecordingAnnotation>,
+    next_id: u64,
+}
+
+impl Annotations {
+    /// Returns the JSON representation of the annotations.
+    pub fn json(&self) -> maia_json::RecordingAnnotations {
+        maia_json::RecordingAnnotations {
+            annotations: self.annotations.clone(),
+        }
+    }
+
+    /// Removes all the annotations.
+    ///
+    /// The IDs of the annotations that are removed are not reused.
+    pub fn clear(&mut self) {
+        self.annotations.clear();
+    }
+
+    fn add(
+        &mut self,
+        post: maia_json::PostRecordingAnnotation,
+    ) -> anyhow::Result<maia_json::RecordingAnnotation> {
+        post.validate()?;
+        anyhow::ensure!(
+            self.annotations.len() < MAX_ANNOTATIONS,
+            "the recording can have at most {MAX_ANNOTATIONS} annotations"
+        );
+        let annotation = maia_json::RecordingAnnotation {
+            id: self.next_id,
+            start_time: post.start_time,
+            end_time: post.end_time,
+            frequency_min: post.frequency_min,
+            frequency_max: post.frequency_max,
+            label: post.label,
+            comment: post.comment,
+        };
+        self.next_id += 1;
+        self.annotations.push(annotation.clone());
+        Ok(annotation)
+    }
+
+    fn remove(&mut self, id: u64) -> Option<maia_json::RecordingAnnotation> {
+        let index = self
+            .annotations
+            .iter()
+            .position(|annotation| annotation.id == id)?;
+        Some(self.annotations.remove(index))
+    }
+
+    /// Converts the annotations to SigMF annotations.
+    ///
+    /// The recording starts at `recording_start`, given in milliseconds since
+    /// the UNIX epoch, and has a sample rate `sample_rate`. The annotations
+    /// that end before the start of the recording are omitted, and the rest
+    /// are clipped to the start of the recording.
+    pub fn sigmf(&self, recording_start: f64, sample_rate: f64) -> Vec<sigmf::Annotation> {
+        let sample = |time: f64| {
+            ((time - recording_start) * 1e-3 * sample_rate)
+                .round()
+                .max(0.0) as u64
+        };
+        self.annotations
+            .iter()
+            .filter_map(|annotation| {
+                let start = sample(annotation.start_time);
+                let end = sample(annotation.end_time);
+                (end > 0).then(|| sigmf::Annotation {
+                    sample_start: start,
+                    sample_count: end - start,
+                    freq_lower_edge: annotation.frequency_min.0,
+                    freq_upper_edge: annotation.frequency_max.0,
+                    label: annotation.label.clone(),
+                    comment: annotation.comment.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+fn not_found(id: u64) -> JsonError {
+    JsonError::not_found(anyhow::anyhow!("annotation {id} does not exist"))
+}
+
+pub async fn recording_annotations_json(state: &AppState) -> maia_json::RecordingAnnotations {
+    state.recorder().metadata.lock().await.annotations.json()
+}
+
+pub async fn get_recording_annotations(
+    State(state): State<AppState>,
+) -> Json<maia_json::RecordingAnnotations> {
+    Json(recording_annotations_json(&state).await)
+}
+
+pub async fn post_recording_annotation(
+    State(state): State<AppState>,
+    Json(post): Json<maia_json::PostRecordingAnnotation>,
+) -> Result<Json<maia_json::RecordingAnnotation>, JsonError> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    let annotation = metadata
+        .annotations
+        .add(post)
+        .map_err(JsonError::client_error_alert)?;
+    metadata.update_sigmf_annotations();
+    Ok(Json(annotation))
+}
+
+pub async fn delete_recording_annotations(
+    State(state): State<AppState>,
+) -> Json<maia_json::RecordingAnnotations> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    metadata.annotations.clear();
+    metadata.update_sigmf_annotations();
+    Json(metadata.annotations.json())
+}
+
+pub async fn delete_recording_annotation(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<maia_json::RecordingAnnotation>, JsonError> {
+    let mut metadata = state.recorder().metadata.lock().await;
+    let annotation = metadata
+        .annotations
+        .remove(id)
+        .ok_or_else(|| not_found(id))?;
+    metadata.update_sigmf_annotations();
+    Ok(Json(annotation))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::Hertz;
+
+    #[tokio::test]
+    async fn annotations() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let recording_start = state
+            .recorder()
+            .metadata
+            .lock()
+            .await
+            .sigmf_meta
+            .datetime()
+            .timestamp_micros() as f64
+            * 1e-3;
+        let post = |start: f64, end: f64, label: &str| maia_json::PostRecordingAnnotation {
+            start_time: recording_start + start,
+            end_time: recording_start + end,
+            frequency_min: Hertz(2399e6),
+            frequency_max: Hertz(2401e6),
+            label: label.to_string(),
+            comment: String::new(),
+        };
+        let add = |post| post_recording_annotation(State(state.clone()), Json(post));
+        let Json(first) = add(post(1000.0, 2000.0, "first")).await.unwrap();
+        let Json(second) = add(post(-2000.0, -1000.0, "before")).await.unwrap();
+        assert_eq!((first.id, second.id), (0, 1));
+        assert!(add(post(2000.0, 1000.0, "reversed")).await.is_err());
+        let Json(json) = get_recording_annotations(State(state.clone())).await;
+        assert_eq!(json.annotations, [first.clone(), second.clone()]);
+
+        {
+            // The annotation before the recording is omitted from the SigMF
+            // metadata. The first annotation starts 1 s after the recording.
+            let metadata = state.recorder().metadata.lock().await;
+            let sample_rate = metadata.sigmf_meta.sample_rate();
+            let annotations = metadata.sigmf_meta.annotations();
+            assert_eq!(annotations.len(), 1);
+            assert_eq!(annotations[0].label, "first");
+            assert!((annotations[0].sample_start as f64 - sample_rate).abs() < 1e-3 * sample_rate);
+            assert!((annotations[0].sample_count as f64 - sample_rate).abs() < 1e-3 * sample_rate);
+        }
+
+        let Json(removed) = delete_recording_annotation(State(state.clone()), Path(first.id))
+            .await
+            .unwrap();
+        assert_eq!(removed, first);
+        assert!(
+            delete_recording_annotation(State(state.clone()), Path(first.id))
+                .await
+                .is_err()
+        );
+        assert!(state
+            .recorder()
+            .metadata
+            .lock()
+            .await
+            .sigmf_meta
+            .annotations()
+            .is_empty());
+        let Json(json) = delete_recording_annotations(State(state.clone())).await;
+        assert!(json.annotations.is_empty());
+        let Json(third) = add(post(0.0, 10.0, "third")).await.unwrap();
+        assert_eq!(third.id, 2);
+    }
+}
@@ -0,0 +1,426 @@
+use super::super::{json_error::JsonError, list};
+use super::{round_up_multiple_512, tar_header, SigmfStream};
+use crate::app::AppState;
+use crate::sigmf;
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    Json,
+};
+use bytes::Bytes;
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH};
+use maia_json::{Hertz, Seconds};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWriteExt, BufWriter};
+
+// Maximum total size of the SigMF data of the clips, of the scanner hits and of
+// the frequency-hopping recordings that are stored. When this is exceeded, the
+// oldest clips are discarded.
+pub(super) const MAX_CLIPS_SIZE: usize = 64 << 20;
+pub(super) const MAX_HITS_SIZE: usize = 32 << 20;
+pub(super) const MAX_HOPPING_SIZE: usize = 64 << 20;
+// Clip duration used by default.
+pub(super) const DEFAULT_CLIP_DURATION: Duration = Duration::from_secs(1);
+
+/// Clips captured by the recorder in clip mode.
+///
+/// The clips are copied from the recording buffer after each of them finishes,
+/// so that the recording buffer can be used to capture the next clip. The same
+/// storage is used for the recordings of the scanner hits and for the
+/// frequency-hopping recordings.
+///
+/// The SigMF data of each clip is written to a file in a directory, which is
+/// given with [`Clips::set_directory`] or is a directory in the temporary
+/// directory otherwise. The files are removed when the clips are discarded.
+#[derive(Debug)]
+pub(super) struct Clips {
+    // Kind of clip, which is used in the file names
+    kind: &'static str,
+    max_size: usize,
+    directory: PathBuf,
+    clips: VecDeque<Clip>,
+    next_id: u64,
+    size: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Clip {
+    id: u64,
+    filename: String,
+    sigmf_meta: sigmf::Metadata,
+    // Path and size of the SigMF data file
+    path: PathBuf,
+    size: usize,
+    num_items: usize,
+}
+
+impl Clips {
+    // Creates an empty clip storage. The `kind` is used in the file names of
+    // the clips.
+    pub(super) fn new(kind: &'static str, max_size: usize) -> Clips {
+        // Each clip storage uses its own temporary directory, since there can
+        // be several recorders in the same process when running the tests.
+        static NEXT_DIRECTORY: AtomicU64 = AtomicU64::new(0);
+        let directory = std::env::temp_dir().join(format!(
+            "maia-httpd-{kind}-{}-{}",
+            std::process::id(),
+            NEXT_DIRECTORY.fetch_add(1, Ordering::Relaxed)
+        ));
+        Clips {
+            kind,
+            max_size,
+            directory,
+            clips: VecDeque::new(),
+            next_id: 0,
+            size: 0,
+        }
+    }
+
+    // Sets the directory where the SigMF data files are written. The files are
+    // written to a subdirectory named after the kind of clip. The clips stored
+    // so far and any files left in that subdirectory by a previous run are
+    // removed.
+    pub(super) fn set_directory(&mut self, directory: &Path) {
+        self.clear();
+        self.directory = directory.join(self.kind);
+        if let Err(err) = std::fs::remove_dir_all(&self.directory) {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                tracing::error!("failed to clean {}: {err}", self.directory.display());
+            }
+        }
+    }
+
+    // Reserves the ID of a new clip and returns it together with the path of
+    // its SigMF data file.
+    fn reserve(&mut self) -> (u64, PathBuf) {
+        let id = self.next_id;
+        self.next_id += 1;
+        (
+            id,
+            self.directory
+                .join(format!("{}{id:04}.sigmf-data", self.kind)),
+        )
+    }
+
+    // Stores a new clip whose data has been written by a ClipWriter,
+    // discarding the oldest clips if the maximum size is exceeded.
+    fn push(&mut self, clip: Clip) {
+        self.size += clip.size;
+        self.clips.push_back(clip);
+        while self.size > self.max_size {
+            let Some(clip) = self.clips.pop_front() else {
+                break;
+            };
+            tracing::warn!(
+                id = clip.id,
+                kind = self.kind,
+                "discarding clip: maximum clips size exceeded"
+            );
+            self.size -= clip.size;
+            remove_data_file(&clip.path);
+        }
+    }
+
+    // Returns the number of clips stored.
+    pub(super) fn len(&self) -> usize {
+        self.clips.len()
+    }
+
+    fn json(&self) -> maia_json::RecorderClips {
+        maia_json::RecorderClips {
+            clips: self
+                .clips
+                .iter()
+                .map(|clip| {
+                    let sample_rate = clip.sigmf_meta.sample_rate();
+                    maia_json::RecorderClip {
+                        id: clip.id,
+                        filename: clip.filename.clone(),
+                        start_time: clip.sigmf_meta.datetime().timestamp_micros() as f64 * 1e-3,
+                        duration: Seconds(clip.num_items as f64 / sample_rate),
+                        frequency: Hertz(clip.sigmf_meta.frequency()),
+                        sample_rate: Hertz(sample_rate),
+                        size: clip.size,
+                    }
+                })
+                .collect(),
+            total: self.clips.len(),
+        }
+    }
+
+    fn clear(&mut self) -> maia_json::RecorderClips {
+        for clip in self.clips.drain(..) {
+            remove_data_file(&clip.path);
+        }
+        self.size = 0;
+        self.json()
+    }
+}
+
+impl Drop for Clips {
+    fn drop(&mut self) {
+        self.clear();
+        // This fails if the directory was never created, or if it contains
+        // the file of a clip that is being written.
+        let _ = std::fs::remove_dir(&self.directory);
+    }
+}
+
+fn remove_data_file(path: &Path) {
+    if let Err(err) = std::fs::remove_file(path) {
+        tracing::error!("failed to remove {}: {err}", path.display());
+    }
+}
+
+/// Writer of the SigMF data file of a new clip.
+///
+/// The data is written to the file as it is produced, so that the clip does
+/// not need to be held in memory. The clip is added to the storage by
+/// [`ClipWriter::finish`]. If the writer is dropped before that, the file is
+/// removed.
+#[derive(Debug)]
+pub(super) struct ClipWriter {
+    id: u64,
+    path: PathBuf,
+    file: Option<BufWriter<tokio::fs::File>>,
+    size: usize,
+}
+
+impl ClipWriter {
+    // Creates the data file of a new clip in the storage.
+    pub(super) async fn create(clips: &std::sync::Mutex<Clips>) -> Result<ClipWriter> {
+        let (id, path) = clips.lock().unwrap().reserve();
+        if let Some(directory) = path.parent() {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .with_context(|| format!("failed to create {}", directory.display()))?;
+        }
+        let file = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        Ok(ClipWriter {
+            id,
+            path,
+            file: Some(BufWriter::new(file)),
+            size: 0,
+        })
+    }
+
+    // Appends data to the file.
+    pub(super) async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.file
+            .as_mut()
+            .unwrap()
+            .write_all(data)
+            .await
+            .with_context(|| format!("failed to write {}", self.path.display()))?;
+        self.size += data.len();
+        Ok(())
+    }
+
+    // Returns the number of bytes written so far.
+    pub(super) fn size(&self) -> usize {
+        self.size
+    }
+
+    // Finishes writing the file and stores the clip. The `filename` is used
+    // as a prefix of the file name of the clip.
+    pub(super) async fn finish(
+        mut self,
+        clips: &std::sync::Mutex<Clips>,
+        filename: &str,
+        sigmf_meta: sigmf::Metadata,
+        num_items: usize,
+    ) -> Result<()> {
+        let mut file = self.file.take().unwrap();
+        if let Err(err) = file.flush().await {
+            remove_data_file(&self.path);
+            return Err(err).with_context(|| format!("failed to write {}", self.path.display()));
+        }
+        let mut clips = clips.lock().unwrap();
+        let clip = Clip {
+            id: self.id,
+            filename: format!("{filename}_{}{:04}", clips.kind, self.id),
+            sigmf_meta,
+            path: std::mem::take(&mut self.path),
+            size: self.size,
+            num_items,
+        };
+        clips.push(clip);
+        Ok(())
+    }
+}
+
+impl Drop for ClipWriter {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            remove_data_file(&self.path);
+        }
+    }
+}
+
+pub async fn get_clips(
+    State(state): State<AppState>,
+    Query(query): Query<maia_json::ListQuery>,
+) -> Result<Json<maia_json::RecorderClips>, JsonError> {
+    let mut json = state.recorder().clips.lock().unwrap().json();
+    json.total = list::paginate(&mut json.clips, &query)?;
+    Ok(Json(json))
+}
+
+pub async fn delete_clips(State(state): State<AppState>) -> Json<maia_json::RecorderClips> {
+    Json(state.recorder().clips.lock().unwrap().clear())
+}
+
+/// Returns all the clips as a tar file.
+///
+/// The tar file contains a SigMF recording for each clip, stored in a
+/// directory named after the clip.
+pub async fn get_clips_archive(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    archive_response(&state.recorder().clips, "clips", "clips").await
+}
+
+pub async fn get_hits(
+    State(state): State<AppState>,
+    Query(query): Query<maia_json::ListQuery>,
+) -> Result<Json<maia_json::RecorderClips>, JsonError> {
+    let mut json = state.recorder().hits.lock().unwrap().json();
+    json.total = list::paginate(&mut json.clips, &query)?;
+    Ok(Json(json))
+}
+
+pub async fn delete_hits(State(state): State<AppState>) -> Json<maia_json::RecorderClips> {
+    Json(state.recorder().hits.lock().unwrap().clear())
+}
+
+/// Returns the recordings of all the scanner hits as a tar file.
+///
+/// The tar file has the same format as the one returned by
+/// [`get_clips_archive`].
+pub async fn get_hits_archive(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    archive_response(&state.recorder().hits, "hits", "hits").await
+}
+
+pub async fn get_hopping_recordings(
+    State(state): State<AppState>,
+    Query(query): Query<maia_json::ListQuery>,
+) -> Result<Json<maia_json::RecorderClips>, JsonError> {
+    let mut json = state.recorder().hopping_recordings.lock().unwrap().json();
+    json.total = list::paginate(&mut json.clips, &query)?;
+    Ok(Json(json))
+}
+
+pub async fn delete_hopping_recordings(
+    State(state): State<AppState>,
+) -> Json<maia_json::RecorderClips> {
+    Json(state.recorder().hopping_recordings.lock().unwrap().clear())
+}
+
+/// Returns all the frequency-hopping recordings as a tar file.
+///
+/// The tar file has the same format as the one returned by
+/// [`get_clips_archive`].
+pub async fn get_hopping_archive(
+    State(state): State<AppState>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    archive_response(
+        &state.recorder().hopping_recordings,
+        "hopping",
+        "frequency-hopping recordings",
+    )
+    .await
+}
+
+// Returns a response with the tar file of the clips. The `name` is used in the
+// file name of the tar file, and the `description` in the error returned when
+// there are no clips.
+async fn archive_response(
+    clips: &std::sync::Mutex<Clips>,
+    name: &str,
+    description: &str,
+) -> Result<(HeaderMap, Body), JsonError> {
+    let clips = clips
+        .lock()
+        .unwrap()
+        .clips
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+    if clips.is_empty() {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "there are no {description} to download"
+        )));
+    }
+    let filename = format!(
+        "{name}_{}",
+        clips[0].sigmf_meta.datetime().format("%Y-%m-%d-%H-%M-%S")
+    );
+    let (stream, size) = archive(clips).await.map_err(JsonError::server_error)?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{filename}.tar\"")
+            .parse()
+            .unwrap(),
+    );
+    headers.insert(CONTENT_LENGTH, size.to_string().parse().unwrap());
+    Ok((headers, Body::from_stream(stream)))
+}
+
+async fn archive(clips: Vec<Clip>) -> Result<(SigmfStream, usize)> {
+    const DUPLEX_SIZE: usize = 1 << 20;
+    let (duplex_write, duplex_read) = tokio::io::duplex(DUPLEX_SIZE);
+    let stream = tokio_util::io::ReaderStream::new(duplex_read);
+
+    let mut entries = Vec::with_capacity(3 * clips.len());
+    let mut tar_size = 0;
+    for clip in clips {
+        let filename = &clip.filename;
+        let sigmf_meta = Bytes::from(clip.sigmf_meta.to_json());
+        let timestamp = u64::try_from(clip.sigmf_meta.datetime().timestamp())?;
+        // The data file is opened here, so that the archive can still be
+        // written if the clip is discarded in the meantime.
+        let data = tokio::fs::File::open(&clip.path)
+            .await
+            .with_context(|| format!("failed to open {}", clip.path.display()))?;
+        let files: [(_, usize, Box<dyn AsyncRead + Send + Unpin>); 3] = [
+            (format!("{filename}/"), 0, Box::new(&[][..])),
+            (
+                format!("{filename}/{filename}.sigmf-meta"),
+                sigmf_meta.len(),
+                Box::new(std::io::Cursor::new(sigmf_meta)),
+            ),
+            (
+                format!("{filename}/{filename}.sigmf-data"),
+                clip.size,
+                Box::new(data),
+            ),
+        ];
+        for (path, size, data) in files {
+            tar_size += 512 + round_up_multiple_512(size);
+            entries.push((tar_header(&path, size, timestamp)?, data));
+        }
+    }
+    let tar_finish_size = 1024;
+    tar_size += tar_finish_size;
+
+    // Write tar into the duplex concurrently
+    tokio::spawn(async move {
+        let mut tar = tokio_tar::Builder::new(duplex_write);
+        for (header, data) in entries {
+            tar.append(&header, data).await?;
+        }
+        tar.into_inner().await?;
+        Ok::<(), anyhow::Error>(())
+    });
+
+    Ok((stream, tar_size))
+}
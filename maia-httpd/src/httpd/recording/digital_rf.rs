@@ -0,0 +1,107 @@
+use super::{
+    round_up_multiple_512, tar_header, RecorderMode, RecordingBuffer, RecordingMeta,
+    RecordingStream, SigmfStream,
+};
+use crate::digital_rf::Channel;
+use anyhow::Result;
+use tokio::io::AsyncReadExt;
+use tokio::sync::OwnedRwLockReadGuard;
+
+// Name of the Digital RF channel.
+const CHANNEL: &str = "ch0";
+
+/// Creates a tar file stream that contains the recording as a Digital RF
+/// channel.
+///
+/// The tar file contains a directory with the filename of the recording, which
+/// contains the Digital RF channel directory. The samples are stored as
+/// 8-bit or 16-bit integers, with 12-bit recordings being converted to 16-bit.
+pub async fn recording_stream(
+    buffer: OwnedRwLockReadGuard<RecordingBuffer>,
+    metadata: &RecordingMeta,
+) -> Result<(SigmfStream, usize)> {
+    const DUPLEX_SIZE: usize = 1 << 20;
+    let buffer = RecordingStream::new(buffer, metadata);
+    let (duplex_write, duplex_read) = tokio::io::duplex(DUPLEX_SIZE);
+    let stream = tokio_util::io::ReaderStream::new(duplex_read);
+
+    let component_size = match metadata.mode {
+        RecorderMode::IQ8bit => 1,
+        RecorderMode::IQ12bit | RecorderMode::IQ16bit => 2,
+    };
+    let channel = Channel::new(metadata.sigmf_meta.sample_rate(), component_size)?;
+    let datetime = metadata.sigmf_meta.datetime();
+    let timestamp = u64::try_from(datetime.timestamp())?;
+    let global_index = channel.global_index(datetime)?;
+    let rf_files = channel.rf_files(global_index, buffer.info.num_items().try_into()?);
+
+    let filename = &metadata.filename;
+    let channel_dir = format!("{filename}/{CHANNEL}");
+    let mut dirs = vec![
+        tar_header(&format!("{filename}/"), 0, timestamp)?,
+        tar_header(&format!("{channel_dir}/"), 0, timestamp)?,
+    ];
+    let properties = channel.properties_file()?;
+    let mut files = vec![(
+        tar_header(
+            &format!("{channel_dir}/drf_properties.h5"),
+            properties.len(),
+            timestamp,
+        )?,
+        properties,
+        None,
+    )];
+    let mut subdirs = Vec::new();
+    for (sequence_num, rf_file) in rf_files.iter().enumerate() {
+        let subdir = rf_file.path.split_once('/').unwrap().0;
+        if !subdirs.contains(&subdir) {
+            subdirs.push(subdir);
+            dirs.push(tar_header(
+                &format!("{channel_dir}/{subdir}/"),
+                0,
+                timestamp,
+            )?);
+        }
+        let header = channel.rf_file_header(rf_file, sequence_num, timestamp)?;
+        let start = usize::try_from(rf_file.recording_index)?;
+        let end = start + usize::try_from(rf_file.num_samples)?;
+        let size = header.len() + (end - start) * buffer.info.mode.output_bytes_per_item();
+        files.push((
+            tar_header(&format!("{channel_dir}/{}", rf_file.path), size, timestamp)?,
+            header,
+            Some(buffer.items(start..end)),
+        ));
+    }
+
+    let tar_header_size = 512;
+    let tar_finish_size = 1024;
+    let tar_size = tar_header_size * (dirs.len() + files.len())
+        + files
+            .iter()
+            .map(|(header, _, _)| round_up_multiple_512(header.size().unwrap() as usize))
+            .sum::<usize>()
+        + tar_finish_size;
+
+    // Write tar into the duplex concurrently
+    let mut tar = tokio_tar::Builder::new(duplex_write);
+    tokio::spawn(async move {
+        let dir_data: &[u8] = &[];
+        for header in &dirs {
+            tar.append(header, dir_data).await?;
+        }
+        for (header, contents, samples) in files {
+            let contents = std::io::Cursor::new(contents);
+            match samples {
+                Some(samples) => {
+                    let data = contents.chain(tokio_util::io::StreamReader::new(samples));
+                    tar.append(&header, data).await?
+                }
+                None => tar.append(&header, contents).await?,
+            }
+        }
+        tar.into_inner().await?;
+        Ok::<(), anyhow::Error>(())
+    });
+
+    Ok((stream, tar_size))
+}
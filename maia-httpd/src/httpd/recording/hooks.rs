@@ -0,0 +1,118 @@
+use crate::http_client;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use http::{header::CONTENT_TYPE, HeaderMap, Method, Uri};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+
+// Timeout for the POST request to the hook URL.
+const URL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hooks run when a recording finishes.
+///
+/// The hooks can be a command and a URL. Both of them receive a
+/// [`maia_json::RecordingFinished`] as JSON. The command gets it in its
+/// standard input, and the URL in the body of a POST request. The hooks are
+/// run once the SHA512 checksum of the recording has been computed, so that
+/// they can download the recording from `/recording` and verify it.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingHooks {
+    command: Option<PathBuf>,
+    url: Option<Uri>,
+}
+
+impl RecordingHooks {
+    /// Creates the recording hooks.
+    ///
+    /// The URL must be an `http://` or `https://` URL.
+    pub fn new(command: Option<PathBuf>, url: Option<&str>) -> Result<RecordingHooks> {
+        let url = url
+            .map(|url| {
+                let url = url.parse::<Uri>().context("invalid recording hook URL")?;
+                http_client::check_url(&url).context("invalid recording hook URL")?;
+                Ok::<_, anyhow::Error>(url)
+            })
+            .transpose()?;
+        Ok(RecordingHooks { command, url })
+    }
+
+    /// Returns `true` if there are no hooks.
+    pub fn is_empty(&self) -> bool {
+        self.command.is_none() && self.url.is_none()
+    }
+
+    // Runs all the hooks concurrently, logging any errors.
+    pub(super) async fn run(&self, event: &maia_json::RecordingFinished) {
+        let json = match serde_json::to_vec(event) {
+            Ok(json) => json,
+            Err(err) => {
+                tracing::error!("failed to serialize recording hook JSON: {err}");
+                return;
+            }
+        };
+        let command = async {
+            if let Some(command) = &self.command {
+                if let Err(err) = run_command(command, &json).await {
+                    tracing::error!("recording hook command failed: {err:#}");
+                }
+            }
+        };
+        let url = async {
+            if let Some(url) = &self.url {
+                if let Err(err) = post(url, &json).await {
+                    tracing::error!("recording hook URL failed: {err:#}");
+                }
+            }
+        };
+        tokio::join!(command, url);
+    }
+}
+
+async fn run_command(command: &PathBuf, json: &[u8]) -> Result<()> {
+    tracing::info!(?command, "running recording hook command");
+    let mut child = tokio::process::Command::new(command)
+        .stdin(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to run command")?;
+    let mut stdin = child.stdin.take().unwrap();
+    // The command might not read its standard input, so errors writing to it
+    // are ignored.
+    let _ = stdin.write_all(json).await;
+    drop(stdin);
+    let status = child.wait().await?;
+    anyhow::ensure!(status.success(), "command exited with {status}");
+    Ok(())
+}
+
+async fn post(url: &Uri, json: &[u8]) -> Result<()> {
+    tracing::info!(%url, "sending recording hook POST request");
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, "application/json".parse().unwrap());
+    let body = futures::stream::iter([Ok(Bytes::copy_from_slice(json))]);
+    tokio::time::timeout(
+        URL_TIMEOUT,
+        http_client::send(&Method::POST, url, &headers, body, json.len()),
+    )
+    .await
+    .context("request timed out")??
+    .error_for_status()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn urls() {
+        assert!(RecordingHooks::new(None, None).unwrap().is_empty());
+        assert!(!RecordingHooks::new(None, Some("https://example.com/hook"))
+            .unwrap()
+            .is_empty());
+        assert!(RecordingHooks::new(None, Some("ftp://example.com/hook")).is_err());
+        assert!(RecordingHooks::new(None, Some("/hook")).is_err());
+    }
+}
@@ -0,0 +1,309 @@
+use super::super::json_error::JsonError;
+use super::{clips, Mode, RecorderBusy};
+use crate::app::AppState;
+use crate::sigmf;
+use crate::tuner::TunerOwner;
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use chrono::Utc;
+use maia_json::{Hertz, PutRecorderHopping, RecorderHopping};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+// Maximum time during which the capture of a burst is retried while the
+// recorder is being used to capture IQ samples for the demodulators and
+// streams, and period between retries.
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(2);
+const CAPTURE_RETRY_PERIOD: Duration = Duration::from_millis(10);
+
+/// Frequency-hopping recording.
+///
+/// This contains the progress of the last frequency-hopping recording. The
+/// recordings that finish are stored with [`clips::Clips`].
+#[derive(Debug, Default)]
+pub(super) struct Hopping {
+    status: RecorderHopping,
+    // Cancellation token of the recording, which is Some while the recording
+    // is in progress.
+    cancellation: Option<CancellationToken>,
+}
+
+pub async fn get_hopping(State(state): State<AppState>) -> Json<RecorderHopping> {
+    Json(state.recorder().hopping.lock().unwrap().status.clone())
+}
+
+/// Starts a frequency-hopping recording.
+///
+/// The recording runs in the background. Its progress can be obtained with
+/// [`get_hopping`], and it is stored in the library of frequency-hopping
+/// recordings when it finishes. The AD9361 RX frequency is restored once the
+/// recording finishes.
+pub async fn put_hopping(
+    State(state): State<AppState>,
+    Json(put): Json<PutRecorderHopping>,
+) -> Result<Json<RecorderHopping>, JsonError> {
+    put.validate().map_err(JsonError::client_error_alert)?;
+    state
+        .recorder()
+        .check_capture()
+        .await
+        .map_err(JsonError::conflict)?;
+    let (mode, decimation) = {
+        let ip_core = state.ip_core().lock().unwrap();
+        (
+            Mode(ip_core.recorder_mode().map_err(JsonError::server_error)?),
+            ip_core.recorder_input_decimation(),
+        )
+    };
+    let sample_rate = state
+        .ad9361_samp_rate()
+        .await
+        .map_err(JsonError::server_error)?
+        .0
+        / decimation as f64;
+    // The RX LO frequencies used for the bursts must be within the tuning
+    // range and the safety limits.
+    for &frequency in &put.frequencies {
+        state
+            .limits()
+            .check_rx_lo_frequency(lo_frequency(&state, frequency))
+            .map_err(JsonError::client_error_alert)?;
+    }
+    let burst_items = (put.burst_duration.0 * sample_rate).ceil() as usize;
+    if burst_items * mode.input_bytes_per_item() > state.recorder().buffer_size() {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "the bursts do not fit in the recording buffer"
+        )));
+    }
+    let total_segments = put.frequencies.len() * put.cycles as usize;
+    let size = total_segments * burst_items * mode.output_bytes_per_item();
+    if size > clips::MAX_HOPPING_SIZE {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "the recording is too large ({size} bytes, maximum {} bytes)",
+            clips::MAX_HOPPING_SIZE
+        )));
+    }
+    let tuner = state
+        .tuner()
+        .acquire(TunerOwner::Hopping)
+        .map_err(JsonError::conflict)?;
+    let cancellation = CancellationToken::new();
+    let status = {
+        let mut hopping = state.recorder().hopping.lock().unwrap();
+        if hopping.status.running {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "a frequency-hopping recording is already in progress"
+            )));
+        }
+        hopping.status = RecorderHopping {
+            running: true,
+            config: Some(put.clone()),
+            segments: 0,
+            total_segments,
+            error: None,
+        };
+        hopping.cancellation = Some(cancellation.clone());
+        hopping.status.clone()
+    };
+    tracing::info!(
+        frequencies = put.frequencies.len(),
+        cycles = put.cycles,
+        "starting frequency-hopping recording"
+    );
+    tokio::spawn(async move {
+        let result = record(&state, &put, sample_rate, &cancellation).await;
+        // The RX LO is released once the original frequency has been restored.
+        drop(tuner);
+        let mut hopping = state.recorder().hopping.lock().unwrap();
+        hopping.status.running = false;
+        hopping.cancellation = None;
+        match result {
+            Ok(()) => tracing::info!("frequency-hopping recording finished"),
+            Err(err) => {
+                tracing::error!("frequency-hopping recording failed: {err:#}");
+                hopping.status.error = Some(format!("{err:#}"));
+            }
+        }
+    });
+    Ok(Json(status))
+}
+
+/// Cancels the frequency-hopping recording in progress.
+///
+/// The recording stops once the burst that is being captured finishes, and
+/// the bursts captured so far are discarded.
+pub async fn delete_hopping(State(state): State<AppState>) -> Json<RecorderHopping> {
+    let hopping = state.recorder().hopping.lock().unwrap();
+    if let Some(cancellation) = &hopping.cancellation {
+        tracing::info!("cancelling frequency-hopping recording");
+        cancellation.cancel();
+    }
+    Json(hopping.status.clone())
+}
+
+// Makes the recording, restoring the RX frequency when it finishes, and stores
+// it in the library of frequency-hopping recordings.
+async fn record(
+    state: &AppState,
+    config: &PutRecorderHopping,
+    sample_rate: f64,
+    cancellation: &CancellationToken,
+) -> Result<()> {
+    let original_frequency = state.ad9361().lock().await.get_rx_lo_frequency().await?;
+    let result = capture_bursts(state, config, cancellation).await;
+    if let Err(err) = set_rx_lo_frequency(state, original_frequency).await {
+        tracing::error!("failed to restore RX frequency: {err:#}");
+    }
+    let (mode, mut captures, writer) = result?;
+    let num_items = writer.size() / mode.output_bytes_per_item();
+    let first = captures.remove(0);
+    let recorder = state.recorder();
+    let (filename, author) = {
+        let metadata = recorder.metadata.lock().await;
+        (
+            metadata.filename.clone(),
+            metadata.sigmf_meta.author().to_string(),
+        )
+    };
+    let mut sigmf_meta = sigmf::Metadata::new(mode.0.into(), sample_rate, first.frequency);
+    sigmf_meta.set_datetime(first.datetime);
+    sigmf_meta.set_captures(captures);
+    sigmf_meta.set_description(&format!(
+        "Frequency-hopping recording of {} frequencies",
+        config.frequencies.len()
+    ));
+    sigmf_meta.set_author(&author);
+    // It is assumed that the geolocation has been validated, so it should not
+    // error when converting to a GeoJSON point.
+    sigmf_meta.set_geolocation_optional(
+        state
+            .geolocation()
+            .lock()
+            .unwrap()
+            .clone()
+            .map(|geolocation| geolocation.try_into().unwrap()),
+    );
+    let frequency_offset = state.device().frequency_offset();
+    sigmf_meta.set_frequency_offset((frequency_offset != Hertz(0.0)).then_some(frequency_offset.0));
+    sigmf_meta.set_clock_error(state.clock_measurement().clock_error());
+    writer
+        .finish(
+            &recorder.hopping_recordings,
+            &filename,
+            sigmf_meta,
+            num_items,
+        )
+        .await
+}
+
+// Captures the bursts at each of the frequencies. Returns the recorder mode,
+// the SigMF capture segments and the writer to which the SigMF data of the
+// bursts has been written.
+async fn capture_bursts(
+    state: &AppState,
+    config: &PutRecorderHopping,
+    cancellation: &CancellationToken,
+) -> Result<(Mode, Vec<sigmf::Capture>, clips::ClipWriter)> {
+    let burst_duration = config
+        .burst_duration
+        .duration()
+        .context("invalid burst duration")?;
+    let settle_time = config
+        .settle_time
+        .duration()
+        .context("invalid settle time")?;
+    let mut recording_mode = None;
+    let mut captures = Vec::new();
+    let mut writer = clips::ClipWriter::create(&state.recorder().hopping_recordings).await?;
+    for _ in 0..config.cycles {
+        for &frequency in &config.frequencies {
+            let center_frequency = tune(state, frequency).await?;
+            tokio::select! {
+                _ = tokio::time::sleep(settle_time) => {}
+                _ = cancellation.cancelled() => {}
+            }
+            anyhow::ensure!(!cancellation.is_cancelled(), "cancelled");
+            let datetime = Utc::now();
+            let (mode, burst) = capture_burst(state, burst_duration).await?;
+            anyhow::ensure!(
+                *recording_mode.get_or_insert(mode) == mode,
+                "the recorder mode changed during the recording"
+            );
+            captures.push(sigmf::Capture {
+                sample_start: (writer.size() / mode.output_bytes_per_item()) as u64,
+                frequency: center_frequency.0,
+                datetime,
+            });
+            writer.write(&burst).await?;
+            state.recorder().hopping.lock().unwrap().status.segments += 1;
+        }
+    }
+    let mode = recording_mode.context("no bursts captured")?;
+    Ok((mode, captures, writer))
+}
+
+// Captures a burst, retrying while the recorder is in use by the capture of
+// IQ samples for the demodulators and streams.
+async fn capture_burst(state: &AppState, duration: Duration) -> Result<(Mode, Vec<u8>)> {
+    let deadline = Instant::now() + CAPTURE_TIMEOUT;
+    loop {
+        match state
+            .recorder()
+            .capture(state.ip_core(), duration, |mode, data| {
+                (mode, mode.sigmf_data(data))
+            })
+            .await?
+        {
+            Ok((burst, _)) => return Ok(burst),
+            Err(RecorderBusy::InUse) if Instant::now() < deadline => {
+                tokio::time::sleep(CAPTURE_RETRY_PERIOD).await;
+            }
+            Err(busy) => return Err(busy.into()),
+        }
+    }
+}
+
+// Returns the RX LO frequency for which the center frequency of the recorder
+// input, including the frequency offset of the device, is `frequency`.
+fn lo_frequency(state: &AppState, frequency: Hertz) -> Hertz {
+    let input_offset = state
+        .ip_core()
+        .lock()
+        .unwrap()
+        .recorder_input_frequency_offset();
+    Hertz(
+        (frequency - state.device().frequency_offset() - input_offset)
+            .0
+            .round(),
+    )
+}
+
+// Tunes the AD9361 so that the center frequency of the recorder input,
+// including the frequency offset of the device, is `frequency`. Returns the
+// center frequency of the recorder input without the frequency offset of the
+// device, which is the frequency stored in the SigMF metadata.
+async fn tune(state: &AppState, frequency: Hertz) -> Result<Hertz> {
+    let lo_frequency = lo_frequency(state, frequency);
+    state.limits().check_rx_lo_frequency(lo_frequency)?;
+    set_rx_lo_frequency(state, lo_frequency).await?;
+    let input_offset = state
+        .ip_core()
+        .lock()
+        .unwrap()
+        .recorder_input_frequency_offset();
+    Ok(lo_frequency + input_offset)
+}
+
+async fn set_rx_lo_frequency(state: &AppState, frequency: Hertz) -> Result<()> {
+    state
+        .ad9361()
+        .lock()
+        .await
+        .set_rx_lo_frequency(frequency)
+        .await
+        .context("failed to set RX frequency")?;
+    state
+        .spectrometer_config()
+        .retune(&state.ip_core().lock().unwrap());
+    Ok(())
+}
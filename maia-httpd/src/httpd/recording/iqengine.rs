@@ -1,6 +1,7 @@
 use super::super::json_error::JsonError;
-use super::{unpack_12bit_to_16bit, RecorderMode, RecorderState, RecordingBufferInfo};
+use super::{RecorderMode, RecorderState, RecordingBufferInfo};
 use crate::app::AppState;
+use crate::samples::unpack_12bit_to_16bit;
 use anyhow::Result;
 use axum::extract::{Query, State};
 use bytes::{Bytes, BytesMut};
@@ -8,11 +9,12 @@ use serde_json::json;
 use std::collections::HashMap;
 
 async fn get_meta(state: &AppState) -> Result<serde_json::Value> {
+    let buffer = get_buffer(state.recorder())?;
     let metadata = state.recorder().metadata.lock().await.clone();
     let mut meta = metadata.sigmf_meta.to_json_value();
 
     // compute recording length
-    let buffer_info = RecordingBufferInfo::new(&metadata, state.ip_core()).await?;
+    let buffer_info = RecordingBufferInfo::new(&metadata, &buffer);
     let sample_length = buffer_info.num_items();
 
     // add traceability, which is required by IQEngine
@@ -57,7 +59,7 @@ async fn get_iq_data(
 ) -> Result<Bytes> {
     let buffer = get_buffer(state.recorder())?;
     let metadata = state.recorder().metadata.lock().await.clone();
-    let info = RecordingBufferInfo::new(&metadata, state.ip_core()).await?;
+    let info = RecordingBufferInfo::new(&metadata, &buffer);
 
     let bytes_per_input = info.input_bytes_per_item;
     let bytes_per_output = info.mode.output_bytes_per_item();
@@ -68,7 +70,7 @@ async fn get_iq_data(
         if start + len >= info.size {
             anyhow::bail!("requested data is out of bounds");
         }
-        let data = unsafe { std::slice::from_raw_parts(buffer.base.add(start), len) };
+        let data = &buffer.0.as_slice()[start..start + len];
         match info.mode.0 {
             RecorderMode::IQ8bit | RecorderMode::IQ16bit => bytes.extend_from_slice(data),
             RecorderMode::IQ12bit => {
@@ -115,7 +117,7 @@ async fn get_minimap_data(state: &AppState) -> Result<Bytes> {
 
     let buffer = get_buffer(state.recorder())?;
     let metadata = state.recorder().metadata.lock().await.clone();
-    let info = RecordingBufferInfo::new(&metadata, state.ip_core()).await?;
+    let info = RecordingBufferInfo::new(&metadata, &buffer);
 
     let bytes_per_input = info.input_bytes_per_item;
     let bytes_per_output = info.mode.output_bytes_per_item();
@@ -130,7 +132,7 @@ async fn get_minimap_data(state: &AppState) -> Result<Bytes> {
         if start + len >= info.size {
             anyhow::bail!("requested data is out of bounds");
         }
-        let data = unsafe { std::slice::from_raw_parts(buffer.base.add(start), len) };
+        let data = &buffer.0.as_slice()[start..start + len];
         match info.mode.0 {
             RecorderMode::IQ8bit | RecorderMode::IQ16bit => bytes.extend_from_slice(data),
             RecorderMode::IQ12bit => {
@@ -0,0 +1,187 @@
+use super::super::json_error::JsonError;
+use super::{round_up_multiple_512, tar_header, RecordingBuffer, RecordingBufferInfo, SigmfStream};
+use crate::app::AppState;
+use crate::sigmf::{Datatype, Endianness, Field, SampleFormat};
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+};
+use bytes::Bytes;
+use futures::StreamExt;
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH};
+use num_complex::Complex32;
+use serde::Deserialize;
+use tokio::sync::OwnedRwLockReadGuard;
+use tokio_stream::wrappers::ReceiverStream;
+
+const DEFAULT_DECIMATION: usize = 100;
+const MAX_DECIMATION: usize = 1 << 20;
+// Approximate number of items that are converted to IQ samples at once.
+const BLOCK_ITEMS: usize = 1 << 16;
+// Size of a cf32_le sample.
+const SAMPLE_SIZE: usize = std::mem::size_of::<Complex32>();
+// Number of blocks of samples that can wait to be written to an archive.
+const ARCHIVE_QUEUE_BLOCKS: usize = 4;
+
+/// Query parameters of the recording preview.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreviewQuery {
+    #[serde(default = "default_decimation")]
+    decimation: usize,
+}
+
+fn default_decimation() -> usize {
+    DEFAULT_DECIMATION
+}
+
+/// Returns a decimated version of the recording as a SigMF archive.
+///
+/// The recording is decimated by averaging blocks of `decimation` consecutive
+/// samples, which acts as a crude lowpass filter. The preview is stored in
+/// `cf32_le` format, normalized so that the full scale of the recorder
+/// corresponds to an amplitude of one.
+pub async fn get_preview(
+    State(state): State<AppState>,
+    Query(query): Query<PreviewQuery>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    if !(1..=MAX_DECIMATION).contains(&query.decimation) {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "decimation must be between 1 and {MAX_DECIMATION}"
+        )));
+    }
+    let buffer = state
+        .recorder()
+        .buffer
+        .clone()
+        .try_read_owned()
+        .map_err(|_| JsonError::client_error_alert(anyhow::anyhow!("recording in progress")))?;
+    let metadata = state.recorder().metadata.lock().await.clone();
+    let info = RecordingBufferInfo::new(&metadata, &buffer);
+    let decimation = query.decimation;
+    let num_samples = info.num_items() / decimation;
+
+    let mut sigmf_meta = metadata.sigmf_meta.clone();
+    sigmf_meta.set_datatype(Datatype {
+        field: Field::Complex,
+        format: SampleFormat::F32(Endianness::Le),
+    });
+    sigmf_meta.set_sample_rate(sigmf_meta.sample_rate() / decimation as f64);
+    let sample_time = sigmf_meta.sample_time().map(|mut sample_time| {
+        sample_time.decimation *= u32::try_from(decimation).unwrap();
+        sample_time
+    });
+    sigmf_meta.set_sample_time(sample_time);
+    sigmf_meta.set_sha512(None);
+    sigmf_meta.restrict_annotations(0, None, decimation as u64);
+    let filename = format!("{}_preview", metadata.filename);
+    let (tar, size) = archive(
+        &filename,
+        &sigmf_meta,
+        num_samples,
+        decimate(buffer, info, decimation),
+    )
+    .map_err(JsonError::server_error)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{filename}.sigmf\"")
+            .parse()
+            .unwrap(),
+    );
+    headers.insert(CONTENT_LENGTH, size.to_string().parse().unwrap());
+    Ok((headers, Body::from_stream(tar)))
+}
+
+// Returns the blocks of the decimated recording. The blocks are computed as
+// the iterator is advanced, and they contain info.num_items() / decimation
+// samples in total.
+fn decimate(
+    buffer: OwnedRwLockReadGuard<RecordingBuffer>,
+    info: RecordingBufferInfo,
+    decimation: usize,
+) -> impl Iterator<Item = Vec<Complex32>> + Send + 'static {
+    let block_items = BLOCK_ITEMS.div_ceil(decimation) * decimation;
+    let num_items = info.num_items();
+    let scale = 1.0 / decimation as f32;
+    (0..num_items).step_by(block_items).map(move |start| {
+        let end = (start + block_items).min(num_items);
+        let bytes_per_item = info.input_bytes_per_item;
+        info.mode
+            .iq_samples(&buffer.0.as_slice()[start * bytes_per_item..end * bytes_per_item])
+            .chunks_exact(decimation)
+            .map(|x| x.iter().sum::<Complex32>() * scale)
+            .collect()
+    })
+}
+
+// Returns a SigMF archive with cf32_le samples, together with its size.
+//
+// The samples are produced in blocks by an iterator, which is run in a
+// blocking thread while the archive is streamed, so that they do not need to
+// be held in memory at once. The blocks must contain num_samples samples in
+// total.
+pub(super) fn archive<I>(
+    filename: &str,
+    sigmf_meta: &crate::sigmf::Metadata,
+    num_samples: usize,
+    blocks: I,
+) -> Result<(SigmfStream, usize)>
+where
+    I: Iterator<Item = Vec<Complex32>> + Send + 'static,
+{
+    const DUPLEX_SIZE: usize = 1 << 20;
+    let timestamp = u64::try_from(sigmf_meta.datetime().timestamp())?;
+    let sigmf_meta = sigmf_meta.to_json();
+    let data_size = num_samples * SAMPLE_SIZE;
+    let dir_header = tar_header(&format!("{filename}/"), 0, timestamp)?;
+    let meta_header = tar_header(
+        &format!("{filename}/{filename}.sigmf-meta"),
+        sigmf_meta.len(),
+        timestamp,
+    )?;
+    let data_header = tar_header(
+        &format!("{filename}/{filename}.sigmf-data"),
+        data_size,
+        timestamp,
+    )?;
+    let tar_header_size = 512;
+    let num_headers = 3;
+    let tar_finish_size = 1024;
+    let tar_size = tar_header_size * num_headers
+        + round_up_multiple_512(sigmf_meta.len())
+        + round_up_multiple_512(data_size)
+        + tar_finish_size;
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(ARCHIVE_QUEUE_BLOCKS);
+    tokio::task::spawn_blocking(move || {
+        for block in blocks {
+            let bytes = block
+                .iter()
+                .flat_map(|x| [x.re.to_le_bytes(), x.im.to_le_bytes()])
+                .flatten()
+                .collect::<Vec<u8>>();
+            if sender.blocking_send(Bytes::from(bytes)).is_err() {
+                // The download has been dropped
+                return;
+            }
+        }
+    });
+    let data = ReceiverStream::new(receiver).map(Ok::<_, std::io::Error>);
+
+    let (duplex_write, duplex_read) = tokio::io::duplex(DUPLEX_SIZE);
+    let stream = tokio_util::io::ReaderStream::new(duplex_read);
+    let mut tar = tokio_tar::Builder::new(duplex_write);
+    tokio::spawn(async move {
+        let dir_data: &[u8] = &[];
+        tar.append(&dir_header, dir_data).await?;
+        tar.append(&meta_header, sigmf_meta.as_bytes()).await?;
+        tar.append(&data_header, tokio_util::io::StreamReader::new(data))
+            .await?;
+        tar.into_inner().await?;
+        Ok::<(), anyhow::Error>(())
+    });
+
+    Ok((stream, tar_size))
+}
@@ -0,0 +1,377 @@
+use super::super::{json_error::JsonError, list};
+use super::{preview, RecordingMeta, RecordingStream};
+use crate::app::AppState;
+use crate::ddc::{self, noise_blanker::NoiseBlanker, software::SoftwareDdc};
+use crate::sigmf::{self, Datatype, Endianness, Field, SampleFormat};
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    Json,
+};
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH};
+use maia_json::{Hertz, ProcessingJobState};
+use num_complex::Complex32;
+use std::collections::BTreeMap;
+use std::time::Instant;
+use tokio_util::sync::CancellationToken;
+
+// Maximum number of jobs that are kept. When a new job is started, the oldest
+// jobs that are not running are removed to stay below this limit.
+const MAX_JOBS: usize = 16;
+// Maximum total size of the outputs of the jobs that are kept (in bytes).
+const MAX_OUTPUTS_SIZE: usize = 64 << 20;
+// Approximate number of items that are processed at once.
+const BLOCK_ITEMS: usize = 1 << 16;
+// Size of a cf32 sample (in bytes).
+const SAMPLE_SIZE: usize = std::mem::size_of::<Complex32>();
+
+/// Post-capture processing jobs.
+///
+/// This contains the jobs that run the DDC in software over the recording, and
+/// their outputs.
+#[derive(Debug, Default)]
+pub(super) struct Jobs {
+    jobs: BTreeMap<u64, Job>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct Job {
+    status: maia_json::ProcessingJob,
+    sigmf_meta: sigmf::Metadata,
+    samples: Vec<Complex32>,
+    // Expected number of output samples
+    num_outputs: usize,
+    cancellation: CancellationToken,
+    start: Instant,
+}
+
+impl Jobs {
+    fn json(&self) -> maia_json::ProcessingJobs {
+        maia_json::ProcessingJobs {
+            jobs: self.jobs.values().map(|job| job.status.clone()).collect(),
+            total: self.jobs.len(),
+        }
+    }
+
+    fn job_json(&self, id: u64) -> Option<maia_json::ProcessingJob> {
+        self.jobs.get(&id).map(|job| job.status.clone())
+    }
+
+    // Describes the running jobs as users of the recording buffer.
+    pub(super) fn buffer_users(&self) -> impl Iterator<Item = String> + '_ {
+        self.jobs
+            .values()
+            .filter(|job| job.status.state == ProcessingJobState::Running)
+            .map(|job| {
+                format!(
+                    "processing job {} (for {:.0} s)",
+                    job.status.id,
+                    job.start.elapsed().as_secs_f64()
+                )
+            })
+    }
+
+    // Removes the oldest jobs that are not running until a new job with
+    // `num_outputs` samples fits within the limits.
+    fn make_room(&mut self, num_outputs: usize) -> Result<()> {
+        let outputs_size = |jobs: &BTreeMap<u64, Job>| {
+            jobs.values()
+                .map(|job| job.num_outputs * SAMPLE_SIZE)
+                .sum::<usize>()
+                + num_outputs * SAMPLE_SIZE
+        };
+        while self.jobs.len() >= MAX_JOBS || outputs_size(&self.jobs) > MAX_OUTPUTS_SIZE {
+            let Some(&id) = self
+                .jobs
+                .iter()
+                .find(|(_, job)| job.status.state != ProcessingJobState::Running)
+                .map(|(id, _)| id)
+            else {
+                anyhow::bail!("there are too many processing jobs running");
+            };
+            self.jobs.remove(&id);
+        }
+        Ok(())
+    }
+}
+
+fn not_found(id: u64) -> JsonError {
+    JsonError::not_found(anyhow::anyhow!("processing job {id} does not exist"))
+}
+
+pub async fn get_processing_jobs(
+    State(state): State<AppState>,
+    Query(query): Query<maia_json::ListQuery>,
+) -> Result<Json<maia_json::ProcessingJobs>, JsonError> {
+    let mut json = state.recorder().processing.lock().unwrap().json();
+    json.total = list::paginate(&mut json.jobs, &query)?;
+    Ok(Json(json))
+}
+
+pub async fn get_processing_job(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<maia_json::ProcessingJob>, JsonError> {
+    state
+        .recorder()
+        .processing
+        .lock()
+        .unwrap()
+        .job_json(id)
+        .map(Json)
+        .ok_or_else(|| not_found(id))
+}
+
+pub async fn delete_processing_job(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<maia_json::ProcessingJob>, JsonError> {
+    let job = state
+        .recorder()
+        .processing
+        .lock()
+        .unwrap()
+        .jobs
+        .remove(&id)
+        .ok_or_else(|| not_found(id))?;
+    job.cancellation.cancel();
+    Ok(Json(job.status))
+}
+
+// Obtains the DDC configuration for a job.
+async fn job_ddc_config(
+    state: &AppState,
+    post: &maia_json::PostProcessingJob,
+    sample_rate: f64,
+) -> Result<maia_json::PutDDCConfig, JsonError> {
+    match (&post.config, &post.design) {
+        (Some(_), Some(_)) => Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "only one of the DDC configuration and the DDC design can be given"
+        ))),
+        (Some(config), None) => Ok(config.clone()),
+        (None, Some(design)) => {
+            // The DDC design can take a couple seconds to calculate, so it is
+            // run in a blocking thread.
+            let design = design.clone();
+            tokio::task::spawn_blocking(move || ddc::make_design(&design, Hertz(sample_rate)))
+                .await
+                .map_err(JsonError::server_error)?
+                .map_err(JsonError::client_error_alert)
+        }
+        (None, None) => {
+            let config = state
+                .ip_core()
+                .lock()
+                .unwrap()
+                .ddc_config(Hertz(sample_rate));
+            Ok(maia_json::PutDDCConfig {
+                frequency: config.frequency,
+                fir1: config.fir1,
+                fir2: config.fir2,
+                fir3: config.fir3,
+                transition_bandwidth: config.transition_bandwidth,
+            })
+        }
+    }
+}
+
+pub async fn post_processing_job(
+    State(state): State<AppState>,
+    Json(post): Json<maia_json::PostProcessingJob>,
+) -> Result<Json<maia_json::ProcessingJob>, JsonError> {
+    // The job keeps a read lock on the recording buffer while it runs, so that
+    // a new recording cannot overwrite it.
+    let buffer = state
+        .recorder()
+        .buffer
+        .clone()
+        .try_read_owned()
+        .map_err(|_| JsonError::client_error_alert(anyhow::anyhow!("recording in progress")))?;
+    let metadata = state.recorder().metadata.lock().await.trimmed();
+    let sample_rate = metadata.sigmf_meta.sample_rate();
+    let config = job_ddc_config(&state, &post, sample_rate).await?;
+    let software_ddc =
+        SoftwareDdc::new(&config, sample_rate).map_err(JsonError::client_error_alert)?;
+    let noise_blanker_config = state.noise_blanker().lock().unwrap().clone();
+    let noise_blanker = noise_blanker_config
+        .processing
+        .then(|| NoiseBlanker::new(&noise_blanker_config, sample_rate))
+        .transpose()
+        .map_err(JsonError::client_error_alert)?;
+    let stream = RecordingStream::new(buffer, &metadata);
+    let decimation = software_ddc.decimation();
+    let num_outputs = stream.info.num_items() / decimation;
+
+    let filename = post
+        .filename
+        .unwrap_or_else(|| format!("{}_ddc", metadata.filename));
+    let sigmf_meta = output_sigmf_meta(&metadata, &config, decimation);
+    let status = maia_json::ProcessingJob {
+        id: 0,
+        state: ProcessingJobState::Running,
+        progress: 0.0,
+        filename,
+        frequency: Hertz(sigmf_meta.frequency()),
+        decimation: u32::try_from(decimation).map_err(JsonError::client_error_alert)?,
+        output_sampling_frequency: Hertz(sigmf_meta.sample_rate()),
+        size: 0,
+        error: None,
+    };
+    let cancellation = CancellationToken::new();
+    let status = {
+        let mut jobs = state.recorder().processing.lock().unwrap();
+        jobs.make_room(num_outputs)
+            .map_err(JsonError::client_error_alert)?;
+        let id = jobs.next_id;
+        jobs.next_id += 1;
+        let status = maia_json::ProcessingJob { id, ..status };
+        jobs.jobs.insert(
+            id,
+            Job {
+                status: status.clone(),
+                sigmf_meta,
+                samples: Vec::with_capacity(num_outputs),
+                num_outputs,
+                cancellation: cancellation.clone(),
+                start: Instant::now(),
+            },
+        );
+        status
+    };
+    tracing::info!(
+        id = status.id,
+        filename = status.filename,
+        decimation,
+        "starting processing job"
+    );
+    let id = status.id;
+    let job = {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            run_job(
+                &state,
+                id,
+                stream,
+                noise_blanker,
+                software_ddc,
+                &cancellation,
+            )
+        })
+    };
+    tokio::spawn(async move {
+        if let Err(err) = job.await {
+            tracing::error!(id, "processing job failed: {err}");
+            if let Some(job) = state
+                .recorder()
+                .processing
+                .lock()
+                .unwrap()
+                .jobs
+                .get_mut(&id)
+            {
+                job.status.state = ProcessingJobState::Failed;
+                job.status.error = Some(err.to_string());
+            }
+        }
+    });
+    Ok(Json(status))
+}
+
+fn output_sigmf_meta(
+    metadata: &RecordingMeta,
+    config: &maia_json::PutDDCConfig,
+    decimation: usize,
+) -> sigmf::Metadata {
+    let mut sigmf_meta = metadata.sigmf_meta.clone();
+    sigmf_meta.set_datatype(Datatype {
+        field: Field::Complex,
+        format: SampleFormat::F32(Endianness::Le),
+    });
+    sigmf_meta.set_sample_rate(sigmf_meta.sample_rate() / decimation as f64);
+    sigmf_meta.set_frequency(sigmf_meta.frequency() + config.frequency.0);
+    let sample_time = sigmf_meta.sample_time().map(|mut sample_time| {
+        sample_time.decimation *= u32::try_from(decimation).unwrap();
+        sample_time
+    });
+    sigmf_meta.set_sample_time(sample_time);
+    sigmf_meta.set_sha512(None);
+    sigmf_meta.restrict_annotations(0, None, decimation as u64);
+    sigmf_meta
+}
+
+// Runs a processing job, updating its progress and output after each block of
+// samples. The noise blanker, if any, is applied before the software DDC.
+fn run_job(
+    state: &AppState,
+    id: u64,
+    stream: RecordingStream,
+    mut noise_blanker: Option<NoiseBlanker>,
+    mut software_ddc: SoftwareDdc,
+    cancellation: &CancellationToken,
+) {
+    let bytes_per_item = stream.info.input_bytes_per_item;
+    let data = &stream.buffer.0.as_slice()[stream.offset..stream.end];
+    let num_blocks = data.len().div_ceil(BLOCK_ITEMS * bytes_per_item).max(1);
+    for (n, block) in data.chunks(BLOCK_ITEMS * bytes_per_item).enumerate() {
+        if cancellation.is_cancelled() {
+            return;
+        }
+        let mut samples = stream.info.mode.iq_samples(block);
+        if let Some(noise_blanker) = noise_blanker.as_mut() {
+            noise_blanker.process(&mut samples);
+        }
+        let output = software_ddc.process(&samples);
+        let mut jobs = state.recorder().processing.lock().unwrap();
+        let Some(job) = jobs.jobs.get_mut(&id) else {
+            // The job has been deleted
+            return;
+        };
+        job.samples.extend_from_slice(&output);
+        job.status.progress = (n + 1) as f64 / num_blocks as f64;
+        job.status.size = job.samples.len() * SAMPLE_SIZE;
+    }
+    let mut jobs = state.recorder().processing.lock().unwrap();
+    if let Some(job) = jobs.jobs.get_mut(&id) {
+        job.status.state = ProcessingJobState::Finished;
+        job.status.progress = 1.0;
+        tracing::info!(id, "processing job finished");
+    }
+}
+
+pub async fn get_processing_output(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    let (filename, sigmf_meta, samples) = {
+        let jobs = state.recorder().processing.lock().unwrap();
+        let job = jobs.jobs.get(&id).ok_or_else(|| not_found(id))?;
+        if job.status.state != ProcessingJobState::Finished {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "processing job {id} has not finished"
+            )));
+        }
+        (
+            job.status.filename.clone(),
+            job.sigmf_meta.clone(),
+            job.samples.clone(),
+        )
+    };
+    let (tar, size) = preview::archive(
+        &filename,
+        &sigmf_meta,
+        samples.len(),
+        std::iter::once(samples),
+    )
+    .map_err(JsonError::server_error)?;
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{filename}.sigmf\"")
+            .parse()
+            .unwrap(),
+    );
+    headers.insert(CONTENT_LENGTH, size.to_string().parse().unwrap());
+    Ok((headers, Body::from_stream(tar)))
+}
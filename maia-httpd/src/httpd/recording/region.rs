@@ -0,0 +1,297 @@
+use super::super::json_error::JsonError;
+use super::{preview, RecordingBuffer, RecordingBufferInfo};
+use crate::app::AppState;
+use crate::sigmf::{self, Datatype, Endianness, Field, SampleFormat};
+use anyhow::Result;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+};
+use http::header::{HeaderMap, CONTENT_DISPOSITION, CONTENT_LENGTH};
+use num_complex::Complex32;
+use serde::Deserialize;
+use std::f64::consts::{PI, TAU};
+use std::ops::Range;
+use tokio::sync::OwnedRwLockReadGuard;
+
+const MAX_DECIMATION: usize = 1 << 16;
+// Length of the lowpass filter, in units of the decimation factor.
+const TAPS_PER_DECIMATION: usize = 8;
+// Approximate number of items that are converted to IQ samples at once.
+const BLOCK_ITEMS: usize = 1 << 16;
+
+/// Query parameters of the recording region export.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegionQuery {
+    // Start and end of the time span of the region, in milliseconds since the
+    // UNIX epoch. If they are not given, the region extends to the start or end
+    // of the recording.
+    start_time: Option<f64>,
+    end_time: Option<f64>,
+    // Lowest and highest frequency of the region (in Hz).
+    frequency_min: f64,
+    frequency_max: f64,
+}
+
+/// Returns a time and frequency region of the recording as a SigMF archive.
+///
+/// The region is extracted with a software DDC. The samples in the time span
+/// are shifted in frequency so that the center of the frequency range is at
+/// 0 Hz, lowpass filtered with a windowed-sinc FIR filter, and decimated by the
+/// largest factor whose output sample rate still covers the frequency
+/// range. The region is stored in `cf32_le` format, normalized so that the full
+/// scale of the recorder corresponds to an amplitude of one.
+pub async fn get_region(
+    State(state): State<AppState>,
+    Query(query): Query<RegionQuery>,
+) -> Result<(HeaderMap, Body), JsonError> {
+    let buffer = state
+        .recorder()
+        .buffer
+        .clone()
+        .try_read_owned()
+        .map_err(|_| JsonError::client_error_alert(anyhow::anyhow!("recording in progress")))?;
+    let metadata = state.recorder().metadata.lock().await.clone();
+    let info = RecordingBufferInfo::new(&metadata, &buffer);
+    let region = Region::new(&query, &metadata.sigmf_meta, info.num_items())
+        .map_err(JsonError::client_error_alert)?;
+
+    let mut sigmf_meta = metadata.sigmf_meta.clone();
+    sigmf_meta.set_datatype(Datatype {
+        field: Field::Complex,
+        format: SampleFormat::F32(Endianness::Le),
+    });
+    let start = region.samples.start;
+    let nanoseconds = (start as f64 * 1e9 / region.sample_rate).round() as i64;
+    sigmf_meta.set_datetime(sigmf_meta.datetime() + chrono::Duration::nanoseconds(nanoseconds));
+    let sample_time = sigmf_meta.sample_time().map(|mut sample_time| {
+        sample_time.start_sample += start as u64 * u64::from(sample_time.decimation);
+        sample_time.decimation *= u32::try_from(region.decimation).unwrap();
+        sample_time
+    });
+    sigmf_meta.set_sample_time(sample_time);
+    sigmf_meta.set_frequency(sigmf_meta.frequency() + region.frequency_shift);
+    sigmf_meta.set_sample_rate(region.sample_rate / region.decimation as f64);
+    sigmf_meta.set_sha512(None);
+    sigmf_meta.restrict_annotations(
+        start as u64,
+        Some(region.samples.end as u64),
+        region.decimation as u64,
+    );
+    let filename = format!("{}_region", metadata.filename);
+    let (tar, size) = preview::archive(
+        &filename,
+        &sigmf_meta,
+        region.num_outputs(),
+        region.clone().extract(buffer, info),
+    )
+    .map_err(JsonError::server_error)?;
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        CONTENT_DISPOSITION,
+        format!("attachment; filename=\"{filename}.sigmf\"")
+            .parse()
+            .unwrap(),
+    );
+    headers.insert(CONTENT_LENGTH, size.to_string().parse().unwrap());
+    Ok((headers, Body::from_stream(tar)))
+}
+
+#[derive(Debug, Clone)]
+struct Region {
+    // Samples of the recording in the time span of the region.
+    samples: Range<usize>,
+    // Sample rate of the recording.
+    sample_rate: f64,
+    // Center frequency of the region, relative to the center frequency of the
+    // recording (in Hz).
+    frequency_shift: f64,
+    decimation: usize,
+    taps: Vec<f32>,
+}
+
+impl Region {
+    fn new(query: &RegionQuery, sigmf_meta: &sigmf::Metadata, num_items: usize) -> Result<Region> {
+        let sample_rate = sigmf_meta.sample_rate();
+        let recording_start = sigmf_meta.datetime().timestamp_micros() as f64 * 1e-3;
+        let sample = |time: Option<f64>, default| match time {
+            Some(time) => {
+                anyhow::ensure!(time.is_finite(), "invalid time");
+                let sample = ((time - recording_start) * 1e-3 * sample_rate).round();
+                Ok(sample.clamp(0.0, num_items as f64) as usize)
+            }
+            None => Ok(default),
+        };
+        let samples = sample(query.start_time, 0)?..sample(query.end_time, num_items)?;
+        anyhow::ensure!(
+            !samples.is_empty(),
+            "the time span does not overlap the recording"
+        );
+
+        let center = sigmf_meta.frequency();
+        let frequency_min = query.frequency_min.max(center - 0.5 * sample_rate);
+        let frequency_max = query.frequency_max.min(center + 0.5 * sample_rate);
+        anyhow::ensure!(
+            frequency_min < frequency_max,
+            "the frequency range does not overlap the recording"
+        );
+        let bandwidth = frequency_max - frequency_min;
+        let decimation = ((sample_rate / bandwidth).floor() as usize).clamp(1, MAX_DECIMATION);
+        Ok(Region {
+            samples,
+            sample_rate,
+            frequency_shift: 0.5 * (frequency_min + frequency_max) - center,
+            decimation,
+            taps: lowpass(0.5 * bandwidth / sample_rate, decimation),
+        })
+    }
+
+    fn num_outputs(&self) -> usize {
+        self.samples.len().div_ceil(self.decimation)
+    }
+
+    // Returns the blocks of outputs of the DDC. The blocks are computed as the
+    // iterator is advanced, and they contain num_outputs() samples in total.
+    fn extract(
+        self,
+        buffer: OwnedRwLockReadGuard<RecordingBuffer>,
+        info: RecordingBufferInfo,
+    ) -> impl Iterator<Item = Vec<Complex32>> + Send + 'static {
+        let bytes_per_item = info.input_bytes_per_item;
+        let num_items = info.num_items();
+        let half_taps = self.taps.len() / 2;
+        let block_outputs = BLOCK_ITEMS.div_ceil(self.decimation);
+        let num_outputs = self.num_outputs();
+        (0..num_outputs)
+            .step_by(block_outputs)
+            .map(move |block_start| {
+                let outputs = block_start..(block_start + block_outputs).min(num_outputs);
+                // The input of the block includes the tails of the filter,
+                // which can extend outside the time span of the region.
+                let input_start = (self.samples.start + outputs.start * self.decimation)
+                    .saturating_sub(half_taps);
+                let input_end =
+                    (self.samples.start + (outputs.end - 1) * self.decimation + half_taps + 1)
+                        .min(num_items);
+                let data =
+                    &buffer.0.as_slice()[input_start * bytes_per_item..input_end * bytes_per_item];
+                let input = info.mode.iq_samples(data);
+                self.process_block(&input, input_start, outputs)
+            })
+    }
+
+    // Computes the outputs of the DDC from a block of input samples, whose
+    // first sample has the index input_start in the recording.
+    fn process_block(
+        &self,
+        input: &[Complex32],
+        input_start: usize,
+        outputs: Range<usize>,
+    ) -> Vec<Complex32> {
+        // The phase of the mixer is zero at the start of the region.
+        let phase_step = -TAU * self.frequency_shift / self.sample_rate;
+        let mixed = input
+            .iter()
+            .enumerate()
+            .map(|(j, &x)| {
+                let n = (input_start + j) as f64 - self.samples.start as f64;
+                let (sin, cos) = (phase_step * n).rem_euclid(TAU).sin_cos();
+                x * Complex32::new(cos as f32, sin as f32)
+            })
+            .collect::<Vec<_>>();
+        let half_taps = self.taps.len() / 2;
+        outputs
+            .map(|k| {
+                let center = self.samples.start + k * self.decimation;
+                self.taps
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(n, &h)| {
+                        let j = (center + n).checked_sub(half_taps + input_start)?;
+                        mixed.get(j).map(|&x| x * h)
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+// Designs a lowpass FIR filter for a decimation by a factor `decimation` using
+// the window method with a Blackman window. The cutoff frequency is given in
+// cycles per sample. The filter has unit gain at DC.
+fn lowpass(cutoff: f64, decimation: usize) -> Vec<f32> {
+    let num_taps = TAPS_PER_DECIMATION * decimation + 1;
+    let m = (num_taps - 1) as f64;
+    let taps = (0..num_taps)
+        .map(|n| {
+            let t = n as f64 - 0.5 * m;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                (TAU * cutoff * t).sin() / (PI * t)
+            };
+            let window =
+                0.42 - 0.5 * (TAU * n as f64 / m).cos() + 0.08 * (2.0 * TAU * n as f64 / m).cos();
+            sinc * window
+        })
+        .collect::<Vec<f64>>();
+    let gain = taps.iter().sum::<f64>();
+    taps.iter().map(|&h| (h / gain) as f32).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lowpass_gain() {
+        let taps = lowpass(0.05, 10);
+        assert_eq!(taps.len(), TAPS_PER_DECIMATION * 10 + 1);
+        assert!((taps.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        // the filter is symmetric
+        assert!(taps.iter().zip(taps.iter().rev()).all(|(a, b)| a == b));
+        // the gain at the Nyquist frequency of the output is small
+        let gain = taps
+            .iter()
+            .enumerate()
+            .map(|(n, &h)| if n % 2 == 0 { h } else { -h })
+            .sum::<f32>();
+        assert!(gain.abs() < 1e-3);
+    }
+
+    #[test]
+    fn downconvert_tone() {
+        let sample_rate = 1e6;
+        let decimation = 10;
+        let region = Region {
+            samples: 1000..3000,
+            sample_rate,
+            frequency_shift: 200e3,
+            decimation,
+            taps: lowpass(0.5 / decimation as f64, decimation),
+        };
+        let tone = |frequency: f64| {
+            (0..4000)
+                .map(|n| {
+                    let phase = TAU * frequency * n as f64 / sample_rate;
+                    Complex32::new(phase.cos() as f32, phase.sin() as f32)
+                })
+                .collect::<Vec<_>>()
+        };
+        let power = |output: &[Complex32]| {
+            output.iter().map(|x| x.norm_sqr()).sum::<f32>() / output.len() as f32
+        };
+        let outputs = 0..region.num_outputs();
+        assert_eq!(outputs.len(), 200);
+        // a tone in the region is kept at baseband
+        let output = region.process_block(&tone(202e3), 0, outputs.clone());
+        assert!((power(&output) - 1.0).abs() < 2e-2);
+        let phase_step = output[1].arg() - output[0].arg();
+        let expected = (TAU * 2e3 * decimation as f64 / sample_rate) as f32;
+        assert!((phase_step - expected).abs() < 1e-3);
+        // a tone outside the region is rejected
+        let output = region.process_block(&tone(-100e3), 0, outputs);
+        assert!(power(&output) < 1e-4);
+    }
+}
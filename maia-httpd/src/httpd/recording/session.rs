@@ -0,0 +1,158 @@
+use super::RecordingMeta;
+use anyhow::{Context, Result};
+use maia_json::RecorderMode;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// State of a recording in progress saved to the session file.
+///
+/// The session is saved when a recording starts, and the session file is
+/// removed when the recording finishes. If maia-httpd restarts while the FPGA
+/// IP core is recording, the new process finds the session file and uses it
+/// to finish the recording, so that the contents of the recording buffer can
+/// be downloaded.
+///
+/// A session can only be recovered if the recording buffer still contains the
+/// recording. This is not the case after a power cycle, so the session stores
+/// the boot ID of the kernel, the size of the recording buffer and the
+/// recorder mode, which are checked against the current ones with
+/// [`Session::check`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(super) struct Session {
+    filename: String,
+    filename_template: String,
+    description_template: String,
+    description: String,
+    author: String,
+    mode: RecorderMode,
+    decimation: usize,
+    sample_rate: f64,
+    frequency: f64,
+    // Start time of the recording, in milliseconds since the UNIX epoch.
+    start_time: f64,
+    geolocation: Option<maia_json::Geolocation>,
+    // Size of the recording buffer in bytes.
+    buffer_size: usize,
+    // Boot ID of the kernel, if it is available.
+    boot_id: Option<String>,
+}
+
+impl Session {
+    pub(super) async fn new(metadata: &RecordingMeta, buffer_size: usize) -> Session {
+        let sigmf_meta = &metadata.sigmf_meta;
+        Session {
+            filename: metadata.filename.clone(),
+            filename_template: metadata.filename_template.clone(),
+            description_template: metadata.description_template.clone(),
+            description: sigmf_meta.description().to_string(),
+            author: sigmf_meta.author().to_string(),
+            mode: metadata.mode,
+            decimation: metadata.decimation,
+            sample_rate: sigmf_meta.sample_rate(),
+            frequency: sigmf_meta.frequency(),
+            start_time: sigmf_meta.datetime().timestamp_micros() as f64 * 1e-3,
+            geolocation: sigmf_meta.geolocation().map(|g| g.into()),
+            buffer_size,
+            boot_id: boot_id().await,
+        }
+    }
+
+    // Checks that the recording of the session can still be in the recording
+    // buffer, given the current size of the buffer and the recorder mode
+    // register of the IP core.
+    pub(super) async fn check(&self, buffer_size: usize, mode: RecorderMode) -> Result<()> {
+        if let (Some(session), Some(current)) = (&self.boot_id, boot_id().await) {
+            anyhow::ensure!(
+                *session == current,
+                "the session is from a previous boot, so the recording buffer has been lost"
+            );
+        }
+        anyhow::ensure!(
+            self.buffer_size == buffer_size,
+            "the recording buffer size has changed from {} to {buffer_size} bytes",
+            self.buffer_size
+        );
+        anyhow::ensure!(
+            self.mode == mode,
+            "the recorder mode has changed from {:?} to {mode:?}",
+            self.mode
+        );
+        Ok(())
+    }
+
+    pub(super) fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    // Restores the metadata of the recording saved in the session.
+    pub(super) fn restore(self, metadata: &mut RecordingMeta) -> Result<()> {
+        metadata.filename = self.filename;
+        metadata.filename_template = self.filename_template;
+        metadata.description_template = self.description_template;
+        metadata.mode = self.mode;
+        metadata.decimation = self.decimation;
+        let sigmf_meta = &mut metadata.sigmf_meta;
+        sigmf_meta.set_description(&self.description);
+        sigmf_meta.set_author(&self.author);
+        sigmf_meta.set_datatype(self.mode.into());
+        sigmf_meta.set_sample_rate(self.sample_rate);
+        sigmf_meta.set_frequency(self.frequency);
+        let datetime = chrono::DateTime::from_timestamp_micros((self.start_time * 1e3) as i64)
+            .context("invalid start time")?;
+        sigmf_meta.set_datetime(datetime);
+        sigmf_meta.set_geolocation_optional(self.geolocation.map(|g| g.try_into()).transpose()?);
+        Ok(())
+    }
+}
+
+// Returns the boot ID of the kernel, which changes each time that the system
+// boots. This is None if it cannot be read.
+async fn boot_id() -> Option<String> {
+    tokio::fs::read_to_string("/proc/sys/kernel/random/boot_id")
+        .await
+        .ok()
+        .map(|id| id.trim().to_string())
+}
+
+/// Saves a session to the session file.
+pub(super) async fn save(file: &Path, session: &Session) -> Result<()> {
+    crate::fs::write_atomic(file, serde_json::to_vec_pretty(session)?).await
+}
+
+/// Loads the session from the session file.
+///
+/// Returns `None` if the file does not exist.
+pub(super) async fn load(file: &Path) -> Result<Option<Session>> {
+    match tokio::fs::read(file).await {
+        Ok(json) => Ok(Some(
+            serde_json::from_slice(&json).with_context(|| format!("failed to parse {file:?}"))?,
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {file:?}")),
+    }
+}
+
+/// Puts aside a session file that cannot be restored.
+///
+/// The file is renamed with a `.bad` extension, so that it can be inspected
+/// but it is not loaded again.
+pub(super) async fn discard(file: &Path) -> Result<()> {
+    let mut bad_file = file.as_os_str().to_owned();
+    bad_file.push(".bad");
+    tracing::warn!("moving invalid recording session {file:?} to {bad_file:?}");
+    tokio::fs::rename(file, &bad_file)
+        .await
+        .with_context(|| format!("failed to rename {file:?}"))
+}
+
+/// Removes the session file.
+///
+/// It is not an error if the file does not exist.
+pub(super) async fn remove(file: &Path) -> Result<()> {
+    match tokio::fs::remove_file(file).await {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(err).with_context(|| format!("failed to remove {file:?}"))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,163 @@
+use super::super::json_error::JsonError;
+use super::{RecordingBuffer, RecordingBufferInfo};
+use crate::app::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use num_complex::{Complex32, Complex64};
+use serde::Deserialize;
+
+const DEFAULT_BINS: usize = 64;
+const MAX_BINS: usize = 4096;
+// Approximate number of items that are converted to IQ samples at once.
+const BLOCK_ITEMS: usize = 1 << 16;
+
+/// Query parameters of the recording statistics.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StatsQuery {
+    #[serde(default = "default_bins")]
+    bins: usize,
+}
+
+fn default_bins() -> usize {
+    DEFAULT_BINS
+}
+
+/// Returns statistics of the IQ samples of the recording.
+///
+/// The statistics are computed on the whole recording each time that they are
+/// requested, which can take a few seconds for long recordings. The number of
+/// bins of the histogram is given by the `bins` query parameter.
+pub async fn get_stats(
+    State(state): State<AppState>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<maia_json::RecordingStats>, JsonError> {
+    if !(1..=MAX_BINS).contains(&query.bins) {
+        return Err(JsonError::client_error_alert(anyhow::anyhow!(
+            "bins must be between 1 and {MAX_BINS}"
+        )));
+    }
+    let buffer = state
+        .recorder()
+        .buffer
+        .clone()
+        .try_read_owned()
+        .map_err(|_| JsonError::client_error_alert(anyhow::anyhow!("recording in progress")))?;
+    let metadata = state.recorder().metadata.lock().await.clone();
+    let info = RecordingBufferInfo::new(&metadata, &buffer);
+    let bins = query.bins;
+    let stats = tokio::task::spawn_blocking(move || compute_stats(&buffer, &info, bins))
+        .await
+        .map_err(JsonError::server_error)?;
+    Ok(Json(stats))
+}
+
+fn compute_stats(
+    buffer: &RecordingBuffer,
+    info: &RecordingBufferInfo,
+    bins: usize,
+) -> maia_json::RecordingStats {
+    let data = &buffer.0.as_slice()[..info.num_items() * info.input_bytes_per_item];
+    let mut stats = Stats::new(bins);
+    for block in data.chunks(BLOCK_ITEMS * info.input_bytes_per_item) {
+        let clipped_samples = info.mode.clipped_samples(&info.mode.sigmf_data(block));
+        stats.update(&info.mode.iq_samples(block), clipped_samples);
+    }
+    stats.json()
+}
+
+// Accumulates the statistics of the IQ samples.
+struct Stats {
+    num_samples: u64,
+    sum: Complex64,
+    power: f64,
+    clipped_samples: u64,
+    histogram: Vec<u64>,
+}
+
+impl Stats {
+    fn new(bins: usize) -> Stats {
+        Stats {
+            num_samples: 0,
+            sum: Complex64::default(),
+            power: 0.0,
+            clipped_samples: 0,
+            histogram: vec![0; bins],
+        }
+    }
+
+    // Adds a block of samples, of which clipped_samples are clipped.
+    fn update(&mut self, samples: &[Complex32], clipped_samples: usize) {
+        let bins = self.histogram.len();
+        let bin_scale = 0.5 * bins as f32;
+        // The sums of each block are done in f32 and accumulated in f64, to
+        // avoid losing precision in long recordings.
+        let mut sum = Complex32::default();
+        let mut power = 0.0;
+        for x in samples {
+            sum += x;
+            power += x.norm_sqr();
+            for y in [x.re, x.im] {
+                let bin = ((y + 1.0) * bin_scale) as usize;
+                self.histogram[bin.min(bins - 1)] += 1;
+            }
+        }
+        self.num_samples += samples.len() as u64;
+        self.clipped_samples += clipped_samples as u64;
+        self.sum += Complex64::new(sum.re.into(), sum.im.into());
+        self.power += f64::from(power);
+    }
+
+    fn json(self) -> maia_json::RecordingStats {
+        let (dc_offset, power) = if self.num_samples == 0 {
+            (Complex64::default(), 0.0)
+        } else {
+            let n = self.num_samples as f64;
+            (self.sum / n, self.power / n)
+        };
+        maia_json::RecordingStats {
+            num_samples: self.num_samples,
+            dc_offset_i: dc_offset.re,
+            dc_offset_q: dc_offset.im,
+            power_dbfs: (power > 0.0).then(|| maia_json::Decibels::from_power_ratio(power)),
+            clipped_samples: self.clipped_samples,
+            histogram: self.histogram,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::Decibels;
+
+    #[test]
+    fn stats() {
+        let mut stats = Stats::new(4);
+        stats.update(
+            &[
+                Complex32::new(0.5, -0.25),
+                Complex32::new(-1.0, 0.0),
+                Complex32::new(127.0 / 128.0, 0.75),
+            ],
+            2,
+        );
+        stats.update(&[Complex32::new(0.0, 0.5)], 0);
+        let stats = stats.json();
+        assert_eq!(stats.num_samples, 4);
+        assert!((stats.dc_offset_i - (0.5 - 1.0 + 127.0 / 128.0) / 4.0).abs() < 1e-6);
+        assert!((stats.dc_offset_q - 0.25).abs() < 1e-6);
+        let power = (0.25 + 0.0625 + 1.0 + (127.0f64 / 128.0).powi(2) + 0.5625 + 0.25) / 4.0;
+        assert!(
+            (stats.power_dbfs.unwrap() - Decibels::from_power_ratio(power)).abs() < Decibels(1e-5)
+        );
+        assert_eq!(stats.clipped_samples, 2);
+        assert_eq!(stats.histogram, vec![1, 1, 2, 4]);
+
+        let stats = Stats::new(8).json();
+        assert_eq!(stats.num_samples, 0);
+        assert_eq!(stats.power_dbfs, None);
+        assert_eq!(stats.histogram, vec![0; 8]);
+    }
+}
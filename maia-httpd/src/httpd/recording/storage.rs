@@ -0,0 +1,301 @@
+//! Recording storage.
+//!
+//! This module defines the [`RecordingStorage`] trait, which is used by the
+//! recorder to access the recorded data, and its implementations. The
+//! recorder handlers only use this trait, so that the recording can be stored
+//! in places other than the recording DMA buffer without modifying the REST
+//! API.
+
+use crate::fpga::{FpgaBackend, RecordingMemory};
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+
+/// Recording storage.
+///
+/// This trait gives read access to the storage that contains the data of the
+/// latest recording. The data has the format produced by the recorder of the
+/// FPGA IP core.
+pub trait RecordingStorage: std::fmt::Debug + Send + Sync {
+    /// Returns the contents of the storage.
+    ///
+    /// The latest recording is at the beginning of the slice, which can be
+    /// larger than the recording.
+    fn as_slice(&self) -> &[u8];
+
+    /// Returns the size of the storage in bytes.
+    ///
+    /// This is the maximum size of a recording. It can change when the storage
+    /// is refreshed.
+    fn size(&self) -> usize;
+
+    /// Returns the size of the latest recording in bytes.
+    ///
+    /// This can be smaller than the range written by the recorder of the FPGA
+    /// IP core, for instance if the storage is smaller than the recording DMA
+    /// buffer or if copying the recording to a [`FileStorage`] failed.
+    fn recording_size(&self) -> usize;
+
+    /// Refreshes the storage after a recording has finished.
+    ///
+    /// The `recorded` argument is the number of bytes that the recorder of the
+    /// FPGA IP core has written to the recording DMA buffer. Storages other
+    /// than the DMA buffer copy the recording from it in this function. The
+    /// contents returned by [`RecordingStorage::as_slice`] are only guaranteed
+    /// to be up to date with the latest recording after this function has been
+    /// called.
+    fn refresh(&mut self, recorded: usize) -> BoxFuture<'_, Result<()>>;
+}
+
+/// Recording DMA buffer storage.
+///
+/// This storage gives access to the DMA buffer to which the FPGA IP core
+/// writes the recordings. It is the storage used by default.
+#[derive(Debug)]
+pub struct DmaStorage {
+    backend: Arc<dyn FpgaBackend>,
+    memory: Box<dyn RecordingMemory>,
+    recording_size: usize,
+}
+
+impl DmaStorage {
+    /// Creates a new DMA buffer storage.
+    ///
+    /// The storage does not contain any recording until it is refreshed.
+    pub async fn new(backend: Arc<dyn FpgaBackend>) -> Result<DmaStorage> {
+        let memory = backend.recording_buffer().await?;
+        Ok(DmaStorage {
+            backend,
+            memory,
+            recording_size: 0,
+        })
+    }
+}
+
+impl RecordingStorage for DmaStorage {
+    fn as_slice(&self) -> &[u8] {
+        self.memory.as_slice()
+    }
+
+    fn size(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn recording_size(&self) -> usize {
+        self.recording_size
+    }
+
+    fn refresh(&mut self, recorded: usize) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            // mmap() the buffer again to invalidate the cache
+            self.memory = self.backend.recording_buffer().await?;
+            self.recording_size = recorded.min(self.size());
+            Ok(())
+        })
+    }
+}
+
+/// File storage.
+///
+/// This storage keeps the latest recording in a file, which can be in an SD
+/// card or in a network filesystem such as NFS, so that it persists across
+/// reboots. The file contains the recorded data with the same format as the
+/// recording DMA buffer. Each time that a recording finishes, it is copied from
+/// the DMA buffer to the file, and the file is then mapped into memory to
+/// access it. The file must not be modified by other programs while it is used
+/// by the storage.
+#[derive(Debug)]
+pub struct FileStorage {
+    path: PathBuf,
+    dma: DmaStorage,
+    mapping: Option<FileMapping>,
+}
+
+impl FileStorage {
+    /// Opens a file storage.
+    ///
+    /// The recordings are copied to the file from the recording DMA buffer of
+    /// `backend`. If the file exists, its contents are used as the latest
+    /// recording until the storage is refreshed. This function fails if the
+    /// file exists but cannot be read.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        backend: Arc<dyn FpgaBackend>,
+    ) -> Result<FileStorage> {
+        let path = path.as_ref().to_path_buf();
+        let dma = DmaStorage::new(backend).await?;
+        let mapping = match tokio::fs::File::open(&path).await {
+            Ok(file) => FileMapping::new(file)
+                .await
+                .with_context(|| format!("failed to map recording file {}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to open recording file {}", path.display()))
+            }
+        };
+        Ok(FileStorage { path, dma, mapping })
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    async fn write_file(&self) -> Result<()> {
+        let data = &self.dma.as_slice()[..self.dma.recording_size()];
+        let mut file = tokio::fs::File::create(&self.path).await?;
+        file.write_all(data).await?;
+        file.flush().await?;
+        Ok(())
+    }
+}
+
+impl RecordingStorage for FileStorage {
+    fn as_slice(&self) -> &[u8] {
+        self.mapping.as_ref().map_or(&[], FileMapping::as_slice)
+    }
+
+    fn size(&self) -> usize {
+        self.dma.size()
+    }
+
+    fn recording_size(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    fn refresh(&mut self, recorded: usize) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            // The file is unmapped before it is overwritten. If copying the
+            // recording fails, the storage is left empty.
+            self.mapping = None;
+            self.dma.refresh(recorded).await?;
+            let path = self.path.display();
+            self.write_file()
+                .await
+                .with_context(|| format!("failed to write recording file {path}"))?;
+            let file = tokio::fs::File::open(&self.path)
+                .await
+                .with_context(|| format!("failed to open recording file {path}"))?;
+            self.mapping = FileMapping::new(file)
+                .await
+                .with_context(|| format!("failed to map recording file {path}"))?;
+            Ok(())
+        })
+    }
+}
+
+// Read-only memory mapping of the whole recording file.
+#[derive(Debug)]
+struct FileMapping {
+    base: *const u8,
+    size: usize,
+}
+
+// The mapping is read-only, so it can be shared between threads.
+unsafe impl Send for FileMapping {}
+unsafe impl Sync for FileMapping {}
+
+impl FileMapping {
+    // Maps a file. Returns None if the file is empty, since empty mappings
+    // are not allowed.
+    async fn new(file: tokio::fs::File) -> Result<Option<FileMapping>> {
+        let size = usize::try_from(file.metadata().await?.len())?;
+        if size == 0 {
+            return Ok(None);
+        }
+        // mmap() is run with spawn_blocking, since it can take some time for
+        // large files.
+        tokio::task::spawn_blocking(move || unsafe {
+            match libc::mmap(
+                std::ptr::null_mut::<libc::c_void>(),
+                size,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            ) {
+                libc::MAP_FAILED => Err(std::io::Error::last_os_error().into()),
+                x => Ok(Some(FileMapping {
+                    base: x as *const u8,
+                    size,
+                })),
+            }
+        })
+        .await?
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.base, self.size) }
+    }
+}
+
+impl Drop for FileMapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.size);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn dma_storage() {
+        let (state, _) = crate::mock::app_state().await;
+        let backend = Arc::clone(state.ip_core().lock().unwrap().backend());
+        let mut storage = DmaStorage::new(backend).await.unwrap();
+        assert_eq!(storage.as_slice().len(), crate::mock::RECORDING_SIZE);
+        assert_eq!(storage.size(), crate::mock::RECORDING_SIZE);
+        assert_eq!(storage.recording_size(), 0);
+        storage.refresh(1000).await.unwrap();
+        assert_eq!(storage.as_slice().len(), crate::mock::RECORDING_SIZE);
+        assert_eq!(storage.recording_size(), 1000);
+        // the recording size is limited to the size of the buffer
+        storage
+            .refresh(crate::mock::RECORDING_SIZE + 1)
+            .await
+            .unwrap();
+        assert_eq!(storage.recording_size(), crate::mock::RECORDING_SIZE);
+    }
+
+    #[tokio::test]
+    async fn file_storage() {
+        let path = std::env::temp_dir().join(format!(
+            "maia-httpd-file-storage-{}.bin",
+            std::process::id()
+        ));
+        let (state, _) = crate::mock::app_state().await;
+        let backend = Arc::clone(state.ip_core().lock().unwrap().backend());
+        let dma = DmaStorage::new(Arc::clone(&backend)).await.unwrap();
+
+        // a missing file is an empty storage
+        let storage = FileStorage::open(&path, Arc::clone(&backend))
+            .await
+            .unwrap();
+        assert_eq!(storage.path(), path);
+        assert_eq!(storage.recording_size(), 0);
+        assert_eq!(storage.size(), crate::mock::RECORDING_SIZE);
+
+        // an existing file is used as the latest recording
+        tokio::fs::write(&path, [1, 2, 3, 4]).await.unwrap();
+        let mut storage = FileStorage::open(&path, backend).await.unwrap();
+        assert_eq!(storage.as_slice(), &[1, 2, 3, 4]);
+        assert_eq!(storage.recording_size(), 4);
+
+        // the recording is copied from the DMA buffer when the storage is
+        // refreshed
+        storage.refresh(1000).await.unwrap();
+        assert_eq!(storage.recording_size(), 1000);
+        assert_eq!(storage.as_slice(), &dma.as_slice()[..1000]);
+        assert_eq!(tokio::fs::metadata(&path).await.unwrap().len(), 1000);
+        storage.refresh(0).await.unwrap();
+        assert_eq!(storage.recording_size(), 0);
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
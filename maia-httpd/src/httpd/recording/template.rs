@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use maia_json::RecorderMode;
+
+/// Variables of the recording metadata templates.
+///
+/// The file name and the description of a recording can contain variables
+/// such as `{freq_mhz}` or `{date}`, which are replaced by their values when
+/// the recording starts. The supported variables are:
+///
+/// - `{freq_hz}`, `{freq_khz}` and `{freq_mhz}`: center frequency of the
+///   recording.
+/// - `{samp_rate_ksps}`: sample rate of the recording.
+/// - `{date}` and `{time}`: UTC date and time of the start of the recording, as
+///   `YYYY-MM-DD` and `HH-MM-SS`.
+/// - `{mode}`: recorder mode, such as `IQ12bit`.
+/// - `{gain}`: AD9361 RX gain in dB.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateVariables {
+    /// Center frequency (in Hz).
+    pub frequency: f64,
+    /// Sample rate (in samples per second).
+    pub samp_rate: f64,
+    /// Start of the recording.
+    pub datetime: DateTime<Utc>,
+    /// Recorder mode.
+    pub mode: RecorderMode,
+    /// RX gain (in dB).
+    pub gain: f64,
+}
+
+impl TemplateVariables {
+    // Returns the value of a variable, or None if the variable is unknown.
+    fn value(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "freq_hz" => format!("{:.0}", self.frequency),
+            "freq_khz" => format_decimal(self.frequency * 1e-3, 3),
+            "freq_mhz" => format_decimal(self.frequency * 1e-6, 6),
+            "samp_rate_ksps" => format_decimal(self.samp_rate * 1e-3, 3),
+            "date" => self.datetime.format("%Y-%m-%d").to_string(),
+            "time" => self.datetime.format("%H-%M-%S").to_string(),
+            // The Debug format matches the name used in the JSON API.
+            "mode" => format!("{:?}", self.mode),
+            "gain" => format_decimal(self.gain, 2),
+            _ => return None,
+        })
+    }
+
+    /// Expands the variables in a template.
+    ///
+    /// Unknown variables and unmatched braces are left as they are.
+    pub fn expand(&self, template: &str) -> String {
+        let mut expanded = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            match rest[1..]
+                .find(['{', '}'])
+                .filter(|&end| rest.as_bytes()[end + 1] == b'}')
+                .and_then(|end| Some((end + 2, self.value(&rest[1..end + 1])?)))
+            {
+                Some((len, value)) => {
+                    expanded.push_str(&value);
+                    rest = &rest[len..];
+                }
+                None => {
+                    expanded.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+// Formats a number with at most the given number of decimals, removing
+// trailing zeros.
+fn format_decimal(x: f64, decimals: usize) -> String {
+    let s = format!("{x:.decimals$}");
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.').to_string()
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn expand() {
+        let variables = TemplateVariables {
+            frequency: 145.825e6,
+            samp_rate: 61.44e6 / 20.0,
+            datetime: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            mode: RecorderMode::IQ12bit,
+            gain: 42.5,
+        };
+        assert_eq!(
+            variables.expand("iss_{freq_mhz}MHz_{date}T{time}"),
+            "iss_145.825MHz_2023-11-14T22-13-20"
+        );
+        assert_eq!(
+            variables.expand("{freq_hz} {freq_khz} {samp_rate_ksps} {mode} {gain}"),
+            "145825000 145825 3072 IQ12bit 42.5"
+        );
+        assert_eq!(
+            variables.expand("{unknown} {{date}} {date {}"),
+            "{unknown} {2023-11-14} {date {}"
+        );
+        assert_eq!(variables.expand("no variables"), "no variables");
+    }
+}
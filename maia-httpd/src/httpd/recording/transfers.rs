@@ -0,0 +1,170 @@
+use super::super::{json_error::JsonError, list};
+use crate::app::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use futures::{Stream, StreamExt};
+use maia_json::Seconds;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Instant;
+
+/// Downloads of the recording that are in progress.
+#[derive(Debug, Default)]
+pub(super) struct Transfers {
+    transfers: BTreeMap<u64, Transfer>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+struct Transfer {
+    filename: String,
+    client: Option<SocketAddr>,
+    size: usize,
+    bytes_sent: Arc<AtomicU64>,
+    start: Instant,
+}
+
+impl Transfer {
+    fn json(&self, id: u64) -> maia_json::RecorderTransfer {
+        let bytes_sent = self.bytes_sent.load(Ordering::Relaxed);
+        let duration = self.start.elapsed().as_secs_f64();
+        maia_json::RecorderTransfer {
+            id,
+            filename: self.filename.clone(),
+            client: self.client.map(|client| client.to_string()),
+            size: self.size as u64,
+            bytes_sent,
+            rate: if duration > 0.0 {
+                bytes_sent as f64 / duration
+            } else {
+                0.0
+            },
+            duration: Seconds(duration),
+        }
+    }
+
+    fn description(&self) -> String {
+        let client = match self.client {
+            Some(client) => format!(" by {}", client.ip()),
+            None => String::new(),
+        };
+        format!(
+            "download of {}{client} (for {:.0} s)",
+            self.filename,
+            self.start.elapsed().as_secs_f64()
+        )
+    }
+}
+
+impl Transfers {
+    fn json(&self) -> maia_json::RecorderTransfers {
+        maia_json::RecorderTransfers {
+            transfers: self
+                .transfers
+                .iter()
+                .map(|(&id, transfer)| transfer.json(id))
+                .collect(),
+            total: self.transfers.len(),
+        }
+    }
+}
+
+// Removes a transfer from the list when the download finishes or is
+// interrupted.
+#[derive(Debug)]
+struct TransferGuard {
+    state: AppState,
+    id: u64,
+}
+
+impl Drop for TransferGuard {
+    fn drop(&mut self) {
+        self.state
+            .recorder()
+            .transfers
+            .lock()
+            .unwrap()
+            .transfers
+            .remove(&self.id);
+    }
+}
+
+/// Tracks the progress of a download of the recording.
+///
+/// The download is listed in `/api/recorder/transfers` until the returned
+/// stream is dropped.
+pub(super) fn track<S, B, E>(
+    state: &AppState,
+    filename: String,
+    client: Option<SocketAddr>,
+    size: usize,
+    stream: S,
+) -> impl Stream<Item = Result<B, E>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+{
+    let bytes_sent = Arc::new(AtomicU64::new(0));
+    let id = {
+        let mut transfers = state.recorder().transfers.lock().unwrap();
+        let id = transfers.next_id;
+        transfers.next_id += 1;
+        transfers.transfers.insert(
+            id,
+            Transfer {
+                filename,
+                client,
+                size,
+                bytes_sent: Arc::clone(&bytes_sent),
+                start: Instant::now(),
+            },
+        );
+        id
+    };
+    let guard = TransferGuard {
+        state: state.clone(),
+        id,
+    };
+    stream.inspect(move |chunk| {
+        // The guard is moved into the closure so that it lives as long as the
+        // stream.
+        let _ = &guard;
+        if let Ok(chunk) = chunk {
+            bytes_sent.fetch_add(chunk.as_ref().len() as u64, Ordering::Relaxed);
+        }
+    })
+}
+
+/// Returns a description of the users of the recording buffer.
+///
+/// This lists the downloads, uploads and processing jobs that hold a lock on
+/// the recording buffer, and for how long they have held it.
+pub(super) fn buffer_users(state: &AppState) -> Vec<String> {
+    let recorder = state.recorder();
+    let mut users = recorder
+        .transfers
+        .lock()
+        .unwrap()
+        .transfers
+        .values()
+        .map(Transfer::description)
+        .collect::<Vec<_>>();
+    users.extend(recorder.upload.lock().unwrap().buffer_user());
+    users.extend(recorder.processing.lock().unwrap().buffer_users());
+    users
+}
+
+pub async fn get_recorder_transfers(
+    State(state): State<AppState>,
+    Query(query): Query<maia_json::ListQuery>,
+) -> Result<Json<maia_json::RecorderTransfers>, JsonError> {
+    let mut json = state.recorder().transfers.lock().unwrap().json();
+    json.total = list::paginate(&mut json.transfers, &query)?;
+    Ok(Json(json))
+}
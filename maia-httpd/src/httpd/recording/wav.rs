@@ -0,0 +1,43 @@
+use super::{RecorderMode, RecordingBuffer, RecordingMeta, RecordingStream};
+use crate::wav::Header;
+use anyhow::Result;
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use tokio::sync::OwnedRwLockReadGuard;
+
+/// Creates a stream that contains the recording as an IQ WAV file.
+///
+/// The samples are stored as 8-bit or 16-bit integers, with 12-bit recordings
+/// being converted to 16-bit. The sample rate is rounded to an integer, since
+/// this is required by the WAV format.
+pub async fn recording_stream(
+    buffer: OwnedRwLockReadGuard<RecordingBuffer>,
+    metadata: &RecordingMeta,
+) -> Result<(impl Stream<Item = Result<Bytes, std::io::Error>>, usize)> {
+    let buffer = RecordingStream::new(buffer, metadata);
+    let bits_per_sample = match metadata.mode {
+        RecorderMode::IQ8bit => 8,
+        RecorderMode::IQ12bit | RecorderMode::IQ16bit => 16,
+    };
+    let header = Header {
+        sample_rate: metadata.sigmf_meta.sample_rate().round() as u32,
+        bits_per_sample,
+        num_samples: buffer.info.num_items().try_into()?,
+        start: metadata.sigmf_meta.datetime(),
+        frequency: metadata.sigmf_meta.frequency(),
+    };
+    let size = usize::try_from(header.file_size())?;
+    let samples = buffer.map(move |chunk| {
+        chunk.map(|chunk| {
+            if bits_per_sample == 8 {
+                // 8-bit samples are unsigned in WAV files
+                Bytes::from(chunk.iter().map(|x| x ^ 0x80).collect::<Vec<u8>>())
+            } else {
+                chunk
+            }
+        })
+    });
+    let header = Bytes::from(header.to_bytes());
+    let stream = futures::stream::once(async { Ok(header) }).chain(samples);
+    Ok((stream, size))
+}
@@ -0,0 +1,90 @@
+use super::json_error::JsonError;
+use crate::{app::AppState, scanner, tuner::TunerOwner};
+use axum::{extract::State, Json};
+use maia_json::{PatchScanner, Scanner};
+
+pub async fn get_scanner(State(state): State<AppState>) -> Json<Scanner> {
+    Json(state.scanner().json())
+}
+
+pub async fn patch_scanner(
+    State(state): State<AppState>,
+    Json(patch): Json<PatchScanner>,
+) -> Result<Json<Scanner>, JsonError> {
+    patch.validate().map_err(JsonError::client_error_alert)?;
+    // The limits apply to the RX LO frequency. This is checked again when the
+    // channels are tuned, since the DDC frequency can change in the meantime.
+    for channel in patch.channels.iter().flatten() {
+        state
+            .limits()
+            .check_frequency(scanner::lo_frequency(&state, channel))
+            .map_err(JsonError::client_error_alert)?;
+    }
+    if patch.enabled == Some(true) {
+        if let Some(owner) = state.tuner().owner() {
+            if owner != TunerOwner::Scanner {
+                return Err(JsonError::conflict(anyhow::anyhow!(
+                    "the RX frequency is being controlled by {owner}"
+                )));
+            }
+        }
+    }
+    state
+        .scanner()
+        .patch(patch)
+        .map(Json)
+        .map_err(JsonError::client_error_alert)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scanner::test_channels;
+    use axum::{http::StatusCode, response::IntoResponse};
+    use maia_json::{Decibels, ScannerState};
+
+    #[tokio::test]
+    async fn scanner() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(scanner) = get_scanner(State(state.clone())).await;
+        assert!(!scanner.enabled);
+        assert!(scanner.channels.is_empty());
+        assert_eq!(scanner.state, ScannerState::Stopped);
+        let patch = PatchScanner {
+            enabled: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(
+            patch_scanner(State(state.clone()), Json(patch))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        let patch = PatchScanner {
+            hold: Some(PatchScanner::MAX_HOLD * 2.0),
+            ..Default::default()
+        };
+        assert_eq!(
+            patch_scanner(State(state.clone()), Json(patch))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        let patch = PatchScanner {
+            channels: Some(test_channels()),
+            threshold: Some(Decibels(6.0)),
+            ..Default::default()
+        };
+        let Json(scanner) = patch_scanner(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        assert!(!scanner.enabled);
+        assert_eq!(scanner.channels, test_channels());
+        assert_eq!(scanner.threshold, Decibels(6.0));
+        assert_eq!(get_scanner(State(state)).await.channels, test_channels());
+    }
+}
@@ -0,0 +1,43 @@
+use super::json_error::JsonError;
+use crate::{app::AppState, iio};
+use anyhow::Result;
+use axum::{extract::State, Json};
+use maia_json::Sensors;
+
+pub async fn sensors_json(iio: &iio::Ad9361) -> Result<Sensors> {
+    Ok(Sensors {
+        rx_rssi: iio.get_rx_rssi().await?,
+        rx_gain: iio.get_rx_gain().await?,
+    })
+}
+
+pub async fn get_sensors(State(state): State<AppState>) -> Result<Json<Sensors>, JsonError> {
+    let ad9361 = state.ad9361().lock().await;
+    sensors_json(&ad9361)
+        .await
+        .map_err(JsonError::server_error)
+        .map(Json)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::Decibels;
+
+    #[tokio::test]
+    async fn sensors() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(sensors) = get_sensors(State(state.clone())).await.unwrap();
+        assert!(sensors.rx_rssi > Decibels(0.0));
+        assert_eq!(sensors.rx_gain, Decibels(70.0));
+        state
+            .ad9361()
+            .lock()
+            .await
+            .set_rx_gain(Decibels(20.0))
+            .await
+            .unwrap();
+        let Json(sensors) = get_sensors(State(state)).await.unwrap();
+        assert_eq!(sensors.rx_gain, Decibels(20.0));
+    }
+}
@@ -0,0 +1,45 @@
+// Service worker for maia-wasm. This file is generated by maia-httpd, which
+// fills in the cache name and the list of assets.
+
+const CACHE_PREFIX = "maia-sdr-";
+const CACHE_NAME = CACHE_PREFIX + "{{VERSION}}";
+const ASSETS = {{ASSETS}};
+
+self.addEventListener("install", (event) => {
+    event.waitUntil(
+        caches.open(CACHE_NAME)
+            .then((cache) => cache.addAll(ASSETS))
+            .then(() => self.skipWaiting())
+    );
+});
+
+self.addEventListener("activate", (event) => {
+    // Remove the caches of previous versions of the assets
+    event.waitUntil(
+        caches.keys()
+            .then((keys) => Promise.all(
+                keys.filter((key) => key.startsWith(CACHE_PREFIX) && key !== CACHE_NAME)
+                    .map((key) => caches.delete(key))
+            ))
+            .then(() => self.clients.claim())
+    );
+});
+
+self.addEventListener("fetch", (event) => {
+    const request = event.request;
+    const url = new URL(request.url);
+    if (request.method !== "GET" || url.origin !== self.location.origin) {
+        return;
+    }
+    // The root URL serves index.html
+    const path = url.pathname === "/" ? "/index.html" : url.pathname;
+    if (!ASSETS.includes(path)) {
+        // API requests and other files are always sent to the network
+        return;
+    }
+    event.respondWith(
+        caches.open(CACHE_NAME)
+            .then((cache) => cache.match(path))
+            .then((response) => response || fetch(request))
+    );
+});
@@ -0,0 +1,156 @@
+use super::json_error::JsonError;
+use anyhow::Result;
+use axum::{http::header, response::IntoResponse};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::SystemTime;
+
+// Template of the service worker.
+const TEMPLATE: &str = include_str!("service_worker.js");
+// Directories, relative to the static files directory, that contain the assets
+// that are cached by the service worker.
+const ASSET_DIRS: [&str; 2] = ["", "pkg"];
+// File extensions of the assets that are cached by the service worker.
+// Precompressed variants (.br and .gz files) are not included, since the
+// browser requests the uncompressed file.
+const ASSET_EXTENSIONS: [&str; 6] = ["html", "js", "css", "json", "png", "wasm"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Asset {
+    // URL path of the asset
+    path: String,
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+// Lists the assets that are cached by the service worker, sorted by path.
+async fn assets(root: &Path) -> Result<Vec<Asset>> {
+    let mut assets = Vec::new();
+    for dir in ASSET_DIRS {
+        let mut entries = match tokio::fs::read_dir(root.join(dir)).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let metadata = entry.metadata().await?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            let is_asset = Path::new(name)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| ASSET_EXTENSIONS.contains(&ext));
+            if !metadata.is_file() || !is_asset {
+                continue;
+            }
+            let path = if dir.is_empty() {
+                format!("/{name}")
+            } else {
+                format!("/{dir}/{name}")
+            };
+            assets.push(Asset {
+                path,
+                size: metadata.len(),
+                modified: metadata.modified().ok(),
+            });
+        }
+    }
+    assets.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(assets)
+}
+
+// Computes the cache version. It changes whenever any of the assets changes,
+// which includes each new build of maia-wasm.
+fn cache_version(assets: &[Asset]) -> String {
+    let mut hasher = Sha256::new();
+    for asset in assets {
+        hasher.update(asset.path.as_bytes());
+        hasher.update(asset.size.to_le_bytes());
+        let modified = asset
+            .modified
+            .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        hasher.update(modified.as_nanos().to_le_bytes());
+    }
+    hasher.finalize()[..8]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn service_worker(assets: &[Asset]) -> Result<String> {
+    let paths = assets.iter().map(|a| &a.path).collect::<Vec<_>>();
+    Ok(TEMPLATE
+        .replace("{{VERSION}}", &cache_version(assets))
+        .replace("{{ASSETS}}", &serde_json::to_string(&paths)?))
+}
+
+/// Returns the service worker of maia-wasm.
+///
+/// The service worker caches the maia-wasm assets, so that the UI can be
+/// installed as a Progressive Web App and loads without waiting for the
+/// network. It is generated from the files present in the static files
+/// directory, and its cache is versioned by their sizes and modification
+/// times, so that a new version of maia-wasm replaces the cached assets.
+pub async fn get_service_worker() -> Result<impl IntoResponse, JsonError> {
+    let assets = assets(Path::new("."))
+        .await
+        .map_err(JsonError::server_error)?;
+    let service_worker = service_worker(&assets).map_err(JsonError::server_error)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "text/javascript"),
+            // The browser checks for updates of the service worker on each
+            // page load
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        service_worker,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn asset_list() {
+        let root = std::env::temp_dir().join(format!("maia-httpd-sw-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("pkg")).unwrap();
+        for (name, contents) in [
+            ("index.html", "html"),
+            ("index.0123abcd0123abcd.js", "js"),
+            ("index.0123abcd0123abcd.js.gz", "gz"),
+            ("ca.crt", "crt"),
+            ("pkg/maia_wasm_bg.wasm", "wasm"),
+        ] {
+            std::fs::write(root.join(name), contents).unwrap();
+        }
+        let list = assets(&root).await.unwrap();
+        let paths = list.iter().map(|a| a.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                "/index.0123abcd0123abcd.js",
+                "/index.html",
+                "/pkg/maia_wasm_bg.wasm"
+            ]
+        );
+        let sw = service_worker(&list).unwrap();
+        assert!(sw.contains(&format!(
+            "const CACHE_NAME = CACHE_PREFIX + \"{}\";",
+            cache_version(&list)
+        )));
+        assert!(sw.contains(
+            r#"const ASSETS = ["/index.0123abcd0123abcd.js","/index.html","/pkg/maia_wasm_bg.wasm"];"#
+        ));
+
+        // A new build of maia-wasm changes the cache version
+        std::fs::write(root.join("pkg/maia_wasm_bg.wasm"), "new wasm").unwrap();
+        let new_list = assets(&root).await.unwrap();
+        assert_ne!(cache_version(&list), cache_version(&new_list));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
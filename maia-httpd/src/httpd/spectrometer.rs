@@ -1,16 +1,28 @@
 use super::json_error::JsonError;
-use crate::app::AppState;
+use crate::{
+    app::AppState,
+    colormap::Colormap,
+    fpga::IpCore,
+    spectrogram::{self, Spectrogram},
+    spectrometer::{self, SpectrumHistory, FFT_SIZE},
+};
 use anyhow::Result;
-use axum::{extract::State, Json};
-use maia_json::{PatchSpectrometer, Spectrometer};
-
-// TODO: do not hardcode FFT size
-const FFT_SIZE: u32 = 4096;
+use axum::{
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+    Json,
+};
+use maia_json::{
+    Decibels, Hertz, PatchSpectrometer, Spectrometer, SpectrometerBinOrder,
+    SpectrometerEqualization, SpectrometerFormat, SpectrometerUnits,
+};
+use serde::Deserialize;
 
 pub async fn spectrometer_json(state: &AppState) -> Result<Spectrometer> {
     let ad9361_samp_rate = state.ad9361_samp_rate().await?;
     let ip_core = state.ip_core().lock().unwrap();
-    let samp_rate = ad9361_samp_rate / ip_core.spectrometer_input_decimation() as f64;
+    let samp_rate = (ad9361_samp_rate / ip_core.spectrometer_input_decimation() as f64).0;
     let input = ip_core.spectrometer_input();
     let num_integrations = ip_core.spectrometer_number_integrations();
     let mode = ip_core.spectrometer_mode();
@@ -18,13 +30,33 @@ pub async fn spectrometer_json(state: &AppState) -> Result<Spectrometer> {
     state
         .spectrometer_config()
         .set_samp_rate_mode(samp_rate as f32, mode);
+    let alignment_period = state.spectrometer_config().alignment_period();
+    let (noise_floor_estimator, noise_floor_averaging) =
+        state.spectrometer_config().noise_floor_estimator();
+    let software_integrations = state.spectrometer_config().software_integrations();
+    let fpga_output_sampling_frequency = match alignment_period {
+        // The number of integrations changes on each spectrum, so the
+        // output sampling frequency is given by the alignment period.
+        Some(period) => period.recip(),
+        None => samp_rate / (f64::from(FFT_SIZE) * f64::from(num_integrations)),
+    };
+    let output_sampling_frequency =
+        fpga_output_sampling_frequency / f64::from(software_integrations);
     Ok(Spectrometer {
         input,
-        input_sampling_frequency: samp_rate,
-        output_sampling_frequency: samp_rate / (f64::from(FFT_SIZE) * f64::from(num_integrations)),
+        input_sampling_frequency: Hertz(samp_rate),
+        output_sampling_frequency: Hertz(output_sampling_frequency),
         number_integrations: num_integrations,
         fft_size: FFT_SIZE,
         mode,
+        retune_mode: state.spectrometer_config().retune_mode(),
+        wall_clock_alignment: alignment_period.is_some(),
+        noise_floor_estimator,
+        noise_floor_averaging,
+        dc_bin_removal: state.spectrometer_config().dc_bin_removal(),
+        software_integrations,
+        total_integrations: u64::from(num_integrations) * u64::from(software_integrations),
+        noise_floor: state.spectrometer_config().noise_floor().map(Decibels),
     })
 }
 
@@ -41,7 +73,10 @@ pub async fn get_spectrometer(
     get_spectrometer_json(&state).await
 }
 
-async fn update_spectrometer(state: &AppState, patch: &PatchSpectrometer) -> Result<(), JsonError> {
+pub(super) async fn update_spectrometer(
+    state: &AppState,
+    patch: &PatchSpectrometer,
+) -> Result<(), JsonError> {
     let ad9361_samp_rate = state
         .ad9361_samp_rate()
         .await
@@ -57,40 +92,597 @@ async fn update_spectrometer(state: &AppState, patch: &PatchSpectrometer) -> Res
     if let Some(mode) = &patch.mode {
         state.ip_core().lock().unwrap().set_spectrometer_mode(*mode);
     }
-    match patch {
+    if let Some(retune_mode) = &patch.retune_mode {
+        state.spectrometer_config().set_retune_mode(*retune_mode);
+    }
+    if let Some(dc_bin_removal) = patch.dc_bin_removal {
+        state
+            .spectrometer_config()
+            .set_dc_bin_removal(dc_bin_removal);
+    }
+    if let Some(software_integrations) = patch.software_integrations {
+        state
+            .spectrometer_config()
+            .set_software_integrations(software_integrations)
+            .map_err(JsonError::client_error_alert)?;
+    }
+    if patch.noise_floor_estimator.is_some() || patch.noise_floor_averaging.is_some() {
+        let (estimator, averaging) = state.spectrometer_config().noise_floor_estimator();
+        state
+            .spectrometer_config()
+            .set_noise_floor_estimator(
+                patch.noise_floor_estimator.unwrap_or(estimator),
+                patch.noise_floor_averaging.unwrap_or(averaging),
+            )
+            .map_err(JsonError::client_error_alert)?;
+    }
+    let config = state.spectrometer_config();
+    let previous_period = config.alignment_period();
+    let aligned = patch
+        .wall_clock_alignment
+        .unwrap_or(previous_period.is_some());
+    let mut ip_core = state.ip_core().lock().unwrap();
+    let in_freq = (ad9361_samp_rate / ip_core.spectrometer_input_decimation() as f64).0;
+    if aligned {
+        // The spectrometer task sets the number of integrations according to
+        // the alignment period.
+        let mut software_integrations = config.software_integrations();
+        let period = match patch {
+            PatchSpectrometer {
+                number_integrations: Some(n),
+                ..
+            } => f64::from(FFT_SIZE) * f64::from(*n) / in_freq,
+            PatchSpectrometer {
+                output_sampling_frequency: Some(Hertz(out_freq)),
+                ..
+            } => {
+                // The output sampling frequency refers to the spectrum lines
+                // after the software integration, so the FPGA spectrometer
+                // period is shorter.
+                let line_period = out_freq.recip();
+                software_integrations =
+                    line_software_integrations(patch, line_period * in_freq / f64::from(FFT_SIZE));
+                line_period / f64::from(software_integrations)
+            }
+            _ => previous_period.unwrap_or_else(|| {
+                f64::from(FFT_SIZE) * f64::from(ip_core.spectrometer_number_integrations())
+                    / in_freq
+            }),
+        };
+        let max_period = f64::from(FFT_SIZE)
+            * f64::from(IpCore::spectrometer_max_number_integrations())
+            / in_freq;
+        if period > max_period {
+            return Err(JsonError::client_error_alert(anyhow::anyhow!(
+                "wall clock alignment requires an output sampling frequency \
+                 of at least {} Hz with the current sample rate",
+                (max_period * f64::from(software_integrations)).recip()
+            )));
+        }
+        config
+            .set_software_integrations(software_integrations)
+            .map_err(JsonError::client_error_alert)?;
+        return config
+            .set_alignment_period(Some(period))
+            .map_err(JsonError::client_error_alert);
+    }
+    config.set_alignment_period(None).unwrap();
+    let mut software_integrations = config.software_integrations();
+    let out_freq = match patch {
         PatchSpectrometer {
             number_integrations: Some(n),
             ..
-        } => state
-            .ip_core()
-            .lock()
-            .unwrap()
-            .set_spectrometer_number_integrations(*n)
-            .map_err(JsonError::client_error)?,
+        } => {
+            return ip_core
+                .set_spectrometer_number_integrations(*n)
+                .map_err(JsonError::client_error);
+        }
         PatchSpectrometer {
             output_sampling_frequency: Some(out_freq),
             ..
         } => {
-            let mut ip_core = state.ip_core().lock().unwrap();
-            let in_freq = ad9361_samp_rate / ip_core.spectrometer_input_decimation() as f64;
-            let num_integrations = (in_freq / (f64::from(FFT_SIZE) * *out_freq))
-                .round()
-                .clamp(1.0, f64::from(u32::MAX)) as u32;
-            ip_core
-                .set_spectrometer_number_integrations(num_integrations)
-                .map_err(JsonError::client_error)?;
-        }
-        _ => {
-            // No parameters were specified. We don't do anything.
+            software_integrations =
+                line_software_integrations(patch, in_freq / (f64::from(FFT_SIZE) * out_freq.0));
+            out_freq.0 * f64::from(software_integrations)
         }
-    }
-    Ok(())
+        _ => match previous_period {
+            // The wall clock alignment has been disabled. Keep the output
+            // sampling frequency that it was using.
+            Some(period) => period.recip(),
+            None => {
+                // No parameters were specified. We don't do anything.
+                return Ok(());
+            }
+        },
+    };
+    let num_integrations = (in_freq / (f64::from(FFT_SIZE) * out_freq))
+        .round()
+        .clamp(1.0, f64::from(u32::MAX)) as u32;
+    ip_core
+        .set_spectrometer_number_integrations(num_integrations)
+        .map_err(JsonError::client_error)?;
+    config
+        .set_software_integrations(software_integrations)
+        .map_err(JsonError::client_error_alert)
+}
+
+// Returns the number of software integrations for spectrum lines that
+// integrate a total number of FFTs. If the patch gives the number of software
+// integrations, that number is used. Otherwise the smallest number for which
+// the number of integrations of the FPGA spectrometer fits in the register of
+// the IP core is used, so that integrations longer than those supported by the
+// IP core can be requested with the output sampling frequency.
+fn line_software_integrations(patch: &PatchSpectrometer, total: f64) -> u32 {
+    patch.software_integrations.unwrap_or_else(|| {
+        (total / f64::from(IpCore::spectrometer_max_number_integrations()))
+            .ceil()
+            .clamp(1.0, f64::from(PatchSpectrometer::MAX_SOFTWARE_INTEGRATIONS)) as u32
+    })
 }
 
 pub async fn patch_spectrometer(
     State(state): State<AppState>,
     Json(patch): Json<PatchSpectrometer>,
 ) -> Result<Json<Spectrometer>, JsonError> {
+    patch.validate().map_err(JsonError::client_error_alert)?;
     update_spectrometer(&state, &patch).await?;
     get_spectrometer_json(&state).await
 }
+
+pub async fn get_spectrometer_equalization(
+    State(state): State<AppState>,
+) -> Json<SpectrometerEqualization> {
+    Json(state.spectrometer_equalization().json())
+}
+
+pub async fn put_spectrometer_equalization(
+    State(state): State<AppState>,
+    Json(put): Json<SpectrometerEqualization>,
+) -> Result<Json<SpectrometerEqualization>, JsonError> {
+    put.validate().map_err(JsonError::client_error_alert)?;
+    state
+        .spectrometer_equalization()
+        .set(put)
+        .await
+        .map_err(JsonError::server_error)?;
+    Ok(Json(state.spectrometer_equalization().json()))
+}
+
+/// Returns the format of the spectrum data sent through the waterfall
+/// WebSocket.
+pub async fn get_spectrometer_format(
+    State(state): State<AppState>,
+) -> Result<Json<SpectrometerFormat>, JsonError> {
+    let (ad9361_samp_rate, lo_frequency) = {
+        let ad9361 = state.ad9361().lock().await;
+        (
+            ad9361
+                .get_sampling_frequency()
+                .await
+                .map_err(JsonError::server_error)?,
+            ad9361
+                .get_rx_lo_frequency()
+                .await
+                .map_err(JsonError::server_error)?,
+        )
+    };
+    let (samp_rate, input_offset, num_integrations, mode) = {
+        let ip_core = state.ip_core().lock().unwrap();
+        (
+            ad9361_samp_rate / ip_core.spectrometer_input_decimation() as f64,
+            ip_core.spectrometer_input_frequency_offset(),
+            ip_core.spectrometer_number_integrations(),
+            ip_core.spectrometer_mode(),
+        )
+    };
+    let equalization = state.spectrometer_equalization().json();
+    Ok(Json(SpectrometerFormat {
+        fft_size: FFT_SIZE,
+        bin_order: SpectrometerBinOrder::FftShifted,
+        datatype: "f32_le".to_string(),
+        units: SpectrometerUnits::LinearPower,
+        scale: spectrometer::scale(mode, num_integrations as f32, samp_rate.0 as f32).into(),
+        equalization: equalization.enabled && !equalization.points.is_empty(),
+        dc_bin_removal: state.spectrometer_config().dc_bin_removal(),
+        center_frequency: lo_frequency + input_offset + state.device().frequency_offset(),
+        bin_width: samp_rate / f64::from(FFT_SIZE),
+    }))
+}
+
+/// Query parameters of the spectrogram.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SpectrogramQuery {
+    #[serde(default = "default_lines")]
+    lines: usize,
+    #[serde(default)]
+    colormap: Colormap,
+    #[serde(default = "default_min")]
+    min: f32,
+    #[serde(default = "default_max")]
+    max: f32,
+    width: Option<usize>,
+}
+
+fn default_lines() -> usize {
+    SpectrumHistory::CAPACITY
+}
+
+fn default_min() -> f32 {
+    spectrogram::Parameters::default().min
+}
+
+fn default_max() -> f32 {
+    spectrogram::Parameters::default().max
+}
+
+/// Returns the most recent spectra as a spectrogram PNG image.
+///
+/// The most recent spectrum is the top row of the image. The colormap and the
+/// levels in dB that correspond to its beginning and end can be given as query
+/// parameters, and use the same defaults as the maia-wasm waterfall.
+pub async fn get_spectrogram(
+    State(state): State<AppState>,
+    Query(query): Query<SpectrogramQuery>,
+) -> Result<impl IntoResponse, JsonError> {
+    if !(1..=SpectrumHistory::CAPACITY).contains(&query.lines) {
+        return Err(JsonError::client_error(anyhow::anyhow!(
+            "lines must be between 1 and {}",
+            SpectrumHistory::CAPACITY
+        )));
+    }
+    if query.min >= query.max {
+        return Err(JsonError::client_error(anyhow::anyhow!(
+            "min must be smaller than max"
+        )));
+    }
+    if query
+        .width
+        .is_some_and(|width| !(1..=FFT_SIZE as usize).contains(&width))
+    {
+        return Err(JsonError::client_error(anyhow::anyhow!(
+            "width must be between 1 and {FFT_SIZE}"
+        )));
+    }
+    let lines = state.spectrum_history().latest(query.lines);
+    if lines.is_empty() {
+        return Err(JsonError::service_unavailable(anyhow::anyhow!(
+            "no spectrum available yet"
+        )));
+    }
+    let parameters = spectrogram::Parameters {
+        colormap: query.colormap,
+        min: query.min,
+        max: query.max,
+        width: query.width,
+    };
+    let png = tokio::task::spawn_blocking(move || {
+        let lines = lines
+            .iter()
+            .map(|line| spectrometer::spectrum_from_bytes(line))
+            .collect::<Vec<_>>();
+        Spectrogram::render(&lines, &parameters).to_png()
+    })
+    .await
+    .map_err(JsonError::server_error)?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        png,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{body::to_bytes, http::StatusCode, response::IntoResponse};
+    use maia_json::NoiseFloorEstimator;
+
+    #[tokio::test]
+    async fn wall_clock_alignment() {
+        let (state, _) = crate::mock::app_state().await;
+        // 600 FFTs per second
+        state
+            .ad9361()
+            .lock()
+            .await
+            .set_sampling_frequency(Hertz(2_457_600.0))
+            .await
+            .unwrap();
+        let patch = PatchSpectrometer {
+            output_sampling_frequency: Some(Hertz(2.0)),
+            wall_clock_alignment: Some(true),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        assert_eq!(state.spectrometer_config().alignment_period(), Some(0.5));
+        let json = spectrometer_json(&state).await.unwrap();
+        assert!(json.wall_clock_alignment);
+        assert_eq!(json.output_sampling_frequency, Hertz(2.0));
+
+        for out_freq in [100.0, 1e-4] {
+            let patch = PatchSpectrometer {
+                output_sampling_frequency: Some(Hertz(out_freq)),
+                ..Default::default()
+            };
+            assert!(update_spectrometer(&state, &patch).await.is_err());
+        }
+        assert_eq!(state.spectrometer_config().alignment_period(), Some(0.5));
+
+        let patch = PatchSpectrometer {
+            wall_clock_alignment: Some(false),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        assert_eq!(state.spectrometer_config().alignment_period(), None);
+        let json = spectrometer_json(&state).await.unwrap();
+        assert!(!json.wall_clock_alignment);
+        assert_eq!(json.number_integrations, 300);
+    }
+
+    #[tokio::test]
+    async fn software_integrations() {
+        let (state, _) = crate::mock::app_state().await;
+        // 600 FFTs per second
+        state
+            .ad9361()
+            .lock()
+            .await
+            .set_sampling_frequency(Hertz(2_457_600.0))
+            .await
+            .unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 1);
+        let patch = PatchSpectrometer {
+            output_sampling_frequency: Some(Hertz(2.0)),
+            software_integrations: Some(4),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 4);
+        assert_eq!(json.number_integrations, 75);
+        assert_eq!(json.output_sampling_frequency, Hertz(2.0));
+
+        // changing only the software integrations keeps the FPGA spectrometer
+        // settings
+        let patch = PatchSpectrometer {
+            software_integrations: Some(2),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.number_integrations, 75);
+        assert_eq!(json.output_sampling_frequency, Hertz(4.0));
+
+        for software_integrations in [0, PatchSpectrometer::MAX_SOFTWARE_INTEGRATIONS + 1] {
+            let patch = PatchSpectrometer {
+                software_integrations: Some(software_integrations),
+                ..Default::default()
+            };
+            assert_eq!(
+                patch_spectrometer(State(state.clone()), Json(patch))
+                    .await
+                    .unwrap_err()
+                    .into_response()
+                    .status(),
+                StatusCode::BAD_REQUEST
+            );
+        }
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 2);
+    }
+
+    #[tokio::test]
+    async fn long_integration() {
+        let (state, _) = crate::mock::app_state().await;
+        // 600 FFTs per second
+        state
+            .ad9361()
+            .lock()
+            .await
+            .set_sampling_frequency(Hertz(2_457_600.0))
+            .await
+            .unwrap();
+        // 6000 FFTs do not fit in the number of integrations register, so
+        // they are split between the FPGA and the software integration
+        let patch = PatchSpectrometer {
+            output_sampling_frequency: Some(Hertz(0.1)),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 6);
+        assert_eq!(json.number_integrations, 1000);
+        assert_eq!(json.total_integrations, 6000);
+        assert!((json.output_sampling_frequency - Hertz(0.1)).abs() < Hertz(1e-9));
+
+        let patch = PatchSpectrometer {
+            output_sampling_frequency: Some(Hertz(0.05)),
+            wall_clock_alignment: Some(true),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 12);
+        assert!((json.output_sampling_frequency - Hertz(0.05)).abs() < Hertz(1e-9));
+
+        // short integrations go back to using only the FPGA
+        let patch = PatchSpectrometer {
+            output_sampling_frequency: Some(Hertz(200.0)),
+            wall_clock_alignment: Some(false),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 1);
+        assert_eq!(json.number_integrations, 3);
+        assert_eq!(json.total_integrations, 3);
+    }
+
+    // The Tokio clock is paused so that the test does not need to wait for the
+    // line in real time.
+    #[tokio::test(start_paused = true)]
+    async fn long_integration_lines() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        // 15000 FFTs per second at 61.44 Msps, so each line integrates
+        // 147 spectra of 1020 FFTs
+        let patch = PatchSpectrometer {
+            output_sampling_frequency: Some(Hertz(0.1)),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.software_integrations, 147);
+        assert_eq!(json.number_integrations, 1020);
+
+        let (sender, mut lines) = tokio::sync::broadcast::channel(16);
+        let mut watchdog_lines = sender.subscribe();
+        let spectrometer = spectrometer::Spectrometer::new(
+            state.clone(),
+            interrupt_handler.waiter_spectrometer(),
+            sender,
+        );
+        tokio::spawn(interrupt_handler.run());
+        tokio::spawn(spectrometer.run());
+        // the line takes longer than the minimum watchdog timeout, which
+        // must not fire
+        let line = tokio::select! {
+            err = crate::app::watchdog(&state, &mut watchdog_lines) => {
+                panic!("watchdog fired: {err:#}")
+            }
+            line = tokio::time::timeout(std::time::Duration::from_secs(30), lines.recv()) => {
+                line.unwrap().unwrap()
+            }
+        };
+        assert_eq!(
+            line.spectrum.len(),
+            FFT_SIZE as usize * std::mem::size_of::<f32>()
+        );
+        let power = line
+            .spectrum
+            .chunks_exact(4)
+            .map(|x| f32::from_le_bytes(x.try_into().unwrap()));
+        assert!(power.into_iter().all(|x| x.is_finite() && x > 0.0));
+    }
+
+    #[tokio::test]
+    async fn noise_floor_estimator() {
+        let (state, _) = crate::mock::app_state().await;
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(json.noise_floor_estimator, NoiseFloorEstimator::Median);
+        assert_eq!(json.noise_floor, None);
+        let patch = PatchSpectrometer {
+            noise_floor_estimator: Some(NoiseFloorEstimator::MinimumStatistics),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let patch = PatchSpectrometer {
+            noise_floor_averaging: Some(32),
+            ..Default::default()
+        };
+        update_spectrometer(&state, &patch).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(
+            json.noise_floor_estimator,
+            NoiseFloorEstimator::MinimumStatistics
+        );
+        assert_eq!(json.noise_floor_averaging, 32);
+        let patch = PatchSpectrometer {
+            noise_floor_averaging: Some(0),
+            ..Default::default()
+        };
+        assert!(update_spectrometer(&state, &patch).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn equalization() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(json) = get_spectrometer_equalization(State(state.clone())).await;
+        assert_eq!(json, SpectrometerEqualization::default());
+        let point = |frequency, gain| maia_json::SpectrometerEqualizationPoint {
+            frequency: Hertz(frequency),
+            gain: Decibels(gain),
+        };
+        let put = SpectrometerEqualization {
+            enabled: true,
+            points: vec![point(-20e6, 2.0), point(20e6, 2.0)],
+        };
+        let Json(json) = put_spectrometer_equalization(State(state.clone()), Json(put.clone()))
+            .await
+            .unwrap();
+        assert_eq!(json, put);
+        let bad = SpectrometerEqualization {
+            enabled: true,
+            points: vec![point(0.0, 100.0)],
+        };
+        assert!(
+            put_spectrometer_equalization(State(state.clone()), Json(bad))
+                .await
+                .is_err()
+        );
+        let Json(json) = get_spectrometer_equalization(State(state)).await;
+        assert_eq!(json, put);
+    }
+
+    #[tokio::test]
+    async fn spectrometer_format() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(format) = get_spectrometer_format(State(state.clone())).await.unwrap();
+        let json = spectrometer_json(&state).await.unwrap();
+        assert_eq!(format.fft_size, json.fft_size);
+        assert_eq!(format.bin_order, SpectrometerBinOrder::FftShifted);
+        assert_eq!(
+            format.bin_width,
+            json.input_sampling_frequency / f64::from(json.fft_size)
+        );
+        let lo_frequency = state
+            .ad9361()
+            .lock()
+            .await
+            .get_rx_lo_frequency()
+            .await
+            .unwrap();
+        assert_eq!(format.center_frequency, lo_frequency);
+        assert!(format.scale > 0.0);
+        assert!(!format.equalization);
+    }
+
+    #[tokio::test]
+    async fn spectrogram() {
+        let (state, _) = crate::mock::app_state().await;
+        let query = |query: &str| {
+            let state = state.clone();
+            let query = Query::try_from_uri(&format!("/?{query}").parse().unwrap()).unwrap();
+            async move {
+                get_spectrogram(State(state), query)
+                    .await
+                    .map(|r| r.into_response())
+            }
+        };
+        // there are no spectra before the spectrometer starts
+        assert_eq!(
+            query("").await.unwrap_err().into_response().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        let spectrum = vec![1e6f32; FFT_SIZE as usize]
+            .iter()
+            .flat_map(|x| x.to_ne_bytes())
+            .collect::<Vec<u8>>();
+        for _ in 0..10 {
+            state.spectrum_history().push(spectrum.clone().into());
+        }
+        for bad in ["lines=0", "min=50&max=40", "width=8192"] {
+            assert!(query(bad).await.is_err(), "{bad}");
+        }
+        assert!(
+            Query::<SpectrogramQuery>::try_from_uri(&"/?colormap=Jet".parse().unwrap()).is_err()
+        );
+        let response = query("lines=4&colormap=Viridis&width=256").await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "image/png");
+        let png = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        // width and height in the IHDR chunk
+        assert_eq!(png[16..24], [0, 0, 1, 0, 0, 0, 0, 4]);
+    }
+}
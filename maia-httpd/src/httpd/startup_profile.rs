@@ -0,0 +1,279 @@
+use super::{ad9361, ddc, json_error::JsonError, spectrometer};
+use crate::app::AppState;
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use maia_json::{PatchAd9361, PatchSpectrometer, PutDDCConfig};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Startup profile.
+///
+/// This struct is part of the [`AppState`]. It contains the AD9361, DDC and
+/// spectrometer settings that are applied when maia-httpd starts, instead of
+/// the factory defaults. If a file is given with [`StartupProfile::load`], the
+/// profile is read from this file and saved to it each time that it is
+/// changed.
+#[derive(Debug, Default)]
+pub struct StartupProfile(Mutex<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: maia_json::StartupProfile,
+    file: Option<PathBuf>,
+}
+
+impl StartupProfile {
+    /// Creates a new startup profile, which is empty.
+    pub fn new() -> StartupProfile {
+        StartupProfile::default()
+    }
+
+    /// Loads the startup profile from a file.
+    ///
+    /// The `file` is remembered so that the profile is saved to it when it is
+    /// changed. If the file does not exist, the current profile is kept.
+    pub async fn load(&self, file: Option<PathBuf>) -> Result<()> {
+        if let Some(file) = &file {
+            match tokio::fs::read(file).await {
+                Ok(json) => {
+                    let config: maia_json::StartupProfile = serde_json::from_slice(&json)
+                        .with_context(|| format!("failed to parse {file:?}"))?;
+                    config
+                        .validate()
+                        .with_context(|| format!("invalid startup profile in {file:?}"))?;
+                    self.0.lock().unwrap().config = config;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("failed to read {file:?}")),
+            }
+        }
+        self.0.lock().unwrap().file = file;
+        Ok(())
+    }
+
+    /// Returns the JSON representation of the startup profile.
+    pub fn json(&self) -> maia_json::StartupProfile {
+        self.0.lock().unwrap().config.clone()
+    }
+
+    /// Replaces the startup profile.
+    ///
+    /// The profile must have been validated with
+    /// [`maia_json::StartupProfile::validate`]. The new profile is saved to the
+    /// startup profile file before it replaces the current one, so the function
+    /// fails and keeps the current profile if it cannot be saved.
+    pub async fn set(&self, config: maia_json::StartupProfile) -> Result<()> {
+        let file = self.0.lock().unwrap().file.clone();
+        if let Some(file) = file {
+            crate::fs::write_atomic(&file, serde_json::to_vec_pretty(&config)?)
+                .await
+                .context("failed to write startup profile file")?;
+        }
+        self.0.lock().unwrap().config = config;
+        Ok(())
+    }
+
+    /// Applies the startup profile.
+    ///
+    /// The settings are applied in the same way as the corresponding API
+    /// requests, so they are also checked against the safety limits. If some
+    /// setting cannot be applied, the function returns an error and the
+    /// remaining settings are not applied.
+    pub async fn apply(state: &AppState) -> Result<()> {
+        let config = state.startup_profile().json();
+        if let Some(patch) = &config.ad9361 {
+            apply_ad9361(state, patch)
+                .await
+                .context("failed to apply the AD9361 settings")?;
+        }
+        if let Some(put) = config.ddc {
+            ddc::set_ddc_config(state, put)
+                .await
+                .context("failed to apply the DDC configuration")?;
+        }
+        if let Some(patch) = &config.spectrometer {
+            spectrometer::update_spectrometer(state, patch)
+                .await
+                .context("failed to apply the spectrometer settings")?;
+        }
+        Ok(())
+    }
+}
+
+async fn apply_ad9361(state: &AppState, patch: &PatchAd9361) -> Result<(), JsonError> {
+    ad9361::check_limits(state, patch)?;
+    if patch.rx_lo_frequency.is_some() {
+        state.tuner().check_free().map_err(JsonError::conflict)?;
+    }
+    let iio = state.ad9361().lock().await;
+    ad9361::ad9361_update(state, &iio, patch).await
+}
+
+// Returns a startup profile with the current settings.
+async fn current_profile(state: &AppState) -> Result<maia_json::StartupProfile> {
+    let ad9361 = ad9361::ad9361_json(&*state.ad9361().lock().await).await?;
+    let ddc = state
+        .ip_core()
+        .lock()
+        .unwrap()
+        .ddc_config(ad9361.sampling_frequency);
+    let spectrometer = spectrometer::spectrometer_json(state).await?;
+    Ok(maia_json::StartupProfile {
+        ad9361: Some(PatchAd9361::from(ad9361)),
+        ddc: Some(PutDDCConfig {
+            frequency: ddc.frequency,
+            fir1: ddc.fir1,
+            fir2: ddc.fir2,
+            fir3: ddc.fir3,
+            transition_bandwidth: ddc.transition_bandwidth,
+        }),
+        spectrometer: Some(PatchSpectrometer {
+            input: Some(spectrometer.input),
+            output_sampling_frequency: Some(spectrometer.output_sampling_frequency),
+            number_integrations: None,
+            mode: Some(spectrometer.mode),
+            retune_mode: Some(spectrometer.retune_mode),
+            wall_clock_alignment: Some(spectrometer.wall_clock_alignment),
+            noise_floor_estimator: Some(spectrometer.noise_floor_estimator),
+            noise_floor_averaging: Some(spectrometer.noise_floor_averaging),
+            dc_bin_removal: Some(spectrometer.dc_bin_removal),
+            software_integrations: Some(spectrometer.software_integrations),
+        }),
+    })
+}
+
+async fn set_startup_profile(
+    state: &AppState,
+    config: maia_json::StartupProfile,
+) -> Result<Json<maia_json::StartupProfile>, JsonError> {
+    config.validate().map_err(JsonError::client_error_alert)?;
+    if let Some(patch) = &config.ad9361 {
+        ad9361::check_limits(state, patch)?;
+    }
+    state
+        .startup_profile()
+        .set(config)
+        .await
+        .map_err(JsonError::server_error)?;
+    Ok(Json(state.startup_profile().json()))
+}
+
+pub async fn get_startup_profile(State(state): State<AppState>) -> Json<maia_json::StartupProfile> {
+    Json(state.startup_profile().json())
+}
+
+pub async fn put_startup_profile(
+    State(state): State<AppState>,
+    Json(put): Json<maia_json::StartupProfile>,
+) -> Result<Json<maia_json::StartupProfile>, JsonError> {
+    set_startup_profile(&state, put).await
+}
+
+pub async fn delete_startup_profile(
+    State(state): State<AppState>,
+) -> Result<Json<maia_json::StartupProfile>, JsonError> {
+    set_startup_profile(&state, Default::default()).await
+}
+
+pub async fn put_startup_profile_current(
+    State(state): State<AppState>,
+) -> Result<Json<maia_json::StartupProfile>, JsonError> {
+    let config = current_profile(&state)
+        .await
+        .map_err(JsonError::server_error)?;
+    set_startup_profile(&state, config).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use axum::{http::StatusCode, response::IntoResponse};
+    use maia_json::Hertz;
+
+    #[tokio::test]
+    async fn save_and_apply() {
+        let file = std::env::temp_dir().join(format!(
+            "maia-httpd-startup-profile-{}.json",
+            std::process::id()
+        ));
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        state
+            .startup_profile()
+            .load(Some(file.clone()))
+            .await
+            .unwrap();
+        let patch = PatchAd9361 {
+            rx_lo_frequency: Some(Hertz(433_920_000.0)),
+            ..Default::default()
+        };
+        let Json(_) = ad9361::patch_ad9361(State(state.clone()), Json(patch))
+            .await
+            .unwrap();
+        let Json(saved) = put_startup_profile_current(State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            saved.ad9361.as_ref().unwrap().rx_lo_frequency,
+            Some(Hertz(433_920_000.0))
+        );
+        assert!(saved.ddc.is_some());
+        assert!(file.exists());
+
+        // A new instance loads the profile from the file and applies it.
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        state
+            .startup_profile()
+            .load(Some(file.clone()))
+            .await
+            .unwrap();
+        assert_eq!(state.startup_profile().json(), saved);
+        StartupProfile::apply(&state).await.unwrap();
+        let ad9361 = ad9361::ad9361_json(&*state.ad9361().lock().await)
+            .await
+            .unwrap();
+        assert_eq!(ad9361.rx_lo_frequency, Hertz(433_920_000.0));
+
+        let Json(cleared) = delete_startup_profile(State(state.clone())).await.unwrap();
+        assert_eq!(cleared, Default::default());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[tokio::test]
+    async fn invalid_profile() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        let put = maia_json::StartupProfile {
+            spectrometer: Some(PatchSpectrometer {
+                output_sampling_frequency: Some(Hertz(-1.0)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(put_startup_profile(State(state.clone()), Json(put))
+            .await
+            .is_err());
+        assert_eq!(state.startup_profile().json(), Default::default());
+    }
+
+    #[tokio::test]
+    async fn unsaved_profile() {
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        state
+            .startup_profile()
+            .load(Some(
+                std::env::temp_dir().join("maia-httpd-missing/startup-profile.json"),
+            ))
+            .await
+            .unwrap();
+        // The profile is kept if it cannot be saved, and the failure is a
+        // server error
+        assert_eq!(
+            put_startup_profile_current(State(state.clone()))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(state.startup_profile().json(), Default::default());
+    }
+}
@@ -0,0 +1,123 @@
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// Cache-Control for files whose name contains a hash of their contents. These
+// never change, so they can be cached forever.
+const CACHE_CONTROL_IMMUTABLE: &str = "public, max-age=31536000, immutable";
+// Cache-Control for other files. The browser can cache them, but it needs to
+// revalidate them with a conditional request each time they are used.
+const CACHE_CONTROL_REVALIDATE: &str = "no-cache";
+// Minimum length of the hexadecimal hash in a hashed filename.
+const MIN_HASH_LENGTH: usize = 8;
+
+// Returns true if the file name has the form name.HASH.ext, where HASH is a
+// hexadecimal string, as generated by util/hash_assets.py.
+fn is_hashed_filename(path: &str) -> bool {
+    let filename = path.rsplit('/').next().unwrap_or(path);
+    let mut parts = filename.rsplit('.');
+    let (Some(_ext), Some(hash), Some(name)) = (parts.next(), parts.next(), parts.next()) else {
+        return false;
+    };
+    !name.is_empty() && hash.len() >= MIN_HASH_LENGTH && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+// Computes a weak ETag from the headers of a file response. ServeDir already
+// gives the Last-Modified and Content-Length of the file, and different
+// precompressed variants are distinguished by their Content-Encoding.
+fn etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let last_modified = headers.get(header::LAST_MODIFIED)?;
+    let mut hasher = DefaultHasher::new();
+    last_modified.as_bytes().hash(&mut hasher);
+    for name in [header::CONTENT_LENGTH, header::CONTENT_ENCODING] {
+        headers.get(name).map(|v| v.as_bytes()).hash(&mut hasher);
+    }
+    HeaderValue::from_str(&format!("W/\"{:016x}\"", hasher.finish())).ok()
+}
+
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(if_none_match) = if_none_match.to_str() else {
+        return false;
+    };
+    let etag = etag.to_str().unwrap_or_default();
+    // Weak comparison, since the ETags are weak
+    let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    if_none_match
+        .split(',')
+        .any(|tag| tag.trim() == "*" || weak(tag) == weak(etag))
+}
+
+/// Middleware that sets the caching headers of static files.
+///
+/// Files with a hashed name are marked as immutable. Other files need to be
+/// revalidated, for which an ETag is added and `If-None-Match` requests are
+/// answered with 304 Not Modified.
+pub async fn cache_headers(request: Request, next: Next) -> Response {
+    if !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return next.run(request).await;
+    }
+    let immutable = is_hashed_filename(request.uri().path());
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(if immutable {
+            CACHE_CONTROL_IMMUTABLE
+        } else {
+            CACHE_CONTROL_REVALIDATE
+        }),
+    );
+    let Some(etag) = etag(headers) else {
+        return response;
+    };
+    if if_none_match.is_some_and(|v| etag_matches(&v, &etag)) {
+        let mut not_modified = Response::new(Body::empty());
+        *not_modified.status_mut() = StatusCode::NOT_MODIFIED;
+        let not_modified_headers = not_modified.headers_mut();
+        for name in [header::CACHE_CONTROL, header::LAST_MODIFIED, header::VARY] {
+            if let Some(value) = headers.get(&name) {
+                not_modified_headers.insert(name, value.clone());
+            }
+        }
+        not_modified_headers.insert(header::ETAG, etag);
+        return not_modified;
+    }
+    headers.insert(header::ETAG, etag);
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hashed_filenames() {
+        assert!(is_hashed_filename("/pkg/maia_wasm_bg.0123abcd.wasm"));
+        assert!(is_hashed_filename("style.0123456789abcdef.css"));
+        assert!(!is_hashed_filename("/pkg/maia_wasm_bg.wasm"));
+        assert!(!is_hashed_filename("/index.html"));
+        assert!(!is_hashed_filename("/.0123abcd.js"));
+        assert!(!is_hashed_filename("/maia-icon-32x32.png"));
+        assert!(!is_hashed_filename("/file.notahash.js"));
+    }
+
+    #[test]
+    fn etag_comparison() {
+        let etag = HeaderValue::from_static("W/\"0123\"");
+        let matches = |v| etag_matches(&HeaderValue::from_static(v), &etag);
+        assert!(matches("W/\"0123\""));
+        assert!(matches("\"0123\""));
+        assert!(matches("\"abcd\", W/\"0123\""));
+        assert!(matches("*"));
+        assert!(!matches("W/\"abcd\""));
+    }
+}
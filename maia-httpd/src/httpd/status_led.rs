@@ -0,0 +1,252 @@
+use super::device::led_trigger;
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{extract::State, Json};
+use maia_json::{PatchSystemLed, SystemLed, SystemLedPattern, SystemLedPatterns, SystemLedState};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Period with which the state of the device is checked.
+const UPDATE_PERIOD: Duration = Duration::from_millis(250);
+// On and off times of the blinking patterns (in ms).
+const SLOW_BLINK_MS: u32 = 500;
+const FAST_BLINK_MS: u32 = 100;
+
+/// System status LED.
+///
+/// This struct is part of the [`AppState`]. It contains the settings of the
+/// status LED, which is controlled by the [`StatusLedRunner`], and the last
+/// state of the device determined by the runner.
+#[derive(Debug)]
+pub struct StatusLed(Mutex<SystemLed>);
+
+impl StatusLed {
+    /// Creates a new status LED, which is disabled and uses the default
+    /// patterns.
+    pub fn new() -> StatusLed {
+        StatusLed(Mutex::new(SystemLed {
+            enabled: false,
+            state: SystemLedState::Idle,
+            patterns: SystemLedPatterns::default(),
+        }))
+    }
+
+    /// Returns the JSON representation of the status LED.
+    pub fn json(&self) -> SystemLed {
+        *self.0.lock().unwrap()
+    }
+
+    /// Enables or disables the status LED.
+    ///
+    /// When the status LED is disabled, the trigger that the LED had before
+    /// it was enabled is restored.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.0.lock().unwrap().enabled = enabled;
+    }
+
+    fn patch(&self, patch: &PatchSystemLed) {
+        let mut led = self.0.lock().unwrap();
+        if let Some(enabled) = patch.enabled {
+            led.enabled = enabled;
+        }
+        if let Some(patterns) = patch.patterns {
+            led.patterns = patterns;
+        }
+    }
+
+    // Updates the state of the device and returns the pattern to show, or
+    // None if the LED is disabled.
+    fn update_state(&self, state: SystemLedState) -> Option<SystemLedPattern> {
+        let mut led = self.0.lock().unwrap();
+        led.state = state;
+        led.enabled.then(|| led.patterns.pattern(state))
+    }
+}
+
+impl Default for StatusLed {
+    fn default() -> StatusLed {
+        StatusLed::new()
+    }
+}
+
+/// System status LED runner.
+///
+/// This struct checks the state of the device periodically and shows it in
+/// the identify LED with the patterns configured in the [`StatusLed`]. It
+/// implements a [`run`](StatusLedRunner::run) async method that should be run
+/// concurrently with the rest of the application.
+#[derive(Debug)]
+pub struct StatusLedRunner {
+    state: AppState,
+    led: PathBuf,
+}
+
+impl StatusLedRunner {
+    /// Creates a new status LED runner.
+    ///
+    /// The `led` is the sysfs directory of the LED. This function only creates
+    /// the object. The [`run`](StatusLedRunner::run) method needs to be called
+    /// afterwards.
+    pub fn new(state: AppState, led: PathBuf) -> StatusLedRunner {
+        StatusLedRunner { state, led }
+    }
+
+    /// Runs the status LED runner.
+    ///
+    /// This function never returns. If the LED cannot be set, the error is
+    /// logged and the status LED is disabled.
+    #[tracing::instrument(name = "status_led", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let mut interval = tokio::time::interval(UPDATE_PERIOD);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        // Pattern currently shown by the LED, and trigger that the LED had
+        // before the status LED was enabled.
+        let mut shown = None;
+        let mut system_trigger = None;
+        loop {
+            interval.tick().await;
+            let pattern = self
+                .state
+                .status_led()
+                .update_state(device_state(&self.state).await);
+            if self.state.device().is_identifying() {
+                // The identify request controls the LED. The pattern is shown
+                // again once it finishes.
+                shown = None;
+                continue;
+            }
+            let result = match pattern {
+                Some(pattern) if shown != Some(pattern) => {
+                    self.show(pattern, &mut system_trigger).await
+                }
+                None => match system_trigger.take() {
+                    Some(trigger) => tokio::fs::write(self.led.join("trigger"), trigger)
+                        .await
+                        .map_err(anyhow::Error::from),
+                    None => Ok(()),
+                },
+                _ => Ok(()),
+            };
+            shown = pattern;
+            if let Err(err) = result {
+                tracing::error!("failed to set status LED, disabling it: {err:#}");
+                self.state.status_led().set_enabled(false);
+                shown = None;
+            }
+        }
+    }
+
+    async fn show(
+        &self,
+        pattern: SystemLedPattern,
+        system_trigger: &mut Option<String>,
+    ) -> Result<()> {
+        if system_trigger.is_none() {
+            *system_trigger = Some(led_trigger(&self.led).await?);
+        }
+        match pattern {
+            SystemLedPattern::Off => set_brightness(&self.led, false).await,
+            SystemLedPattern::On => set_brightness(&self.led, true).await,
+            SystemLedPattern::SlowBlink => set_blink(&self.led, SLOW_BLINK_MS).await,
+            SystemLedPattern::FastBlink => set_blink(&self.led, FAST_BLINK_MS).await,
+            SystemLedPattern::Heartbeat => {
+                Ok(tokio::fs::write(self.led.join("trigger"), "heartbeat").await?)
+            }
+        }
+    }
+}
+
+// Returns the state of the device shown by the status LED.
+async fn device_state(state: &AppState) -> SystemLedState {
+    if state.system().status() == maia_json::SystemStatus::Degraded {
+        SystemLedState::Error
+    } else if !state.recorder().is_stopped().await {
+        SystemLedState::Recording
+    } else if state.notifications().clients() > 0 || !state.streams().is_empty() {
+        SystemLedState::Streaming
+    } else {
+        SystemLedState::Idle
+    }
+}
+
+async fn set_brightness(led: &Path, on: bool) -> Result<()> {
+    tokio::fs::write(led.join("trigger"), "none").await?;
+    tokio::fs::write(led.join("brightness"), if on { "1" } else { "0" }).await?;
+    Ok(())
+}
+
+async fn set_blink(led: &Path, period_ms: u32) -> Result<()> {
+    tokio::fs::write(led.join("trigger"), "timer").await?;
+    for file in ["delay_on", "delay_off"] {
+        tokio::fs::write(led.join(file), period_ms.to_string()).await?;
+    }
+    Ok(())
+}
+
+pub async fn get_system_led(State(state): State<AppState>) -> Json<SystemLed> {
+    Json(state.status_led().json())
+}
+
+pub async fn patch_system_led(
+    State(state): State<AppState>,
+    Json(patch): Json<PatchSystemLed>,
+) -> Json<SystemLed> {
+    state.status_led().patch(&patch);
+    Json(state.status_led().json())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    async fn wait_trigger(led: &Path, trigger: &str) {
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while std::fs::read_to_string(led.join("trigger")).unwrap() != trigger {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn status_led() {
+        let led = std::env::temp_dir().join(format!("maia-httpd-led-{}", std::process::id()));
+        std::fs::create_dir_all(&led).unwrap();
+        std::fs::write(led.join("trigger"), "none [mmc0] timer heartbeat").unwrap();
+        let (state, _interrupt_handler) = crate::mock::app_state().await;
+        tokio::spawn(StatusLedRunner::new(state.clone(), led.clone()).run());
+
+        let Json(json) = get_system_led(State(state.clone())).await;
+        assert!(!json.enabled);
+        assert_eq!(json.state, SystemLedState::Idle);
+
+        let patch = PatchSystemLed {
+            enabled: Some(true),
+            patterns: None,
+        };
+        let Json(json) = patch_system_led(State(state.clone()), Json(patch)).await;
+        assert!(json.enabled);
+        wait_trigger(&led, "heartbeat").await;
+
+        state
+            .system()
+            .record_incident("spectrometer", &anyhow::anyhow!("failure"));
+        wait_trigger(&led, "timer").await;
+        assert_eq!(
+            std::fs::read_to_string(led.join("delay_on")).unwrap(),
+            FAST_BLINK_MS.to_string()
+        );
+        assert_eq!(state.status_led().json().state, SystemLedState::Error);
+
+        // Disabling the status LED restores the previous trigger.
+        let patch = PatchSystemLed {
+            enabled: Some(false),
+            patterns: None,
+        };
+        let Json(_) = patch_system_led(State(state.clone()), Json(patch)).await;
+        wait_trigger(&led, "mmc0").await;
+        std::fs::remove_dir_all(led).unwrap();
+    }
+}
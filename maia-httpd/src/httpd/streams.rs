@@ -0,0 +1,51 @@
+use super::json_error::JsonError;
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+
+fn not_found(name: &str) -> JsonError {
+    JsonError::not_found(anyhow::anyhow!("stream {name} does not exist"))
+}
+
+pub async fn get_streams(State(state): State<AppState>) -> Json<maia_json::Streams> {
+    Json(state.streams().json())
+}
+
+pub async fn get_stream(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Stream>, JsonError> {
+    state
+        .streams()
+        .instance_json(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+pub async fn put_stream(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(put): Json<maia_json::PutStream>,
+) -> Result<Json<maia_json::Stream>, JsonError> {
+    state
+        .streams()
+        .start(&name, put.protocol, &put.address)
+        .await
+        .map(Json)
+        .map_err(JsonError::client_error_alert)
+}
+
+pub async fn delete_stream(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Stream>, JsonError> {
+    state
+        .streams()
+        .stop(&name)
+        .await
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
@@ -0,0 +1,165 @@
+use super::json_error::JsonError;
+use crate::app::AppState;
+use anyhow::Result;
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+
+fn not_found(name: &str) -> JsonError {
+    JsonError::not_found(anyhow::anyhow!("survey {name} does not exist"))
+}
+
+pub async fn get_surveys(State(state): State<AppState>) -> Json<maia_json::Surveys> {
+    Json(state.surveys().json())
+}
+
+pub async fn get_survey(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Survey>, JsonError> {
+    state
+        .surveys()
+        .survey_json(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+pub async fn put_survey(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Json(put): Json<maia_json::PutSurvey>,
+) -> Result<Json<maia_json::Survey>, JsonError> {
+    for band in &put.bands {
+        state
+            .limits()
+            .check_band(band.start_frequency, band.stop_frequency)
+            .map_err(JsonError::client_error_alert)?;
+    }
+    state
+        .surveys()
+        .set(&name, put)
+        .map(Json)
+        .map_err(JsonError::client_error_alert)
+}
+
+pub async fn delete_survey(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Survey>, JsonError> {
+    state
+        .surveys()
+        .remove(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+pub async fn put_survey_run(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<maia_json::Survey>, JsonError> {
+    state
+        .surveys()
+        .request_run(&name)
+        .map(Json)
+        .ok_or_else(|| not_found(&name))
+}
+
+/// Format of a survey report.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// JSON format.
+    #[default]
+    Json,
+    /// CSV format.
+    Csv,
+}
+
+/// Query parameters of a survey report.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReportQuery {
+    #[serde(default)]
+    format: ReportFormat,
+}
+
+pub async fn get_survey_report(
+    State(state): State<AppState>,
+    Path((name, id)): Path<(String, u64)>,
+    Query(query): Query<ReportQuery>,
+) -> Result<Response, JsonError> {
+    let report = state.surveys().report(&name, id).ok_or_else(|| {
+        JsonError::not_found(anyhow::anyhow!(
+            "report {id} of survey {name} does not exist"
+        ))
+    })?;
+    Ok(match query.format {
+        ReportFormat::Json => Json(&*report).into_response(),
+        ReportFormat::Csv => (
+            [(header::CONTENT_TYPE, "text/csv")],
+            crate::survey::report_csv(&report),
+        )
+            .into_response(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::survey::test_config;
+    use axum::http::StatusCode;
+    use maia_json::Seconds;
+
+    #[tokio::test]
+    async fn surveys() {
+        let (state, _) = crate::mock::app_state().await;
+        let name = || Path("vhf".to_string());
+        assert_eq!(
+            get_survey(State(state.clone()), name())
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::NOT_FOUND
+        );
+        let mut config = test_config("");
+        config.dwell = Seconds(0.0);
+        assert_eq!(
+            put_survey(State(state.clone()), name(), Json(config))
+                .await
+                .unwrap_err()
+                .into_response()
+                .status(),
+            StatusCode::BAD_REQUEST
+        );
+        let Json(survey) = put_survey(State(state.clone()), name(), Json(test_config("")))
+            .await
+            .unwrap();
+        assert_eq!(survey.config, test_config(""));
+        assert!(survey.reports.is_empty());
+        let Json(surveys) = get_surveys(State(state.clone())).await;
+        assert_eq!(surveys.surveys, [survey]);
+        let Json(run) = put_survey_run(State(state.clone()), name()).await.unwrap();
+        assert!(run.running);
+        let report_query = Query::try_from_uri(&"/?format=csv".parse().unwrap()).unwrap();
+        assert_eq!(
+            get_survey_report(
+                State(state.clone()),
+                Path(("vhf".to_string(), 0)),
+                report_query
+            )
+            .await
+            .unwrap_err()
+            .into_response()
+            .status(),
+            StatusCode::NOT_FOUND
+        );
+        assert!(Query::<ReportQuery>::try_from_uri(&"/?format=xml".parse().unwrap()).is_err());
+        let Json(deleted) = delete_survey(State(state.clone()), name()).await.unwrap();
+        assert_eq!(deleted.name, "vhf");
+        assert!(get_surveys(State(state)).await.surveys.is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+use crate::app::AppState;
+use axum::{extract::State, Json};
+
+pub async fn get_system(State(state): State<AppState>) -> Json<maia_json::System> {
+    Json(state.system().json())
+}
+
+pub async fn get_system_clock(State(state): State<AppState>) -> Json<maia_json::SystemClock> {
+    Json(state.clock_measurement().json())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn system() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(system) = get_system(State(state.clone())).await;
+        assert_eq!(system.status, maia_json::SystemStatus::Ok);
+        assert!(system.incidents.is_empty());
+        state
+            .system()
+            .record_incident("interrupt handler", &anyhow::anyhow!("UIO read failed"));
+        let Json(system) = get_system(State(state)).await;
+        assert_eq!(system.status, maia_json::SystemStatus::Degraded);
+        assert_eq!(system.incidents[0].task, "interrupt handler");
+    }
+
+    #[tokio::test]
+    async fn system_clock() {
+        let (state, _) = crate::mock::app_state().await;
+        let Json(clock) = get_system_clock(State(state)).await;
+        assert!(clock.measured_samp_rate.is_none());
+        assert!(clock.clock_error.is_none());
+    }
+}
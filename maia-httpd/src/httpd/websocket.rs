@@ -1,47 +1,128 @@
+use super::clients::{self, disconnect_close_message};
+use crate::{
+    app::AppState,
+    notifications::Notification,
+    spectrometer::{SpectrumLine, FFT_SIZE},
+};
 use anyhow::Result;
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, State,
     },
     response::Response,
 };
 use bytes::Bytes;
-use futures::stream::StreamExt;
+use chrono::Utc;
+use futures::{stream::StreamExt, SinkExt};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream};
 use tracing::Instrument;
 
 pub async fn handler(
-    State(sender): State<broadcast::Sender<Bytes>>,
+    State((sender, state)): State<(broadcast::Sender<SpectrumLine>, AppState)>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     ws: WebSocketUpgrade,
 ) -> Response {
     let span = tracing::debug_span!("websocket");
     let receiver = sender.subscribe();
-    ws.on_upgrade(move |socket| handle(socket, receiver).instrument(span))
+    let notifications = state.notifications().subscribe();
+    let client = connect_info.map(|ConnectInfo(client)| client);
+    ws.protocols([
+        maia_json::WATERFALL_PROTOCOL_V4,
+        maia_json::WATERFALL_PROTOCOL_V3,
+        maia_json::WATERFALL_PROTOCOL_V2,
+    ])
+    .on_upgrade(move |socket| {
+        handle(socket, state, client, receiver, notifications).instrument(span)
+    })
+}
+
+// Version of the waterfall protocol used by a client.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum Protocol {
+    V1,
+    V2,
+    V3,
+    V4,
 }
 
-async fn handle(socket: WebSocket, receiver: broadcast::Receiver<Bytes>) {
-    if let Err(error) = handle_socket(socket, receiver).await {
+async fn handle(
+    socket: WebSocket,
+    state: AppState,
+    client: Option<SocketAddr>,
+    receiver: broadcast::Receiver<SpectrumLine>,
+    notifications: broadcast::Receiver<Notification>,
+) {
+    if let Err(error) = handle_socket(socket, state, client, receiver, notifications).await {
         tracing::error!(%error, "client error");
     }
 }
 
-async fn handle_socket(socket: WebSocket, receiver: broadcast::Receiver<Bytes>) -> Result<()> {
-    tracing::info!("websocket handshake");
-    let (ws_send, ws_recv) = socket.split();
-    // Future to forward messages from the receiver to the websocket.
-    let send = BroadcastStream::new(receiver)
-        .filter_map(|x| async move {
-            match x {
-                Ok(bytes) => Some(Ok(Message::Binary(bytes.to_vec()))),
+async fn handle_socket(
+    socket: WebSocket,
+    state: AppState,
+    client: Option<SocketAddr>,
+    receiver: broadcast::Receiver<SpectrumLine>,
+    notifications: broadcast::Receiver<Notification>,
+) -> Result<()> {
+    let subprotocol = socket.protocol().and_then(|p| p.to_str().ok());
+    let protocol = match subprotocol {
+        Some(maia_json::WATERFALL_PROTOCOL_V4) => Protocol::V4,
+        Some(maia_json::WATERFALL_PROTOCOL_V3) => Protocol::V3,
+        Some(maia_json::WATERFALL_PROTOCOL_V2) => Protocol::V2,
+        _ => Protocol::V1,
+    };
+    tracing::info!(?protocol, "websocket handshake");
+    let registration = clients::register(
+        &state,
+        maia_json::WebSocketKind::Waterfall,
+        client,
+        subprotocol,
+    );
+    let (mut ws_send, ws_recv) = socket.split();
+    let lines = BroadcastStream::new(receiver).flat_map(move |x| {
+        let messages = match x {
+            Ok(line) => line_messages(&line, protocol),
+            Err(BroadcastStreamRecvError::Lagged(lagged)) => {
+                tracing::info!("client lagged {} items", lagged);
+                if protocol >= Protocol::V2 {
+                    // The client detects dropped lines with the sequence
+                    // numbers (in the v2 and later protocols)
+                    Vec::new()
+                } else {
+                    // Let the client know that some lines have been dropped
+                    let dropped = maia_json::WaterfallDropped {
+                        dropped_lines: lagged,
+                    };
+                    vec![Message::Text(serde_json::to_string(&dropped).unwrap())]
+                }
+            }
+        };
+        futures::stream::iter(messages)
+    });
+    // Notifications are only sent in the v4 protocol
+    let notifications = futures::stream::iter((protocol == Protocol::V4).then_some(notifications))
+        .flat_map(BroadcastStream::new)
+        .filter_map(|x| {
+            futures::future::ready(match x {
+                Ok(notification) => Some(notification_message(&notification)),
                 Err(BroadcastStreamRecvError::Lagged(lagged)) => {
-                    tracing::info!("client lagged {} items", lagged);
+                    tracing::info!("client lagged {} notifications", lagged);
                     None
                 }
-            }
+            })
+        });
+    // Future to forward messages from the receivers to the websocket.
+    let send = futures::stream::select(lines, notifications)
+        .map(|message| {
+            registration.count(&message);
+            Ok(message)
         })
-        .forward(ws_send);
+        .take_until(registration.disconnect().cancelled())
+        .forward(&mut ws_send);
     // Future to receive messages form the websocket and ignore them. This
     // is needed to make the lower layers reply to ping messages automatically.
     let mut receive = ws_recv.skip_while(|r| futures::future::ready(r.is_ok()));
@@ -53,5 +134,259 @@ async fn handle_socket(socket: WebSocket, receiver: broadcast::Receiver<Bytes>)
             Some(Err(e)) => Err(e)?,
         },
     };
+    if state.shutdown().closing().is_cancelled() {
+        ws_send.send(shutdown_close_message()).await?;
+    } else if registration.disconnect().is_cancelled() {
+        ws_send.send(disconnect_close_message()).await?;
+    }
     Ok(())
 }
+
+// Returns the messages that send a spectrum line in a protocol.
+fn line_messages(line: &SpectrumLine, protocol: Protocol) -> Vec<Message> {
+    match protocol {
+        Protocol::V1 => {
+            // The timestamp is only sent before the line when the wall clock
+            // alignment is enabled
+            let timestamp = line.wall_clock_aligned.then(|| {
+                let timestamp = maia_json::WaterfallTimestamp {
+                    timestamp: timestamp_millis(line),
+                };
+                Message::Text(serde_json::to_string(&timestamp).unwrap())
+            });
+            timestamp
+                .into_iter()
+                .chain(std::iter::once(Message::Binary(line.spectrum.to_vec())))
+                .collect()
+        }
+        Protocol::V2 => vec![Message::Binary(frame(line, false))],
+        Protocol::V3 => vec![Message::Binary(frame(line, true))],
+        Protocol::V4 => vec![Message::Binary(message(
+            maia_json::WaterfallMessageType::SpectrumLine,
+            &frame(line, true),
+        ))],
+    }
+}
+
+// Returns the v4 protocol message that sends a notification.
+fn notification_message(notification: &Notification) -> Message {
+    let (message_type, payload) = match notification {
+        Notification::ConfigChange(change) => (
+            maia_json::WaterfallMessageType::ConfigChange,
+            serde_json::to_vec(change).unwrap(),
+        ),
+        Notification::Alert(alert) => (
+            maia_json::WaterfallMessageType::Alert,
+            serde_json::to_vec(alert).unwrap(),
+        ),
+    };
+    Message::Binary(message(message_type, &payload))
+}
+
+// Returns a v4 protocol message formed by the message header and the contents
+// of the message, which include the type-specific header and the payload.
+fn message(message_type: maia_json::WaterfallMessageType, contents: &[u8]) -> Vec<u8> {
+    let header = maia_json::WaterfallMessageHeader::new(message_type);
+    let mut message = Vec::with_capacity(maia_json::WaterfallMessageHeader::SIZE + contents.len());
+    message.extend_from_slice(&header.to_bytes());
+    message.extend_from_slice(contents);
+    message
+}
+
+/// Throughput of the waterfall WebSocket.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Throughput {
+    /// Number of spectra per second sent to each client.
+    pub rate: f64,
+    /// Total number of bytes per second sent to all the clients.
+    pub bytes_per_second: f64,
+}
+
+/// Measures the throughput of the waterfall WebSocket.
+///
+/// Synthetic spectrum lines are sent through a broadcast channel to `clients`
+/// simulated clients during `duration`. Each client converts the lines to the
+/// messages of the v4 protocol, as the WebSocket handler does, but the
+/// messages are not sent through the network.
+pub async fn throughput(clients: usize, duration: Duration) -> Throughput {
+    let (sender, _) = broadcast::channel(16);
+    let tasks = (0..clients)
+        .map(|_| {
+            let mut receiver = sender.subscribe();
+            tokio::spawn(async move {
+                let (mut lines, mut bytes) = (0, 0);
+                loop {
+                    match receiver.recv().await {
+                        Ok(line) => {
+                            for message in line_messages(&line, Protocol::V4) {
+                                if let Message::Binary(data) = message {
+                                    bytes += data.len();
+                                }
+                            }
+                            lines += 1;
+                        }
+                        Err(broadcast::error::RecvError::Lagged(_)) => {}
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+                (lines, bytes)
+            })
+        })
+        .collect::<Vec<_>>();
+    let spectrum = Bytes::from(vec![0; FFT_SIZE as usize * std::mem::size_of::<f32>()]);
+    let start = Instant::now();
+    let mut sequence = 0;
+    while start.elapsed() < duration {
+        let _ = sender.send(SpectrumLine {
+            spectrum: spectrum.clone(),
+            sequence,
+            timestamp: Utc::now(),
+            wall_clock_aligned: false,
+            noise_floor: None,
+        });
+        sequence += 1;
+        // Let the clients run, so that the lines are only dropped if the
+        // clients cannot keep up.
+        tokio::task::yield_now().await;
+    }
+    drop(sender);
+    let (mut lines, mut bytes) = (0, 0);
+    for task in tasks {
+        let (task_lines, task_bytes) = task.await.unwrap_or_default();
+        lines += task_lines;
+        bytes += task_bytes;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    Throughput {
+        rate: lines as f64 / clients.max(1) as f64 / elapsed,
+        bytes_per_second: bytes as f64 / elapsed,
+    }
+}
+
+/// Returns the close message sent to the WebSocket clients when maia-httpd
+/// shuts down.
+pub fn shutdown_close_message() -> Message {
+    Message::Close(Some(CloseFrame {
+        code: close_code::AWAY,
+        reason: "server shutting down".into(),
+    }))
+}
+
+// Returns the start time of the integration of a line in milliseconds since the
+// UNIX epoch.
+fn timestamp_millis(line: &SpectrumLine) -> f64 {
+    line.timestamp.timestamp_micros() as f64 * 1e-3
+}
+
+// Returns a binary message formed by the frame header and the spectrum, as
+// used in the v2 protocol, or in the v3 protocol if v3 is true.
+fn frame(line: &SpectrumLine, v3: bool) -> Vec<u8> {
+    let header = maia_json::WaterfallFrameHeader {
+        sequence: line.sequence,
+        timestamp: timestamp_millis(line),
+        noise_floor: line.noise_floor,
+    };
+    let mut frame =
+        Vec::with_capacity(maia_json::WaterfallFrameHeader::SIZE_V3 + line.spectrum.len());
+    if v3 {
+        frame.extend_from_slice(&header.to_bytes_v3());
+    } else {
+        frame.extend_from_slice(&header.to_bytes());
+    }
+    frame.extend_from_slice(&line.spectrum);
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+    use chrono::DateTime;
+
+    #[test]
+    fn v2_frame() {
+        let line = SpectrumLine {
+            spectrum: Bytes::from_static(&[1, 2, 3, 4]),
+            sequence: 7,
+            timestamp: DateTime::from_timestamp(1704110400, 250_000_000).unwrap(),
+            wall_clock_aligned: false,
+            noise_floor: Some(12.5),
+        };
+        let frame = frame(&line, false);
+        assert_eq!(
+            maia_json::WaterfallFrameHeader::from_bytes(&frame),
+            Some(maia_json::WaterfallFrameHeader {
+                sequence: 7,
+                timestamp: 1704110400250.0,
+                noise_floor: None,
+            })
+        );
+        assert_eq!(
+            &frame[maia_json::WaterfallFrameHeader::SIZE..],
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn v3_frame() {
+        let line = SpectrumLine {
+            spectrum: Bytes::from_static(&[1, 2, 3, 4]),
+            sequence: 7,
+            timestamp: DateTime::from_timestamp(1704110400, 250_000_000).unwrap(),
+            wall_clock_aligned: false,
+            noise_floor: Some(12.5),
+        };
+        let frame = frame(&line, true);
+        assert_eq!(
+            maia_json::WaterfallFrameHeader::from_bytes_v3(&frame),
+            Some(maia_json::WaterfallFrameHeader {
+                sequence: 7,
+                timestamp: 1704110400250.0,
+                noise_floor: Some(12.5),
+            })
+        );
+        assert_eq!(
+            &frame[maia_json::WaterfallFrameHeader::SIZE_V3..],
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn v4_messages() {
+        let line = SpectrumLine {
+            spectrum: Bytes::from_static(&[1, 2, 3, 4]),
+            sequence: 7,
+            timestamp: DateTime::from_timestamp(1704110400, 250_000_000).unwrap(),
+            wall_clock_aligned: false,
+            noise_floor: Some(12.5),
+        };
+        let [Message::Binary(message)] = &line_messages(&line, Protocol::V4)[..] else {
+            panic!("unexpected messages");
+        };
+        let header = maia_json::WaterfallMessageHeader::from_bytes(message).unwrap();
+        assert_eq!(
+            header.message_type(),
+            Some(maia_json::WaterfallMessageType::SpectrumLine)
+        );
+        assert_eq!(
+            &message[maia_json::WaterfallMessageHeader::SIZE..],
+            frame(&line, true)
+        );
+        assert_eq!(&message[usize::from(header.payload_offset)..], [1, 2, 3, 4]);
+
+        let notification = Notification::ConfigChange(maia_json::WaterfallConfigChange {
+            path: "/api/v1/ad9361".to_string(),
+        });
+        let Message::Binary(message) = notification_message(&notification) else {
+            panic!("unexpected message");
+        };
+        let header = maia_json::WaterfallMessageHeader::from_bytes(&message).unwrap();
+        assert_eq!(
+            header.message_type(),
+            Some(maia_json::WaterfallMessageType::ConfigChange)
+        );
+        let change: maia_json::WaterfallConfigChange =
+            serde_json::from_slice(&message[usize::from(header.payload_offset)..]).unwrap();
+        assert_eq!(change.path, "/api/v1/ad9361");
+    }
+}
@@ -3,40 +3,188 @@
 //! This module is used to control IIO devices, such as the ADI AD9361 driver.
 
 use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use maia_json::{Ad9361RfBandwidthMode, Decibels, Hertz, PatchAd9361};
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use tokio::fs;
 
 /// AD9361 IIO device.
 ///
 /// This struct represents the AD9361 IIO device (ad9361-phy) and can be used to
-/// control its attributes.
+/// control its attributes. The attributes are accessed through an
+/// [`RficBackend`].
+///
+/// Besides the attributes of the driver, this struct keeps the receive RF
+/// bandwidth mode, which is implemented by maia-httpd.
 #[derive(Debug)]
 pub struct Ad9361 {
+    backend: Box<dyn RficBackend>,
+    rx_rf_bandwidth_mode: Mutex<Ad9361RfBandwidthMode>,
+}
+
+/// RFIC backend.
+///
+/// This trait abstracts the access to the IIO attributes of the AD9361, so that
+/// [`Ad9361`] can be used either with the real device, through
+/// [`IioDevice`], or with a simulated device, such as
+/// [`MockRfic`](crate::mock::MockRfic).
+///
+/// The attributes are identified by the name of their file in the IIO device
+/// sysfs directory (for instance `out_altvoltage0_RX_LO_frequency`), and their
+/// values are formatted as in these files. Debug attributes are identified in
+/// the same way by the name of their file in the IIO device debugfs directory
+/// (for instance `adi,agc-outer-thresh-high`).
+pub trait RficBackend: std::fmt::Debug + Send + Sync {
+    /// Reads the value of an IIO attribute.
+    fn read_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>>;
+
+    /// Writes the value of an IIO attribute.
+    fn write_attribute<'a>(
+        &'a self,
+        attribute: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<()>>;
+
+    /// Reads the value of an IIO debug attribute.
+    ///
+    /// The default implementation returns an error, indicating that debug
+    /// attributes are not supported by the backend.
+    fn read_debug_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { anyhow::bail!("debug attribute {attribute} not supported") })
+    }
+
+    /// Writes the value of an IIO debug attribute.
+    ///
+    /// The default implementation returns an error, indicating that debug
+    /// attributes are not supported by the backend.
+    fn write_debug_attribute<'a>(
+        &'a self,
+        attribute: &'a str,
+        _value: String,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move { anyhow::bail!("debug attribute {attribute} not supported") })
+    }
+}
+
+/// IIO device.
+///
+/// This [`RficBackend`] accesses the attributes of an IIO device through its
+/// sysfs directory, and the debug attributes through its debugfs directory.
+#[derive(Debug)]
+pub struct IioDevice {
     iio_device_path: PathBuf,
+    iio_debugfs_path: PathBuf,
 }
 
-macro_rules! iio_getset {
+impl IioDevice {
+    /// Opens an IIO device.
+    ///
+    /// This function opens the first IIO device with the given name that is
+    /// found in the system.
+    pub async fn new(name: &str) -> Result<IioDevice> {
+        let iio_device_path = Self::find_iio_device(name)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("{name} IIO device not found"))?;
+        let iio_debugfs_path = Path::new("/sys/kernel/debug/iio").join(
+            iio_device_path
+                .file_name()
+                .ok_or_else(|| anyhow::anyhow!("IIO device path has no file name"))?,
+        );
+        Ok(IioDevice {
+            iio_device_path,
+            iio_debugfs_path,
+        })
+    }
+
+    async fn find_iio_device(name: &str) -> Result<Option<PathBuf>> {
+        let mut entries = fs::read_dir(Path::new("/sys/bus/iio/devices")).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry
+                .file_name()
+                .to_str()
+                .ok_or_else(|| anyhow::anyhow!("file name is not valid UTF8"))?
+                .starts_with("iio:device")
+            {
+                let mut path = entry.path();
+                path.push("name");
+                let this_name = fs::read_to_string(path).await?;
+                if this_name.trim_end() == name {
+                    return Ok(Some(entry.path()));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl RficBackend for IioDevice {
+    fn read_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(async move { Ok(fs::read_to_string(self.iio_device_path.join(attribute)).await?) })
+    }
+
+    fn write_attribute<'a>(
+        &'a self,
+        attribute: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            fs::write(self.iio_device_path.join(attribute), value.as_bytes()).await?;
+            Ok(())
+        })
+    }
+
+    fn read_debug_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>> {
+        Box::pin(
+            async move { Ok(fs::read_to_string(self.iio_debugfs_path.join(attribute)).await?) },
+        )
+    }
+
+    fn write_debug_attribute<'a>(
+        &'a self,
+        attribute: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            fs::write(self.iio_debugfs_path.join(attribute), value.as_bytes()).await?;
+            Ok(())
+        })
+    }
+}
+
+macro_rules! iio_get {
     ($attribute:ident, $filename:expr, $ty_internal:ty, $ty_external:ty) => {
+        iio_get!(@read_attribute, "IIO", $attribute, $filename, $ty_internal, $ty_external);
+    };
+    (@$read:ident, $kind:literal, $attribute:ident, $filename:expr, $ty_internal:ty,
+     $ty_external:ty) => {
         paste::paste! {
             #[doc = concat!("Returns the value of the `", stringify!($attribute),
-                            "` IIO attribute.")]
+                            "` ", $kind, " attribute.")]
             pub async fn [<get_ $attribute>](&self) -> Result<$ty_external> {
-                fs::read_to_string(self.iio_device_path.join($filename))
+                self.backend.$read($filename)
                     .await?
                     .trim_end()
                     .parse::<$ty_internal>()
                     .map_err(|_| anyhow::anyhow!(concat!(
-                        "failed to parse IIO attribute ", stringify!($attribute))))
+                        "failed to parse ", $kind, " attribute ", stringify!($attribute))))
                     .map(|x| x.into())
             }
+        }
+    };
+}
 
+macro_rules! iio_set {
+    (@$write:ident, $kind:literal, $attribute:ident, $filename:expr, $ty_internal:ty,
+     $ty_external:ty) => {
+        paste::paste! {
             #[doc = concat!("Sets the value of the `", stringify!($attribute),
-                            "` IIO attribute.")]
+                            "` ", $kind, " attribute.")]
             pub async fn [<set_ $attribute>](&self, value: $ty_external) -> Result<()> {
-                fs::write(
-                    self.iio_device_path.join($filename),
-                    Into::<$ty_internal>::into(value).to_string().as_bytes(),
-                ).await.context(concat!("failed to set IIO attribute ",
+                self.backend.$write(
+                    $filename,
+                    Into::<$ty_internal>::into(value).to_string(),
+                ).await.context(concat!("failed to set ", $kind, " attribute ",
                                         stringify!($attribute)))?;
                 Ok(())
             }
@@ -44,56 +192,193 @@ macro_rules! iio_getset {
     };
 }
 
+macro_rules! iio_getset {
+    ($attribute:ident, $filename:expr, $ty_internal:ty, $ty_external:ty) => {
+        iio_get!(@read_attribute, "IIO", $attribute, $filename, $ty_internal, $ty_external);
+        iio_set!(@write_attribute, "IIO", $attribute, $filename, $ty_internal, $ty_external);
+    };
+}
+
+macro_rules! iio_debug_getset {
+    ($attribute:ident, $filename:expr, $ty_internal:ty, $ty_external:ty) => {
+        iio_get!(@read_debug_attribute, "debugfs", $attribute, $filename, $ty_internal,
+                 $ty_external);
+        iio_set!(@write_debug_attribute, "debugfs", $attribute, $filename, $ty_internal,
+                 $ty_external);
+    };
+}
+
 impl Ad9361 {
     /// Opens an AD9361 IIO device.
     ///
     /// This function opens the first IIO device with name ad9361-phy that is
     /// found in the system.
     pub async fn new() -> Result<Ad9361> {
-        let iio_device_path = Self::find_iio_device()
-            .await?
-            .ok_or_else(|| anyhow::anyhow!("ad9361-phy IIO device not found"))?;
-        Ok(Ad9361 { iio_device_path })
+        Ok(Self::with_backend(IioDevice::new("ad9361-phy").await?))
     }
 
-    async fn find_iio_device() -> Result<Option<PathBuf>> {
-        let mut entries = fs::read_dir(Path::new("/sys/bus/iio/devices")).await?;
-        while let Some(entry) = entries.next_entry().await? {
-            if entry
-                .file_name()
-                .to_str()
-                .ok_or_else(|| anyhow::anyhow!("file name is not valid UTF8"))?
-                .starts_with("iio:device")
-            {
-                let mut path = entry.path();
-                path.push("name");
-                let this_name = fs::read_to_string(path).await?;
-                if this_name == "ad9361-phy\n" {
-                    return Ok(Some(entry.path()));
-                }
-            }
+    /// Creates an AD9361 object that uses a custom backend.
+    ///
+    /// This is mainly used to run maia-httpd with a simulated AD9361 (see
+    /// [`MockRfic`](crate::mock::MockRfic)).
+    pub fn with_backend<B: RficBackend + 'static>(backend: B) -> Ad9361 {
+        Ad9361 {
+            backend: Box::new(backend),
+            rx_rf_bandwidth_mode: Mutex::new(Ad9361RfBandwidthMode::Manual),
         }
-        Ok(None)
     }
 
     iio_getset!(
         sampling_frequency,
         "in_voltage_sampling_frequency",
-        u32,
-        u32
+        IioHertz,
+        Hertz
     );
-    iio_getset!(rx_rf_bandwidth, "in_voltage_rf_bandwidth", u32, u32);
-    iio_getset!(tx_rf_bandwidth, "out_voltage_rf_bandwidth", u32, u32);
-    iio_getset!(rx_lo_frequency, "out_altvoltage0_RX_LO_frequency", u64, u64);
-    iio_getset!(tx_lo_frequency, "out_altvoltage1_TX_LO_frequency", u64, u64);
-    iio_getset!(rx_gain, "in_voltage0_hardwaregain", Dbf64, f64);
-    iio_getset!(tx_gain, "out_voltage0_hardwaregain", Dbf64, f64);
+    iio_getset!(rx_rf_bandwidth, "in_voltage_rf_bandwidth", IioHertz, Hertz);
+    iio_getset!(tx_rf_bandwidth, "out_voltage_rf_bandwidth", IioHertz, Hertz);
+    iio_getset!(
+        rx_lo_frequency,
+        "out_altvoltage0_RX_LO_frequency",
+        IioHertz,
+        Hertz
+    );
+    iio_getset!(
+        tx_lo_frequency,
+        "out_altvoltage1_TX_LO_frequency",
+        IioHertz,
+        Hertz
+    );
+    iio_getset!(rx_gain, "in_voltage0_hardwaregain", Dbf64, Decibels);
+    iio_getset!(tx_gain, "out_voltage0_hardwaregain", Dbf64, Decibels);
+    iio_get!(rx_rssi, "in_voltage0_rssi", Dbf64, Decibels);
     iio_getset!(
         rx_gain_mode,
         "in_voltage0_gain_control_mode",
         Ad9361GainMode,
         Ad9361GainMode
     );
+
+    // AGC parameters. These are only accessible through debugfs, and they are
+    // applied by calling `initialize` after modifying them.
+    iio_debug_getset!(agc_outer_thresh_high, "adi,agc-outer-thresh-high", u32, u32);
+    iio_debug_getset!(
+        agc_outer_thresh_high_dec_steps,
+        "adi,agc-outer-thresh-high-dec-steps",
+        u32,
+        u32
+    );
+    iio_debug_getset!(agc_inner_thresh_high, "adi,agc-inner-thresh-high", u32, u32);
+    iio_debug_getset!(
+        agc_inner_thresh_high_dec_steps,
+        "adi,agc-inner-thresh-high-dec-steps",
+        u32,
+        u32
+    );
+    iio_debug_getset!(agc_inner_thresh_low, "adi,agc-inner-thresh-low", u32, u32);
+    iio_debug_getset!(
+        agc_inner_thresh_low_inc_steps,
+        "adi,agc-inner-thresh-low-inc-steps",
+        u32,
+        u32
+    );
+    iio_debug_getset!(agc_outer_thresh_low, "adi,agc-outer-thresh-low", u32, u32);
+    iio_debug_getset!(
+        agc_outer_thresh_low_inc_steps,
+        "adi,agc-outer-thresh-low-inc-steps",
+        u32,
+        u32
+    );
+    iio_debug_getset!(
+        agc_attack_delay_extra_margin_us,
+        "adi,agc-attack-delay-extra-margin-us",
+        u32,
+        u32
+    );
+    iio_debug_getset!(
+        agc_gain_update_interval_us,
+        "adi,agc-gain-update-interval-us",
+        u32,
+        u32
+    );
+    iio_debug_getset!(agc_fast_lock_level, "adi,fagc-lock-level", u32, u32);
+    iio_debug_getset!(
+        agc_fast_state_wait_time_ns,
+        "adi,fagc-state-wait-time-ns",
+        u32,
+        u32
+    );
+    iio_debug_getset!(
+        agc_fast_allow_gain_increase,
+        "adi,fagc-allow-agc-gain-increase-enable",
+        IioBool,
+        bool
+    );
+    iio_debug_getset!(
+        agc_fast_gain_increase_after_lock,
+        "adi,fagc-gain-increase-after-gain-lock-enable",
+        IioBool,
+        bool
+    );
+    iio_debug_getset!(
+        agc_fast_unlock_stronger_signal_thresh,
+        "adi,fagc-rst-gla-stronger-sig-thresh-above-ll",
+        u32,
+        u32
+    );
+    // The misspelling of "energy" comes from the AD9361 driver.
+    iio_debug_getset!(
+        agc_fast_unlock_energy_lost_thresh,
+        "adi,fagc-rst-gla-engergy-lost-sig-thresh-below-ll",
+        u32,
+        u32
+    );
+
+    /// Returns the receive RF bandwidth mode.
+    ///
+    /// This is not an attribute of the AD9361 driver, so it never fails. It is
+    /// async for consistency with the getters of the attributes.
+    pub async fn get_rx_rf_bandwidth_mode(&self) -> Result<Ad9361RfBandwidthMode> {
+        Ok(*self.rx_rf_bandwidth_mode.lock().unwrap())
+    }
+
+    /// Sets the receive RF bandwidth mode.
+    ///
+    /// This only records the mode. The caller is responsible for updating the
+    /// receive RF bandwidth when the mode is
+    /// [`Auto`](Ad9361RfBandwidthMode::Auto).
+    pub fn set_rx_rf_bandwidth_mode(&self, mode: Ad9361RfBandwidthMode) {
+        *self.rx_rf_bandwidth_mode.lock().unwrap() = mode;
+    }
+
+    /// Calibrates the receive analog filters.
+    ///
+    /// The AD9361 driver tunes the receive analog filters each time that the
+    /// receive RF bandwidth changes, but not when the same bandwidth is written
+    /// again. The calibration is forced by changing the bandwidth by 1 Hz and
+    /// then restoring it.
+    pub async fn calibrate_rx_filter(&self) -> Result<()> {
+        let bandwidth = self.get_rx_rf_bandwidth().await?;
+        let detuned = if bandwidth < Hertz(*PatchAd9361::RF_BANDWIDTH_RANGE.end()) {
+            bandwidth + Hertz(1.0)
+        } else {
+            bandwidth - Hertz(1.0)
+        };
+        self.set_rx_rf_bandwidth(detuned).await?;
+        self.set_rx_rf_bandwidth(bandwidth).await
+    }
+
+    /// Initializes the AD9361.
+    ///
+    /// This resets and sets up the AD9361 again, applying the values of the
+    /// debugfs attributes. Some IIO attributes might not be preserved across
+    /// the initialization, so they should be read before calling this function
+    /// and restored afterwards.
+    pub async fn initialize(&self) -> Result<()> {
+        self.backend
+            .write_debug_attribute("initialize", "1".to_string())
+            .await
+            .context("failed to initialize AD9361")
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
@@ -145,20 +430,84 @@ impl std::fmt::Display for Ad9361GainMode {
 }
 
 #[derive(Debug, Clone, Copy)]
-struct Dbf64(f64);
+struct IioBool(bool);
 
-impl From<f64> for Dbf64 {
-    fn from(value: f64) -> Dbf64 {
-        Dbf64(value)
+impl From<bool> for IioBool {
+    fn from(value: bool) -> IioBool {
+        IioBool(value)
     }
 }
 
-impl From<Dbf64> for f64 {
-    fn from(value: Dbf64) -> f64 {
+impl From<IioBool> for bool {
+    fn from(value: IioBool) -> bool {
         value.0
     }
 }
 
+impl std::str::FromStr for IioBool {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "0" => Ok(IioBool(false)),
+            "1" => Ok(IioBool(true)),
+            _ => Err(()),
+        }
+    }
+}
+
+impl std::fmt::Display for IioBool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "{}", u8::from(self.0))
+    }
+}
+
+// Frequency attribute. The AD9361 driver uses an integer number of Hz, so the
+// frequency is rounded when it is written.
+#[derive(Debug, Clone, Copy)]
+struct IioHertz(u64);
+
+impl From<Hertz> for IioHertz {
+    fn from(value: Hertz) -> IioHertz {
+        IioHertz(value.0.round() as u64)
+    }
+}
+
+impl From<IioHertz> for Hertz {
+    fn from(value: IioHertz) -> Hertz {
+        Hertz(value.0 as f64)
+    }
+}
+
+impl std::str::FromStr for IioHertz {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse().map(IioHertz)
+    }
+}
+
+impl std::fmt::Display for IioHertz {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        self.0.fmt(f)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Dbf64(f64);
+
+impl From<Decibels> for Dbf64 {
+    fn from(value: Decibels) -> Dbf64 {
+        Dbf64(value.0)
+    }
+}
+
+impl From<Dbf64> for Decibels {
+    fn from(value: Dbf64) -> Decibels {
+        Decibels(value.0)
+    }
+}
+
 impl std::str::FromStr for Dbf64 {
     type Err = <f64 as std::str::FromStr>::Err;
 
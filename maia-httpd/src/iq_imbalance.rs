@@ -0,0 +1,152 @@
+//! IQ imbalance and DC offset estimation.
+//!
+//! This module estimates the residual IQ imbalance and DC offset of the AD9361
+//! receiver from a block of IQ samples. The IQ imbalance is estimated blindly
+//! from the second order statistics of the samples. A circular signal, such as
+//! noise, has `E[x^2] = 0`, while an IQ imbalance produces a mirror image of the
+//! signal that makes `E[x^2]` nonzero. The ratio between `|E[x^2]|` and
+//! `E[|x|^2]` gives the power of the image relative to the power of the signal.
+
+use num_complex::{Complex32, Complex64};
+
+/// IQ imbalance and DC offset estimate.
+///
+/// The powers and amplitudes use the same units as the IQ samples from which
+/// the estimate is made.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Estimate {
+    /// Average power of the samples.
+    pub power: f64,
+    /// DC offset.
+    pub dc_offset: Complex64,
+    /// Image rejection ratio (as a power ratio).
+    ///
+    /// This is `None` if there is no measurable IQ imbalance.
+    pub image_rejection: Option<f64>,
+    /// Gain imbalance.
+    ///
+    /// This is the ratio between the power of the I component and the power of
+    /// the Q component, after removing the DC offset.
+    pub gain_imbalance: f64,
+    /// Phase error (in radians).
+    ///
+    /// This is the deviation from 90 degrees of the phase difference between
+    /// the I and Q components.
+    pub phase_error: f64,
+}
+
+/// Estimates the IQ imbalance and DC offset of a block of IQ samples.
+///
+/// The received signal is assumed to be circular. Returns `None` if the block
+/// is empty or if either the I or the Q component of the samples is constant,
+/// since then the IQ imbalance cannot be estimated.
+pub fn estimate(samples: &[Complex32]) -> Option<Estimate> {
+    if samples.is_empty() {
+        return None;
+    }
+    let n = samples.len() as f64;
+    let samples = samples
+        .iter()
+        .map(|x| Complex64::new(x.re.into(), x.im.into()));
+    let dc_offset = samples.clone().sum::<Complex64>() / n;
+    let power = samples.clone().map(|x| x.norm_sqr()).sum::<f64>() / n;
+    let (mut ii, mut qq, mut iq) = (0.0, 0.0, 0.0);
+    for x in samples.map(|x| x - dc_offset) {
+        ii += x.re * x.re;
+        qq += x.im * x.im;
+        iq += x.re * x.im;
+    }
+    if ii == 0.0 || qq == 0.0 {
+        return None;
+    }
+    // Circularity coefficient |E[x^2]| / E[|x|^2]. For x = a s + b conj(s),
+    // with s circular, this is 2 sqrt(r) / (1 + r), where r = |b|^2 / |a|^2
+    // is the power of the image relative to the signal.
+    let circularity = (Complex64::new(ii - qq, 2.0 * iq).norm() / (ii + qq)).min(1.0);
+    let image_rejection = (circularity > 0.0).then(|| {
+        let r = ((1.0 - (1.0 - circularity * circularity).sqrt()) / circularity).powi(2);
+        r.recip()
+    });
+    Some(Estimate {
+        power,
+        dc_offset,
+        image_rejection,
+        gain_imbalance: ii / qq,
+        phase_error: (iq / (ii * qq).sqrt()).clamp(-1.0, 1.0).asin(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Sum of complex exponentials whose frequencies fit an integer number of
+    // cycles in the block, so that the signal is exactly circular.
+    fn circular_signal() -> Vec<Complex64> {
+        const N: usize = 4096;
+        (0..N)
+            .map(|j| {
+                let t = j as f64 / N as f64;
+                [(100.0, 0.3), (517.0, 0.1), (-1234.0, 0.2)]
+                    .iter()
+                    .map(|&(k, a)| Complex64::from_polar(a, 2.0 * std::f64::consts::PI * k * t))
+                    .sum()
+            })
+            .collect()
+    }
+
+    fn to_complex32(samples: impl Iterator<Item = Complex64>) -> Vec<Complex32> {
+        samples
+            .map(|x| Complex32::new(x.re as f32, x.im as f32))
+            .collect()
+    }
+
+    #[test]
+    fn balanced() {
+        let samples = to_complex32(circular_signal().into_iter());
+        let estimate = estimate(&samples).unwrap();
+        assert!((estimate.power - 0.14).abs() < 1e-6);
+        assert!(estimate.dc_offset.norm() < 1e-6);
+        // the residual imbalance is due to the f32 rounding
+        assert!(estimate.image_rejection.is_none_or(|r| r > 1e8));
+        assert!((estimate.gain_imbalance - 1.0).abs() < 1e-5);
+        assert!(estimate.phase_error.abs() < 1e-5);
+    }
+
+    #[test]
+    fn image_and_dc_offset() {
+        let image = Complex64::new(0.006, -0.008);
+        let dc_offset = Complex64::new(0.01, -0.02);
+        let samples = to_complex32(
+            circular_signal()
+                .into_iter()
+                .map(|x| x + image * x.conj() + dc_offset),
+        );
+        let estimate = estimate(&samples).unwrap();
+        assert!((estimate.dc_offset - dc_offset).norm() < 1e-6);
+        // the image is 40 dB below the signal
+        let image_rejection_db = 10.0 * estimate.image_rejection.unwrap().log10();
+        assert!((image_rejection_db - 40.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn gain_imbalance() {
+        let samples = to_complex32(
+            circular_signal()
+                .into_iter()
+                .map(|x| Complex64::new(1.1 * x.re, x.im)),
+        );
+        let estimate = estimate(&samples).unwrap();
+        assert!((estimate.gain_imbalance - 1.21).abs() < 1e-5);
+        assert!(estimate.phase_error.abs() < 1e-5);
+        // an amplitude imbalance g gives an image rejection ((g + 1) / (g - 1))^2
+        let expected = (2.1f64 / 0.1).powi(2);
+        assert!((estimate.image_rejection.unwrap() / expected - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn no_estimate() {
+        assert!(estimate(&[]).is_none());
+        assert!(estimate(&[Complex32::new(1.0, 0.0); 16]).is_none());
+    }
+}
@@ -8,11 +8,34 @@
 
 pub mod app;
 pub mod args;
+pub mod capture;
+pub mod colormap;
 pub mod ddc;
+pub mod demodulator;
+pub mod digital_rf;
+pub mod doppler;
 pub mod fpga;
+pub mod fs;
+pub mod hdf5;
+pub mod http_client;
 pub mod httpd;
 pub mod iio;
+pub mod iq_imbalance;
+pub mod limits;
+pub mod mock;
+pub mod notifications;
+pub mod peaks;
 pub mod rxbuffer;
+pub mod sample_time;
+pub mod samples;
+pub mod scanner;
+pub mod shutdown;
 pub mod sigmf;
+pub mod spectrogram;
 pub mod spectrometer;
+pub mod stream;
+pub mod survey;
+pub mod system;
+pub mod tuner;
 pub mod uio;
+pub mod wav;
@@ -0,0 +1,202 @@
+//! Safety limits.
+//!
+//! This module implements the safety limits configured through
+//! `/api/limits-admin`. They restrict the transmit gain and the frequencies to
+//! which the AD9361 can be tuned, so that shared or remote installations cannot
+//! be driven outside legal or safe parameters.
+//!
+//! The frequency limits apply to the LO frequencies that are programmed in the
+//! AD9361. They do not include the frequency offset of the device or the DDC
+//! frequency.
+
+use crate::args::Args;
+use anyhow::{Context, Result};
+use maia_json::{Decibels, Hertz, LimitsFrequencyRange, PatchAd9361};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Safety limits.
+///
+/// This struct is part of the [`AppState`](crate::app::AppState). It contains
+/// the limits and the token that authorizes changing them. If a limits file is
+/// given in the command line, the limits are read from this file and saved to
+/// it each time that they are changed.
+#[derive(Debug)]
+pub struct Limits {
+    config: Mutex<maia_json::Limits>,
+    file: Option<PathBuf>,
+    token: Option<String>,
+}
+
+impl Limits {
+    /// Creates the safety limits.
+    ///
+    /// If a limits file is given in the `args`, the limits are read from this
+    /// file (if it exists). Otherwise, nothing is limited.
+    pub async fn new(args: &Args) -> Result<Limits> {
+        let config = match &args.limits_file {
+            Some(file) => match tokio::fs::read(file).await {
+                Ok(json) => {
+                    let config: maia_json::Limits = serde_json::from_slice(&json)
+                        .with_context(|| format!("failed to parse {file:?}"))?;
+                    config
+                        .validate()
+                        .with_context(|| format!("invalid limits in {file:?}"))?;
+                    config
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+                Err(err) => return Err(err).with_context(|| format!("failed to read {file:?}")),
+            },
+            None => Default::default(),
+        };
+        Ok(Limits {
+            config: Mutex::new(config),
+            file: args.limits_file.clone(),
+            token: args.limits_token.clone(),
+        })
+    }
+
+    /// Returns the token that authorizes changing the limits.
+    ///
+    /// This is `None` if no token was given in the command line, in which case
+    /// the limits cannot be changed through the API.
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Returns the JSON representation of the limits.
+    pub fn json(&self) -> maia_json::Limits {
+        self.config.lock().unwrap().clone()
+    }
+
+    /// Replaces the limits.
+    ///
+    /// The limits must have been validated with [`maia_json::Limits::validate`].
+    /// The new limits are saved to the limits file before they are applied, so
+    /// the function fails and keeps the current limits if they cannot be saved.
+    pub async fn set(&self, config: maia_json::Limits) -> Result<()> {
+        if let Some(file) = &self.file {
+            crate::fs::write_atomic(file, serde_json::to_vec_pretty(&config)?)
+                .await
+                .context("failed to write limits file")?;
+        }
+        *self.config.lock().unwrap() = config;
+        Ok(())
+    }
+
+    /// Checks that a transmit gain is within the limits.
+    pub fn check_tx_gain(&self, gain: Decibels) -> Result<()> {
+        if let Some(max) = self.config.lock().unwrap().max_tx_gain {
+            anyhow::ensure!(
+                gain <= max,
+                "the safety limits do not allow a TX gain above {max}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Checks that a frequency is within the limits.
+    pub fn check_frequency(&self, frequency: Hertz) -> Result<()> {
+        self.check_band(frequency, frequency)
+    }
+
+    /// Checks that the AD9361 RX LO can be tuned to a frequency.
+    ///
+    /// The frequency must be within the tuning range of the AD9361 and within
+    /// the limits. This is used by the tasks that retune the RX LO on their
+    /// own.
+    pub fn check_rx_lo_frequency(&self, frequency: Hertz) -> Result<()> {
+        anyhow::ensure!(
+            PatchAd9361::RX_LO_FREQUENCY_RANGE.contains(&frequency.0),
+            "RX frequency {frequency} is out of the tuning range"
+        );
+        self.check_frequency(frequency)
+    }
+
+    /// Checks that a frequency band is within the limits.
+    ///
+    /// The band goes from `start` to `stop`. It must be inside one of the
+    /// allowed ranges, if there are any, and it cannot overlap any of the
+    /// blocked ranges.
+    pub fn check_band(&self, start: Hertz, stop: Hertz) -> Result<()> {
+        let config = self.config.lock().unwrap();
+        let contains = |range: &LimitsFrequencyRange| {
+            range.start_frequency <= start && stop <= range.stop_frequency
+        };
+        let overlaps = |range: &LimitsFrequencyRange| {
+            range.start_frequency <= stop && start <= range.stop_frequency
+        };
+        let allowed = (config.frequency_allowlist.is_empty()
+            || config.frequency_allowlist.iter().any(contains))
+            && !config.frequency_blocklist.iter().any(overlaps);
+        if !allowed {
+            if start == stop {
+                anyhow::bail!("the safety limits do not allow the frequency {start}");
+            }
+            anyhow::bail!("the safety limits do not allow the band from {start} to {stop}");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use clap::Parser;
+
+    fn range(start_frequency: f64, stop_frequency: f64) -> LimitsFrequencyRange {
+        LimitsFrequencyRange {
+            start_frequency: Hertz(start_frequency),
+            stop_frequency: Hertz(stop_frequency),
+        }
+    }
+
+    #[tokio::test]
+    async fn limits() {
+        let limits = Limits::new(&Args::parse_from(["maia-httpd"]))
+            .await
+            .unwrap();
+        assert!(limits.check_tx_gain(Decibels(0.0)).is_ok());
+        assert!(limits.check_frequency(Hertz(2.4e9)).is_ok());
+        limits
+            .set(maia_json::Limits {
+                max_tx_gain: Some(Decibels(-20.0)),
+                frequency_allowlist: vec![range(144e6, 146e6)],
+                frequency_blocklist: vec![range(145.8e6, 146e6)],
+            })
+            .await
+            .unwrap();
+        assert!(limits.check_tx_gain(Decibels(-20.0)).is_ok());
+        assert!(limits.check_tx_gain(Decibels(-10.0)).is_err());
+        assert!(limits.check_frequency(Hertz(145e6)).is_ok());
+        assert!(limits.check_frequency(Hertz(2.4e9)).is_err());
+        assert!(limits.check_frequency(Hertz(145.9e6)).is_err());
+        assert!(limits.check_band(Hertz(144e6), Hertz(145e6)).is_ok());
+        assert!(limits.check_band(Hertz(145e6), Hertz(145.9e6)).is_err());
+        assert!(limits.check_band(Hertz(143e6), Hertz(145e6)).is_err());
+        assert!(limits.check_rx_lo_frequency(Hertz(145e6)).is_ok());
+        assert!(limits.check_rx_lo_frequency(Hertz(145.9e6)).is_err());
+    }
+
+    #[tokio::test]
+    async fn persistence() {
+        let file =
+            std::env::temp_dir().join(format!("maia-httpd-limits-{}.json", std::process::id()));
+        let args = Args::parse_from(["maia-httpd", "--limits-file", file.to_str().unwrap()]);
+        let config = maia_json::Limits {
+            max_tx_gain: Some(Decibels(-20.0)),
+            ..Default::default()
+        };
+        let limits = Limits::new(&args).await.unwrap();
+        limits.set(config.clone()).await.unwrap();
+        assert_eq!(Limits::new(&args).await.unwrap().json(), config);
+        std::fs::remove_file(&file).unwrap();
+
+        // The limits are kept if they cannot be saved
+        let file = std::env::temp_dir().join("maia-httpd-missing/limits.json");
+        let args = Args::parse_from(["maia-httpd", "--limits-file", file.to_str().unwrap()]);
+        let limits = Limits::new(&args).await.unwrap();
+        assert!(limits.set(config).await.is_err());
+        assert_eq!(limits.json(), maia_json::Limits::default());
+    }
+}
@@ -1,6 +1,4 @@
 use anyhow::Result;
-#[cfg(not(feature = "uclibc"))]
-use clap::Parser;
 use maia_httpd::{app::App, args::Args};
 use tracing_subscriber::{fmt, prelude::*, EnvFilter};
 
@@ -11,11 +9,7 @@ async fn main() -> Result<()> {
         .with(EnvFilter::from_default_env())
         .init();
 
-    // workaround for https://github.com/rust-lang/rust/issues/112488
-    #[cfg(feature = "uclibc")]
-    let args = Args::default();
-    #[cfg(not(feature = "uclibc"))]
-    let args = Args::parse();
+    let args = Args::load()?;
 
     App::new(&args).await?.run().await
 }
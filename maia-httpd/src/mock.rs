@@ -0,0 +1,834 @@
+//! Mock hardware backends.
+//!
+//! This module contains simulated versions of the Maia SDR FPGA IP core and of
+//! the AD9361, which implement the [`FpgaBackend`] and [`RficBackend`] traits
+//! respectively. They allow running maia-httpd on a development PC without any
+//! hardware, either for testing or in simulation mode (see the `--simulate`
+//! command line argument).
+//!
+//! The simulation is not meant to be accurate. The mock IP core keeps its
+//! registers in RAM, and it produces spectra at the rate given by the number
+//! of spectrometer integrations and recordings of the requested length. Both
+//! are generated from a synthetic signal formed by noise and a few moving
+//! carriers. Timing is computed assuming a nominal sample rate of 61.44 Msps,
+//! regardless of the sample rate of the AD9361. The simulation follows the
+//! Tokio clock, so tests can pause it and let it advance automatically.
+
+use crate::fpga::{FpgaBackend, RecordingMemory};
+use crate::iio::RficBackend;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use signal::SignalSource;
+use std::{
+    cell::UnsafeCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+use tokio::time::Instant;
+
+mod signal;
+
+// Nominal sample rate used to compute the timing of the simulation.
+const SAMP_RATE: f64 = 61.44e6;
+// Number of bins of the spectrometer FFT.
+const FFT_SIZE: usize = 4096;
+// Number of buffers in the spectrometer DMA ring. The last_buffer field of the
+// spectrometer register is 3 bits wide.
+const NUM_SPECTROMETER_BUFFERS: usize = 8;
+// Size and physical address of the recording DMA buffer.
+pub(crate) const RECORDING_SIZE: usize = 16 << 20;
+const RECORDING_BASE_ADDRESS: usize = 0x1000_0000;
+// Interval at which the simulation is updated.
+const TICK: Duration = Duration::from_millis(5);
+// Number of spectrometer integrations at reset, which gives approximately 20
+// spectra per second.
+const RESET_NUM_INTEGRATIONS: u32 = 750;
+
+// Register indices (byte offset divided by 4) and fields.
+const PRODUCT_ID: usize = 0;
+const VERSION: usize = 1;
+const INTERRUPTS: usize = 3;
+const RECORDER_CONTROL: usize = 4;
+const RECORDER_NEXT_ADDRESS: usize = 5;
+const RECORDER_START_SAMPLE_LO: usize = 6;
+const RECORDER_START_SAMPLE_HI: usize = 7;
+const SPECTROMETER: usize = 8;
+const SAMPLE_COUNTER_LO: usize = 14;
+const SAMPLE_COUNTER_HI: usize = 15;
+const INTERRUPTS_SPECTROMETER: u32 = 1 << 0;
+const INTERRUPTS_RECORDER: u32 = 1 << 1;
+const RECORDER_CONTROL_START: u32 = 1 << 0;
+const RECORDER_CONTROL_STOP: u32 = 1 << 1;
+const RECORDER_CONTROL_MODE_SHIFT: u32 = 2;
+const RECORDER_CONTROL_ARM_TRIGGER: u32 = 1 << 5;
+const RECORDER_CONTROL_DISARM: u32 = 1 << 6;
+const RECORDER_CONTROL_ARMED: u32 = 1 << 7;
+const RECORDER_CONTROL_TRIGGER_STOP: u32 = 1 << 9;
+const SPECTROMETER_NUM_INTEGRATIONS_SHIFT: u32 = 1;
+const SPECTROMETER_NUM_INTEGRATIONS_MASK: u32 = 0x3ff;
+const SPECTROMETER_ABORT: u32 = 1 << 11;
+const SPECTROMETER_LAST_BUFFER_SHIFT: u32 = 12;
+const SPECTROMETER_LAST_BUFFER_MASK: u32 = 0x7;
+const SPECTROMETER_PEAK_DETECT: u32 = 1 << 15;
+const SAMPLE_COUNTER_HI_SNAPSHOT: u32 = 1 << 16;
+const SAMPLE_COUNTER_HI_PPS_ENABLE: u32 = 1 << 17;
+const SAMPLE_COUNTER_HI_LATCH_COUNT_SHIFT: u32 = 18;
+const SAMPLE_COUNTER_HI_LATCH_COUNT_MASK: u32 = 0xf;
+const SAMPLE_COUNTER_HI_LATCH_PPS: u32 = 1 << 22;
+
+/// Mock FPGA IP core.
+///
+/// This [`FpgaBackend`] simulates the Maia SDR FPGA IP core. The simulation
+/// advances each time that [`FpgaBackend::wait_interrupt`] is called, so the
+/// [`InterruptHandler`](crate::fpga::InterruptHandler) must be running for
+/// the mock IP core to produce spectra and finish recordings.
+#[derive(Debug)]
+pub struct MockFpga {
+    registers: Box<[AtomicU32]>,
+    spectrometer: DmaMemory,
+    recording: Arc<DmaMemory>,
+    simulation: Mutex<Simulation>,
+}
+
+#[derive(Debug)]
+struct Simulation {
+    start: Instant,
+    signal: SignalSource,
+    last_spectrum: Instant,
+    recording_start: Option<Instant>,
+    // Data of the recording in progress, which is written to the recording
+    // buffer in whole 64-bit words as the recording advances
+    recording_data: Vec<u8>,
+    last_pps: SystemTime,
+    rng: XorShift,
+}
+
+// Memory written by the simulated DMA of the IP core.
+//
+// As with the DMA buffers of the real hardware, this memory is written by the
+// IP core while there might be references to it. The IP core only writes
+// regions that the readers are not supposed to be reading (the next buffer of
+// the spectrometer ring, or the part of the recording buffer after the next
+// address of the recorder). The memory is stored as u64 words to guarantee the alignment of
+// the spectrometer buffers.
+struct DmaMemory(Box<[UnsafeCell<u64>]>);
+
+unsafe impl Sync for DmaMemory {}
+
+#[derive(Debug)]
+struct MockRecording(Arc<DmaMemory>);
+
+#[derive(Debug, Clone)]
+struct XorShift(u64);
+
+impl MockFpga {
+    /// Creates a new mock IP core.
+    ///
+    /// The registers of the IP core are set to their reset values.
+    pub fn new() -> MockFpga {
+        let num_registers = std::mem::size_of::<maia_pac::maia_sdr::RegisterBlock>() / 4;
+        let registers = (0..num_registers).map(|_| AtomicU32::new(0)).collect();
+        let mock = MockFpga {
+            registers,
+            spectrometer: DmaMemory::new(NUM_SPECTROMETER_BUFFERS * FFT_SIZE),
+            recording: Arc::new(DmaMemory::new(RECORDING_SIZE / 8)),
+            simulation: Mutex::new(Simulation {
+                start: Instant::now(),
+                signal: SignalSource::new(FFT_SIZE),
+                last_spectrum: Instant::now(),
+                recording_start: None,
+                recording_data: Vec::new(),
+                last_pps: SystemTime::now(),
+                rng: XorShift(0x2545_f491_4f6c_dd1d),
+            }),
+        };
+        mock.register(PRODUCT_ID)
+            .store(u32::from_ne_bytes(*b"maia"), Ordering::Relaxed);
+        // Version 0.7.0 is the first version that has a sample counter.
+        mock.register(VERSION)
+            .store(0x00_00_07_00, Ordering::Relaxed);
+        mock.register(RECORDER_NEXT_ADDRESS)
+            .store(RECORDING_BASE_ADDRESS as u32, Ordering::Relaxed);
+        mock.register(SPECTROMETER).store(
+            RESET_NUM_INTEGRATIONS << SPECTROMETER_NUM_INTEGRATIONS_SHIFT,
+            Ordering::Relaxed,
+        );
+        mock
+    }
+
+    fn register(&self, offset: usize) -> &AtomicU32 {
+        &self.registers[offset]
+    }
+
+    // Advances the simulation and returns the value of the interrupts
+    // register.
+    fn step(&self) -> u32 {
+        let mut simulation = self.simulation.lock().unwrap();
+        let now = Instant::now();
+        let mut interrupts = 0;
+        if self.step_spectrometer(&mut simulation, now) {
+            interrupts |= INTERRUPTS_SPECTROMETER;
+        }
+        let pps = Self::step_pps(&mut simulation, now);
+        if self.step_recorder(&mut simulation, now, pps) {
+            interrupts |= INTERRUPTS_RECORDER;
+        }
+        self.step_sample_counter(&simulation, now, pps);
+        interrupts
+    }
+
+    // Simulates the PPS input and the trigger input. Both have a rising edge
+    // at the start of each second of the system clock. This returns the
+    // instant of the rising edge if there has been one since the last step.
+    fn step_pps(simulation: &mut Simulation, now: Instant) -> Option<Instant> {
+        let system_now = SystemTime::now();
+        let since_second = Duration::from_nanos(u64::from(
+            system_now
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .subsec_nanos(),
+        ));
+        let second = system_now - since_second;
+        if second <= simulation.last_pps {
+            return None;
+        }
+        simulation.last_pps = second;
+        Some(now.checked_sub(since_second).unwrap_or(now))
+    }
+
+    // Value of the sample counter at a given instant.
+    fn sample_counter(simulation: &Simulation, instant: Instant) -> u64 {
+        (instant
+            .saturating_duration_since(simulation.start)
+            .as_secs_f64()
+            * SAMP_RATE) as u64
+    }
+
+    fn step_sample_counter(&self, simulation: &Simulation, now: Instant, pps: Option<Instant>) {
+        let control = self
+            .register(SAMPLE_COUNTER_HI)
+            .fetch_and(!SAMPLE_COUNTER_HI_SNAPSHOT, Ordering::Relaxed);
+        let pps = pps.filter(|_| control & SAMPLE_COUNTER_HI_PPS_ENABLE != 0);
+        let (counter, from_pps) = if let Some(edge) = pps {
+            (Self::sample_counter(simulation, edge), true)
+        } else if control & SAMPLE_COUNTER_HI_SNAPSHOT != 0 {
+            (Self::sample_counter(simulation, now), false)
+        } else {
+            return;
+        };
+        let count = ((control >> SAMPLE_COUNTER_HI_LATCH_COUNT_SHIFT) + 1)
+            & SAMPLE_COUNTER_HI_LATCH_COUNT_MASK;
+        let latch_pps = if from_pps {
+            SAMPLE_COUNTER_HI_LATCH_PPS
+        } else {
+            0
+        };
+        self.register(SAMPLE_COUNTER_LO)
+            .store(counter as u32, Ordering::Relaxed);
+        self.register(SAMPLE_COUNTER_HI).store(
+            (control & SAMPLE_COUNTER_HI_PPS_ENABLE)
+                | (count << SAMPLE_COUNTER_HI_LATCH_COUNT_SHIFT)
+                | latch_pps
+                | (counter >> 32) as u32,
+            Ordering::Relaxed,
+        );
+    }
+
+    fn step_spectrometer(&self, simulation: &mut Simulation, now: Instant) -> bool {
+        let spectrometer = self
+            .register(SPECTROMETER)
+            .fetch_and(!SPECTROMETER_ABORT, Ordering::Relaxed);
+        let num_integrations = ((spectrometer >> SPECTROMETER_NUM_INTEGRATIONS_SHIFT)
+            & SPECTROMETER_NUM_INTEGRATIONS_MASK)
+            .max(1);
+        let peak_detect = spectrometer & SPECTROMETER_PEAK_DETECT != 0;
+        let period =
+            Duration::from_secs_f64(f64::from(num_integrations) * FFT_SIZE as f64 / SAMP_RATE);
+        let elapsed = now.saturating_duration_since(simulation.last_spectrum);
+        let num_new = if spectrometer & SPECTROMETER_ABORT != 0 {
+            // The current integration is finished early.
+            1
+        } else {
+            (elapsed.as_secs_f64() / period.as_secs_f64()) as u32
+        };
+        if num_new == 0 {
+            return false;
+        }
+        simulation.last_spectrum = now;
+        let t = now
+            .saturating_duration_since(simulation.start)
+            .as_secs_f64();
+        let mut last_buffer =
+            (spectrometer >> SPECTROMETER_LAST_BUFFER_SHIFT) & SPECTROMETER_LAST_BUFFER_MASK;
+        // If the software is lagging behind, the older spectra are
+        // overwritten, so there is no point in producing more spectra than
+        // buffers in the ring.
+        for _ in 0..num_new.min(NUM_SPECTROMETER_BUFFERS as u32 - 1) {
+            last_buffer = (last_buffer + 1) & SPECTROMETER_LAST_BUFFER_MASK;
+            self.write_spectrum(
+                simulation,
+                t,
+                last_buffer as usize,
+                num_integrations,
+                peak_detect,
+            );
+        }
+        let _ =
+            self.register(SPECTROMETER)
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                    Some(
+                        (x & !(SPECTROMETER_LAST_BUFFER_MASK << SPECTROMETER_LAST_BUFFER_SHIFT))
+                            | (last_buffer << SPECTROMETER_LAST_BUFFER_SHIFT),
+                    )
+                });
+        true
+    }
+
+    fn write_spectrum(
+        &self,
+        simulation: &mut Simulation,
+        t: f64,
+        num_buffer: usize,
+        num_integrations: u32,
+        peak_detect: bool,
+    ) {
+        // Power values are scaled so that they give the same levels in dB as
+        // the real hardware, which are around 40 dB for the noise floor.
+        let scale = if peak_detect {
+            1.0
+        } else {
+            f64::from(num_integrations)
+        } * SAMP_RATE
+            / 4e6;
+        let noise_floor = 1e4;
+        let noise_std = if peak_detect {
+            0.5
+        } else {
+            1.0 / f64::from(num_integrations).sqrt()
+        };
+        let noise_mean = if peak_detect {
+            1.0 + f64::from(num_integrations).ln()
+        } else {
+            1.0
+        };
+        let rng = &mut simulation.rng;
+        let mut spectrum = (0..FFT_SIZE)
+            .map(|_| (noise_mean + noise_std * rng.gaussian()).max(0.01))
+            .collect::<Vec<f64>>();
+        simulation.signal.add_to_spectrum(t, &mut spectrum);
+        // The values are below 2^56, so the exponent is zero.
+        let spectrum = spectrum
+            .into_iter()
+            .map(|x| (noise_floor * x * scale) as u64);
+        unsafe {
+            self.spectrometer.write(num_buffer * FFT_SIZE, spectrum);
+        }
+    }
+
+    fn step_recorder(
+        &self,
+        simulation: &mut Simulation,
+        now: Instant,
+        trigger: Option<Instant>,
+    ) -> bool {
+        let control = self.register(RECORDER_CONTROL).fetch_and(
+            !(RECORDER_CONTROL_START
+                | RECORDER_CONTROL_STOP
+                | RECORDER_CONTROL_ARM_TRIGGER
+                | RECORDER_CONTROL_DISARM),
+            Ordering::Relaxed,
+        );
+        let mut armed = control & RECORDER_CONTROL_ARMED != 0;
+        if control & RECORDER_CONTROL_ARM_TRIGGER != 0 {
+            armed = true;
+        }
+        if control & RECORDER_CONTROL_DISARM != 0 {
+            armed = false;
+        }
+        // The trigger stops a recording that was already running.
+        let trigger_stop = control & RECORDER_CONTROL_TRIGGER_STOP != 0
+            && trigger.is_some()
+            && simulation.recording_start.is_some();
+        let mut start = (control & RECORDER_CONTROL_START != 0).then_some(now);
+        if let Some(edge) = trigger.filter(|_| armed) {
+            armed = false;
+            start = Some(edge);
+        }
+        if armed {
+            self.register(RECORDER_CONTROL)
+                .fetch_or(RECORDER_CONTROL_ARMED, Ordering::Relaxed);
+        } else {
+            self.register(RECORDER_CONTROL)
+                .fetch_and(!RECORDER_CONTROL_ARMED, Ordering::Relaxed);
+        }
+        if let (Some(start), None) = (start, simulation.recording_start) {
+            simulation.recording_start = Some(start);
+            simulation.recording_data.clear();
+            self.register(RECORDER_NEXT_ADDRESS)
+                .store(RECORDING_BASE_ADDRESS as u32, Ordering::Relaxed);
+            let start_sample = Self::sample_counter(simulation, start);
+            self.register(RECORDER_START_SAMPLE_LO)
+                .store(start_sample as u32, Ordering::Relaxed);
+            self.register(RECORDER_START_SAMPLE_HI)
+                .store((start_sample >> 32) as u32, Ordering::Relaxed);
+        }
+        let Some(start) = simulation.recording_start else {
+            return false;
+        };
+        let mode = (control >> RECORDER_CONTROL_MODE_SHIFT) & 0x3;
+        let bytes_per_item = match mode {
+            0 => 4,
+            1 => 3,
+            _ => 2,
+        };
+        let max_items = RECORDING_SIZE / bytes_per_item;
+        let items = ((now.saturating_duration_since(start).as_secs_f64() * SAMP_RATE) as usize)
+            .min(max_items);
+        let written_items = simulation.recording_data.len() / bytes_per_item;
+        if items > written_items {
+            let t = (start + Duration::from_secs_f64(written_items as f64 / SAMP_RATE))
+                .saturating_duration_since(simulation.start)
+                .as_secs_f64();
+            let Simulation {
+                signal,
+                rng,
+                recording_data,
+                ..
+            } = simulation;
+            for (i, q) in signal.iq_samples(t, items - written_items, rng) {
+                pack_sample(recording_data, mode, i, q);
+            }
+        }
+        let finished = control & RECORDER_CONTROL_STOP != 0 || trigger_stop || items >= max_items;
+        // While the recording is in progress, only whole words are written to
+        // the recording buffer. The last word is padded with zeros when the
+        // recording finishes.
+        let data = &simulation.recording_data;
+        let size = if finished {
+            data.len()
+        } else {
+            data.len() / 8 * 8
+        };
+        let next_address = self.register(RECORDER_NEXT_ADDRESS).load(Ordering::Relaxed) as usize;
+        let first_word = (next_address - RECORDING_BASE_ADDRESS) / 8;
+        unsafe {
+            self.recording.write(
+                first_word,
+                (first_word..size.div_ceil(8)).map(|word| {
+                    let mut bytes = [0; 8];
+                    let chunk = &data[8 * word..(8 * word + 8).min(data.len())];
+                    bytes[..chunk.len()].copy_from_slice(chunk);
+                    u64::from_ne_bytes(bytes)
+                }),
+            );
+        }
+        self.register(RECORDER_NEXT_ADDRESS)
+            .store((RECORDING_BASE_ADDRESS + size) as u32, Ordering::Relaxed);
+        if !finished {
+            return false;
+        }
+        simulation.recording_start = None;
+        simulation.recording_data = Vec::new();
+        true
+    }
+}
+
+// Quantizes an IQ sample (relative to full scale) and appends it to the
+// recording data in the format used by the recorder mode.
+fn pack_sample(data: &mut Vec<u8>, mode: u32, i: f64, q: f64) {
+    let quantize = |x: f64, bits: u32| {
+        let scale = f64::from(1 << (bits - 1));
+        (x * scale).round().clamp(-scale, scale - 1.0) as i16
+    };
+    match mode {
+        // 16-bit mode: 12-bit ADC samples placed on the 12 MSBs
+        0 => {
+            data.extend_from_slice(&(quantize(i, 12) << 4).to_le_bytes());
+            data.extend_from_slice(&(quantize(q, 12) << 4).to_le_bytes());
+        }
+        // 12-bit mode: packed in 3 bytes
+        1 => {
+            let (i, q) = (quantize(i, 12) as u16, quantize(q, 12) as u16);
+            data.push((i >> 4) as u8);
+            data.push((((i & 0xf) << 4) | ((q >> 8) & 0xf)) as u8);
+            data.push(q as u8);
+        }
+        // 8-bit mode: 8 MSBs of the samples
+        _ => {
+            data.push(quantize(i, 8) as u8);
+            data.push(quantize(q, 8) as u8);
+        }
+    }
+}
+
+impl Default for MockFpga {
+    fn default() -> MockFpga {
+        MockFpga::new()
+    }
+}
+
+impl FpgaBackend for MockFpga {
+    fn registers(&self) -> *mut libc::c_void {
+        self.registers.as_ptr() as *mut libc::c_void
+    }
+
+    fn phys_addr(&self) -> usize {
+        0
+    }
+
+    fn spectrometer_num_buffers(&self) -> usize {
+        NUM_SPECTROMETER_BUFFERS
+    }
+
+    fn spectrometer_buffer(&self, num_buffer: usize) -> &[u8] {
+        assert!(num_buffer < NUM_SPECTROMETER_BUFFERS);
+        let buffer_size = FFT_SIZE * std::mem::size_of::<u64>();
+        &self.spectrometer.as_slice()[num_buffer * buffer_size..(num_buffer + 1) * buffer_size]
+    }
+
+    fn spectrometer_cache_invalidate(&self, num_buffer: usize) -> Result<()> {
+        assert!(num_buffer < NUM_SPECTROMETER_BUFFERS);
+        Ok(())
+    }
+
+    fn recording_buffer(&self) -> BoxFuture<'_, Result<Box<dyn RecordingMemory>>> {
+        let recording = MockRecording(Arc::clone(&self.recording));
+        Box::pin(async move { Ok(Box::new(recording) as Box<dyn RecordingMemory>) })
+    }
+
+    fn recording_base_address(&self) -> BoxFuture<'_, Result<usize>> {
+        Box::pin(async move { Ok(RECORDING_BASE_ADDRESS) })
+    }
+
+    fn wait_interrupt(&self) -> BoxFuture<'_, Result<()>> {
+        Box::pin(async move {
+            // The interrupts register is cleared when it is read by the
+            // interrupt handler.
+            self.register(INTERRUPTS).store(0, Ordering::Relaxed);
+            loop {
+                tokio::time::sleep(TICK).await;
+                let interrupts = self.step();
+                if interrupts != 0 {
+                    self.register(INTERRUPTS)
+                        .store(interrupts, Ordering::Relaxed);
+                    return Ok(());
+                }
+            }
+        })
+    }
+}
+
+impl DmaMemory {
+    fn new(num_words: usize) -> DmaMemory {
+        DmaMemory((0..num_words).map(|_| UnsafeCell::new(0)).collect())
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(
+                self.0.as_ptr() as *const u8,
+                self.0.len() * std::mem::size_of::<u64>(),
+            )
+        }
+    }
+
+    // Writes words to the memory, starting at the word given by `offset`.
+    //
+    // Safety: the region that is written must not be read concurrently.
+    unsafe fn write(&self, offset: usize, words: impl Iterator<Item = u64>) {
+        let base = UnsafeCell::raw_get(self.0.as_ptr());
+        for (j, word) in words.enumerate() {
+            assert!(offset + j < self.0.len());
+            base.add(offset + j).write_volatile(word);
+        }
+    }
+}
+
+impl std::fmt::Debug for DmaMemory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        f.debug_struct("DmaMemory")
+            .field("size", &(self.0.len() * std::mem::size_of::<u64>()))
+            .finish()
+    }
+}
+
+impl RecordingMemory for MockRecording {
+    fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Approximately Gaussian with zero mean and unit variance (Irwin-Hall
+    // distribution with n = 4).
+    fn gaussian(&mut self) -> f64 {
+        ((0..4).map(|_| self.uniform()).sum::<f64>() - 2.0) * 3.0f64.sqrt()
+    }
+}
+
+/// Mock AD9361.
+///
+/// This [`RficBackend`] simulates the IIO attributes of the AD9361. The
+/// attributes are stored in RAM. Writing an attribute fails if the value is
+/// outside of the range supported by the AD9361.
+#[derive(Debug)]
+pub struct MockRfic {
+    attributes: Mutex<HashMap<String, String>>,
+    debug_attributes: Mutex<HashMap<String, String>>,
+}
+
+impl MockRfic {
+    /// Creates a new mock AD9361.
+    ///
+    /// The attributes are set to some typical values.
+    pub fn new() -> MockRfic {
+        let attributes = [
+            ("in_voltage_sampling_frequency", "61440000"),
+            ("in_voltage_rf_bandwidth", "56000000"),
+            ("out_voltage_rf_bandwidth", "56000000"),
+            ("out_altvoltage0_RX_LO_frequency", "2400000000"),
+            ("out_altvoltage1_TX_LO_frequency", "2450000000"),
+            ("in_voltage0_hardwaregain", "70.000000 dB"),
+            ("out_voltage0_hardwaregain", "-10.000000 dB"),
+            ("in_voltage0_gain_control_mode", "slow_attack"),
+            ("in_voltage0_rssi", "64.250000 dB"),
+        ]
+        .into_iter()
+        .map(|(attribute, value)| (attribute.to_string(), format!("{value}\n")))
+        .collect();
+        // AGC settings used in the ADALM-Pluto device tree
+        let debug_attributes = [
+            ("adi,agc-outer-thresh-high", "5"),
+            ("adi,agc-outer-thresh-high-dec-steps", "2"),
+            ("adi,agc-inner-thresh-high", "10"),
+            ("adi,agc-inner-thresh-high-dec-steps", "1"),
+            ("adi,agc-inner-thresh-low", "12"),
+            ("adi,agc-inner-thresh-low-inc-steps", "1"),
+            ("adi,agc-outer-thresh-low", "18"),
+            ("adi,agc-outer-thresh-low-inc-steps", "2"),
+            ("adi,agc-attack-delay-extra-margin-us", "1"),
+            ("adi,agc-gain-update-interval-us", "1000"),
+            ("adi,fagc-lock-level", "10"),
+            ("adi,fagc-state-wait-time-ns", "260"),
+            ("adi,fagc-allow-agc-gain-increase-enable", "0"),
+            ("adi,fagc-gain-increase-after-gain-lock-enable", "0"),
+            ("adi,fagc-rst-gla-stronger-sig-thresh-above-ll", "10"),
+            ("adi,fagc-rst-gla-engergy-lost-sig-thresh-below-ll", "10"),
+        ]
+        .into_iter()
+        .map(|(attribute, value)| (attribute.to_string(), format!("{value}\n")))
+        .collect();
+        MockRfic {
+            attributes: Mutex::new(attributes),
+            debug_attributes: Mutex::new(debug_attributes),
+        }
+    }
+
+    // Validates and formats the value of an attribute in the same way as the
+    // AD9361 driver.
+    fn format_value(attribute: &str, value: &str) -> Result<String> {
+        let in_range =
+            |min: f64, max: f64| value.parse::<f64>().is_ok_and(|x| (min..=max).contains(&x));
+        let valid = match attribute {
+            "in_voltage_sampling_frequency" => in_range(2_083_333.0, 61_440_000.0),
+            "in_voltage_rf_bandwidth" | "out_voltage_rf_bandwidth" => {
+                in_range(200_000.0, 56_000_000.0)
+            }
+            "out_altvoltage0_RX_LO_frequency" => in_range(70e6, 6e9),
+            "out_altvoltage1_TX_LO_frequency" => in_range(47e6, 6e9),
+            "in_voltage0_hardwaregain" => in_range(-3.0, 71.0),
+            "out_voltage0_hardwaregain" => in_range(-89.75, 0.0),
+            "in_voltage0_gain_control_mode" => {
+                ["manual", "fast_attack", "slow_attack", "hybrid"].contains(&value)
+            }
+            _ => anyhow::bail!("IIO attribute {attribute} does not exist"),
+        };
+        anyhow::ensure!(valid, "invalid value {value} for IIO attribute {attribute}");
+        Ok(if attribute.ends_with("hardwaregain") {
+            format!("{:.6} dB\n", value.parse::<f64>().unwrap())
+        } else {
+            format!("{value}\n")
+        })
+    }
+}
+
+impl Default for MockRfic {
+    fn default() -> MockRfic {
+        MockRfic::new()
+    }
+}
+
+impl RficBackend for MockRfic {
+    fn read_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>> {
+        let value = self.attributes.lock().unwrap().get(attribute).cloned();
+        Box::pin(async move {
+            value.ok_or_else(|| anyhow::anyhow!("IIO attribute {attribute} does not exist"))
+        })
+    }
+
+    fn write_attribute<'a>(
+        &'a self,
+        attribute: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let value = Self::format_value(attribute, &value)?;
+            self.attributes
+                .lock()
+                .unwrap()
+                .insert(attribute.to_string(), value);
+            Ok(())
+        })
+    }
+
+    fn read_debug_attribute<'a>(&'a self, attribute: &'a str) -> BoxFuture<'a, Result<String>> {
+        let value = self
+            .debug_attributes
+            .lock()
+            .unwrap()
+            .get(attribute)
+            .cloned();
+        Box::pin(async move {
+            value.ok_or_else(|| anyhow::anyhow!("debug attribute {attribute} does not exist"))
+        })
+    }
+
+    fn write_debug_attribute<'a>(
+        &'a self,
+        attribute: &'a str,
+        value: String,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            // Writing to initialize resets the AD9361. Here it does nothing.
+            if attribute == "initialize" {
+                return Ok(());
+            }
+            let mut attributes = self.debug_attributes.lock().unwrap();
+            let Some(current) = attributes.get_mut(attribute) else {
+                anyhow::bail!("debug attribute {attribute} does not exist");
+            };
+            anyhow::ensure!(
+                value.parse::<u32>().is_ok(),
+                "invalid value {value} for debug attribute {attribute}"
+            );
+            *current = format!("{value}\n");
+            Ok(())
+        })
+    }
+}
+
+/// Creates an [`AppState`](crate::app::AppState) that uses the mock backends.
+///
+/// The [`InterruptHandler`](crate::fpga::InterruptHandler) of the mock IP core
+/// is also returned. It needs to be run for the spectrometer and recorder to
+/// advance.
+#[cfg(test)]
+pub(crate) async fn app_state() -> (crate::app::AppState, crate::fpga::InterruptHandler) {
+    app_state_with_args(&[]).await
+}
+
+/// Creates an [`AppState`](crate::app::AppState) that uses the mock backends
+/// and the given command line arguments.
+#[cfg(test)]
+pub(crate) async fn app_state_with_args(
+    args: &[&str],
+) -> (crate::app::AppState, crate::fpga::InterruptHandler) {
+    use clap::Parser;
+    let (ip_core, interrupt_handler) = crate::fpga::IpCore::with_backend(Arc::new(MockFpga::new()))
+        .await
+        .unwrap();
+    let ad9361 = crate::iio::Ad9361::with_backend(MockRfic::new());
+    let args = crate::args::Args::parse_from(std::iter::once(&"maia-httpd").chain(args));
+    let device = crate::httpd::DeviceState::new(&args).await.unwrap();
+    let limits = crate::limits::Limits::new(&args).await.unwrap();
+    let state = crate::app::AppState::new(ad9361, ip_core, device, limits)
+        .await
+        .unwrap();
+    (state, interrupt_handler)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{fpga::IpCore, iio::Ad9361};
+    use maia_json::{Decibels, Hertz};
+
+    #[tokio::test]
+    async fn rfic_attributes() {
+        let ad9361 = Ad9361::with_backend(MockRfic::new());
+        assert_eq!(
+            ad9361.get_sampling_frequency().await.unwrap(),
+            Hertz(61_440_000.0)
+        );
+        ad9361
+            .set_rx_lo_frequency(Hertz(100_000_000.0))
+            .await
+            .unwrap();
+        assert_eq!(
+            ad9361.get_rx_lo_frequency().await.unwrap(),
+            Hertz(100_000_000.0)
+        );
+        // out of range
+        assert!(ad9361
+            .set_rx_lo_frequency(Hertz(10_000_000.0))
+            .await
+            .is_err());
+        assert_eq!(
+            ad9361.get_rx_lo_frequency().await.unwrap(),
+            Hertz(100_000_000.0)
+        );
+        ad9361.set_rx_gain(Decibels(20.5)).await.unwrap();
+        assert_eq!(ad9361.get_rx_gain().await.unwrap(), Decibels(20.5));
+        assert!(ad9361.set_tx_gain(Decibels(3.0)).await.is_err());
+    }
+
+    #[test]
+    fn recorder_formats() {
+        let mut data = Vec::new();
+        pack_sample(&mut data, 0, 0.5, -1.0);
+        assert_eq!(data, [0x00, 0x40, 0x00, 0x80]);
+        data.clear();
+        // I = 0x400, Q = 0x800
+        pack_sample(&mut data, 1, 0.5, -1.0);
+        assert_eq!(data, [0x40, 0x08, 0x00]);
+        data.clear();
+        pack_sample(&mut data, 2, 0.5, -1.0);
+        assert_eq!(data, [0x40, 0x80]);
+    }
+
+    #[tokio::test]
+    async fn spectrometer() {
+        let (mut ip_core, interrupt_handler) = IpCore::with_backend(Arc::new(MockFpga::new()))
+            .await
+            .unwrap();
+        ip_core.set_spectrometer_number_integrations(16).unwrap();
+        let waiter = interrupt_handler.waiter_spectrometer();
+        tokio::spawn(interrupt_handler.run());
+        // the first call only records the last buffer
+        assert_eq!(ip_core.get_spectrometer_buffers().count(), 0);
+        tokio::time::timeout(Duration::from_secs(1), waiter.wait())
+            .await
+            .unwrap();
+        let buffers = ip_core.get_spectrometer_buffers().collect::<Vec<_>>();
+        assert!(!buffers.is_empty());
+        for buffer in buffers {
+            assert_eq!(buffer.len(), FFT_SIZE);
+            assert!(buffer.iter().all(|&x| x > 0));
+        }
+    }
+}
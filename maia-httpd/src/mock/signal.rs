@@ -0,0 +1,211 @@
+//! Synthetic signal source.
+//!
+//! This module contains the signal that is "received" by the mock IP
+//! core. The signal is formed by white noise and a few carriers whose
+//! frequency changes with time. The same signal is used to generate the
+//! spectra of the spectrometer and the IQ samples of the recorder, so that
+//! the carriers that are seen in the waterfall are also present in the
+//! recordings.
+
+use super::XorShift;
+use std::f64::consts::PI;
+
+// RMS amplitude of each of the I and Q components of the noise, relative to
+// the ADC full scale.
+const NOISE_RMS: f64 = 0.02;
+
+/// Synthetic signal source.
+///
+/// Frequencies are given as a fraction of the sample rate, in the interval
+/// [-0.5, 0.5). Carrier powers are given as the SNR in a bin of the
+/// spectrometer FFT.
+#[derive(Debug, Clone)]
+pub struct SignalSource {
+    carriers: Vec<Carrier>,
+    fft_size: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Carrier {
+    motion: Motion,
+    // SNR in dB
+    snr: f64,
+    // Period in seconds of on-off keying (the carrier is on during the first
+    // half of the period), or None if the carrier is always on.
+    keying: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+enum Motion {
+    // Fixed frequency.
+    Fixed(f64),
+    // Sinusoidal drift around a center frequency.
+    Drift {
+        center: f64,
+        amplitude: f64,
+        period: f64,
+    },
+    // Linear sweep across the whole band, wrapping around at the edges. The
+    // rate is given in units of the sample rate per second.
+    Sweep {
+        start: f64,
+        rate: f64,
+    },
+}
+
+impl SignalSource {
+    /// Creates the default signal source.
+    ///
+    /// The signal contains a strong carrier that drifts slowly, a weaker
+    /// carrier that sweeps the band, a carrier that is keyed on and off, and a
+    /// weak fixed carrier. The `fft_size` is the size of the spectrometer FFT,
+    /// which is used to convert the carrier SNRs to amplitudes.
+    pub fn new(fft_size: usize) -> SignalSource {
+        SignalSource {
+            carriers: vec![
+                Carrier {
+                    motion: Motion::Drift {
+                        center: -0.2,
+                        amplitude: 0.05,
+                        period: 30.0,
+                    },
+                    snr: 35.0,
+                    keying: None,
+                },
+                Carrier {
+                    motion: Motion::Sweep {
+                        start: 0.0,
+                        rate: 0.02,
+                    },
+                    snr: 20.0,
+                    keying: None,
+                },
+                Carrier {
+                    motion: Motion::Fixed(0.125),
+                    snr: 25.0,
+                    keying: Some(2.0),
+                },
+                Carrier {
+                    motion: Motion::Fixed(0.3),
+                    snr: 10.0,
+                    keying: None,
+                },
+            ],
+            fft_size,
+        }
+    }
+
+    // Returns the frequency and linear SNR of the carriers that are on at
+    // time t (in seconds).
+    fn active_carriers(&self, t: f64) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.carriers.iter().filter_map(move |carrier| {
+            let on = carrier
+                .keying
+                .is_none_or(|period| (t / period).fract() < 0.5);
+            on.then(|| (carrier.motion.frequency(t), 10.0f64.powf(0.1 * carrier.snr)))
+        })
+    }
+
+    /// Adds the carriers to a power spectrum.
+    ///
+    /// The `spectrum` is in FFT-shifted order and in units of the noise
+    /// floor. The carriers that are on at time `t` (in seconds) are added to
+    /// it. The power of each carrier is spread over a few bins to imitate the
+    /// leakage of the FFT window.
+    pub fn add_to_spectrum(&self, t: f64, spectrum: &mut [f64]) {
+        // Width (standard deviation) of the carrier in bins.
+        const WIDTH: f64 = 0.6;
+        let size = spectrum.len() as f64;
+        for (frequency, snr) in self.active_carriers(t) {
+            let bin = (frequency + 0.5) * size;
+            let first = (bin.floor() - 2.0).max(0.0) as usize;
+            let last = ((bin.ceil() + 2.0) as usize).min(spectrum.len() - 1);
+            for (j, x) in spectrum.iter_mut().enumerate().take(last + 1).skip(first) {
+                let d = (j as f64 - bin) / WIDTH;
+                *x += snr * (-0.5 * d * d).exp();
+            }
+        }
+    }
+
+    /// Generates IQ samples.
+    ///
+    /// The samples start at time `t` (in seconds) and are relative to the ADC
+    /// full scale. The frequencies of the carriers are kept constant during
+    /// the samples, since the carriers move slowly in comparison with the
+    /// duration of a recording.
+    pub fn iq_samples<'a>(
+        &self,
+        t: f64,
+        num_samples: usize,
+        rng: &'a mut XorShift,
+    ) -> impl Iterator<Item = (f64, f64)> + 'a {
+        // The SNR in an FFT bin is fft_size times the ratio between the
+        // carrier power and the noise power.
+        let noise_power = 2.0 * NOISE_RMS * NOISE_RMS;
+        let carriers = self
+            .active_carriers(t)
+            .map(|(frequency, snr)| {
+                let amplitude = (snr * noise_power / self.fft_size as f64).sqrt();
+                let phase = 2.0 * PI * rng.uniform();
+                (2.0 * PI * frequency, amplitude, phase)
+            })
+            .collect::<Vec<_>>();
+        (0..num_samples).map(move |n| {
+            let mut i = NOISE_RMS * rng.gaussian();
+            let mut q = NOISE_RMS * rng.gaussian();
+            for &(omega, amplitude, phase) in &carriers {
+                let (sin, cos) = (omega * n as f64 + phase).sin_cos();
+                i += amplitude * cos;
+                q += amplitude * sin;
+            }
+            (i, q)
+        })
+    }
+}
+
+impl Motion {
+    fn frequency(&self, t: f64) -> f64 {
+        match *self {
+            Motion::Fixed(frequency) => frequency,
+            Motion::Drift {
+                center,
+                amplitude,
+                period,
+            } => center + amplitude * (2.0 * PI * t / period).sin(),
+            Motion::Sweep { start, rate } => (start + rate * t + 0.5).rem_euclid(1.0) - 0.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sweep_wraps_around() {
+        let sweep = Motion::Sweep {
+            start: 0.4,
+            rate: 0.1,
+        };
+        assert!((sweep.frequency(0.0) - 0.4).abs() < 1e-9);
+        assert!((sweep.frequency(2.0) + 0.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn carriers_in_spectrum() {
+        let source = SignalSource::new(4096);
+        let mut spectrum = vec![1.0; 4096];
+        source.add_to_spectrum(0.0, &mut spectrum);
+        // The drifting carrier is at -0.2 at t = 0.
+        let bin = (0.3 * 4096.0) as usize;
+        assert!(spectrum[bin] > 1000.0);
+        // Far from any carrier only the noise floor remains.
+        assert_eq!(spectrum[100], 1.0);
+        // The keyed carrier is off during the second half of its period.
+        let keyed = (0.625 * 4096.0) as usize;
+        assert!(spectrum[keyed] > 100.0);
+        let mut spectrum = vec![1.0; 4096];
+        source.add_to_spectrum(1.5, &mut spectrum);
+        assert_eq!(spectrum[keyed], 1.0);
+    }
+}
@@ -0,0 +1,82 @@
+//! Notifications.
+//!
+//! This module implements the notifications that are pushed to the clients of
+//! the `/waterfall` WebSocket that use the
+//! [`WATERFALL_PROTOCOL_V4`](maia_json::WATERFALL_PROTOCOL_V4) subprotocol,
+//! such as the configuration changes made through the REST API and the alerts.
+
+use std::time::UNIX_EPOCH;
+use tokio::sync::broadcast;
+
+// Number of notifications that are buffered for each client.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// Notification.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    /// Configuration change.
+    ConfigChange(maia_json::WaterfallConfigChange),
+    /// Alert.
+    Alert(maia_json::WaterfallAlert),
+}
+
+/// Notifications channel.
+///
+/// This struct is part of the [`AppState`](crate::app::AppState). It is used
+/// to send notifications to all the WebSocket clients that have subscribed to
+/// them.
+#[derive(Debug)]
+pub struct Notifications(broadcast::Sender<Notification>);
+
+impl Notifications {
+    /// Creates a new notifications channel.
+    pub fn new() -> Notifications {
+        Notifications(broadcast::channel(CHANNEL_CAPACITY).0)
+    }
+
+    /// Subscribes to the notifications.
+    ///
+    /// The returned receiver obtains the notifications sent after this call.
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.0.subscribe()
+    }
+
+    /// Returns the number of clients that have subscribed to the
+    /// notifications.
+    ///
+    /// Each client of the `/waterfall` WebSocket subscribes to the
+    /// notifications, so this is the number of connected waterfall clients.
+    pub fn clients(&self) -> usize {
+        self.0.receiver_count()
+    }
+
+    /// Notifies that the REST API resource in `path` has been modified.
+    pub fn config_change(&self, path: &str) {
+        self.send(Notification::ConfigChange(
+            maia_json::WaterfallConfigChange {
+                path: path.to_string(),
+            },
+        ));
+    }
+
+    /// Sends an alert with a message.
+    pub fn alert(&self, message: String) {
+        self.send(Notification::Alert(maia_json::WaterfallAlert {
+            time: UNIX_EPOCH
+                .elapsed()
+                .map_or(0.0, |time| time.as_secs_f64() * 1e3),
+            message,
+        }));
+    }
+
+    fn send(&self, notification: Notification) {
+        // It is ok if send returns Err, because there might be no subscribers
+        let _ = self.0.send(notification);
+    }
+}
+
+impl Default for Notifications {
+    fn default() -> Notifications {
+        Notifications::new()
+    }
+}
@@ -0,0 +1,452 @@
+//! Spectrometer peak detection.
+//!
+//! This module implements a detector that extracts peaks from the spectra
+//! produced by the spectrometer. In each spectrum, the peaks are found as
+//! groups of adjacent bins whose power is above the noise floor estimated by
+//! the spectrometer by at least a threshold (a simple form of blob
+//! detection). The peaks are tracked across
+//! consecutive spectra by matching peaks whose frequency ranges overlap, so
+//! that each peak represents a discrete signal event with a frequency, a
+//! bandwidth estimate, a power and a duration.
+
+use crate::{
+    app::AppState,
+    spectrometer::{median, spectrum_from_bytes, SpectrumLine},
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use maia_json::{Decibels, Hertz, Seconds, SpectrometerPeak, SpectrometerPeakEvent};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
+
+// Default detection threshold, in dB above the noise floor.
+const DEFAULT_THRESHOLD: f64 = 10.0;
+// Number of consecutive spectra in which a peak can be missing before it is
+// considered to have ended. This prevents fading signals from being split into
+// many peaks.
+const MAX_MISSES: u32 = 2;
+// Maximum number of active peaks. When the threshold is too low, noise can
+// produce many peaks, so the weakest are discarded.
+const MAX_ACTIVE: usize = 64;
+// Number of recent peaks that are kept.
+const RECENT_LEN: usize = 64;
+// Capacity of the peak events channel.
+const EVENTS_CAPACITY: usize = 256;
+
+/// Spectrometer peaks.
+///
+/// This struct contains the peaks detected in the spectra produced by the
+/// spectrometer, and the detection threshold. It is updated by the
+/// [`PeakDetector`], and it sends the start and end of each peak as
+/// [`SpectrometerPeakEvent`]'s through a broadcast channel.
+#[derive(Debug)]
+pub struct Peaks {
+    inner: Mutex<Inner>,
+    events: broadcast::Sender<SpectrometerPeakEvent>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    threshold: f64,
+    tracker: Tracker,
+}
+
+impl Default for Peaks {
+    fn default() -> Peaks {
+        Peaks::new()
+    }
+}
+
+impl Peaks {
+    /// Creates a new peaks object without any peaks.
+    pub fn new() -> Peaks {
+        Peaks {
+            inner: Mutex::new(Inner {
+                threshold: DEFAULT_THRESHOLD,
+                tracker: Tracker::default(),
+            }),
+            events: broadcast::channel(EVENTS_CAPACITY).0,
+        }
+    }
+
+    /// Returns the JSON representation of the peaks.
+    pub fn json(&self) -> maia_json::SpectrometerPeaks {
+        let inner = self.inner.lock().unwrap();
+        maia_json::SpectrometerPeaks {
+            threshold: Decibels(inner.threshold),
+            active: inner
+                .tracker
+                .active
+                .iter()
+                .map(|active| active.peak.clone())
+                .collect(),
+            recent: inner.tracker.recent.iter().cloned().collect(),
+        }
+    }
+
+    /// Sets the detection threshold.
+    ///
+    /// The threshold is given in dB above the noise floor, and it must be
+    /// positive.
+    pub fn set_threshold(&self, threshold: f64) -> Result<()> {
+        anyhow::ensure!(
+            threshold.is_finite() && threshold > 0.0,
+            "peak detection threshold must be positive"
+        );
+        self.inner.lock().unwrap().threshold = threshold;
+        Ok(())
+    }
+
+    /// Subscribes to the peak events.
+    pub fn subscribe(&self) -> broadcast::Receiver<SpectrometerPeakEvent> {
+        self.events.subscribe()
+    }
+
+    // Updates the peaks with a new spectrum, sending the events that it
+    // produces.
+    fn update(
+        &self,
+        spectrum: &[f32],
+        noise_floor: Option<f64>,
+        center_frequency: f64,
+        samp_rate: f64,
+        time: DateTime<Utc>,
+    ) {
+        let events = {
+            let mut inner = self.inner.lock().unwrap();
+            let blobs = find_blobs(
+                spectrum,
+                noise_floor,
+                center_frequency,
+                samp_rate,
+                inner.threshold,
+            );
+            inner.tracker.update(blobs, time)
+        };
+        for event in events {
+            // It is ok if send returns Err, because there might be no
+            // receiver handles in this moment.
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+/// Peak detector.
+///
+/// The peak detector receives the spectra produced by the spectrometer and
+/// updates the [`Peaks`] of the application. It implements a
+/// [`run`](PeakDetector::run) async method that should be run concurrently
+/// with the rest of the application.
+#[derive(Debug)]
+pub struct PeakDetector {
+    state: AppState,
+    receiver: broadcast::Receiver<SpectrumLine>,
+}
+
+impl PeakDetector {
+    /// Creates a new peak detector.
+    ///
+    /// The `receiver` should receive the spectra sent by the
+    /// [`Spectrometer`](crate::spectrometer::Spectrometer).
+    pub fn new(state: AppState, receiver: broadcast::Receiver<SpectrumLine>) -> PeakDetector {
+        PeakDetector { state, receiver }
+    }
+
+    /// Runs the peak detector.
+    ///
+    /// This function only returns if there is an error.
+    #[tracing::instrument(name = "peaks", skip_all)]
+    pub async fn run(mut self) -> Result<()> {
+        loop {
+            let line = match self.receiver.recv().await {
+                Ok(line) => line,
+                Err(broadcast::error::RecvError::Lagged(lagged)) => {
+                    tracing::debug!("peak detector lagged {} spectra", lagged);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+            let lo_frequency = self
+                .state
+                .ad9361()
+                .lock()
+                .await
+                .get_rx_lo_frequency()
+                .await?
+                .0;
+            let center_frequency = lo_frequency
+                + self
+                    .state
+                    .ip_core()
+                    .lock()
+                    .unwrap()
+                    .spectrometer_input_frequency_offset()
+                    .0;
+            let samp_rate = f64::from(self.state.spectrometer_config().samp_rate());
+            let spectrum = spectrum_from_bytes(&line.spectrum);
+            self.state.peaks().update(
+                &spectrum,
+                line.noise_floor,
+                center_frequency,
+                samp_rate,
+                line.timestamp,
+            );
+        }
+    }
+}
+
+// Group of adjacent bins above the threshold in a spectrum.
+#[derive(Debug, Clone, PartialEq)]
+struct Blob {
+    // Frequencies of the lower and upper edges of the blob
+    low: f64,
+    high: f64,
+    // Power-weighted centroid
+    frequency: f64,
+    // Maximum power of the bins (in dB)
+    power: f64,
+    snr: f64,
+}
+
+// Finds the blobs in a spectrum. The spectrum contains linear power values in
+// FFT-shifted order. The noise floor is given in dB. If it is not available,
+// the median of the spectrum is used.
+fn find_blobs(
+    spectrum: &[f32],
+    noise_floor: Option<f64>,
+    center_frequency: f64,
+    samp_rate: f64,
+    threshold: f64,
+) -> Vec<Blob> {
+    let n = spectrum.len();
+    if n == 0 || samp_rate <= 0.0 {
+        return Vec::new();
+    }
+    let bin_width = samp_rate / n as f64;
+    let bin_frequency = |j: usize| center_frequency + (j as f64 - (n / 2) as f64) * bin_width;
+    let to_db = |power: f32| 10.0 * f64::from(power).log10();
+    let noise_floor = noise_floor.unwrap_or_else(|| to_db(median(&mut spectrum.to_vec())));
+    let above = |j: usize| to_db(spectrum[j]) > noise_floor + threshold;
+    let mut blobs = Vec::new();
+    let mut j = 0;
+    while j < n {
+        if !above(j) {
+            j += 1;
+            continue;
+        }
+        let start = j;
+        while j < n && above(j) {
+            j += 1;
+        }
+        let bins = start..j;
+        let total = bins.clone().map(|k| f64::from(spectrum[k])).sum::<f64>();
+        let frequency = bins
+            .clone()
+            .map(|k| bin_frequency(k) * f64::from(spectrum[k]))
+            .sum::<f64>()
+            / total;
+        let power = to_db(spectrum[bins.clone()].iter().copied().fold(0.0, f32::max));
+        blobs.push(Blob {
+            low: bin_frequency(start) - 0.5 * bin_width,
+            high: bin_frequency(j - 1) + 0.5 * bin_width,
+            frequency,
+            power,
+            snr: power - noise_floor,
+        });
+    }
+    blobs
+}
+
+// Tracker of the peaks across consecutive spectra.
+#[derive(Debug, Default)]
+struct Tracker {
+    next_id: u64,
+    active: Vec<ActivePeak>,
+    recent: VecDeque<SpectrometerPeak>,
+    last_time: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+struct ActivePeak {
+    peak: SpectrometerPeak,
+    // Frequency range of the peak in the last spectrum in which it was
+    // present
+    low: f64,
+    high: f64,
+    misses: u32,
+}
+
+impl Tracker {
+    // Updates the tracker with the blobs found in the spectrum that started
+    // at `time`. Returns the peak events.
+    fn update(&mut self, mut blobs: Vec<Blob>, time: DateTime<Utc>) -> Vec<SpectrometerPeakEvent> {
+        // The duration of each spectrum is estimated as the time between
+        // consecutive spectra.
+        let spectrum_duration = self
+            .last_time
+            .map(|last| timestamp_millis(time) - timestamp_millis(last))
+            .filter(|&d| d > 0.0)
+            .unwrap_or(0.0);
+        self.last_time = Some(time);
+        let now = timestamp_millis(time) + spectrum_duration;
+        // Match the strongest blobs first, and discard the weakest ones if there
+        // are too many.
+        blobs.sort_by(|a, b| b.power.total_cmp(&a.power));
+        blobs.truncate(MAX_ACTIVE);
+        let mut matched = vec![false; self.active.len()];
+        let mut events = Vec::new();
+        for blob in blobs {
+            let existing = self.active.iter().enumerate().position(|(j, active)| {
+                !matched[j] && blob.low < active.high && active.low < blob.high
+            });
+            match existing {
+                Some(j) => {
+                    matched[j] = true;
+                    let active = &mut self.active[j];
+                    active.low = blob.low;
+                    active.high = blob.high;
+                    active.misses = 0;
+                    let peak = &mut active.peak;
+                    peak.bandwidth = Hertz(peak.bandwidth.0.max(blob.high - blob.low));
+                    peak.duration = Seconds((now - peak.start_time) * 1e-3);
+                    if blob.power > peak.power.0 {
+                        peak.frequency = Hertz(blob.frequency);
+                        peak.power = Decibels(blob.power);
+                        peak.snr = Decibels(blob.snr);
+                    }
+                }
+                None if self.active.len() < MAX_ACTIVE => {
+                    let peak = SpectrometerPeak {
+                        id: self.next_id,
+                        frequency: Hertz(blob.frequency),
+                        bandwidth: Hertz(blob.high - blob.low),
+                        power: Decibels(blob.power),
+                        snr: Decibels(blob.snr),
+                        start_time: timestamp_millis(time),
+                        duration: Seconds(spectrum_duration * 1e-3),
+                    };
+                    self.next_id += 1;
+                    events.push(SpectrometerPeakEvent::Start(peak.clone()));
+                    self.active.push(ActivePeak {
+                        peak,
+                        low: blob.low,
+                        high: blob.high,
+                        misses: 0,
+                    });
+                    matched.push(true);
+                }
+                None => {}
+            }
+        }
+        let mut j = 0;
+        while j < self.active.len() {
+            if matched[j] {
+                j += 1;
+                continue;
+            }
+            self.active[j].misses += 1;
+            if self.active[j].misses <= MAX_MISSES {
+                j += 1;
+                continue;
+            }
+            matched.remove(j);
+            let peak = self.active.remove(j).peak;
+            if self.recent.len() == RECENT_LEN {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(peak.clone());
+            events.push(SpectrometerPeakEvent::End(peak));
+        }
+        events
+    }
+}
+
+// Returns a time as the number of milliseconds since the UNIX epoch.
+fn timestamp_millis(time: DateTime<Utc>) -> f64 {
+    time.timestamp_micros() as f64 * 1e-3
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Returns a spectrum with a flat noise floor of 1 and the given signals,
+    // each of which is given by its first bin, its number of bins and its
+    // power.
+    fn spectrum(signals: &[(usize, usize, f32)]) -> Vec<f32> {
+        let mut spectrum = vec![1.0; 64];
+        for &(start, len, power) in signals {
+            spectrum[start..start + len].fill(power);
+        }
+        spectrum
+    }
+
+    #[test]
+    fn blobs() {
+        let blobs = find_blobs(
+            &spectrum(&[(10, 1, 100.0), (40, 4, 1000.0)]),
+            None,
+            1e6,
+            64e3,
+            10.0,
+        );
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(blobs[0].frequency, 1e6 - 22e3);
+        assert_eq!(blobs[0].high - blobs[0].low, 1e3);
+        assert!((blobs[0].snr - 20.0).abs() < 1e-9);
+        assert_eq!(blobs[1].frequency, 1e6 + 9.5e3);
+        assert_eq!(blobs[1].high - blobs[1].low, 4e3);
+        assert!((blobs[1].power - 30.0).abs() < 1e-9);
+        // The signal at bin 10 is below the threshold above the noise floor
+        // given by the spectrometer
+        assert_eq!(
+            find_blobs(&spectrum(&[(10, 1, 100.0)]), Some(15.0), 1e6, 64e3, 10.0),
+            []
+        );
+        // The signal at bin 10 is below the threshold
+        assert_eq!(
+            find_blobs(&spectrum(&[(10, 1, 100.0)]), None, 1e6, 64e3, 25.0),
+            []
+        );
+    }
+
+    #[test]
+    fn tracking() {
+        let mut tracker = Tracker::default();
+        let t0 = DateTime::from_timestamp(1704110400, 0).unwrap();
+        let update = |tracker: &mut Tracker, j: i64, signals: &[(usize, usize, f32)]| {
+            let blobs = find_blobs(&spectrum(signals), None, 0.0, 64e3, 10.0);
+            tracker.update(blobs, t0 + chrono::Duration::milliseconds(100 * j))
+        };
+        let events = update(&mut tracker, 0, &[(10, 2, 100.0)]);
+        let [SpectrometerPeakEvent::Start(peak)] = &events[..] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert_eq!(peak.id, 0);
+        assert_eq!(peak.start_time, 1704110400000.0);
+        // The peak drifts and widens, and a second peak starts
+        assert_eq!(update(&mut tracker, 1, &[(11, 3, 1000.0)]).len(), 0);
+        let events = update(&mut tracker, 2, &[(11, 3, 1000.0), (50, 1, 100.0)]);
+        assert!(matches!(&events[..], [SpectrometerPeakEvent::Start(p)] if p.id == 1));
+        // The first peak fades for less than MAX_MISSES spectra
+        assert_eq!(update(&mut tracker, 3, &[(50, 1, 100.0)]).len(), 0);
+        assert_eq!(
+            update(&mut tracker, 4, &[(12, 1, 100.0), (50, 1, 100.0)]).len(),
+            0
+        );
+        assert_eq!(tracker.active.len(), 2);
+        let events = (5..=7)
+            .flat_map(|j| update(&mut tracker, j, &[(50, 1, 100.0)]))
+            .collect::<Vec<_>>();
+        let [SpectrometerPeakEvent::End(peak)] = &events[..] else {
+            panic!("unexpected events {events:?}");
+        };
+        assert_eq!(peak.id, 0);
+        assert_eq!(peak.bandwidth, Hertz(3e3));
+        assert!((peak.power - Decibels(30.0)).abs() < Decibels(1e-9));
+        assert!((peak.duration - Seconds(0.5)).abs() < Seconds(1e-9));
+        assert_eq!(tracker.recent.len(), 1);
+        assert_eq!(tracker.active.len(), 1);
+    }
+}
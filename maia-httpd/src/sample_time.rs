@@ -0,0 +1,245 @@
+//! Sample time.
+//!
+//! This module maps the sample counter of the FPGA IP core to UTC. The sample
+//! counter counts the samples produced by the AD9361, so a mapping between a
+//! value of the sample counter and UTC allows to time-stamp each sample of a
+//! recording. If several devices share a sample clock or a PPS signal, their
+//! recordings can be aligned to within a sample.
+
+use crate::app::AppState;
+use anyhow::Result;
+use chrono::prelude::*;
+use std::time::Duration;
+
+// Period with which the sample counter is latched when the PPS input is not
+// used.
+const SNAPSHOT_PERIOD: Duration = Duration::from_secs(1);
+// Period with which the latched sample counter is polled when the PPS input is
+// used.
+const PPS_POLL_PERIOD: Duration = Duration::from_millis(100);
+// Maximum number of attempts to read a new latched value after a snapshot, and
+// period between attempts.
+const SNAPSHOT_MAX_READS: usize = 100;
+const SNAPSHOT_RETRY_PERIOD: Duration = Duration::from_millis(1);
+
+/// Sample time reference.
+///
+/// A sample time reference is a pair formed by a value of the sample counter
+/// and the UTC time at which the sample counter had this value.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct SampleTimeReference {
+    /// Value of the sample counter.
+    pub sample_counter: u64,
+    /// UTC time corresponding to the value of the sample counter.
+    pub datetime: DateTime<Utc>,
+    /// Source of the time reference.
+    pub source: TimeSource,
+}
+
+/// Source of a sample time reference.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TimeSource {
+    /// The sample counter was latched on the rising edge of the PPS input.
+    ///
+    /// The accuracy of the reference is given by the accuracy of the PPS
+    /// signal.
+    Pps,
+    /// The sample counter was latched by software and the time was obtained
+    /// from the system clock.
+    ///
+    /// The accuracy of the reference is limited by the accuracy of the system
+    /// clock and by the latency of the register accesses.
+    SystemClock,
+}
+
+impl std::fmt::Display for TimeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                TimeSource::Pps => "pps",
+                TimeSource::SystemClock => "system_clock",
+            }
+        )
+    }
+}
+
+impl SampleTimeReference {
+    /// Gives the UTC time corresponding to a value of the sample counter.
+    ///
+    /// The `sample_rate` is the rate at which the sample counter increments,
+    /// which is the AD9361 sample rate.
+    pub fn datetime_at(&self, sample_counter: u64, sample_rate: f64) -> DateTime<Utc> {
+        let samples = sample_counter as i128 - self.sample_counter as i128;
+        let nanoseconds = (samples as f64 * 1e9 / sample_rate).round() as i64;
+        self.datetime + chrono::Duration::nanoseconds(nanoseconds)
+    }
+}
+
+/// Sample time tracker.
+///
+/// This struct latches the sample counter of the FPGA IP core periodically, or
+/// on each PPS, and updates the sample time reference stored in the
+/// [`AppState`]. It implements a [`run`](SampleTimeTracker::run) async method
+/// that should be run concurrently with the rest of the application.
+#[derive(Debug)]
+pub struct SampleTimeTracker {
+    state: AppState,
+    pps: bool,
+}
+
+impl SampleTimeTracker {
+    /// Creates a new sample time tracker.
+    ///
+    /// If `pps` is `true`, the sample counter is latched by the PPS input of
+    /// the IP core. Otherwise, it is latched by software and mapped to the
+    /// system clock.
+    pub fn new(state: AppState, pps: bool) -> SampleTimeTracker {
+        SampleTimeTracker { state, pps }
+    }
+
+    /// Runs the sample time tracker.
+    ///
+    /// This function only returns if there is an error. If the IP core does not
+    /// have a sample counter, the function never returns.
+    #[tracing::instrument(name = "sample_time", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let has_sample_counter = {
+            let ip_core = self.state.ip_core().lock().unwrap();
+            if ip_core.has_sample_counter() {
+                ip_core.set_pps_enable(self.pps);
+                true
+            } else {
+                tracing::info!(
+                    "IP core version {} does not have a sample counter",
+                    ip_core.version()
+                );
+                false
+            }
+        };
+        if !has_sample_counter {
+            return std::future::pending().await;
+        }
+        let period = if self.pps {
+            PPS_POLL_PERIOD
+        } else {
+            SNAPSHOT_PERIOD
+        };
+        let mut interval = tokio::time::interval(period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut last_count = self
+            .state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .sample_counter_latch()
+            .count;
+        loop {
+            interval.tick().await;
+            let reference = if self.pps {
+                self.poll_pps(&mut last_count)
+            } else {
+                self.snapshot(&mut last_count).await
+            };
+            if let Some(reference) = reference {
+                tracing::trace!(?reference, "updated sample time reference");
+                *self.state.sample_time_reference().lock().unwrap() = Some(reference);
+            }
+        }
+    }
+
+    fn poll_pps(&self, last_count: &mut u8) -> Option<SampleTimeReference> {
+        let latch = self.state.ip_core().lock().unwrap().sample_counter_latch();
+        let now = Utc::now();
+        if latch.count == *last_count || !latch.pps {
+            *last_count = latch.count;
+            return None;
+        }
+        *last_count = latch.count;
+        // The PPS happened at some point during the last polling period. The
+        // system clock is assumed to be accurate to within a fraction of a
+        // second, so the time of the PPS is obtained by rounding to the
+        // nearest second.
+        let pps_time = now - chrono::Duration::from_std(PPS_POLL_PERIOD / 2).unwrap();
+        let seconds = (pps_time.timestamp_millis() as f64 * 1e-3).round() as i64;
+        let datetime = DateTime::from_timestamp(seconds, 0).unwrap();
+        Some(SampleTimeReference {
+            sample_counter: latch.counter,
+            datetime,
+            source: TimeSource::Pps,
+        })
+    }
+
+    async fn snapshot(&self, last_count: &mut u8) -> Option<SampleTimeReference> {
+        let before = Utc::now();
+        self.state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .sample_counter_snapshot();
+        // In the hardware the new value is latched a few clock cycles after
+        // the snapshot is requested, so it is normally available in the first
+        // read.
+        let mut latch = None;
+        for _ in 0..SNAPSHOT_MAX_READS {
+            let new = self.state.ip_core().lock().unwrap().sample_counter_latch();
+            if new.count != *last_count {
+                latch = Some(new);
+                break;
+            }
+            tokio::time::sleep(SNAPSHOT_RETRY_PERIOD).await;
+        }
+        let after = Utc::now();
+        let Some(latch) = latch else {
+            tracing::warn!("sample counter snapshot timed out");
+            return None;
+        };
+        *last_count = latch.count;
+        // The counter was latched at some point between before and after. The
+        // midpoint is used as an estimate.
+        Some(SampleTimeReference {
+            sample_counter: latch.counter,
+            datetime: before + (after - before) / 2,
+            source: TimeSource::SystemClock,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn datetime_at() {
+        let reference = SampleTimeReference {
+            sample_counter: 1_000_000,
+            datetime: Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap(),
+            source: TimeSource::Pps,
+        };
+        let samp_rate = 1e6;
+        assert_eq!(
+            reference.datetime_at(3_500_000, samp_rate),
+            Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 2).unwrap()
+                + chrono::Duration::milliseconds(500)
+        );
+        assert_eq!(
+            reference.datetime_at(0, samp_rate),
+            Utc.with_ymd_and_hms(2023, 12, 31, 23, 59, 59).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn snapshot() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        tokio::spawn(interrupt_handler.run());
+        let tracker = SampleTimeTracker::new(state.clone(), false);
+        let mut last_count = state.ip_core().lock().unwrap().sample_counter_latch().count;
+        let before = Utc::now();
+        let reference = tracker.snapshot(&mut last_count).await.unwrap();
+        assert_eq!(reference.source, TimeSource::SystemClock);
+        assert!(reference.datetime >= before && reference.datetime <= Utc::now());
+        let next = tracker.snapshot(&mut last_count).await.unwrap();
+        assert!(next.sample_counter >= reference.sample_counter);
+    }
+}
@@ -0,0 +1,217 @@
+//! Sample unpacking and conversion.
+//!
+//! This module contains the routines that convert the samples stored in the
+//! recording buffer to the formats used by the SigMF files and by the DSP
+//! code. They run on every chunk of a recording that is downloaded, so they are
+//! a bottleneck for large downloads.
+//!
+//! The samples are processed in blocks of [`BLOCK_ITEMS`] IQ samples with fixed
+//! size arrays, without bounds checks. The block loops are written so that the
+//! compiler turns them into interleaved vector loads and stores. With the NEON
+//! target feature that is enabled for the Zynq-7000, these become NEON
+//! `vld3`/`vst4` instructions. Explicit NEON intrinsics are not used because
+//! they are not available in stable Rust for 32-bit ARM. The samples that do
+//! not fill a whole block are processed by the portable scalar
+//! implementations, such as [`unpack_12bit_to_16bit_scalar`], which are also
+//! used as reference in the tests and benchmarks.
+
+use num_complex::Complex32;
+
+/// Number of IQ samples processed in each block.
+pub const BLOCK_ITEMS: usize = 16;
+
+/// Unpacks 12-bit IQ samples to 16-bit IQ samples.
+///
+/// The `input` contains IQ samples packed in 3 bytes, as written by the
+/// recorder in 12-bit mode. The `output` is filled with the samples in
+/// `ci16_le` format, sign extended from 12 to 16 bits. The `output` must have a
+/// length of at least 4 bytes for each 3 bytes of `input`.
+///
+/// # Examples
+///
+/// ```
+/// use maia_httpd::samples::unpack_12bit_to_16bit;
+///
+/// // I = 0x123, Q = -1
+/// let input = [0x12, 0x3f, 0xff];
+/// let mut output = [0; 4];
+/// unpack_12bit_to_16bit(&mut output, &input);
+/// assert_eq!(output, [0x23, 0x01, 0xff, 0xff]);
+/// ```
+pub fn unpack_12bit_to_16bit(output: &mut [u8], input: &[u8]) {
+    let input_blocks = input.chunks_exact(3 * BLOCK_ITEMS);
+    let input_tail = input_blocks.remainder();
+    let num_blocks = input_blocks.len();
+    let (output_blocks, output_tail) = output.split_at_mut(4 * BLOCK_ITEMS * num_blocks);
+    for (input, output) in input_blocks.zip(output_blocks.chunks_exact_mut(4 * BLOCK_ITEMS)) {
+        unpack_12bit_block(output.try_into().unwrap(), input.try_into().unwrap());
+    }
+    unpack_12bit_to_16bit_scalar(output_tail, input_tail);
+}
+
+/// Unpacks 12-bit IQ samples to 16-bit IQ samples one sample at a time.
+///
+/// This is the portable implementation of [`unpack_12bit_to_16bit`]. It gives
+/// the same result, but it is not written to be vectorized.
+pub fn unpack_12bit_to_16bit_scalar(output: &mut [u8], input: &[u8]) {
+    for (j, x) in input.chunks_exact(3).enumerate() {
+        output[4 * j] = (x[0] << 4) | (x[1] >> 4);
+        output[4 * j + 1] = ((x[0] & 0xf0) as i8 >> 4) as u8;
+        output[4 * j + 2] = x[2];
+        output[4 * j + 3] = ((x[1] << 4) as i8 >> 4) as u8;
+    }
+}
+
+fn unpack_12bit_block(output: &mut [u8; 4 * BLOCK_ITEMS], input: &[u8; 3 * BLOCK_ITEMS]) {
+    // De-interleave the three bytes of each sample
+    let x0: [u8; BLOCK_ITEMS] = std::array::from_fn(|j| input[3 * j]);
+    let x1: [u8; BLOCK_ITEMS] = std::array::from_fn(|j| input[3 * j + 1]);
+    let x2: [u8; BLOCK_ITEMS] = std::array::from_fn(|j| input[3 * j + 2]);
+    for (j, y) in output.chunks_exact_mut(4).enumerate() {
+        y[0] = (x0[j] << 4) | (x1[j] >> 4);
+        y[1] = ((x0[j] & 0xf0) as i8 >> 4) as u8;
+        y[2] = x2[j];
+        y[3] = ((x1[j] << 4) as i8 >> 4) as u8;
+    }
+}
+
+/// Converts 8-bit IQ samples to complex samples.
+///
+/// The `data` contains IQ samples in `ci8` format. The samples are normalized
+/// so that the full scale corresponds to an amplitude of one.
+pub fn ci8_to_complex(data: &[u8]) -> Vec<Complex32> {
+    let mut output = vec![Complex32::default(); data.len() / 2];
+    let blocks = data.chunks_exact(2 * BLOCK_ITEMS);
+    let tail = blocks.remainder();
+    let (output_blocks, output_tail) = output.split_at_mut(blocks.len() * BLOCK_ITEMS);
+    for (x, y) in blocks.zip(output_blocks.chunks_exact_mut(BLOCK_ITEMS)) {
+        let x: &[u8; 2 * BLOCK_ITEMS] = x.try_into().unwrap();
+        let re: [f32; BLOCK_ITEMS] = std::array::from_fn(|j| f32::from(x[2 * j] as i8));
+        let im: [f32; BLOCK_ITEMS] = std::array::from_fn(|j| f32::from(x[2 * j + 1] as i8));
+        for (j, y) in y.iter_mut().enumerate() {
+            *y = Complex32::new(re[j], im[j]) / 128.0;
+        }
+    }
+    for (x, y) in tail.chunks_exact(2).zip(output_tail.iter_mut()) {
+        *y = Complex32::new(f32::from(x[0] as i8), f32::from(x[1] as i8)) / 128.0;
+    }
+    output
+}
+
+/// Converts 16-bit IQ samples to complex samples.
+///
+/// The `data` contains IQ samples in `ci16_le` format. The samples are divided
+/// by `scale`, which is usually the full scale of the samples.
+pub fn ci16_to_complex(data: &[u8], scale: f32) -> Vec<Complex32> {
+    let mut output = vec![Complex32::default(); data.len() / 4];
+    ci16_to_complex_into(&mut output, data, scale);
+    output
+}
+
+fn ci16_to_complex_into(output: &mut [Complex32], data: &[u8], scale: f32) {
+    let scale = scale.recip();
+    let blocks = data.chunks_exact(4 * BLOCK_ITEMS);
+    let tail = blocks.remainder();
+    let (output_blocks, output_tail) = output.split_at_mut(blocks.len() * BLOCK_ITEMS);
+    for (x, y) in blocks.zip(output_blocks.chunks_exact_mut(BLOCK_ITEMS)) {
+        let x: &[u8; 4 * BLOCK_ITEMS] = x.try_into().unwrap();
+        let re: [f32; BLOCK_ITEMS] =
+            std::array::from_fn(|j| f32::from(i16::from_le_bytes([x[4 * j], x[4 * j + 1]])));
+        let im: [f32; BLOCK_ITEMS] =
+            std::array::from_fn(|j| f32::from(i16::from_le_bytes([x[4 * j + 2], x[4 * j + 3]])));
+        for (j, y) in y.iter_mut().enumerate() {
+            *y = Complex32::new(re[j], im[j]) * scale;
+        }
+    }
+    for (x, y) in tail.chunks_exact(4).zip(output_tail.iter_mut()) {
+        *y = Complex32::new(
+            f32::from(i16::from_le_bytes([x[0], x[1]])),
+            f32::from(i16::from_le_bytes([x[2], x[3]])),
+        ) * scale;
+    }
+}
+
+/// Converts 12-bit packed IQ samples to complex samples.
+///
+/// The `data` contains IQ samples packed in 3 bytes, as in
+/// [`unpack_12bit_to_16bit`]. The samples are normalized so that the full scale
+/// corresponds to an amplitude of one.
+pub fn ci12_to_complex(data: &[u8]) -> Vec<Complex32> {
+    // The samples are unpacked in pieces that fit in the stack, to avoid
+    // allocating a buffer for the whole 16-bit data.
+    const PIECE_ITEMS: usize = 64 * BLOCK_ITEMS;
+    let mut output = vec![Complex32::default(); data.len() / 3];
+    let mut unpacked = [0; 4 * PIECE_ITEMS];
+    for (x, y) in data
+        .chunks(3 * PIECE_ITEMS)
+        .zip(output.chunks_mut(PIECE_ITEMS))
+    {
+        let unpacked = &mut unpacked[..4 * y.len()];
+        unpack_12bit_to_16bit(unpacked, x);
+        ci16_to_complex_into(y, unpacked, 2048.0);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_data(len: usize) -> Vec<u8> {
+        // simple LCG to cover all the byte values
+        let mut state = 12345_u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unpack_matches_scalar() {
+        for items in [0, 1, 15, 16, 17, 100, 1000] {
+            let input = test_data(3 * items);
+            let mut expected = vec![0; 4 * items];
+            unpack_12bit_to_16bit_scalar(&mut expected, &input);
+            let mut output = vec![0; 4 * items];
+            unpack_12bit_to_16bit(&mut output, &input);
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn unpack_sign_extension() {
+        // I = -2048, Q = 2047
+        let mut output = [0; 4];
+        unpack_12bit_to_16bit(&mut output, &[0x80, 0x07, 0xff]);
+        assert_eq!(i16::from_le_bytes([output[0], output[1]]), -2048);
+        assert_eq!(i16::from_le_bytes([output[2], output[3]]), 2047);
+    }
+
+    #[test]
+    fn conversions() {
+        let data = test_data(12 * 1001);
+        let expected = data
+            .chunks_exact(2)
+            .map(|x| Complex32::new(f32::from(x[0] as i8), f32::from(x[1] as i8)) / 128.0)
+            .collect::<Vec<_>>();
+        assert_eq!(ci8_to_complex(&data), expected);
+
+        let expected = data
+            .chunks_exact(4)
+            .map(|x| {
+                Complex32::new(
+                    f32::from(i16::from_le_bytes([x[0], x[1]])),
+                    f32::from(i16::from_le_bytes([x[2], x[3]])),
+                ) / 32768.0
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ci16_to_complex(&data, 32768.0), expected);
+
+        let mut unpacked = vec![0; data.len() / 3 * 4];
+        unpack_12bit_to_16bit_scalar(&mut unpacked, &data);
+        let expected = ci16_to_complex(&unpacked, 2048.0);
+        assert_eq!(ci12_to_complex(&data), expected);
+    }
+}
@@ -0,0 +1,647 @@
+//! Frequency scanner.
+//!
+//! This module implements a scanner that cycles through a list of channels,
+//! spending a dwell time on each of them. The activity of each channel is
+//! detected by comparing the power in the channel bandwidth, as measured by
+//! the spectrometer, with the noise floor of the spectrum (a simple squelch).
+//! When activity is detected, the scanner stops on the channel until the
+//! activity ends and a hold time has elapsed, and then it resumes scanning.
+//! Priority channels are interleaved with the other channels, so that they are
+//! checked more often.
+//!
+//! Optionally, the scanner can record the channels with activity. Each time
+//! that the scanner stops on an active channel, a short recording is made with
+//! the recorder and stored in a library of scanner hits. Hits are not recorded
+//! while the recorder is in use or while the recording buffer contains a
+//! recording that has not been discarded.
+//! These recordings use the recorder settings, so they contain the decimated
+//! channel when the recorder input is the DDC.
+//!
+//! The channels are tuned by changing the AD9361 RX frequency, keeping the DDC
+//! frequency, so that the channel is at the center of the DDC output. The
+//! scanner owns the RX LO through the [`Tuner`](crate::tuner::Tuner) while it
+//! is running, so it cannot be enabled while a frequency-hopping recording or
+//! a survey is running. The scanner is run by the [`ScannerRunner`].
+
+use crate::{
+    app::AppState,
+    httpd::ScannerHit,
+    spectrometer::{median, spectrum_from_bytes},
+    tuner::TunerOwner,
+};
+use anyhow::{Context, Result};
+use maia_json::{Decibels, Hertz, PatchScanner, ScannerChannel, ScannerState, Seconds};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::{sync::Notify, time::Instant};
+
+// Limits of the settings, which are shared with the validation of
+// PatchScanner in maia-json.
+const MAX_CHANNELS: usize = PatchScanner::MAX_CHANNELS;
+const MAX_DWELL: Seconds = PatchScanner::MAX_DWELL;
+const MAX_HOLD: Seconds = PatchScanner::MAX_HOLD;
+const MAX_HIT_DURATION: Seconds = PatchScanner::MAX_HIT_DURATION;
+// Default settings.
+const DEFAULT_DWELL: Seconds = Seconds(0.5);
+const DEFAULT_HOLD: Seconds = Seconds(2.0);
+const DEFAULT_THRESHOLD: Decibels = Decibels(10.0);
+const DEFAULT_HIT_DURATION: Seconds = Seconds(5.0);
+// Time that the spectrometer can take to produce a spectrum before the scanner
+// fails.
+const SPECTRUM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Frequency scanner.
+///
+/// This struct contains the settings and the state of the scanner. It is used
+/// by the REST API and by the [`ScannerRunner`].
+#[derive(Debug)]
+pub struct Scanner {
+    inner: Mutex<Inner>,
+    changed: Notify,
+}
+
+#[derive(Debug)]
+struct Inner {
+    enabled: bool,
+    config: Config,
+    state: ScannerState,
+    channel: Option<usize>,
+    error: Option<String>,
+}
+
+// The durations are checked by check(), so they can always be converted to a
+// Duration.
+#[derive(Debug, Clone, PartialEq)]
+struct Config {
+    channels: Vec<ScannerChannel>,
+    dwell: Seconds,
+    hold: Seconds,
+    threshold: Decibels,
+    record_hits: bool,
+    hit_duration: Seconds,
+}
+
+impl Default for Scanner {
+    fn default() -> Scanner {
+        Scanner::new()
+    }
+}
+
+impl Scanner {
+    /// Creates a new scanner.
+    ///
+    /// The scanner is initially disabled and has no channels.
+    pub fn new() -> Scanner {
+        Scanner {
+            inner: Mutex::new(Inner {
+                enabled: false,
+                config: Config {
+                    channels: Vec::new(),
+                    dwell: DEFAULT_DWELL,
+                    hold: DEFAULT_HOLD,
+                    threshold: DEFAULT_THRESHOLD,
+                    record_hits: false,
+                    hit_duration: DEFAULT_HIT_DURATION,
+                },
+                state: ScannerState::Stopped,
+                channel: None,
+                error: None,
+            }),
+            changed: Notify::new(),
+        }
+    }
+
+    /// Returns the JSON representation of the scanner.
+    pub fn json(&self) -> maia_json::Scanner {
+        let inner = self.inner.lock().unwrap();
+        maia_json::Scanner {
+            enabled: inner.enabled,
+            channels: inner.config.channels.clone(),
+            dwell: inner.config.dwell,
+            hold: inner.config.hold,
+            threshold: inner.config.threshold,
+            record_hits: inner.config.record_hits,
+            hit_duration: inner.config.hit_duration,
+            state: inner.state,
+            channel: inner.channel,
+            error: inner.error.clone(),
+        }
+    }
+
+    /// Modifies the settings of the scanner.
+    ///
+    /// The settings are only modified if all of them are valid. If the
+    /// scanner is running, it restarts from the first channel with the new
+    /// settings.
+    pub fn patch(&self, patch: PatchScanner) -> Result<maia_json::Scanner> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let mut config = inner.config.clone();
+            if let Some(channels) = patch.channels {
+                config.channels = channels;
+            }
+            if let Some(dwell) = patch.dwell {
+                config.dwell = dwell;
+            }
+            if let Some(hold) = patch.hold {
+                config.hold = hold;
+            }
+            if let Some(threshold) = patch.threshold {
+                config.threshold = threshold;
+            }
+            if let Some(record_hits) = patch.record_hits {
+                config.record_hits = record_hits;
+            }
+            if let Some(hit_duration) = patch.hit_duration {
+                config.hit_duration = hit_duration;
+            }
+            check_config(&config)?;
+            let enabled = patch.enabled.unwrap_or(inner.enabled);
+            anyhow::ensure!(
+                !enabled || !config.channels.is_empty(),
+                "the scanner needs at least one channel"
+            );
+            if enabled && !inner.enabled {
+                inner.error = None;
+            }
+            inner.enabled = enabled;
+            inner.config = config;
+        }
+        self.changed.notify_one();
+        Ok(self.json())
+    }
+
+    // Returns the configuration if the scanner is enabled.
+    fn config(&self) -> Option<Config> {
+        let inner = self.inner.lock().unwrap();
+        inner.enabled.then(|| inner.config.clone())
+    }
+
+    fn set_state(&self, state: ScannerState, channel: Option<usize>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.state = state;
+        inner.channel = channel;
+    }
+
+    fn stop_with_error(&self, error: String) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.enabled = false;
+        inner.state = ScannerState::Stopped;
+        inner.channel = None;
+        inner.error = Some(error);
+    }
+}
+
+fn check_config(config: &Config) -> Result<()> {
+    anyhow::ensure!(
+        config.channels.len() <= MAX_CHANNELS,
+        "the scanner can have at most {MAX_CHANNELS} channels"
+    );
+    for channel in &config.channels {
+        anyhow::ensure!(
+            channel.frequency.is_finite() && channel.frequency > Hertz(0.0),
+            "channel frequency {} is invalid",
+            channel.frequency
+        );
+        anyhow::ensure!(
+            channel.bandwidth.is_finite() && channel.bandwidth > Hertz(0.0),
+            "channel bandwidth {} is invalid",
+            channel.bandwidth
+        );
+    }
+    anyhow::ensure!(
+        config.dwell > Seconds(0.0) && config.dwell <= MAX_DWELL,
+        "the dwell time must be positive and at most {MAX_DWELL}"
+    );
+    anyhow::ensure!(
+        (Seconds(0.0)..=MAX_HOLD).contains(&config.hold),
+        "the hold time must be between 0 and {MAX_HOLD}"
+    );
+    anyhow::ensure!(
+        config.threshold > Decibels(0.0),
+        "the threshold must be positive"
+    );
+    anyhow::ensure!(
+        config.hit_duration > Seconds(0.0) && config.hit_duration <= MAX_HIT_DURATION,
+        "the hit duration must be positive and at most {MAX_HIT_DURATION}"
+    );
+    Ok(())
+}
+
+// Returns the order in which the channels are visited in each cycle of the
+// scan. Each of the other channels is followed by a priority channel, and all
+// the channels are visited at least once.
+fn scan_order(channels: &[ScannerChannel]) -> Vec<usize> {
+    let (priority, normal): (Vec<usize>, Vec<usize>) =
+        (0..channels.len()).partition(|&j| channels[j].priority);
+    if priority.is_empty() || normal.is_empty() {
+        return (0..channels.len()).collect();
+    }
+    (0..normal.len().max(priority.len()))
+        .flat_map(|j| [normal[j % normal.len()], priority[j % priority.len()]])
+        .collect()
+}
+
+// Determines whether there is activity in a channel. The spectrum contains
+// linear power values in FFT-shifted order. The average power of the bins in
+// the channel bandwidth is compared with the median of the spectrum, which is
+// used as an estimate of the noise floor.
+fn channel_active(
+    spectrum: &[f32],
+    center_frequency: Hertz,
+    samp_rate: f64,
+    channel_frequency: Hertz,
+    bandwidth: Hertz,
+    threshold: Decibels,
+) -> bool {
+    let n = spectrum.len();
+    if n == 0 || samp_rate <= 0.0 {
+        return false;
+    }
+    let bin_width = Hertz(samp_rate / n as f64);
+    let bin = |frequency: Hertz| (frequency - center_frequency) / bin_width + (n / 2) as f64;
+    let first = bin(channel_frequency - bandwidth * 0.5).ceil().max(0.0) as usize;
+    let last = bin(channel_frequency + bandwidth * 0.5)
+        .floor()
+        .min((n - 1) as f64);
+    // If the bandwidth is narrower than a bin, the nearest bin is used
+    let (first, last) = if last >= first as f64 {
+        (first, last as usize)
+    } else {
+        let nearest = bin(channel_frequency).round();
+        if !(0.0..n as f64).contains(&nearest) {
+            return false;
+        }
+        (nearest as usize, nearest as usize)
+    };
+    let power = spectrum[first..=last]
+        .iter()
+        .map(|&x| f64::from(x))
+        .sum::<f64>()
+        / (last - first + 1) as f64;
+    let noise_floor = f64::from(median(&mut spectrum.to_vec()));
+    Decibels::from_power_ratio(power / noise_floor) >= threshold
+}
+
+/// Scanner runner.
+///
+/// This struct runs the scanner when it is enabled. It implements a
+/// [`run`](ScannerRunner::run) async method that should be run concurrently
+/// with the rest of the application.
+#[derive(Debug)]
+pub struct ScannerRunner {
+    state: AppState,
+}
+
+impl ScannerRunner {
+    /// Creates a new scanner runner.
+    pub fn new(state: AppState) -> ScannerRunner {
+        ScannerRunner { state }
+    }
+
+    /// Runs the scanner runner.
+    ///
+    /// This function loops forever. Errors during the scan stop the scanner
+    /// and are stored in its state.
+    #[tracing::instrument(name = "scanner", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let scanner = self.state.scanner();
+        loop {
+            let changed = scanner.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            let Some(config) = scanner.config() else {
+                scanner.set_state(ScannerState::Stopped, None);
+                changed.await;
+                continue;
+            };
+            tokio::select! {
+                _ = changed => {}
+                Err(err) = self.scan(&config) => {
+                    tracing::error!("scanner failed: {err:#}");
+                    scanner.stop_with_error(format!("{err:#}"));
+                }
+            }
+        }
+    }
+
+    // Scans the channels. This only returns if there is an error. The RX LO is
+    // owned by the scanner while it scans.
+    async fn scan(&self, config: &Config) -> Result<()> {
+        let _tuner = self.state.tuner().acquire(TunerOwner::Scanner)?;
+        let order = scan_order(&config.channels);
+        loop {
+            for &index in &order {
+                self.visit(config, index).await?;
+            }
+        }
+    }
+
+    // Tunes a channel and stays on it during the dwell time, or while it is
+    // active and during the hold time. If the scanner hits are recorded, the
+    // scanner also stays on the channel until the recording finishes.
+    async fn visit(&self, config: &Config, index: usize) -> Result<()> {
+        let channel = &config.channels[index];
+        let (center_frequency, samp_rate) = self.tune(channel).await?;
+        let scanner = self.state.scanner();
+        let mut state = ScannerState::Scanning;
+        scanner.set_state(state, Some(index));
+        let frequency = channel.frequency - self.state.device().frequency_offset();
+        let history = self.state.spectrum_history();
+        // The first spectrum is discarded, since it can contain samples from
+        // before the AD9361 was tuned.
+        let mut count = history.count() + 1;
+        let mut deadline = Some(Instant::now() + config.dwell.duration().unwrap_or_default());
+        // End of the recording of the hit in this channel. This is Some(None)
+        // if the recording could not be started.
+        let mut hit_end = None;
+        loop {
+            let timeout = Instant::now() + SPECTRUM_TIMEOUT;
+            tokio::select! {
+                _ = history.wait_for_count(count + 1) => {}
+                _ = tokio::time::sleep_until(deadline.unwrap_or(timeout)), if deadline.is_some() => {
+                    return Ok(());
+                }
+                _ = tokio::time::sleep_until(timeout) => {
+                    anyhow::bail!("no spectra received from the spectrometer");
+                }
+            }
+            count = history.count();
+            let Some(spectrum) = history.latest(1).pop() else {
+                continue;
+            };
+            let spectrum = spectrum_from_bytes(&spectrum);
+            let active = channel_active(
+                &spectrum,
+                center_frequency,
+                samp_rate,
+                frequency,
+                channel.bandwidth,
+                config.threshold,
+            );
+            match (active, state) {
+                (true, _) => {
+                    if config.record_hits && hit_end.is_none() {
+                        hit_end = Some(self.record_hit(config, channel).await);
+                    }
+                    state = ScannerState::Active;
+                    deadline = None;
+                }
+                (false, ScannerState::Active) => {
+                    state = ScannerState::Hold;
+                    let hold_end = Instant::now() + config.hold.duration().unwrap_or_default();
+                    deadline = Some(hit_end.flatten().map_or(hold_end, |end| end.max(hold_end)));
+                }
+                _ => {}
+            }
+            scanner.set_state(state, Some(index));
+        }
+    }
+
+    // Starts a recording of a channel with activity. Returns the time at which
+    // the recording ends, or `None` if the recording could not be started.
+    async fn record_hit(&self, config: &Config, channel: &ScannerChannel) -> Option<Instant> {
+        let now = chrono::Utc::now();
+        let mut description = format!("Scanner hit at {:.0}", channel.frequency);
+        if !channel.label.is_empty() {
+            description.push_str(&format!(" ({})", channel.label));
+        }
+        let duration = config.hit_duration.duration().unwrap_or_default();
+        let hit = ScannerHit {
+            filename: format!(
+                "scanner_{:.0}Hz_{}",
+                channel.frequency.0,
+                now.format("%Y-%m-%d-%H-%M-%S")
+            ),
+            description,
+            duration,
+        };
+        match self.state.recorder().record_hit(&self.state, hit).await {
+            Ok(Ok(())) => {
+                tracing::info!(frequency = channel.frequency.0, "recording scanner hit");
+                Some(Instant::now() + duration)
+            }
+            Ok(Err(busy)) => {
+                tracing::info!(
+                    frequency = channel.frequency.0,
+                    "not recording scanner hit: {busy}"
+                );
+                None
+            }
+            Err(err) => {
+                tracing::error!("could not record scanner hit: {err:#}");
+                None
+            }
+        }
+    }
+
+    // Tunes the AD9361 so that the channel is at the DDC frequency. Returns
+    // the center frequency and sample rate of the spectrometer.
+    async fn tune(&self, channel: &ScannerChannel) -> Result<(Hertz, f64)> {
+        let lo_frequency = lo_frequency(&self.state, channel);
+        self.state
+            .limits()
+            .check_rx_lo_frequency(lo_frequency)
+            .with_context(|| format!("cannot tune channel {}", channel.frequency))?;
+        self.state
+            .ad9361()
+            .lock()
+            .await
+            .set_rx_lo_frequency(lo_frequency)
+            .await
+            .context("failed to set RX frequency")?;
+        let ip_core = self.state.ip_core().lock().unwrap();
+        self.state.spectrometer_config().retune(&ip_core);
+        Ok((
+            lo_frequency + ip_core.spectrometer_input_frequency_offset(),
+            f64::from(self.state.spectrometer_config().samp_rate()),
+        ))
+    }
+}
+
+/// Returns the RX LO frequency used to receive a channel.
+///
+/// The channel is placed at the center of the DDC output, taking into account
+/// the frequency offset of the device.
+pub fn lo_frequency(state: &AppState, channel: &ScannerChannel) -> Hertz {
+    let ddc_frequency = state.ip_core().lock().unwrap().ddc_frequency();
+    let lo_frequency = channel.frequency - state.device().frequency_offset() - ddc_frequency;
+    Hertz(lo_frequency.0.round())
+}
+
+// Used by the tests of the REST API.
+#[cfg(test)]
+pub(crate) fn test_channels() -> Vec<ScannerChannel> {
+    [(100e6, false), (145.5e6, true), (200e6, false)]
+        .into_iter()
+        .map(|(frequency, priority)| ScannerChannel {
+            frequency: Hertz(frequency),
+            bandwidth: Hertz::from_khz(12.5),
+            priority,
+            label: String::new(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrometer::Spectrometer;
+
+    #[test]
+    fn config() {
+        let scanner = Scanner::new();
+        let enable = || PatchScanner {
+            enabled: Some(true),
+            ..Default::default()
+        };
+        assert!(scanner.patch(enable()).is_err());
+        let mut channels = test_channels();
+        channels[0].bandwidth = Hertz(0.0);
+        let patch = PatchScanner {
+            channels: Some(channels),
+            ..Default::default()
+        };
+        assert!(scanner.patch(patch).is_err());
+        let patch = PatchScanner {
+            dwell: Some(Seconds(0.0)),
+            ..Default::default()
+        };
+        assert!(scanner.patch(patch).is_err());
+        let patch = PatchScanner {
+            hit_duration: Some(MAX_HIT_DURATION + Seconds(1.0)),
+            ..Default::default()
+        };
+        assert!(scanner.patch(patch).is_err());
+
+        let patch = PatchScanner {
+            enabled: Some(true),
+            channels: Some(test_channels()),
+            hold: Some(Seconds(0.0)),
+            record_hits: Some(true),
+            ..Default::default()
+        };
+        let json = scanner.patch(patch).unwrap();
+        assert!(json.enabled);
+        assert_eq!(json.channels, test_channels());
+        assert_eq!(json.dwell, DEFAULT_DWELL);
+        assert_eq!(json.hold, Seconds(0.0));
+        assert!(json.record_hits);
+        assert_eq!(json.hit_duration, DEFAULT_HIT_DURATION);
+        assert_eq!(json.state, ScannerState::Stopped);
+        assert!(scanner.config().is_some());
+
+        scanner.stop_with_error("test".to_string());
+        assert_eq!(scanner.json().error.as_deref(), Some("test"));
+        assert!(scanner.config().is_none());
+        assert_eq!(scanner.patch(enable()).unwrap().error, None);
+    }
+
+    #[test]
+    fn order() {
+        let mut channels = test_channels();
+        assert_eq!(scan_order(&channels), [0, 1, 2, 1]);
+        channels[1].priority = false;
+        assert_eq!(scan_order(&channels), [0, 1, 2]);
+        channels.iter_mut().for_each(|c| c.priority = true);
+        assert_eq!(scan_order(&channels), [0, 1, 2]);
+        channels[0].priority = false;
+        assert_eq!(scan_order(&channels), [0, 1, 0, 2]);
+    }
+
+    #[test]
+    fn activity() {
+        // 16 bins of 1 kHz centered at 100 MHz
+        let mut spectrum = vec![1.0; 16];
+        spectrum[12] = 20.0;
+        let active = |spectrum: &[f32], frequency, bandwidth| {
+            channel_active(
+                spectrum,
+                Hertz(100e6),
+                16e3,
+                Hertz(frequency),
+                Hertz(bandwidth),
+                Decibels(10.0),
+            )
+        };
+        assert!(active(&spectrum, 100.004e6, 1e3));
+        // Narrow channel uses the nearest bin
+        assert!(active(&spectrum, 100.0042e6, 100.0));
+        assert!(!active(&spectrum, 100.002e6, 1e3));
+        // The power is averaged over the channel bandwidth
+        assert!(!active(&spectrum, 100.004e6, 3e3));
+        spectrum[11] = 20.0;
+        spectrum[13] = 20.0;
+        assert!(active(&spectrum, 100.004e6, 3e3));
+        // Channels outside the spectrum are not active
+        assert!(!active(&spectrum, 101e6, 1e3));
+        assert!(!active(&[], 100e6, 1e3));
+    }
+
+    #[tokio::test]
+    async fn run() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .set_spectrometer_number_integrations(16)
+            .unwrap();
+        let (sender, _) = tokio::sync::broadcast::channel(16);
+        let spectrometer = Spectrometer::new(
+            state.clone(),
+            interrupt_handler.waiter_spectrometer(),
+            sender,
+        );
+        tokio::spawn(interrupt_handler.run());
+        tokio::spawn(spectrometer.run());
+        tokio::spawn(ScannerRunner::new(state.clone()).run());
+
+        let patch = PatchScanner {
+            enabled: Some(true),
+            channels: Some(test_channels()),
+            dwell: Some(Seconds(0.5)),
+            hold: Some(Seconds(0.0)),
+            ..Default::default()
+        };
+        state.scanner().patch(patch).unwrap();
+        // Wait until the scanner visits the last channel
+        tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let json = state.scanner().json();
+                assert_eq!(json.error, None);
+                if json.channel == Some(2) {
+                    assert_ne!(json.state, ScannerState::Stopped);
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            state
+                .ad9361()
+                .lock()
+                .await
+                .get_rx_lo_frequency()
+                .await
+                .unwrap(),
+            Hertz(200_000_000.0)
+        );
+
+        let patch = PatchScanner {
+            enabled: Some(false),
+            ..Default::default()
+        };
+        state.scanner().patch(patch).unwrap();
+        tokio::time::timeout(Duration::from_secs(30), async {
+            while state.scanner().json().state != ScannerState::Stopped {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(state.scanner().json().channel, None);
+    }
+}
@@ -0,0 +1,131 @@
+//! Coordinated shutdown.
+//!
+//! This module handles the shutdown of maia-httpd, which happens when the
+//! process receives SIGTERM or SIGINT, or when a shutdown or reboot of the
+//! device is requested through the API. Before exiting, any recording in
+//! progress is stopped, so that its metadata is finalized, and the WebSockets
+//! and HTTP connections are closed cleanly.
+
+use crate::app::AppState;
+use anyhow::{Context, Result};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+
+// Maximum time to wait for a recording in progress to finish.
+const RECORDER_STOP_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum time given to the HTTP connections to close during the shutdown.
+pub const CONNECTIONS_CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Action done by the device after maia-httpd shuts down.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
+pub enum PowerAction {
+    /// maia-httpd exits and the device keeps running.
+    #[default]
+    Exit,
+    /// The device is powered off.
+    Poweroff,
+    /// The device is rebooted.
+    Reboot,
+}
+
+/// Shutdown state.
+///
+/// This is part of the [`AppState`]. It is used to request a shutdown and to
+/// notify the different parts of the application that they should close their
+/// connections.
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    requested: CancellationToken,
+    closing: CancellationToken,
+    action: Mutex<PowerAction>,
+}
+
+impl Shutdown {
+    /// Requests a shutdown.
+    ///
+    /// The `action` is done by the device once maia-httpd has shut down. If
+    /// several shutdowns are requested, the last action is done.
+    pub fn request(&self, action: PowerAction) {
+        *self.action.lock().unwrap() = action;
+        self.requested.cancel();
+    }
+
+    /// Returns `true` if a shutdown has been requested.
+    pub fn is_requested(&self) -> bool {
+        self.requested.is_cancelled()
+    }
+
+    /// Returns a token that is cancelled when the connections should be closed.
+    ///
+    /// WebSockets and the HTTP server use this token to close their
+    /// connections during the shutdown.
+    pub fn closing(&self) -> &CancellationToken {
+        &self.closing
+    }
+}
+
+/// Shutdown handler.
+///
+/// This struct waits for a shutdown signal or request and performs the
+/// shutdown. It implements a [`run`](ShutdownHandler::run) async method that
+/// should be run concurrently with the rest of the application.
+#[derive(Debug)]
+pub struct ShutdownHandler {
+    state: AppState,
+    simulate: bool,
+}
+
+impl ShutdownHandler {
+    /// Creates a new shutdown handler.
+    ///
+    /// If `simulate` is `true`, maia-httpd is running with simulated hardware,
+    /// so the device is never powered off or rebooted. A request to do so only
+    /// makes maia-httpd exit.
+    pub fn new(state: AppState, simulate: bool) -> ShutdownHandler {
+        ShutdownHandler { state, simulate }
+    }
+
+    /// Runs the shutdown handler.
+    ///
+    /// This function returns once the shutdown has been performed, and then
+    /// the application should exit.
+    #[tracing::instrument(name = "shutdown", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let mut sigterm = signal(SignalKind::terminate())?;
+        let mut sigint = signal(SignalKind::interrupt())?;
+        tokio::select! {
+            _ = sigterm.recv() => tracing::info!("received SIGTERM"),
+            _ = sigint.recv() => tracing::info!("received SIGINT"),
+            _ = self.state.shutdown().requested.cancelled() => {}
+        }
+        let action = *self.state.shutdown().action.lock().unwrap();
+        tracing::info!(?action, "shutting down");
+        if let Err(err) = self
+            .state
+            .recorder()
+            .stop(&self.state, RECORDER_STOP_TIMEOUT)
+            .await
+        {
+            tracing::error!("failed to stop the recorder: {err:#}");
+        }
+        self.state.shutdown().closing.cancel();
+        tokio::time::sleep(CONNECTIONS_CLOSE_TIMEOUT).await;
+        let command = match action {
+            PowerAction::Exit => return Ok(()),
+            _ if self.simulate => {
+                tracing::info!("not powering off or rebooting in simulation mode");
+                return Ok(());
+            }
+            PowerAction::Poweroff => "poweroff",
+            PowerAction::Reboot => "reboot",
+        };
+        let status = tokio::process::Command::new(command)
+            .status()
+            .await
+            .with_context(|| format!("failed to run {command}"))?;
+        anyhow::ensure!(status.success(), "{command} failed with {status}");
+        Ok(())
+    }
+}
@@ -2,11 +2,16 @@
 //!
 //! This module contains a minimal implementation of [SigMF](https://github.com/gnuradio/SigMF/).
 
+use crate::sample_time::SampleTimeReference;
 use anyhow::Result;
 use chrono::prelude::*;
 use serde_json::json;
 
 const SIGMF_VERSION: &str = "1.0.0";
+// Version of the maia SigMF extension, which is used to store the sample
+// counter information, the frequency offset of external converters, the
+// Doppler correction and the sample clock error.
+const MAIA_EXTENSION_VERSION: &str = "1.3.0";
 const SIGMF_RECORDER: &str = concat!("Maia SDR v", env!("CARGO_PKG_VERSION"));
 
 /// SigMF metadata.
@@ -32,6 +37,13 @@ pub struct Metadata {
     frequency: f64,
     datetime: DateTime<Utc>,
     geolocation: Option<GeoJsonPoint>,
+    sample_time: Option<SampleTime>,
+    frequency_offset: Option<f64>,
+    doppler_correction: Option<DopplerCorrection>,
+    clock_error: Option<f64>,
+    sha512: Option<String>,
+    annotations: Vec<Annotation>,
+    captures: Vec<Capture>,
 }
 
 /// SigMF datatype.
@@ -141,6 +153,149 @@ impl From<maia_json::RecorderMode> for Datatype {
     }
 }
 
+/// Sample time information.
+///
+/// This contains the value of the sample counter of the FPGA IP core at the
+/// first sample of the recording, and optionally a reference that maps the
+/// sample counter to UTC. It is stored in the SigMF metadata using the `maia`
+/// extension.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SampleTime {
+    /// Value of the sample counter at the first sample of the recording.
+    pub start_sample: u64,
+    /// Rate at which the sample counter increments (in samples per second).
+    ///
+    /// This is the AD9361 sample rate.
+    pub counter_rate: f64,
+    /// Decimation factor between the sample counter and the recording.
+    ///
+    /// Consecutive samples of the recording are separated by this number of
+    /// counts of the sample counter.
+    pub decimation: u32,
+    /// Sample time reference.
+    ///
+    /// This is `None` if no reference is available.
+    pub reference: Option<SampleTimeReference>,
+}
+
+impl SampleTime {
+    /// Returns a JSON [`serde_json::Map`] with the SigMF global fields that
+    /// represent the sample time.
+    pub fn to_json_map(&self) -> serde_json::Map<String, serde_json::Value> {
+        let mut map = serde_json::Map::new();
+        map.insert("maia:sample_counter".to_string(), self.start_sample.into());
+        map.insert(
+            "maia:sample_counter_rate".to_string(),
+            self.counter_rate.into(),
+        );
+        map.insert(
+            "maia:sample_counter_decimation".to_string(),
+            self.decimation.into(),
+        );
+        if let Some(reference) = &self.reference {
+            map.insert(
+                "maia:sample_time_reference".to_string(),
+                json!({
+                    "sample_counter": reference.sample_counter,
+                    "datetime": reference.datetime.to_rfc3339_opts(SecondsFormat::Nanos, true),
+                    "source": reference.source.to_string(),
+                }),
+            );
+        }
+        map
+    }
+}
+
+/// Doppler correction.
+///
+/// This contains the satellite Doppler correction that was being applied to
+/// the DDC frequency when the recording started. It is stored in the SigMF
+/// metadata using the `maia` extension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DopplerCorrection {
+    /// Name of the satellite.
+    pub satellite: String,
+    /// Transmit frequency of the satellite (in Hz).
+    pub frequency: f64,
+    /// Doppler correction (in Hz).
+    ///
+    /// The DDC frequency was tuned to the transmit frequency plus this
+    /// correction.
+    pub correction: f64,
+}
+
+/// SigMF annotation.
+///
+/// An annotation describes a time and frequency region of the recording. It is
+/// stored in the "annotations" array of the SigMF metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// Index of the first sample of the annotation.
+    pub sample_start: u64,
+    /// Number of samples of the annotation.
+    pub sample_count: u64,
+    /// Lowest frequency of the annotation (in Hz).
+    pub freq_lower_edge: f64,
+    /// Highest frequency of the annotation (in Hz).
+    pub freq_upper_edge: f64,
+    /// Short label of the annotation.
+    ///
+    /// The label is omitted from the metadata if it is empty.
+    pub label: String,
+    /// Comment of the annotation.
+    ///
+    /// The comment is omitted from the metadata if it is empty.
+    pub comment: String,
+}
+
+impl Annotation {
+    /// Returns a JSON [`serde_json::Value`] that represents the annotation.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        let mut json = json!({
+            "core:sample_start": self.sample_start,
+            "core:sample_count": self.sample_count,
+            "core:freq_lower_edge": self.freq_lower_edge,
+            "core:freq_upper_edge": self.freq_upper_edge,
+        });
+        let map = json.as_object_mut().unwrap();
+        if !self.label.is_empty() {
+            map.insert("core:label".to_string(), json!(self.label));
+        }
+        if !self.comment.is_empty() {
+            map.insert("core:comment".to_string(), json!(self.comment));
+        }
+        json
+    }
+}
+
+/// SigMF capture segment.
+///
+/// A capture segment indicates that the center frequency of the recording
+/// changes at a given sample, as in the recordings made while hopping through
+/// several frequencies. It is stored in the "captures" array of the SigMF
+/// metadata.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capture {
+    /// Index of the first sample of the capture segment.
+    pub sample_start: u64,
+    /// Center frequency of the capture segment (in Hz).
+    pub frequency: f64,
+    /// Timestamp of the first sample of the capture segment.
+    pub datetime: DateTime<Utc>,
+}
+
+impl Capture {
+    /// Returns a JSON [`serde_json::Value`] that represents the capture
+    /// segment.
+    pub fn to_json_value(&self) -> serde_json::Value {
+        json!({
+            "core:sample_start": self.sample_start,
+            "core:frequency": self.frequency,
+            "core:datetime": self.datetime.to_rfc3339_opts(SecondsFormat::Millis, true)
+        })
+    }
+}
+
 /// GeoJSON point.
 ///
 /// This struct represents a GeoJSON point, which contains a latitude and
@@ -266,6 +421,13 @@ impl Metadata {
             frequency,
             datetime: Utc::now(),
             geolocation: None,
+            sample_time: None,
+            frequency_offset: None,
+            doppler_correction: None,
+            clock_error: None,
+            sha512: None,
+            annotations: Vec::new(),
+            captures: Vec::new(),
         }
     }
 
@@ -357,6 +519,124 @@ impl Metadata {
         self.geolocation = geolocation;
     }
 
+    /// Gives the value of the sample time field.
+    pub fn sample_time(&self) -> Option<SampleTime> {
+        self.sample_time
+    }
+
+    /// Sets or removes the value of the sample time field.
+    ///
+    /// The sample time is stored using the `maia` SigMF extension.
+    pub fn set_sample_time(&mut self, sample_time: Option<SampleTime>) {
+        self.sample_time = sample_time;
+    }
+
+    /// Gives the value of the frequency offset field (in Hz).
+    pub fn frequency_offset(&self) -> Option<f64> {
+        self.frequency_offset
+    }
+
+    /// Sets or removes the value of the frequency offset field.
+    ///
+    /// The frequency offset is the offset of an external frequency converter,
+    /// which is added to the frequency field to obtain the frequency at the
+    /// converter input. It is stored using the `maia` SigMF extension.
+    pub fn set_frequency_offset(&mut self, frequency_offset: Option<f64>) {
+        self.frequency_offset = frequency_offset;
+    }
+
+    /// Gives the value of the Doppler correction field.
+    pub fn doppler_correction(&self) -> Option<&DopplerCorrection> {
+        self.doppler_correction.as_ref()
+    }
+
+    /// Sets or removes the value of the Doppler correction field.
+    ///
+    /// The Doppler correction is stored using the `maia` SigMF extension.
+    pub fn set_doppler_correction(&mut self, doppler_correction: Option<DopplerCorrection>) {
+        self.doppler_correction = doppler_correction;
+    }
+
+    /// Gives the value of the clock error field (in parts per million).
+    pub fn clock_error(&self) -> Option<f64> {
+        self.clock_error
+    }
+
+    /// Sets or removes the value of the clock error field.
+    ///
+    /// The clock error is the error of the sample clock measured by comparing
+    /// the actual and the nominal sample rates. It is stored using the `maia`
+    /// SigMF extension.
+    pub fn set_clock_error(&mut self, clock_error: Option<f64>) {
+        self.clock_error = clock_error;
+    }
+
+    /// Gives the value of the SHA512 field.
+    pub fn sha512(&self) -> Option<&str> {
+        self.sha512.as_deref()
+    }
+
+    /// Sets or removes the value of the SHA512 field.
+    ///
+    /// The SHA512 field contains the hash of the dataset file, encoded as a
+    /// lowercase hexadecimal string.
+    pub fn set_sha512(&mut self, sha512: Option<String>) {
+        self.sha512 = sha512;
+    }
+
+    /// Gives the annotations.
+    pub fn annotations(&self) -> &[Annotation] {
+        &self.annotations
+    }
+
+    /// Sets the annotations.
+    ///
+    /// The annotations are sorted by their start sample, as required by SigMF.
+    pub fn set_annotations(&mut self, mut annotations: Vec<Annotation>) {
+        annotations.sort_by_key(|annotation| annotation.sample_start);
+        self.annotations = annotations;
+    }
+
+    /// Gives the capture segments that follow the first one.
+    ///
+    /// The first capture segment, which starts at sample zero, is formed by
+    /// the frequency and datetime fields.
+    pub fn captures(&self) -> &[Capture] {
+        &self.captures
+    }
+
+    /// Sets the capture segments that follow the first one.
+    ///
+    /// The capture segments are sorted by their start sample, as required by
+    /// SigMF. They should start after sample zero, since the first capture
+    /// segment is formed by the frequency and datetime fields.
+    pub fn set_captures(&mut self, mut captures: Vec<Capture>) {
+        captures.sort_by_key(|capture| capture.sample_start);
+        self.captures = captures;
+    }
+
+    /// Restricts the annotations to a portion of the recording.
+    ///
+    /// This is used when the metadata is adapted to describe a portion of the
+    /// samples of the recording, which starts at the sample `start` and ends
+    /// before the sample `end`, or at the end of the recording if `end` is
+    /// `None`, and which has been decimated by `decimation`. The annotations
+    /// that do not overlap this portion are removed, and the rest are clipped
+    /// to it and converted to sample indices of the decimated portion.
+    pub fn restrict_annotations(&mut self, start: u64, end: Option<u64>, decimation: u64) {
+        self.annotations.retain_mut(|annotation| {
+            let annotation_end = annotation.sample_start + annotation.sample_count;
+            let first = annotation.sample_start.max(start);
+            let last = end.map_or(annotation_end, |end| annotation_end.min(end));
+            if first >= last {
+                return false;
+            }
+            annotation.sample_start = (first - start) / decimation;
+            annotation.sample_count = (last - first).div_ceil(decimation);
+            true
+        });
+    }
+
     /// Returns a string that represents the metadata in JSON.
     ///
     /// The formatting of the JSON is compliant with the SigMF standard.
@@ -385,16 +665,72 @@ impl Metadata {
                 .unwrap()
                 .insert("core:geolocation".to_string(), geolocation.to_json_value());
         }
+        if let Some(sha512) = self.sha512() {
+            global
+                .as_object_mut()
+                .unwrap()
+                .insert("core:sha512".to_string(), json!(sha512));
+        }
+        if self.sample_time.is_some()
+            || self.frequency_offset.is_some()
+            || self.doppler_correction.is_some()
+            || self.clock_error.is_some()
+        {
+            global.as_object_mut().unwrap().insert(
+                "core:extensions".to_string(),
+                json!([
+                    {
+                        "name": "maia",
+                        "version": MAIA_EXTENSION_VERSION,
+                        "optional": true
+                    }
+                ]),
+            );
+        }
+        if let Some(sample_time) = self.sample_time() {
+            global
+                .as_object_mut()
+                .unwrap()
+                .extend(sample_time.to_json_map());
+        }
+        if let Some(frequency_offset) = self.frequency_offset() {
+            global
+                .as_object_mut()
+                .unwrap()
+                .insert("maia:frequency_offset".to_string(), json!(frequency_offset));
+        }
+        if let Some(doppler_correction) = self.doppler_correction() {
+            global.as_object_mut().unwrap().insert(
+                "maia:doppler_correction".to_string(),
+                json!({
+                    "satellite": doppler_correction.satellite,
+                    "frequency": doppler_correction.frequency,
+                    "correction": doppler_correction.correction,
+                }),
+            );
+        }
+        if let Some(clock_error) = self.clock_error() {
+            global
+                .as_object_mut()
+                .unwrap()
+                .insert("maia:clock_error_ppm".to_string(), json!(clock_error));
+        }
+        let first_capture = Capture {
+            sample_start: 0,
+            frequency: self.frequency,
+            datetime: self.datetime,
+        };
         json!({
             "global": global,
-            "captures": [
-                {
-                    "core:sample_start": 0,
-                    "core:frequency": self.frequency,
-                    "core:datetime": self.datetime.to_rfc3339_opts(SecondsFormat::Millis, true)
-                }
-            ],
-            "annotations": []
+            "captures": std::iter::once(&first_capture)
+                .chain(&self.captures)
+                .map(Capture::to_json_value)
+                .collect::<Vec<_>>(),
+            "annotations": self
+                .annotations
+                .iter()
+                .map(Annotation::to_json_value)
+                .collect::<Vec<_>>()
         })
     }
 }
@@ -416,6 +752,13 @@ mod test {
             frequency: 2400e6,
             datetime: Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap(),
             geolocation: None,
+            sample_time: None,
+            frequency_offset: None,
+            doppler_correction: None,
+            clock_error: None,
+            sha512: None,
+            annotations: Vec::new(),
+            captures: Vec::new(),
         };
         let json = meta.to_json();
         let expected = [
@@ -462,6 +805,13 @@ mod test {
             geolocation: Some(
                 GeoJsonPoint::from_lat_lon_alt(34.0787916, -107.6183682, 2120.0).unwrap(),
             ),
+            sample_time: None,
+            frequency_offset: None,
+            doppler_correction: None,
+            clock_error: None,
+            sha512: None,
+            annotations: Vec::new(),
+            captures: Vec::new(),
         };
         let json = meta.to_json();
         let expected = [
@@ -500,4 +850,227 @@ mod test {
         .join("");
         assert_eq!(json, expected);
     }
+    #[test]
+    fn to_json_with_sample_time() {
+        let meta = Metadata {
+            datatype: Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            sample_rate: 1.92e6,
+            description: String::new(),
+            author: String::new(),
+            frequency: 1090e6,
+            datetime: Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap(),
+            geolocation: None,
+            sample_time: Some(SampleTime {
+                start_sample: 123456789012,
+                counter_rate: 61.44e6,
+                decimation: 32,
+                reference: Some(SampleTimeReference {
+                    sample_counter: 123400000000,
+                    datetime: Utc.with_ymd_and_hms(2022, 10, 31, 23, 59, 59).unwrap(),
+                    source: crate::sample_time::TimeSource::Pps,
+                }),
+            }),
+            frequency_offset: None,
+            doppler_correction: None,
+            clock_error: None,
+            sha512: None,
+            annotations: Vec::new(),
+            captures: Vec::new(),
+        };
+        let json = meta.to_json();
+        let expected = [
+            r#"{
+  "annotations": [],
+  "captures": [
+    {
+      "core:datetime": "2022-11-01T00:00:00.000Z",
+      "core:frequency": 1090000000.0,
+      "core:sample_start": 0
+    }
+  ],
+  "global": {
+    "core:author": "",
+    "core:datatype": "ci8",
+    "core:description": "",
+    "core:extensions": [
+      {
+        "name": "maia",
+        "optional": true,
+        "version": ""#,
+            MAIA_EXTENSION_VERSION,
+            r#""
+      }
+    ],
+    "core:recorder": ""#,
+            SIGMF_RECORDER,
+            r#"",
+    "core:sample_rate": 1920000.0,
+    "core:version": ""#,
+            SIGMF_VERSION,
+            r#"",
+    "maia:sample_counter": 123456789012,
+    "maia:sample_counter_decimation": 32,
+    "maia:sample_counter_rate": 61440000.0,
+    "maia:sample_time_reference": {
+      "datetime": "2022-10-31T23:59:59.000000000Z",
+      "sample_counter": 123400000000,
+      "source": "pps"
+    }
+  }
+}
+"#,
+        ]
+        .join("");
+        assert_eq!(json, expected);
+    }
+
+    #[test]
+    fn to_json_with_frequency_offset() {
+        let mut meta = Metadata::new(
+            Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            1e6,
+            739e6,
+        );
+        assert!(meta.to_json_value()["global"]
+            .get("maia:frequency_offset")
+            .is_none());
+        meta.set_frequency_offset(Some(9750e6));
+        let json = meta.to_json_value();
+        assert_eq!(json["global"]["maia:frequency_offset"], 9750e6);
+        assert_eq!(json["global"]["core:extensions"][0]["name"], "maia");
+        assert_eq!(json["captures"][0]["core:frequency"], 739e6);
+    }
+
+    #[test]
+    fn to_json_with_doppler_correction() {
+        let mut meta = Metadata::new(
+            Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            1e6,
+            437.8e6,
+        );
+        assert!(meta.to_json_value()["global"]
+            .get("maia:doppler_correction")
+            .is_none());
+        meta.set_doppler_correction(Some(DopplerCorrection {
+            satellite: "ISS".to_string(),
+            frequency: 437.8e6,
+            correction: 9500.0,
+        }));
+        let json = meta.to_json_value();
+        assert_eq!(
+            json["global"]["maia:doppler_correction"],
+            json!({"satellite": "ISS", "frequency": 437.8e6, "correction": 9500.0})
+        );
+        assert_eq!(json["global"]["core:extensions"][0]["name"], "maia");
+    }
+
+    #[test]
+    fn to_json_with_clock_error() {
+        let mut meta = Metadata::new(
+            Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            1e6,
+            100e6,
+        );
+        assert!(meta.to_json_value()["global"]
+            .get("maia:clock_error_ppm")
+            .is_none());
+        meta.set_clock_error(Some(-1.5));
+        let json = meta.to_json_value();
+        assert_eq!(json["global"]["maia:clock_error_ppm"], -1.5);
+        assert_eq!(json["global"]["core:extensions"][0]["name"], "maia");
+    }
+
+    #[test]
+    fn to_json_with_sha512() {
+        let mut meta = Metadata::new(
+            Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            1e6,
+            100e6,
+        );
+        assert!(meta.to_json_value()["global"].get("core:sha512").is_none());
+        meta.set_sha512(Some("abcd".to_string()));
+        assert_eq!(meta.to_json_value()["global"]["core:sha512"], "abcd");
+    }
+
+    #[test]
+    fn to_json_with_annotations() {
+        let mut meta = Metadata::new(
+            Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            1e6,
+            100e6,
+        );
+        let annotation = |sample_start, sample_count, label: &str| Annotation {
+            sample_start,
+            sample_count,
+            freq_lower_edge: 99.9e6,
+            freq_upper_edge: 100.1e6,
+            label: label.to_string(),
+            comment: String::new(),
+        };
+        meta.set_annotations(vec![
+            annotation(5000, 1000, "burst"),
+            annotation(1000, 3000, ""),
+        ]);
+        let json = meta.to_json_value();
+        assert_eq!(json["annotations"][0]["core:sample_start"], 1000);
+        assert!(json["annotations"][0].get("core:label").is_none());
+        assert_eq!(json["annotations"][1]["core:sample_count"], 1000);
+        assert_eq!(json["annotations"][1]["core:freq_lower_edge"], 99.9e6);
+        assert_eq!(json["annotations"][1]["core:label"], "burst");
+        assert!(json["annotations"][1].get("core:comment").is_none());
+
+        meta.restrict_annotations(2000, Some(5500), 2);
+        assert_eq!(
+            meta.annotations(),
+            [annotation(0, 1000, ""), annotation(1500, 250, "burst")]
+        );
+        meta.restrict_annotations(2000, None, 1);
+        assert!(meta.annotations().is_empty());
+    }
+
+    #[test]
+    fn to_json_with_captures() {
+        let mut meta = Metadata::new(
+            Datatype {
+                field: Field::Complex,
+                format: SampleFormat::I8,
+            },
+            1e6,
+            100e6,
+        );
+        meta.set_datetime(Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, 0).unwrap());
+        let capture = |sample_start, frequency, second| Capture {
+            sample_start,
+            frequency,
+            datetime: Utc.with_ymd_and_hms(2022, 11, 1, 0, 0, second).unwrap(),
+        };
+        meta.set_captures(vec![capture(2000, 300e6, 2), capture(1000, 200e6, 1)]);
+        let json = meta.to_json_value();
+        let captures = json["captures"].as_array().unwrap();
+        assert_eq!(captures.len(), 3);
+        assert_eq!(captures[0]["core:sample_start"], 0);
+        assert_eq!(captures[0]["core:frequency"], 100e6);
+        assert_eq!(captures[1]["core:sample_start"], 1000);
+        assert_eq!(captures[1]["core:frequency"], 200e6);
+        assert_eq!(captures[1]["core:datetime"], "2022-11-01T00:00:01.000Z");
+        assert_eq!(captures[2]["core:sample_start"], 2000);
+    }
 }
@@ -0,0 +1,209 @@
+//! Spectrogram rendering.
+//!
+//! This module renders spectrometer lines as a spectrogram image, using one of
+//! the [colormaps](crate::colormap), and encodes the image as a PNG file. The
+//! rendering is done in the same way as in the maia-wasm waterfall, so that
+//! the images look like the waterfall.
+
+use crate::colormap::Colormap;
+use flate2::{write::ZlibEncoder, Compression};
+use std::io::Write;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+// PNG color type for 8-bit RGB
+const PNG_COLOR_TYPE_RGB: u8 = 2;
+
+/// Spectrogram rendering parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameters {
+    /// Colormap used to render the spectrogram.
+    pub colormap: Colormap,
+    /// Power in dB that corresponds to the beginning of the colormap.
+    pub min: f32,
+    /// Power in dB that corresponds to the end of the colormap.
+    pub max: f32,
+    /// Width of the image in pixels.
+    ///
+    /// If this is `None`, each spectrum bin is rendered as a pixel. Otherwise,
+    /// the bins are combined by taking the maximum of the bins that fall in
+    /// each pixel.
+    pub width: Option<usize>,
+}
+
+impl Default for Parameters {
+    fn default() -> Parameters {
+        Parameters {
+            colormap: Colormap::default(),
+            min: 35.0,
+            max: 85.0,
+            width: None,
+        }
+    }
+}
+
+/// Spectrogram image.
+///
+/// The image is stored as 8-bit RGB pixels in row-major order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spectrogram {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Spectrogram {
+    /// Renders a spectrogram.
+    ///
+    /// Each of the `lines` is a spectrum with linear power values in
+    /// FFT-shifted order, and it is rendered as a row of the image, starting
+    /// by the top row. The width of the image is the one given in the
+    /// `parameters`, limited to the length of the first line. Lines with a
+    /// different length, which can appear if the FFT size changes, are
+    /// resampled to the width of the image.
+    pub fn render<L: AsRef<[f32]>>(lines: &[L], parameters: &Parameters) -> Spectrogram {
+        let bins = lines.first().map(|l| l.as_ref().len()).unwrap_or(0);
+        let width = parameters.width.unwrap_or(bins).min(bins);
+        let colormap = parameters.colormap.colormap_as_slice();
+        let colors = colormap.len() / 3;
+        let scale = 1.0 / (parameters.max - parameters.min);
+        let mut pixels = Vec::with_capacity(3 * width * lines.len());
+        for line in lines {
+            let line = line.as_ref();
+            let bins = line.len();
+            for pixel in 0..width {
+                let power = line[pixel * bins / width..(pixel + 1) * bins / width]
+                    .iter()
+                    .copied()
+                    .fold(0.0, f32::max);
+                let db = 10.0 * power.log10();
+                let normalized = ((db - parameters.min) * scale).clamp(0.0, 1.0);
+                // NaN (which is produced by a negative power) is mapped to 0,
+                // as well as the pixels of a line shorter than the image.
+                let index = ((normalized * colors as f32) as usize).min(colors - 1);
+                pixels.extend_from_slice(&colormap[3 * index..3 * (index + 1)]);
+            }
+        }
+        Spectrogram {
+            width,
+            height: lines.len(),
+            pixels,
+        }
+    }
+
+    /// Returns the width of the image in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the image in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the pixels of the image as 8-bit RGB in row-major order.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    /// Encodes the image as a PNG file.
+    pub fn to_png(&self) -> Vec<u8> {
+        let mut png = PNG_SIGNATURE.to_vec();
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&u32::try_from(self.width).unwrap().to_be_bytes());
+        ihdr.extend_from_slice(&u32::try_from(self.height).unwrap().to_be_bytes());
+        // bit depth, color type, compression method, filter method, interlace
+        // method
+        ihdr.extend_from_slice(&[8, PNG_COLOR_TYPE_RGB, 0, 0, 0]);
+        png_chunk(&mut png, b"IHDR", &ihdr);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+        if self.width > 0 {
+            for row in self.pixels.chunks_exact(3 * self.width) {
+                // Each row is preceded by its filter type, which is 0 (None)
+                encoder.write_all(&[0]).unwrap();
+                encoder.write_all(row).unwrap();
+            }
+        }
+        png_chunk(&mut png, b"IDAT", &encoder.finish().unwrap());
+        png_chunk(&mut png, b"IEND", &[]);
+        png
+    }
+}
+
+fn png_chunk(png: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&u32::try_from(data.len()).unwrap().to_be_bytes());
+    let crc_start = png.len();
+    png.extend_from_slice(chunk_type);
+    png.extend_from_slice(data);
+    let crc = crc32fast::hash(&png[crc_start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn render() {
+        // 25 dB, 60 dB, 95 dB and 85 dB
+        let lines = [[10f32.powf(2.5), 1e6, 10f32.powf(9.5), 10f32.powf(8.5)]];
+        let spectrogram = Spectrogram::render(&lines, &Parameters::default());
+        assert_eq!(spectrogram.width(), 4);
+        assert_eq!(spectrogram.height(), 1);
+        let turbo = &crate::colormap::turbo::COLORMAP;
+        assert_eq!(&spectrogram.pixels()[..3], &turbo[..3]);
+        assert_eq!(&spectrogram.pixels()[3..6], &turbo[3 * 128..3 * 129]);
+        assert_eq!(&spectrogram.pixels()[6..9], &turbo[3 * 255..]);
+        assert_eq!(&spectrogram.pixels()[9..], &turbo[3 * 255..]);
+
+        // Bins are combined by taking their maximum
+        let parameters = Parameters {
+            width: Some(2),
+            ..Default::default()
+        };
+        let spectrogram = Spectrogram::render(&lines, &parameters);
+        assert_eq!(spectrogram.width(), 2);
+        assert_eq!(&spectrogram.pixels()[..3], &turbo[3 * 128..3 * 129]);
+        assert_eq!(&spectrogram.pixels()[3..], &turbo[3 * 255..]);
+
+        // Lines with a different length are resampled
+        let lines: [&[f32]; 2] = [&[1e6, 1e6], &[10f32.powf(2.5), 1e6, 1e6, 1e6]];
+        let spectrogram = Spectrogram::render(&lines, &Parameters::default());
+        assert_eq!(spectrogram.width(), 2);
+        assert_eq!(spectrogram.height(), 2);
+        assert_eq!(&spectrogram.pixels()[6..9], &turbo[3 * 128..3 * 129]);
+    }
+
+    #[test]
+    fn png() {
+        let lines = [[1.0f32, 1e4, 1e8], [1e8, 1e4, 1.0]];
+        let spectrogram = Spectrogram::render(&lines, &Parameters::default());
+        let png = spectrogram.to_png();
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        // IHDR chunk
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 3, 0, 0, 0, 2, 8, 2, 0, 0, 0]);
+        assert_eq!(
+            u32::from_be_bytes(png[29..33].try_into().unwrap()),
+            crc32fast::hash(&png[12..29])
+        );
+        // IDAT chunk
+        let idat_len = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let mut data = Vec::new();
+        flate2::read::ZlibDecoder::new(&png[41..41 + idat_len])
+            .read_to_end(&mut data)
+            .unwrap();
+        let mut expected = Vec::new();
+        for row in spectrogram.pixels().chunks_exact(9) {
+            expected.push(0);
+            expected.extend_from_slice(row);
+        }
+        assert_eq!(data, expected);
+        // IEND chunk
+        assert_eq!(
+            &png[41 + idat_len + 4..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]
+        );
+    }
+}
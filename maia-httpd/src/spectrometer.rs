@@ -3,40 +3,143 @@
 //! This module is used for the control of the spectrometer included in the Maia
 //! SDR FPGA IP core.
 
-use crate::{app::AppState, fpga::InterruptWaiter};
+use crate::{
+    app::AppState,
+    fpga::{InterruptWaiter, IpCore},
+};
 use anyhow::Result;
 use bytes::Bytes;
-use maia_json::SpectrometerMode;
-use std::sync::Mutex;
+use chrono::prelude::*;
+use maia_json::{DDCSnr, Decibels, NoiseFloorEstimator, SpectrometerMode, SpectrometerRetuneMode};
+use noise_floor::NoiseFloor;
+use std::collections::VecDeque;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 
+mod clock;
+mod equalization;
+mod integration;
+mod noise_floor;
+
+pub use clock::ClockMeasurement;
+pub use equalization::Equalization;
+pub use integration::SoftwareIntegrator;
+
+/// Size of the spectrometer FFT.
+// TODO: do not hardcode FFT size
+pub const FFT_SIZE: u32 = 4096;
+
 // Used to obtain values in dB which are positive
 const BASE_SCALE: f32 = 4e6;
+// Minimum period of the wall clock alignment, in seconds. Shorter periods
+// cannot be aligned accurately because of the interrupt latency.
+const MIN_ALIGNMENT_PERIOD: f64 = 0.1;
+// Maximum error between the end of a spectrum and the wall clock alignment
+// boundary, in seconds.
+const ALIGNMENT_TOLERANCE: f64 = 0.01;
+// Number of spectra stored in the spectrum history.
+const SPECTRUM_HISTORY_LEN: usize = 256;
+// Number of consecutive spectra with an error larger than the tolerance after
+// which the spectrometer is realigned.
+const ALIGNMENT_MAX_MISSES: u32 = 3;
+// Maximum number of spectrum lines over which the noise floor is smoothed.
+const MAX_NOISE_FLOOR_AVERAGING: u32 = maia_json::PatchSpectrometer::MAX_NOISE_FLOOR_AVERAGING;
+// Maximum number of spectra combined in software into each spectrum line.
+const MAX_SOFTWARE_INTEGRATIONS: u32 = maia_json::PatchSpectrometer::MAX_SOFTWARE_INTEGRATIONS;
 
 /// Spectrometer.
 ///
 /// This struct waits for interrupts from the spectrometer in the FPGA IP core,
-/// reads the spectrum data, transforms it from `u64` to `f32` format, and sends
-/// it (serialized into [`Bytes`]) into a [`tokio::sync::broadcast::Sender`].
-#[derive(Debug)]
+/// reads the spectrum data, transforms it from `u64` to `f32` format, applies
+/// the [`Equalization`], combines the spectra with the
+/// [`SoftwareIntegrator`], and sends the result (as a [`SpectrumLine`]) into a
+/// [`tokio::sync::broadcast::Sender`]. The number of samples integrated in
+/// the spectra is recorded in the [`ClockMeasurement`].
+///
+/// When the wall clock alignment is enabled in the [`SpectrometerConfig`], the
+/// number of integrations is updated after each spectrum so that on average
+/// the spectra have the alignment period, and if the spectra drift away from
+/// the alignment boundaries, the current integration is aborted at the next
+/// boundary.
+///
+/// Clones of the spectrometer share the same [`InterruptWaiter`], so only one
+/// of them should be run at a time.
+#[derive(Debug, Clone)]
 pub struct Spectrometer {
     state: AppState,
-    sender: broadcast::Sender<Bytes>,
+    sender: broadcast::Sender<SpectrumLine>,
     interrupt: InterruptWaiter,
 }
 
+/// Spectrum line.
+///
+/// This struct contains a spectrum produced by the spectrometer, serialized as
+/// `f32` values in native endianness.
+#[derive(Debug, Clone)]
+pub struct SpectrumLine {
+    /// Spectrum data.
+    pub spectrum: Bytes,
+    /// Sequence number of the spectrum.
+    ///
+    /// The sequence number is incremented by one for each spectrum sent by the
+    /// spectrometer, so gaps in the sequence numbers received indicate
+    /// spectra that have been dropped.
+    pub sequence: u64,
+    /// Start time of the integration of the spectrum.
+    ///
+    /// Unless the wall clock alignment is enabled, this is estimated from the
+    /// time at which the spectrum was received and the integration time.
+    pub timestamp: DateTime<Utc>,
+    /// Whether the spectrum was produced with the wall clock alignment enabled.
+    pub wall_clock_aligned: bool,
+    /// Noise floor estimate of the spectrum in dB.
+    pub noise_floor: Option<f64>,
+}
+
+// State of the wall clock alignment.
+#[derive(Debug, Default)]
+struct Alignment {
+    // Alignment period, or None if the alignment is disabled.
+    period: Option<f64>,
+    // Accumulated fractional number of integrations.
+    accumulator: f64,
+    // Number of integrations written to the IP core in the last two
+    // spectra. The last element is the most recent.
+    written: [Option<u32>; 2],
+    // Number of consecutive spectra outside of the alignment tolerance.
+    misses: u32,
+    // Whether a realignment task is in progress.
+    realigning: Arc<AtomicBool>,
+}
+
 /// Spectrometer configuration setter.
 ///
 /// This struct gives shared access to getters and setters for the spectrometer
-/// sample rate and mode. It is used to update the sample rate and mode from
+/// sample rate, mode and retune mode. It is used to update these settings from
 /// other parts of the code.
 #[derive(Debug)]
-pub struct SpectrometerConfig(Mutex<Config>);
+pub struct SpectrometerConfig {
+    config: Mutex<Config>,
+    // Whether the next spectrum should be dropped. This is set on retune and
+    // for realignment, and cleared by the spectrometer task.
+    drop_next: AtomicBool,
+}
 
 #[derive(Debug, Clone)]
 struct Config {
     samp_rate: f32,
     mode: SpectrometerMode,
+    retune_mode: SpectrometerRetuneMode,
+    alignment_period: Option<f64>,
+    noise_floor_estimator: NoiseFloorEstimator,
+    noise_floor_averaging: u32,
+    dc_bin_removal: bool,
+    software_integrations: u32,
+    noise_floor: Option<f64>,
 }
 
 impl Spectrometer {
@@ -48,7 +151,7 @@ impl Spectrometer {
     pub fn new(
         state: AppState,
         interrupt: InterruptWaiter,
-        sender: broadcast::Sender<Bytes>,
+        sender: broadcast::Sender<SpectrumLine>,
     ) -> Spectrometer {
         Spectrometer {
             state,
@@ -64,15 +167,46 @@ impl Spectrometer {
     /// to work.
     #[tracing::instrument(name = "spectrometer", skip_all)]
     pub async fn run(self) -> Result<()> {
+        let mut alignment = Alignment::default();
+        let mut sequence = 0;
+        let (estimator, averaging) = self.state.spectrometer_config().noise_floor_estimator();
+        let mut noise_floor = NoiseFloor::new(estimator, averaging);
+        let mut integrator = SoftwareIntegrator::new(
+            self.state.spectrometer_config().software_integrations(),
+            self.state.spectrometer_config().mode(),
+        );
+        let mut last_num_integrations = None;
         loop {
             self.interrupt.wait().await;
+            let now = Utc::now();
+            let instant = Instant::now();
+            let (estimator, averaging) = self.state.spectrometer_config().noise_floor_estimator();
+            if noise_floor.config() != (estimator, averaging) {
+                noise_floor = NoiseFloor::new(estimator, averaging);
+            }
             let (samp_rate, mode) = self.state.spectrometer_config().samp_rate_mode();
+            let software_integrations = self.state.spectrometer_config().software_integrations();
+            if integrator.config() != (software_integrations, mode) {
+                integrator = SoftwareIntegrator::new(software_integrations, mode);
+            }
+            let period = self.state.spectrometer_config().alignment_period();
+            if period != alignment.period {
+                // Alignment has been enabled or its period has changed.
+                // Realign on the next spectrum.
+                alignment = Alignment {
+                    period,
+                    misses: ALIGNMENT_MAX_MISSES,
+                    ..Default::default()
+                };
+            }
             let mut ip_core = self.state.ip_core().lock().unwrap();
-            let num_integrations = ip_core.spectrometer_number_integrations() as f32;
-            let scale = match mode {
-                SpectrometerMode::Average => BASE_SCALE / (num_integrations * samp_rate),
-                SpectrometerMode::PeakDetect => BASE_SCALE / samp_rate,
-            };
+            // When the number of integrations is updated for the alignment,
+            // the integration that has just finished used the value written
+            // two spectra ago.
+            let num_integrations = alignment.written[0]
+                .unwrap_or_else(|| ip_core.spectrometer_number_integrations())
+                as f32;
+            let scale = scale(mode, num_integrations, samp_rate);
             tracing::trace!(
                 last_buffer = ip_core.spectrometer_last_buffer(),
                 samp_rate,
@@ -81,61 +215,395 @@ impl Spectrometer {
             );
             // TODO: potential optimization: do not hold the mutex locked while
             // we iterate over the buffers.
+            let mut spectra = Vec::new();
+            let mut fft_size = 0;
+            let mut drop_next = self.state.spectrometer_config().take_drop_next();
+            let dc_bin_removal = self.state.spectrometer_config().dc_bin_removal();
+            // The clock measurement needs the exact number of samples in the
+            // spectra. This is not known if an integration has been aborted,
+            // or if the number of integrations has just been changed
+            // (without the alignment, the value read from the IP core might
+            // not be the one used in the integration that has just finished).
+            let exact_samples = !drop_next
+                && match alignment.period {
+                    Some(_) => alignment.written[0].is_some(),
+                    None => last_num_integrations == Some(num_integrations),
+                };
+            last_num_integrations = Some(num_integrations);
             for buffer in ip_core.get_spectrometer_buffers() {
+                if drop_next {
+                    // This spectrum was produced by an integration aborted on
+                    // retune or for realignment.
+                    tracing::debug!("dropping spectrum after abort");
+                    drop_next = false;
+                    integrator.reset();
+                    continue;
+                }
+                fft_size = buffer.len();
+                let gains = self
+                    .state
+                    .spectrometer_equalization()
+                    .gains(samp_rate, fft_size);
+                spectra.push(Self::buffer_u64fp_to_f32(
+                    buffer,
+                    scale,
+                    gains.as_deref(),
+                    dc_bin_removal,
+                ));
+            }
+            let mut end = timestamp_secs(now);
+            let duration = match alignment.period {
+                Some(period) if !spectra.is_empty() => {
+                    // The nominal number of integrations is limited in case
+                    // that the sample rate has changed since the alignment was
+                    // enabled.
+                    let nominal = (period * f64::from(samp_rate) / fft_size as f64)
+                        .min(f64::from(IpCore::spectrometer_max_number_integrations()));
+                    let integrations = alignment.next_integrations(nominal);
+                    if let Err(err) =
+                        ip_core.set_spectrometer_next_number_integrations(integrations)
+                    {
+                        tracing::error!(%err, "could not update number of integrations");
+                    }
+                    self.check_alignment(&mut alignment, period, now);
+                    if alignment.misses == 0 {
+                        // The spectrum is aligned, so its true end is the
+                        // boundary, rather than the time at which the interrupt
+                        // was handled.
+                        end = nearest_boundary(end, period);
+                    }
+                    period
+                }
+                _ => f64::from(num_integrations) * fft_size as f64 / f64::from(samp_rate),
+            };
+            drop(ip_core);
+            if !exact_samples {
+                self.state.clock_measurement().reset();
+            } else if !spectra.is_empty() {
+                self.state.clock_measurement().record(
+                    instant,
+                    f64::from(samp_rate),
+                    spectra.len() as u64 * num_integrations as u64 * fft_size as u64,
+                );
+            }
+            let timestamps = start_times(end, duration, spectra.len());
+            let mut last_spectrum = None;
+            for (spectrum, timestamp) in spectra.into_iter().zip(timestamps) {
+                let Some((power, timestamp)) = integrator.push(spectrum, timestamp) else {
+                    continue;
+                };
+                let line_noise_floor = noise_floor.update(&power);
+                self.state
+                    .spectrometer_config()
+                    .set_noise_floor(line_noise_floor);
+                let spectrum = spectrum_bytes(power);
+                let line = SpectrumLine {
+                    spectrum: spectrum.clone(),
+                    sequence,
+                    timestamp,
+                    wall_clock_aligned: alignment.period.is_some(),
+                    noise_floor: line_noise_floor,
+                };
+                sequence += 1;
                 if self.sender.receiver_count() > 0 {
                     // It is ok if send returns Err, because there might be
                     // no receiver handles in this moment.
-                    let _ = self.sender.send(Self::buffer_u64fp_to_f32(buffer, scale));
+                    let _ = self.sender.send(line);
                 }
+                self.state.spectrum_history().push(spectrum.clone());
+                last_spectrum = Some(spectrum);
+            }
+            if let Some(spectrum) = last_spectrum {
+                *self.state.last_spectrum().lock().unwrap() = Some(spectrum);
             }
         }
     }
 
-    fn buffer_u64fp_to_f32(buffer: &[u64], scale: f32) -> Bytes {
+    // Checks whether the last spectrum ended within the tolerance of an
+    // alignment boundary. If too many spectra in a row are outside the
+    // tolerance, a task that aborts the current integration at the next
+    // boundary is spawned.
+    fn check_alignment(&self, alignment: &mut Alignment, period: f64, now: DateTime<Utc>) {
+        let now = timestamp_secs(now);
+        if (now - nearest_boundary(now, period)).abs() <= ALIGNMENT_TOLERANCE {
+            alignment.misses = 0;
+            return;
+        }
+        alignment.misses += 1;
+        if alignment.misses < ALIGNMENT_MAX_MISSES
+            || alignment.realigning.swap(true, Ordering::Relaxed)
+        {
+            return;
+        }
+        tracing::info!("realigning spectrometer to wall clock");
+        let state = self.state.clone();
+        let realigning = Arc::clone(&alignment.realigning);
+        let wait = (now / period).ceil() * period - now;
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            let ip_core = state.ip_core().lock().unwrap();
+            // The spectrum of the aborted integration is shorter than the
+            // alignment period, so it is dropped.
+            ip_core.spectrometer_abort();
+            state.spectrometer_config().set_drop_next();
+            realigning.store(false, Ordering::Relaxed);
+        });
+    }
+
+    fn buffer_u64fp_to_f32(
+        buffer: &[u64],
+        scale: f32,
+        gains: Option<&[f32]>,
+        dc_bin_removal: bool,
+    ) -> Vec<f32> {
         // The spectrometer output is in "floating point" format with an
         // exponent that occupies the 8 MSBs of the 64 value and represents
         // powers of 4, and a mantissa that occupies the LSBs. The way to parse
         // this representation is to separate the exponent and the mantissa and
-        // to shift left the mantissa by 2 times the exponent places.
+        // to shift left the mantissa by 2 times the exponent places. The
+        // equalization gains, if any, are applied together with the scale.
 
         // TODO: optimize using Neon
-        buffer
+        let mut spectrum = buffer
             .iter()
-            .flat_map(|&x| {
+            .enumerate()
+            .map(|(j, &x)| {
                 let exponent = (x >> 56) as u8;
                 let value = x & ((1u64 << 56) - 1);
                 let y = value << (2 * exponent);
-                let z = y as f32 * scale;
-                z.to_ne_bytes().into_iter()
+                y as f32 * scale * gains.map_or(1.0, |gains| gains[j])
             })
-            .collect()
+            .collect::<Vec<f32>>();
+        if dc_bin_removal {
+            remove_dc_bin(&mut spectrum);
+        }
+        spectrum
+    }
+}
+
+// Returns the bytes of a spectrum in native endianness. The Bytes owns the
+// Vec, so the spectrum is not copied.
+fn spectrum_bytes(spectrum: Vec<f32>) -> Bytes {
+    struct Spectrum(Vec<f32>);
+
+    impl AsRef<[u8]> for Spectrum {
+        fn as_ref(&self) -> &[u8] {
+            unsafe {
+                std::slice::from_raw_parts(
+                    self.0.as_ptr() as *const u8,
+                    std::mem::size_of_val(self.0.as_slice()),
+                )
+            }
+        }
     }
+
+    Bytes::from_owner(Spectrum(spectrum))
+}
+
+// Replaces the DC bin of an FFT-shifted spectrum by the average of its two
+// neighbors. The FPGA spectrometer does not have support for this, so it is
+// done in software before the spectrum is sent to the clients and used by the
+// noise floor estimator and the peak detector.
+fn remove_dc_bin(spectrum: &mut [f32]) {
+    let dc = spectrum.len() / 2;
+    if dc >= 1 && dc + 1 < spectrum.len() {
+        spectrum[dc] = 0.5 * (spectrum[dc - 1] + spectrum[dc + 1]);
+    }
+}
+
+impl Alignment {
+    // Returns the number of integrations to write to the IP core, so that the
+    // average number of integrations is equal to nominal.
+    fn next_integrations(&mut self, nominal: f64) -> u32 {
+        self.accumulator += nominal;
+        let integrations = self.accumulator.round().max(1.0);
+        self.accumulator -= integrations;
+        let integrations = integrations as u32;
+        self.written = [self.written[1], Some(integrations)];
+        integrations
+    }
+}
+
+// Returns the start times of the last num_spectra spectra, each of which has
+// the given duration, and the last of which ended at time end. Times are given
+// in seconds since the UNIX epoch.
+fn start_times(end: f64, duration: f64, num_spectra: usize) -> Vec<DateTime<Utc>> {
+    (0..num_spectra)
+        .rev()
+        .map(|j| {
+            let start = end - (j + 1) as f64 * duration;
+            DateTime::from_timestamp_nanos((start * 1e9).round() as i64)
+        })
+        .collect()
+}
+
+// Returns a time as the number of seconds since the UNIX epoch.
+fn timestamp_secs(time: DateTime<Utc>) -> f64 {
+    time.timestamp() as f64 + f64::from(time.timestamp_subsec_nanos()) * 1e-9
+}
+
+// Returns the alignment boundary nearest to a time given in seconds since the
+// UNIX epoch.
+fn nearest_boundary(time: f64, period: f64) -> f64 {
+    (time / period).round() * period
+}
+
+/// Returns the scale factor applied to the spectra.
+///
+/// The power accumulated by the FPGA spectrometer is multiplied by this
+/// factor, which depends on the spectrometer `mode`, the number of
+/// integrations and the sample rate of the spectrometer input.
+pub fn scale(mode: SpectrometerMode, num_integrations: f32, samp_rate: f32) -> f32 {
+    match mode {
+        SpectrometerMode::Average => BASE_SCALE / (num_integrations * samp_rate),
+        SpectrometerMode::PeakDetect => BASE_SCALE / samp_rate,
+    }
+}
+
+/// Measures the rate at which the spectra can be processed.
+///
+/// The processing done by the [`Spectrometer`] on each spectrum read from the
+/// FPGA IP core, which is the conversion from `u64` to `f32` format, the DC
+/// bin removal and the update of the noise floor estimate, is run on synthetic
+/// spectra of [`FFT_SIZE`] bins during `duration`. Returns the number of
+/// spectra processed per second.
+///
+/// This function blocks the thread while it runs, so in async code it should
+/// be run with [`tokio::task::spawn_blocking`].
+pub fn processing_rate(duration: Duration) -> f64 {
+    // Synthetic spectrum with values spread over several exponents
+    let buffer = (0..u64::from(FFT_SIZE))
+        .map(|j| ((j % 4) << 56) | (j.wrapping_mul(2_654_435_761) & 0xff_ffff))
+        .collect::<Vec<u64>>();
+    let scale = scale(SpectrometerMode::Average, 1000.0, 61.44e6);
+    let mut noise_floor = NoiseFloor::new(NoiseFloorEstimator::default(), 1);
+    let start = Instant::now();
+    let mut spectra = 0;
+    while start.elapsed() < duration {
+        let power = Spectrometer::buffer_u64fp_to_f32(&buffer, scale, None, true);
+        std::hint::black_box(noise_floor.update(&power));
+        std::hint::black_box(spectrum_bytes(power));
+        spectra += 1;
+    }
+    spectra as f64 / start.elapsed().as_secs_f64()
+}
+
+/// Converts the bytes of a spectrum to `f32` values.
+///
+/// The bytes are given in native endianness, as in the
+/// [`spectrum`](SpectrumLine::spectrum) field of [`SpectrumLine`].
+pub fn spectrum_from_bytes(spectrum: &[u8]) -> Vec<f32> {
+    spectrum
+        .chunks_exact(std::mem::size_of::<f32>())
+        .map(|x| f32::from_ne_bytes(x.try_into().unwrap()))
+        .collect()
+}
+
+/// Returns the median of some values.
+///
+/// The values are reordered. If the number of values is even, the larger of
+/// the two middle values is returned.
+///
+/// # Panics
+///
+/// This function panics if `values` is empty.
+pub fn median<T: PartialOrd + Copy>(values: &mut [T]) -> T {
+    let n = values.len() / 2;
+    *values
+        .select_nth_unstable_by(n, |a, b| {
+            a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .1
+}
+
+/// Estimates the SNR of a channel in a spectrum.
+///
+/// The `spectrum` contains linear power values in FFT-shifted order (the first
+/// bin corresponds to the frequency `-samp_rate / 2`). The channel is given by
+/// its center frequency `channel_freq` and its `channel_bandwidth`, which are
+/// given in Hz relative to the spectrum center. The signal power is the sum of
+/// the bins inside the channel. The noise floor per bin is estimated as the
+/// median of the bins in a region with the same width as the channel on each
+/// side of the channel.
+///
+/// If the channel does not contain any bins or there are no bins adjacent to
+/// the channel inside the spectrum, `None` is returned.
+pub fn estimate_snr(
+    spectrum: &[f32],
+    samp_rate: f64,
+    channel_freq: f64,
+    channel_bandwidth: f64,
+) -> Option<DDCSnr> {
+    let n = spectrum.len();
+    if n == 0 || samp_rate <= 0.0 {
+        return None;
+    }
+    let bin_width = samp_rate / n as f64;
+    let bin_freq = |j: usize| (j as f64 - (n / 2) as f64) * bin_width;
+    let half_bw = 0.5 * channel_bandwidth;
+    let mut channel_power = 0.0;
+    let mut channel_bins = 0;
+    let mut noise = Vec::new();
+    for (j, &x) in spectrum.iter().enumerate() {
+        let offset = (bin_freq(j) - channel_freq).abs();
+        if offset <= half_bw {
+            channel_power += f64::from(x);
+            channel_bins += 1;
+        } else if offset <= 3.0 * half_bw {
+            noise.push(f64::from(x));
+        }
+    }
+    if channel_bins == 0 || noise.is_empty() {
+        return None;
+    }
+    let noise_floor = median(&mut noise);
+    let noise_power = noise_floor * channel_bins as f64;
+    let signal_power = channel_power - noise_power;
+    Some(DDCSnr {
+        snr_db: if signal_power > 0.0 && noise_power > 0.0 {
+            Some(Decibels::from_power_ratio(signal_power / noise_power))
+        } else {
+            None
+        },
+        channel_power_db: Decibels::from_power_ratio(channel_power),
+        noise_floor_db: Decibels::from_power_ratio(noise_floor),
+    })
 }
 
 impl SpectrometerConfig {
     /// Creates a new spectrometer configuration object.
     fn new() -> SpectrometerConfig {
-        SpectrometerConfig(Mutex::new(Config {
-            samp_rate: 0.0,
-            mode: SpectrometerMode::Average,
-        }))
+        SpectrometerConfig {
+            config: Mutex::new(Config {
+                samp_rate: 0.0,
+                mode: SpectrometerMode::Average,
+                retune_mode: SpectrometerRetuneMode::default(),
+                alignment_period: None,
+                noise_floor_estimator: NoiseFloorEstimator::Median,
+                noise_floor_averaging: 8,
+                dc_bin_removal: false,
+                software_integrations: 1,
+                noise_floor: None,
+            }),
+            drop_next: AtomicBool::new(false),
+        }
     }
 
     /// Returns the spectrometer sample rate.
     ///
     /// The units are samples per second.
     pub fn samp_rate(&self) -> f32 {
-        self.0.lock().unwrap().samp_rate
+        self.config.lock().unwrap().samp_rate
     }
 
     /// Returns the spectrometer mode.
     pub fn mode(&self) -> SpectrometerMode {
-        self.0.lock().unwrap().mode
+        self.config.lock().unwrap().mode
     }
 
     /// Returns the spectrometer sample rate and mode
     pub fn samp_rate_mode(&self) -> (f32, SpectrometerMode) {
-        let conf = self.0.lock().unwrap();
+        let conf = self.config.lock().unwrap();
         (conf.samp_rate, conf.mode)
     }
 
@@ -144,20 +612,150 @@ impl SpectrometerConfig {
     /// Updates the spectrometer sample rate to the value give, in units of
     /// samples per second.
     pub fn set_samp_rate(&self, samp_rate: f32) {
-        self.0.lock().unwrap().samp_rate = samp_rate;
+        self.config.lock().unwrap().samp_rate = samp_rate;
     }
 
     /// Sets the spectrometer mode.
     pub fn set_mode(&self, mode: SpectrometerMode) {
-        self.0.lock().unwrap().mode = mode;
+        self.config.lock().unwrap().mode = mode;
     }
 
     /// Sets the spectrometer sample rate and mode.
     pub fn set_samp_rate_mode(&self, samp_rate: f32, mode: SpectrometerMode) {
-        let mut conf = self.0.lock().unwrap();
+        let mut conf = self.config.lock().unwrap();
         conf.samp_rate = samp_rate;
         conf.mode = mode;
     }
+
+    /// Returns the spectrometer retune mode.
+    pub fn retune_mode(&self) -> SpectrometerRetuneMode {
+        self.config.lock().unwrap().retune_mode
+    }
+
+    /// Sets the spectrometer retune mode.
+    pub fn set_retune_mode(&self, retune_mode: SpectrometerRetuneMode) {
+        self.config.lock().unwrap().retune_mode = retune_mode;
+    }
+
+    /// Handles a change of the receive frequency.
+    ///
+    /// This function should be called after the receive frequency has changed.
+    /// Depending on the retune mode, it aborts the current integration of the
+    /// spectrometer, and it marks the next spectrum to be dropped.
+    pub fn retune(&self, ip_core: &IpCore) {
+        match self.retune_mode() {
+            SpectrometerRetuneMode::Continue => {}
+            SpectrometerRetuneMode::Abort => ip_core.spectrometer_abort(),
+            SpectrometerRetuneMode::AbortAndDrop => {
+                ip_core.spectrometer_abort();
+                self.set_drop_next();
+            }
+        }
+    }
+
+    // Returns whether the next spectrum should be dropped, clearing the flag.
+    fn take_drop_next(&self) -> bool {
+        self.drop_next.swap(false, Ordering::AcqRel)
+    }
+
+    fn set_drop_next(&self) {
+        self.drop_next.store(true, Ordering::Release);
+    }
+
+    /// Returns the period of the wall clock alignment.
+    ///
+    /// The period is given in seconds. This returns `None` if the wall clock
+    /// alignment is disabled.
+    pub fn alignment_period(&self) -> Option<f64> {
+        self.config.lock().unwrap().alignment_period
+    }
+
+    /// Enables or disables the wall clock alignment.
+    ///
+    /// If `period` is `Some`, the spectra are aligned to integer multiples of
+    /// this period (given in seconds) counted from the UNIX epoch. The function
+    /// fails if the period is too short to be aligned accurately.
+    pub fn set_alignment_period(&self, period: Option<f64>) -> Result<()> {
+        if let Some(period) = period {
+            anyhow::ensure!(period.is_finite(), "invalid wall clock alignment period");
+            anyhow::ensure!(
+                period >= MIN_ALIGNMENT_PERIOD,
+                "wall clock alignment requires an output sampling frequency \
+                 of at most {} Hz",
+                MIN_ALIGNMENT_PERIOD.recip()
+            );
+        }
+        self.config.lock().unwrap().alignment_period = period;
+        Ok(())
+    }
+
+    /// Returns the noise floor estimator and the number of spectrum lines over
+    /// which its estimate is smoothed.
+    pub fn noise_floor_estimator(&self) -> (NoiseFloorEstimator, u32) {
+        let conf = self.config.lock().unwrap();
+        (conf.noise_floor_estimator, conf.noise_floor_averaging)
+    }
+
+    /// Sets the noise floor estimator and the number of spectrum lines over
+    /// which its estimate is smoothed.
+    ///
+    /// The function fails if the averaging is zero or too large.
+    pub fn set_noise_floor_estimator(
+        &self,
+        estimator: NoiseFloorEstimator,
+        averaging: u32,
+    ) -> Result<()> {
+        anyhow::ensure!(
+            (1..=MAX_NOISE_FLOOR_AVERAGING).contains(&averaging),
+            "noise floor averaging must be between 1 and {MAX_NOISE_FLOOR_AVERAGING}"
+        );
+        let mut conf = self.config.lock().unwrap();
+        conf.noise_floor_estimator = estimator;
+        conf.noise_floor_averaging = averaging;
+        Ok(())
+    }
+
+    /// Returns whether the DC bin of the spectra is replaced by the average of
+    /// its neighbors.
+    pub fn dc_bin_removal(&self) -> bool {
+        self.config.lock().unwrap().dc_bin_removal
+    }
+
+    /// Enables or disables the replacement of the DC bin of the spectra by the
+    /// average of its neighbors.
+    pub fn set_dc_bin_removal(&self, dc_bin_removal: bool) {
+        self.config.lock().unwrap().dc_bin_removal = dc_bin_removal;
+    }
+
+    /// Returns the number of spectra combined in software into each spectrum
+    /// line.
+    pub fn software_integrations(&self) -> u32 {
+        self.config.lock().unwrap().software_integrations
+    }
+
+    /// Sets the number of spectra combined in software into each spectrum
+    /// line.
+    ///
+    /// The function fails if the number of integrations is zero or too large.
+    pub fn set_software_integrations(&self, integrations: u32) -> Result<()> {
+        anyhow::ensure!(
+            (1..=MAX_SOFTWARE_INTEGRATIONS).contains(&integrations),
+            "software integrations must be between 1 and {MAX_SOFTWARE_INTEGRATIONS}"
+        );
+        self.config.lock().unwrap().software_integrations = integrations;
+        Ok(())
+    }
+
+    /// Returns the latest noise floor estimate in dB.
+    ///
+    /// This is `None` if the spectrometer has not produced any spectrum yet.
+    pub fn noise_floor(&self) -> Option<f64> {
+        self.config.lock().unwrap().noise_floor
+    }
+
+    fn set_noise_floor(&self, noise_floor: Option<f64>) {
+        self.config.lock().unwrap().noise_floor = noise_floor;
+    }
 }
 
 impl Default for SpectrometerConfig {
@@ -165,3 +763,180 @@ impl Default for SpectrometerConfig {
         SpectrometerConfig::new()
     }
 }
+
+/// Spectrum history.
+///
+/// This struct stores the most recent spectra produced by the spectrometer, so
+/// that they can be rendered as a spectrogram. The spectra are serialized as
+/// `f32` values in native endianness, in the same format as in
+/// [`SpectrumLine`].
+#[derive(Debug, Default)]
+pub struct SpectrumHistory {
+    spectra: Mutex<History>,
+    pushed: tokio::sync::Notify,
+}
+
+#[derive(Debug, Default)]
+struct History {
+    spectra: VecDeque<Bytes>,
+    // Total number of spectra that have been pushed
+    count: u64,
+}
+
+impl SpectrumHistory {
+    /// Maximum number of spectra stored in the history.
+    pub const CAPACITY: usize = SPECTRUM_HISTORY_LEN;
+
+    /// Creates an empty spectrum history.
+    pub fn new() -> SpectrumHistory {
+        SpectrumHistory::default()
+    }
+
+    /// Adds a spectrum to the history, discarding the oldest spectrum if the
+    /// history is full.
+    pub fn push(&self, spectrum: Bytes) {
+        {
+            let mut history = self.spectra.lock().unwrap();
+            if history.spectra.len() == Self::CAPACITY {
+                history.spectra.pop_front();
+            }
+            history.spectra.push_back(spectrum);
+            history.count += 1;
+        }
+        self.pushed.notify_waiters();
+    }
+
+    /// Returns the total number of spectra that have been added to the
+    /// history.
+    pub fn count(&self) -> u64 {
+        self.spectra.lock().unwrap().count
+    }
+
+    /// Waits until the total number of spectra that have been added to the
+    /// history is at least `count`.
+    pub async fn wait_for_count(&self, count: u64) {
+        loop {
+            let pushed = self.pushed.notified();
+            tokio::pin!(pushed);
+            pushed.as_mut().enable();
+            if self.count() >= count {
+                return;
+            }
+            pushed.await;
+        }
+    }
+
+    /// Returns the most recent spectra, starting by the most recent one.
+    ///
+    /// At most `count` spectra are returned.
+    pub fn latest(&self, count: usize) -> Vec<Bytes> {
+        self.spectra
+            .lock()
+            .unwrap()
+            .spectra
+            .iter()
+            .rev()
+            .take(count)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dc_bin_removal() {
+        let buffer = [1, 3, 5, 100, 7, 9];
+        assert_eq!(
+            Spectrometer::buffer_u64fp_to_f32(&buffer, 2.0, None, false),
+            [2.0, 6.0, 10.0, 200.0, 14.0, 18.0]
+        );
+        assert_eq!(
+            Spectrometer::buffer_u64fp_to_f32(&buffer, 2.0, None, true),
+            [2.0, 6.0, 10.0, 12.0, 14.0, 18.0]
+        );
+    }
+
+    #[test]
+    fn spectrum_to_bytes() {
+        let spectrum = vec![1.0f32, -2.5, 1e6];
+        let bytes = spectrum
+            .iter()
+            .flat_map(|z| z.to_ne_bytes())
+            .collect::<Vec<u8>>();
+        assert_eq!(spectrum_bytes(spectrum), bytes);
+    }
+
+    #[test]
+    fn alignment_integrations() {
+        let mut alignment = Alignment::default();
+        // 40 Msps with 4096-point FFT and 1 second period
+        let nominal = 40e6 / 4096.0;
+        let total = (0..1000)
+            .map(|_| alignment.next_integrations(nominal))
+            .sum::<u32>();
+        assert!((f64::from(total) - 1000.0 * nominal).abs() <= 0.5);
+        assert!(alignment
+            .written
+            .iter()
+            .all(|n| matches!(n, Some(9765 | 9766))));
+    }
+
+    #[test]
+    fn alignment_timestamps() {
+        let now = DateTime::from_timestamp(1704110400, 3_000_000).unwrap();
+        let end = nearest_boundary(timestamp_secs(now), 0.5);
+        let timestamps = start_times(end, 0.5, 2);
+        assert_eq!(
+            timestamps,
+            [
+                DateTime::from_timestamp(1704110399, 0).unwrap(),
+                DateTime::from_timestamp(1704110399, 500_000_000).unwrap(),
+            ]
+        );
+        assert!(SpectrometerConfig::new()
+            .set_alignment_period(Some(0.01))
+            .is_err());
+    }
+
+    #[test]
+    fn spectrum_history() {
+        let history = SpectrumHistory::new();
+        for j in 0..SpectrumHistory::CAPACITY + 2 {
+            history.push(Bytes::from(j.to_ne_bytes().to_vec()));
+        }
+        let latest = history.latest(3);
+        assert_eq!(latest.len(), 3);
+        assert_eq!(
+            latest[0],
+            &(SpectrumHistory::CAPACITY + 1).to_ne_bytes()[..]
+        );
+        assert_eq!(
+            latest[2],
+            &(SpectrumHistory::CAPACITY - 1).to_ne_bytes()[..]
+        );
+        assert_eq!(history.latest(usize::MAX).len(), SpectrumHistory::CAPACITY);
+        assert_eq!(history.count(), SpectrumHistory::CAPACITY as u64 + 2);
+    }
+
+    #[test]
+    fn snr_flat_noise() {
+        let spectrum = vec![1.0; 4096];
+        let snr = estimate_snr(&spectrum, 4096.0, 0.0, 100.0).unwrap();
+        assert_eq!(snr.snr_db, None);
+        assert!((snr.noise_floor_db - Decibels(0.0)).abs() < Decibels(1e-9));
+    }
+
+    #[test]
+    fn snr_tone() {
+        let mut spectrum = vec![1.0; 4096];
+        // bin 2148 corresponds to 100 Hz
+        spectrum[2148] = 1001.0;
+        let snr = estimate_snr(&spectrum, 4096.0, 100.0, 9.0).unwrap();
+        // 9 bins of noise with power 1 and a tone with power 1000
+        let expected = Decibels::from_power_ratio(1000.0 / 9.0);
+        assert!((snr.snr_db.unwrap() - expected).abs() < Decibels(1e-9));
+    }
+}
@@ -0,0 +1,150 @@
+use maia_json::Hertz;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Duration of the window over which the sample rate is measured.
+const WINDOW: Duration = Duration::from_secs(300);
+// Minimum time between the checkpoints kept in the window.
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+// Minimum measurement duration for which the measured sample rate is given.
+// Shorter measurements are dominated by the interrupt latency.
+const MIN_DURATION: Duration = Duration::from_secs(10);
+
+/// Sample clock measurement.
+///
+/// This struct is part of the [`AppState`](crate::app::AppState). It measures
+/// the actual sample rate by counting the samples integrated in the spectra
+/// received from the spectrometer during a time window measured with the
+/// system clock. The ratio between the measured and the nominal sample rate
+/// gives an estimate of the error of the ADC clock.
+#[derive(Debug, Default)]
+pub struct ClockMeasurement(Mutex<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    nominal_samp_rate: f64,
+    // Number of samples counted since the measurement started.
+    samples: u64,
+    // Times at which spectra were received, together with the number of
+    // samples counted at those times, sorted from oldest to newest.
+    checkpoints: VecDeque<(Instant, u64)>,
+    // Time at which the last spectra were received.
+    last: Option<Instant>,
+}
+
+impl ClockMeasurement {
+    /// Creates a new clock measurement, which has not started yet.
+    pub fn new() -> ClockMeasurement {
+        ClockMeasurement::default()
+    }
+
+    /// Records that spectra have been received.
+    ///
+    /// The spectra were received at `time` and contain a total of `samples`
+    /// samples at the nominal sample rate `samp_rate`. If the nominal sample
+    /// rate has changed, the measurement is restarted.
+    pub fn record(&self, time: Instant, samp_rate: f64, samples: u64) {
+        let mut inner = self.0.lock().unwrap();
+        if samp_rate != inner.nominal_samp_rate {
+            *inner = Inner {
+                nominal_samp_rate: samp_rate,
+                ..Default::default()
+            };
+        }
+        // The start time of the first spectra is not known, so they are only
+        // used as the start of the measurement.
+        if inner.last.is_some() {
+            inner.samples += samples;
+        }
+        inner.last = Some(time);
+        let samples = inner.samples;
+        if inner
+            .checkpoints
+            .back()
+            .is_none_or(|&(t, _)| time - t >= CHECKPOINT_INTERVAL)
+        {
+            inner.checkpoints.push_back((time, samples));
+        }
+        while inner
+            .checkpoints
+            .get(1)
+            .is_some_and(|&(t, _)| time - t >= WINDOW)
+        {
+            inner.checkpoints.pop_front();
+        }
+    }
+
+    /// Restarts the measurement.
+    ///
+    /// This should be called when the number of samples in the spectra that
+    /// have been received is not known exactly, such as when an integration
+    /// has been aborted.
+    pub fn reset(&self) {
+        let mut inner = self.0.lock().unwrap();
+        let nominal_samp_rate = inner.nominal_samp_rate;
+        *inner = Inner {
+            nominal_samp_rate,
+            ..Default::default()
+        };
+    }
+
+    /// Returns the estimated error of the sample clock in parts per million.
+    ///
+    /// This returns `None` if the measurement has not lasted long enough.
+    pub fn clock_error(&self) -> Option<f64> {
+        self.json().clock_error
+    }
+
+    /// Returns the JSON representation of the clock measurement.
+    pub fn json(&self) -> maia_json::SystemClock {
+        let inner = self.0.lock().unwrap();
+        let (duration, samples) = match (inner.checkpoints.front(), inner.last) {
+            (Some(&(start, start_samples)), Some(last)) => {
+                (last - start, inner.samples - start_samples)
+            }
+            _ => (Duration::ZERO, 0),
+        };
+        let measured_samp_rate =
+            (duration >= MIN_DURATION).then(|| samples as f64 / duration.as_secs_f64());
+        maia_json::SystemClock {
+            nominal_samp_rate: Hertz(inner.nominal_samp_rate),
+            measured_samp_rate: measured_samp_rate.map(Hertz),
+            clock_error: measured_samp_rate
+                .map(|rate| (rate / inner.nominal_samp_rate - 1.0) * 1e6),
+            measurement_duration: duration.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::Seconds;
+
+    #[test]
+    fn measurement() {
+        let clock = ClockMeasurement::new();
+        let samp_rate = 1e6;
+        let start = Instant::now();
+        // Spectra of 0.1 s from a clock that is 10 ppm fast
+        let samples = 100_001;
+        for j in 0..100 {
+            clock.record(start + Duration::from_millis(100 * j), samp_rate, samples);
+        }
+        let json = clock.json();
+        assert_eq!(json.nominal_samp_rate, Hertz(samp_rate));
+        assert!(json.measured_samp_rate.is_none());
+        assert!((json.measurement_duration - Seconds(9.9)).abs() < Seconds(1e-9));
+        for j in 100..4000 {
+            clock.record(start + Duration::from_millis(100 * j), samp_rate, samples);
+        }
+        let json = clock.json();
+        assert!((json.measurement_duration - Seconds::from(WINDOW)).abs() < Seconds(1.0));
+        assert!((json.measured_samp_rate.unwrap() - Hertz(1.00001e6)).abs() < Hertz(1e-3));
+        assert!((clock.clock_error().unwrap() - 10.0).abs() < 1e-3);
+        clock.reset();
+        assert!(clock.clock_error().is_none());
+        assert_eq!(clock.json().nominal_samp_rate, Hertz(samp_rate));
+    }
+}
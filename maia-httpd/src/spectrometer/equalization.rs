@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use maia_json::{Decibels, Hertz, SpectrometerEqualization, SpectrometerEqualizationPoint};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Spectrometer equalization.
+///
+/// This struct is part of the [`AppState`](crate::app::AppState). It contains
+/// the equalization table that is applied to the spectra produced by the
+/// spectrometer, and it computes the linear gain of each spectrum bin from
+/// this table. If a file is given with [`Equalization::load`], the table is
+/// read from this file and saved to it each time that it is changed.
+#[derive(Debug, Default)]
+pub struct Equalization(Mutex<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    config: SpectrometerEqualization,
+    file: Option<PathBuf>,
+    // Gains of the spectrum bins for the last sample rate and FFT size used
+    gains: Option<Gains>,
+}
+
+#[derive(Debug)]
+struct Gains {
+    samp_rate: f32,
+    fft_size: usize,
+    gains: Arc<[f32]>,
+}
+
+impl Equalization {
+    /// Creates a new equalization, which is disabled and has an empty table.
+    pub fn new() -> Equalization {
+        Equalization::default()
+    }
+
+    /// Loads the equalization table from a file.
+    ///
+    /// The `file` is remembered so that the table is saved to it when it is
+    /// changed. If the file does not exist, the current table is kept.
+    pub async fn load(&self, file: Option<PathBuf>) -> Result<()> {
+        if let Some(file) = &file {
+            match tokio::fs::read(file).await {
+                Ok(json) => {
+                    let config: SpectrometerEqualization = serde_json::from_slice(&json)
+                        .with_context(|| format!("failed to parse {file:?}"))?;
+                    config
+                        .validate()
+                        .with_context(|| format!("invalid equalization in {file:?}"))?;
+                    let mut inner = self.0.lock().unwrap();
+                    inner.config = config;
+                    inner.gains = None;
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("failed to read {file:?}")),
+            }
+        }
+        self.0.lock().unwrap().file = file;
+        Ok(())
+    }
+
+    /// Returns the JSON representation of the equalization.
+    pub fn json(&self) -> SpectrometerEqualization {
+        self.0.lock().unwrap().config.clone()
+    }
+
+    /// Replaces the equalization.
+    ///
+    /// The table must have been validated with
+    /// [`SpectrometerEqualization::validate`]. The new table is saved to the
+    /// equalization file before it is applied, so the function fails and keeps
+    /// the current table if it cannot be saved.
+    pub async fn set(&self, config: SpectrometerEqualization) -> Result<()> {
+        let file = self.0.lock().unwrap().file.clone();
+        if let Some(file) = file {
+            crate::fs::write_atomic(&file, serde_json::to_vec_pretty(&config)?)
+                .await
+                .context("failed to write equalization file")?;
+        }
+        let mut inner = self.0.lock().unwrap();
+        inner.config = config;
+        inner.gains = None;
+        Ok(())
+    }
+
+    /// Returns the linear power gains of the spectrum bins.
+    ///
+    /// The gains are given for a spectrum with `fft_size` bins in FFT-shifted
+    /// order (the first bin corresponds to the frequency `-samp_rate / 2`). This
+    /// returns `None` if the equalization is disabled or its table is empty.
+    pub fn gains(&self, samp_rate: f32, fft_size: usize) -> Option<Arc<[f32]>> {
+        let mut inner = self.0.lock().unwrap();
+        if !inner.config.enabled || inner.config.points.is_empty() {
+            return None;
+        }
+        match &inner.gains {
+            Some(gains) if gains.samp_rate == samp_rate && gains.fft_size == fft_size => {
+                Some(Arc::clone(&gains.gains))
+            }
+            _ => {
+                let bin_width = f64::from(samp_rate) / fft_size as f64;
+                let gains: Arc<[f32]> = (0..fft_size)
+                    .map(|j| {
+                        let freq = (j as f64 - (fft_size / 2) as f64) * bin_width;
+                        interpolate(&inner.config.points, Hertz(freq)).power_ratio() as f32
+                    })
+                    .collect();
+                inner.gains = Some(Gains {
+                    samp_rate,
+                    fft_size,
+                    gains: Arc::clone(&gains),
+                });
+                Some(gains)
+            }
+        }
+    }
+}
+
+// Returns the gain in dB at a frequency. The gain is interpolated linearly
+// between the points, and the gain of the first or last point is used outside
+// of the table. The points must not be empty.
+fn interpolate(points: &[SpectrometerEqualizationPoint], freq: Hertz) -> Decibels {
+    let j = points.partition_point(|point| point.frequency < freq);
+    if j == 0 {
+        return points[0].gain;
+    }
+    if j == points.len() {
+        return points[j - 1].gain;
+    }
+    let (a, b) = (points[j - 1], points[j]);
+    let t = (freq - a.frequency) / (b.frequency - a.frequency);
+    a.gain + Decibels((b.gain - a.gain).0 * t)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn point(frequency: f64, gain: f64) -> SpectrometerEqualizationPoint {
+        SpectrometerEqualizationPoint {
+            frequency: Hertz(frequency),
+            gain: Decibels(gain),
+        }
+    }
+
+    #[test]
+    fn interpolation() {
+        let points = [point(-10.0, 4.0), point(0.0, 0.0), point(10.0, 2.0)];
+        assert_eq!(interpolate(&points, Hertz(-20.0)), Decibels(4.0));
+        assert_eq!(interpolate(&points, Hertz(-5.0)), Decibels(2.0));
+        assert_eq!(interpolate(&points, Hertz(0.0)), Decibels(0.0));
+        assert_eq!(interpolate(&points, Hertz(2.5)), Decibels(0.5));
+        assert_eq!(interpolate(&points, Hertz(10.0)), Decibels(2.0));
+        assert_eq!(interpolate(&points, Hertz(20.0)), Decibels(2.0));
+    }
+
+    #[tokio::test]
+    async fn gains() {
+        let equalization = Equalization::new();
+        assert!(equalization.gains(8.0, 8).is_none());
+        let config = SpectrometerEqualization {
+            enabled: true,
+            points: vec![point(-4.0, 10.0), point(0.0, 0.0), point(4.0, 20.0)],
+        };
+        equalization.set(config.clone()).await.unwrap();
+        let gains = equalization.gains(8.0, 8).unwrap();
+        assert_eq!(gains.len(), 8);
+        assert!((gains[0] - 10.0).abs() < 1e-4);
+        assert!((gains[4] - 1.0).abs() < 1e-6);
+        assert!((gains[6] - 10.0).abs() < 1e-4);
+    }
+
+    #[tokio::test]
+    async fn persistence() {
+        let file = std::env::temp_dir().join(format!(
+            "maia-httpd-equalization-{}.json",
+            std::process::id()
+        ));
+        let config = SpectrometerEqualization {
+            enabled: true,
+            points: vec![point(-1e6, 1.0), point(1e6, 1.0)],
+        };
+        let equalization = Equalization::new();
+        equalization.load(Some(file.clone())).await.unwrap();
+        equalization.set(config.clone()).await.unwrap();
+        let loaded = Equalization::new();
+        loaded.load(Some(file.clone())).await.unwrap();
+        assert_eq!(loaded.json(), config);
+        std::fs::remove_file(file).unwrap();
+
+        // The table is kept if it cannot be saved
+        let equalization = Equalization::new();
+        equalization
+            .load(Some(
+                std::env::temp_dir().join("maia-httpd-missing/equalization.json"),
+            ))
+            .await
+            .unwrap();
+        equalization.set(config.clone()).await.unwrap_err();
+        assert_eq!(equalization.json(), SpectrometerEqualization::default());
+    }
+}
@@ -0,0 +1,314 @@
+//! Software integration.
+//!
+//! This module combines several consecutive spectra produced by the FPGA
+//! spectrometer into a single spectrum line. It gives integration times longer
+//! than those that can be obtained with the number of integrations register of
+//! the IP core, and it can be used to implement integration modes that the
+//! bitstream does not support.
+//!
+//! On the Zynq the crate is built with the `neon` target feature (see
+//! `.cargo/config.toml`), and the loops that process each bin are written with
+//! NEON instructions, so that the software integration only adds a small CPU
+//! load to the conversion of the spectra. LLVM does not vectorize `f32`
+//! arithmetic with NEON on ARMv7, because NEON flushes denormals to zero, and
+//! the NEON intrinsics are not available in stable Rust for ARMv7, so these
+//! loops use inline assembly. Other targets use plain Rust loops, which the
+//! compiler can vectorize.
+
+use chrono::prelude::*;
+use maia_json::SpectrometerMode;
+
+/// Software integrator.
+///
+/// The integrator accumulates spectra until it has the configured number of
+/// integrations, and then it produces a spectrum line. In
+/// [`SpectrometerMode::Average`] the line is the average of the spectra, and in
+/// [`SpectrometerMode::PeakDetect`] it is their maximum.
+#[derive(Debug, Clone)]
+pub struct SoftwareIntegrator {
+    integrations: u32,
+    mode: SpectrometerMode,
+    accumulator: Vec<f32>,
+    count: u32,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl SoftwareIntegrator {
+    /// Creates a new software integrator.
+    ///
+    /// The integrator combines `integrations` spectra into each line, using
+    /// the given `mode`. An integrator with a single integration returns each
+    /// spectrum unmodified.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `integrations` is zero.
+    pub fn new(integrations: u32, mode: SpectrometerMode) -> SoftwareIntegrator {
+        assert!(integrations >= 1);
+        SoftwareIntegrator {
+            integrations,
+            mode,
+            accumulator: Vec::new(),
+            count: 0,
+            timestamp: None,
+        }
+    }
+
+    /// Returns the number of integrations and the mode of the integrator.
+    pub fn config(&self) -> (u32, SpectrometerMode) {
+        (self.integrations, self.mode)
+    }
+
+    /// Discards the spectra accumulated so far.
+    ///
+    /// This should be called when the spectra that follow are not continuous
+    /// with the previous ones, for instance because a spectrum has been
+    /// dropped.
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.timestamp = None;
+    }
+
+    /// Adds a spectrum to the integration.
+    ///
+    /// The `timestamp` is the start time of the spectrum. When the spectrum
+    /// completes an integration, the integrated spectrum is returned together
+    /// with the start time of the first spectrum in the integration. If the
+    /// spectrum size changes, the spectra accumulated so far are discarded.
+    pub fn push(
+        &mut self,
+        spectrum: Vec<f32>,
+        timestamp: DateTime<Utc>,
+    ) -> Option<(Vec<f32>, DateTime<Utc>)> {
+        if self.integrations == 1 {
+            return Some((spectrum, timestamp));
+        }
+        if self.count == 0 || self.accumulator.len() != spectrum.len() {
+            self.accumulator = spectrum;
+            self.count = 1;
+            self.timestamp = Some(timestamp);
+            return None;
+        }
+        match self.mode {
+            SpectrometerMode::Average => accumulate_sum(&mut self.accumulator, &spectrum),
+            SpectrometerMode::PeakDetect => accumulate_max(&mut self.accumulator, &spectrum),
+        }
+        self.count += 1;
+        if self.count < self.integrations {
+            return None;
+        }
+        let mut line = std::mem::take(&mut self.accumulator);
+        if let SpectrometerMode::Average = self.mode {
+            scale(&mut line, (self.integrations as f32).recip());
+        }
+        let timestamp = self.timestamp.take().unwrap();
+        self.count = 0;
+        Some((line, timestamp))
+    }
+}
+
+fn accumulate_sum(accumulator: &mut [f32], spectrum: &[f32]) {
+    #[cfg(all(target_arch = "arm", target_feature = "neon"))]
+    let (accumulator, spectrum) = neon::accumulate_sum(accumulator, spectrum);
+    for (acc, &x) in accumulator.iter_mut().zip(spectrum) {
+        *acc += x;
+    }
+}
+
+fn accumulate_max(accumulator: &mut [f32], spectrum: &[f32]) {
+    #[cfg(all(target_arch = "arm", target_feature = "neon"))]
+    let (accumulator, spectrum) = neon::accumulate_max(accumulator, spectrum);
+    for (acc, &x) in accumulator.iter_mut().zip(spectrum) {
+        // This is written as a select rather than with f32::max, which has to
+        // handle NaNs and is not vectorized.
+        *acc = if x > *acc { x } else { *acc };
+    }
+}
+
+fn scale(spectrum: &mut [f32], factor: f32) {
+    #[cfg(all(target_arch = "arm", target_feature = "neon"))]
+    let spectrum = neon::scale(spectrum, factor);
+    for x in spectrum.iter_mut() {
+        *x *= factor;
+    }
+}
+
+// NEON implementations of the loops. Each function processes the elements in
+// groups of 4 and returns the remaining elements, which are processed by the
+// caller.
+#[cfg(all(target_arch = "arm", target_feature = "neon"))]
+mod neon {
+    use std::arch::asm;
+
+    const LANES: usize = 4;
+
+    pub fn accumulate_sum<'a, 'b>(
+        accumulator: &'a mut [f32],
+        spectrum: &'b [f32],
+    ) -> (&'a mut [f32], &'b [f32]) {
+        let len = accumulator.len().min(spectrum.len());
+        let groups = len / LANES;
+        if groups != 0 {
+            unsafe {
+                asm!(
+                    "2:",
+                    "vld1.32 {{d0, d1}}, [{acc}]",
+                    "vld1.32 {{d2, d3}}, [{x}]!",
+                    "vadd.f32 q0, q0, q1",
+                    "vst1.32 {{d0, d1}}, [{acc}]!",
+                    "subs {n}, {n}, #1",
+                    "bne 2b",
+                    acc = inout(reg) accumulator.as_mut_ptr() => _,
+                    x = inout(reg) spectrum.as_ptr() => _,
+                    n = inout(reg) groups => _,
+                    out("q0") _,
+                    out("q1") _,
+                    options(nostack),
+                );
+            }
+        }
+        let done = groups * LANES;
+        (&mut accumulator[done..len], &spectrum[done..len])
+    }
+
+    // The NEON maximum returns NaN if any of its inputs is NaN, unlike the
+    // select used in the Rust loop. The spectra never contain NaNs.
+    pub fn accumulate_max<'a, 'b>(
+        accumulator: &'a mut [f32],
+        spectrum: &'b [f32],
+    ) -> (&'a mut [f32], &'b [f32]) {
+        let len = accumulator.len().min(spectrum.len());
+        let groups = len / LANES;
+        if groups != 0 {
+            unsafe {
+                asm!(
+                    "2:",
+                    "vld1.32 {{d0, d1}}, [{acc}]",
+                    "vld1.32 {{d2, d3}}, [{x}]!",
+                    "vmax.f32 q0, q0, q1",
+                    "vst1.32 {{d0, d1}}, [{acc}]!",
+                    "subs {n}, {n}, #1",
+                    "bne 2b",
+                    acc = inout(reg) accumulator.as_mut_ptr() => _,
+                    x = inout(reg) spectrum.as_ptr() => _,
+                    n = inout(reg) groups => _,
+                    out("q0") _,
+                    out("q1") _,
+                    options(nostack),
+                );
+            }
+        }
+        let done = groups * LANES;
+        (&mut accumulator[done..len], &spectrum[done..len])
+    }
+
+    pub fn scale(spectrum: &mut [f32], factor: f32) -> &mut [f32] {
+        let groups = spectrum.len() / LANES;
+        if groups != 0 {
+            unsafe {
+                asm!(
+                    "vdup.32 q1, {factor}",
+                    "2:",
+                    "vld1.32 {{d0, d1}}, [{x}]",
+                    "vmul.f32 q0, q0, q1",
+                    "vst1.32 {{d0, d1}}, [{x}]!",
+                    "subs {n}, {n}, #1",
+                    "bne 2b",
+                    factor = in(reg) factor.to_bits(),
+                    x = inout(reg) spectrum.as_mut_ptr() => _,
+                    n = inout(reg) groups => _,
+                    out("q0") _,
+                    out("q1") _,
+                    options(nostack),
+                );
+            }
+        }
+        &mut spectrum[groups * LANES..]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn single_integration() {
+        let mut integrator = SoftwareIntegrator::new(1, SpectrometerMode::Average);
+        let now = Utc::now();
+        assert_eq!(
+            integrator.push(vec![1.0, 2.0], now),
+            Some((vec![1.0, 2.0], now))
+        );
+    }
+
+    #[test]
+    fn average() {
+        let mut integrator = SoftwareIntegrator::new(3, SpectrometerMode::Average);
+        let start = Utc::now();
+        assert!(integrator.push(vec![1.0, 2.0], start).is_none());
+        assert!(integrator.push(vec![2.0, 4.0], Utc::now()).is_none());
+        assert_eq!(
+            integrator.push(vec![6.0, 0.0], Utc::now()),
+            Some((vec![3.0, 2.0], start))
+        );
+        // the next integration starts from scratch
+        let start = Utc::now();
+        assert!(integrator.push(vec![3.0, 3.0], start).is_none());
+        assert!(integrator.push(vec![3.0, 3.0], Utc::now()).is_none());
+        assert_eq!(
+            integrator.push(vec![3.0, 3.0], Utc::now()),
+            Some((vec![3.0, 3.0], start))
+        );
+    }
+
+    #[test]
+    fn peak_detect() {
+        let mut integrator = SoftwareIntegrator::new(2, SpectrometerMode::PeakDetect);
+        let start = Utc::now();
+        assert!(integrator.push(vec![1.0, 5.0, 3.0], start).is_none());
+        assert_eq!(
+            integrator.push(vec![4.0, 2.0, 3.0], Utc::now()),
+            Some((vec![4.0, 5.0, 3.0], start))
+        );
+    }
+
+    #[test]
+    fn long_spectra() {
+        // These cover both the groups of elements processed with NEON and
+        // the remaining elements
+        let a = (0..4099).map(|j| j as f32).collect::<Vec<f32>>();
+        let b = (0..4099).map(|j| (4098 - j) as f32).collect::<Vec<f32>>();
+        let now = Utc::now();
+        let mut integrator = SoftwareIntegrator::new(2, SpectrometerMode::Average);
+        assert!(integrator.push(a.clone(), now).is_none());
+        assert_eq!(
+            integrator.push(b.clone(), now),
+            Some((vec![2049.0; 4099], now))
+        );
+        let mut integrator = SoftwareIntegrator::new(2, SpectrometerMode::PeakDetect);
+        assert!(integrator.push(a.clone(), now).is_none());
+        let max = a.iter().zip(&b).map(|(x, y)| x.max(*y)).collect();
+        assert_eq!(integrator.push(b, now), Some((max, now)));
+    }
+
+    #[test]
+    fn reset_and_size_change() {
+        let mut integrator = SoftwareIntegrator::new(2, SpectrometerMode::Average);
+        assert!(integrator.push(vec![100.0], Utc::now()).is_none());
+        integrator.reset();
+        let start = Utc::now();
+        assert!(integrator.push(vec![1.0], start).is_none());
+        assert_eq!(
+            integrator.push(vec![3.0], Utc::now()),
+            Some((vec![2.0], start))
+        );
+        assert!(integrator.push(vec![1.0], Utc::now()).is_none());
+        // a spectrum with a different size restarts the integration
+        let start = Utc::now();
+        assert!(integrator.push(vec![1.0, 1.0], start).is_none());
+        assert_eq!(
+            integrator.push(vec![3.0, 5.0], Utc::now()),
+            Some((vec![2.0, 3.0], start))
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use maia_json::NoiseFloorEstimator;
+use std::collections::VecDeque;
+
+// Number of spectrum lines over which the minimum statistics estimator takes
+// the minimum.
+const MINIMUM_STATISTICS_WINDOW: usize = 64;
+
+// Noise floor estimator.
+#[derive(Debug, Clone)]
+pub(super) struct NoiseFloor {
+    estimator: NoiseFloorEstimator,
+    averaging: u32,
+    // Smoothed median, in linear units
+    smoothed: Option<f64>,
+    // Most recent smoothed medians, used by the minimum statistics estimator
+    window: VecDeque<f64>,
+}
+
+impl NoiseFloor {
+    pub(super) fn new(estimator: NoiseFloorEstimator, averaging: u32) -> NoiseFloor {
+        NoiseFloor {
+            estimator,
+            averaging,
+            smoothed: None,
+            window: VecDeque::with_capacity(MINIMUM_STATISTICS_WINDOW),
+        }
+    }
+
+    // Returns the estimator and averaging with which this was created.
+    pub(super) fn config(&self) -> (NoiseFloorEstimator, u32) {
+        (self.estimator, self.averaging)
+    }
+
+    // Updates the estimate with a new spectrum line, which contains linear
+    // power values, and returns the estimate in dB.
+    pub(super) fn update(&mut self, spectrum: &[f32]) -> Option<f64> {
+        if spectrum.is_empty() {
+            return None;
+        }
+        let median = f64::from(super::median(&mut spectrum.to_vec()));
+        let alpha = f64::from(self.averaging.max(1)).recip();
+        let smoothed = match self.smoothed {
+            Some(smoothed) => smoothed + alpha * (median - smoothed),
+            None => median,
+        };
+        self.smoothed = Some(smoothed);
+        let estimate = match self.estimator {
+            NoiseFloorEstimator::Median => smoothed,
+            NoiseFloorEstimator::MinimumStatistics => {
+                if self.window.len() == MINIMUM_STATISTICS_WINDOW {
+                    self.window.pop_front();
+                }
+                self.window.push_back(smoothed);
+                self.window.iter().copied().fold(f64::INFINITY, f64::min)
+            }
+        };
+        Some(10.0 * estimate.log10())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Returns a spectrum with noise floor `noise` and a signal in a quarter of
+    // the bins
+    fn spectrum(noise: f32, signal: f32) -> Vec<f32> {
+        let mut spectrum = vec![noise; 64];
+        spectrum[..16].fill(signal);
+        spectrum
+    }
+
+    #[test]
+    fn median() {
+        let mut noise_floor = NoiseFloor::new(NoiseFloorEstimator::Median, 1);
+        assert_eq!(noise_floor.update(&[]), None);
+        assert_eq!(noise_floor.update(&spectrum(10.0, 1e4)), Some(10.0));
+        assert_eq!(noise_floor.update(&spectrum(100.0, 1e4)), Some(20.0));
+
+        let mut noise_floor = NoiseFloor::new(NoiseFloorEstimator::Median, 4);
+        noise_floor.update(&spectrum(100.0, 1e4));
+        let estimate = noise_floor.update(&spectrum(500.0, 1e4)).unwrap();
+        assert!((estimate - 10.0 * 200.0f64.log10()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn minimum_statistics() {
+        let mut noise_floor = NoiseFloor::new(NoiseFloorEstimator::MinimumStatistics, 1);
+        assert_eq!(noise_floor.update(&spectrum(10.0, 1e4)), Some(10.0));
+        // A wideband signal that occupies the whole spectrum does not raise the
+        // estimate
+        assert_eq!(noise_floor.update(&spectrum(1e4, 1e4)), Some(10.0));
+        assert_eq!(noise_floor.update(&spectrum(1.0, 1e4)), Some(0.0));
+        // The estimate recovers once the minimum leaves the window
+        for _ in 0..MINIMUM_STATISTICS_WINDOW {
+            noise_floor.update(&spectrum(100.0, 1e4));
+        }
+        assert_eq!(noise_floor.update(&spectrum(100.0, 1e4)), Some(20.0));
+    }
+}
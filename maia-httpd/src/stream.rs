@@ -0,0 +1,386 @@
+//! IQ sample streams.
+//!
+//! This module forwards the IQ samples produced by the DDC over the network,
+//! so that they can be processed in real time by existing SDR software running
+//! on a host PC. The blocks of IQ samples are obtained by the
+//! [`CaptureRunner`](crate::capture::CaptureRunner), so there are gaps between
+//! consecutive blocks.
+//!
+//! Samples are sent in `cf32_le` format (interleaved 32-bit floating point
+//! little-endian I and Q, normalized so that the full scale of the recorder
+//! corresponds to an amplitude of one). The following protocols are
+//! supported:
+//!
+//! - UDP. Each datagram contains a header of [`UDP_HEADER_SIZE`] bytes followed
+//!   by up to [`UDP_SAMPLES_PER_DATAGRAM`] samples. The header contains the
+//!   sequence number of the datagram as a `u64` in little-endian, which
+//!   increments by one in each datagram, and the value of the sample counter of
+//!   the FPGA IP core for the first sample of the datagram as a `u64` in
+//!   little-endian, or `u64::MAX` if the IP core does not have a sample
+//!   counter. The sample counter increments by the DDC decimation factor in
+//!   each sample, so a jump in the sample counter indicates a gap between
+//!   blocks.
+//!
+//! - ZeroMQ. A PUB socket is bound to the given endpoint, and each block is
+//!   published as a single message that contains the samples, without any
+//!   header. This is compatible with the ZMQ SUB Source block of GNU Radio.
+//!   ZeroMQ is only available if maia-httpd is built with the `zeromq`
+//!   feature.
+
+use crate::capture::IqBlock;
+use crate::demodulator::UdpOutput;
+use anyhow::Result;
+use bytes::{BufMut, BytesMut};
+use maia_json::StreamProtocol;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// Size in bytes of the header of the UDP datagrams.
+pub const UDP_HEADER_SIZE: usize = 16;
+/// Maximum number of samples in each UDP datagram.
+///
+/// This is chosen so that datagrams fit in a 1500 byte Ethernet MTU.
+pub const UDP_SAMPLES_PER_DATAGRAM: usize = 182;
+// Length of the queue of messages waiting to be sent by a ZeroMQ socket. When
+// the queue is full, blocks are dropped.
+#[cfg(feature = "zeromq")]
+const ZMQ_QUEUE_LENGTH: usize = 4;
+
+/// IQ streams.
+///
+/// This struct contains the IQ streams that are running. It is used by the
+/// REST API and by the [`CaptureRunner`](crate::capture::CaptureRunner).
+#[derive(Debug, Default)]
+pub struct Streams {
+    instances: Mutex<BTreeMap<String, Instance>>,
+    changed: Notify,
+}
+
+#[derive(Debug)]
+struct Instance {
+    address: String,
+    sink: Sink,
+    packets: u64,
+}
+
+#[derive(Debug)]
+enum Sink {
+    Udp {
+        output: UdpOutput,
+        sequence: u64,
+    },
+    #[cfg(feature = "zeromq")]
+    Zmq {
+        sender: tokio::sync::mpsc::Sender<bytes::Bytes>,
+        task: tokio::task::JoinHandle<()>,
+    },
+}
+
+impl Instance {
+    fn json(&self, name: &str) -> maia_json::Stream {
+        maia_json::Stream {
+            name: name.to_string(),
+            protocol: self.sink.protocol(),
+            address: self.address.clone(),
+            packets: self.packets,
+        }
+    }
+}
+
+impl Sink {
+    // Creates a sink. Besides the sink, this returns its address. For ZeroMQ
+    // this is the endpoint that has been bound, in which port 0 has been
+    // replaced by the port assigned by the operating system.
+    async fn new(protocol: StreamProtocol, address: &str) -> Result<(Sink, String)> {
+        match protocol {
+            StreamProtocol::UDP => {
+                let address: SocketAddr = address
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("invalid UDP address {address}"))?;
+                let sink = Sink::Udp {
+                    output: UdpOutput::new(address)?,
+                    sequence: 0,
+                };
+                Ok((sink, address.to_string()))
+            }
+            #[cfg(feature = "zeromq")]
+            StreamProtocol::ZMQ => {
+                use zeromq::{Socket, SocketSend};
+                let mut socket = zeromq::PubSocket::new();
+                let endpoint = socket.bind(address).await?;
+                let (sender, mut receiver) =
+                    tokio::sync::mpsc::channel::<bytes::Bytes>(ZMQ_QUEUE_LENGTH);
+                let task = tokio::spawn(async move {
+                    while let Some(message) = receiver.recv().await {
+                        if let Err(err) = socket.send(message.into()).await {
+                            tracing::warn!("failed to send ZeroMQ message: {err}");
+                        }
+                    }
+                });
+                Ok((Sink::Zmq { sender, task }, endpoint.to_string()))
+            }
+            #[cfg(not(feature = "zeromq"))]
+            StreamProtocol::ZMQ => {
+                anyhow::bail!("maia-httpd has been built without ZeroMQ support")
+            }
+        }
+    }
+
+    fn protocol(&self) -> StreamProtocol {
+        match self {
+            Sink::Udp { .. } => StreamProtocol::UDP,
+            #[cfg(feature = "zeromq")]
+            Sink::Zmq { .. } => StreamProtocol::ZMQ,
+        }
+    }
+
+    // Sends a block of samples. Returns the number of packets sent.
+    fn send(&mut self, block: &IqBlock<'_>) -> Result<u64> {
+        match self {
+            Sink::Udp { output, sequence } => {
+                let mut packets = 0;
+                for (j, chunk) in block.samples.chunks(UDP_SAMPLES_PER_DATAGRAM).enumerate() {
+                    let sample_counter = block
+                        .sample_counter
+                        .map(|counter| {
+                            counter + (j * UDP_SAMPLES_PER_DATAGRAM * block.decimation) as u64
+                        })
+                        .unwrap_or(u64::MAX);
+                    let mut datagram = BytesMut::with_capacity(UDP_HEADER_SIZE + 8 * chunk.len());
+                    datagram.put_u64_le(*sequence);
+                    datagram.put_u64_le(sample_counter);
+                    put_samples(&mut datagram, chunk);
+                    output.send(&datagram)?;
+                    *sequence += 1;
+                    packets += 1;
+                }
+                Ok(packets)
+            }
+            #[cfg(feature = "zeromq")]
+            Sink::Zmq { sender, .. } => {
+                let mut message = BytesMut::with_capacity(8 * block.samples.len());
+                put_samples(&mut message, block.samples);
+                // The block is dropped if the queue is full.
+                Ok(u64::from(sender.try_send(message.freeze()).is_ok()))
+            }
+        }
+    }
+
+    async fn close(self) {
+        #[cfg(feature = "zeromq")]
+        if let Sink::Zmq { sender, task } = self {
+            // Dropping the sender causes the task to finish, which drops the
+            // socket, so that its endpoint can be bound again.
+            drop(sender);
+            let _ = task.await;
+        }
+    }
+}
+
+fn put_samples(buffer: &mut BytesMut, samples: &[num_complex::Complex32]) {
+    for x in samples {
+        buffer.put_f32_le(x.re);
+        buffer.put_f32_le(x.im);
+    }
+}
+
+impl Streams {
+    /// Creates a new object with no streams running.
+    pub fn new() -> Streams {
+        Streams::default()
+    }
+
+    /// Returns the protocols that can be used by the streams.
+    ///
+    /// ZeroMQ is only supported if maia-httpd has been built with the `zeromq`
+    /// feature.
+    pub fn protocols() -> Vec<StreamProtocol> {
+        let mut protocols = vec![StreamProtocol::UDP];
+        if cfg!(feature = "zeromq") {
+            protocols.push(StreamProtocol::ZMQ);
+        }
+        protocols
+    }
+
+    /// Starts a stream.
+    ///
+    /// The stream is identified by its `name`. If there is already a stream
+    /// with the same name, it is stopped before starting the new stream.
+    pub async fn start(
+        &self,
+        name: &str,
+        protocol: StreamProtocol,
+        address: &str,
+    ) -> Result<maia_json::Stream> {
+        let old = self.instances.lock().unwrap().remove(name);
+        if let Some(old) = old {
+            old.sink.close().await;
+        }
+        let (sink, address) = Sink::new(protocol, address).await?;
+        let instance = Instance {
+            address,
+            sink,
+            packets: 0,
+        };
+        let json = instance.json(name);
+        tracing::info!(name, %protocol, address = json.address, "starting stream");
+        self.instances
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), instance);
+        self.changed.notify_one();
+        Ok(json)
+    }
+
+    /// Stops a stream.
+    ///
+    /// Returns `None` if there is no stream with this name.
+    pub async fn stop(&self, name: &str) -> Option<maia_json::Stream> {
+        let instance = self.instances.lock().unwrap().remove(name)?;
+        let json = instance.json(name);
+        instance.sink.close().await;
+        tracing::info!(name, "stopped stream");
+        Some(json)
+    }
+
+    /// Returns the JSON description of a stream.
+    ///
+    /// Returns `None` if there is no stream with this name.
+    pub fn instance_json(&self, name: &str) -> Option<maia_json::Stream> {
+        self.instances
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|instance| instance.json(name))
+    }
+
+    /// Returns the JSON description of the streams.
+    pub fn json(&self) -> maia_json::Streams {
+        maia_json::Streams {
+            protocols: Streams::protocols(),
+            instances: self
+                .instances
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(name, instance)| instance.json(name))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.instances.lock().unwrap().is_empty()
+    }
+
+    pub(crate) fn changed(&self) -> &Notify {
+        &self.changed
+    }
+
+    pub(crate) fn process(&self, block: &IqBlock<'_>) {
+        let mut instances = self.instances.lock().unwrap();
+        for (name, instance) in instances.iter_mut() {
+            match instance.sink.send(block) {
+                Ok(packets) => instance.packets += packets,
+                Err(err) => tracing::warn!(name, "failed to send stream: {err:#}"),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_complex::Complex32;
+
+    #[tokio::test]
+    async fn udp_stream() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        let streams = Streams::new();
+        assert!(streams
+            .start("iq", StreamProtocol::UDP, "localhost")
+            .await
+            .is_err());
+        streams
+            .start("iq", StreamProtocol::UDP, &address)
+            .await
+            .unwrap();
+        let samples = (0..200)
+            .map(|n| Complex32::new(n as f32, -(n as f32)))
+            .collect::<Vec<_>>();
+        streams.process(&IqBlock {
+            samples: &samples,
+            sample_rate: 1e6,
+            frequency: 100e6,
+            sample_counter: Some(1000),
+            decimation: 4,
+        });
+        assert_eq!(streams.instance_json("iq").unwrap().packets, 2);
+        let mut datagram = [0; 2048];
+        let len = socket.recv(&mut datagram).unwrap();
+        assert_eq!(len, UDP_HEADER_SIZE + 8 * UDP_SAMPLES_PER_DATAGRAM);
+        assert_eq!(datagram[..8], 0u64.to_le_bytes());
+        assert_eq!(datagram[8..16], 1000u64.to_le_bytes());
+        assert_eq!(
+            datagram[16 + 8..16 + 16],
+            [1.0f32, -1.0].map(f32::to_le_bytes).concat()
+        );
+        let len = socket.recv(&mut datagram).unwrap();
+        assert_eq!(len, UDP_HEADER_SIZE + 8 * (200 - UDP_SAMPLES_PER_DATAGRAM));
+        assert_eq!(datagram[..8], 1u64.to_le_bytes());
+        assert_eq!(
+            datagram[8..16],
+            (1000 + 4 * UDP_SAMPLES_PER_DATAGRAM as u64).to_le_bytes()
+        );
+        assert_eq!(streams.stop("iq").await.unwrap().packets, 2);
+        assert!(streams.is_empty());
+    }
+
+    #[cfg(feature = "zeromq")]
+    #[tokio::test]
+    async fn zmq_stream() {
+        use zeromq::{Socket, SocketRecv};
+        let streams = Streams::new();
+        let json = streams
+            .start("iq", StreamProtocol::ZMQ, "tcp://127.0.0.1:0")
+            .await
+            .unwrap();
+        let endpoint = json.address;
+        assert!(!endpoint.ends_with(":0"));
+        // replacing the stream binds the same endpoint again
+        streams
+            .start("iq", StreamProtocol::ZMQ, &endpoint)
+            .await
+            .unwrap();
+        let mut sub = zeromq::SubSocket::new();
+        sub.connect(&endpoint).await.unwrap();
+        sub.subscribe("").await.unwrap();
+        let samples = [Complex32::new(0.5, -0.25); 4];
+        let block = IqBlock {
+            samples: &samples,
+            sample_rate: 1e6,
+            frequency: 100e6,
+            sample_counter: None,
+            decimation: 1,
+        };
+        let message = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            // the subscription takes some time to reach the PUB socket
+            loop {
+                streams.process(&block);
+                tokio::select! {
+                    message = sub.recv() => break message.unwrap(),
+                    _ = tokio::time::sleep(std::time::Duration::from_millis(10)) => {}
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            message.get(0).unwrap()[..],
+            [0.5f32, -0.25].map(f32::to_le_bytes).concat().repeat(4)
+        );
+        streams.stop("iq").await.unwrap();
+    }
+}
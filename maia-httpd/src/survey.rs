@@ -0,0 +1,723 @@
+//! Spectrum surveys.
+//!
+//! This module implements scheduled spectrum surveys for unattended long-term
+//! spectrum monitoring. Each survey has a cron-like [`Schedule`] and a list of
+//! frequency bands. When a survey runs, the AD9361 RX frequency is swept
+//! across each of the bands, and the spectra produced by the spectrometer at
+//! each frequency are used to compute the occupancy and maximum power of each
+//! channel and a list of the strongest peaks. The results are stored in a
+//! [`maia_json::SurveyReport`]. The RX frequency is restored once the survey
+//! finishes.
+//!
+//! The surveys are run by the [`SurveyRunner`]. Surveys cannot run while the
+//! recorder is in use or when the spectrometer input is the DDC. A survey owns
+//! the RX LO through the [`Tuner`](crate::tuner::Tuner) while it runs, so it
+//! cannot run at the same time as a frequency-hopping recording or the
+//! scanner, and recordings cannot be started while it runs.
+
+use crate::{app::AppState, spectrometer, tuner::TunerOwner};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use maia_json::{
+    Hertz, PatchAd9361, PutSurvey, Seconds, SurveyBandReport, SurveyReport, SurveyReportSummary,
+};
+use statistics::BandStatistics;
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+pub use schedule::Schedule;
+
+mod schedule;
+mod statistics;
+
+// Maximum number of reports of each survey that are kept in memory.
+const MAX_REPORTS: usize = 16;
+// Maximum number of channels in a band.
+const MAX_CHANNELS: usize = 1 << 16;
+// Maximum dwell time.
+const MAX_DWELL: Seconds = Seconds(60.0);
+// Time that the spectrometer can take to produce a spectrum before a survey
+// fails.
+const SPECTRUM_TIMEOUT: Duration = Duration::from_secs(10);
+// Tuning range of the AD9361 RX LO.
+const RX_LO_MIN: Hertz = Hertz(*PatchAd9361::RX_LO_FREQUENCY_RANGE.start());
+const RX_LO_MAX: Hertz = Hertz(*PatchAd9361::RX_LO_FREQUENCY_RANGE.end());
+// Name of the file in the survey directory that stores the surveys.
+const SURVEYS_FILENAME: &str = "surveys.json";
+
+/// Spectrum surveys.
+///
+/// This struct contains the configured spectrum surveys and their reports. It
+/// is used by the REST API and by the [`SurveyRunner`].
+#[derive(Debug, Default)]
+pub struct Surveys {
+    inner: Mutex<Inner>,
+    changed: Notify,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    surveys: BTreeMap<String, Entry>,
+    next_report_id: u64,
+    // Whether the configuration has changed since it was last saved
+    config_changed: bool,
+}
+
+#[derive(Debug)]
+struct Entry {
+    config: PutSurvey,
+    schedule: Option<Schedule>,
+    next_run: Option<DateTime<Utc>>,
+    run_requested: bool,
+    running: bool,
+    reports: VecDeque<Arc<SurveyReport>>,
+}
+
+impl Entry {
+    fn json(&self, name: &str) -> maia_json::Survey {
+        maia_json::Survey {
+            name: name.to_string(),
+            config: self.config.clone(),
+            next_run: self.next_run.map(|t| t.timestamp_micros() as f64 * 1e-3),
+            running: self.running || self.run_requested,
+            reports: self
+                .reports
+                .iter()
+                .map(|report| report.summary.clone())
+                .collect(),
+        }
+    }
+}
+
+impl Surveys {
+    /// Creates an empty list of surveys.
+    pub fn new() -> Surveys {
+        Surveys::default()
+    }
+
+    /// Returns the JSON representation of the surveys.
+    pub fn json(&self) -> maia_json::Surveys {
+        maia_json::Surveys {
+            surveys: self
+                .inner
+                .lock()
+                .unwrap()
+                .surveys
+                .iter()
+                .map(|(name, entry)| entry.json(name))
+                .collect(),
+        }
+    }
+
+    /// Returns the JSON representation of a survey.
+    ///
+    /// Returns `None` if there is no survey with this name.
+    pub fn survey_json(&self, name: &str) -> Option<maia_json::Survey> {
+        let inner = self.inner.lock().unwrap();
+        inner.surveys.get(name).map(|entry| entry.json(name))
+    }
+
+    /// Configures a survey.
+    ///
+    /// If a survey with the same name exists, its configuration is replaced
+    /// and its reports are kept.
+    pub fn set(&self, name: &str, config: PutSurvey) -> Result<maia_json::Survey> {
+        anyhow::ensure!(
+            !name.is_empty()
+                && name.len() <= 64
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+            "survey name must be formed by up to 64 letters, digits, '_' and '-'"
+        );
+        let schedule = check_config(&config)?;
+        let next_run = schedule.as_ref().and_then(|s| s.next_after(Utc::now()));
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner
+            .surveys
+            .entry(name.to_string())
+            .and_modify(|entry| {
+                entry.config = config.clone();
+                entry.schedule = schedule.clone();
+                entry.next_run = next_run;
+            })
+            .or_insert_with(|| Entry {
+                config,
+                schedule,
+                next_run,
+                run_requested: false,
+                running: false,
+                reports: VecDeque::new(),
+            });
+        let json = entry.json(name);
+        inner.config_changed = true;
+        drop(inner);
+        self.changed.notify_one();
+        Ok(json)
+    }
+
+    /// Removes a survey.
+    ///
+    /// Returns `None` if there is no survey with this name. A survey that is
+    /// running is not stopped, but its report is not stored.
+    pub fn remove(&self, name: &str) -> Option<maia_json::Survey> {
+        let mut inner = self.inner.lock().unwrap();
+        let json = inner.surveys.remove(name)?.json(name);
+        inner.config_changed = true;
+        drop(inner);
+        self.changed.notify_one();
+        Some(json)
+    }
+
+    /// Requests a survey to run as soon as possible.
+    ///
+    /// Returns `None` if there is no survey with this name.
+    pub fn request_run(&self, name: &str) -> Option<maia_json::Survey> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner.surveys.get_mut(name)?;
+        entry.run_requested = true;
+        let json = entry.json(name);
+        drop(inner);
+        self.changed.notify_one();
+        Some(json)
+    }
+
+    /// Returns a report of a survey.
+    ///
+    /// Returns `None` if the survey or the report do not exist.
+    pub fn report(&self, name: &str, id: u64) -> Option<Arc<SurveyReport>> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .surveys
+            .get(name)?
+            .reports
+            .iter()
+            .find(|report| report.summary.id == id)
+            .cloned()
+    }
+
+    // Returns the configuration of all the surveys if it has changed since the
+    // last call.
+    fn take_changed_config(&self) -> Option<BTreeMap<String, PutSurvey>> {
+        let mut inner = self.inner.lock().unwrap();
+        if !std::mem::take(&mut inner.config_changed) {
+            return None;
+        }
+        Some(
+            inner
+                .surveys
+                .iter()
+                .map(|(name, entry)| (name.clone(), entry.config.clone()))
+                .collect(),
+        )
+    }
+
+    // Returns a survey that needs to run at the time `now`, marking it as
+    // running and updating its next run.
+    fn take_due(&self, now: DateTime<Utc>) -> Option<(String, PutSurvey)> {
+        let mut inner = self.inner.lock().unwrap();
+        let (name, entry) = inner
+            .surveys
+            .iter_mut()
+            .find(|(_, entry)| entry.run_requested || entry.next_run.is_some_and(|t| t <= now))?;
+        entry.run_requested = false;
+        entry.running = true;
+        if entry.next_run.is_some_and(|t| t <= now) {
+            entry.next_run = entry.schedule.as_ref().and_then(|s| s.next_after(now));
+        }
+        Some((name.clone(), entry.config.clone()))
+    }
+
+    // Returns the earliest scheduled run of all the surveys.
+    fn next_run(&self) -> Option<DateTime<Utc>> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .surveys
+            .values()
+            .filter_map(|entry| entry.next_run)
+            .min()
+    }
+
+    // Stores the report of a survey run, assigning its identifier.
+    fn add_report(&self, name: &str, mut report: SurveyReport) -> SurveyReport {
+        let mut inner = self.inner.lock().unwrap();
+        report.summary.id = inner.next_report_id;
+        inner.next_report_id += 1;
+        if let Some(entry) = inner.surveys.get_mut(name) {
+            entry.running = false;
+            if entry.reports.len() == MAX_REPORTS {
+                entry.reports.pop_front();
+            }
+            entry.reports.push_back(Arc::new(report.clone()));
+        }
+        report
+    }
+}
+
+// Checks a survey configuration, returning its schedule.
+fn check_config(config: &PutSurvey) -> Result<Option<Schedule>> {
+    anyhow::ensure!(
+        !config.bands.is_empty(),
+        "survey must have at least one band"
+    );
+    anyhow::ensure!(
+        config.resolution > Hertz(0.0),
+        "resolution must be positive"
+    );
+    anyhow::ensure!(
+        config.dwell > Seconds(0.0) && config.dwell <= MAX_DWELL,
+        "dwell must be positive and at most {MAX_DWELL}"
+    );
+    anyhow::ensure!(config.threshold.is_finite(), "threshold must be finite");
+    for band in &config.bands {
+        anyhow::ensure!(
+            RX_LO_MIN <= band.start_frequency
+                && band.start_frequency < band.stop_frequency
+                && band.stop_frequency <= RX_LO_MAX,
+            "band start and stop frequencies must be increasing and between {RX_LO_MIN} and \
+             {RX_LO_MAX}"
+        );
+        anyhow::ensure!(
+            statistics::num_channels(band, config.resolution.0) <= MAX_CHANNELS,
+            "band has more than {MAX_CHANNELS} channels"
+        );
+    }
+    if config.schedule.trim().is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(config.schedule.parse().context("invalid schedule")?))
+    }
+}
+
+/// Formats a survey report as CSV.
+///
+/// The CSV file has a row for each channel of each of the bands, containing
+/// the center frequency of the channel (in Hz), its occupancy, and its maximum
+/// power (in dB).
+pub fn report_csv(report: &SurveyReport) -> String {
+    let mut csv = String::from("frequency,occupancy,max_power\n");
+    for band in &report.bands {
+        for (channel, (occupancy, max_power)) in
+            band.occupancy.iter().zip(&band.max_power).enumerate()
+        {
+            let frequency =
+                band.band.start_frequency.0 + (channel as f64 + 0.5) * report.resolution.0;
+            csv.push_str(&format!("{frequency},{occupancy},{max_power:.2}\n"));
+        }
+    }
+    csv
+}
+
+/// Survey runner.
+///
+/// This struct runs the surveys when they are scheduled or requested. It
+/// implements a [`run`](SurveyRunner::run) async method that should be run
+/// concurrently with the rest of the application.
+///
+/// Optionally, the runner uses a directory to store the surveys, so that they
+/// persist across restarts, and to write each of the reports in JSON and CSV
+/// format.
+#[derive(Debug)]
+pub struct SurveyRunner {
+    state: AppState,
+    directory: Option<PathBuf>,
+}
+
+impl SurveyRunner {
+    /// Creates a new survey runner.
+    ///
+    /// If a `directory` is given, it is created if it does not exist, and the
+    /// surveys stored in it are loaded.
+    pub async fn new(state: AppState, directory: Option<PathBuf>) -> Result<SurveyRunner> {
+        if let Some(directory) = &directory {
+            tokio::fs::create_dir_all(directory)
+                .await
+                .with_context(|| format!("failed to create survey directory {directory:?}"))?;
+            let path = directory.join(SURVEYS_FILENAME);
+            match tokio::fs::read(&path).await {
+                Ok(json) => {
+                    let surveys: BTreeMap<String, PutSurvey> = serde_json::from_slice(&json)
+                        .with_context(|| format!("failed to parse {path:?}"))?;
+                    for (name, config) in surveys {
+                        state
+                            .surveys()
+                            .set(&name, config)
+                            .with_context(|| format!("invalid survey {name} in {path:?}"))?;
+                    }
+                    // The configuration has just been loaded, so it does not
+                    // need to be saved.
+                    state.surveys().take_changed_config();
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err).with_context(|| format!("failed to read {path:?}")),
+            }
+        }
+        Ok(SurveyRunner { state, directory })
+    }
+
+    /// Runs the survey runner.
+    ///
+    /// This function loops forever. Errors during the surveys are stored in
+    /// their reports, and errors writing to the survey directory are logged.
+    #[tracing::instrument(name = "survey", skip_all)]
+    pub async fn run(self) -> Result<()> {
+        let surveys = self.state.surveys();
+        loop {
+            let changed = surveys.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if let Some(config) = surveys.take_changed_config() {
+                if let Err(err) = self.save_config(&config).await {
+                    tracing::error!("failed to save surveys: {err:#}");
+                }
+            }
+            if let Some((name, config)) = surveys.take_due(Utc::now()) {
+                let report = self.run_survey(&name, &config).await;
+                let report = surveys.add_report(&name, report);
+                if let Err(err) = self.save_report(&report).await {
+                    tracing::error!("failed to save survey report: {err:#}");
+                }
+                continue;
+            }
+            match surveys.next_run() {
+                Some(next_run) => {
+                    let wait = (next_run - Utc::now()).to_std().unwrap_or_default();
+                    tokio::select! {
+                        _ = changed => {}
+                        _ = tokio::time::sleep(wait) => {}
+                    }
+                }
+                None => changed.await,
+            }
+        }
+    }
+
+    async fn save_config(&self, config: &BTreeMap<String, PutSurvey>) -> Result<()> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+        let path = directory.join(SURVEYS_FILENAME);
+        crate::fs::write_atomic(&path, serde_json::to_vec_pretty(config)?).await
+    }
+
+    async fn save_report(&self, report: &SurveyReport) -> Result<()> {
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+        let start_time =
+            DateTime::from_timestamp_millis(report.summary.start_time as i64).unwrap_or_default();
+        let filename = format!(
+            "{}_{}",
+            report.survey,
+            start_time.format("%Y-%m-%d-%H-%M-%S")
+        );
+        let path = directory.join(filename);
+        tokio::fs::write(path.with_extension("json"), serde_json::to_vec(report)?).await?;
+        if report.summary.error.is_none() {
+            tokio::fs::write(path.with_extension("csv"), report_csv(report)).await?;
+        }
+        Ok(())
+    }
+
+    async fn run_survey(&self, name: &str, config: &PutSurvey) -> SurveyReport {
+        tracing::info!(name, "starting survey");
+        let start_time = Utc::now();
+        let result = self.sweep(config).await;
+        let duration = (Utc::now() - start_time).num_microseconds().unwrap_or(0) as f64 * 1e-6;
+        let error = match &result {
+            Ok(_) => {
+                tracing::info!(name, duration, "survey finished");
+                None
+            }
+            Err(err) => {
+                tracing::error!(name, "survey failed: {err:#}");
+                Some(format!("{err:#}"))
+            }
+        };
+        SurveyReport {
+            survey: name.to_string(),
+            summary: SurveyReportSummary {
+                id: 0,
+                start_time: start_time.timestamp_micros() as f64 * 1e-3,
+                duration: Seconds(duration),
+                error,
+            },
+            resolution: config.resolution,
+            bands: result.unwrap_or_default(),
+        }
+    }
+
+    // Sweeps the bands of the survey. The RX LO is owned by the survey until
+    // the original frequency has been restored.
+    async fn sweep(&self, config: &PutSurvey) -> Result<Vec<SurveyBandReport>> {
+        let _tuner = self.state.tuner().acquire(TunerOwner::Survey)?;
+        anyhow::ensure!(
+            self.state.recorder().is_stopped().await,
+            "the recorder is in use"
+        );
+        anyhow::ensure!(
+            self.state.ip_core().lock().unwrap().spectrometer_input()
+                == maia_json::SpectrometerInput::AD9361,
+            "the spectrometer input must be the AD9361"
+        );
+        let samp_rate = self.state.ad9361_samp_rate().await?;
+        let original_frequency = self
+            .state
+            .ad9361()
+            .lock()
+            .await
+            .get_rx_lo_frequency()
+            .await?;
+        let result = self.sweep_bands(config, samp_rate).await;
+        if let Err(err) = self.tune(original_frequency).await {
+            tracing::error!("failed to restore RX frequency: {err:#}");
+        }
+        result
+    }
+
+    async fn sweep_bands(
+        &self,
+        config: &PutSurvey,
+        samp_rate: Hertz,
+    ) -> Result<Vec<SurveyBandReport>> {
+        let dwell = config
+            .dwell
+            .duration()
+            .context("dwell must be a valid duration")?;
+        let mut reports = Vec::with_capacity(config.bands.len());
+        for band in &config.bands {
+            let mut statistics =
+                BandStatistics::new(*band, config.resolution.0, config.threshold.0);
+            for frequency in statistics::sweep_frequencies(band, samp_rate.0) {
+                let frequency = frequency.round().clamp(RX_LO_MIN.0, RX_LO_MAX.0);
+                self.tune(Hertz(frequency)).await?;
+                let spectra = self.measure(dwell).await?;
+                statistics.add(frequency, samp_rate.0, &spectra);
+            }
+            reports.push(statistics.finish());
+        }
+        Ok(reports)
+    }
+
+    async fn tune(&self, frequency: Hertz) -> Result<()> {
+        self.state.limits().check_frequency(frequency)?;
+        self.state
+            .ad9361()
+            .lock()
+            .await
+            .set_rx_lo_frequency(frequency)
+            .await?;
+        self.state
+            .spectrometer_config()
+            .retune(&self.state.ip_core().lock().unwrap());
+        Ok(())
+    }
+
+    // Returns the spectra produced by the spectrometer during the dwell
+    // time. The first spectrum is discarded, since it can contain samples
+    // from before the AD9361 was tuned.
+    async fn measure(&self, dwell: Duration) -> Result<Vec<Vec<f32>>> {
+        let history = self.state.spectrum_history();
+        let spectra = tokio::time::timeout(dwell + SPECTRUM_TIMEOUT, async {
+            history.wait_for_count(history.count() + 1).await;
+            let start = history.count();
+            tokio::time::sleep(dwell).await;
+            history.wait_for_count(start + 1).await;
+            let count = history.count() - start;
+            history.latest(usize::try_from(count).unwrap_or(usize::MAX))
+        })
+        .await
+        .context("no spectra received from the spectrometer")?;
+        Ok(spectra
+            .iter()
+            .map(|spectrum| spectrometer::spectrum_from_bytes(spectrum))
+            .collect())
+    }
+}
+
+// Used by the tests of the REST API.
+#[cfg(test)]
+pub(crate) fn test_config(schedule: &str) -> PutSurvey {
+    PutSurvey {
+        schedule: schedule.to_string(),
+        bands: vec![maia_json::SurveyBand {
+            start_frequency: Hertz(100_000_000.0),
+            stop_frequency: Hertz(110_000_000.0),
+        }],
+        resolution: Hertz(100e3),
+        threshold: maia_json::Decibels(10.0),
+        dwell: Seconds(0.1),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::spectrometer::Spectrometer;
+    use maia_json::Decibels;
+
+    #[test]
+    fn config() {
+        let surveys = Surveys::new();
+        assert!(surveys.set("a b", test_config("")).is_err());
+        assert!(surveys.set("vhf", test_config("* *")).is_err());
+        let mut config = test_config("");
+        config.bands[0].stop_frequency = Hertz(6_100_000_000.0);
+        assert!(surveys.set("vhf", config).is_err());
+        let mut config = test_config("");
+        config.resolution = Hertz(1.0);
+        assert!(surveys.set("vhf", config).is_err());
+
+        let survey = surveys.set("vhf", test_config("0 * * * *")).unwrap();
+        assert!(survey.next_run.is_some());
+        assert!(!survey.running);
+        assert_eq!(surveys.json().surveys, [survey]);
+        assert!(surveys.take_changed_config().is_some());
+        assert!(surveys.take_changed_config().is_none());
+        assert!(surveys.take_due(Utc::now()).is_none());
+
+        // The schedule is removed, and a run is requested
+        surveys.set("vhf", test_config("")).unwrap();
+        assert_eq!(surveys.next_run(), None);
+        assert!(surveys.request_run("vhf").unwrap().running);
+        let (name, _) = surveys.take_due(Utc::now()).unwrap();
+        assert_eq!(name, "vhf");
+        assert!(surveys.take_due(Utc::now()).is_none());
+
+        assert!(surveys.remove("vhf").is_some());
+        assert!(surveys.remove("vhf").is_none());
+        assert!(surveys.request_run("vhf").is_none());
+    }
+
+    #[tokio::test]
+    async fn run() {
+        let (state, interrupt_handler) = crate::mock::app_state().await;
+        state
+            .ip_core()
+            .lock()
+            .unwrap()
+            .set_spectrometer_number_integrations(16)
+            .unwrap();
+        let (sender, _) = tokio::sync::broadcast::channel(16);
+        let spectrometer = Spectrometer::new(
+            state.clone(),
+            interrupt_handler.waiter_spectrometer(),
+            sender,
+        );
+        tokio::spawn(interrupt_handler.run());
+        tokio::spawn(spectrometer.run());
+        let original_frequency = state
+            .ad9361()
+            .lock()
+            .await
+            .get_rx_lo_frequency()
+            .await
+            .unwrap();
+
+        let directory =
+            std::env::temp_dir().join(format!("maia-httpd-survey-{}", std::process::id()));
+        let runner = SurveyRunner::new(state.clone(), Some(directory.clone()))
+            .await
+            .unwrap();
+        tokio::spawn(runner.run());
+        state.surveys().set("vhf", test_config("")).unwrap();
+        state.surveys().request_run("vhf").unwrap();
+        let report = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                if let Some(report) = state.surveys().report("vhf", 0) {
+                    return report;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(report.summary.error, None);
+        assert_eq!(report.bands.len(), 1);
+        let band = &report.bands[0];
+        assert_eq!(band.occupancy.len(), 100);
+        assert!(band.noise_floor.is_finite());
+        assert!(band.max_power.iter().all(|p| p.is_finite()));
+        assert!(!state.surveys().survey_json("vhf").unwrap().running);
+        assert_eq!(
+            state
+                .ad9361()
+                .lock()
+                .await
+                .get_rx_lo_frequency()
+                .await
+                .unwrap(),
+            original_frequency
+        );
+
+        // The configuration and the report are stored in the directory
+        let saved: BTreeMap<String, PutSurvey> =
+            serde_json::from_slice(&std::fs::read(directory.join(SURVEYS_FILENAME)).unwrap())
+                .unwrap();
+        assert_eq!(saved["vhf"], test_config(""));
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        assert!(files
+            .iter()
+            .any(|f| f.starts_with("vhf_") && f.ends_with(".csv")));
+
+        // The survey fails without retuning if the RX LO is owned by someone
+        // else
+        let tuner = state.tuner().acquire(TunerOwner::Scanner).unwrap();
+        state.surveys().request_run("vhf").unwrap();
+        let report = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                if let Some(report) = state.surveys().report("vhf", 1) {
+                    return report;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(report
+            .summary
+            .error
+            .as_ref()
+            .unwrap()
+            .contains("the scanner"));
+        assert_eq!(state.tuner().owner(), Some(TunerOwner::Scanner));
+        drop(tuner);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn csv() {
+        let report = SurveyReport {
+            survey: "vhf".to_string(),
+            summary: SurveyReportSummary {
+                id: 0,
+                start_time: 0.0,
+                duration: Seconds(1.0),
+                error: None,
+            },
+            resolution: Hertz(100e3),
+            bands: vec![SurveyBandReport {
+                band: maia_json::SurveyBand {
+                    start_frequency: Hertz(100_000_000.0),
+                    stop_frequency: Hertz(100_200_000.0),
+                },
+                noise_floor: Decibels(0.0),
+                occupancy: vec![0.0, 0.5],
+                max_power: vec![1.0, 20.0],
+                peaks: Vec::new(),
+            }],
+        };
+        assert_eq!(
+            report_csv(&report),
+            "frequency,occupancy,max_power\n\
+             100050000,0,1.00\n\
+             100150000,0.5,20.00\n"
+        );
+    }
+}
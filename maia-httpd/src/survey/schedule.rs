@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Datelike, Duration, DurationRound, Timelike, Utc};
+
+// Maximum time that is searched for the next run of a schedule. Schedules
+// such as `0 0 31 2 *` never run.
+const MAX_SEARCH: Duration = Duration::days(4 * 366);
+
+/// Survey schedule.
+///
+/// The schedule is given in the cron format, with five fields for the minute,
+/// hour, day of month, month and day of week. Each field can be `*`, a number,
+/// a range such as `1-5`, or a comma separated list of these, optionally
+/// followed by a step such as `*/15`. As in cron, if both the day of month
+/// and the day of week are restricted, the schedule runs on the days that
+/// match either of them. Times are in UTC.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Schedule> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        anyhow::ensure!(
+            fields.len() == 5,
+            "schedule must have 5 fields (minute, hour, day of month, month and day of week)"
+        );
+        // Sunday can be given as 0 or 7
+        let days_of_week = parse_field(fields[4], 0, 7).context("invalid day of week")?;
+        let days_of_week = (days_of_week | (days_of_week >> 7)) & 0x7f;
+        Ok(Schedule {
+            minutes: parse_field(fields[0], 0, 59).context("invalid minute")?,
+            hours: parse_field(fields[1], 0, 23).context("invalid hour")?,
+            days_of_month: parse_field(fields[2], 1, 31).context("invalid day of month")?,
+            months: parse_field(fields[3], 1, 12).context("invalid month")?,
+            days_of_week,
+            days_of_month_restricted: !fields[2].starts_with('*'),
+            days_of_week_restricted: !fields[4].starts_with('*'),
+        })
+    }
+}
+
+// Parses a field as a bitmask of the values that it matches.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64> {
+    let mut mask = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>()?),
+            None => (item, 1),
+        };
+        anyhow::ensure!(step > 0, "step must be positive");
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (start.parse()?, end.parse()?)
+        } else {
+            let value = range.parse()?;
+            // A single value with a step extends to the maximum, as in cron
+            (value, if step > 1 { max } else { value })
+        };
+        anyhow::ensure!(
+            min <= start && start <= end && end <= max,
+            "{range} is not within {min}-{max}"
+        );
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+fn matches(mask: u64, value: u32) -> bool {
+    mask & (1 << value) != 0
+}
+
+impl Schedule {
+    /// Returns the first time after `time` at which the schedule runs.
+    ///
+    /// Returns `None` if the schedule does not run in the next few years.
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut time = time.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        let end = time + MAX_SEARCH;
+        while time < end {
+            if !matches(self.months, time.month()) || !self.matches_day(time) {
+                // Skip to the next day
+                time = time.duration_trunc(Duration::days(1)).ok()? + Duration::days(1);
+            } else if !matches(self.hours, time.hour()) {
+                // Skip to the next hour
+                time = time.duration_trunc(Duration::hours(1)).ok()? + Duration::hours(1);
+            } else if !matches(self.minutes, time.minute()) {
+                time += Duration::minutes(1);
+            } else {
+                return Some(time);
+            }
+        }
+        None
+    }
+
+    fn matches_day(&self, time: DateTime<Utc>) -> bool {
+        let day_of_month = matches(self.days_of_month, time.day());
+        let day_of_week = matches(self.days_of_week, time.weekday().num_days_from_sunday());
+        if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn next(schedule: &str, after: &str) -> Option<DateTime<Utc>> {
+        schedule
+            .parse::<Schedule>()
+            .unwrap()
+            .next_after(time(after))
+    }
+
+    #[test]
+    fn parse_errors() {
+        for schedule in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(schedule.parse::<Schedule>().is_err(), "{schedule}");
+        }
+    }
+
+    #[test]
+    fn next_run() {
+        // 2024-01-01 is a Monday
+        let now = "2024-01-01T10:07:30Z";
+        assert_eq!(next("* * * * *", now), Some(time("2024-01-01T10:08:00Z")));
+        assert_eq!(
+            next("*/15 * * * *", now),
+            Some(time("2024-01-01T10:15:00Z"))
+        );
+        assert_eq!(next("7 * * * *", now), Some(time("2024-01-01T11:07:00Z")));
+        assert_eq!(
+            next("0 8-9,20 * * *", now),
+            Some(time("2024-01-01T20:00:00Z"))
+        );
+        assert_eq!(next("0 8 * * 0", now), Some(time("2024-01-07T08:00:00Z")));
+        assert_eq!(next("0 8 * * 7", now), Some(time("2024-01-07T08:00:00Z")));
+        assert_eq!(next("0 0 1 * *", now), Some(time("2024-02-01T00:00:00Z")));
+        // Day of month or day of week
+        assert_eq!(next("0 0 15 * 3", now), Some(time("2024-01-03T00:00:00Z")));
+        assert_eq!(next("0 0 29 2 *", now), Some(time("2024-02-29T00:00:00Z")));
+        assert_eq!(next("0 0 31 2 *", now), None);
+    }
+}
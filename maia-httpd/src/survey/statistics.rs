@@ -0,0 +1,211 @@
+use crate::spectrometer::median;
+use maia_json::{Decibels, Hertz, SurveyBand, SurveyBandReport, SurveyPeak};
+
+// Fraction of the spectrometer band that is used. The edges of the AD9361
+// spectrum are not used because of the roll-off of its filters.
+pub(super) const USABLE_FRACTION: f64 = 0.7;
+// Maximum number of peaks listed for each band.
+const MAX_PEAKS: usize = 20;
+
+// Statistics of a band that is being surveyed.
+#[derive(Debug, Clone)]
+pub(super) struct BandStatistics {
+    band: SurveyBand,
+    resolution: f64,
+    threshold: f64,
+    observations: Vec<u32>,
+    occupied: Vec<u32>,
+    max_power: Vec<f32>,
+    noise_floors: Vec<f64>,
+    peaks: Vec<SurveyPeak>,
+}
+
+impl BandStatistics {
+    pub(super) fn new(band: SurveyBand, resolution: f64, threshold: f64) -> BandStatistics {
+        let channels = num_channels(&band, resolution);
+        BandStatistics {
+            band,
+            resolution,
+            threshold,
+            observations: vec![0; channels],
+            occupied: vec![0; channels],
+            max_power: vec![f32::NEG_INFINITY; channels],
+            noise_floors: Vec::new(),
+            peaks: Vec::new(),
+        }
+    }
+
+    // Adds the spectra measured with the AD9361 tuned to `frequency`. The
+    // spectra contain linear power values in FFT-shifted order.
+    pub(super) fn add(&mut self, frequency: f64, samp_rate: f64, spectra: &[Vec<f32>]) {
+        let Some(bins) = spectra.first().map(|s| s.len()) else {
+            return;
+        };
+        let bin_width = samp_rate / bins as f64;
+        let bin_frequency = |bin: usize| frequency + (bin as f64 - 0.5 * bins as f64) * bin_width;
+        let start = self.band.start_frequency.0;
+        let stop = self.band.stop_frequency.0;
+        // Bins that are in the usable part of the spectrum and in the band,
+        // together with their channel
+        let usable = (0..bins)
+            .filter_map(|bin| {
+                let freq = bin_frequency(bin);
+                ((freq - frequency).abs() <= 0.5 * USABLE_FRACTION * samp_rate
+                    && (start..stop).contains(&freq))
+                .then(|| (bin, ((freq - start) / self.resolution) as usize))
+            })
+            .collect::<Vec<_>>();
+        if usable.is_empty() {
+            return;
+        }
+
+        let to_db = |power: f32| 10.0 * f64::from(power).log10();
+        let mut average = vec![0.0; bins];
+        for spectrum in spectra {
+            for (avg, &power) in average.iter_mut().zip(spectrum) {
+                *avg += power / spectra.len() as f32;
+            }
+        }
+        let mut usable_powers = usable
+            .iter()
+            .map(|&(bin, _)| average[bin])
+            .collect::<Vec<_>>();
+        let noise_floor = to_db(median(&mut usable_powers));
+        self.noise_floors.push(noise_floor);
+        let occupied_threshold = noise_floor + self.threshold;
+
+        for spectrum in spectra {
+            for &(bin, channel) in &usable {
+                let power = to_db(spectrum[bin]);
+                self.observations[channel] += 1;
+                if power > occupied_threshold {
+                    self.occupied[channel] += 1;
+                }
+                self.max_power[channel] = self.max_power[channel].max(power as f32);
+            }
+        }
+
+        for (j, &(bin, _)) in usable.iter().enumerate() {
+            let power = average[bin];
+            let is_local_max = (j == 0 || average[usable[j - 1].0] < power)
+                && (j + 1 == usable.len() || average[usable[j + 1].0] <= power);
+            let power = to_db(power);
+            if is_local_max && power > occupied_threshold {
+                self.peaks.push(SurveyPeak {
+                    frequency: Hertz(bin_frequency(bin)),
+                    power: Decibels(power),
+                    snr: Decibels(power - noise_floor),
+                });
+            }
+        }
+    }
+
+    pub(super) fn finish(mut self) -> SurveyBandReport {
+        let occupancy = self
+            .observations
+            .iter()
+            .zip(&self.occupied)
+            .map(|(&total, &occupied)| {
+                if total == 0 {
+                    0.0
+                } else {
+                    occupied as f32 / total as f32
+                }
+            })
+            .collect();
+        let noise_floor = if self.noise_floors.is_empty() {
+            f64::NAN
+        } else {
+            median(&mut self.noise_floors)
+        };
+        // Keep the strongest peaks, discarding those that are closer than the
+        // resolution to a stronger peak.
+        self.peaks.sort_by(|a, b| {
+            b.power
+                .partial_cmp(&a.power)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut peaks: Vec<SurveyPeak> = Vec::new();
+        for peak in self.peaks {
+            if peaks.len() == MAX_PEAKS {
+                break;
+            }
+            if peaks
+                .iter()
+                .all(|p| (p.frequency - peak.frequency).abs() >= Hertz(self.resolution))
+            {
+                peaks.push(peak);
+            }
+        }
+        SurveyBandReport {
+            band: self.band,
+            noise_floor: Decibels(noise_floor),
+            occupancy,
+            max_power: self.max_power,
+            peaks,
+        }
+    }
+}
+
+// Returns the number of channels of a band.
+pub(super) fn num_channels(band: &SurveyBand, resolution: f64) -> usize {
+    ((band.stop_frequency - band.start_frequency).0 / resolution).ceil() as usize
+}
+
+// Returns the frequencies to which the AD9361 is tuned to sweep a band.
+pub(super) fn sweep_frequencies(band: &SurveyBand, samp_rate: f64) -> Vec<f64> {
+    let step = USABLE_FRACTION * samp_rate;
+    let width = (band.stop_frequency - band.start_frequency).0;
+    let steps = (width / step).ceil().max(1.0) as usize;
+    (0..steps)
+        .map(|j| band.start_frequency.0 + (j as f64 + 0.5) * step)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frequencies() {
+        let band = SurveyBand {
+            start_frequency: Hertz(100_000_000.0),
+            stop_frequency: Hertz(120_000_000.0),
+        };
+        assert_eq!(num_channels(&band, 25e3), 800);
+        assert_eq!(num_channels(&band, 3e6), 7);
+        assert_eq!(sweep_frequencies(&band, 10e6), [103.5e6, 110.5e6, 117.5e6]);
+        let narrow = SurveyBand {
+            start_frequency: Hertz(100_000_000.0),
+            stop_frequency: Hertz(100_100_000.0),
+        };
+        assert_eq!(sweep_frequencies(&narrow, 10e6), [103.5e6]);
+    }
+
+    #[test]
+    fn band_statistics() {
+        let band = SurveyBand {
+            start_frequency: Hertz(99_000_000.0),
+            stop_frequency: Hertz(101_000_000.0),
+        };
+        let mut statistics = BandStatistics::new(band, 500e3, 10.0);
+        // 16 bins of 250 kHz centered at 100 MHz. Bins 3 to 13 are usable, but
+        // bins 3 (98.75 MHz) and 12 and 13 (101 and 101.25 MHz) are outside
+        // the band, so there are 2 bins in each channel.
+        let mut spectra = vec![vec![1.0f32; 16]; 4];
+        // A signal 20 dB above the noise in the bin at 100.25 MHz, present in
+        // half of the spectra
+        spectra[0][9] = 100.0;
+        spectra[1][9] = 100.0;
+        statistics.add(100e6, 4e6, &spectra);
+        let report = statistics.finish();
+        assert_eq!(report.noise_floor, Decibels(0.0));
+        assert_eq!(report.occupancy, [0.0, 0.0, 0.25, 0.0]);
+        assert_eq!(report.max_power, [0.0, 0.0, 20.0, 0.0]);
+        assert_eq!(report.peaks.len(), 1);
+        assert_eq!(report.peaks[0].frequency, Hertz(100.25e6));
+        let expected_power = Decibels::from_power_ratio(50.5);
+        assert!((report.peaks[0].power - expected_power).abs() < Decibels(1e-9));
+        assert!((report.peaks[0].snr - expected_power).abs() < Decibels(1e-9));
+    }
+}
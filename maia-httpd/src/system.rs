@@ -0,0 +1,104 @@
+//! System health.
+//!
+//! This module keeps track of the health of the tasks that handle the FPGA IP
+//! core. When one of these tasks fails and it is restarted by the
+//! [`App`](crate::app::App), the failure is recorded as an incident and the
+//! system is reported as degraded for some time.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+// Maximum number of incidents that are kept.
+const MAX_INCIDENTS: usize = 32;
+// Time after an incident during which the system is reported as degraded.
+const DEGRADED_PERIOD: Duration = Duration::from_secs(600);
+
+/// System health.
+///
+/// This struct contains the incidents in which a task has failed and has been
+/// restarted, and is used to obtain the health status given in `/api/system`.
+#[derive(Debug, Default)]
+pub struct System(Mutex<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    incidents: VecDeque<maia_json::SystemIncident>,
+    last_incident: Option<Instant>,
+    restarts: u64,
+}
+
+impl System {
+    /// Creates a new system health object with no incidents.
+    pub fn new() -> System {
+        System::default()
+    }
+
+    /// Records the failure of a task.
+    ///
+    /// The failure is logged and added to the list of incidents. The oldest
+    /// incidents are discarded when the list is full.
+    pub fn record_incident(&self, task: &str, error: &anyhow::Error) {
+        tracing::error!(task, "task failed: {error:#}");
+        let mut inner = self.0.lock().unwrap();
+        if inner.incidents.len() == MAX_INCIDENTS {
+            inner.incidents.pop_front();
+        }
+        inner.incidents.push_back(maia_json::SystemIncident {
+            time: UNIX_EPOCH
+                .elapsed()
+                .map_or(0.0, |time| time.as_secs_f64() * 1e3),
+            task: task.to_string(),
+            error: format!("{error:#}"),
+        });
+        inner.last_incident = Some(Instant::now());
+    }
+
+    /// Records that a failed task has been restarted.
+    pub fn record_restart(&self) {
+        self.0.lock().unwrap().restarts += 1;
+    }
+
+    /// Returns the health status.
+    ///
+    /// The system is degraded if an incident has happened recently.
+    pub fn status(&self) -> maia_json::SystemStatus {
+        match self.0.lock().unwrap().last_incident {
+            Some(time) if time.elapsed() < DEGRADED_PERIOD => maia_json::SystemStatus::Degraded,
+            _ => maia_json::SystemStatus::Ok,
+        }
+    }
+
+    /// Returns the JSON representation of the system health.
+    pub fn json(&self) -> maia_json::System {
+        let status = self.status();
+        let inner = self.0.lock().unwrap();
+        maia_json::System {
+            status,
+            restarts: inner.restarts,
+            incidents: inner.incidents.iter().cloned().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use maia_json::SystemStatus;
+
+    #[test]
+    fn incidents() {
+        let system = System::new();
+        assert_eq!(system.status(), SystemStatus::Ok);
+        for n in 0..MAX_INCIDENTS + 2 {
+            system.record_incident("spectrometer", &anyhow::anyhow!("failure {n}"));
+            system.record_restart();
+        }
+        let json = system.json();
+        assert_eq!(json.status, SystemStatus::Degraded);
+        assert_eq!(json.restarts, MAX_INCIDENTS as u64 + 2);
+        assert_eq!(json.incidents.len(), MAX_INCIDENTS);
+        assert_eq!(json.incidents[0].error, "failure 2");
+        assert_eq!(json.incidents[0].task, "spectrometer");
+    }
+}
@@ -0,0 +1,99 @@
+//! Ownership of the AD9361 RX LO.
+//!
+//! The frequency-hopping recordings, the scanner and the surveys retune the
+//! AD9361 RX LO on their own. This module implements a [`Tuner`] that makes
+//! sure that only one of them does so at a time, and that the RX LO frequency
+//! is not changed through the REST API while one of them owns it.
+
+use anyhow::Result;
+use std::sync::{Arc, Mutex};
+
+/// Owner of the AD9361 RX LO.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TunerOwner {
+    /// Frequency-hopping recording.
+    Hopping,
+    /// Frequency scanner.
+    Scanner,
+    /// Survey.
+    Survey,
+}
+
+impl std::fmt::Display for TunerOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TunerOwner::Hopping => "a frequency-hopping recording",
+            TunerOwner::Scanner => "the scanner",
+            TunerOwner::Survey => "a survey",
+        })
+    }
+}
+
+/// AD9361 RX LO ownership.
+///
+/// This struct is part of the [`AppState`](crate::app::AppState). The RX LO is
+/// owned while the [`TunerGuard`] returned by [`Tuner::acquire`] is alive.
+#[derive(Debug, Default, Clone)]
+pub struct Tuner(Arc<Mutex<Option<TunerOwner>>>);
+
+/// Guard of the AD9361 RX LO ownership.
+///
+/// The ownership is released when the guard is dropped.
+#[derive(Debug)]
+pub struct TunerGuard(Tuner);
+
+impl Tuner {
+    /// Creates a new tuner, which is not owned by anyone.
+    pub fn new() -> Tuner {
+        Tuner::default()
+    }
+
+    /// Returns the current owner of the RX LO.
+    pub fn owner(&self) -> Option<TunerOwner> {
+        *self.0.lock().unwrap()
+    }
+
+    /// Takes the ownership of the RX LO.
+    ///
+    /// The function fails if the RX LO is already owned.
+    pub fn acquire(&self, owner: TunerOwner) -> Result<TunerGuard> {
+        let mut current = self.0.lock().unwrap();
+        if let Some(current) = *current {
+            anyhow::bail!("the RX frequency is being controlled by {current}");
+        }
+        *current = Some(owner);
+        Ok(TunerGuard(self.clone()))
+    }
+
+    /// Checks that the RX LO is not owned, so that it can be retuned.
+    pub fn check_free(&self) -> Result<()> {
+        match self.owner() {
+            Some(owner) => anyhow::bail!("the RX frequency is being controlled by {owner}"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TunerGuard {
+    fn drop(&mut self) {
+        *self.0 .0.lock().unwrap() = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ownership() {
+        let tuner = Tuner::new();
+        assert!(tuner.check_free().is_ok());
+        let guard = tuner.acquire(TunerOwner::Scanner).unwrap();
+        assert_eq!(tuner.owner(), Some(TunerOwner::Scanner));
+        assert!(tuner.check_free().is_err());
+        assert!(tuner.acquire(TunerOwner::Hopping).is_err());
+        drop(guard);
+        assert_eq!(tuner.owner(), None);
+        assert!(tuner.acquire(TunerOwner::Hopping).is_ok());
+    }
+}
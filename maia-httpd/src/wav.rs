@@ -0,0 +1,215 @@
+//! WAV format.
+//!
+//! This module contains the functions required to write recordings as IQ WAV
+//! files. These are stereo PCM WAV files in which the left channel contains the
+//! in-phase component and the right channel contains the quadrature
+//! component. The files include an `auxi` chunk, as written by SDR# and HDSDR,
+//! which contains the start and stop times and the center frequency of the
+//! recording, so that these can be used by the file players of these
+//! programs. Files larger than 4 GiB are written in the
+//! [RF64](https://tech.ebu.ch/docs/tech/tech3306v1_1.pdf) format.
+
+use chrono::{DateTime, Datelike, Timelike, Utc};
+
+const FMT_CHUNK_SIZE: u32 = 16;
+const AUXI_CHUNK_SIZE: u32 = 164;
+const DS64_CHUNK_SIZE: u32 = 28;
+const WAVE_FORMAT_PCM: u16 = 1;
+const NUM_CHANNELS: u16 = 2;
+
+/// WAV file header.
+///
+/// This contains the parameters of an IQ WAV file and is used to produce the
+/// bytes that precede the samples in the file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// Sample rate in samples per second.
+    pub sample_rate: u32,
+    /// Number of bits of each of the IQ components.
+    ///
+    /// This should be 8 or 16. The 8-bit samples are unsigned, as usual in
+    /// WAV files, and the 16-bit samples are signed and little-endian.
+    pub bits_per_sample: u16,
+    /// Number of IQ samples in the file.
+    pub num_samples: u64,
+    /// Time of the first sample.
+    pub start: DateTime<Utc>,
+    /// Center frequency in Hz.
+    ///
+    /// Since the `auxi` chunk stores the frequency as a 32-bit integer,
+    /// frequencies above 4.29 GHz are saturated.
+    pub frequency: f64,
+}
+
+impl Header {
+    /// Returns the size in bytes of the data chunk.
+    pub fn data_size(&self) -> u64 {
+        self.num_samples * u64::from(self.block_align())
+    }
+
+    /// Returns the size in bytes of the whole file.
+    pub fn file_size(&self) -> u64 {
+        self.header_size() as u64 + self.data_size()
+    }
+
+    /// Returns whether the file uses the RF64 format.
+    ///
+    /// This is needed when the file is larger than 4 GiB.
+    pub fn is_rf64(&self) -> bool {
+        self.riff_size(false) > u64::from(u32::MAX)
+    }
+
+    fn block_align(&self) -> u16 {
+        NUM_CHANNELS * self.bits_per_sample / 8
+    }
+
+    fn riff_size(&self, rf64: bool) -> u64 {
+        let ds64 = if rf64 { 8 + DS64_CHUNK_SIZE } else { 0 };
+        // "WAVE" and the headers of the fmt, auxi and data chunks
+        u64::from(4 + ds64 + 8 + FMT_CHUNK_SIZE + 8 + AUXI_CHUNK_SIZE + 8) + self.data_size()
+    }
+
+    fn header_size(&self) -> usize {
+        let ds64 = if self.is_rf64() {
+            8 + DS64_CHUNK_SIZE
+        } else {
+            0
+        };
+        (12 + ds64 + 8 + FMT_CHUNK_SIZE + 8 + AUXI_CHUNK_SIZE + 8) as usize
+    }
+
+    /// Returns the bytes of the file that precede the samples.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let rf64 = self.is_rf64();
+        let riff_size = self.riff_size(rf64);
+        let data_size = self.data_size();
+        let mut out = Vec::with_capacity(self.header_size());
+        if rf64 {
+            out.extend_from_slice(b"RF64");
+            out.extend_from_slice(&u32::MAX.to_le_bytes());
+        } else {
+            out.extend_from_slice(b"RIFF");
+            out.extend_from_slice(&(riff_size as u32).to_le_bytes());
+        }
+        out.extend_from_slice(b"WAVE");
+        if rf64 {
+            out.extend_from_slice(b"ds64");
+            out.extend_from_slice(&DS64_CHUNK_SIZE.to_le_bytes());
+            out.extend_from_slice(&riff_size.to_le_bytes());
+            out.extend_from_slice(&data_size.to_le_bytes());
+            out.extend_from_slice(&self.num_samples.to_le_bytes());
+            // table length
+            out.extend_from_slice(&0u32.to_le_bytes());
+        }
+
+        out.extend_from_slice(b"fmt ");
+        out.extend_from_slice(&FMT_CHUNK_SIZE.to_le_bytes());
+        out.extend_from_slice(&WAVE_FORMAT_PCM.to_le_bytes());
+        out.extend_from_slice(&NUM_CHANNELS.to_le_bytes());
+        out.extend_from_slice(&self.sample_rate.to_le_bytes());
+        let byte_rate = self.sample_rate * u32::from(self.block_align());
+        out.extend_from_slice(&byte_rate.to_le_bytes());
+        out.extend_from_slice(&self.block_align().to_le_bytes());
+        out.extend_from_slice(&self.bits_per_sample.to_le_bytes());
+
+        out.extend_from_slice(b"auxi");
+        out.extend_from_slice(&AUXI_CHUNK_SIZE.to_le_bytes());
+        let duration_nanos = self.num_samples as f64 * 1e9 / f64::from(self.sample_rate);
+        let stop = self.start + chrono::Duration::nanoseconds(duration_nanos.round() as i64);
+        put_systemtime(&mut out, &self.start);
+        put_systemtime(&mut out, &stop);
+        let frequency = self.frequency.round().clamp(0.0, f64::from(u32::MAX)) as u32;
+        // center frequency, ADC frequency, IF frequency, bandwidth, IQ
+        // offset, 4 unused fields
+        for field in [frequency, self.sample_rate, 0, 0, 0, 0, 0, 0, 0] {
+            out.extend_from_slice(&field.to_le_bytes());
+        }
+        // next filename
+        out.extend_from_slice(&[0; 96]);
+
+        out.extend_from_slice(b"data");
+        let data_chunk_size = if rf64 { u32::MAX } else { data_size as u32 };
+        out.extend_from_slice(&data_chunk_size.to_le_bytes());
+        debug_assert_eq!(out.len(), self.header_size());
+        out
+    }
+}
+
+// Appends a Windows SYSTEMTIME structure.
+fn put_systemtime(out: &mut Vec<u8>, time: &DateTime<Utc>) {
+    let fields = [
+        time.year() as u16,
+        time.month() as u16,
+        time.weekday().num_days_from_sunday() as u16,
+        time.day() as u16,
+        time.hour() as u16,
+        time.minute() as u16,
+        time.second() as u16,
+        (time.timestamp_subsec_millis() % 1000) as u16,
+    ];
+    for field in fields {
+        out.extend_from_slice(&field.to_le_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(num_samples: u64) -> Header {
+        Header {
+            sample_rate: 2_000_000,
+            bits_per_sample: 16,
+            num_samples,
+            start: DateTime::from_timestamp(1704110400, 250_000_000).unwrap(),
+            frequency: 100e6,
+        }
+    }
+
+    #[test]
+    fn riff() {
+        let header = header(1000);
+        let bytes = header.to_bytes();
+        assert!(!header.is_rf64());
+        assert_eq!(bytes.len(), 12 + 24 + 172 + 8);
+        assert_eq!(header.file_size(), bytes.len() as u64 + 4000);
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(
+            u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            header.file_size() as u32 - 8
+        );
+        // block align
+        assert_eq!(bytes[32..34], [4, 0]);
+        // start time: Monday 2024-01-01 12:00:00.250
+        assert_eq!(
+            bytes[44..60],
+            [0xe8, 0x07, 1, 0, 1, 0, 1, 0, 12, 0, 0, 0, 0, 0, 250, 0]
+        );
+        // stop time is 0.5 ms later
+        assert_eq!(bytes[74..76], [250, 0]);
+        assert_eq!(
+            u32::from_le_bytes(bytes[76..80].try_into().unwrap()),
+            100_000_000
+        );
+        assert_eq!(&bytes[bytes.len() - 8..bytes.len() - 4], b"data");
+    }
+
+    #[test]
+    fn rf64() {
+        let header = header(1 << 30);
+        let bytes = header.to_bytes();
+        assert!(header.is_rf64());
+        assert_eq!(bytes.len(), 12 + 36 + 24 + 172 + 8);
+        assert_eq!(&bytes[..4], b"RF64");
+        assert_eq!(&bytes[12..16], b"ds64");
+        assert_eq!(
+            u64::from_le_bytes(bytes[20..28].try_into().unwrap()),
+            header.file_size() - 8
+        );
+        assert_eq!(
+            u64::from_le_bytes(bytes[28..36].try_into().unwrap()),
+            1 << 32
+        );
+        assert_eq!(bytes[bytes.len() - 4..], [0xff; 4]);
+    }
+}
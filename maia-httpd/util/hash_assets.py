@@ -0,0 +1,94 @@
+#!/usr/bin/env python3
+
+"""Prepares the maia-wasm static assets for being served by maia-httpd.
+
+The files that are loaded by index.html and dashboard.html are renamed to
+include a hash of their contents in their filename (name.HASH.ext), and the
+references to them are updated. maia-httpd serves these files as immutable, so
+that the browser can cache them forever. Additionally, gzip and brotli (if the
+brotli Python module is available) precompressed variants of the text and wasm
+files are generated. maia-httpd serves these variants to browsers that support
+them.
+
+This script is intended to be run on a copy of the maia-wasm assets (including
+the pkg directory generated by wasm-pack) when building the root filesystem
+that is served by maia-httpd.
+"""
+
+import argparse
+import gzip
+import hashlib
+import pathlib
+
+try:
+    import brotli
+except ImportError:
+    brotli = None
+
+COMPRESS_SUFFIXES = {'.html', '.js', '.css', '.json', '.svg', '.wasm'}
+
+# Files that are renamed, in order, together with the files that reference
+# them. Each file must be renamed before the files that reference it, since
+# renaming it changes the contents of those files.
+HASHED_FILES = [
+    ('pkg/maia_wasm_bg.wasm', ['pkg/maia_wasm.js']),
+    ('pkg/maia_wasm.js', ['index.js', 'dashboard.js']),
+    ('index.js', ['index.html']),
+    ('dashboard.js', ['dashboard.html']),
+    ('style.css', ['index.html', 'dashboard.html']),
+]
+
+
+def hash_file(root, name, references):
+    path = root / name
+    if not path.exists():
+        return
+    digest = hashlib.sha256(path.read_bytes()).hexdigest()[:16]
+    hashed = path.with_name(f'{path.stem}.{digest}{path.suffix}')
+    path.rename(hashed)
+    for reference in references:
+        reference = root / reference
+        if not reference.exists():
+            continue
+        contents = reference.read_text()
+        # References are relative to the directory of the referencing file
+        old = path.relative_to(reference.parent).as_posix()
+        new = hashed.relative_to(reference.parent).as_posix()
+        reference.write_text(contents.replace(old, new))
+
+
+def compress(root):
+    for path in sorted(root.rglob('*')):
+        if not path.is_file() or path.suffix not in COMPRESS_SUFFIXES:
+            continue
+        data = path.read_bytes()
+        gz = path.with_name(path.name + '.gz')
+        gz.write_bytes(gzip.compress(data, compresslevel=9, mtime=0))
+        if brotli is not None:
+            br = path.with_name(path.name + '.br')
+            br.write_bytes(brotli.compress(data))
+
+
+def parse_args():
+    parser = argparse.ArgumentParser(
+        description='Prepare maia-wasm assets for maia-httpd')
+    parser.add_argument('root', type=pathlib.Path,
+                        help='directory containing the maia-wasm assets')
+    parser.add_argument('--no-hash', action='store_true',
+                        help='do not rename files to include a hash')
+    parser.add_argument('--no-compress', action='store_true',
+                        help='do not generate precompressed files')
+    return parser.parse_args()
+
+
+def main():
+    args = parse_args()
+    if not args.no_hash:
+        for name, references in HASHED_FILES:
+            hash_file(args.root, name, references)
+    if not args.no_compress:
+        compress(args.root)
+
+
+if __name__ == '__main__':
+    main()
@@ -0,0 +1,8 @@
+import init, { maia_wasm_start_dashboard } from "./pkg/maia_wasm.js";
+
+async function run() {
+    await init();
+    maia_wasm_start_dashboard();
+};
+
+run();
@@ -5,4 +5,13 @@ async function run() {
     maia_wasm_start();
 };
 
+// The service worker served by maia-httpd caches the assets, so that the UI
+// can be installed as an app and loads without waiting for the network. It
+// is only available in secure contexts (HTTPS).
+if ("serviceWorker" in navigator) {
+    navigator.serviceWorker.register("/sw.js").catch((error) => {
+        console.error("could not register service worker:", error);
+    });
+}
+
 run();
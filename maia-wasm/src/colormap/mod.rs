@@ -1,8 +0,0 @@
-//! Colormaps.
-//!
-//! This module contains different colormaps that can be used to plot the
-//! waterfall.
-
-pub mod inferno;
-pub mod turbo;
-pub mod viridis;
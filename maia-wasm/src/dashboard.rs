@@ -0,0 +1,336 @@
+//! Multi-device dashboard.
+//!
+//! This module implements a dashboard that shows the waterfalls of several
+//! Maia SDR devices side by side. Each device is identified by the base URL of
+//! its maia-httpd server (for instance `http://192.168.2.1:8000`). For each
+//! device, the dashboard creates a [`Waterfall`] that receives data through its
+//! own [`WebSocketClient`], and some basic controls to change the RX frequency
+//! of the device.
+//!
+//! Since the dashboard makes requests to the API of devices which are served
+//! from a different origin, the maia-httpd servers need to be configured to
+//! allow cross-origin requests from the origin that serves the dashboard.
+
+use crate::render::RenderEngine;
+use crate::ui::{
+    input::{set_field_metadata, MHzPresentation},
+    request,
+};
+use crate::waterfall::Waterfall;
+use crate::websocket::WebSocketClient;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+use web_sys::{
+    Document, Element, HtmlAnchorElement, HtmlCanvasElement, HtmlElement, HtmlInputElement,
+    HtmlSpanElement, Response, Storage, Window,
+};
+
+use crate::ui::input::{InputElement, NumberInput};
+
+const DEVICES_KEY: &str = "dashboard_devices";
+
+/// Multi-device dashboard.
+///
+/// The dashboard is created with [`Dashboard::new`], which sets up all the
+/// waterfalls and the callbacks that keep them updated.
+pub struct Dashboard {
+    devices: Vec<Device>,
+}
+
+#[derive(Clone)]
+struct Device {
+    window: Rc<Window>,
+    base_url: Rc<str>,
+    render_engine: Rc<RefCell<RenderEngine>>,
+    waterfall: Rc<RefCell<Waterfall>>,
+    info: Rc<HtmlSpanElement>,
+    rx_frequency: NumberInput<maia_json::Hertz, MHzPresentation>,
+}
+
+impl Dashboard {
+    /// Creates a new dashboard.
+    ///
+    /// The list of devices is obtained from the `devices` query parameter of
+    /// the page URL, which contains a comma-separated list of base URLs. If
+    /// this parameter is not present, the list that was last applied by the
+    /// user (which is stored in the local storage) is used. The devices are
+    /// added as children of the `#dashboard` element of the `document`.
+    pub fn new(window: Rc<Window>, document: Rc<Document>) -> Result<Dashboard, JsValue> {
+        let storage = window.local_storage()?;
+        let devices_list = Self::devices_list(&window, storage.as_ref())?;
+        Self::setup_devices_form(&document, storage, &devices_list)?;
+        let container = document
+            .get_element_by_id("dashboard")
+            .ok_or("unable to get #dashboard element")?;
+        let devices = devices_list
+            .iter()
+            .map(|base_url| {
+                let device = Device::new(&window, &document, &container, base_url)?;
+                device.set_callbacks()?;
+                Ok(device)
+            })
+            .collect::<Result<Vec<Device>, JsValue>>()?;
+        Ok(Dashboard { devices })
+    }
+
+    /// Returns the base URLs of the devices in the dashboard.
+    pub fn base_urls(&self) -> Vec<String> {
+        self.devices
+            .iter()
+            .map(|d| d.base_url.to_string())
+            .collect()
+    }
+
+    fn devices_list(window: &Window, storage: Option<&Storage>) -> Result<Vec<String>, JsValue> {
+        let search = window.location().search()?;
+        let query = search.strip_prefix('?').unwrap_or(&search);
+        let from_query = query.split('&').find_map(|param| {
+            param
+                .strip_prefix("devices=")
+                .and_then(|v| js_sys::decode_uri_component(v).ok())
+                .and_then(|v| v.as_string())
+        });
+        let list = match (from_query, storage) {
+            (Some(list), _) => list,
+            (None, Some(storage)) => storage.get_item(DEVICES_KEY)?.unwrap_or_default(),
+            (None, None) => String::new(),
+        };
+        Ok(parse_devices_list(&list))
+    }
+
+    fn setup_devices_form(
+        document: &Document,
+        storage: Option<Storage>,
+        devices_list: &[String],
+    ) -> Result<(), JsValue> {
+        let input = document
+            .get_element_by_id("dashboard_devices")
+            .ok_or("unable to get #dashboard_devices element")?
+            .dyn_into::<HtmlInputElement>()?;
+        input.set_value(&devices_list.join(", "));
+        let apply = document
+            .get_element_by_id("dashboard_apply")
+            .ok_or("unable to get #dashboard_apply element")?
+            .dyn_into::<HtmlElement>()?;
+        let window = web_sys::window().ok_or("unable to get window")?;
+        let onclick = Closure::<dyn Fn()>::new(move || {
+            let list = parse_devices_list(&input.value()).join(",");
+            if let Some(storage) = storage.as_ref() {
+                if let Err(e) = storage.set_item(DEVICES_KEY, &list) {
+                    web_sys::console::error_1(&e);
+                }
+            }
+            // Reload the page without the devices query parameter, so that the
+            // list stored in local storage is used.
+            let location = window.location();
+            if let Err(e) = location
+                .pathname()
+                .and_then(|pathname| location.set_href(&pathname))
+            {
+                web_sys::console::error_1(&e);
+            }
+        });
+        apply.set_onclick(Some(onclick.into_js_value().unchecked_ref()));
+        Ok(())
+    }
+}
+
+fn parse_devices_list(list: &str) -> Vec<String> {
+    list.split([',', ' ', '\n'])
+        .map(|url| url.trim().trim_end_matches('/'))
+        .filter(|url| !url.is_empty())
+        .map(|url| url.to_string())
+        .collect()
+}
+
+impl Device {
+    fn new(
+        window: &Rc<Window>,
+        document: &Document,
+        container: &Element,
+        base_url: &str,
+    ) -> Result<Device, JsValue> {
+        // The URL is validated before it is used anywhere in the document, so
+        // that URLs with other schemes (such as javascript:) never become links.
+        let waterfall_url = waterfall_url(base_url)?;
+        let holder = document.create_element("div")?;
+        holder.set_class_name("dashboard_device");
+
+        let header = document.create_element("div")?;
+        header.set_class_name("dashboard_header");
+        let link = document
+            .create_element("a")?
+            .dyn_into::<HtmlAnchorElement>()?;
+        link.set_href(&format!("{base_url}/"));
+        link.set_target("_blank");
+        link.set_text_content(Some(base_url));
+        header.append_child(&link)?;
+        let rx_frequency = document
+            .create_element("input")?
+            .dyn_into::<HtmlInputElement>()?;
+        rx_frequency.set_type("number");
+        set_field_metadata::<MHzPresentation>(
+            &rx_frequency,
+            &maia_json::PatchAd9361::RX_LO_FREQUENCY_METADATA,
+        );
+        rx_frequency.set_class_name("rf_frequency");
+        let label = document.create_element("label")?;
+        label.append_with_str_1("RX freq ")?;
+        label.append_child(&rx_frequency)?;
+        label.append_with_str_1(" MHz")?;
+        header.append_child(&label)?;
+        let info = document
+            .create_element("span")?
+            .dyn_into::<HtmlSpanElement>()?;
+        header.append_child(&info)?;
+        holder.append_child(&header)?;
+
+        let canvas = Rc::new(
+            document
+                .create_element("canvas")?
+                .dyn_into::<HtmlCanvasElement>()?,
+        );
+        canvas.set_class_name("dashboard_canvas");
+        holder.append_child(&canvas)?;
+        // The canvas must be in the document before the waterfall is created,
+        // so that its dimensions can be obtained.
+        container.append_child(&holder)?;
+
+        let (render_engine, waterfall, _) = crate::new_waterfall(window, document, &canvas)?;
+        WebSocketClient::start_with_url(&waterfall_url, Rc::clone(&waterfall))?;
+        crate::setup_render_loop(Rc::clone(&render_engine), Rc::clone(&waterfall));
+
+        Ok(Device {
+            window: Rc::clone(window),
+            base_url: base_url.into(),
+            render_engine,
+            waterfall,
+            info: Rc::new(info),
+            rx_frequency: NumberInput::from(Rc::new(rx_frequency)),
+        })
+    }
+
+    fn set_callbacks(&self) -> Result<(), JsValue> {
+        let device = self.clone();
+        let onchange = Closure::<dyn Fn() -> JsValue>::new(move || {
+            let Some(freq) = device.rx_frequency.get() else {
+                return JsValue::NULL;
+            };
+            let device = device.clone();
+            future_to_promise(async move {
+                let patch = maia_json::PatchAd9361 {
+                    rx_lo_frequency: Some(freq),
+                    ..Default::default()
+                };
+                let request = request::json_request(
+                    &format!("{}/api/ad9361", device.base_url),
+                    &patch,
+                    "PATCH",
+                )?;
+                let response = JsFuture::from(device.window.fetch_with_request(&request))
+                    .await?
+                    .dyn_into::<Response>()?;
+                if !response.ok() {
+                    let status = response.status();
+                    let error: maia_json::Error = request::response_to_json(&response).await?;
+                    let message = format!(
+                        "PATCH request failed with HTTP code {status}. \
+                         Error description: {}",
+                        error.error_description
+                    );
+                    web_sys::console::error_1(&message.as_str().into());
+                    device.info.set_text_content(Some(&error.error_description));
+                    if matches!(error.suggested_action, maia_json::ErrorAction::Alert) {
+                        device.window.alert_with_message(&error.error_description)?;
+                    }
+                    return Err(message.into());
+                }
+                device.update().await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        });
+        self.rx_frequency
+            .set_onchange(Some(onchange.into_js_value().unchecked_ref()));
+
+        let device = self.clone();
+        let handler = Closure::<dyn Fn() -> js_sys::Promise>::new(move || {
+            let device = device.clone();
+            future_to_promise(async move {
+                if let Err(e) = device.update().await {
+                    device
+                        .info
+                        .set_text_content(Some("unable to connect to device"));
+                    return Err(e);
+                }
+                Ok(JsValue::NULL)
+            })
+        });
+        let handler = handler.into_js_value();
+        let handler: &js_sys::Function = handler.unchecked_ref();
+        handler.call0(&JsValue::NULL)?;
+        self.window
+            .set_interval_with_callback_and_timeout_and_arguments_0(handler, 1000)?;
+        Ok(())
+    }
+
+    async fn update(&self) -> Result<(), JsValue> {
+        // The unversioned API paths are used in the dashboard, since the
+        // devices can run firmware versions that predate API versioning.
+        let response = JsFuture::from(
+            self.window
+                .fetch_with_str(&format!("{}/api", self.base_url)),
+        )
+        .await?
+        .dyn_into::<Response>()?;
+        let api: maia_json::Api = request::response_to_json(&response).await?;
+        let ddc_tuning = match api.spectrometer.input {
+            maia_json::SpectrometerInput::DDC => api.ddc.frequency.0,
+            maia_json::SpectrometerInput::AD9361 => 0.0,
+        };
+        {
+            let mut waterfall = self.waterfall.borrow_mut();
+            waterfall.set_freq_samprate(
+                api.ad9361.rx_lo_frequency.0 + ddc_tuning,
+                api.spectrometer.input_sampling_frequency.0,
+                &mut self.render_engine.borrow_mut(),
+            )?;
+            waterfall.set_frequency_offset(
+                api.device.frequency_offset.0,
+                &mut self.render_engine.borrow_mut(),
+            )?;
+            waterfall
+                .set_waterfall_update_rate(api.spectrometer.output_sampling_frequency.0 as f32);
+        }
+        // Do not overwrite the RX frequency while the user is editing it
+        let editing = self
+            .window
+            .document()
+            .and_then(|d| d.active_element())
+            .is_some_and(|e| JsValue::from(e) == JsValue::from(&*self.rx_frequency));
+        if !editing {
+            self.rx_frequency.set(&api.ad9361.rx_lo_frequency);
+        }
+        self.info.set_text_content(Some(&format!(
+            "{:.3} Msps, gain {:.0} dB ({})",
+            api.spectrometer.input_sampling_frequency.0 * 1e-6,
+            api.ad9361.rx_gain,
+            api.ad9361.rx_gain_mode
+        )));
+        Ok(())
+    }
+}
+
+fn waterfall_url(base_url: &str) -> Result<String, JsValue> {
+    let (protocol, rest) = if let Some(rest) = base_url.strip_prefix("https://") {
+        ("wss", rest)
+    } else if let Some(rest) = base_url.strip_prefix("http://") {
+        ("ws", rest)
+    } else {
+        return Err(format!("invalid device URL {base_url}").into());
+    };
+    Ok(format!("{protocol}://{rest}/waterfall"))
+}
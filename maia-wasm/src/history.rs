@@ -0,0 +1,219 @@
+//! Spectrum history.
+//!
+//! This module keeps a long history of the live spectrum lines, so that the
+//! [`Waterfall`](crate::waterfall::Waterfall) can show several hours of
+//! spectrum coarsely. The history is stored as a set of mipmaps in which each
+//! level averages pairs of lines of the previous level. Each level holds a
+//! fixed number of lines, so the memory used by the history is bounded
+//! regardless of how long it spans, and any level can be drawn using a
+//! waterfall texture of the usual size.
+
+/// Number of levels of the history.
+///
+/// The lines of level `k` are the average of `2^(k+1)` live spectrum lines.
+pub const LEVELS: usize = 8;
+
+/// Number of lines stored in each level of the history.
+pub const LINES_PER_LEVEL: usize = 512;
+
+/// Number of frequency bins of the lines stored in the history.
+///
+/// The live spectrum lines are decimated in frequency by averaging groups of
+/// adjacent bins to this number of bins before they are stored.
+pub const WIDTH: usize = 1024;
+
+/// Spectrum history.
+///
+/// The history is fed with the live spectrum lines by calling
+/// [`History::push`]. The lines are stored in linear power units.
+///
+/// # Examples
+///
+/// ```
+/// use maia_wasm::history::{History, WIDTH};
+///
+/// let mut history = History::new();
+/// for n in 0..8 {
+///     history.push(&vec![n as f32; 4 * WIDTH], Some(1e3 * n as f64));
+/// }
+/// // Each line of level 1 averages 4 live lines
+/// assert_eq!(history.decimation(1), 4);
+/// assert_eq!(history.num_lines(1), 2);
+/// assert_eq!(history.line(1, 1)[0], 5.5);
+/// assert_eq!(history.line_timestamp(1, 1), Some(4e3));
+/// ```
+pub struct History {
+    levels: Vec<Level>,
+    center_freq: f64,
+    samp_rate: f64,
+    // Live spectrum line decimated in frequency
+    line: Vec<f32>,
+}
+
+struct Level {
+    lines: Box<[f32]>,
+    timestamps: Box<[Option<f64>]>,
+    // Index of the line that will be written next
+    next: usize,
+    num_lines: usize,
+    // Sum of the lines that are being averaged
+    accumulator: Box<[f32]>,
+    accumulated: usize,
+    // Timestamp of the first line that is being averaged
+    accumulator_timestamp: Option<f64>,
+}
+
+impl Level {
+    fn new() -> Level {
+        Level {
+            lines: vec![0.0; LINES_PER_LEVEL * WIDTH].into_boxed_slice(),
+            timestamps: vec![None; LINES_PER_LEVEL].into_boxed_slice(),
+            next: 0,
+            num_lines: 0,
+            accumulator: vec![0.0; WIDTH].into_boxed_slice(),
+            accumulated: 0,
+            accumulator_timestamp: None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.next = 0;
+        self.num_lines = 0;
+        self.accumulated = 0;
+    }
+
+    // Adds a line to the accumulator. When two lines have been accumulated,
+    // their average is stored and its index is returned.
+    fn accumulate(&mut self, line: &[f32], timestamp: Option<f64>) -> Option<usize> {
+        if self.accumulated == 0 {
+            self.accumulator.copy_from_slice(line);
+            self.accumulator_timestamp = timestamp;
+            self.accumulated = 1;
+            return None;
+        }
+        let index = self.next;
+        for ((y, &a), &x) in self.lines[index * WIDTH..(index + 1) * WIDTH]
+            .iter_mut()
+            .zip(self.accumulator.iter())
+            .zip(line.iter())
+        {
+            *y = 0.5 * (a + x);
+        }
+        self.timestamps[index] = self.accumulator_timestamp.or(timestamp);
+        self.accumulated = 0;
+        self.next = (self.next + 1) % LINES_PER_LEVEL;
+        self.num_lines = (self.num_lines + 1).min(LINES_PER_LEVEL);
+        Some(index)
+    }
+
+    // Index in the ring buffer of the line with a given age order, where 0 is
+    // the oldest line stored.
+    fn index(&self, line: usize) -> usize {
+        assert!(line < self.num_lines);
+        (self.next + LINES_PER_LEVEL - self.num_lines + line) % LINES_PER_LEVEL
+    }
+}
+
+impl History {
+    /// Creates an empty history.
+    pub fn new() -> History {
+        History {
+            levels: (0..LEVELS).map(|_| Level::new()).collect(),
+            center_freq: 0.0,
+            samp_rate: 0.0,
+            line: vec![0.0; WIDTH],
+        }
+    }
+
+    /// Adds a live spectrum line to the history.
+    ///
+    /// The line is given in linear power units. Its length must be a multiple
+    /// of [`WIDTH`]. The `timestamp` of the line is given as the number of
+    /// milliseconds since the UNIX epoch, as in
+    /// [`Waterfall::put_line_timestamp`](crate::waterfall::Waterfall::put_line_timestamp).
+    pub fn push(&mut self, line: &[f32], timestamp: Option<f64>) {
+        assert_eq!(line.len() % WIDTH, 0);
+        let bins = line.len() / WIDTH;
+        let scale = 1.0 / bins as f32;
+        for (y, x) in self.line.iter_mut().zip(line.chunks_exact(bins)) {
+            *y = scale * x.iter().sum::<f32>();
+        }
+        let mut timestamp = timestamp;
+        for n in 0..LEVELS {
+            let Some(index) = self.levels[n].accumulate(&self.line, timestamp) else {
+                break;
+            };
+            let level = &self.levels[n];
+            self.line
+                .copy_from_slice(&level.lines[index * WIDTH..(index + 1) * WIDTH]);
+            timestamp = level.timestamps[index];
+        }
+    }
+
+    /// Removes all the lines from the history.
+    pub fn clear(&mut self) {
+        for level in self.levels.iter_mut() {
+            level.clear();
+        }
+    }
+
+    /// Sets the center frequency and sample rate of the spectrum lines.
+    ///
+    /// The center frequency and sample rate are given in units of Hz and
+    /// samples per second. If they are different from those of the lines
+    /// stored in the history, the history is cleared, since the old lines
+    /// cannot be shown together with the new ones.
+    pub fn set_freq_samprate(&mut self, center_freq: f64, samp_rate: f64) {
+        if center_freq != self.center_freq || samp_rate != self.samp_rate {
+            self.center_freq = center_freq;
+            self.samp_rate = samp_rate;
+            self.clear();
+        }
+    }
+
+    /// Returns the center frequency of the spectrum lines (in Hz).
+    pub fn center_freq(&self) -> f64 {
+        self.center_freq
+    }
+
+    /// Returns the sample rate of the spectrum lines (in samples per second).
+    pub fn samp_rate(&self) -> f64 {
+        self.samp_rate
+    }
+
+    /// Returns the number of live spectrum lines averaged in each line of a
+    /// level.
+    pub fn decimation(&self, level: usize) -> usize {
+        2 << level
+    }
+
+    /// Returns the number of lines stored in a level.
+    pub fn num_lines(&self, level: usize) -> usize {
+        self.levels[level].num_lines
+    }
+
+    /// Returns a line of a level.
+    ///
+    /// The lines are indexed in time order, so the line with index 0 is the
+    /// oldest line stored in the level. The line has [`WIDTH`] frequency bins.
+    pub fn line(&self, level: usize, line: usize) -> &[f32] {
+        let level = &self.levels[level];
+        let index = level.index(line);
+        &level.lines[index * WIDTH..(index + 1) * WIDTH]
+    }
+
+    /// Returns the timestamp of a line of a level.
+    ///
+    /// This is the timestamp of the first live spectrum line averaged in the
+    /// line, if it is known. The lines are indexed as in [`History::line`].
+    pub fn line_timestamp(&self, level: usize, line: usize) -> Option<f64> {
+        let level = &self.levels[level];
+        level.timestamps[level.index(line)]
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
@@ -1,6 +1,47 @@
 //! maia-wasm is part of Maia SDR. It is a web application that serves as the UI
 //! of Maia SDR. It renders the waterfall using WebGL2 and gives a UI composed
 //! of HTML elements that interacts with the maia-httpd RESTful API.
+//!
+//! # Standalone waterfall
+//!
+//! The waterfall can also be used as a widget by other web applications. The
+//! web application of Maia SDR is contained in the `app` feature, which is
+//! enabled by default. When maia-wasm is used with `default-features = false`,
+//! only the waterfall and its render engine are included, and there is no
+//! dependency on the maia-httpd API.
+//!
+//! The waterfall is created with [`new_waterfall`] on a canvas element and
+//! rendered with [`setup_render_loop`]. The [`WaterfallInteraction`] object
+//! returned by `new_waterfall` handles the resizes of the canvas, as well as
+//! zooming and panning with the mouse and touch gestures. The frequency axis
+//! is set with [`Waterfall::set_freq_samprate`], and the spectrum lines, which
+//! contain [`Waterfall::SPECTRUM_POINTS`] points in linear power units, are
+//! added with [`Waterfall::put_waterfall_spectrum`].
+//!
+//! ```no_run
+//! use std::rc::Rc;
+//! use wasm_bindgen::{JsCast, JsValue};
+//!
+//! fn waterfall_widget() -> Result<(), JsValue> {
+//!     let (window, document) = maia_wasm::get_window_and_document()?;
+//!     let canvas = Rc::new(
+//!         document
+//!             .get_element_by_id("canvas")
+//!             .ok_or("unable to get #canvas element")?
+//!             .dyn_into::<web_sys::HtmlCanvasElement>()?,
+//!     );
+//!     let (render_engine, waterfall, _) =
+//!         maia_wasm::new_waterfall(&window, &document, &canvas)?;
+//!     {
+//!         let mut waterfall = waterfall.borrow_mut();
+//!         waterfall.set_freq_samprate(915e6, 960e3, &mut render_engine.borrow_mut())?;
+//!         let line = vec![1e4; maia_wasm::waterfall::Waterfall::SPECTRUM_POINTS];
+//!         waterfall.put_waterfall_spectrum(&js_sys::Float32Array::from(&line[..]));
+//!     }
+//!     maia_wasm::setup_render_loop(render_engine, waterfall);
+//!     Ok(())
+//! }
+//! ```
 
 #![warn(missing_docs)]
 
@@ -10,19 +51,29 @@ use wasm_bindgen::{prelude::*, JsCast};
 use web_sys::{Document, HtmlCanvasElement, Window};
 
 use crate::render::RenderEngine;
-use crate::ui::Ui;
+#[cfg(feature = "app")]
+use crate::ui::{extension::UiBuilder, Ui};
 use crate::waterfall::Waterfall;
 use crate::waterfall_interaction::WaterfallInteraction;
+#[cfg(feature = "app")]
 use crate::websocket::WebSocketClient;
 
 pub mod array_view;
-pub mod colormap;
+pub use maia_json::colormap;
+#[cfg(feature = "app")]
+pub mod dashboard;
+pub mod history;
+pub mod offline;
 pub mod pointer;
 pub mod render;
+pub mod simd;
+#[cfg(feature = "app")]
 pub mod ui;
+#[cfg(feature = "app")]
 pub mod version;
 pub mod waterfall;
 pub mod waterfall_interaction;
+#[cfg(feature = "app")]
 pub mod websocket;
 
 /// Initialize the wasm module.
@@ -42,8 +93,20 @@ pub fn start() -> Result<(), JsValue> {
 /// This function starts the maia-wasm application. It should be called from
 /// JavaScript when the web page is loaded. It sets up all the objects and
 /// callbacks that keep the application running.
+#[cfg(feature = "app")]
 #[wasm_bindgen]
 pub fn maia_wasm_start() -> Result<(), JsValue> {
+    maia_wasm_start_with(|builder| builder)
+}
+
+/// Starts the maia-wasm web application with a customized UI.
+///
+/// This function does the same as [`maia_wasm_start`], but it calls
+/// `configure` with the [`UiBuilder`] before building the [`Ui`]. This allows
+/// crates that embed maia-wasm to register their own
+/// [`UiExtension`](crate::ui::extension::UiExtension)s.
+#[cfg(feature = "app")]
+pub fn maia_wasm_start_with(configure: impl FnOnce(UiBuilder) -> UiBuilder) -> Result<(), JsValue> {
     let (window, document) = get_window_and_document()?;
     let canvas = Rc::new(
         document
@@ -55,12 +118,13 @@ pub fn maia_wasm_start() -> Result<(), JsValue> {
     let (render_engine, waterfall, mut waterfall_interaction) =
         new_waterfall(&window, &document, &canvas)?;
     WebSocketClient::start(&window, Rc::clone(&waterfall))?;
-    let ui = Ui::new(
+    let ui = configure(Ui::builder(
         Rc::clone(&window),
         Rc::clone(&document),
         Rc::clone(&render_engine),
         Rc::clone(&waterfall),
-    )?;
+    ))
+    .build()?;
     waterfall_interaction.set_ui(ui);
 
     setup_render_loop(render_engine, waterfall);
@@ -68,6 +132,21 @@ pub fn maia_wasm_start() -> Result<(), JsValue> {
     Ok(())
 }
 
+/// Starts the maia-wasm multi-device dashboard.
+///
+/// This function starts the dashboard application, which shows the waterfalls
+/// of several Maia SDR devices side by side (see [`dashboard`]). It should be
+/// called from JavaScript when the dashboard web page is loaded.
+#[cfg(feature = "app")]
+#[wasm_bindgen]
+pub fn maia_wasm_start_dashboard() -> Result<(), JsValue> {
+    let (window, document) = get_window_and_document()?;
+    // The dashboard lives for the whole duration of the web page, since its
+    // callbacks are leaked.
+    dashboard::Dashboard::new(window, document)?;
+    Ok(())
+}
+
 /// Returns the [`Window`] and [`Document`] objects.
 ///
 /// These are returned inside an [`Rc`] so that their ownership can be shared.
@@ -119,13 +198,24 @@ pub fn new_waterfall(
 /// time the the callback triggers, the waterfall is prepared for rendering and
 /// the render engine is called. Then, the rendering of the next frame is
 /// scheduled using `requestAnimationFrame()`.
+///
+/// Rendering is skipped while the page is hidden (for instance, because it is
+/// in a background tab). The spectrum lines added to the waterfall in the
+/// meantime are shown when the page becomes visible again.
 pub fn setup_render_loop(
     render_engine: Rc<RefCell<RenderEngine>>,
     waterfall: Rc<RefCell<Waterfall>>,
 ) {
     let f = Rc::new(RefCell::new(None));
     let g = f.clone();
+    let document = web_sys::window().and_then(|window| window.document());
     *g.borrow_mut() = Some(Closure::new(move |dt| {
+        if document.as_ref().is_some_and(|document| document.hidden()) {
+            // Most browsers do not call requestAnimationFrame callbacks for
+            // hidden pages, but this is not guaranteed.
+            request_animation_frame(f.borrow().as_ref().unwrap());
+            return;
+        }
         let mut render_engine = render_engine.borrow_mut();
         if let Err(e) = waterfall
             .borrow_mut()
@@ -0,0 +1,493 @@
+//! Offline IQ viewer.
+//!
+//! This module computes the spectrogram of an IQ recording that is opened
+//! locally in the browser, so that it can be displayed in the
+//! [`Waterfall`](crate::waterfall::Waterfall) in the same way as the live
+//! spectrum. SigMF archives, such as the recordings downloaded from
+//! maia-httpd, and raw IQ files are supported.
+
+use serde::Deserialize;
+use std::ops::Range;
+
+/// Number of frequency bins of the spectrogram.
+///
+/// This matches the FFT size of the spectrometer in the FPGA, so that each
+/// line of the spectrogram fills a line of the waterfall.
+pub const FFT_SIZE: usize = 4096;
+
+// Maximum number of FFTs that are averaged in each spectrogram line. Long
+// recordings are decimated in time by skipping some of the samples in order to
+// bound the computation time.
+const MAX_FFTS_PER_LINE: usize = 32;
+
+// Size of the blocks of a tar archive.
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// IQ sample format.
+///
+/// This enum lists the sample formats of the IQ files that can be opened.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum SampleFormat {
+    /// Complex 8-bit signed integer.
+    Ci8,
+    /// Complex 16-bit signed integer, little-endian.
+    Ci16Le,
+    /// Complex 32-bit float, little-endian.
+    Cf32Le,
+}
+
+impl SampleFormat {
+    /// Returns the sample format corresponding to a SigMF datatype.
+    ///
+    /// Only the complex datatypes that have a corresponding [`SampleFormat`]
+    /// are supported. `None` is returned for other datatypes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::offline::SampleFormat;
+    ///
+    /// assert_eq!(SampleFormat::from_sigmf_datatype("ci16_le"), Some(SampleFormat::Ci16Le));
+    /// assert_eq!(SampleFormat::from_sigmf_datatype("ri16_le"), None);
+    /// ```
+    pub fn from_sigmf_datatype(datatype: &str) -> Option<SampleFormat> {
+        match datatype {
+            "ci8" => Some(SampleFormat::Ci8),
+            "ci16_le" => Some(SampleFormat::Ci16Le),
+            "cf32_le" => Some(SampleFormat::Cf32Le),
+            _ => None,
+        }
+    }
+
+    /// Returns the sample format corresponding to the extension of a raw IQ
+    /// file.
+    ///
+    /// The extensions used by GNU Radio and other common SDR software are
+    /// supported.
+    pub fn from_extension(extension: &str) -> Option<SampleFormat> {
+        match extension.to_ascii_lowercase().as_str() {
+            "ci8" | "cs8" => Some(SampleFormat::Ci8),
+            "ci16" | "cs16" | "sc16" => Some(SampleFormat::Ci16Le),
+            "cf32" | "cfile" | "fc32" => Some(SampleFormat::Cf32Le),
+            _ => None,
+        }
+    }
+
+    /// Returns the size of a sample (in bytes).
+    pub fn sample_size(&self) -> usize {
+        match self {
+            SampleFormat::Ci8 => 2,
+            SampleFormat::Ci16Le => 4,
+            SampleFormat::Cf32Le => 8,
+        }
+    }
+
+    // Converts a sample to floating point. The samples are scaled so that the
+    // full scale of all the formats is that of 16-bit integers, which gives
+    // power levels similar to those of the spectrometer.
+    fn sample(&self, bytes: &[u8]) -> Complex {
+        match self {
+            SampleFormat::Ci8 => Complex::new(
+                f32::from(bytes[0] as i8) * 256.0,
+                f32::from(bytes[1] as i8) * 256.0,
+            ),
+            SampleFormat::Ci16Le => Complex::new(
+                f32::from(i16::from_le_bytes([bytes[0], bytes[1]])),
+                f32::from(i16::from_le_bytes([bytes[2], bytes[3]])),
+            ),
+            SampleFormat::Cf32Le => Complex::new(
+                f32::from_le_bytes(bytes[..4].try_into().unwrap()) * 32768.0,
+                f32::from_le_bytes(bytes[4..8].try_into().unwrap()) * 32768.0,
+            ),
+        }
+    }
+}
+
+/// IQ file.
+///
+/// This struct describes an IQ file that has been opened locally. It borrows
+/// the contents of the file.
+#[derive(Debug, Clone)]
+pub struct IqFile<'a> {
+    samples: &'a [u8],
+    format: SampleFormat,
+    sample_rate: Option<f64>,
+    frequency: Option<f64>,
+    datetime: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SigmfMeta {
+    global: SigmfGlobal,
+    #[serde(default)]
+    captures: Vec<SigmfCapture>,
+}
+
+#[derive(Deserialize)]
+struct SigmfGlobal {
+    #[serde(rename = "core:datatype")]
+    datatype: String,
+    #[serde(rename = "core:sample_rate")]
+    sample_rate: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct SigmfCapture {
+    #[serde(rename = "core:frequency")]
+    frequency: Option<f64>,
+    #[serde(rename = "core:datetime")]
+    datetime: Option<String>,
+}
+
+impl<'a> IqFile<'a> {
+    /// Parses the contents of a file.
+    ///
+    /// Files with a `.sigmf` extension are parsed as a SigMF archive, which is
+    /// a tar file containing the SigMF metadata and data. Other files are
+    /// parsed as raw IQ files, whose sample format is given by the extension.
+    /// Raw IQ files do not contain a sample rate nor a frequency.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::offline::{IqFile, SampleFormat};
+    ///
+    /// let samples = [0u8; 64];
+    /// let file = IqFile::parse("recording.cs16", &samples).unwrap();
+    /// assert_eq!(file.format(), SampleFormat::Ci16Le);
+    /// assert_eq!(file.num_samples(), 16);
+    /// assert_eq!(file.sample_rate(), None);
+    /// assert!(IqFile::parse("recording.wav", &samples).is_err());
+    /// ```
+    pub fn parse(filename: &str, contents: &'a [u8]) -> Result<IqFile<'a>, String> {
+        let extension = filename.rsplit_once('.').map_or("", |(_, ext)| ext);
+        if extension.eq_ignore_ascii_case("sigmf") {
+            return Self::parse_sigmf_archive(contents);
+        }
+        let format = SampleFormat::from_extension(extension)
+            .ok_or_else(|| format!("unsupported file extension: {filename}"))?;
+        Ok(IqFile {
+            samples: contents,
+            format,
+            sample_rate: None,
+            frequency: None,
+            datetime: None,
+        })
+    }
+
+    fn parse_sigmf_archive(contents: &'a [u8]) -> Result<IqFile<'a>, String> {
+        let mut meta = None;
+        let mut data = None;
+        for (name, range) in TarEntries::new(contents) {
+            if name.ends_with(".sigmf-meta") {
+                meta = Some(&contents[range]);
+            } else if name.ends_with(".sigmf-data") {
+                data = Some(&contents[range]);
+            }
+        }
+        let meta = meta.ok_or("SigMF archive does not contain a .sigmf-meta file")?;
+        let data = data.ok_or("SigMF archive does not contain a .sigmf-data file")?;
+        let meta: SigmfMeta = serde_json::from_slice(meta)
+            .map_err(|err| format!("unable to parse SigMF metadata: {err}"))?;
+        let format = SampleFormat::from_sigmf_datatype(&meta.global.datatype)
+            .ok_or_else(|| format!("unsupported SigMF datatype: {}", meta.global.datatype))?;
+        let capture = meta.captures.into_iter().next();
+        Ok(IqFile {
+            samples: data,
+            format,
+            sample_rate: meta.global.sample_rate,
+            frequency: capture.as_ref().and_then(|capture| capture.frequency),
+            datetime: capture.and_then(|capture| capture.datetime),
+        })
+    }
+
+    /// Returns the sample format of the file.
+    pub fn format(&self) -> SampleFormat {
+        self.format
+    }
+
+    /// Returns the number of IQ samples in the file.
+    pub fn num_samples(&self) -> usize {
+        self.samples.len() / self.format.sample_size()
+    }
+
+    /// Returns the sample rate of the file (in samples per second).
+    ///
+    /// This is `None` if the file does not contain metadata.
+    pub fn sample_rate(&self) -> Option<f64> {
+        self.sample_rate
+    }
+
+    /// Returns the center frequency of the file (in Hz).
+    ///
+    /// This is `None` if the file does not contain metadata.
+    pub fn frequency(&self) -> Option<f64> {
+        self.frequency
+    }
+
+    /// Returns the start time of the file.
+    ///
+    /// This is the SigMF `core:datetime` of the first capture, which is an
+    /// ISO 8601 string. It is `None` if the file does not contain metadata.
+    pub fn datetime(&self) -> Option<&str> {
+        self.datetime.as_deref()
+    }
+
+    /// Computes the spectrogram of the file.
+    ///
+    /// The spectrogram has at most `max_lines` lines of [`FFT_SIZE`] bins. The
+    /// file is split in time into consecutive segments, one for each line,
+    /// and the power spectra of the FFTs in each segment are averaged. A Hann
+    /// window is applied to each FFT. The spectrum lines are given in linear
+    /// power units, with the DC bin in the center, as the lines produced by
+    /// the spectrometer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::offline::{IqFile, FFT_SIZE};
+    ///
+    /// // A complex exponential at +1/4 of the sample rate
+    /// let samples = (0..3 * FFT_SIZE)
+    ///     .flat_map(|n| {
+    ///         let phase = std::f32::consts::FRAC_PI_2 * n as f32;
+    ///         [phase.cos(), phase.sin()]
+    ///     })
+    ///     .flat_map(f32::to_le_bytes)
+    ///     .collect::<Vec<u8>>();
+    /// let file = IqFile::parse("tone.cf32", &samples).unwrap();
+    /// let spectrogram = file.spectrogram(512).unwrap();
+    /// assert_eq!(spectrogram.num_lines(), 3);
+    /// let line = spectrogram.line(0);
+    /// let peak = (0..FFT_SIZE)
+    ///     .max_by(|&a, &b| line[a].total_cmp(&line[b]))
+    ///     .unwrap();
+    /// assert_eq!(peak, 3 * FFT_SIZE / 4);
+    /// ```
+    pub fn spectrogram(&self, max_lines: usize) -> Result<Spectrogram, String> {
+        let total_ffts = self.num_samples() / FFT_SIZE;
+        if total_ffts == 0 {
+            return Err(format!(
+                "the file is too short (it must contain at least {FFT_SIZE} samples)"
+            ));
+        }
+        let num_lines = total_ffts.min(max_lines.max(1));
+        let fft = Fft::new(FFT_SIZE);
+        let window = (0..FFT_SIZE)
+            .map(|n| {
+                let s = (std::f32::consts::PI * n as f32 / FFT_SIZE as f32).sin();
+                s * s
+            })
+            .collect::<Vec<f32>>();
+        let block_size = FFT_SIZE * self.format.sample_size();
+        let mut buffer = vec![Complex::default(); FFT_SIZE];
+        let mut lines = vec![0.0; num_lines * FFT_SIZE];
+        let mut line_starts = Vec::with_capacity(num_lines);
+        for (n, line) in lines.chunks_exact_mut(FFT_SIZE).enumerate() {
+            let ffts = n * total_ffts / num_lines..(n + 1) * total_ffts / num_lines;
+            line_starts.push(ffts.start * FFT_SIZE);
+            // Use at most MAX_FFTS_PER_LINE FFTs, evenly spaced in the segment
+            let step = ffts.len().div_ceil(MAX_FFTS_PER_LINE);
+            let mut averaged = 0;
+            for k in ffts.step_by(step) {
+                let block = &self.samples[k * block_size..(k + 1) * block_size];
+                for ((x, sample), w) in buffer
+                    .iter_mut()
+                    .zip(block.chunks_exact(self.format.sample_size()))
+                    .zip(&window)
+                {
+                    *x = self.format.sample(sample).scale(*w);
+                }
+                fft.process(&mut buffer);
+                // Accumulate with fftshift
+                let (neg, pos) = buffer.split_at(FFT_SIZE / 2);
+                for (y, x) in line.iter_mut().zip(pos.iter().chain(neg)) {
+                    *y += x.norm_sqr();
+                }
+                averaged += 1;
+            }
+            let scale = 1.0 / averaged as f32;
+            for y in line.iter_mut() {
+                *y *= scale;
+            }
+        }
+        Ok(Spectrogram { lines, line_starts })
+    }
+}
+
+/// Spectrogram.
+///
+/// This struct contains the spectrogram of an IQ file, computed with
+/// [`IqFile::spectrogram`].
+#[derive(Debug, Clone)]
+pub struct Spectrogram {
+    lines: Vec<f32>,
+    // Index of the first sample of each line
+    line_starts: Vec<usize>,
+}
+
+impl Spectrogram {
+    /// Returns the number of lines of the spectrogram.
+    pub fn num_lines(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// Returns a line of the spectrogram.
+    ///
+    /// The line contains [`FFT_SIZE`] bins in linear power units.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `index` is not smaller than the number of
+    /// lines.
+    pub fn line(&self, index: usize) -> &[f32] {
+        &self.lines[index * FFT_SIZE..(index + 1) * FFT_SIZE]
+    }
+
+    /// Returns the index of the first sample of a line of the spectrogram.
+    ///
+    /// Dividing the index by the sample rate gives the time of the line with
+    /// respect to the start of the file.
+    pub fn line_start(&self, index: usize) -> usize {
+        self.line_starts[index]
+    }
+
+    /// Returns suitable levels for displaying the spectrogram.
+    ///
+    /// The levels are given in dB, as a minimum and maximum, which are
+    /// slightly below the noise floor and at the strongest signals
+    /// respectively. The minimum is never negative.
+    pub fn levels(&self) -> (f32, f32) {
+        let mut db = self
+            .lines
+            .iter()
+            .filter(|&&x| x > 0.0)
+            .map(|x| 10.0 * x.log10())
+            .collect::<Vec<f32>>();
+        if db.is_empty() {
+            return (0.0, 1.0);
+        }
+        db.sort_unstable_by(f32::total_cmp);
+        let percentile = |p: f32| db[((db.len() - 1) as f32 * p).round() as usize];
+        let min = (percentile(0.1) - 3.0).floor().max(0.0);
+        let max = percentile(0.9999).ceil().max(min + 20.0);
+        (min, max)
+    }
+}
+
+// Iterator over the entries of a tar archive, giving the name and the range of
+// the archive in which the contents of each regular file are.
+struct TarEntries<'a> {
+    archive: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TarEntries<'a> {
+    fn new(archive: &'a [u8]) -> TarEntries<'a> {
+        TarEntries { archive, offset: 0 }
+    }
+}
+
+impl<'a> Iterator for TarEntries<'a> {
+    type Item = (&'a str, Range<usize>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let header = self
+                .archive
+                .get(self.offset..self.offset + TAR_BLOCK_SIZE)?;
+            if header.iter().all(|&b| b == 0) {
+                // End of archive
+                return None;
+            }
+            let field = |range: Range<usize>| {
+                let field = &header[range];
+                let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+                std::str::from_utf8(&field[..len]).ok()
+            };
+            let size = usize::from_str_radix(field(124..136)?.trim(), 8).ok()?;
+            let typeflag = header[156];
+            let name = field(0..100)?;
+            let start = self.offset + TAR_BLOCK_SIZE;
+            let end = start.checked_add(size)?;
+            if end > self.archive.len() {
+                return None;
+            }
+            self.offset = start + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+            // Only regular files are returned
+            if typeflag == b'0' || typeflag == 0 {
+                return Some((name, start..end));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Default)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    fn new(re: f32, im: f32) -> Complex {
+        Complex { re, im }
+    }
+
+    fn scale(self, a: f32) -> Complex {
+        Complex::new(a * self.re, a * self.im)
+    }
+
+    fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+// Radix-2 decimation-in-time FFT.
+struct Fft {
+    twiddles: Vec<Complex>,
+}
+
+impl Fft {
+    fn new(size: usize) -> Fft {
+        assert!(size.is_power_of_two());
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let phase = -2.0 * std::f64::consts::PI * k as f64 / size as f64;
+                Complex::new(phase.cos() as f32, phase.sin() as f32)
+            })
+            .collect();
+        Fft { twiddles }
+    }
+
+    fn process(&self, data: &mut [Complex]) {
+        let size = 2 * self.twiddles.len();
+        assert_eq!(data.len(), size);
+        let bits = size.trailing_zeros();
+        for j in 0..size {
+            let k = j.reverse_bits() >> (usize::BITS - bits);
+            if j < k {
+                data.swap(j, k);
+            }
+        }
+        let mut len = 2;
+        while len <= size {
+            let stride = size / len;
+            for chunk in data.chunks_exact_mut(len) {
+                let (a, b) = chunk.split_at_mut(len / 2);
+                for (j, (x, y)) in a.iter_mut().zip(b.iter_mut()).enumerate() {
+                    let t = y.mul(self.twiddles[j * stride]);
+                    *y = Complex::new(x.re - t.re, x.im - t.im);
+                    *x = Complex::new(x.re + t.re, x.im + t.im);
+                }
+            }
+            len *= 2;
+        }
+    }
+}
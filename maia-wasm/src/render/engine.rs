@@ -178,6 +178,10 @@ mod render_engine {
             gl_attrs.set_power_preference(web_sys::WebGlPowerPreference::LowPower);
             let canvas_dims = CanvasDims::from_canvas_and_window(&canvas, &window);
             let current = Current::new(&gl)?;
+            let max_texture_size =
+                gl.get_parameter(WebGl2RenderingContext::MAX_TEXTURE_SIZE)?
+                    .as_f64()
+                    .ok_or("MAX_TEXTURE_SIZE is not a number")? as u32;
 
             // We use pre-multiplied alpha to obtain correct results with bilinear
             // interpolation. See
@@ -195,7 +199,7 @@ mod render_engine {
                 gl,
                 current,
                 objects: Vec::new(),
-                text_render: TextRender::new(document)?,
+                text_render: TextRender::new(document, max_texture_size)?,
             })
         }
 
@@ -331,8 +335,11 @@ mod render_engine {
         /// bounding boxes of each text is return, allowing parts of the texture
         /// to be used to display each text.
         ///
-        /// A fixed text height in pixels, `text_height_px`, is used to set the
-        /// size of the font and of the image loaded in the texture.
+        /// A fixed text height in CSS pixels, `text_height_px`, is used to set
+        /// the size of the font. The texts are rasterized at the device pixel
+        /// ratio of the canvas, so that they look sharp in high-DPI displays.
+        /// If the texture would exceed the maximum texture size supported by
+        /// WebGL, the texts are rasterized at a lower resolution instead.
         pub fn render_texts_to_texture(
             &mut self,
             texture: &Rc<WebGlTexture>,
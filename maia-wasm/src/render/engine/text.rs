@@ -6,6 +6,7 @@ use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
 pub struct TextRender {
     canvas: HtmlCanvasElement,
     context: CanvasRenderingContext2d,
+    max_texture_size: u32,
 }
 
 /// Rendered texts dimensions.
@@ -25,6 +26,12 @@ pub struct TextsDimensions {
     /// This gives the width of the bounding box of each of the rendered
     /// strings, using screen coordinates.
     pub text_width: f32,
+    /// Width of each string.
+    ///
+    /// This gives the width that each of the rendered strings actually
+    /// occupies, using screen coordinates. It is at most
+    /// [`TextsDimensions::text_width`].
+    pub text_widths: Vec<f32>,
     /// Height for the bounding box of each string.
     ///
     /// This gives the height of the bounding box of each of the rendered
@@ -33,7 +40,7 @@ pub struct TextsDimensions {
 }
 
 impl TextRender {
-    pub fn new(document: &web_sys::Document) -> Result<TextRender, JsValue> {
+    pub fn new(document: &web_sys::Document, max_texture_size: u32) -> Result<TextRender, JsValue> {
         let canvas = document
             .create_element("canvas")?
             .dyn_into::<HtmlCanvasElement>()?;
@@ -41,7 +48,11 @@ impl TextRender {
             .get_context("2d")?
             .ok_or("unable to get 2d context")?
             .dyn_into::<CanvasRenderingContext2d>()?;
-        Ok(TextRender { canvas, context })
+        Ok(TextRender {
+            canvas,
+            context,
+            max_texture_size,
+        })
     }
 
     pub fn canvas(&self) -> &HtmlCanvasElement {
@@ -65,28 +76,50 @@ impl TextRender {
         dims: CanvasDims,
         height_px: u32,
     ) -> Result<TextsDimensions, JsValue> {
-        // Find maximum width over all the texts
-        self.set_font(height_px);
-        let mut max = None;
-        for text in texts.iter() {
-            let w = self.context.measure_text(text)?.width();
-            max = match (max, w) {
-                (Some(z), w) if z >= w => Some(z),
-                _ => Some(w),
-            };
-        }
-        let width_px = max.ok_or("no texts specified")?.ceil() as u32;
+        // The texts are rasterized in device pixels, so that they are crisp in
+        // high-DPI displays. The dimensions that are returned are given in
+        // screen coordinates, so they do not depend on the device pixel ratio.
+        let (canvas_width_px, canvas_height_px) = dims.device_pixels();
+        let screen_height_px = ((height_px as f64 * dims.device_pixel_ratio).round() as u32).max(1);
+        let mut height_px = screen_height_px;
 
-        // Add some pixels of vertical margin to prevent pieces of texts
-        // from showing in the labels for other texts
-        let height_px_margin = height_px + 2;
-        let margin = 0.5 * (1.0 - height_px as f32 / height_px_margin as f32);
+        // Lay out the texts in a grid, reducing the rasterization height if
+        // the grid does not fit in the maximum texture size.
+        let (widths, width_px, height_px_margin, n, m) = loop {
+            // Find maximum width over all the texts
+            self.set_font(height_px);
+            let widths = texts
+                .iter()
+                .map(|text| Ok(self.context.measure_text(text)?.width()))
+                .collect::<Result<Vec<f64>, JsValue>>()?;
+            let width_px = (widths
+                .iter()
+                .copied()
+                .reduce(f64::max)
+                .ok_or("no texts specified")?
+                .ceil() as u32)
+                .max(1);
+
+            // Add some pixels of vertical margin to prevent pieces of texts
+            // from showing in the labels for other texts
+            let height_px_margin = height_px + 2;
 
-        // Set 2D canvas dimensions to contain all the texts
-        let n = (texts.len() as f32 * width_px as f32 / height_px as f32)
-            .sqrt()
-            .round() as usize;
-        let m = texts.len().div_ceil(n);
+            // Use a 2D canvas that contains all the texts
+            let n = ((texts.len() as f32 * width_px as f32 / height_px as f32)
+                .sqrt()
+                .round() as usize)
+                .max(1);
+            let m = texts.len().div_ceil(n);
+            let total_px = (height_px_margin * n as u32).max(width_px * m as u32);
+            if total_px <= self.max_texture_size || height_px == 1 {
+                break (widths, width_px, height_px_margin, n, m);
+            }
+            height_px =
+                ((height_px as u64 * u64::from(self.max_texture_size) / u64::from(total_px))
+                    as u32)
+                    .clamp(1, height_px - 1);
+        };
+        let margin = 0.5 * (1.0 - height_px as f32 / height_px_margin as f32);
         let total_height_px = height_px_margin * n as u32;
         let total_width_px = width_px * m as u32;
         self.canvas.set_width(total_width_px);
@@ -122,11 +155,19 @@ impl TextRender {
             texture_coords.push((a as f32 + margin) / n as f32);
         }
 
-        let width_relative = 2.0 * width_px as f32 / dims.width as f32;
-        let height_relative = 2.0 * height_px as f32 / dims.height as f32;
+        // The screen dimensions correspond to the requested text height, even
+        // if the texts have been rasterized at a lower resolution.
+        let scale = screen_height_px as f32 / height_px as f32;
+        let width_relative = 2.0 * scale * width_px as f32 / canvas_width_px as f32;
+        let height_relative = 2.0 * screen_height_px as f32 / canvas_height_px as f32;
+        let widths_relative = widths
+            .iter()
+            .map(|&w| 2.0 * scale * w as f32 / canvas_width_px as f32)
+            .collect();
         Ok(TextsDimensions {
             texture_coordinates: texture_coords,
             text_width: width_relative,
+            text_widths: widths_relative,
             text_height: height_relative,
         })
     }
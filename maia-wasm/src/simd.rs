@@ -0,0 +1,56 @@
+//! Vectorized spectrum preprocessing.
+//!
+//! This module contains the computations that are done for each bin of each
+//! spectrum line before it is drawn in the waterfall. They are written without
+//! branches or calls to non-inlined functions, so that the compiler can
+//! vectorize them. The crate is built with the `simd128` target feature for
+//! `wasm32` (see `.cargo/config.toml`), so these computations use the
+//! WebAssembly SIMD instructions, which keeps the main thread responsive at
+//! high waterfall line rates on low-end devices.
+
+use std::f32::consts::{LOG10_2, LOG10_E, SQRT_2};
+
+/// Computes the base-10 logarithm of a spectrum line in place.
+///
+/// The spectrum line is given in linear power units. Bins which are zero are
+/// left as zero, rather than replaced by minus infinity, to avoid the shaders
+/// from having to handle this case. Subnormal values are rounded up to
+/// [`f32::MIN_POSITIVE`].
+///
+/// The logarithm is computed with an absolute error smaller than `1e-6` (in
+/// addition to the rounding errors of `f32`), which is much smaller than the
+/// resolution of the waterfall.
+///
+/// # Examples
+///
+/// ```
+/// use maia_wasm::simd::log10_in_place;
+///
+/// let mut line = [0.0, 1.0, 2.0, 1e-3, 3.5e7];
+/// log10_in_place(&mut line);
+/// assert_eq!(line[0], 0.0);
+/// for (x, y) in line[1..].iter().zip([0.0, 2.0f32.log10(), -3.0, 3.5e7f32.log10()]) {
+///     assert!((x - y).abs() < 1e-5);
+/// }
+/// ```
+pub fn log10_in_place(line: &mut [f32]) {
+    for x in line.iter_mut() {
+        let bits = x.max(f32::MIN_POSITIVE).to_bits();
+        // Decompose x = 2^exponent * mantissa, with mantissa in
+        // [sqrt(2)/2, sqrt(2)).
+        let exponent = ((bits >> 23) as i32 - 127) as f32;
+        let mantissa = f32::from_bits((bits & 0x007f_ffff) | 0x3f80_0000);
+        let (exponent, mantissa) = if mantissa > SQRT_2 {
+            (exponent + 1.0, 0.5 * mantissa)
+        } else {
+            (exponent, mantissa)
+        };
+        // ln(mantissa) = 2 atanh(t), with |t| < 0.172, so a few terms of the
+        // Taylor series of atanh are enough.
+        let t = (mantissa - 1.0) / (mantissa + 1.0);
+        let t2 = t * t;
+        let ln_mantissa = 2.0 * t * (1.0 + t2 * (1.0 / 3.0 + t2 * (1.0 / 5.0 + t2 * (1.0 / 7.0))));
+        let log10 = exponent * LOG10_2 + ln_mantissa * LOG10_E;
+        *x = if *x == 0.0 { 0.0 } else { log10 };
+    }
+}
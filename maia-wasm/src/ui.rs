@@ -4,48 +4,86 @@
 //! (buttons, input elements, etc.) with the RESTful API of maia-httpd and with
 //! other operations that are performed client-side (such as changing the
 //! waterfall levels or colormap).
+//!
+//! The logic that does not depend on the DOM, such as finding the changes in
+//! the API state and computing the frequencies to tune, is in the [`sync`]
+//! module.
 
 use serde::Deserialize;
 use std::{
     cell::{Cell, Ref, RefCell},
+    collections::HashMap,
     rc::Rc,
 };
 use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 use wasm_bindgen_futures::{future_to_promise, JsFuture};
 use web_sys::{
-    Document, Geolocation, HtmlButtonElement, HtmlDialogElement, HtmlElement, HtmlInputElement,
-    HtmlParagraphElement, HtmlSelectElement, HtmlSpanElement, PositionOptions, Response, Window,
+    Document, Element, Event, File, Geolocation, HtmlAnchorElement, HtmlButtonElement,
+    HtmlDialogElement, HtmlElement, HtmlInputElement, HtmlParagraphElement, HtmlSelectElement,
+    HtmlSpanElement, KeyboardEvent, Notification, NotificationOptions, NotificationPermission,
+    PositionOptions, Request, RequestInit, Response, Window,
 };
 
+use crate::offline;
 use crate::render::RenderEngine;
-use crate::waterfall::Waterfall;
+use crate::waterfall::{self, format_line_timestamp, FrequencyUnit, ReceivedStats, Waterfall};
+use crate::waterfall_interaction::{DragMode, ZoomAnchor};
 
-use input::{CheckboxInput, EnumInput, InputElement, NumberInput, NumberSpan, TextInput};
+use active::IsElementActive;
+use input::{
+    CheckboxInput, EnumInput, FrequencyInput, InputElement, NumberInput, NumberSpan, TextInput,
+};
 
 pub mod active;
 pub mod colormap;
+pub mod extension;
 pub mod input;
+pub mod link;
 #[macro_use]
 mod macros;
-// For the time being preferences is not made public because we lack a good way
-// to allow an external crate to define preferences for a custom UI.
-mod preferences;
+pub mod preferences;
+pub mod raster;
 pub mod request;
-
-const API_URL: &str = "/api";
-const AD9361_URL: &str = "/api/ad9361";
-const DDC_CONFIG_URL: &str = "/api/ddc/config";
-const DDC_DESIGN_URL: &str = "/api/ddc/design";
-const GEOLOCATION_URL: &str = "/api/geolocation";
-const RECORDER_URL: &str = "/api/recorder";
-const RECORDING_METADATA_URL: &str = "/api/recording/metadata";
-const SPECTROMETER_URL: &str = "/api/spectrometer";
-const TIME_URL: &str = "/api/time";
+pub mod sync;
+
+use extension::{UiBuilder, UiExtension};
+
+const API_URL: &str = "/api/v1";
+const AD9361_URL: &str = "/api/v1/ad9361";
+const CLIENTS_URL: &str = "/api/v1/clients";
+const COLORMAPS_URL: &str = "/api/v1/colormaps";
+const DDC_CONFIG_URL: &str = "/api/v1/ddc/config";
+const DDC_DESIGN_URL: &str = "/api/v1/ddc/design";
+const DDC_SNR_URL: &str = "/api/v1/ddc/snr";
+const DEVICE_URL: &str = "/api/v1/device";
+const DEVICE_IDENTIFY_URL: &str = "/api/v1/device/identify";
+const GEOLOCATION_URL: &str = "/api/v1/geolocation";
+const REBOOT_URL: &str = "/api/v1/reboot";
+const RECORDER_URL: &str = "/api/v1/recorder";
+const RECORDING_ANNOTATIONS_URL: &str = "/api/v1/recording/annotations";
+const RECORDING_METADATA_URL: &str = "/api/v1/recording/metadata";
+const RECORDING_REGION_URL: &str = "/api/v1/recording/region";
+const RECORDING_UPLOAD_URL: &str = "/api/v1/recording/upload";
+const SHUTDOWN_URL: &str = "/api/v1/shutdown";
+const SPECTROMETER_URL: &str = "/api/v1/spectrometer";
+const STARTUP_PROFILE_URL: &str = "/api/v1/startup-profile";
+const STARTUP_PROFILE_CURRENT_URL: &str = "/api/v1/startup-profile/current";
+const TIME_URL: &str = "/api/v1/time";
+const UPLOAD_TARGET_URL: &str = "/api/v1/upload-target";
+
+// Interval between the GET requests to the API while the page is visible and
+// while it is hidden (for instance, because it is in a background tab).
+const API_INTERVAL_MS: i32 = 1000;
+const API_HIDDEN_INTERVAL_MS: i32 = 10000;
+// Minimum interval between the requests that change the RX frequency while the
+// waterfall is dragged in tune mode.
+const TUNE_INTERVAL_MS: i32 = 100;
 
 /// User interface.
 ///
 /// This structure is used to create and set up the appropriate callbacks that
-/// implement all the UI interactions.
+/// implement all the UI interactions. Additional functionality can be added to
+/// the user interface by registering [`UiExtension`]s with a [`UiBuilder`].
 #[derive(Clone)]
 pub struct Ui {
     window: Rc<Window>,
@@ -54,10 +92,28 @@ pub struct Ui {
     api_state: Rc<RefCell<Option<maia_json::Api>>>,
     geolocation: Rc<RefCell<Option<Geolocation>>>,
     geolocation_watch_id: Rc<Cell<Option<i32>>>,
+    rx_frequency_tune: Rc<RefCell<RxFrequencyTune>>,
+    rx_agc_mode: Rc<Cell<maia_json::Ad9361GainMode>>,
+    // Time (in ms) and waterfall statistics at the previous API update
+    waterfall_stats: Rc<Cell<Option<(f64, ReceivedStats)>>>,
+    // Whether the last API request succeeded
+    api_connected: Rc<Cell<bool>>,
+    // SNR of the DDC channel (in dB) estimated by the server at the last API
+    // update
+    channel_snr: Rc<Cell<Option<f64>>>,
+    // Waterfall levels used for the live spectrum while an offline
+    // spectrogram is shown
+    live_waterfall_levels: Rc<Cell<Option<(f32, f32)>>>,
+    // Span of the view link given when the page was loaded, which is applied
+    // once the spectrometer sample rate is known
+    view_link_span: Rc<Cell<Option<f64>>>,
+    // Colors of the custom colormaps obtained from maia-httpd, indexed by name
+    custom_colormaps: Rc<RefCell<HashMap<String, Vec<u8>>>>,
     local_settings: Rc<RefCell<LocalSettings>>,
     preferences: Rc<RefCell<preferences::Preferences>>,
     render_engine: Rc<RefCell<RenderEngine>>,
     waterfall: Rc<RefCell<Waterfall>>,
+    extensions: Rc<Vec<Rc<dyn UiExtension>>>,
 }
 
 // Defines the 'struct Elements' and its constructor
@@ -65,14 +121,26 @@ ui_elements! {
     colormap_select: HtmlSelectElement => EnumInput<colormap::Colormap>,
     waterfall_show_waterfall: HtmlInputElement => CheckboxInput,
     waterfall_show_spectrum: HtmlInputElement => CheckboxInput,
+    waterfall_show_color_scale: HtmlInputElement => CheckboxInput,
     waterfall_show_ddc: HtmlInputElement => CheckboxInput,
+    waterfall_ddc_preview: HtmlInputElement => CheckboxInput,
+    waterfall_zoom_anchor: HtmlSelectElement => EnumInput<ZoomAnchor>,
+    waterfall_frequency_unit: HtmlSelectElement => EnumInput<FrequencyUnit>,
+    waterfall_invert_spectrum: HtmlInputElement => CheckboxInput,
+    waterfall_drag_mode: HtmlSelectElement => EnumInput<DragMode>,
     recorder_button: HtmlButtonElement => Rc<HtmlButtonElement>,
+    recorder_overload: HtmlSpanElement => Rc<HtmlSpanElement>,
+    recorder_buffer_duration: HtmlSpanElement => Rc<HtmlSpanElement>,
     recorder_button_replica: HtmlButtonElement => Rc<HtmlButtonElement>,
     settings_button: HtmlButtonElement => Rc<HtmlButtonElement>,
     alert_dialog: HtmlDialogElement => Rc<HtmlDialogElement>,
     alert_message: HtmlParagraphElement => Rc<HtmlParagraphElement>,
     close_alert: HtmlButtonElement => Rc<HtmlButtonElement>,
     settings: HtmlDialogElement => Rc<HtmlDialogElement>,
+    frequency_dialog: HtmlDialogElement => Rc<HtmlDialogElement>,
+    frequency_entry: HtmlInputElement => FrequencyInput<input::MHzPresentation>,
+    frequency_keypad: HtmlElement => Rc<HtmlElement>,
+    frequency_entry_button: HtmlButtonElement => Rc<HtmlButtonElement>,
     close_settings: HtmlButtonElement => Rc<HtmlButtonElement>,
     recording_tab: HtmlButtonElement => Rc<HtmlButtonElement>,
     ddc_tab: HtmlButtonElement => Rc<HtmlButtonElement>,
@@ -87,56 +155,151 @@ ui_elements! {
     waterfall_min: HtmlInputElement => NumberInput<f32>,
     waterfall_max: HtmlInputElement => NumberInput<f32>,
     ad9361_rx_lo_frequency: HtmlInputElement
-        => NumberInput<u64, input::MHzPresentation>,
+        => NumberInput<maia_json::Hertz, input::MHzPresentation>,
     ad9361_sampling_frequency: HtmlInputElement
-        => NumberInput<u32, input::MHzPresentation>,
+        => NumberInput<maia_json::Hertz, input::MHzPresentation>,
     ad9361_rx_rf_bandwidth: HtmlInputElement
-        => NumberInput<u32, input::MHzPresentation>,
+        => NumberInput<maia_json::Hertz, input::MHzPresentation>,
+    ad9361_rx_rf_bandwidth_mode: HtmlSelectElement
+        => EnumInput<maia_json::Ad9361RfBandwidthMode>,
+    ad9361_calibrate_rx_filter: HtmlButtonElement => Rc<HtmlButtonElement>,
     ad9361_rx_gain_mode: HtmlSelectElement => EnumInput<maia_json::Ad9361GainMode>,
-    ad9361_rx_gain: HtmlInputElement => NumberInput<f64>,
-    ddc_frequency: HtmlInputElement => NumberInput<f64, input::KHzPresentation>,
+    ad9361_rx_gain: HtmlInputElement => NumberInput<maia_json::Decibels>,
+    ad9361_rx_gain_value: HtmlSpanElement => Rc<HtmlSpanElement>,
+    ad9361_rx_agc_toggle: HtmlButtonElement => Rc<HtmlButtonElement>,
+    rx_rssi: HtmlSpanElement => Rc<HtmlSpanElement>,
+    rx_rssi_bar: HtmlSpanElement => Rc<HtmlSpanElement>,
+    ddc_frequency: HtmlInputElement => NumberInput<maia_json::Hertz, input::KHzPresentation>,
+    channel_raster: HtmlSelectElement => EnumInput<raster::ChannelRaster>,
     ddc_decimation: HtmlInputElement => NumberInput<u32>,
     ddc_transition_bandwidth: HtmlInputElement => NumberInput<f64>,
     ddc_passband_ripple: HtmlInputElement => NumberInput<f64>,
-    ddc_stopband_attenuation_db: HtmlInputElement => NumberInput<f64>,
+    ddc_stopband_attenuation_db: HtmlInputElement => NumberInput<maia_json::Decibels>,
     ddc_stopband_one_over_f: HtmlInputElement => CheckboxInput,
-    ddc_output_sampling_frequency: HtmlSpanElement => NumberSpan<f64, input::MHzPresentation>,
-    ddc_max_input_sampling_frequency: HtmlSpanElement => NumberSpan<f64, input::MHzPresentation>,
+    ddc_output_sampling_frequency: HtmlSpanElement
+        => NumberSpan<maia_json::Hertz, input::MHzPresentation>,
+    ddc_max_input_sampling_frequency: HtmlSpanElement
+        => NumberSpan<maia_json::Hertz, input::MHzPresentation>,
+    ddc_error_budget: HtmlSpanElement => Rc<HtmlSpanElement>,
     spectrometer_input: HtmlSelectElement => EnumInput<maia_json::SpectrometerInput>,
     spectrometer_output_sampling_frequency: HtmlInputElement
-        => NumberInput<f64, input::IntegerPresentation>,
+        => NumberInput<maia_json::Hertz, input::IntegerPresentation>,
     spectrometer_mode: HtmlSelectElement => EnumInput<maia_json::SpectrometerMode>,
+    spectrometer_retune_mode: HtmlSelectElement => EnumInput<maia_json::SpectrometerRetuneMode>,
+    spectrometer_wall_clock_alignment: HtmlInputElement => CheckboxInput,
+    spectrometer_dc_bin_removal: HtmlInputElement => CheckboxInput,
+    offline_file: HtmlInputElement => Rc<HtmlInputElement>,
+    offline_resume: HtmlButtonElement => Rc<HtmlButtonElement>,
+    waterfall_history: HtmlSelectElement => Rc<HtmlSelectElement>,
+    view_link_copy: HtmlButtonElement => Rc<HtmlButtonElement>,
     recording_metadata_filename: HtmlInputElement => TextInput,
     recorder_prepend_timestamp: HtmlInputElement => CheckboxInput,
     recording_metadata_description: HtmlInputElement => TextInput,
     recording_metadata_author: HtmlInputElement => TextInput,
     recorder_mode: HtmlSelectElement => EnumInput<maia_json::RecorderMode>,
-    recorder_maximum_duration: HtmlInputElement => NumberInput<f64>,
+    recorder_maximum_duration: HtmlInputElement => NumberInput<maia_json::Seconds>,
+    recorder_auto_download: HtmlInputElement => CheckboxInput,
+    recorder_clip_mode: HtmlInputElement => CheckboxInput,
+    recorder_clip_duration: HtmlInputElement => NumberInput<maia_json::Seconds>,
+    download_recording: HtmlElement => Rc<HtmlElement>,
     recording_metadata_geolocation: HtmlSpanElement => Rc<HtmlSpanElement>,
     recording_metadata_geolocation_update: HtmlButtonElement => Rc<HtmlButtonElement>,
     recording_metadata_geolocation_clear: HtmlButtonElement => Rc<HtmlButtonElement>,
+    upload_target_protocol: HtmlSelectElement => EnumInput<maia_json::UploadProtocol>,
+    upload_target_url: HtmlInputElement => TextInput,
+    upload_target_username: HtmlInputElement => TextInput,
+    upload_target_password: HtmlInputElement => Rc<HtmlInputElement>,
+    upload_target_region: HtmlInputElement => TextInput,
+    recording_upload_status: HtmlSpanElement => Rc<HtmlSpanElement>,
+    recording_upload: HtmlButtonElement => Rc<HtmlButtonElement>,
+    recording_annotations_count: HtmlSpanElement => Rc<HtmlSpanElement>,
+    recording_annotations_clear: HtmlButtonElement => Rc<HtmlButtonElement>,
     geolocation_point: HtmlSpanElement => Rc<HtmlSpanElement>,
     geolocation_update: HtmlButtonElement => Rc<HtmlButtonElement>,
     geolocation_watch: HtmlInputElement => CheckboxInput,
     geolocation_clear: HtmlButtonElement => Rc<HtmlButtonElement>,
+    measurement: HtmlElement => Rc<HtmlElement>,
+    measurement_power: HtmlSpanElement => Rc<HtmlSpanElement>,
+    measurement_occupied_bandwidth: HtmlSpanElement => Rc<HtmlSpanElement>,
+    measurement_clear: HtmlButtonElement => Rc<HtmlButtonElement>,
+    channel_snr: HtmlElement => Rc<HtmlElement>,
+    waterfall_stats: HtmlSpanElement => Rc<HtmlSpanElement>,
     maia_wasm_version: HtmlSpanElement => Rc<HtmlSpanElement>,
+    device_name: HtmlInputElement => TextInput,
+    device_frequency_offset: HtmlInputElement
+        => NumberInput<maia_json::Hertz, input::MHzPresentation>,
+    device_identify: HtmlButtonElement => Rc<HtmlButtonElement>,
+    device_reboot: HtmlButtonElement => Rc<HtmlButtonElement>,
+    device_shutdown: HtmlButtonElement => Rc<HtmlButtonElement>,
+    startup_profile_save: HtmlButtonElement => Rc<HtmlButtonElement>,
+    startup_profile_reset: HtmlButtonElement => Rc<HtmlButtonElement>,
+    clients_refresh: HtmlButtonElement => Rc<HtmlButtonElement>,
+    clients_list: HtmlElement => Rc<HtmlElement>,
+    device_label: HtmlSpanElement => Rc<HtmlSpanElement>,
+    scanner_status: HtmlSpanElement => Rc<HtmlSpanElement>,
+    notifications: HtmlInputElement => CheckboxInput,
 }
 
 #[derive(Default)]
 struct LocalSettings {
     waterfall_show_ddc: bool,
+    waterfall_ddc_preview: bool,
+    waterfall_zoom_anchor: ZoomAnchor,
+    waterfall_drag_mode: DragMode,
+    recorder_auto_download: bool,
+    notifications: bool,
+    channel_raster: raster::ChannelRaster,
+}
+
+// State used to rate limit the changes of the RX frequency in tune mode.
+#[derive(Default)]
+struct RxFrequencyTune {
+    // Frequency waiting to be set when the timeout expires.
+    pending: Option<u64>,
+    // Timeout that is running since the last frequency change, if any.
+    timeout: Option<i32>,
 }
 
 impl Ui {
     /// Creates a new user interface.
+    ///
+    /// The user interface is created without any extensions. Use
+    /// [`Ui::builder`] to create a user interface with extensions.
     pub fn new(
         window: Rc<Window>,
         document: Rc<Document>,
         render_engine: Rc<RefCell<RenderEngine>>,
         waterfall: Rc<RefCell<Waterfall>>,
+    ) -> Result<Ui, JsValue> {
+        Self::builder(window, document, render_engine, waterfall).build()
+    }
+
+    /// Returns a builder for the user interface.
+    ///
+    /// The builder can be used to register [`UiExtension`]s.
+    pub fn builder(
+        window: Rc<Window>,
+        document: Rc<Document>,
+        render_engine: Rc<RefCell<RenderEngine>>,
+        waterfall: Rc<RefCell<Waterfall>>,
+    ) -> UiBuilder {
+        UiBuilder::new(window, document, render_engine, waterfall)
+    }
+
+    fn with_extensions(
+        window: Rc<Window>,
+        document: Rc<Document>,
+        render_engine: Rc<RefCell<RenderEngine>>,
+        waterfall: Rc<RefCell<Waterfall>>,
+        extensions: Vec<Rc<dyn UiExtension>>,
     ) -> Result<Ui, JsValue> {
         let elements = Elements::new(&document)?;
         let preferences = Rc::new(RefCell::new(preferences::Preferences::new(&window)?));
+        // The settings of the view link override the preferences
+        let view_link = link::ViewLink::parse(&window.location().hash()?);
+        if !view_link.is_empty() {
+            preferences.borrow_mut().update_view_link(&view_link)?;
+        }
         let ui = Ui {
             window,
             document,
@@ -144,10 +307,19 @@ impl Ui {
             api_state: Rc::new(RefCell::new(None)),
             geolocation: Rc::new(RefCell::new(None)),
             geolocation_watch_id: Rc::new(Cell::new(None)),
+            rx_frequency_tune: Rc::new(RefCell::new(RxFrequencyTune::default())),
+            rx_agc_mode: Rc::new(Cell::new(maia_json::Ad9361GainMode::SlowAttack)),
+            waterfall_stats: Rc::new(Cell::new(None)),
+            api_connected: Rc::new(Cell::new(true)),
+            channel_snr: Rc::new(Cell::new(None)),
+            live_waterfall_levels: Rc::new(Cell::new(None)),
+            view_link_span: Rc::new(Cell::new(view_link.span)),
+            custom_colormaps: Rc::new(RefCell::new(HashMap::new())),
             local_settings: Rc::new(RefCell::new(LocalSettings::default())),
             preferences,
             render_engine,
             waterfall,
+            extensions: Rc::new(extensions),
         };
         ui.elements
             .maia_wasm_version
@@ -156,14 +328,81 @@ impl Ui {
                 crate::version::maia_wasm_version(),
                 crate::version::maia_wasm_git_version()
             )));
+        ui.set_field_metadata();
         ui.set_callbacks()?;
+        // The custom colormap in the preferences needs an option in the
+        // colormap select before the preferences are applied. Its colors are
+        // loaded once the custom colormaps are obtained from maia-httpd.
+        if let colormap::Colormap::Custom(name) = ui.preferences.borrow().colormap_select() {
+            ui.add_custom_colormap_option(name)?;
+        }
         ui.preferences.borrow().apply(&ui)?;
         ui.set_callbacks_post_apply()?;
+        ui.setup_extensions()?;
+        let _ = future_to_promise({
+            let ui = ui.clone();
+            async move {
+                ui.update_custom_colormaps().await?;
+                Ok(JsValue::NULL)
+            }
+        });
         Ok(ui)
     }
 
+    /// Returns the [`Window`] used by the user interface.
+    pub fn window(&self) -> &Rc<Window> {
+        &self.window
+    }
+
+    /// Returns the [`Document`] used by the user interface.
+    pub fn document(&self) -> &Rc<Document> {
+        &self.document
+    }
+
+    /// Returns the [`RenderEngine`] used by the user interface.
+    pub fn render_engine(&self) -> &Rc<RefCell<RenderEngine>> {
+        &self.render_engine
+    }
+
+    /// Returns the [`Waterfall`] controlled by the user interface.
+    pub fn waterfall(&self) -> &Rc<RefCell<Waterfall>> {
+        &self.waterfall
+    }
+
+    /// Returns the preferences of the user interface.
+    pub fn preferences(&self) -> &Rc<RefCell<preferences::Preferences>> {
+        &self.preferences
+    }
+
+    /// Returns the last state of the maia-httpd API obtained by the user
+    /// interface.
+    ///
+    /// This returns `None` if the state of the API has not been obtained yet.
+    pub fn api_state(&self) -> Option<maia_json::Api> {
+        self.api_state.borrow().clone()
+    }
+
+    // Sets the limits of the input elements from the metadata of the fields of
+    // the maia-json schemas.
+    fn set_field_metadata(&self) {
+        use maia_json::{PatchAd9361, PatchDevice};
+        self.elements
+            .ad9361_rx_lo_frequency
+            .set_field_metadata(&PatchAd9361::RX_LO_FREQUENCY_METADATA);
+        self.elements
+            .ad9361_sampling_frequency
+            .set_field_metadata(&PatchAd9361::SAMPLING_FREQUENCY_METADATA);
+        self.elements
+            .ad9361_rx_rf_bandwidth
+            .set_field_metadata(&PatchAd9361::RX_RF_BANDWIDTH_METADATA);
+        self.elements
+            .device_frequency_offset
+            .set_field_metadata(&PatchDevice::FREQUENCY_OFFSET_METADATA);
+    }
+
     fn set_callbacks(&self) -> Result<(), JsValue> {
-        self.set_api_get_periodic(1000)?;
+        self.set_api_get_periodic(API_INTERVAL_MS, API_HIDDEN_INTERVAL_MS)?;
+        self.set_measurement_periodic(250)?;
 
         set_on!(
             change,
@@ -171,24 +410,47 @@ impl Ui {
             colormap_select,
             waterfall_show_waterfall,
             waterfall_show_spectrum,
+            waterfall_show_color_scale,
             waterfall_show_ddc,
+            waterfall_ddc_preview,
+            waterfall_zoom_anchor,
+            waterfall_frequency_unit,
+            waterfall_invert_spectrum,
+            waterfall_drag_mode,
             waterfall_min,
             waterfall_max,
             ad9361_rx_lo_frequency,
             ad9361_sampling_frequency,
             ad9361_rx_rf_bandwidth,
+            ad9361_rx_rf_bandwidth_mode,
             ad9361_rx_gain_mode,
             ddc_frequency,
+            channel_raster,
             spectrometer_input,
             spectrometer_output_sampling_frequency,
             spectrometer_mode,
+            spectrometer_retune_mode,
+            spectrometer_wall_clock_alignment,
+            spectrometer_dc_bin_removal,
             recording_metadata_filename,
             recorder_prepend_timestamp,
             recording_metadata_description,
             recording_metadata_author,
             recorder_mode,
             recorder_maximum_duration,
-            geolocation_watch
+            recorder_auto_download,
+            recorder_clip_mode,
+            recorder_clip_duration,
+            upload_target_protocol,
+            upload_target_url,
+            upload_target_username,
+            upload_target_region,
+            geolocation_watch,
+            device_name,
+            device_frequency_offset,
+            offline_file,
+            waterfall_history,
+            notifications
         );
 
         // This uses a custom onchange function that calls the macro-generated one.
@@ -197,6 +459,15 @@ impl Ui {
                 .into_js_value()
                 .unchecked_ref(),
         ));
+        // The password is not part of the upload target section, since it is
+        // never returned by the server.
+        self.elements.upload_target_password.set_onchange(Some(
+            self.upload_target_password_onchange()
+                .into_js_value()
+                .unchecked_ref(),
+        ));
+        set_on!(input, self, ad9361_rx_gain, frequency_entry);
+        set_on!(close, self, frequency_dialog);
 
         set_on!(
             click,
@@ -207,8 +478,23 @@ impl Ui {
             close_settings,
             recording_metadata_geolocation_update,
             recording_metadata_geolocation_clear,
+            recording_upload,
+            recording_annotations_clear,
             geolocation_update,
             geolocation_clear,
+            device_identify,
+            device_reboot,
+            device_shutdown,
+            startup_profile_save,
+            startup_profile_reset,
+            clients_refresh,
+            ad9361_rx_agc_toggle,
+            ad9361_calibrate_rx_filter,
+            frequency_entry_button,
+            frequency_keypad,
+            measurement_clear,
+            offline_resume,
+            view_link_copy,
             recording_tab,
             ddc_tab,
             waterfall_tab,
@@ -218,6 +504,19 @@ impl Ui {
         self.elements
             .recorder_button_replica
             .set_onclick(self.elements.recorder_button.onclick().as_ref());
+        // The custom colormaps are obtained again each time that the waterfall
+        // tab is opened, since they can be changed by other clients.
+        self.elements
+            .waterfall_tab
+            .add_event_listener_with_callback(
+                "click",
+                self.update_custom_colormaps_closure()
+                    .into_js_value()
+                    .unchecked_ref(),
+            )?;
+        self.document.set_onkeydown(Some(
+            self.document_onkeydown().into_js_value().unchecked_ref(),
+        ));
 
         Ok(())
     }
@@ -252,7 +551,9 @@ impl Ui {
 
 // Alert
 impl Ui {
-    fn alert(&self, message: &str) -> Result<(), JsValue> {
+    /// Shows an alert dialog with a message.
+    pub fn alert(&self, message: &str) -> Result<(), JsValue> {
+        self.notify("Maia SDR alert", message);
         self.elements.alert_message.set_text_content(Some(message));
         self.elements.alert_dialog.show_modal()?;
         Ok(())
@@ -264,6 +565,208 @@ impl Ui {
     }
 }
 
+// Browser notifications
+impl Ui {
+    /// Shows a browser notification.
+    ///
+    /// The notification is only shown if notifications have been enabled in
+    /// the settings, the user has granted permission to show them, and the
+    /// page is hidden (for instance because it is in a background tab). When
+    /// the page is visible, the events that cause notifications are already
+    /// displayed in the UI.
+    pub fn notify(&self, title: &str, body: &str) {
+        if !self.local_settings.borrow().notifications
+            || !self.notifications_supported()
+            || Notification::permission() != NotificationPermission::Granted
+            || !self.document.hidden()
+        {
+            return;
+        }
+        let options = NotificationOptions::new();
+        options.set_body(body);
+        // Notifications with the same title replace each other
+        options.set_tag(title);
+        if let Err(err) = Notification::new_with_options(title, &options) {
+            web_sys::console::error_2(&"could not show notification".into(), &err);
+        }
+    }
+
+    fn notifications_supported(&self) -> bool {
+        // The Notification API is only available in secure contexts
+        js_sys::Reflect::has(&self.window, &"Notification".into()).unwrap_or(false)
+    }
+
+    fn notifications_onchange(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let enabled = ui.elements.notifications.get().unwrap();
+            ui.local_settings.borrow_mut().notifications = enabled;
+            // try_borrow_mut fails if this closure is called by
+            // preferences.apply at initialization. In that case the
+            // permission is not requested, since browsers only allow
+            // requesting it as a consequence of a user action.
+            if let Ok(mut prefs) = ui.preferences.try_borrow_mut() {
+                if let Err(e) = prefs.update_notifications(&enabled) {
+                    web_sys::console::error_1(&e);
+                }
+                drop(prefs);
+                if enabled {
+                    let _ = future_to_promise(ui.clone().request_notification_permission());
+                }
+            }
+        })
+    }
+
+    async fn request_notification_permission(self) -> Result<JsValue, JsValue> {
+        if !self.notifications_supported() {
+            self.disable_notifications(
+                "Notifications are not supported by this browser. \
+                 They are only available when the page is loaded using HTTPS.",
+            )?;
+            return Ok(JsValue::NULL);
+        }
+        let permission = match Notification::permission() {
+            NotificationPermission::Default => {
+                JsFuture::from(Notification::request_permission()?).await?
+            }
+            permission => permission.into(),
+        };
+        if NotificationPermission::from_js_value(&permission)
+            != Some(NotificationPermission::Granted)
+        {
+            self.disable_notifications(
+                "Permission to show notifications has been denied. \
+                 It can be granted in the site settings of the browser.",
+            )?;
+        }
+        Ok(JsValue::NULL)
+    }
+
+    fn disable_notifications(&self, message: &str) -> Result<(), JsValue> {
+        self.elements.notifications.set(&false);
+        self.local_settings.borrow_mut().notifications = false;
+        self.preferences.borrow_mut().update_notifications(&false)?;
+        self.alert(message)
+    }
+}
+
+// Frequency entry
+impl Ui {
+    fn open_frequency_dialog(&self) -> Result<(), JsValue> {
+        let entry = &self.elements.frequency_entry;
+        entry.set_value("");
+        entry.set_custom_validity("");
+        self.elements.frequency_dialog.set_return_value("");
+        self.elements.frequency_dialog.show_modal()?;
+        entry.focus()
+    }
+
+    fn frequency_entry_button_onclick(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || ui.open_frequency_dialog().unwrap())
+    }
+
+    // Opens the frequency entry dialog when the F key is pressed.
+    fn document_onkeydown(&self) -> Closure<dyn Fn(KeyboardEvent)> {
+        let ui = self.clone();
+        Closure::new(move |event: KeyboardEvent| {
+            if !event.key().eq_ignore_ascii_case("f")
+                || event.ctrl_key()
+                || event.meta_key()
+                || event.alt_key()
+            {
+                return;
+            }
+            // Do not steal key presses from form fields or open dialogs
+            let typing = ui.document.active_element().is_some_and(|element| {
+                matches!(element.tag_name().as_str(), "INPUT" | "SELECT" | "TEXTAREA")
+            });
+            let dialog_open = [
+                &ui.elements.alert_dialog,
+                &ui.elements.settings,
+                &ui.elements.frequency_dialog,
+            ]
+            .iter()
+            .any(|dialog| dialog.open());
+            if typing || dialog_open {
+                return;
+            }
+            // Prevent the key from being typed into the frequency entry
+            event.prevent_default();
+            ui.open_frequency_dialog().unwrap();
+        })
+    }
+
+    fn frequency_entry_oninput(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || ui.validate_frequency_entry())
+    }
+
+    // Marks the frequency entry as invalid if it cannot be parsed. This
+    // prevents the dialog form from being submitted.
+    fn validate_frequency_entry(&self) {
+        let entry = &self.elements.frequency_entry;
+        entry.set_custom_validity(if entry.get().is_some() {
+            ""
+        } else {
+            "Invalid frequency"
+        });
+    }
+
+    fn frequency_keypad_onclick(&self) -> Closure<dyn Fn(Event)> {
+        let ui = self.clone();
+        Closure::new(move |event: Event| {
+            let Some(key) = event
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+                .and_then(|element| element.get_attribute("data-key"))
+            else {
+                return;
+            };
+            let entry = &ui.elements.frequency_entry;
+            let mut value = entry.value();
+            match key.as_str() {
+                "Clear" => value.clear(),
+                "Backspace" => {
+                    value.pop();
+                }
+                _ => value.push_str(&key),
+            }
+            entry.set_value(&value);
+            ui.validate_frequency_entry();
+        })
+    }
+
+    fn frequency_dialog_onclose(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || {
+            if ui.elements.frequency_dialog.return_value() != "ok" {
+                return;
+            }
+            let Some(entry) = ui.elements.frequency_entry.get() else {
+                return;
+            };
+            // The entered frequency refers to the input of the external
+            // converter, as the frequencies shown in the waterfall.
+            let current = {
+                let state = ui.api_state.borrow();
+                let Some(state) = state.as_ref() else {
+                    return;
+                };
+                state.ad9361.rx_lo_frequency.0 + ui.waterfall_ddc_tuning()
+            };
+            let freq = {
+                let waterfall = ui.waterfall.borrow();
+                let current = waterfall.display_frequency(current);
+                clamp_rx_frequency(waterfall.device_frequency(entry.resolve(current)))
+            };
+            if let Err(e) = ui.set_rx_frequency(freq.round() as u64) {
+                web_sys::console::error_1(&e);
+            }
+        })
+    }
+}
+
 // Settings
 impl Ui {
     fn settings_button_onclick(&self) -> Closure<dyn Fn()> {
@@ -285,9 +788,117 @@ impl Ui {
     impl_tabs!(recording, ddc, waterfall, geolocation, other);
 }
 
+// Extensions
+impl Ui {
+    fn setup_extensions(&self) -> Result<(), JsValue> {
+        let mut panels = Vec::with_capacity(self.extensions.len());
+        let mut tabs = Vec::new();
+        for extension in self.extensions.iter() {
+            let panel = match extension.panel_title() {
+                Some(title) => {
+                    let (tab, panel) = self.create_extension_tab(extension.name(), title)?;
+                    tabs.push((tab, Rc::clone(&panel)));
+                    Some(panel)
+                }
+                None => None,
+            };
+            panels.push(panel);
+        }
+        if !tabs.is_empty() {
+            self.set_extension_tabs_callbacks(Rc::new(tabs))?;
+        }
+        for (extension, panel) in self.extensions.iter().zip(panels) {
+            extension.setup(self, panel.as_deref())?;
+        }
+        Ok(())
+    }
+
+    fn create_extension_tab(
+        &self,
+        name: &str,
+        title: &str,
+    ) -> Result<(Rc<HtmlButtonElement>, Rc<HtmlElement>), JsValue> {
+        let tab = self
+            .document
+            .create_element("button")?
+            .dyn_into::<HtmlButtonElement>()?;
+        tab.set_id(&format!("{name}_tab"));
+        tab.set_attribute("role", "tab")?;
+        tab.set_attribute("aria-selected", "false")?;
+        tab.set_attribute("aria-controls", &format!("{name}_panel"))?;
+        tab.set_text_content(Some(title));
+        // The close button is the last element of the tab list
+        self.elements.close_settings.before_with_node_1(&tab)?;
+        let panel = self
+            .document
+            .create_element("div")?
+            .dyn_into::<HtmlElement>()?;
+        panel.set_id(&format!("{name}_panel"));
+        panel.set_class_name("hidden");
+        panel.set_attribute("role", "tabpanel")?;
+        panel.set_attribute("aria-labelledby", &format!("{name}_tab"))?;
+        self.elements.settings.append_child(&panel)?;
+        Ok((Rc::new(tab), Rc::new(panel)))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn set_extension_tabs_callbacks(
+        &self,
+        tabs: Rc<Vec<(Rc<HtmlButtonElement>, Rc<HtmlElement>)>>,
+    ) -> Result<(), JsValue> {
+        let hide_extension_panels = {
+            let tabs = Rc::clone(&tabs);
+            move || -> Result<(), JsValue> {
+                for (tab, panel) in tabs.iter() {
+                    panel.class_list().add_1("hidden")?;
+                    tab.set_attribute("aria-selected", "false")?;
+                }
+                Ok(())
+            }
+        };
+        // The onclick of the tabs of the Ui only knows about its own panels,
+        // so an additional listener is used to hide the extension panels.
+        let onclick = Closure::<dyn Fn()>::new({
+            let hide_extension_panels = hide_extension_panels.clone();
+            move || hide_extension_panels().unwrap()
+        })
+        .into_js_value();
+        for tab in [
+            &self.elements.recording_tab,
+            &self.elements.ddc_tab,
+            &self.elements.waterfall_tab,
+            &self.elements.geolocation_tab,
+            &self.elements.other_tab,
+        ] {
+            tab.add_event_listener_with_callback("click", onclick.unchecked_ref())?;
+        }
+        for (tab, panel) in tabs.iter() {
+            let ui = self.clone();
+            let hide_extension_panels = hide_extension_panels.clone();
+            let this_tab = Rc::clone(tab);
+            let panel = Rc::clone(panel);
+            let onclick = Closure::<dyn Fn()>::new(move || {
+                ui.hide_all_tab_panels().unwrap();
+                hide_extension_panels().unwrap();
+                panel.class_list().remove_1("hidden").unwrap();
+                this_tab.set_attribute("aria-selected", "true").unwrap();
+            });
+            tab.set_onclick(Some(onclick.into_js_value().unchecked_ref()));
+        }
+        Ok(())
+    }
+}
+
 // API methods
 impl Ui {
-    fn set_api_get_periodic(&self, interval_ms: i32) -> Result<(), JsValue> {
+    // The API is polled every interval_ms while the page is visible and every
+    // hidden_interval_ms while it is hidden, to reduce the load on the device
+    // caused by background tabs.
+    fn set_api_get_periodic(
+        &self,
+        interval_ms: i32,
+        hidden_interval_ms: i32,
+    ) -> Result<(), JsValue> {
         let ui = self.clone();
         let handler = Closure::<dyn Fn() -> js_sys::Promise>::new(move || {
             let ui = ui.clone();
@@ -296,27 +907,85 @@ impl Ui {
                 Ok(JsValue::NULL)
             })
         });
-        let handler_ = handler.into_js_value();
-        let handler: &js_sys::Function = handler_.unchecked_ref();
-        // call handler immediately
-        handler.call0(&JsValue::NULL)?;
-        // call handler every interval_ms
-        self.window
-            .set_interval_with_callback_and_timeout_and_arguments_0(handler, interval_ms)?;
+        let handler: js_sys::Function = handler.into_js_value().unchecked_into();
+        let window = Rc::clone(&self.window);
+        let document = Rc::clone(&self.document);
+        let interval_id = Cell::new(None);
+        let schedule = move || -> Result<(), JsValue> {
+            if let Some(id) = interval_id.take() {
+                window.clear_interval_with_handle(id);
+            }
+            // call handler immediately, so that the UI is up to date when the
+            // page becomes visible
+            handler.call0(&JsValue::NULL)?;
+            let interval_ms = if document.hidden() {
+                hidden_interval_ms
+            } else {
+                interval_ms
+            };
+            interval_id.set(Some(
+                window.set_interval_with_callback_and_timeout_and_arguments_0(
+                    &handler,
+                    interval_ms,
+                )?,
+            ));
+            Ok(())
+        };
+        schedule()?;
+        let onvisibilitychange = Closure::<dyn Fn()>::new(move || {
+            if let Err(e) = schedule() {
+                web_sys::console::error_1(&e);
+            }
+        });
+        self.document.add_event_listener_with_callback(
+            "visibilitychange",
+            onvisibilitychange.into_js_value().unchecked_ref(),
+        )?;
         Ok(())
     }
 
     async fn get_api_update_elements(&self) -> Result<(), JsValue> {
-        let json = self.get_api().await?;
-        self.api_state.replace(Some(json.clone()));
+        let json = match self.get_api().await {
+            Ok(json) => json,
+            Err(err) => {
+                if self.api_connected.replace(false) {
+                    self.notify(
+                        "Maia SDR disconnected",
+                        "The connection to the device has been lost",
+                    );
+                }
+                return Err(err);
+            }
+        };
+        self.api_connected.set(true);
+        let previous = self.api_state.replace(Some(json.clone()));
+        let changes = sync::ApiChanges::new(previous.as_ref(), &json);
+        self.recorder_state_transition(&changes, &json.recorder);
+        self.update_api_errors(&json.errors)?;
         self.update_ad9361_inactive_elements(&json.ad9361)?;
+        self.update_sensors(&json.sensors)?;
+        self.update_scanner(&json.scanner)?;
         self.update_ddc_inactive_elements(&json.ddc)?;
         self.update_spectrometer_inactive_elements(&json.spectrometer)?;
         self.update_waterfall_rate(&json.spectrometer);
+        self.update_waterfall_stats();
+        self.update_view_link(&json)?;
         self.update_recorder_button(&json.recorder);
+        self.update_recorder_overload(&json.recorder)?;
+        self.update_recorder_buffer_duration(&json.recorder);
         self.update_recording_metadata_inactive_elements(&json.recording_metadata)?;
         self.update_recorder_inactive_elements(&json.recorder)?;
+        self.update_upload_target_inactive_elements(&json.upload_target)?;
+        self.update_recording_upload(&json.upload);
+        if changes.recording_annotations {
+            self.update_recording_annotations(&json.recording_annotations);
+        }
         self.update_geolocation_elements(&json.geolocation)?;
+        self.update_device_inactive_elements(&json.device)?;
+        for extension in self.extensions.iter() {
+            extension.api_update(self, &json)?;
+        }
+        self.channel_snr.set(self.get_channel_snr().await);
 
         // This potentially takes some time to complete, since it might have to
         // do a fetch call to PATCH the server time. We do this last.
@@ -325,12 +994,69 @@ impl Ui {
         Ok(())
     }
 
+    // Obtains the SNR of the DDC channel from the server. The SNR is only
+    // requested while the DDC channel is shown in the waterfall. If the server
+    // cannot estimate it, None is returned.
+    async fn get_channel_snr(&self) -> Option<f64> {
+        if !self.waterfall.borrow().is_channel_visible() {
+            return None;
+        }
+        let response = JsFuture::from(self.window.fetch_with_str(DDC_SNR_URL))
+            .await
+            .ok()?
+            .dyn_into::<Response>()
+            .ok()?;
+        if !response.ok() {
+            return None;
+        }
+        let snr: maia_json::DDCSnr = request::response_to_json(&response).await.ok()?;
+        snr.snr_db.map(f64::from)
+    }
+
     async fn get_api(&self) -> Result<maia_json::Api, JsValue> {
         let response = JsFuture::from(self.window.fetch_with_str(API_URL))
             .await?
             .dyn_into::<Response>()?;
         request::response_to_json(&response).await
     }
+
+    // Marks the elements corresponding to the sections of the API that the
+    // server could not read. The values shown in these elements might be
+    // outdated.
+    fn update_api_errors(&self, errors: &[maia_json::ApiSectionError]) -> Result<(), JsValue> {
+        use maia_json::ApiSection;
+        for section in [
+            ApiSection::Ad9361,
+            ApiSection::DDC,
+            ApiSection::Recorder,
+            ApiSection::Sensors,
+            ApiSection::Spectrometer,
+            ApiSection::Time,
+        ] {
+            let ids: &[&str] = match section {
+                ApiSection::Ad9361 => &[
+                    "ad9361_rx_lo_frequency",
+                    "ad9361_sampling_frequency",
+                    "ad9361_rx_rf_bandwidth",
+                    "ad9361_rx_gain",
+                ],
+                ApiSection::DDC => &["ddc_tab", "ddc_panel"],
+                ApiSection::Recorder => &["recorder_button", "recording_tab", "recording_panel"],
+                ApiSection::Sensors => &["rx_rssi"],
+                ApiSection::Spectrometer => &["waterfall_tab", "waterfall_panel"],
+                ApiSection::Time => &[],
+            };
+            let failed = errors.iter().any(|error| error.section == section);
+            for id in ids {
+                if let Some(element) = self.document.get_element_by_id(id) {
+                    element
+                        .class_list()
+                        .toggle_with_force("api_error", failed)?;
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 // AD9361 methods
@@ -341,31 +1067,24 @@ impl Ui {
     /// is the input of the waterfall and the frequency can still be changed, or
     /// by changing the AD9361 frequency otherwise.
     pub fn set_rx_frequency(&self, freq: u64) -> Result<(), JsValue> {
-        let mut ad9361_freq = Some(freq);
         let state = self.api_state.borrow();
         let Some(state) = state.as_ref() else {
             return Err("set_rx_frequency: api_state not available yet".into());
         };
-        if matches!(state.spectrometer.input, maia_json::SpectrometerInput::DDC) {
-            // Change the DDC frequency if possible
-            let samp_rate = state.ad9361.sampling_frequency as f64;
-            let mut ddc_freq = freq as f64 - state.ad9361.rx_lo_frequency as f64;
-            // Assume that 15% of the edges of the AD9361 spectrum is not usable
-            // due to aliasing.
-            const MARGIN: f64 = 0.5 * (1.0 - 0.15);
-            let ddc_samp_rate = state.ddc.output_sampling_frequency;
-            let limit = samp_rate * MARGIN - 0.5 * ddc_samp_rate;
-            if ddc_freq.abs() > limit {
-                ddc_freq = if ddc_freq < 0.0 { limit } else { -limit }.round();
-                ad9361_freq = Some(u64::try_from(freq as i64 - ddc_freq as i64).unwrap());
-            } else {
-                ad9361_freq = None;
-            }
-            self.set_ddc_frequency(ddc_freq)?;
+        let freq =
+            clamp_rx_frequency(self.snap_to_channel_raster(state, freq as f64)).round() as u64;
+        let change = sync::RxFrequencyChange::new(&sync::Tuning::from_api(state), freq);
+        if let Some(ddc_freq) = change.ddc_frequency {
+            // The frequency has already been snapped to the raster, and the
+            // AD9361 frequency might be about to change, so the DDC frequency
+            // is not snapped again.
+            self.apply_ddc_frequency(ddc_freq)?;
         }
-        if let Some(freq) = ad9361_freq {
+        if let Some(freq) = change.ad9361_frequency {
             // Change the AD9361 frequency
-            self.elements.ad9361_rx_lo_frequency.set(&freq);
+            self.elements
+                .ad9361_rx_lo_frequency
+                .set(&maia_json::Hertz(freq as f64));
             self.elements
                 .ad9361_rx_lo_frequency
                 .onchange()
@@ -375,6 +1094,53 @@ impl Ui {
         Ok(())
     }
 
+    /// Sets the value of the RX frequency while it is being tuned continuously.
+    ///
+    /// This function is intended to be called many times in a short period,
+    /// such as when the waterfall is dragged in tune mode. The frequency
+    /// changes are rate limited: the first change is applied immediately, and
+    /// further changes are applied at most once every 100 ms, using the most
+    /// recent frequency. The frequency is clamped to the AD9361 tuning range
+    /// and the clamped value is returned.
+    pub fn tune_rx_frequency(&self, freq: f64) -> Result<f64, JsValue> {
+        let freq = clamp_rx_frequency(freq);
+        let mut tune = self.rx_frequency_tune.borrow_mut();
+        if tune.timeout.is_some() {
+            tune.pending = Some(freq as u64);
+        } else {
+            tune.timeout = Some(self.set_tune_timeout()?);
+            drop(tune);
+            self.set_rx_frequency(freq as u64)?;
+        }
+        Ok(freq)
+    }
+
+    fn set_tune_timeout(&self) -> Result<i32, JsValue> {
+        let ui = self.clone();
+        let handler = Closure::once_into_js(move || {
+            let pending = {
+                let mut tune = ui.rx_frequency_tune.borrow_mut();
+                tune.timeout = None;
+                tune.pending.take()
+            };
+            if let Some(freq) = pending {
+                // Keep rate limiting while changes keep coming
+                let result = ui.set_tune_timeout().and_then(|timeout| {
+                    ui.rx_frequency_tune.borrow_mut().timeout = Some(timeout);
+                    ui.set_rx_frequency(freq)
+                });
+                if let Err(e) = result {
+                    web_sys::console::error_1(&e);
+                }
+            }
+        });
+        self.window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(
+                handler.unchecked_ref(),
+                TUNE_INTERVAL_MS,
+            )
+    }
+
     impl_section_custom!(
         ad9361,
         maia_json::Ad9361,
@@ -383,13 +1149,19 @@ impl Ui {
         rx_lo_frequency,
         sampling_frequency,
         rx_rf_bandwidth,
+        rx_rf_bandwidth_mode,
         rx_gain,
         rx_gain_mode
     );
     impl_onchange_patch_modify_noop!(ad9361, maia_json::PatchAd9361);
 
     fn post_update_ad9361_elements(&self, json: &maia_json::Ad9361) -> Result<(), JsValue> {
-        self.update_rx_gain_disabled_status(json);
+        self.update_rx_gain_disabled_status(json)?;
+        // In automatic mode the RX bandwidth follows the sampling frequency
+        self.elements.ad9361_rx_rf_bandwidth.set_disabled(matches!(
+            json.rx_rf_bandwidth_mode,
+            maia_json::Ad9361RfBandwidthMode::Auto
+        ));
         self.update_waterfall_ad9361(json)
     }
 
@@ -403,7 +1175,7 @@ impl Ui {
         Ok(())
     }
 
-    fn update_rx_gain_disabled_status(&self, json: &maia_json::Ad9361) {
+    fn update_rx_gain_disabled_status(&self, json: &maia_json::Ad9361) -> Result<(), JsValue> {
         let disabled = match json.rx_gain_mode {
             maia_json::Ad9361GainMode::Manual => false,
             maia_json::Ad9361GainMode::FastAttack => true,
@@ -411,6 +1183,66 @@ impl Ui {
             maia_json::Ad9361GainMode::Hybrid => true,
         };
         self.elements.ad9361_rx_gain.set_disabled(disabled);
+        if disabled {
+            // Remember the AGC mode so that the toggle button can restore it
+            self.rx_agc_mode.set(json.rx_gain_mode);
+        }
+        self.elements
+            .ad9361_rx_agc_toggle
+            .set_attribute("aria-pressed", if disabled { "true" } else { "false" })
+    }
+
+    // Shows the value of the RX gain slider while it is being moved.
+    fn ad9361_rx_gain_oninput(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || {
+            if let Some(gain) = ui.elements.ad9361_rx_gain.get() {
+                ui.set_rx_gain_value(gain.0);
+            }
+        })
+    }
+
+    fn set_rx_gain_value(&self, gain: f64) {
+        self.elements
+            .ad9361_rx_gain_value
+            .set_text_content(Some(&format!("{gain:.0} dB")));
+    }
+
+    // Toggles between manual gain and the last AGC mode that was used.
+    fn ad9361_rx_agc_toggle_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let mode = {
+                let state = ui.api_state.borrow();
+                let Some(state) = state.as_ref() else {
+                    return JsValue::NULL;
+                };
+                sync::agc_toggle_mode(state.ad9361.rx_gain_mode, ui.rx_agc_mode.get())
+            };
+            ui.elements.ad9361_rx_gain_mode.set(&mode);
+            ui.elements
+                .ad9361_rx_gain_mode
+                .onchange()
+                .unwrap()
+                .call0(&JsValue::NULL)
+                .unwrap()
+        })
+    }
+
+    fn ad9361_calibrate_rx_filter_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                let patch = maia_json::PatchAd9361 {
+                    calibrate_rx_filter: Some(true),
+                    ..Default::default()
+                };
+                ui.patch_ad9361_update_elements(&patch).await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
     }
 
     // Custom onchange function for the RX gain. This avoids trying to change
@@ -458,7 +1290,7 @@ impl Ui {
     impl_put!(
         ddc,
         maia_json::PutDDCDesign,
-        maia_json::DDCConfig,
+        maia_json::DDCDesign,
         DDC_DESIGN_URL
     );
 
@@ -523,7 +1355,12 @@ impl Ui {
             };
             let ui = ui.clone();
             future_to_promise(async move {
-                request::ignore_request_failed(ui.put_ddc(&put).await)?;
+                if let Some(design) = request::ignore_request_failed(ui.put_ddc(&put).await)? {
+                    ui.update_ddc_error_budget(&design.error_budget);
+                    ui.waterfall.borrow_mut().set_channel_transition_bandwidth(
+                        design.config.transition_bandwidth.unwrap_or(0.0),
+                    );
+                }
                 ui.update_spectrometer_settings()?;
                 Ok(JsValue::NULL)
             })
@@ -531,6 +1368,29 @@ impl Ui {
         })
     }
 
+    // Shows the errors of the complete DDC, and the errors of each FIR filter
+    // in the tooltip.
+    fn update_ddc_error_budget(&self, budget: &maia_json::DDCErrorBudget) {
+        let format = |errors: &maia_json::DDCFilterErrors| {
+            format!(
+                "ripple {:.4}, attenuation {:.1} dB",
+                errors.passband_ripple, errors.stopband_attenuation_db
+            )
+        };
+        let title = [
+            ("FIR1", Some(&budget.fir1)),
+            ("FIR2", budget.fir2.as_ref()),
+            ("FIR3", budget.fir3.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, errors)| Some(format!("{name}: {}", format(errors?))))
+        .collect::<Vec<_>>()
+        .join("\n");
+        let element = &self.elements.ddc_error_budget;
+        element.set_text_content(Some(&format(&budget.composite)));
+        element.set_title(&title);
+    }
+
     fn post_update_ddc_elements(&self, json: &maia_json::DDCConfigSummary) -> Result<(), JsValue> {
         self.update_waterfall_ddc(json)
     }
@@ -551,8 +1411,24 @@ impl Ui {
     }
 
     /// Sets the DDC frequency.
+    ///
+    /// The frequency is snapped to the channel raster selected in the user
+    /// interface.
     pub fn set_ddc_frequency(&self, frequency: f64) -> Result<(), JsValue> {
-        self.elements.ddc_frequency.set(&frequency);
+        let frequency = match self.api_state.borrow().as_ref() {
+            Some(state) => {
+                let lo_frequency = state.ad9361.rx_lo_frequency.0;
+                self.snap_to_channel_raster(state, lo_frequency + frequency) - lo_frequency
+            }
+            None => frequency,
+        };
+        self.apply_ddc_frequency(frequency)
+    }
+
+    fn apply_ddc_frequency(&self, frequency: f64) -> Result<(), JsValue> {
+        self.elements
+            .ddc_frequency
+            .set(&maia_json::Hertz(frequency));
         self.elements
             .ddc_frequency
             .onchange()
@@ -560,6 +1436,29 @@ impl Ui {
             .call0(&JsValue::NULL)?;
         Ok(())
     }
+
+    onchange_apply!(channel_raster);
+
+    fn channel_raster_apply(&self, value: raster::ChannelRaster) {
+        self.local_settings.borrow_mut().channel_raster = value;
+    }
+
+    // Snaps a frequency at the device input to the channel raster. The raster
+    // applies to the displayed frequency, which takes into account the
+    // frequency offset and the spectrum inversion. These are obtained from the
+    // API state and the UI elements rather than from the waterfall, since the
+    // waterfall is borrowed while it is being dragged.
+    fn snap_to_channel_raster(&self, state: &maia_json::Api, freq: f64) -> f64 {
+        sync::snap_to_channel_raster(
+            self.local_settings.borrow().channel_raster,
+            state.device.frequency_offset.0,
+            self.elements
+                .waterfall_invert_spectrum
+                .get()
+                .unwrap_or(false),
+            freq,
+        )
+    }
 }
 
 // Geolocation methods
@@ -841,6 +1740,8 @@ impl Ui {
         Ok(())
     }
 
+    onchange_apply!(recorder_auto_download);
+
     impl_section!(
         recorder,
         maia_json::Recorder,
@@ -848,30 +1749,121 @@ impl Ui {
         RECORDER_URL,
         prepend_timestamp,
         mode,
-        maximum_duration
+        maximum_duration,
+        clip_mode,
+        clip_duration
     );
 
     fn update_recorder_button(&self, json: &maia_json::Recorder) {
-        let text = match json.state {
+        let action = match json.state {
             maia_json::RecorderState::Stopped => "Record",
             maia_json::RecorderState::Running => "Stop",
             maia_json::RecorderState::Stopping => "Stopping",
+            maia_json::RecorderState::Armed => "Disarm",
+        };
+        // Show a countdown while a recording with a maximum duration is running
+        // or while the recorder is armed to start at a given time
+        let start_time = json.armed.and_then(|arm| arm.start_time);
+        let text = match (json.state, json.remaining_duration, start_time) {
+            (maia_json::RecorderState::Running, Some(remaining), _) => {
+                format!("{action} ({:.0}s)", remaining.0.ceil())
+            }
+            (maia_json::RecorderState::Armed, _, Some(start_time)) => {
+                let remaining = (start_time - js_sys::Date::now()).max(0.0) * 1e-3;
+                format!("{action} ({:.0}s)", remaining.ceil())
+            }
+            _ => action.to_string(),
         };
         for button in [
             &self.elements.recorder_button,
             &self.elements.recorder_button_replica,
         ] {
             if button.inner_html() != text {
-                button.set_text_content(Some(text));
-                button.set_class_name(&format!("{}_button", text.to_lowercase()));
+                button.set_text_content(Some(&text));
+                button.set_class_name(&format!("{}_button", action.to_lowercase()));
             }
         }
     }
 
-    fn patch_recorder_promise(&self, patch: maia_json::PatchRecorder) -> JsValue {
-        let ui = self.clone();
-        future_to_promise(async move {
-            if let Some(json_output) =
+    fn update_recorder_overload(&self, json: &maia_json::Recorder) -> Result<(), JsValue> {
+        let overload = &self.elements.recorder_overload;
+        match json.clipping {
+            Some(clipping) if clipping > 0.0 => {
+                overload.set_title(&if json.state == maia_json::RecorderState::Running {
+                    format!(
+                        "The recording in progress contains about {clipping:.3}% clipped samples"
+                    )
+                } else {
+                    format!("The last recording contains {clipping:.3}% clipped samples")
+                });
+                overload.class_list().remove_1("hidden")?;
+            }
+            _ => overload.class_list().add_1("hidden")?,
+        }
+        Ok(())
+    }
+
+    fn update_recorder_buffer_duration(&self, json: &maia_json::Recorder) {
+        let text = format!(
+            "{:.1} s ({} MiB)",
+            json.max_duration_at_current_settings,
+            json.buffer_size_bytes >> 20
+        );
+        let span = &self.elements.recorder_buffer_duration;
+        if span.text_content().as_deref() != Some(&text) {
+            span.set_text_content(Some(&text));
+        }
+    }
+
+    fn recorder_state_transition(&self, changes: &sync::ApiChanges, current: &maia_json::Recorder) {
+        if changes.recording_finished {
+            self.notify("Maia SDR recording finished", "The recording has finished");
+        }
+        // Auto-download is only performed for fixed-duration recordings
+        if changes.recording_finished
+            && current.maximum_duration > maia_json::Seconds(0.0)
+            && self.local_settings.borrow().recorder_auto_download
+        {
+            self.elements.download_recording.click();
+        }
+    }
+
+    fn recorder_auto_download_apply(&self, value: bool) {
+        self.local_settings.borrow_mut().recorder_auto_download = value;
+    }
+
+    /// Downloads a time and frequency region of the recording.
+    ///
+    /// The region is extracted by maia-httpd from the current recording and
+    /// downloaded as a SigMF archive. The time span is given in milliseconds
+    /// since the UNIX epoch, as in the waterfall line timestamps, and the
+    /// frequency range is given in Hz.
+    pub fn export_recording_region(
+        &self,
+        time_span: (f64, f64),
+        frequency_range: (f64, f64),
+    ) -> Result<(), JsValue> {
+        let url = format!(
+            "{RECORDING_REGION_URL}?start_time={}&end_time={}&frequency_min={}&frequency_max={}",
+            time_span.0.min(time_span.1),
+            time_span.0.max(time_span.1),
+            frequency_range.0.min(frequency_range.1),
+            frequency_range.0.max(frequency_range.1),
+        );
+        let link = self
+            .document
+            .create_element("a")?
+            .dyn_into::<HtmlAnchorElement>()?;
+        link.set_href(&url);
+        link.set_download("");
+        link.click();
+        Ok(())
+    }
+
+    fn patch_recorder_promise(&self, patch: maia_json::PatchRecorder) -> JsValue {
+        let ui = self.clone();
+        future_to_promise(async move {
+            if let Some(json_output) =
                 request::ignore_request_failed(ui.patch_recorder(&patch).await)?
             {
                 ui.update_recorder_button(&json_output);
@@ -884,9 +1876,11 @@ impl Ui {
     fn recorder_button_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
         let ui = self.clone();
         Closure::new(move || {
-            let action = match ui.elements.recorder_button.text_content().as_deref() {
+            // The button text can contain a countdown after the action name
+            let text = ui.elements.recorder_button.text_content();
+            let action = match text.as_deref().and_then(|t| t.split_whitespace().next()) {
                 Some("Record") => maia_json::RecorderStateChange::Start,
-                Some("Stop") => maia_json::RecorderStateChange::Stop,
+                Some("Stop") | Some("Disarm") => maia_json::RecorderStateChange::Stop,
                 Some("Stopping") => {
                     // ignore click
                     return JsValue::NULL;
@@ -953,6 +1947,215 @@ impl Ui {
     }
 }
 
+// Upload methods
+impl Ui {
+    impl_section_custom!(
+        upload_target,
+        maia_json::UploadTarget,
+        maia_json::PatchUploadTarget,
+        UPLOAD_TARGET_URL,
+        protocol,
+        url,
+        username,
+        region
+    );
+    impl_post_patch_update_elements_noop!(upload_target, maia_json::PatchUploadTarget);
+    impl_onchange_patch_modify_noop!(upload_target, maia_json::PatchUploadTarget);
+
+    fn post_update_upload_target_elements(
+        &self,
+        json: &maia_json::UploadTarget,
+    ) -> Result<(), JsValue> {
+        // The password is never shown, but the placeholder indicates whether
+        // it has been set.
+        self.elements
+            .upload_target_password
+            .set_placeholder(if json.password_set { "(set)" } else { "" });
+        Ok(())
+    }
+
+    fn upload_target_password_onchange(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let password = &ui.elements.upload_target_password;
+            let patch = maia_json::PatchUploadTarget {
+                password: Some(password.value()),
+                ..Default::default()
+            };
+            // The password is cleared from the UI once it has been sent.
+            password.set_value("");
+            let ui = ui.clone();
+            future_to_promise(async move {
+                ui.patch_upload_target_update_elements(&patch).await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    impl_put!(
+        recording_upload,
+        (),
+        maia_json::RecordingUpload,
+        RECORDING_UPLOAD_URL
+    );
+    impl_request!(
+        recording_upload,
+        (),
+        maia_json::RecordingUpload,
+        RECORDING_UPLOAD_URL,
+        delete,
+        "DELETE"
+    );
+
+    fn update_recording_upload(&self, json: &maia_json::RecordingUpload) {
+        let (status, button) = match json.state {
+            maia_json::UploadState::Idle => (String::new(), "Upload"),
+            maia_json::UploadState::Uploading => {
+                let percent = if json.size == 0 {
+                    0.0
+                } else {
+                    100.0 * json.bytes_uploaded as f64 / json.size as f64
+                };
+                let mut status = format!("{}: {percent:.0}%", json.filename);
+                if json.attempt > 1 {
+                    status.push_str(&format!(" (attempt {})", json.attempt));
+                }
+                (status, "Cancel")
+            }
+            maia_json::UploadState::Finished => (format!("{} uploaded", json.filename), "Upload"),
+            maia_json::UploadState::Failed => (
+                format!(
+                    "{} failed: {}",
+                    json.filename,
+                    json.error.as_deref().unwrap_or_default()
+                ),
+                "Upload",
+            ),
+        };
+        self.elements
+            .recording_upload_status
+            .set_text_content(Some(&status));
+        self.elements
+            .recording_upload
+            .set_text_content(Some(button));
+    }
+
+    fn recording_upload_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                let uploading =
+                    ui.elements.recording_upload.text_content().as_deref() == Some("Cancel");
+                let response = if uploading {
+                    ui.delete_recording_upload(&()).await
+                } else {
+                    ui.put_recording_upload(&()).await
+                };
+                if let Some(response) = request::ignore_request_failed(response)? {
+                    ui.update_recording_upload(&response);
+                }
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    impl_request!(
+        recording_annotation,
+        maia_json::PostRecordingAnnotation,
+        maia_json::RecordingAnnotation,
+        RECORDING_ANNOTATIONS_URL,
+        post,
+        "POST"
+    );
+    impl_request!(
+        recording_annotations,
+        (),
+        maia_json::RecordingAnnotations,
+        RECORDING_ANNOTATIONS_URL,
+        delete,
+        "DELETE"
+    );
+
+    fn update_recording_annotations(&self, json: &maia_json::RecordingAnnotations) {
+        let annotations = json
+            .annotations
+            .iter()
+            .map(|annotation| waterfall::Annotation {
+                start_time: annotation.start_time,
+                end_time: annotation.end_time,
+                frequency_min: annotation.frequency_min.0,
+                frequency_max: annotation.frequency_max.0,
+                label: annotation.label.clone(),
+            })
+            .collect();
+        self.waterfall.borrow_mut().set_annotations(annotations);
+        self.elements
+            .recording_annotations_count
+            .set_text_content(Some(&json.annotations.len().to_string()));
+    }
+
+    /// Adds an annotation to the recording.
+    ///
+    /// The user is asked for the label of the annotation, which is then added
+    /// to the annotations of the current recording by maia-httpd. The time
+    /// span is given in milliseconds since the UNIX epoch, as in the waterfall
+    /// line timestamps, and the frequency range is given in Hz.
+    pub fn add_recording_annotation(
+        &self,
+        time_span: (f64, f64),
+        frequency_range: (f64, f64),
+    ) -> Result<(), JsValue> {
+        let Some(label) = self.window.prompt_with_message("Annotation label")? else {
+            return Ok(());
+        };
+        let post = maia_json::PostRecordingAnnotation {
+            start_time: time_span.0.min(time_span.1),
+            end_time: time_span.0.max(time_span.1),
+            frequency_min: maia_json::Hertz(frequency_range.0.min(frequency_range.1)),
+            frequency_max: maia_json::Hertz(frequency_range.0.max(frequency_range.1)),
+            label,
+            comment: String::new(),
+        };
+        let ui = self.clone();
+        // The promise is not awaited. Errors are shown by post_recording_annotation.
+        let _ = future_to_promise(async move {
+            if let Some(annotation) =
+                request::ignore_request_failed(ui.post_recording_annotation(&post).await)?
+            {
+                // Show the annotation without waiting for the next API update.
+                let json = ui.api_state.borrow_mut().as_mut().map(|state| {
+                    state.recording_annotations.annotations.push(annotation);
+                    state.recording_annotations.clone()
+                });
+                if let Some(json) = json {
+                    ui.update_recording_annotations(&json);
+                }
+            }
+            Ok(JsValue::NULL)
+        });
+        Ok(())
+    }
+
+    fn recording_annotations_clear_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                if let Some(response) =
+                    request::ignore_request_failed(ui.delete_recording_annotations(&()).await)?
+                {
+                    ui.update_recording_annotations(&response);
+                }
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+}
+
 // Spectrometer methods
 impl Ui {
     impl_section_custom!(
@@ -962,7 +2165,10 @@ impl Ui {
         SPECTROMETER_URL,
         input,
         output_sampling_frequency,
-        mode
+        mode,
+        retune_mode,
+        wall_clock_alignment,
+        dc_bin_removal
     );
     impl_post_patch_update_elements_noop!(spectrometer, maia_json::PatchSpectrometer);
 
@@ -974,20 +2180,12 @@ impl Ui {
     }
 
     fn spectrometer_onchange_patch_modify(&self, json: &mut maia_json::PatchSpectrometer) {
-        if json.input.is_some() {
-            // add output_sampling_frequency to the patch to maintain this
-            // parameter across the sample rate change
-            if let Some(freq) = self
-                .api_state
-                .borrow()
-                .as_ref()
-                .map(|s| s.spectrometer.output_sampling_frequency)
-            {
-                // if the format of the element fails, there is not much we can
-                // do
-                json.output_sampling_frequency = Some(freq);
-            }
-        }
+        let output_sampling_frequency = self
+            .api_state
+            .borrow()
+            .as_ref()
+            .map(|s| s.spectrometer.output_sampling_frequency);
+        sync::complete_spectrometer_patch(json, output_sampling_frequency);
     }
 
     // This function fakes an onchange event for the spectrometer_rate in order
@@ -1002,6 +2200,314 @@ impl Ui {
     }
 }
 
+// Device methods
+impl Ui {
+    impl_section_custom!(
+        device,
+        maia_json::Device,
+        maia_json::PatchDevice,
+        DEVICE_URL,
+        name,
+        frequency_offset
+    );
+    impl_post_patch_update_elements_noop!(device, maia_json::PatchDevice);
+    impl_onchange_patch_modify_noop!(device, maia_json::PatchDevice);
+
+    fn post_update_device_elements(&self, json: &maia_json::Device) -> Result<(), JsValue> {
+        let label = &self.elements.device_label;
+        if label.text_content().as_deref() != Some(&json.name) {
+            label.set_text_content(Some(&json.name));
+            if json.name.is_empty() {
+                label.class_list().add_1("hidden")?;
+                self.document.set_title("Maia SDR");
+            } else {
+                label.class_list().remove_1("hidden")?;
+                self.document
+                    .set_title(&format!("{} - Maia SDR", json.name));
+            }
+        }
+        self.waterfall.borrow_mut().set_frequency_offset(
+            json.frequency_offset.0,
+            &mut self.render_engine.borrow_mut(),
+        )
+    }
+
+    impl_put!(
+        device_identify,
+        maia_json::Identify,
+        maia_json::Identify,
+        DEVICE_IDENTIFY_URL
+    );
+
+    fn device_identify_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                let put = maia_json::Identify {
+                    duration: maia_json::Seconds(10.0),
+                };
+                request::ignore_request_failed(ui.put_device_identify(&put).await)?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    // Sends a shutdown or reboot request, authorized with the power token.
+    async fn put_power(&self, url: &str, token: &str) -> Result<(), JsValue> {
+        let opts = RequestInit::new();
+        opts.set_method("PUT");
+        let request = Request::new_with_str_and_init(url, &opts)?;
+        request
+            .headers()
+            .set("Authorization", &format!("Bearer {token}"))?;
+        let response = JsFuture::from(self.window.fetch_with_request(&request))
+            .await?
+            .dyn_into::<Response>()?;
+        if !response.ok() {
+            let error: maia_json::Error = request::response_to_json(&response).await?;
+            web_sys::console::error_1(
+                &format!(
+                    "PUT request failed with HTTP code {}. Error description: {}",
+                    response.status(),
+                    error.error_description
+                )
+                .into(),
+            );
+            self.alert(&error.error_description)?;
+        }
+        Ok(())
+    }
+
+    fn power_onclick(
+        &self,
+        url: &'static str,
+        question: &'static str,
+    ) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                if !ui.window.confirm_with_message(question)? {
+                    return Ok(JsValue::NULL);
+                }
+                let Some(token) = ui.window.prompt_with_message("Power token")? else {
+                    return Ok(JsValue::NULL);
+                };
+                ui.put_power(url, &token).await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    fn device_reboot_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        self.power_onclick(REBOOT_URL, "Reboot the device?")
+    }
+
+    fn device_shutdown_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        self.power_onclick(SHUTDOWN_URL, "Shut down the device?")
+    }
+
+    impl_request!(
+        startup_profile_current,
+        (),
+        maia_json::StartupProfile,
+        STARTUP_PROFILE_CURRENT_URL,
+        put,
+        "PUT"
+    );
+    impl_request!(
+        startup_profile,
+        (),
+        maia_json::StartupProfile,
+        STARTUP_PROFILE_URL,
+        delete,
+        "DELETE"
+    );
+
+    fn startup_profile_save_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                if ui
+                    .window
+                    .confirm_with_message("Apply the current settings when the device starts?")?
+                {
+                    request::ignore_request_failed(ui.put_startup_profile_current(&()).await)?;
+                }
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    fn startup_profile_reset_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                if ui
+                    .window
+                    .confirm_with_message("Use the factory defaults when the device starts?")?
+                {
+                    request::ignore_request_failed(ui.delete_startup_profile(&()).await)?;
+                }
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+}
+
+// WebSocket clients methods
+impl Ui {
+    fn clients_refresh_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                ui.update_clients().await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    // Obtains the clients connected to the WebSockets of maia-httpd and lists
+    // them, each with a button to disconnect it.
+    async fn update_clients(&self) -> Result<(), JsValue> {
+        let response = JsFuture::from(self.window.fetch_with_str(CLIENTS_URL))
+            .await?
+            .dyn_into::<Response>()?;
+        let json: maia_json::WebSocketClients = request::response_to_json(&response).await?;
+        let list = &self.elements.clients_list;
+        list.set_text_content(None);
+        for client in &json.clients {
+            let item = self.document.create_element("li")?;
+            let websocket = match client.websocket {
+                maia_json::WebSocketKind::Waterfall => "waterfall",
+                maia_json::WebSocketKind::Peaks => "peaks",
+            };
+            item.set_text_content(Some(&format!(
+                "{} ({websocket}, {:.0} s, {:.1} kB/s) ",
+                client.client.as_deref().unwrap_or("unknown client"),
+                client.duration,
+                1e-3 * client.rate
+            )));
+            let button = self
+                .document
+                .create_element("button")?
+                .dyn_into::<HtmlButtonElement>()?;
+            button.set_type("button");
+            button.set_text_content(Some("Disconnect"));
+            let onclick = Closure::<dyn Fn() -> JsValue>::new({
+                let ui = self.clone();
+                let id = client.id;
+                move || {
+                    let ui = ui.clone();
+                    future_to_promise(async move {
+                        ui.delete_client(id).await?;
+                        ui.update_clients().await?;
+                        Ok(JsValue::NULL)
+                    })
+                    .into()
+                }
+            });
+            button.set_onclick(Some(onclick.into_js_value().unchecked_ref()));
+            item.append_child(&button)?;
+            list.append_child(&item)?;
+        }
+        Ok(())
+    }
+
+    async fn delete_client(&self, id: u64) -> Result<(), JsValue> {
+        let request = request::json_request(&format!("{CLIENTS_URL}/{id}"), &(), "DELETE")?;
+        let response = JsFuture::from(self.window.fetch_with_request(&request))
+            .await?
+            .dyn_into::<Response>()?;
+        if !response.ok() {
+            let error: maia_json::Error = request::response_to_json(&response).await?;
+            web_sys::console::error_1(
+                &format!(
+                    "DELETE request failed with HTTP code {}. Error description: {}",
+                    response.status(),
+                    error.error_description
+                )
+                .into(),
+            );
+            self.alert(&error.error_description)?;
+        }
+        Ok(())
+    }
+}
+
+// Sensors methods
+impl Ui {
+    fn update_sensors(&self, json: &maia_json::Sensors) -> Result<(), JsValue> {
+        // The RSSI is given in dB below full scale. The meter spans from -100
+        // dBFS to 0 dBFS.
+        const RSSI_METER_RANGE: f64 = 100.0;
+        let dbfs = -json.rx_rssi.0;
+        self.elements
+            .rx_rssi
+            .set_text_content(Some(&format!("{dbfs:.1} dBFS")));
+        let fill = (100.0 * (1.0 + dbfs / RSSI_METER_RANGE)).clamp(0.0, 100.0);
+        self.elements
+            .rx_rssi_bar
+            .style()
+            .set_property("width", &format!("{fill:.1}%"))?;
+        // While the gain slider is being used, its value is shown instead of
+        // the actual gain.
+        if !self.document.is_element_active("ad9361_rx_gain") {
+            self.set_rx_gain_value(json.rx_gain.0);
+        }
+        Ok(())
+    }
+}
+
+// Scanner methods
+impl Ui {
+    fn update_scanner(&self, json: &maia_json::Scanner) -> Result<(), JsValue> {
+        let status = &self.elements.scanner_status;
+        let channel = json.channel.and_then(|index| json.channels.get(index));
+        let text = match (json.state, channel) {
+            (maia_json::ScannerState::Stopped, _) | (_, None) => json
+                .error
+                .as_ref()
+                .map(|error| format!("Scanner stopped: {error}")),
+            (state, Some(channel)) => {
+                let state = match state {
+                    maia_json::ScannerState::Active => "Active",
+                    maia_json::ScannerState::Hold => "Hold",
+                    _ => "Scanning",
+                };
+                let frequency = format!("{:.4} MHz", channel.frequency.mhz());
+                Some(if channel.label.is_empty() {
+                    format!("{state} {frequency}")
+                } else {
+                    format!("{state} {} ({frequency})", channel.label)
+                })
+            }
+        };
+        let class_list = status.class_list();
+        class_list.toggle_with_force(
+            "scanner_active",
+            matches!(json.state, maia_json::ScannerState::Active),
+        )?;
+        match text {
+            Some(text) => {
+                status.set_text_content(Some(&text));
+                class_list.remove_1("hidden")?;
+            }
+            None => class_list.add_1("hidden")?,
+        }
+        Ok(())
+    }
+}
+
 // Time methods
 impl Ui {
     impl_patch!(time, maia_json::PatchTime, maia_json::Time, TIME_URL);
@@ -1027,17 +2533,124 @@ impl Ui {
         waterfall_max,
         waterfall_show_waterfall,
         waterfall_show_spectrum,
-        waterfall_show_ddc
+        waterfall_show_color_scale,
+        waterfall_show_ddc,
+        waterfall_ddc_preview,
+        waterfall_zoom_anchor,
+        waterfall_frequency_unit,
+        waterfall_invert_spectrum,
+        waterfall_drag_mode
     );
 
     fn colormap_select_apply(&self, value: colormap::Colormap) {
+        let custom_colormaps = self.custom_colormaps.borrow();
+        let colors = match &value {
+            colormap::Colormap::Custom(name) => match custom_colormaps.get(name) {
+                Some(colors) => colors.as_slice(),
+                // The colormap is loaded once the custom colormaps have been
+                // obtained from maia-httpd.
+                None => return,
+            },
+            builtin => builtin.colormap_as_slice().unwrap(),
+        };
         let mut render_engine = self.render_engine.borrow_mut();
         self.waterfall
             .borrow()
-            .load_colormap(&mut render_engine, value.colormap_as_slice())
+            .load_colormap(&mut render_engine, colors)
             .unwrap();
     }
 
+    // Adds an option for a custom colormap to the colormap select, unless it
+    // already has one.
+    fn add_custom_colormap_option(&self, name: &str) -> Result<(), JsValue> {
+        if self.custom_colormap_option(name)?.is_some() {
+            return Ok(());
+        }
+        let value = colormap::Colormap::Custom(name.to_string()).to_string();
+        let option = self.document.create_element("option")?;
+        option.set_attribute("value", &value)?;
+        option.set_text_content(Some(name));
+        self.elements.colormap_select.append_child(&option)?;
+        Ok(())
+    }
+
+    // Returns the option for a custom colormap in the colormap select.
+    //
+    // maia-httpd only allows letters, digits, '_' and '-' in colormap names, so
+    // the name can be used in a selector without escaping.
+    fn custom_colormap_option(&self, name: &str) -> Result<Option<web_sys::Element>, JsValue> {
+        let value = colormap::Colormap::Custom(name.to_string()).to_string();
+        self.elements
+            .colormap_select
+            .query_selector(&format!("option[value=\"{value}\"]"))
+    }
+
+    fn update_custom_colormaps_closure(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                ui.update_custom_colormaps().await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    // Obtains the custom colormaps from maia-httpd and lists them in the
+    // colormap select. If the selected colormap is a custom colormap, it is
+    // loaded, or the default colormap is selected if it no longer exists.
+    async fn update_custom_colormaps(&self) -> Result<(), JsValue> {
+        let response = JsFuture::from(self.window.fetch_with_str(COLORMAPS_URL))
+            .await?
+            .dyn_into::<Response>()?;
+        if !response.ok() {
+            return Ok(());
+        }
+        let json: maia_json::Colormaps = request::response_to_json(&response).await?;
+        let selected = self.elements.colormap_select.get();
+        let mut previous = self
+            .custom_colormaps
+            .borrow()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        if let Some(colormap::Colormap::Custom(name)) = &selected {
+            previous.push(name.clone());
+        }
+        let custom_colormaps = json
+            .colormaps
+            .into_iter()
+            .filter_map(|colormap| Some((colormap.name?, colormap.colors.as_flattened().to_vec())))
+            .collect::<HashMap<_, _>>();
+        for name in custom_colormaps.keys() {
+            self.add_custom_colormap_option(name)?;
+        }
+        if let Some(colormap::Colormap::Custom(name)) = &selected {
+            if !custom_colormaps.contains_key(name) {
+                self.elements
+                    .colormap_select
+                    .set(&colormap::Colormap::Turbo);
+            }
+        }
+        for name in previous {
+            if !custom_colormaps.contains_key(&name) {
+                if let Some(option) = self.custom_colormap_option(&name)? {
+                    option.remove();
+                }
+            }
+        }
+        self.custom_colormaps.replace(custom_colormaps);
+        if let Some(colormap::Colormap::Custom(_)) = &selected {
+            // The onchange closure loads the colormap and updates the
+            // preferences.
+            if let Some(onchange) = self.elements.colormap_select.onchange() {
+                onchange.call0(&JsValue::NULL)?;
+            }
+        }
+        Ok(())
+    }
+
     fn waterfall_min_apply(&self, value: f32) {
         self.waterfall.borrow_mut().set_waterfall_min(value);
     }
@@ -1046,6 +2659,70 @@ impl Ui {
         self.waterfall.borrow_mut().set_waterfall_max(value);
     }
 
+    /// Changes the waterfall levels by the given amounts.
+    ///
+    /// The waterfall minimum and maximum are incremented by `delta_min` and
+    /// `delta_max` respectively (in dB units). The change is ignored if it
+    /// would make the minimum greater or equal than the maximum or negative.
+    pub fn adjust_waterfall_levels(&self, delta_min: f32, delta_max: f32) -> Result<(), JsValue> {
+        let (min, max) = {
+            let waterfall = self.waterfall.borrow();
+            (
+                waterfall.get_waterfall_min() + delta_min,
+                waterfall.get_waterfall_max() + delta_max,
+            )
+        };
+        if min < 0.0 || min >= max {
+            return Ok(());
+        }
+        for (element, value) in [
+            (&self.elements.waterfall_min, min),
+            (&self.elements.waterfall_max, max),
+        ] {
+            element.set(&value);
+            element.onchange().unwrap().call0(&JsValue::NULL)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the zoom anchor selected in the waterfall settings.
+    pub fn waterfall_zoom_anchor(&self) -> ZoomAnchor {
+        self.local_settings.borrow().waterfall_zoom_anchor
+    }
+
+    fn waterfall_zoom_anchor_apply(&self, value: ZoomAnchor) {
+        self.local_settings.borrow_mut().waterfall_zoom_anchor = value;
+    }
+
+    fn waterfall_frequency_unit_apply(&self, value: FrequencyUnit) {
+        if let Err(e) = self
+            .waterfall
+            .borrow_mut()
+            .set_frequency_unit(value, &mut self.render_engine.borrow_mut())
+        {
+            web_sys::console::error_1(&e);
+        }
+    }
+
+    fn waterfall_invert_spectrum_apply(&self, value: bool) {
+        if let Err(e) = self
+            .waterfall
+            .borrow_mut()
+            .set_spectrum_inverted(value, &mut self.render_engine.borrow_mut())
+        {
+            web_sys::console::error_1(&e);
+        }
+    }
+
+    /// Returns the drag mode selected in the waterfall settings.
+    pub fn waterfall_drag_mode(&self) -> DragMode {
+        self.local_settings.borrow().waterfall_drag_mode
+    }
+
+    fn waterfall_drag_mode_apply(&self, value: DragMode) {
+        self.local_settings.borrow_mut().waterfall_drag_mode = value;
+    }
+
     fn waterfall_show_waterfall_apply(&self, value: bool) {
         self.waterfall.borrow_mut().set_waterfall_visible(value);
     }
@@ -1054,26 +2731,126 @@ impl Ui {
         self.waterfall.borrow_mut().set_spectrum_visible(value);
     }
 
+    fn waterfall_show_color_scale_apply(&self, value: bool) {
+        self.waterfall.borrow().set_color_scale_visible(value);
+    }
+
     fn waterfall_show_ddc_apply(&self, value: bool) {
         self.local_settings.borrow_mut().waterfall_show_ddc = value;
+        self.waterfall_ddc_visibility_apply("waterfall_show_ddc_apply");
+    }
+
+    fn waterfall_ddc_preview_apply(&self, value: bool) {
+        self.local_settings.borrow_mut().waterfall_ddc_preview = value;
+        self.waterfall_ddc_visibility_apply("waterfall_ddc_preview_apply");
+    }
+
+    fn waterfall_ddc_visibility_apply(&self, caller: &str) {
         let state = self.api_state.borrow();
         let Some(state) = state.as_ref() else {
-            web_sys::console::error_1(
-                &"waterfall_show_ddc_apply: api_state not available yet".into(),
-            );
+            web_sys::console::error_1(&format!("{caller}: api_state not available yet").into());
             return;
         };
         let input_is_ddc = matches!(state.spectrometer.input, maia_json::SpectrometerInput::DDC);
-        self.waterfall
-            .borrow_mut()
-            .set_channel_visible(value && !input_is_ddc);
+        self.set_waterfall_ddc_visibility(&self.waterfall.borrow(), input_is_ddc);
+    }
+
+    // Shows the DDC channel and the preview of the DDC filter in the waterfall
+    // according to the local settings. They are only shown when the
+    // spectrometer input is not the DDC and the waterfall is live.
+    fn set_waterfall_ddc_visibility(&self, waterfall: &Waterfall, input_is_ddc: bool) {
+        let settings = self.local_settings.borrow();
+        let visible = !input_is_ddc && !waterfall.is_offline();
+        waterfall.set_channel_visible(settings.waterfall_show_ddc && visible);
+        waterfall.set_channel_preview_visible(settings.waterfall_ddc_preview && visible);
+    }
+
+    fn set_measurement_periodic(&self, interval_ms: i32) -> Result<(), JsValue> {
+        let ui = self.clone();
+        let handler = Closure::<dyn Fn()>::new(move || {
+            ui.update_measurement();
+            ui.update_channel_snr();
+        });
+        self.window
+            .set_interval_with_callback_and_timeout_and_arguments_0(
+                handler.into_js_value().unchecked_ref(),
+                interval_ms,
+            )?;
+        Ok(())
+    }
+
+    fn update_measurement(&self) {
+        let Some(measurement) = self.waterfall.borrow().channel_measurement() else {
+            self.elements
+                .measurement
+                .class_list()
+                .add_1("hidden")
+                .unwrap();
+            return;
+        };
+        self.elements
+            .measurement_power
+            .set_text_content(Some(&format!("{:.1}", measurement.power_db)));
+        self.elements
+            .measurement_occupied_bandwidth
+            .set_text_content(Some(&format!(
+                "{:.3}",
+                measurement.occupied_bandwidth * 1e-3
+            )));
+        self.elements
+            .measurement
+            .class_list()
+            .remove_1("hidden")
+            .unwrap();
+    }
+
+    fn update_channel_snr(&self) {
+        let waterfall = self.waterfall.borrow();
+        let Some(snr) = self
+            .channel_snr
+            .get()
+            .filter(|_| waterfall.is_channel_visible())
+        else {
+            self.elements
+                .channel_snr
+                .class_list()
+                .add_1("hidden")
+                .unwrap();
+            return;
+        };
+        // Place the SNR label next to the right edge of the DDC channel
+        let edge = waterfall.get_zoom()
+            * (waterfall.get_channel_frequency_uniform() + waterfall.get_channel_width_uniform()
+                - waterfall.get_center_frequency());
+        let canvas_width = self.render_engine.borrow().canvas_dims().css_pixels().0;
+        let left = 0.5 * (edge + 1.0) * canvas_width as f32;
+        let style = self.elements.channel_snr.style();
+        style
+            .set_property("left", &format!("{}px", left.round()))
+            .unwrap();
+        self.elements
+            .channel_snr
+            .set_text_content(Some(&format!("SNR {snr:.1} dB")));
+        self.elements
+            .channel_snr
+            .class_list()
+            .remove_1("hidden")
+            .unwrap();
+    }
+
+    fn measurement_clear_onclick(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || {
+            ui.waterfall.borrow_mut().set_measurement_region(None);
+            ui.update_measurement();
+        })
     }
 
     fn update_waterfall_ad9361(&self, json: &maia_json::Ad9361) -> Result<(), JsValue> {
         // updates only the frequency
         let mut waterfall = self.waterfall.borrow_mut();
         let samp_rate = waterfall.get_freq_samprate().1;
-        let freq = json.rx_lo_frequency as f64 + self.waterfall_ddc_tuning();
+        let freq = json.rx_lo_frequency.0 + self.waterfall_ddc_tuning();
         waterfall.set_freq_samprate(freq, samp_rate, &mut self.render_engine.borrow_mut())
     }
 
@@ -1085,7 +2862,7 @@ impl Ui {
         if !matches!(state.spectrometer.input, maia_json::SpectrometerInput::DDC) {
             return 0.0;
         }
-        state.ddc.frequency
+        state.ddc.frequency.0
     }
 
     fn update_waterfall_ddc(&self, json: &maia_json::DDCConfigSummary) -> Result<(), JsValue> {
@@ -1099,14 +2876,16 @@ impl Ui {
         if input_is_ddc {
             // update the center frequency
             let samp_rate = waterfall.get_freq_samprate().1;
-            let freq = state.ad9361.rx_lo_frequency as f64 + json.frequency;
+            let freq = state.ad9361.rx_lo_frequency.0 + json.frequency.0;
             waterfall.set_freq_samprate(freq, samp_rate, &mut self.render_engine.borrow_mut())?;
         }
         // update the DDC channel settings
-        let show_ddc = self.local_settings.borrow().waterfall_show_ddc;
-        waterfall.set_channel_visible(show_ddc && !input_is_ddc);
-        waterfall.set_channel_frequency(json.frequency);
+        self.set_waterfall_ddc_visibility(&waterfall, input_is_ddc);
+        waterfall.set_channel_frequency(json.frequency.0);
         waterfall.set_channel_decimation(json.decimation);
+        // The transition bands are not shaded if the FIR filters were not
+        // designed by maia-httpd.
+        waterfall.set_channel_transition_bandwidth(json.transition_bandwidth.unwrap_or(0.0));
         Ok(())
     }
 
@@ -1118,25 +2897,307 @@ impl Ui {
         };
         let input_is_ddc = matches!(json.input, maia_json::SpectrometerInput::DDC);
         let ddc_tuning = if input_is_ddc {
-            state.ddc.frequency
+            state.ddc.frequency.0
         } else {
             0.0
         };
-        let freq = state.ad9361.rx_lo_frequency as f64 + ddc_tuning;
+        let freq = state.ad9361.rx_lo_frequency.0 + ddc_tuning;
         waterfall.set_freq_samprate(
             freq,
-            json.input_sampling_frequency,
+            json.input_sampling_frequency.0,
             &mut self.render_engine.borrow_mut(),
         )?;
-        let show_ddc = self.local_settings.borrow().waterfall_show_ddc;
-        waterfall.set_channel_visible(show_ddc && !input_is_ddc);
-        waterfall.set_channel_frequency(state.ddc.frequency);
+        self.set_waterfall_ddc_visibility(&waterfall, input_is_ddc);
+        waterfall.set_channel_frequency(state.ddc.frequency.0);
+        Ok(())
+    }
+
+    fn offline_file_onchange(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                let Some(file) = ui
+                    .elements
+                    .offline_file
+                    .files()
+                    .and_then(|files| files.get(0))
+                else {
+                    return Ok(JsValue::NULL);
+                };
+                // Clear the input so that the same file can be opened again
+                ui.elements.offline_file.set_value("");
+                ui.open_offline_file(&file).await?;
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    // Shows the spectrogram of a local IQ file in the waterfall.
+    async fn open_offline_file(&self, file: &File) -> Result<(), JsValue> {
+        let contents = js_sys::Uint8Array::new(&JsFuture::from(file.array_buffer()).await?);
+        let contents = contents.to_vec();
+        let iq_file = match offline::IqFile::parse(&file.name(), &contents) {
+            Ok(iq_file) => iq_file,
+            Err(err) => return self.alert(&format!("Unable to open {}: {err}", file.name())),
+        };
+        let (live_freq, live_samp_rate) = self.waterfall.borrow().get_freq_samprate();
+        // Raw IQ files do not contain metadata, so the user is asked for the
+        // sample rate and frequency.
+        let Some(samp_rate) = iq_file.sample_rate().or_else(|| {
+            self.prompt_number("Sample rate (Msps)", live_samp_rate * 1e-6)
+                .map(|x| x * 1e6)
+        }) else {
+            return Ok(());
+        };
+        let Some(freq) = iq_file.frequency().or_else(|| {
+            self.prompt_number("Center frequency (MHz)", live_freq * 1e-6)
+                .map(|x| x * 1e6)
+        }) else {
+            return Ok(());
+        };
+        let spectrogram = match iq_file.spectrogram(Waterfall::MAX_LINES) {
+            Ok(spectrogram) => spectrogram,
+            Err(err) => return self.alert(&format!("Unable to open {}: {err}", file.name())),
+        };
+        let start_time = iq_file
+            .datetime()
+            .map(js_sys::Date::parse)
+            .filter(|time| time.is_finite());
+        {
+            let mut waterfall = self.waterfall.borrow_mut();
+            if !waterfall.is_offline() {
+                self.live_waterfall_levels.set(Some((
+                    waterfall.get_waterfall_min(),
+                    waterfall.get_waterfall_max(),
+                )));
+            }
+            waterfall.show_offline(
+                &spectrogram,
+                freq,
+                samp_rate,
+                start_time,
+                &mut self.render_engine.borrow_mut(),
+            )?;
+            waterfall.set_channel_visible(false);
+            waterfall.set_channel_preview_visible(false);
+        }
+        let (min, max) = spectrogram.levels();
+        self.set_waterfall_levels(min, max);
+        self.elements.offline_resume.set_disabled(false);
+        self.elements.waterfall_history.set_selected_index(0);
+        Ok(())
+    }
+
+    fn waterfall_history_onchange(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            if let Err(err) = ui.show_waterfall_history() {
+                web_sys::console::error_1(&err);
+            }
+            JsValue::NULL
+        })
+    }
+
+    // Shows the level of the history of the live spectrum selected in the
+    // waterfall_history select. The first option of the select does not show
+    // the history.
+    fn show_waterfall_history(&self) -> Result<(), JsValue> {
+        let index = self.elements.waterfall_history.selected_index();
+        if index <= 0 {
+            return self.resume_live_waterfall();
+        }
+        let level = usize::try_from(index - 1).unwrap();
+        {
+            let mut waterfall = self.waterfall.borrow_mut();
+            if waterfall.history().num_lines(level) == 0 {
+                drop(waterfall);
+                self.elements.waterfall_history.set_selected_index(0);
+                return self.alert("There is not enough spectrum history for this time scale yet");
+            }
+            if !waterfall.is_offline() {
+                self.live_waterfall_levels.set(Some((
+                    waterfall.get_waterfall_min(),
+                    waterfall.get_waterfall_max(),
+                )));
+            }
+            waterfall.show_history(level, &mut self.render_engine.borrow_mut())?;
+            waterfall.set_channel_visible(false);
+            waterfall.set_channel_preview_visible(false);
+        }
+        // The history is shown with the levels of the live waterfall, in case
+        // that an IQ file was being shown.
+        if let Some((min, max)) = self.live_waterfall_levels.get() {
+            self.set_waterfall_levels(min, max);
+        }
+        self.elements.offline_resume.set_disabled(false);
+        Ok(())
+    }
+
+    // Asks the user for a number, returning None if the dialog is cancelled or
+    // the number is not valid.
+    fn prompt_number(&self, message: &str, default: f64) -> Option<f64> {
+        self.window
+            .prompt_with_message_and_default(message, &default.to_string())
+            .ok()
+            .flatten()
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|value| value.is_finite())
+    }
+
+    // Sets the waterfall levels without storing them in the preferences.
+    fn set_waterfall_levels(&self, min: f32, max: f32) {
+        self.elements.waterfall_min.set(&min);
+        self.elements.waterfall_max.set(&max);
+        let mut waterfall = self.waterfall.borrow_mut();
+        waterfall.set_waterfall_min(min);
+        waterfall.set_waterfall_max(max);
+    }
+
+    fn offline_resume_onclick(&self) -> Closure<dyn Fn()> {
+        let ui = self.clone();
+        Closure::new(move || {
+            if let Err(err) = ui.resume_live_waterfall() {
+                web_sys::console::error_1(&err);
+            }
+        })
+    }
+
+    // Stops showing an offline spectrogram or the history and restores the
+    // live waterfall.
+    fn resume_live_waterfall(&self) -> Result<(), JsValue> {
+        self.waterfall
+            .borrow_mut()
+            .resume_live(&mut self.render_engine.borrow_mut())?;
+        self.elements.offline_resume.set_disabled(true);
+        self.elements.waterfall_history.set_selected_index(0);
+        if let Some((min, max)) = self.live_waterfall_levels.take() {
+            self.set_waterfall_levels(min, max);
+        }
+        let spectrometer = self
+            .api_state
+            .borrow()
+            .as_ref()
+            .map(|state| state.spectrometer.clone());
+        if let Some(spectrometer) = spectrometer {
+            self.update_waterfall_spectrometer(&spectrometer)?;
+        }
         Ok(())
     }
 
     fn update_waterfall_rate(&self, json: &maia_json::Spectrometer) {
         self.waterfall
             .borrow_mut()
-            .set_waterfall_update_rate(json.output_sampling_frequency as f32);
+            .set_waterfall_update_rate(json.output_sampling_frequency.0 as f32);
+    }
+
+    fn update_waterfall_stats(&self) {
+        let now = js_sys::Date::now();
+        let stats = self.waterfall.borrow().received_stats();
+        let Some((previous_time, previous)) = self.waterfall_stats.replace(Some((now, stats)))
+        else {
+            return;
+        };
+        let elapsed = (now - previous_time) * 1e-3;
+        if elapsed <= 0.0 {
+            return;
+        }
+        let line_rate = (stats.lines - previous.lines) as f64 / elapsed;
+        let throughput = (stats.bytes - previous.bytes) as f64 / elapsed;
+        let mut text = format!(
+            "{line_rate:.1} lines/s {:.0} kB/s {} dropped",
+            throughput * 1e-3,
+            stats.dropped_lines
+        );
+        if let Some(timestamp) = self.waterfall.borrow().last_line_timestamp() {
+            text += &format!(" last line {}", format_line_timestamp(timestamp));
+        }
+        self.elements.waterfall_stats.set_text_content(Some(&text));
     }
 }
+
+// View link methods
+impl Ui {
+    // Applies the span of the view link given when the page was loaded, and
+    // updates the view link in the URL of the page.
+    fn update_view_link(&self, json: &maia_json::Api) -> Result<(), JsValue> {
+        if let Some(span) = self.view_link_span.take() {
+            let zoom = (json.spectrometer.input_sampling_frequency.0 / span).clamp(1.0, 128.0);
+            let mut waterfall = self.waterfall.borrow_mut();
+            waterfall.set_zoom(zoom as f32);
+            waterfall.set_center_frequency(0.0);
+        }
+        let Some(view_link) = self.view_link(json) else {
+            return Ok(());
+        };
+        let hash = format!("#{view_link}");
+        if self.window.location().hash()? != hash {
+            // replaceState is used so that the browser history is not filled
+            // with an entry for each change of the view.
+            self.window
+                .history()?
+                .replace_state_with_url(&JsValue::NULL, "", Some(&hash))?;
+        }
+        Ok(())
+    }
+
+    // Returns the view link for the current view, or None if the waterfall
+    // shows an offline spectrogram.
+    fn view_link(&self, json: &maia_json::Api) -> Option<link::ViewLink> {
+        let waterfall = self.waterfall.borrow();
+        if waterfall.is_offline() {
+            return None;
+        }
+        let (center_freq, samp_rate) = waterfall.get_freq_samprate();
+        let sign = if waterfall.is_spectrum_inverted() {
+            -1.0
+        } else {
+            1.0
+        };
+        let offset = sign * 0.5 * f64::from(waterfall.get_center_frequency()) * samp_rate;
+        Some(link::ViewLink {
+            frequency: Some(center_freq + offset),
+            span: Some(samp_rate / f64::from(waterfall.get_zoom())),
+            ddc_frequency: Some(json.ddc.frequency.0),
+            ddc_decimation: Some(json.ddc.decimation),
+            colormap: self.elements.colormap_select.get(),
+        })
+    }
+
+    fn view_link_copy_onclick(&self) -> Closure<dyn Fn() -> JsValue> {
+        let ui = self.clone();
+        Closure::new(move || {
+            let ui = ui.clone();
+            future_to_promise(async move {
+                if let Some(state) = ui.api_state() {
+                    ui.update_view_link(&state)?;
+                }
+                let url = ui.window.location().href()?;
+                if ui.copy_to_clipboard(&url).await.is_err() {
+                    // The clipboard is only available in secure contexts, so
+                    // the link is shown for the user to copy it.
+                    ui.window
+                        .prompt_with_message_and_default("Copy this link", &url)?;
+                }
+                Ok(JsValue::NULL)
+            })
+            .into()
+        })
+    }
+
+    async fn copy_to_clipboard(&self, text: &str) -> Result<(), JsValue> {
+        let navigator = self.window.navigator();
+        if js_sys::Reflect::get(&navigator, &"clipboard".into())?.is_undefined() {
+            return Err("clipboard not available".into());
+        }
+        JsFuture::from(navigator.clipboard().write_text(text)).await?;
+        Ok(())
+    }
+}
+
+// Clamps a frequency to the tuning range of the AD9361 RX LO.
+fn clamp_rx_frequency(freq: f64) -> f64 {
+    let range = maia_json::PatchAd9361::RX_LO_FREQUENCY_RANGE;
+    freq.clamp(*range.start(), *range.end())
+}
@@ -1,14 +1,18 @@
 //! Colormaps.
 //!
 //! This module defines the colormaps that are supported by the maia-wasm
-//! waterfall.
+//! waterfall. Besides the built-in colormaps, the waterfall can use the custom
+//! colormaps stored in maia-httpd.
 
 use serde::{Deserialize, Serialize};
 
+/// Prefix of the string representation of the custom colormaps.
+const CUSTOM_PREFIX: &str = "custom:";
+
 /// Waterfall colormap.
 ///
 /// This enum lists the supported waterfall colormaps.
-#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum Colormap {
     /// Turbo colormap.
     Turbo,
@@ -16,6 +20,11 @@ pub enum Colormap {
     Viridis,
     /// Inferno colormap.
     Inferno,
+    /// Custom colormap stored in maia-httpd.
+    ///
+    /// The colormap is identified by its name. Its colors are obtained from
+    /// `/api/colormaps`.
+    Custom(String),
 }
 
 impl Colormap {
@@ -24,11 +33,15 @@ impl Colormap {
     /// The format of the slice is 8-bit RGB as a flattened array. Usually the
     /// length of the colormap is 255 RGB pixels, since it is indexed by an
     /// 8-bit integer, but this need not be the case.
-    pub fn colormap_as_slice(&self) -> &[u8] {
+    ///
+    /// This returns `None` for custom colormaps, since their colors are
+    /// obtained from maia-httpd.
+    pub fn colormap_as_slice(&self) -> Option<&'static [u8]> {
         match self {
-            Colormap::Turbo => &crate::colormap::turbo::COLORMAP,
-            Colormap::Viridis => &crate::colormap::viridis::COLORMAP,
-            Colormap::Inferno => &crate::colormap::inferno::COLORMAP,
+            Colormap::Turbo => Some(&crate::colormap::turbo::COLORMAP),
+            Colormap::Viridis => Some(&crate::colormap::viridis::COLORMAP),
+            Colormap::Inferno => Some(&crate::colormap::inferno::COLORMAP),
+            Colormap::Custom(_) => None,
         }
     }
 }
@@ -36,7 +49,24 @@ impl Colormap {
 impl std::str::FromStr for Colormap {
     type Err = ();
 
+    /// Parses a colormap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::ui::colormap::Colormap;
+    ///
+    /// assert_eq!("Viridis".parse(), Ok(Colormap::Viridis));
+    /// assert_eq!(
+    ///     "custom:night".parse(),
+    ///     Ok(Colormap::Custom("night".to_string()))
+    /// );
+    /// assert_eq!(Colormap::Custom("night".to_string()).to_string(), "custom:night");
+    /// ```
     fn from_str(s: &str) -> Result<Colormap, ()> {
+        if let Some(name) = s.strip_prefix(CUSTOM_PREFIX) {
+            return Ok(Colormap::Custom(name.to_string()));
+        }
         Ok(match s {
             "Turbo" => Colormap::Turbo,
             "Viridis" => Colormap::Viridis,
@@ -48,14 +78,11 @@ impl std::str::FromStr for Colormap {
 
 impl std::fmt::Display for Colormap {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
-        write!(
-            f,
-            "{}",
-            match self {
-                Colormap::Turbo => "Turbo",
-                Colormap::Viridis => "Viridis",
-                Colormap::Inferno => "Inferno",
-            }
-        )
+        match self {
+            Colormap::Turbo => write!(f, "Turbo"),
+            Colormap::Viridis => write!(f, "Viridis"),
+            Colormap::Inferno => write!(f, "Inferno"),
+            Colormap::Custom(name) => write!(f, "{CUSTOM_PREFIX}{name}"),
+        }
     }
 }
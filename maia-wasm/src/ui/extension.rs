@@ -0,0 +1,168 @@
+//! UI extensions.
+//!
+//! This module allows third parties that embed maia-wasm to add their own
+//! functionality to the [`Ui`] without modifying maia-wasm. An extension is a
+//! type that implements the [`UiExtension`] trait. Extensions are registered
+//! with a [`UiBuilder`] before building the [`Ui`].
+//!
+//! An extension can have its own tab in the settings dialog. The [`Ui`] creates
+//! the tab and an empty panel for it, and passes the panel to
+//! [`UiExtension::setup`], so that the extension can fill it with its own HTML
+//! elements. Each time that the [`Ui`] obtains the state of the maia-httpd API,
+//! it calls [`UiExtension::api_update`], which allows the extension to keep its
+//! elements up to date. Extensions can store their settings in the
+//! [`Preferences`](super::preferences::Preferences) by using
+//! [`Preferences::extension_value`](super::preferences::Preferences::extension_value)
+//! and
+//! [`Preferences::update_extension_value`](super::preferences::Preferences::update_extension_value).
+//!
+//! # Example
+//!
+//! ```
+//! use maia_wasm::ui::{extension::UiExtension, Ui};
+//! use wasm_bindgen::JsValue;
+//! use web_sys::HtmlElement;
+//!
+//! struct RxFrequencyLog {}
+//!
+//! impl UiExtension for RxFrequencyLog {
+//!     fn name(&self) -> &str {
+//!         "rx_frequency_log"
+//!     }
+//!
+//!     fn panel_title(&self) -> Option<&str> {
+//!         Some("Log")
+//!     }
+//!
+//!     fn setup(&self, _ui: &Ui, panel: Option<&HtmlElement>) -> Result<(), JsValue> {
+//!         if let Some(panel) = panel {
+//!             panel.set_text_content(Some("RX frequency is logged to the console"));
+//!         }
+//!         Ok(())
+//!     }
+//!
+//!     fn api_update(&self, _ui: &Ui, api: &maia_json::Api) -> Result<(), JsValue> {
+//!         web_sys::console::log_1(&api.ad9361.rx_lo_frequency.0.into());
+//!         Ok(())
+//!     }
+//! }
+//!
+//! // The Ui is built as follows:
+//! //
+//! // let ui = Ui::builder(window, document, render_engine, waterfall)
+//! //     .extension(RxFrequencyLog {})
+//! //     .build()?;
+//! ```
+
+use super::Ui;
+use crate::render::RenderEngine;
+use crate::waterfall::Waterfall;
+use std::{cell::RefCell, rc::Rc};
+use wasm_bindgen::JsValue;
+use web_sys::{Document, HtmlElement, Window};
+
+/// UI extension.
+///
+/// This trait is implemented by the types that add functionality to the
+/// [`Ui`]. All the methods except [`UiExtension::name`] have a default
+/// implementation that does nothing, so an extension only needs to implement
+/// the methods that it uses.
+pub trait UiExtension {
+    /// Returns the name of the extension.
+    ///
+    /// The name must be unique among the extensions registered in the
+    /// [`Ui`]. It is used to form the IDs of the HTML elements created for the
+    /// extension (`{name}_tab` and `{name}_panel`) and to scope the values that
+    /// the extension stores in the preferences, so it should only contain
+    /// characters that are valid in an HTML ID.
+    fn name(&self) -> &str;
+
+    /// Returns the title of the settings tab of the extension.
+    ///
+    /// If this returns `None`, which is the default, no tab is created in the
+    /// settings dialog for this extension.
+    fn panel_title(&self) -> Option<&str> {
+        None
+    }
+
+    /// Sets up the extension.
+    ///
+    /// This function is called once, when the [`Ui`] is built, after the
+    /// preferences of the [`Ui`] have been applied. If the extension has a
+    /// settings tab, `panel` contains the (initially empty) panel of the tab.
+    fn setup(&self, _ui: &Ui, _panel: Option<&HtmlElement>) -> Result<(), JsValue> {
+        Ok(())
+    }
+
+    /// Updates the extension with the current state of the API.
+    ///
+    /// This function is called each time that the [`Ui`] obtains the state of
+    /// the maia-httpd API, after the elements of the [`Ui`] have been updated.
+    fn api_update(&self, _ui: &Ui, _api: &maia_json::Api) -> Result<(), JsValue> {
+        Ok(())
+    }
+}
+
+/// Builder for [`Ui`].
+///
+/// The builder is used to register [`UiExtension`]s before creating the
+/// [`Ui`]. It is obtained with [`Ui::builder`] or [`UiBuilder::new`]. The
+/// [`Ui`] is created by calling [`UiBuilder::build`].
+pub struct UiBuilder {
+    window: Rc<Window>,
+    document: Rc<Document>,
+    render_engine: Rc<RefCell<RenderEngine>>,
+    waterfall: Rc<RefCell<Waterfall>>,
+    extensions: Vec<Rc<dyn UiExtension>>,
+}
+
+impl UiBuilder {
+    /// Creates a new builder.
+    ///
+    /// The arguments are the same as those of [`Ui::new`].
+    pub fn new(
+        window: Rc<Window>,
+        document: Rc<Document>,
+        render_engine: Rc<RefCell<RenderEngine>>,
+        waterfall: Rc<RefCell<Waterfall>>,
+    ) -> UiBuilder {
+        UiBuilder {
+            window,
+            document,
+            render_engine,
+            waterfall,
+            extensions: Vec::new(),
+        }
+    }
+
+    /// Registers an extension.
+    ///
+    /// Extensions are set up and updated in the order in which they are
+    /// registered, and their settings tabs are placed in this order after the
+    /// tabs of the [`Ui`].
+    pub fn extension<E: UiExtension + 'static>(mut self, extension: E) -> UiBuilder {
+        self.extensions.push(Rc::new(extension));
+        self
+    }
+
+    /// Builds the [`Ui`].
+    ///
+    /// This creates the [`Ui`] and sets up all the registered extensions.
+    pub fn build(self) -> Result<Ui, JsValue> {
+        if let Some(name) = self.extensions.iter().enumerate().find_map(|(j, ext)| {
+            self.extensions[..j]
+                .iter()
+                .any(|other| other.name() == ext.name())
+                .then(|| ext.name())
+        }) {
+            return Err(format!("duplicate UI extension name {name}").into());
+        }
+        Ui::with_extensions(
+            self.window,
+            self.document,
+            self.render_engine,
+            self.waterfall,
+            self.extensions,
+        )
+    }
+}
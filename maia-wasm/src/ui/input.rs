@@ -193,8 +193,65 @@ macro_rules! number_input_float {
     }
 }
 
-number_input_int!(u64, u32);
+macro_rules! number_input_unit {
+    ($($t:ident),*) => {
+        $(
+            impl<P: NumberPresentation> InputElement<HtmlInputElement>
+                for NumberInput<maia_json::$t, P>
+            {
+                type T = maia_json::$t;
+
+                fn get(&self) -> Option<maia_json::$t> {
+                    Some(maia_json::$t(self.element.value_as_number() * P::SCALE))
+                }
+
+                fn set(&self, value: &maia_json::$t) {
+                    let value = if let Some(resolution) = P::RESOLUTION {
+                        (value.0 / resolution).round() * resolution
+                    } else {
+                        value.0
+                    };
+                    self.element.set_value_as_number(value / P::SCALE);
+                }
+            }
+        )*
+    }
+}
+
+number_input_int!(u64, u32, i64);
 number_input_float!(f64, f32);
+number_input_unit!(Hertz, Decibels, Seconds);
+
+impl<T, P: NumberPresentation> NumberInput<T, P> {
+    /// Sets the limits of the input element from the metadata of a field.
+    ///
+    /// See [`set_field_metadata`].
+    pub fn set_field_metadata(&self, metadata: &maia_json::FieldMetadata) {
+        set_field_metadata::<P>(&self.element, metadata);
+    }
+}
+
+/// Sets the limits of a number input element from the metadata of a field.
+///
+/// The `min`, `max` and `step` attributes of the element are set according to
+/// the [`FieldMetadata`](maia_json::FieldMetadata) of a field of the maia-json
+/// schemas, converted to the units of the [`NumberPresentation`] `P`. The step
+/// is never finer than the resolution of the presentation.
+pub fn set_field_metadata<P: NumberPresentation>(
+    element: &HtmlInputElement,
+    metadata: &maia_json::FieldMetadata,
+) {
+    element.set_min(&(metadata.min / P::SCALE).to_string());
+    element.set_max(&(metadata.max / P::SCALE).to_string());
+    let step = match (metadata.step, P::RESOLUTION) {
+        (Some(step), Some(resolution)) => Some(step.max(resolution)),
+        (step, resolution) => step.or(resolution),
+    };
+    match step {
+        Some(step) => element.set_step(&(step / P::SCALE).to_string()),
+        None => element.set_step("any"),
+    }
+}
 
 /// Number input.
 ///
@@ -275,8 +332,36 @@ macro_rules! number_span_float {
     }
 }
 
+macro_rules! number_span_unit {
+    ($($t:ident),*) => {
+        $(
+            impl<P: NumberPresentation> InputElement<HtmlSpanElement>
+                for NumberSpan<maia_json::$t, P>
+            {
+                type T = maia_json::$t;
+
+                fn get(&self) -> Option<maia_json::$t> {
+                    let value: f64 = self.element.text_content()?.parse().ok()?;
+                    Some(maia_json::$t(value * P::SCALE))
+                }
+
+                fn set(&self, value: &maia_json::$t) {
+                    let value = if let Some(resolution) = P::RESOLUTION {
+                        (value.0 / resolution).round() * resolution
+                    } else {
+                        value.0
+                    };
+                    let value = value / P::SCALE;
+                    self.element.set_text_content(Some(&value.to_string()));
+                }
+            }
+        )*
+    }
+}
+
 number_span_int!(u64, u32);
 number_span_float!(f64, f32);
+number_span_unit!(Hertz);
 
 /// Text input.
 ///
@@ -314,6 +399,145 @@ impl InputElement<HtmlInputElement> for TextInput {
     }
 }
 
+/// Frequency entry.
+///
+/// This represents a frequency typed by the user, which can be either an
+/// absolute frequency or a change relative to the current frequency. The values
+/// are given in Hz.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FrequencyEntry {
+    /// Absolute frequency.
+    Absolute(f64),
+    /// Frequency change relative to the current frequency.
+    Relative(f64),
+}
+
+impl FrequencyEntry {
+    /// Parses a frequency entry.
+    ///
+    /// The entry is a number optionally followed by a unit, such as `145.8M`,
+    /// `7.074 MHz` or `433920 kHz`. The units are case insensitive, so `m`
+    /// means MHz rather than mHz, and the `Hz` suffix can be omitted. Numbers
+    /// without units are multiplied by `default_scale`. A leading `+` or `-`
+    /// sign indicates a relative change.
+    ///
+    /// If the entry cannot be parsed, `None` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::ui::input::FrequencyEntry;
+    ///
+    /// assert_eq!(
+    ///     FrequencyEntry::parse("7.074 MHz", 1.0),
+    ///     Some(FrequencyEntry::Absolute(7.074e6))
+    /// );
+    /// assert_eq!(
+    ///     FrequencyEntry::parse("+12.5k", 1.0),
+    ///     Some(FrequencyEntry::Relative(12.5e3))
+    /// );
+    /// assert_eq!(
+    ///     FrequencyEntry::parse("100", 1e6),
+    ///     Some(FrequencyEntry::Absolute(100e6))
+    /// );
+    /// assert_eq!(FrequencyEntry::parse("12 parsecs", 1.0), None);
+    /// ```
+    pub fn parse(entry: &str, default_scale: f64) -> Option<FrequencyEntry> {
+        let entry = entry.trim();
+        let (sign, entry) = if let Some(entry) = entry.strip_prefix('+') {
+            (Some(1.0), entry)
+        } else if let Some(entry) = entry.strip_prefix('-') {
+            (Some(-1.0), entry)
+        } else {
+            (None, entry)
+        };
+        let entry = entry.trim_start();
+        let number_len = entry
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(entry.len());
+        let (number, unit) = entry.split_at(number_len);
+        let number = number.parse::<f64>().ok()?;
+        let unit = unit.trim().to_ascii_lowercase();
+        let scale = match unit.as_str() {
+            "" => default_scale,
+            "hz" => 1.0,
+            "k" | "khz" => 1e3,
+            "m" | "mhz" => 1e6,
+            "g" | "ghz" => 1e9,
+            _ => return None,
+        };
+        let value = number * scale;
+        Some(match sign {
+            None => FrequencyEntry::Absolute(value),
+            Some(sign) => FrequencyEntry::Relative(sign * value),
+        })
+    }
+
+    /// Returns the frequency corresponding to the entry.
+    ///
+    /// The `current` frequency is used as a reference for relative entries.
+    pub fn resolve(&self, current: f64) -> f64 {
+        match *self {
+            FrequencyEntry::Absolute(freq) => freq,
+            FrequencyEntry::Relative(change) => current + change,
+        }
+    }
+}
+
+/// Frequency input.
+///
+/// This struct behaves as a wrapper over `Rc<HtmlInputElement>` and implements
+/// the [`InputElement`] trait. It should be used with text input elements in
+/// which the user types a frequency with units, and it gives access to the
+/// value as a [`FrequencyEntry`]. Numbers without units are interpreted in the
+/// units of the [`NumberPresentation`] `P`.
+#[derive(Clone)]
+pub struct FrequencyInput<P = DefaultPresentation> {
+    element: Rc<HtmlInputElement>,
+    _phantom: std::marker::PhantomData<P>,
+}
+
+impl<P> From<Rc<HtmlInputElement>> for FrequencyInput<P> {
+    fn from(element: Rc<HtmlInputElement>) -> FrequencyInput<P> {
+        FrequencyInput {
+            element,
+            _phantom: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<P> Deref for FrequencyInput<P> {
+    type Target = HtmlInputElement;
+
+    fn deref(&self) -> &HtmlInputElement {
+        &self.element
+    }
+}
+
+impl<P: NumberPresentation> InputElement<HtmlInputElement> for FrequencyInput<P> {
+    type T = FrequencyEntry;
+
+    fn get(&self) -> Option<FrequencyEntry> {
+        FrequencyEntry::parse(&self.element.value(), P::SCALE)
+    }
+
+    fn set(&self, value: &FrequencyEntry) {
+        let (sign, value) = match *value {
+            FrequencyEntry::Absolute(freq) => ("", freq),
+            FrequencyEntry::Relative(change) => {
+                (if change < 0.0 { "-" } else { "+" }, change.abs())
+            }
+        };
+        let value = if let Some(resolution) = P::RESOLUTION {
+            (value / resolution).round() * resolution
+        } else {
+            value
+        };
+        self.element
+            .set_value(&format!("{sign}{}", value / P::SCALE));
+    }
+}
+
 /// Enum input.
 ///
 /// This struct behaves as a wrapper over `Rc<HtmlSelectElement>` and implements
@@ -0,0 +1,147 @@
+//! Shareable view links.
+//!
+//! This module implements the links that encode the current view of the
+//! waterfall in the fragment of the URL, such as `/#freq=145.8M&span=1M`. The
+//! link is parsed when the web application starts, and it is kept up to date
+//! as the user tunes, so that the URL can be shared with other users of the
+//! same receiver.
+
+use super::colormap::Colormap;
+
+/// View link.
+///
+/// This struct contains the settings encoded in a view link. Settings that are
+/// not present in the link are `None`. The frequencies are device frequencies,
+/// so they do not include the frequency offset of an external converter.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ViewLink {
+    /// Frequency at the center of the view (in Hz).
+    pub frequency: Option<f64>,
+    /// Frequency span of the view (in Hz).
+    pub span: Option<f64>,
+    /// DDC frequency (in Hz).
+    pub ddc_frequency: Option<f64>,
+    /// DDC decimation.
+    pub ddc_decimation: Option<u32>,
+    /// Waterfall colormap.
+    pub colormap: Option<Colormap>,
+}
+
+impl ViewLink {
+    /// Parses the fragment of a URL.
+    ///
+    /// The fragment can be given with or without the leading `#`. Unknown keys
+    /// and invalid values are ignored. Frequencies can use the suffixes `k`,
+    /// `M` and `G`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::ui::link::ViewLink;
+    ///
+    /// let link = ViewLink::parse("#freq=145.8M&span=1M&colormap=Viridis&foo=1");
+    /// assert_eq!(link.frequency, Some(145.8e6));
+    /// assert_eq!(link.span, Some(1e6));
+    /// assert!(link.ddc_frequency.is_none());
+    /// ```
+    pub fn parse(fragment: &str) -> ViewLink {
+        let mut link = ViewLink::default();
+        for (key, value) in fragment
+            .trim_start_matches('#')
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+        {
+            match key {
+                "freq" => link.frequency = parse_frequency(value).filter(|f| *f > 0.0),
+                "span" => link.span = parse_frequency(value).filter(|f| *f > 0.0),
+                "ddc_freq" => link.ddc_frequency = parse_frequency(value),
+                "ddc_dec" => link.ddc_decimation = value.parse().ok().filter(|&d| d > 0),
+                "colormap" => link.colormap = value.parse().ok(),
+                _ => (),
+            }
+        }
+        link
+    }
+
+    /// Returns whether the link does not contain any settings.
+    pub fn is_empty(&self) -> bool {
+        *self == ViewLink::default()
+    }
+}
+
+impl std::fmt::Display for ViewLink {
+    /// Formats the link as the fragment of a URL, without the leading `#`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::ui::{colormap::Colormap, link::ViewLink};
+    ///
+    /// let link = ViewLink {
+    ///     frequency: Some(145.8e6),
+    ///     span: Some(1e6),
+    ///     ddc_frequency: Some(-12.5e3),
+    ///     ddc_decimation: Some(20),
+    ///     colormap: Some(Colormap::Turbo),
+    /// };
+    /// assert_eq!(
+    ///     link.to_string(),
+    ///     "freq=145.8M&span=1M&ddc_freq=-12.5k&ddc_dec=20&colormap=Turbo"
+    /// );
+    /// assert_eq!(ViewLink::parse(&link.to_string()), link);
+    /// ```
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let mut pairs = Vec::new();
+        if let Some(frequency) = self.frequency {
+            pairs.push(format!("freq={}", format_frequency(frequency)));
+        }
+        if let Some(span) = self.span {
+            pairs.push(format!("span={}", format_frequency(span)));
+        }
+        if let Some(frequency) = self.ddc_frequency {
+            pairs.push(format!("ddc_freq={}", format_frequency(frequency)));
+        }
+        if let Some(decimation) = self.ddc_decimation {
+            pairs.push(format!("ddc_dec={decimation}"));
+        }
+        if let Some(colormap) = &self.colormap {
+            pairs.push(format!("colormap={colormap}"));
+        }
+        write!(f, "{}", pairs.join("&"))
+    }
+}
+
+// Parses a frequency in Hz with an optional k, M or G suffix.
+fn parse_frequency(value: &str) -> Option<f64> {
+    // The suffix is replaced by an exponent, so that the number is parsed
+    // without rounding errors.
+    let (number, exponent) = match value.char_indices().last()? {
+        (j, 'k') => (&value[..j], 3),
+        (j, 'M') => (&value[..j], 6),
+        (j, 'G') => (&value[..j], 9),
+        _ => (value, 0),
+    };
+    if number.contains(['e', 'E']) {
+        return None;
+    }
+    format!("{number}e{exponent}")
+        .parse::<f64>()
+        .ok()
+        .filter(|x| x.is_finite())
+}
+
+// Formats a frequency in Hz using the largest suffix that keeps the number
+// above one. The frequency is rounded to 1 Hz.
+fn format_frequency(frequency: f64) -> String {
+    // Adding zero turns -0.0 into 0.0
+    let frequency = frequency.round() + 0.0;
+    let (exponent, suffix) = match frequency.abs() {
+        x if x >= 1e9 => (9, "G"),
+        x if x >= 1e6 => (6, "M"),
+        x if x >= 1e3 => (3, "k"),
+        _ => return format!("{frequency}"),
+    };
+    let number = format!("{:.*}", exponent, frequency / 10f64.powi(exponent as i32));
+    let number = number.trim_end_matches('0').trim_end_matches('.');
+    format!("{number}{suffix}")
+}
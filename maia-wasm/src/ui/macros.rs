@@ -94,7 +94,6 @@ macro_rules! onchange_apply {
                             return;
                         }
                         if let Some(value) = element.get() {
-                            ui.[<$name _apply>](value);
                             // try_borrow_mut prevents trying to update the
                             // preferences as a consequence of the
                             // Preferences::apply_client calling this closure
@@ -103,6 +102,7 @@ macro_rules! onchange_apply {
                                     web_sys::console::error_1(&e);
                                 }
                             }
+                            ui.[<$name _apply>](value);
                         } else {
                             ui.window
                                 .alert_with_message(concat!("Invalid value for ",
@@ -1,10 +1,23 @@
+//! UI preferences.
+//!
+//! This module implements the preferences of the user interface, which are
+//! stored in the local storage of the browser, so that the values of the UI
+//! elements are kept when the page is reloaded.
+
 use super::input::InputElement;
-use serde::{Deserialize, Serialize};
+use super::link::ViewLink;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::BTreeMap;
 use wasm_bindgen::JsValue;
 use web_sys::{Storage, Window};
 
 const PREFERENCES_KEY: &str = "preferences";
 
+/// UI preferences.
+///
+/// This structure contains the values of the UI elements that are persisted
+/// in the local storage, and the values stored by the
+/// [`UiExtension`](super::extension::UiExtension)s.
 pub struct Preferences {
     storage: Option<Storage>,
     data: PreferenceData,
@@ -17,6 +30,10 @@ macro_rules! impl_preference_data {
             $(
                 $name: $ty,
             )*
+            // Values stored by the UI extensions, indexed by extension name
+            // and key
+            #[serde(default)]
+            extensions: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
         }
 
         impl Default for PreferenceData {
@@ -25,6 +42,7 @@ macro_rules! impl_preference_data {
                     $(
                         $name: $default,
                     )*
+                    extensions: BTreeMap::new(),
                 }
             }
         }
@@ -32,6 +50,7 @@ macro_rules! impl_preference_data {
         impl Preferences {
             $(
                 paste::paste! {
+                    #[doc = concat!("Updates the `", stringify!($name), "` preference.")]
                     pub fn [<update_ $name>](&mut self, value: &$ty) -> Result<(), JsValue> {
                         if (*value != self.data.$name) {
                             self.data.$name.clone_from(value);
@@ -64,33 +83,61 @@ impl_preference_data! {
     colormap_select: super::colormap::Colormap = super::colormap::Colormap::Turbo,
     waterfall_show_waterfall: bool = true,
     waterfall_show_spectrum: bool = false,
+    waterfall_show_color_scale: bool = false,
     waterfall_show_ddc: bool = true,
+    waterfall_ddc_preview: bool = false,
+    waterfall_zoom_anchor: crate::waterfall_interaction::ZoomAnchor =
+        crate::waterfall_interaction::ZoomAnchor::Pointer,
+    waterfall_frequency_unit: crate::waterfall::FrequencyUnit =
+        crate::waterfall::FrequencyUnit::MHz,
+    waterfall_invert_spectrum: bool = false,
+    waterfall_drag_mode: crate::waterfall_interaction::DragMode =
+        crate::waterfall_interaction::DragMode::Pan,
     waterfall_min: f32 = 35.0,
     waterfall_max: f32 = 85.0,
-    ad9361_rx_lo_frequency: u64 = 2_400_000_000,
-    ad9361_sampling_frequency: u32 = 61_440_000,
-    ad9361_rx_rf_bandwidth: u32 = 56_000_000,
+    ad9361_rx_lo_frequency: maia_json::Hertz = maia_json::Hertz(2.4e9),
+    ad9361_sampling_frequency: maia_json::Hertz = maia_json::Hertz(61.44e6),
+    ad9361_rx_rf_bandwidth: maia_json::Hertz = maia_json::Hertz(56e6),
+    ad9361_rx_rf_bandwidth_mode: maia_json::Ad9361RfBandwidthMode =
+        maia_json::Ad9361RfBandwidthMode::Manual,
     ad9361_rx_gain_mode: maia_json::Ad9361GainMode = maia_json::Ad9361GainMode::SlowAttack,
-    ad9361_rx_gain: f64 = 70.0,
-    ddc_frequency: f64 = 0.0,
+    ad9361_rx_gain: maia_json::Decibels = maia_json::Decibels(70.0),
+    ddc_frequency: maia_json::Hertz = maia_json::Hertz(0.0),
+    channel_raster: super::raster::ChannelRaster = super::raster::ChannelRaster::Off,
     ddc_decimation: u32 = 20,
     ddc_transition_bandwidth: f64 = 0.05,
     ddc_passband_ripple: f64 = 0.01,
-    ddc_stopband_attenuation_db: f64 = 60.0,
+    ddc_stopband_attenuation_db: maia_json::Decibels = maia_json::Decibels(60.0),
     ddc_stopband_one_over_f: bool = true,
     spectrometer_input: maia_json::SpectrometerInput = maia_json::SpectrometerInput::AD9361,
-    spectrometer_output_sampling_frequency: f64 = 20.0,
+    spectrometer_output_sampling_frequency: maia_json::Hertz = maia_json::Hertz(20.0),
     spectrometer_mode: maia_json::SpectrometerMode = maia_json::SpectrometerMode::Average,
+    spectrometer_retune_mode: maia_json::SpectrometerRetuneMode =
+        maia_json::SpectrometerRetuneMode::Abort,
+    spectrometer_wall_clock_alignment: bool = false,
+    spectrometer_dc_bin_removal: bool = false,
     recording_metadata_filename: String = "recording".to_string(),
     recorder_prepend_timestamp: bool = false,
     recording_metadata_description: String = "".to_string(),
     recording_metadata_author: String = "".to_string(),
     recorder_mode: maia_json::RecorderMode = maia_json::RecorderMode::IQ12bit,
-    recorder_maximum_duration: f64 = 0.0,
+    recorder_maximum_duration: maia_json::Seconds = maia_json::Seconds(0.0),
+    recorder_auto_download: bool = false,
+    recorder_clip_mode: bool = false,
+    recorder_clip_duration: maia_json::Seconds = maia_json::Seconds(1.0),
+    upload_target_protocol: maia_json::UploadProtocol = maia_json::UploadProtocol::S3,
+    upload_target_url: String = "".to_string(),
+    upload_target_username: String = "".to_string(),
+    upload_target_region: String = "us-east-1".to_string(),
     geolocation_watch: bool = false,
+    notifications: bool = false,
 }
 
 impl Preferences {
+    /// Loads the preferences from the local storage.
+    ///
+    /// If the local storage is not available or does not contain any
+    /// preferences, the default values are used.
     pub fn new(window: &Window) -> Result<Preferences, JsValue> {
         let storage = window.local_storage()?;
         let data = match &storage {
@@ -110,6 +157,72 @@ impl Preferences {
         Ok(Preferences { storage, data })
     }
 
+    /// Updates the preferences with the settings of a view link.
+    ///
+    /// The RX frequency is chosen so that the frequency of the link is at the
+    /// center of the waterfall, taking into account whether the waterfall shows
+    /// the DDC output. The span of the link is not a preference, so it is
+    /// ignored.
+    pub(super) fn update_view_link(&mut self, link: &ViewLink) -> Result<(), JsValue> {
+        if let Some(frequency) = link.ddc_frequency {
+            self.data.ddc_frequency = maia_json::Hertz(frequency);
+        }
+        if let Some(decimation) = link.ddc_decimation {
+            self.data.ddc_decimation = decimation;
+        }
+        if let Some(colormap) = &link.colormap {
+            self.data.colormap_select = colormap.clone();
+        }
+        if let Some(frequency) = link.frequency {
+            let ddc_tuning = match self.data.spectrometer_input {
+                maia_json::SpectrometerInput::DDC => self.data.ddc_frequency.0,
+                _ => 0.0,
+            };
+            self.data.ad9361_rx_lo_frequency =
+                maia_json::Hertz(super::clamp_rx_frequency((frequency - ddc_tuning).round()));
+        }
+        self.store()
+    }
+
+    /// Returns the colormap in the preferences.
+    pub(super) fn colormap_select(&self) -> &super::colormap::Colormap {
+        &self.data.colormap_select
+    }
+
+    /// Returns a value stored by an extension.
+    ///
+    /// The value is identified by the name of the extension and a key. This
+    /// returns `None` if the value has not been stored or if it cannot be
+    /// deserialized as a `T`.
+    pub fn extension_value<T: DeserializeOwned>(&self, extension: &str, key: &str) -> Option<T> {
+        let value = self.data.extensions.get(extension)?.get(key)?;
+        serde_json::from_value(value.clone()).ok()
+    }
+
+    /// Updates a value stored by an extension.
+    ///
+    /// The value is identified by the name of the extension and a key. The
+    /// preferences are written to the local storage if the value has changed.
+    pub fn update_extension_value<T: Serialize>(
+        &mut self,
+        extension: &str,
+        key: &str,
+        value: &T,
+    ) -> Result<(), JsValue> {
+        let value = serde_json::to_value(value).map_err(|e| e.to_string())?;
+        let values = self
+            .data
+            .extensions
+            .entry(extension.to_string())
+            .or_default();
+        if values.get(key) != Some(&value) {
+            values.insert(key.to_string(), value);
+            self.store()
+        } else {
+            Ok(())
+        }
+    }
+
     fn store(&self) -> Result<(), JsValue> {
         if let Some(storage) = self.storage.as_ref() {
             let data = serde_json::to_string(&self.data).unwrap();
@@ -151,6 +264,7 @@ macro_rules! impl_dummy_preferences {
         impl Preferences {
             $(
                 paste::paste! {
+                    #[doc = concat!("Dummy update method for `", stringify!($name), "`.")]
                     pub fn [<update_ $name>](&mut self, _value: &$ty) -> Result<(), wasm_bindgen::JsValue> {
                         Ok(())
                     }
@@ -161,6 +275,8 @@ macro_rules! impl_dummy_preferences {
 }
 
 impl_dummy_preferences!(
-    ddc_output_sampling_frequency: f64,
-    ddc_max_input_sampling_frequency: f64,
+    ddc_output_sampling_frequency: maia_json::Hertz,
+    ddc_max_input_sampling_frequency: maia_json::Hertz,
+    device_name: String,
+    device_frequency_offset: maia_json::Hertz,
 );
@@ -0,0 +1,87 @@
+//! Channel raster.
+//!
+//! This module defines the channel rasters to which the DDC and RX frequencies
+//! can be snapped when they are tuned from the user interface.
+
+use serde::{Deserialize, Serialize};
+
+/// Channel raster.
+///
+/// This enum lists the supported channel rasters. The raster applies to the
+/// frequencies shown in the waterfall, which take into account the frequency
+/// offset of an external converter.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum ChannelRaster {
+    /// Frequencies are not snapped to a raster.
+    #[default]
+    Off,
+    /// 6.25 kHz raster.
+    Khz6_25,
+    /// 12.5 kHz raster.
+    Khz12_5,
+    /// 25 kHz raster.
+    Khz25,
+}
+
+impl ChannelRaster {
+    /// Returns the channel spacing in Hz.
+    ///
+    /// Returns `None` if the raster is [`ChannelRaster::Off`].
+    pub fn spacing(&self) -> Option<f64> {
+        match self {
+            ChannelRaster::Off => None,
+            ChannelRaster::Khz6_25 => Some(6.25e3),
+            ChannelRaster::Khz12_5 => Some(12.5e3),
+            ChannelRaster::Khz25 => Some(25e3),
+        }
+    }
+
+    /// Snaps a frequency to the nearest channel of the raster.
+    ///
+    /// The frequency is given in Hz. If the raster is [`ChannelRaster::Off`],
+    /// the frequency is returned unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::ui::raster::ChannelRaster;
+    ///
+    /// assert_eq!(ChannelRaster::Khz12_5.snap(446_011_000.0), 446_012_500.0);
+    /// assert_eq!(ChannelRaster::Off.snap(446_011_000.0), 446_011_000.0);
+    /// ```
+    pub fn snap(&self, frequency: f64) -> f64 {
+        match self.spacing() {
+            Some(spacing) => (frequency / spacing).round() * spacing,
+            None => frequency,
+        }
+    }
+}
+
+impl std::str::FromStr for ChannelRaster {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ChannelRaster, ()> {
+        Ok(match s {
+            "Off" => ChannelRaster::Off,
+            "6.25 kHz" => ChannelRaster::Khz6_25,
+            "12.5 kHz" => ChannelRaster::Khz12_5,
+            "25 kHz" => ChannelRaster::Khz25,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl std::fmt::Display for ChannelRaster {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                ChannelRaster::Off => "Off",
+                ChannelRaster::Khz6_25 => "6.25 kHz",
+                ChannelRaster::Khz12_5 => "12.5 kHz",
+                ChannelRaster::Khz25 => "25 kHz",
+            }
+        )
+    }
+}
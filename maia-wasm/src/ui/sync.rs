@@ -0,0 +1,250 @@
+//! API state synchronization.
+//!
+//! This module contains the logic that keeps the [`Ui`](super::Ui) synchronized
+//! with the state of maia-httpd and that does not depend on the DOM: finding
+//! what has changed between two consecutive states of the API, and computing
+//! the requests that apply the changes made by the user. It only works with
+//! the [`maia_json`] types and plain values, so it can be unit tested without a
+//! browser or an HTML document, and it can be reused by other frontends. The
+//! [`Ui`](super::Ui) is the binding of this logic to the HTML elements.
+
+use super::raster::ChannelRaster;
+use maia_json::{Ad9361GainMode, Api, Hertz, PatchSpectrometer, RecorderState, SpectrometerInput};
+
+/// Changes between two consecutive states of the API.
+///
+/// This contains the changes that the [`Ui`](super::Ui) reacts to, besides
+/// keeping its elements synchronized with the API state.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
+pub struct ApiChanges {
+    /// The recording annotations have changed.
+    ///
+    /// This is `true` when there is no previous state.
+    pub recording_annotations: bool,
+    /// A recording has finished.
+    ///
+    /// This is `true` when the recorder has changed to the stopped state.
+    pub recording_finished: bool,
+}
+
+impl ApiChanges {
+    /// Finds the changes between two consecutive states of the API.
+    pub fn new(previous: Option<&Api>, current: &Api) -> ApiChanges {
+        let Some(previous) = previous else {
+            return ApiChanges {
+                recording_annotations: true,
+                recording_finished: false,
+            };
+        };
+        ApiChanges {
+            recording_annotations: previous.recording_annotations != current.recording_annotations,
+            recording_finished: recording_finished(previous.recorder.state, current.recorder.state),
+        }
+    }
+}
+
+/// Returns whether a recording has finished.
+///
+/// A recording has finished when the recorder changes from any other state to
+/// the stopped state.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::RecorderState;
+/// use maia_wasm::ui::sync::recording_finished;
+///
+/// assert!(recording_finished(RecorderState::Stopping, RecorderState::Stopped));
+/// assert!(!recording_finished(RecorderState::Stopped, RecorderState::Stopped));
+/// assert!(!recording_finished(RecorderState::Stopped, RecorderState::Running));
+/// ```
+pub fn recording_finished(previous: RecorderState, current: RecorderState) -> bool {
+    !matches!(previous, RecorderState::Stopped) && matches!(current, RecorderState::Stopped)
+}
+
+/// Tuning state.
+///
+/// This contains the settings of the API that determine how the RX frequency
+/// is changed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Tuning {
+    /// Input of the spectrometer.
+    pub spectrometer_input: SpectrometerInput,
+    /// AD9361 sampling frequency (in samples per second).
+    pub ad9361_sampling_frequency: f64,
+    /// AD9361 RX LO frequency (in Hz).
+    pub ad9361_rx_lo_frequency: f64,
+    /// DDC output sampling frequency (in samples per second).
+    pub ddc_output_sampling_frequency: f64,
+}
+
+impl Tuning {
+    /// Obtains the tuning state from the API state.
+    pub fn from_api(api: &Api) -> Tuning {
+        Tuning {
+            spectrometer_input: api.spectrometer.input,
+            ad9361_sampling_frequency: api.ad9361.sampling_frequency.0,
+            ad9361_rx_lo_frequency: api.ad9361.rx_lo_frequency.0,
+            ddc_output_sampling_frequency: api.ddc.output_sampling_frequency.0,
+        }
+    }
+}
+
+/// Change of the RX frequency.
+///
+/// This contains the frequencies that need to be set to change the RX
+/// frequency. A frequency that is `None` does not need to be changed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RxFrequencyChange {
+    /// New AD9361 RX LO frequency (in Hz).
+    pub ad9361_frequency: Option<u64>,
+    /// New DDC frequency (in Hz).
+    pub ddc_frequency: Option<f64>,
+}
+
+impl RxFrequencyChange {
+    /// Computes the change that sets the RX frequency to `frequency`.
+    ///
+    /// When the DDC is the input of the spectrometer, the DDC frequency is
+    /// changed if the new frequency is within the usable band of the AD9361.
+    /// Otherwise, the AD9361 is retuned and the DDC frequency is moved to the
+    /// edge of the usable band, so that the DDC ends up at the new frequency.
+    /// When the AD9361 is the input of the spectrometer, only the AD9361 is
+    /// retuned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_json::SpectrometerInput;
+    /// use maia_wasm::ui::sync::{RxFrequencyChange, Tuning};
+    ///
+    /// let tuning = Tuning {
+    ///     spectrometer_input: SpectrometerInput::DDC,
+    ///     ad9361_sampling_frequency: 10e6,
+    ///     ad9361_rx_lo_frequency: 100e6,
+    ///     ddc_output_sampling_frequency: 1e6,
+    /// };
+    /// // within the AD9361 band: only the DDC is tuned
+    /// let change = RxFrequencyChange::new(&tuning, 102_000_000);
+    /// assert_eq!(change.ad9361_frequency, None);
+    /// assert_eq!(change.ddc_frequency, Some(2e6));
+    /// // outside of the AD9361 band: both are tuned
+    /// let change = RxFrequencyChange::new(&tuning, 120_000_000);
+    /// assert_eq!(change.ad9361_frequency, Some(123_750_000));
+    /// assert_eq!(change.ddc_frequency, Some(-3.75e6));
+    ///
+    /// let tuning = Tuning {
+    ///     spectrometer_input: SpectrometerInput::AD9361,
+    ///     ..tuning
+    /// };
+    /// let change = RxFrequencyChange::new(&tuning, 102_000_000);
+    /// assert_eq!(change.ad9361_frequency, Some(102_000_000));
+    /// assert_eq!(change.ddc_frequency, None);
+    /// ```
+    pub fn new(tuning: &Tuning, frequency: u64) -> RxFrequencyChange {
+        if !matches!(tuning.spectrometer_input, SpectrometerInput::DDC) {
+            return RxFrequencyChange {
+                ad9361_frequency: Some(frequency),
+                ddc_frequency: None,
+            };
+        }
+        let mut ad9361_frequency = None;
+        let mut ddc_frequency = frequency as f64 - tuning.ad9361_rx_lo_frequency;
+        // Assume that 15% of the edges of the AD9361 spectrum is not usable
+        // due to aliasing.
+        const MARGIN: f64 = 0.5 * (1.0 - 0.15);
+        let limit =
+            tuning.ad9361_sampling_frequency * MARGIN - 0.5 * tuning.ddc_output_sampling_frequency;
+        if ddc_frequency.abs() > limit {
+            ddc_frequency = if ddc_frequency < 0.0 { limit } else { -limit }.round();
+            ad9361_frequency =
+                Some(u64::try_from(frequency as i64 - ddc_frequency as i64).unwrap());
+        }
+        RxFrequencyChange {
+            ad9361_frequency,
+            ddc_frequency: Some(ddc_frequency),
+        }
+    }
+}
+
+/// Snaps a frequency at the device input to the channel raster.
+///
+/// The raster applies to the displayed frequency, which takes into account the
+/// `frequency_offset` of an external converter and whether the spectrum is
+/// `inverted` by the converter.
+///
+/// # Examples
+///
+/// ```
+/// use maia_wasm::ui::{raster::ChannelRaster, sync::snap_to_channel_raster};
+///
+/// let raster = ChannelRaster::Khz12_5;
+/// assert_eq!(snap_to_channel_raster(raster, 0.0, false, 446_011_000.0), 446_012_500.0);
+/// // 10 GHz LNB with spectrum inversion
+/// assert_eq!(snap_to_channel_raster(raster, 10e9, true, 1_000_011_000.0), 1_000_012_500.0);
+/// ```
+pub fn snap_to_channel_raster(
+    raster: ChannelRaster,
+    frequency_offset: f64,
+    inverted: bool,
+    frequency: f64,
+) -> f64 {
+    if raster.spacing().is_none() {
+        return frequency;
+    }
+    if inverted {
+        frequency_offset - raster.snap(frequency_offset - frequency)
+    } else {
+        raster.snap(frequency_offset + frequency) - frequency_offset
+    }
+}
+
+/// Returns the gain mode selected by the AGC toggle.
+///
+/// The toggle switches between the manual gain and the last AGC mode that was
+/// used.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::Ad9361GainMode;
+/// use maia_wasm::ui::sync::agc_toggle_mode;
+///
+/// let last_agc = Ad9361GainMode::FastAttack;
+/// assert_eq!(agc_toggle_mode(Ad9361GainMode::Manual, last_agc), last_agc);
+/// assert_eq!(agc_toggle_mode(last_agc, last_agc), Ad9361GainMode::Manual);
+/// ```
+pub fn agc_toggle_mode(current: Ad9361GainMode, last_agc: Ad9361GainMode) -> Ad9361GainMode {
+    match current {
+        Ad9361GainMode::Manual => last_agc,
+        _ => Ad9361GainMode::Manual,
+    }
+}
+
+/// Completes a spectrometer PATCH request made by the user.
+///
+/// When the spectrometer input is changed, the sample rate of the input
+/// usually changes. The current output sampling frequency of the spectrometer
+/// is added to the patch, so that it is maintained across the change.
+///
+/// # Examples
+///
+/// ```
+/// use maia_json::{Hertz, PatchSpectrometer, SpectrometerInput};
+/// use maia_wasm::ui::sync::complete_spectrometer_patch;
+///
+/// let mut patch = PatchSpectrometer {
+///     input: Some(SpectrometerInput::DDC),
+///     ..Default::default()
+/// };
+/// complete_spectrometer_patch(&mut patch, Some(Hertz(20.0)));
+/// assert_eq!(patch.output_sampling_frequency, Some(Hertz(20.0)));
+/// ```
+pub fn complete_spectrometer_patch(
+    patch: &mut PatchSpectrometer,
+    output_sampling_frequency: Option<Hertz>,
+) {
+    if patch.input.is_some() && output_sampling_frequency.is_some() {
+        patch.output_sampling_frequency = output_sampling_frequency;
+    }
+}
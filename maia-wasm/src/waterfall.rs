@@ -3,11 +3,15 @@
 //! This module contains the implementation of a WebGL2 waterfall using the
 //! render engine contained in [`crate::render`].
 
+use crate::history::History;
+use crate::offline::Spectrogram;
 use crate::render::{
     texture_formats::{R16f, Rgb},
     DrawMode, ProgramSource, RenderEngine, RenderObject, Texture, TextureMagFilter,
     TextureMinFilter, TextureParameter, TextureWrap, Uniform, UniformValue,
 };
+use crate::simd;
+use serde::{Deserialize, Serialize};
 use std::cell::Cell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -31,16 +35,252 @@ pub struct Waterfall {
     last_spectrum_timestamp: Option<f32>,
     waterfall_rate: Option<f32>,
     waterfall_wraps: usize,
+    received: ReceivedStats,
+    // Number of lines received when prepare_render was last called
+    rendered_lines: u64,
+    // Timestamp of the next spectrum line and of each line in the texture
+    next_line_timestamp: Option<f64>,
+    line_timestamps: Box<[Option<f64>]>,
+    // Sequence number of the last spectrum line received
+    last_line_sequence: Option<u64>,
     center_freq: f64,
     samp_rate: f64,
+    frequency_offset: f64,
+    frequency_unit: FrequencyUnit,
+    spectrum_inverted: bool,
     // Auxiliary for frequency axis
     num_freqs: Vec<usize>,
     freq_radixes: Vec<u8>,
     freq_num_idx: Rc<Cell<u32>>,
     freq_num_idx_ticks: Rc<Cell<u32>>,
     zoom_levels: Vec<f32>,
+    // Frequencies of the labels, sorted by depth, and step between the labels
+    // of each depth
+    freqs_labels: Vec<f64>,
+    freq_steps: Vec<f64>,
+    // Unit of the frequency labels, which is chosen once for the whole axis
+    freq_labels_unit: FrequencyUnit,
+    // Deepest depth of the labels that are drawn at the current zoom level,
+    // and depth for which the texts of the labels have been rendered
+    labels_depth: usize,
+    labels_texts_depth: Option<usize>,
     waterfall_min: f32,
     waterfall_max: f32,
+    // Offline spectrogram being shown, or None if the live spectrum is shown
+    offline: Option<OfflineView>,
+    // Long history of the live spectrum
+    history: History,
+    annotations: Vec<Annotation>,
+    annotations_num_idx: Rc<Cell<u32>>,
+    // Line at the bottom of the waterfall when the annotations VAO was built,
+    // or None if the VAO needs to be rebuilt
+    annotations_line: Option<usize>,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct OfflineView {
+    num_lines: usize,
+    // Index of the line shown at the bottom of the waterfall. This is
+    // fractional to accumulate small scrolls.
+    position: f32,
+}
+
+/// Waterfall annotation.
+///
+/// An annotation marks a time and frequency region of the waterfall, which is
+/// drawn as a box. See [`Waterfall::set_annotations`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    /// Start time of the annotation.
+    ///
+    /// The time is given as the number of milliseconds since the UNIX epoch,
+    /// as the timestamps given to [`Waterfall::put_line_timestamp`].
+    pub start_time: f64,
+    /// End time of the annotation.
+    pub end_time: f64,
+    /// Lowest frequency of the annotation (in Hz).
+    pub frequency_min: f64,
+    /// Highest frequency of the annotation (in Hz).
+    pub frequency_max: f64,
+    /// Label of the annotation.
+    pub label: String,
+}
+
+/// Statistics of the spectrum lines received.
+///
+/// This contains the totals of the spectrum lines that have been given to the
+/// waterfall with [`Waterfall::put_waterfall_spectrum`], and of the lines that
+/// have been dropped, as given by [`Waterfall::put_dropped_lines`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ReceivedStats {
+    /// Number of spectrum lines received.
+    pub lines: u64,
+    /// Number of bytes received.
+    pub bytes: u64,
+    /// Number of spectrum lines dropped.
+    pub dropped_lines: u64,
+}
+
+/// Formats the timestamp of a spectrum line.
+///
+/// The timestamp is given as the number of milliseconds since the UNIX epoch,
+/// as in [`Waterfall::put_line_timestamp`]. It is formatted as a UTC time of
+/// the form `HH:MM:SS.s UTC`.
+pub fn format_line_timestamp(timestamp: f64) -> String {
+    let date = String::from(js_sys::Date::new(&timestamp.into()).to_iso_string());
+    format!("{} UTC", &date[11..21])
+}
+
+/// Frequency unit.
+///
+/// This enum lists the units that can be used in the frequency labels of the
+/// waterfall. The number of decimals of the labels is chosen according to the
+/// spacing between labels, so that the labels are as short as possible.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum FrequencyUnit {
+    /// Hertz.
+    Hz,
+    /// Kilohertz.
+    KHz,
+    /// Megahertz.
+    #[default]
+    MHz,
+    /// Gigahertz.
+    GHz,
+    /// Automatic unit.
+    ///
+    /// The SI unit is chosen once for the whole frequency axis according to
+    /// the magnitude of the frequencies that it shows, and the unit symbol is
+    /// included in the labels.
+    Auto,
+}
+
+impl FrequencyUnit {
+    /// Returns the value of the unit in Hz.
+    ///
+    /// [`FrequencyUnit::Auto`] does not have a fixed value, so its scale is 1.
+    /// [`FrequencyUnit::resolve`] can be used to obtain the unit that it uses
+    /// for a particular frequency.
+    pub fn scale(&self) -> f64 {
+        match self {
+            FrequencyUnit::Hz | FrequencyUnit::Auto => 1.0,
+            FrequencyUnit::KHz => 1e3,
+            FrequencyUnit::MHz => 1e6,
+            FrequencyUnit::GHz => 1e9,
+        }
+    }
+
+    /// Returns the unit used to show a frequency given in Hz.
+    ///
+    /// For [`FrequencyUnit::Auto`] this is the largest unit that is not larger
+    /// than the frequency. For the other units this is the unit itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::waterfall::FrequencyUnit;
+    ///
+    /// assert_eq!(FrequencyUnit::Auto.resolve(10.4e9), FrequencyUnit::GHz);
+    /// assert_eq!(FrequencyUnit::Auto.resolve(-250e3), FrequencyUnit::KHz);
+    /// assert_eq!(FrequencyUnit::Auto.resolve(0.0), FrequencyUnit::Hz);
+    /// assert_eq!(FrequencyUnit::MHz.resolve(10.4e9), FrequencyUnit::MHz);
+    /// ```
+    pub fn resolve(&self, frequency: f64) -> FrequencyUnit {
+        if !matches!(self, FrequencyUnit::Auto) {
+            return *self;
+        }
+        [FrequencyUnit::GHz, FrequencyUnit::MHz, FrequencyUnit::KHz]
+            .into_iter()
+            .find(|unit| frequency.abs() >= unit.scale())
+            .unwrap_or(FrequencyUnit::Hz)
+    }
+
+    /// Returns the number of decimals needed to show frequencies with a
+    /// resolution given in Hz using this unit.
+    ///
+    /// This is the smallest number of decimals that can show the multiples of
+    /// the resolution exactly, up to a maximum of 9 decimals.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::waterfall::FrequencyUnit;
+    ///
+    /// assert_eq!(FrequencyUnit::MHz.decimals(1e6), 0);
+    /// assert_eq!(FrequencyUnit::MHz.decimals(200e3), 1);
+    /// assert_eq!(FrequencyUnit::MHz.decimals(25e3), 3);
+    /// assert_eq!(FrequencyUnit::KHz.decimals(25e3), 0);
+    /// ```
+    pub fn decimals(&self, resolution: f64) -> usize {
+        const MAX_DECIMALS: usize = 9;
+        let mut value = resolution / self.scale();
+        for decimals in 0..MAX_DECIMALS {
+            // The tolerance absorbs the rounding errors of the scaling.
+            if (value - value.round()).abs() <= 1e-6 * value {
+                return decimals;
+            }
+            value *= 10.0;
+        }
+        MAX_DECIMALS
+    }
+
+    /// Formats a frequency given in Hz using this unit.
+    ///
+    /// The number of decimals is the smallest that can show frequencies with
+    /// the `resolution` given in Hz. The unit symbol is only included in the
+    /// output for [`FrequencyUnit::Auto`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use maia_wasm::waterfall::FrequencyUnit;
+    ///
+    /// assert_eq!(FrequencyUnit::MHz.format(10_489_550e3, 1e3), "10489.550");
+    /// assert_eq!(FrequencyUnit::MHz.format(10_489_500e3, 100e3), "10489.5");
+    /// assert_eq!(FrequencyUnit::GHz.format(10_489_550e3, 1e3), "10.489550");
+    /// assert_eq!(FrequencyUnit::Auto.format(10_489_550e3, 50e3), "10.48955 GHz");
+    /// assert_eq!(FrequencyUnit::Auto.format(-250e3, 50e3), "-250 kHz");
+    /// ```
+    pub fn format(&self, frequency: f64, resolution: f64) -> String {
+        let unit = self.resolve(frequency);
+        let value = format!("{:.*}", unit.decimals(resolution), frequency / unit.scale());
+        if matches!(self, FrequencyUnit::Auto) {
+            format!("{value} {unit}")
+        } else {
+            value
+        }
+    }
+}
+
+impl std::str::FromStr for FrequencyUnit {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<FrequencyUnit, ()> {
+        Ok(match s {
+            "Hz" => FrequencyUnit::Hz,
+            "kHz" => FrequencyUnit::KHz,
+            "MHz" => FrequencyUnit::MHz,
+            "GHz" => FrequencyUnit::GHz,
+            "Auto" => FrequencyUnit::Auto,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl std::fmt::Display for FrequencyUnit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                FrequencyUnit::Hz => "Hz",
+                FrequencyUnit::KHz => "kHz",
+                FrequencyUnit::MHz => "MHz",
+                FrequencyUnit::GHz => "GHz",
+                FrequencyUnit::Auto => "Auto",
+            }
+        )
+    }
 }
 
 #[derive(Default)]
@@ -51,6 +291,10 @@ struct Enables {
     frequency_labels: Rc<Cell<bool>>,
     frequency_ticks: Rc<Cell<bool>>,
     channel: Rc<Cell<bool>>,
+    channel_preview: Rc<Cell<bool>>,
+    measurement: Rc<Cell<bool>>,
+    annotations: Rc<Cell<bool>>,
+    color_scale: Rc<Cell<bool>>,
 }
 
 struct Uniforms {
@@ -68,23 +312,54 @@ struct Uniforms {
     major_ticks_end: Rc<Uniform<i32>>,
     channel_freq: Rc<Uniform<f32>>,
     channel_width: Rc<Uniform<f32>>,
+    channel_transition: Rc<Uniform<f32>>,
+    measurement_freq: Rc<Uniform<f32>>,
+    measurement_width: Rc<Uniform<f32>>,
+    color_scale_labels_width: Rc<Uniform<f32>>,
+    color_scale_labels_height: Rc<Uniform<f32>>,
+    color_scale_step: Rc<Uniform<f32>>,
+}
+
+/// Channel power measurement.
+///
+/// This struct contains the results of the measurement of the power in a
+/// frequency region of the waterfall, as performed by
+/// [`Waterfall::channel_measurement`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChannelMeasurement {
+    /// Total power in the measurement region.
+    ///
+    /// The power is given in dB, using the same units as the waterfall power
+    /// levels.
+    pub power_db: f64,
+    /// Occupied bandwidth.
+    ///
+    /// This gives the width (in Hz) of the smallest group of contiguous bins
+    /// that contains 99% of the power in the measurement region, excluding
+    /// 0.5% of the power at each edge.
+    pub occupied_bandwidth: f64,
 }
 
 struct Textures {
     waterfall: Rc<WebGlTexture>,
     colormap: Rc<WebGlTexture>,
     text: Rc<WebGlTexture>,
+    color_scale_text: Rc<WebGlTexture>,
 }
 
 struct Programs {
     frequency_labels: Rc<WebGlProgram>,
     frequency_ticks: Rc<WebGlProgram>,
+    annotations: Rc<WebGlProgram>,
+    color_scale_labels: Rc<WebGlProgram>,
 }
 
 #[derive(Default)]
 struct VAOs {
     frequency_labels: Option<Rc<WebGlVertexArrayObject>>,
     frequency_ticks: Option<Rc<WebGlVertexArrayObject>>,
+    annotations: Option<Rc<WebGlVertexArrayObject>>,
+    color_scale_labels: Option<Rc<WebGlVertexArrayObject>>,
 }
 
 impl Waterfall {
@@ -97,7 +372,14 @@ impl Waterfall {
     const TEXTURE_WIDTH: usize = 4096;
     const TEXTURE_HEIGHT: usize = 512;
 
-    const SPECTRUM_POINTS: usize = Self::TEXTURE_WIDTH;
+    /// Maximum number of spectrum lines that the waterfall can hold.
+    pub const MAX_LINES: usize = Self::TEXTURE_HEIGHT;
+
+    /// Number of points of each spectrum line.
+    ///
+    /// This is the length of the spectrum lines given to
+    /// [`Waterfall::put_waterfall_spectrum`].
+    pub const SPECTRUM_POINTS: usize = Self::TEXTURE_WIDTH;
 
     // horizontal divisions are spaced by 1 dB; these cover a 200 dB range,
     // which is more than enough
@@ -106,6 +388,30 @@ impl Waterfall {
     // waterfall brightness when spectrum is visible
     const WATERFALL_BRIGHTNESS_WITH_SPECTRUM: f32 = 0.7;
 
+    // vertical position of the bottom of the frequency labels, in screen
+    // coordinates
+    const FREQUENCY_LABELS_Y: f32 = -0.96;
+    // height of the frequency labels text, in pixels
+    const FREQUENCY_LABELS_TEXT_HEIGHT_PX: u32 = 16;
+    // ratio between the width of the bounding box of the frequency labels
+    // and the width of their text
+    const FREQUENCY_LABELS_MARGIN_FACTOR: f32 = 1.1;
+
+    // vertical extent of the color scale, in screen coordinates
+    const COLOR_SCALE_BOTTOM: f32 = -0.7;
+    const COLOR_SCALE_TOP: f32 = 0.7;
+    // width of the color scale bar, in CSS pixels
+    const COLOR_SCALE_WIDTH_PX: f32 = 12.0;
+    // distance from the color scale bar to the right edge of the canvas, in
+    // CSS pixels
+    const COLOR_SCALE_MARGIN_PX: f32 = 8.0;
+    // distance from the color scale labels to the bar, in CSS pixels
+    const COLOR_SCALE_LABELS_GAP_PX: f32 = 4.0;
+    const COLOR_SCALE_TEXT_HEIGHT_PX: u32 = 12;
+    // the color scale has labels for each integer dB value between 0 and this
+    // value
+    const COLOR_SCALE_MAX_LABEL: u16 = 200;
+
     /// Creates a new waterfall, adding it to the [`RenderEngine`].
     ///
     /// The `performance` parameter should contain a performance object obtained
@@ -114,6 +420,8 @@ impl Waterfall {
         let programs = Programs {
             frequency_labels: Self::frequency_labels_program(engine)?,
             frequency_ticks: Self::frequency_ticks_program(engine)?,
+            annotations: Self::annotations_program(engine)?,
+            color_scale_labels: Self::color_scale_labels_program(engine)?,
         };
         // These default values will be overwritten by the UI
         let samp_rate = 30.72e6;
@@ -131,15 +439,33 @@ impl Waterfall {
             waterfall_wraps: 0,
             last_spectrum_timestamp: None,
             waterfall_rate: None,
+            received: ReceivedStats::default(),
+            rendered_lines: 0,
+            next_line_timestamp: None,
+            line_timestamps: vec![None; Self::TEXTURE_HEIGHT].into_boxed_slice(),
+            last_line_sequence: None,
             center_freq,
             samp_rate,
+            frequency_offset: 0.0,
+            frequency_unit: FrequencyUnit::default(),
+            spectrum_inverted: false,
             num_freqs: Vec::new(),
             freq_radixes: Vec::new(),
             zoom_levels: Vec::new(),
+            freqs_labels: Vec::new(),
+            freq_steps: Vec::new(),
+            freq_labels_unit: FrequencyUnit::default(),
+            labels_depth: 0,
+            labels_texts_depth: None,
             freq_num_idx: Rc::new(Cell::new(0)),
             freq_num_idx_ticks: Rc::new(Cell::new(0)),
             waterfall_min: 35.0,
             waterfall_max: 85.0,
+            offline: None,
+            history: History::new(),
+            annotations: Vec::new(),
+            annotations_num_idx: Rc::new(Cell::new(0)),
+            annotations_line: None,
         };
 
         w.update_canvas_size(engine);
@@ -156,14 +482,24 @@ impl Waterfall {
         engine.add_object(spectrum_object);
         let channel_object = w.channel_object(engine)?;
         engine.add_object(channel_object);
+        let channel_preview_object = w.channel_preview_object(engine)?;
+        engine.add_object(channel_preview_object);
+        let measurement_object = w.measurement_object(engine)?;
+        engine.add_object(measurement_object);
+        let annotations_object = w.annotations_object(engine)?;
+        engine.add_object(annotations_object);
         let (frequency_labels_object, frequency_ticks_object) =
             w.frequency_labels_object(engine)?;
         engine.add_object(frequency_labels_object);
         engine.add_object(frequency_ticks_object);
+        let (color_scale_object, color_scale_labels_object) = w.color_scale_object(engine)?;
+        engine.add_object(color_scale_object);
+        engine.add_object(color_scale_labels_object);
 
         w.enables.waterfall.set(true);
         w.enables.frequency_labels.set(true);
         w.enables.frequency_ticks.set(true);
+        w.enables.annotations.set(true);
 
         Ok(w)
     }
@@ -172,22 +508,272 @@ impl Waterfall {
     ///
     /// This function updates the waterfall by adding a new spectrum line to
     /// it. The spectrum is given in linear power units.
+    ///
+    /// The spectrum is also added to the history of the waterfall (see
+    /// [`Waterfall::show_history`]). Besides this, the spectrum is ignored
+    /// while the waterfall shows an offline spectrogram (see
+    /// [`Waterfall::show_offline`]).
     pub fn put_waterfall_spectrum(&mut self, spectrum_linear: &js_sys::Float32Array) {
+        if self.offline.is_some() {
+            self.history
+                .push(&spectrum_linear.to_vec(), self.next_line_timestamp.take());
+            return;
+        }
         self.last_spectrum_timestamp = Some(self.performance.now() as f32);
+        self.received.lines += 1;
+        self.received.bytes += u64::from(spectrum_linear.byte_length());
         self.current_draw_line = (self.current_draw_line + 1) % Self::TEXTURE_HEIGHT;
         let line = self.current_draw_line;
+        self.line_timestamps[line] = self.next_line_timestamp.take();
         let spectrum_texture =
             &mut self.texture_map[line * Self::TEXTURE_WIDTH..(line + 1) * Self::TEXTURE_WIDTH];
         spectrum_linear.copy_to(spectrum_texture);
+        self.history
+            .push(spectrum_texture, self.line_timestamps[line]);
+        Self::linear_to_db(spectrum_texture, self.spectrum_inverted);
+    }
+
+    // Converts a spectrum line in linear power units in the texture map to the
+    // units used by the texture, reversing it if the spectrum is inverted.
+    fn linear_to_db(spectrum_texture: &mut [f32], inverted: bool) {
+        if inverted {
+            spectrum_texture.reverse();
+        }
         // Convert to "dB". We don't include the 10.0 factor to save us a multiplication.
         // This will later be taken into account in the shader.
-        for x in spectrum_texture.iter_mut() {
-            // do not compute the log10 of pixels that are zero to avoid the
-            // shader from having to handle -infty
-            if *x != 0.0 {
-                *x = x.log10();
+        simd::log10_in_place(spectrum_texture);
+    }
+
+    /// Shows the spectrogram of an IQ file in the waterfall.
+    ///
+    /// This replaces the contents of the waterfall by the `spectrogram`, with
+    /// its last line at the bottom of the waterfall, and stops the live
+    /// spectrum from being displayed until [`Waterfall::resume_live`] is
+    /// called. The center frequency and sample rate of the file are given in
+    /// Hz and samples per second. If `start_time` is not `None`, it is used to
+    /// compute the timestamps of the lines. It is given as the number of
+    /// milliseconds since the UNIX epoch.
+    ///
+    /// Only the last [`Waterfall::MAX_LINES`] lines of the spectrogram are
+    /// shown. The spectrogram can be scrolled with
+    /// [`Waterfall::scroll_offline`].
+    pub fn show_offline(
+        &mut self,
+        spectrogram: &Spectrogram,
+        center_freq: f64,
+        samp_rate: f64,
+        start_time: Option<f64>,
+        engine: &mut RenderEngine,
+    ) -> Result<(), JsValue> {
+        let skip = spectrogram.num_lines().saturating_sub(Self::TEXTURE_HEIGHT);
+        let lines = (skip..spectrogram.num_lines()).map(|n| {
+            (
+                spectrogram.line(n),
+                start_time.map(|t0| t0 + 1e3 * spectrogram.line_start(n) as f64 / samp_rate),
+            )
+        });
+        let num_lines = Self::fill_texture(
+            &mut self.texture_map,
+            &mut self.line_timestamps,
+            lines,
+            self.spectrum_inverted,
+        );
+        self.start_offline(num_lines, center_freq, samp_rate, engine)
+    }
+
+    /// Shows the history of the live spectrum in the waterfall.
+    ///
+    /// The waterfall keeps a history of the live spectrum which spans a much
+    /// longer time than the waterfall itself. This is stored with several
+    /// levels of decimation in time and frequency (see [`crate::history`]).
+    /// This function replaces the contents of the waterfall by one `level` of
+    /// the history, with its most recent line at the bottom of the waterfall,
+    /// in the same way as [`Waterfall::show_offline`]. Therefore, the history
+    /// can be scrolled with [`Waterfall::scroll_offline`], and the live
+    /// spectrum is shown again by calling [`Waterfall::resume_live`].
+    ///
+    /// The history keeps being updated while it is shown, but the lines added
+    /// to it are only shown the next time that this function is called.
+    pub fn show_history(&mut self, level: usize, engine: &mut RenderEngine) -> Result<(), JsValue> {
+        let history = &self.history;
+        let lines = (0..history.num_lines(level))
+            .map(|n| (history.line(level, n), history.line_timestamp(level, n)));
+        let num_lines = Self::fill_texture(
+            &mut self.texture_map,
+            &mut self.line_timestamps,
+            lines,
+            self.spectrum_inverted,
+        );
+        let center_freq = history.center_freq();
+        let samp_rate = history.samp_rate();
+        self.start_offline(num_lines, center_freq, samp_rate, engine)
+    }
+
+    /// Returns the history of the live spectrum.
+    ///
+    /// See [`Waterfall::show_history`].
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    // Fills the texture map with spectrum lines in linear power units and
+    // their timestamps. The lines are expanded to the texture width if they
+    // have fewer frequency bins. Returns the number of lines written.
+    fn fill_texture<'a>(
+        texture_map: &mut [f32],
+        line_timestamps: &mut [Option<f64>],
+        lines: impl Iterator<Item = (&'a [f32], Option<f64>)>,
+        inverted: bool,
+    ) -> usize {
+        texture_map.fill(0.0);
+        line_timestamps.fill(None);
+        let mut num_lines = 0;
+        for ((texture_line, texture_timestamp), (line, timestamp)) in texture_map
+            .chunks_exact_mut(Self::TEXTURE_WIDTH)
+            .zip(line_timestamps.iter_mut())
+            .zip(lines)
+        {
+            let bins = Self::TEXTURE_WIDTH / line.len();
+            for (y, &x) in texture_line.chunks_exact_mut(bins).zip(line.iter()) {
+                y.fill(x);
+            }
+            Self::linear_to_db(texture_line, inverted);
+            *texture_timestamp = timestamp;
+            num_lines += 1;
+        }
+        num_lines
+    }
+
+    // Starts showing the offline spectrogram that has been written to the
+    // texture map.
+    fn start_offline(
+        &mut self,
+        num_lines: usize,
+        center_freq: f64,
+        samp_rate: f64,
+        engine: &mut RenderEngine,
+    ) -> Result<(), JsValue> {
+        self.current_draw_line = num_lines.saturating_sub(1);
+        self.offline = Some(OfflineView {
+            num_lines,
+            position: self.current_draw_line as f32,
+        });
+        self.last_draw_line = self.current_draw_line;
+        self.last_spectrum_timestamp = None;
+        self.annotations_line = None;
+        self.load_waterfall(engine)?;
+        self.update_freq_samprate(center_freq, samp_rate, engine)
+    }
+
+    /// Returns `true` if the waterfall shows an offline spectrogram.
+    ///
+    /// See [`Waterfall::show_offline`].
+    pub fn is_offline(&self) -> bool {
+        self.offline.is_some()
+    }
+
+    /// Scrolls the offline spectrogram.
+    ///
+    /// The spectrogram is moved upwards by `dy`, which is given in screen
+    /// coordinates, in which the height of the canvas is 2.0. Positive values
+    /// scroll towards the end of the file. The scroll is limited to the lines
+    /// of the spectrogram. This does nothing if the waterfall does not show an
+    /// offline spectrogram.
+    pub fn scroll_offline(&mut self, dy: f32) {
+        let Some(offline) = self.offline.as_mut() else {
+            return;
+        };
+        // The waterfall texture spans 4 units in screen coordinates.
+        let lines = dy * Self::TEXTURE_HEIGHT as f32 / 4.0;
+        let last = offline.num_lines.saturating_sub(1);
+        let first = last.min(Self::TEXTURE_HEIGHT / 2 - 1);
+        offline.position = (offline.position + lines).clamp(first as f32, last as f32);
+        self.current_draw_line = offline.position.round() as usize;
+    }
+
+    /// Stops showing an offline spectrogram and resumes the live spectrum.
+    ///
+    /// The waterfall is cleared. The center frequency and sample rate of the
+    /// live spectrum should be set again with
+    /// [`Waterfall::set_freq_samprate`].
+    pub fn resume_live(&mut self, engine: &mut RenderEngine) -> Result<(), JsValue> {
+        if self.offline.take().is_none() {
+            return Ok(());
+        }
+        self.texture_map.fill(0.0);
+        self.line_timestamps.fill(None);
+        self.current_draw_line = Self::TEXTURE_HEIGHT - 1;
+        self.last_draw_line = self.current_draw_line;
+        self.annotations_line = None;
+        self.load_waterfall(engine)
+    }
+
+    /// Adds spectrum lines that have been dropped.
+    ///
+    /// This function is used to count the spectrum lines that the server could
+    /// not send to this client, in order to report them in the
+    /// [`ReceivedStats`].
+    pub fn put_dropped_lines(&mut self, dropped_lines: u64) {
+        self.received.dropped_lines += dropped_lines;
+    }
+
+    /// Sets the timestamp of the next spectrum line.
+    ///
+    /// The server gives a timestamp for each spectrum line in the frame header
+    /// of the v2 WebSocket protocol, or, with the v1 protocol, only when the
+    /// spectrometer integrations are aligned to the wall clock. The timestamp
+    /// is the start time of the integration, given as the number of
+    /// milliseconds since the UNIX epoch. It applies to the next call to
+    /// [`Waterfall::put_waterfall_spectrum`].
+    pub fn put_line_timestamp(&mut self, timestamp: f64) {
+        self.next_line_timestamp = Some(timestamp);
+    }
+
+    /// Sets the sequence number of the next spectrum line.
+    ///
+    /// The sequence numbers are given by the server in the frame header of the
+    /// v2 WebSocket protocol. Gaps in the sequence numbers are counted as
+    /// dropped lines in the [`ReceivedStats`].
+    pub fn put_line_sequence(&mut self, sequence: u64) {
+        if let Some(last) = self.last_line_sequence {
+            if sequence > last {
+                self.received.dropped_lines += sequence - last - 1;
             }
         }
+        self.last_line_sequence = Some(sequence);
+    }
+
+    /// Returns the timestamp of the last spectrum line received.
+    ///
+    /// This is `None` if the last line was not preceded by a call to
+    /// [`Waterfall::put_line_timestamp`].
+    pub fn last_line_timestamp(&self) -> Option<f64> {
+        self.line_timestamps[self.current_draw_line]
+    }
+
+    /// Returns the timestamp of the spectrum line at a vertical position.
+    ///
+    /// The position `y` is given in screen coordinates, in which the canvas
+    /// spans from -1 (bottom) to 1 (top). This returns `None` if there is no
+    /// line with a timestamp at this position.
+    pub fn line_timestamp_at(&self, y: f32) -> Option<f64> {
+        // The waterfall texture spans 4 units in screen coordinates, with the
+        // most recent line at the bottom of the canvas.
+        let age = ((y + 1.0) * Self::TEXTURE_HEIGHT as f32 / 4.0).round();
+        if !(0.0..Self::TEXTURE_HEIGHT as f32).contains(&age) {
+            return None;
+        }
+        let line =
+            (self.current_draw_line + Self::TEXTURE_HEIGHT - age as usize) % Self::TEXTURE_HEIGHT;
+        self.line_timestamps[line]
+    }
+
+    /// Returns the statistics of the spectrum lines received.
+    ///
+    /// These are the totals since the waterfall was created. They can be used
+    /// to compute the achieved line rate and throughput.
+    pub fn received_stats(&self) -> ReceivedStats {
+        self.received
     }
 
     /// Updates the waterfall for rendering.
@@ -216,6 +802,31 @@ impl Waterfall {
         // TODO use elapsed_ms to effect draw_t. This needs us to know the spectrometer rate.
         self.uniforms.time_translation.set_data(4.0 * draw_t);
 
+        if self.annotations_line != Some(self.current_draw_line) {
+            self.annotations_vao(engine)?;
+        }
+
+        if self.labels_texts_depth != Some(self.labels_depth) {
+            self.frequency_labels_texts_vao(engine)?;
+        }
+
+        if self.offline.is_some() {
+            // The offline spectrogram is loaded in full by show_offline
+            self.last_draw_line = self.current_draw_line;
+            return Ok(());
+        }
+
+        let new_lines = self.received.lines - self.rendered_lines;
+        self.rendered_lines = self.received.lines;
+        if new_lines >= Self::TEXTURE_HEIGHT as u64 {
+            // All the lines in the texture have been replaced since the last
+            // render. This happens when rendering is paused, for instance
+            // because the page is hidden.
+            self.load_waterfall(engine)?;
+            self.last_draw_line = self.current_draw_line;
+            return Ok(());
+        }
+
         let end_draw = self.current_draw_line;
         let start_draw = if end_draw < self.last_draw_line {
             // wraps around
@@ -262,6 +873,7 @@ impl Waterfall {
     pub fn resize_canvas(&mut self, engine: &mut RenderEngine) -> Result<(), JsValue> {
         // update frequency labels VAOs and texts texture
         self.frequency_labels_vao(engine)?;
+        self.color_scale_labels_vao(engine)?;
         self.update_canvas_size(engine);
         Ok(())
     }
@@ -276,23 +888,156 @@ impl Waterfall {
     /// Updates the waterfall with a new center frequency and sample rate.
     ///
     /// The center frequency and sample rate should be given in units of Hz and
-    /// samples per second.
+    /// samples per second. The change is ignored while the waterfall shows an
+    /// offline spectrogram (see [`Waterfall::show_offline`]). If the center
+    /// frequency or sample rate change, the history of the live spectrum is
+    /// cleared.
     pub fn set_freq_samprate(
         &mut self,
         center_freq: f64,
         samp_rate: f64,
         engine: &mut RenderEngine,
+    ) -> Result<(), JsValue> {
+        self.history.set_freq_samprate(center_freq, samp_rate);
+        if self.offline.is_some() {
+            return Ok(());
+        }
+        self.update_freq_samprate(center_freq, samp_rate, engine)
+    }
+
+    fn update_freq_samprate(
+        &mut self,
+        center_freq: f64,
+        samp_rate: f64,
+        engine: &mut RenderEngine,
     ) -> Result<(), JsValue> {
         let center_freq = Self::actual_center_freq(center_freq, samp_rate);
         if center_freq != self.center_freq || samp_rate != self.samp_rate {
             self.center_freq = center_freq;
             self.samp_rate = samp_rate;
+            self.annotations_line = None;
             // update frequency labels VAOs and texts texture
             self.frequency_labels_vao(engine)?;
         }
         Ok(())
     }
 
+    /// Returns the frequency offset of the frequency labels.
+    ///
+    /// See [`Waterfall::set_frequency_offset`].
+    pub fn frequency_offset(&self) -> f64 {
+        self.frequency_offset
+    }
+
+    /// Sets the frequency offset of the frequency labels.
+    ///
+    /// The offset, in Hz, is added to the frequencies shown in the labels of
+    /// the frequency axis (see [`Waterfall::display_frequency`]). This is used
+    /// to show the frequencies at the input of an external frequency
+    /// converter. The center frequency given in
+    /// [`Waterfall::set_freq_samprate`] is not affected by the offset.
+    pub fn set_frequency_offset(
+        &mut self,
+        offset: f64,
+        engine: &mut RenderEngine,
+    ) -> Result<(), JsValue> {
+        if offset != self.frequency_offset {
+            self.frequency_offset = offset;
+            self.frequency_labels_vao(engine)?;
+        }
+        Ok(())
+    }
+
+    /// Returns whether the spectrum is inverted.
+    ///
+    /// See [`Waterfall::set_spectrum_inverted`].
+    pub fn is_spectrum_inverted(&self) -> bool {
+        self.spectrum_inverted
+    }
+
+    /// Sets whether the spectrum is inverted.
+    ///
+    /// When the spectrum is inverted, the frequency axis is flipped, so that
+    /// the frequencies at the device input decrease from left to right. This
+    /// is used with external frequency converters whose local oscillator is
+    /// above the signal frequency, which invert the spectrum. The frequency
+    /// labels then show the frequency offset minus the frequency at the device
+    /// input.
+    ///
+    /// The spectrum lines already in the waterfall, the DDC channel and the
+    /// measurement region are flipped, and the view is mirrored, so that the
+    /// same signals remain visible.
+    ///
+    /// By default the spectrum is not inverted.
+    pub fn set_spectrum_inverted(
+        &mut self,
+        inverted: bool,
+        engine: &mut RenderEngine,
+    ) -> Result<(), JsValue> {
+        if inverted == self.spectrum_inverted {
+            return Ok(());
+        }
+        self.spectrum_inverted = inverted;
+        for line in self.texture_map.chunks_exact_mut(Self::TEXTURE_WIDTH) {
+            line.reverse();
+        }
+        self.load_waterfall(engine)?;
+        self.uniforms
+            .channel_freq
+            .set_data(-self.uniforms.channel_freq.get_data());
+        if let Some((start, end)) = self.get_measurement_region() {
+            self.set_measurement_region(Some((-end, -start)));
+        }
+        self.set_center_frequency(-self.get_center_frequency());
+        self.annotations_line = None;
+        self.frequency_labels_vao(engine)?;
+        Ok(())
+    }
+
+    /// Converts a frequency at the device input to a displayed frequency.
+    ///
+    /// The displayed frequency is the frequency shown in the frequency labels,
+    /// which takes into account the frequency offset and the spectrum
+    /// inversion. Frequencies are given in Hz.
+    pub fn display_frequency(&self, frequency: f64) -> f64 {
+        if self.spectrum_inverted {
+            self.frequency_offset - frequency
+        } else {
+            self.frequency_offset + frequency
+        }
+    }
+
+    /// Converts a displayed frequency to a frequency at the device input.
+    ///
+    /// This is the inverse of [`Waterfall::display_frequency`].
+    pub fn device_frequency(&self, display_frequency: f64) -> f64 {
+        if self.spectrum_inverted {
+            self.frequency_offset - display_frequency
+        } else {
+            display_frequency - self.frequency_offset
+        }
+    }
+
+    /// Returns the unit of the frequency labels.
+    pub fn frequency_unit(&self) -> FrequencyUnit {
+        self.frequency_unit
+    }
+
+    /// Sets the unit of the frequency labels.
+    ///
+    /// By default the frequency labels are shown in MHz.
+    pub fn set_frequency_unit(
+        &mut self,
+        unit: FrequencyUnit,
+        engine: &mut RenderEngine,
+    ) -> Result<(), JsValue> {
+        if unit != self.frequency_unit {
+            self.frequency_unit = unit;
+            self.frequency_labels_vao(engine)?;
+        }
+        Ok(())
+    }
+
     fn actual_center_freq(center_freq: f64, samp_rate: f64) -> f64 {
         // Take note that the actual center_frequency in the waterfall is not
         // baseband DC, but rather the frequency between the DC FFT bin and one
@@ -351,6 +1096,21 @@ impl Waterfall {
         });
     }
 
+    /// Returns whether the color scale is visible.
+    pub fn is_color_scale_visible(&self) -> bool {
+        self.enables.color_scale.get()
+    }
+
+    /// Sets whether the color scale is visible.
+    ///
+    /// The color scale is a bar drawn at the right side of the waterfall that
+    /// shows the colors of the colormap together with labels giving the
+    /// corresponding power values in dB. By default the color scale is not
+    /// visible.
+    pub fn set_color_scale_visible(&self, visible: bool) {
+        self.enables.color_scale.set(visible);
+    }
+
     /// Returns whether the DDC channel is visible in the waterfall.
     pub fn is_channel_visible(&self) -> bool {
         self.enables.channel.get()
@@ -363,12 +1123,39 @@ impl Waterfall {
         self.enables.channel.set(visible);
     }
 
+    /// Returns whether the preview of the DDC filter is visible in the
+    /// waterfall.
+    pub fn is_channel_preview_visible(&self) -> bool {
+        self.enables.channel_preview.get()
+    }
+
+    /// Sets whether the preview of the DDC filter is visible in the waterfall.
+    ///
+    /// The preview shows the predicted passband of the DDC output at the DDC
+    /// channel, and shades the transition bands of the filter, where the
+    /// signals are attenuated. By default the preview is not visible.
+    pub fn set_channel_preview_visible(&self, visible: bool) {
+        self.enables.channel_preview.set(visible);
+    }
+
+    /// Sets the transition bandwidth of the DDC channel in the waterfall.
+    ///
+    /// This function shall be called when the DDC configuration changes. The
+    /// `transition_bandwidth` is the fraction of the DDC output bandwidth used
+    /// as transition bands, as in [`maia_json::DDCConfig`]. It is used by the
+    /// preview of the DDC filter.
+    pub fn set_channel_transition_bandwidth(&mut self, transition_bandwidth: f64) {
+        self.uniforms
+            .channel_transition
+            .set_data(transition_bandwidth.clamp(0.0, 1.0) as f32);
+    }
+
     /// Returns the frequency of the DDC channel in the waterfall.
     ///
     /// The frequency is given in Hz of offset with respect to the waterfall
     /// center frequency.
     pub fn get_channel_frequency(&self) -> f64 {
-        0.5 * self.uniforms.channel_freq.get_data() as f64 * self.samp_rate
+        0.5 * self.uniforms.channel_freq.get_data() as f64 * self.samp_rate * self.frequency_sign()
     }
 
     /// Sets the frequency of the DDC channel in the waterfall.
@@ -381,10 +1168,20 @@ impl Waterfall {
     /// `[Self::set_freq_samprate]`.
     pub fn set_channel_frequency(&mut self, frequency: f64) {
         // The range for frequency is [-1, 1], so we need to multiply by 2.
-        let frequency = 2.0 * frequency / self.samp_rate;
+        let frequency = 2.0 * frequency / self.samp_rate * self.frequency_sign();
         self.uniforms.channel_freq.set_data(frequency as f32);
     }
 
+    // Sign of the change of the frequency at the device input when moving
+    // from left to right in the waterfall.
+    fn frequency_sign(&self) -> f64 {
+        if self.spectrum_inverted {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
     /// Sets the decimation factor of the DDC channel in the waterfall.
     ///
     /// This function shall be called when the DDC decimation factor changes.
@@ -394,6 +1191,149 @@ impl Waterfall {
             .set_data(f64::from(decimation).recip() as f32);
     }
 
+    /// Returns the frequency region used for the channel power measurement.
+    ///
+    /// The region is given as a pair of frequencies with a value between -1
+    /// and 1, as in [`set_center_frequency`](Waterfall::set_center_frequency).
+    /// If there is no measurement region, `None` is returned.
+    pub fn get_measurement_region(&self) -> Option<(f32, f32)> {
+        if !self.enables.measurement.get() {
+            return None;
+        }
+        let freq = self.uniforms.measurement_freq.get_data();
+        let width = self.uniforms.measurement_width.get_data();
+        Some((freq - width, freq + width))
+    }
+
+    /// Sets the frequency region used for the channel power measurement.
+    ///
+    /// The region is given as a pair of frequencies with a value between -1
+    /// and 1, as in [`set_center_frequency`](Waterfall::set_center_frequency).
+    /// The region is highlighted in the waterfall. If `region` is `None`, the
+    /// measurement is disabled.
+    pub fn set_measurement_region(&mut self, region: Option<(f32, f32)>) {
+        let Some((start, end)) = region else {
+            self.enables.measurement.set(false);
+            return;
+        };
+        let (start, end) = (start.min(end), start.max(end));
+        self.uniforms.measurement_freq.set_data(0.5 * (start + end));
+        self.uniforms
+            .measurement_width
+            .set_data(0.5 * (end - start));
+        self.enables.measurement.set(true);
+    }
+
+    /// Measures the power in the measurement region.
+    ///
+    /// The channel power and occupied bandwidth are computed using the latest
+    /// spectrum line that has been added to the waterfall. If there is no
+    /// measurement region, or if the region does not contain any spectrum bins,
+    /// `None` is returned.
+    pub fn channel_measurement(&self) -> Option<ChannelMeasurement> {
+        let (start, end) = self.get_measurement_region()?;
+        let bins = self.last_spectrum_linear(start, end)?;
+        let total = bins.iter().sum::<f64>();
+        let edge = 0.005 * total;
+        let mut lower = 0;
+        let mut accum = 0.0;
+        for (j, &b) in bins.iter().enumerate() {
+            accum += b;
+            if accum > edge {
+                lower = j;
+                break;
+            }
+        }
+        let mut upper = bins.len() - 1;
+        accum = 0.0;
+        for (j, &b) in bins.iter().enumerate().rev() {
+            accum += b;
+            if accum > edge {
+                upper = j;
+                break;
+            }
+        }
+        let bin_width = self.samp_rate / Self::SPECTRUM_POINTS as f64;
+        Some(ChannelMeasurement {
+            power_db: 10.0 * total.log10(),
+            occupied_bandwidth: (upper.saturating_sub(lower) + 1) as f64 * bin_width,
+        })
+    }
+
+    /// Returns the power of the latest spectrum line at a frequency.
+    ///
+    /// The frequency is given as a value between -1 and 1, as in
+    /// [`set_center_frequency`](Waterfall::set_center_frequency). The power of
+    /// the nearest spectrum bin is returned in dB, in the same units as the
+    /// waterfall levels. If the frequency is outside the spectrum, or if there
+    /// is no spectrum data for this bin, `None` is returned.
+    pub fn spectrum_power_at(&self, frequency: f32) -> Option<f32> {
+        // Bin j of the spectrum is located at the frequency
+        // -1 + 2 * j / (SPECTRUM_POINTS - 1).
+        let bin = ((frequency + 1.0) * 0.5 * (Self::SPECTRUM_POINTS - 1) as f32).round();
+        if !(0.0..Self::SPECTRUM_POINTS as f32).contains(&bin) {
+            return None;
+        }
+        let value = self.texture_map[self.current_draw_line * Self::TEXTURE_WIDTH + bin as usize];
+        // The texture map stores log10 of the power, and 0.0 for bins without
+        // data.
+        (value != 0.0).then_some(10.0 * value)
+    }
+
+    // Returns the bins of the latest spectrum line that lie between the
+    // frequencies start and end (given in screen units), converted to linear
+    // power.
+    fn last_spectrum_linear(&self, start: f32, end: f32) -> Option<Vec<f64>> {
+        // Bin j of the spectrum is located at the frequency
+        // -1 + 2 * j / (SPECTRUM_POINTS - 1).
+        let scale = 0.5 * (Self::SPECTRUM_POINTS - 1) as f32;
+        let first = ((start + 1.0) * scale).ceil().max(0.0) as usize;
+        let last = (((end + 1.0) * scale).floor() as usize).min(Self::SPECTRUM_POINTS - 1);
+        if first > last {
+            return None;
+        }
+        let line = self.current_draw_line;
+        let spectrum =
+            &self.texture_map[line * Self::TEXTURE_WIDTH..(line + 1) * Self::TEXTURE_WIDTH];
+        // The texture map stores log10 of the power
+        Some(
+            spectrum[first..=last]
+                .iter()
+                .map(|&x| 10.0_f64.powf(f64::from(x)))
+                .collect(),
+        )
+    }
+
+    /// Sets the annotations drawn on the waterfall.
+    ///
+    /// Each annotation is drawn as a box that covers the spectrum lines whose
+    /// timestamps are within the time span of the annotation, so only the
+    /// annotations that overlap with lines that have timestamps (see
+    /// [`Waterfall::put_line_timestamp`]) are shown.
+    pub fn set_annotations(&mut self, annotations: Vec<Annotation>) {
+        if annotations != self.annotations {
+            self.annotations = annotations;
+            self.annotations_line = None;
+        }
+    }
+
+    /// Returns the annotation at a position of the waterfall.
+    ///
+    /// The position is given by a frequency `x` with a value between -1 and 1,
+    /// as in [`set_center_frequency`](Waterfall::set_center_frequency), and a
+    /// vertical position `y` in screen coordinates, as in
+    /// [`line_timestamp_at`](Waterfall::line_timestamp_at). If several
+    /// annotations overlap, the last one is returned.
+    pub fn annotation_at(&self, x: f32, y: f32) -> Option<&Annotation> {
+        let time = self.line_timestamp_at(y)?;
+        let (center_freq, samp_rate) = self.get_freq_samprate();
+        let frequency = center_freq + self.frequency_sign() * 0.5 * f64::from(x) * samp_rate;
+        self.annotations.iter().rev().find(|annotation| {
+            (annotation.start_time..=annotation.end_time).contains(&time)
+                && (annotation.frequency_min..=annotation.frequency_max).contains(&frequency)
+        })
+    }
+
     fn waterfall_object(&self, engine: &mut RenderEngine) -> Result<RenderObject, JsValue> {
         let program = Self::waterfall_program(engine)?;
         let vao = self.waterfall_vao(engine, &program)?;
@@ -471,6 +1411,35 @@ impl Waterfall {
         Ok((object_labels, object_ticks))
     }
 
+    fn color_scale_object(
+        &mut self,
+        engine: &mut RenderEngine,
+    ) -> Result<(RenderObject, RenderObject), JsValue> {
+        let program = Self::color_scale_program(engine)?;
+        let vao = self.rectangle_vao(engine, &program)?;
+        let object_bar = RenderObject {
+            enabled: Rc::clone(&self.enables.color_scale),
+            program,
+            vao,
+            draw_mode: DrawMode::Triangles,
+            draw_num_indices: Rc::new(Cell::new(Self::RECTANGLE_NUM_INDICES as u32)),
+            draw_offset_elements: Rc::new(Cell::new(0)),
+            uniforms: self.uniforms.color_scale_uniforms(),
+            textures: self.textures.color_scale_textures(),
+        };
+        let object_labels = RenderObject {
+            enabled: Rc::clone(&self.enables.color_scale),
+            program: Rc::clone(&self.programs.color_scale_labels),
+            vao: self.color_scale_labels_vao(engine)?,
+            draw_mode: DrawMode::Triangles,
+            draw_num_indices: Rc::new(Cell::new(6 * (u32::from(Self::COLOR_SCALE_MAX_LABEL) + 1))),
+            draw_offset_elements: Rc::new(Cell::new(0)),
+            uniforms: self.uniforms.color_scale_labels_uniforms(),
+            textures: self.textures.color_scale_text_textures(),
+        };
+        Ok((object_bar, object_labels))
+    }
+
     fn horizontal_divisions_object(
         &mut self,
         engine: &mut RenderEngine,
@@ -504,6 +1473,50 @@ impl Waterfall {
         })
     }
 
+    fn channel_preview_object(&self, engine: &mut RenderEngine) -> Result<RenderObject, JsValue> {
+        let program = Self::channel_preview_program(engine)?;
+        let vao = self.rectangle_vao(engine, &program)?;
+        Ok(RenderObject {
+            enabled: Rc::clone(&self.enables.channel_preview),
+            program,
+            vao,
+            draw_mode: DrawMode::Triangles,
+            draw_num_indices: Rc::new(Cell::new(Self::RECTANGLE_NUM_INDICES as u32)),
+            draw_offset_elements: Rc::new(Cell::new(0)),
+            uniforms: self.uniforms.channel_preview_uniforms(),
+            textures: Box::new([]),
+        })
+    }
+
+    fn measurement_object(&self, engine: &mut RenderEngine) -> Result<RenderObject, JsValue> {
+        let program = Self::measurement_program(engine)?;
+        let vao = self.rectangle_vao(engine, &program)?;
+        Ok(RenderObject {
+            enabled: Rc::clone(&self.enables.measurement),
+            program,
+            vao,
+            draw_mode: DrawMode::Triangles,
+            draw_num_indices: Rc::new(Cell::new(Self::RECTANGLE_NUM_INDICES as u32)),
+            draw_offset_elements: Rc::new(Cell::new(0)),
+            uniforms: self.uniforms.measurement_uniforms(),
+            textures: Box::new([]),
+        })
+    }
+
+    fn annotations_object(&mut self, engine: &mut RenderEngine) -> Result<RenderObject, JsValue> {
+        let vao = self.annotations_vao(engine)?;
+        Ok(RenderObject {
+            enabled: Rc::clone(&self.enables.annotations),
+            program: Rc::clone(&self.programs.annotations),
+            vao,
+            draw_mode: DrawMode::Lines,
+            draw_num_indices: Rc::clone(&self.annotations_num_idx),
+            draw_offset_elements: Rc::new(Cell::new(0)),
+            uniforms: self.uniforms.annotations_uniforms(),
+            textures: Box::new([]),
+        })
+    }
+
     fn waterfall_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
         let source = ProgramSource {
             vertex_shader: r#"#version 300 es
@@ -642,78 +1655,263 @@ impl Waterfall {
         uniform float uZoom;
         uniform float uLabelWidth;
         uniform float uLabelHeight;
+        in float aMinZoom;
         out vec2 vTextureCoordinates;
         void main() {
+            if (uZoom < aMinZoom) {
+                // The label would collide with other labels. Hide it by
+                // placing all its vertices outside of the clip space.
+                gl_Position = vec4(2.0, 2.0, 0.0, 1.0);
+                vTextureCoordinates = aTextureCoordinates;
+                return;
+            }
             float side_offset = (float(gl_VertexID & 1) - 0.5) * uLabelWidth;
             float vertical_offset = (gl_VertexID & 2) != 0 ? uLabelHeight : 0.0;
             float center = uZoom * (aPosition.x - uCenterFreq);
             gl_Position = vec4(center + side_offset,
                                aPosition.y + vertical_offset,
                                0.0, 1.0);
-            vTextureCoordinates = aTextureCoordinates;
-        }"#,
+            vTextureCoordinates = aTextureCoordinates;
+        }"#,
+            fragment_shader: r#"#version 300 es
+        precision highp float;
+        in vec2 vTextureCoordinates;
+        uniform sampler2D uSampler;
+        out vec4 color;
+        void main() {
+            color = texture(uSampler, vTextureCoordinates);
+        }"#,
+        };
+        engine.make_program(source)
+    }
+
+    fn horizontal_divisions_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        let source = ProgramSource {
+            vertex_shader: r#"#version 300 es
+        in vec2 aPosition;
+        uniform float uWaterfallScaleAdd;
+        uniform float uWaterfallScaleAddFloor;
+        uniform float uWaterfallScaleMult;
+        out float vAlpha;
+        void main() {
+            bool majorDivision = (gl_VertexID >> 1) % 10 == 0;
+            vAlpha = float(majorDivision) * 0.4 + 0.4;
+            // power is in units of 10 dB, because the conversion to power does not include
+            // the 10 factor in the 10*log10 formula
+            //
+            // subtracting uWaterfallScaleAddFloor + 1.0 ensures that power >= 0 for most of
+            // the horizontal divisions, so that few of them are hidden below the lower edge
+            // of the screen
+            float power = aPosition.y - uWaterfallScaleAddFloor - 1.0;
+            float normalizedPower = 2.0 * uWaterfallScaleMult * (power + uWaterfallScaleAdd) - 1.0;
+            gl_Position = vec4(aPosition.x, normalizedPower, 0.0, 1.0);
+        }"#,
+            fragment_shader: r#"#version 300 es
+        precision highp float;
+        in float vAlpha;
+        out vec4 color;
+        void main() {
+            color = vec4(vAlpha);
+        }"#,
+        };
+        engine.make_program(source)
+    }
+
+    fn channel_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        let source = ProgramSource {
+            vertex_shader: r#"#version 300 es
+        in vec2 aPosition;
+        uniform float uCenterFreq;
+        uniform float uZoom;
+        uniform float uChannelFreq;
+        uniform float uChannelWidth;
+        void main() {
+            gl_Position = vec4(
+                uZoom * (aPosition.x * uChannelWidth + uChannelFreq - uCenterFreq),
+                aPosition.y, 0.0, 1.0);
+        }"#,
+            fragment_shader: r#"#version 300 es
+        precision highp float;
+        out vec4 color;
+        void main() {
+            color = vec4(0.0, 0.0, 0.0, 0.33);
+        }"#,
+        };
+
+        engine.make_program(source)
+    }
+
+    fn channel_preview_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        // The rectangle extends up to the start of the stopband of the DDC
+        // filter. vPosition is the frequency in units of half the DDC output
+        // bandwidth, so the passband ends at 1 - uChannelTransition and the
+        // stopband starts at 1 + uChannelTransition.
+        let source = ProgramSource {
+            vertex_shader: r#"#version 300 es
+        in vec2 aPosition;
+        uniform float uCenterFreq;
+        uniform float uZoom;
+        uniform float uChannelFreq;
+        uniform float uChannelWidth;
+        uniform float uChannelTransition;
+        out float vPosition;
+        void main() {
+            vPosition = aPosition.x * (1.0 + uChannelTransition);
+            gl_Position = vec4(
+                uZoom * (vPosition * uChannelWidth + uChannelFreq - uCenterFreq),
+                aPosition.y, 0.0, 1.0);
+        }"#,
+            fragment_shader: r#"#version 300 es
+        precision highp float;
+        in float vPosition;
+        uniform float uChannelTransition;
+        out vec4 color;
+        void main() {
+            float passbandEnd = 1.0 - uChannelTransition;
+            float skirt = clamp(
+                (abs(vPosition) - passbandEnd) / max(2.0 * uChannelTransition, 1e-6),
+                0.0, 1.0);
+            // The passband is highlighted and the skirts get darker towards the
+            // stopband. The colors use pre-multiplied alpha.
+            color = abs(vPosition) <= passbandEnd
+                ? vec4(0.12, 0.12, 0.12, 0.12)
+                : vec4(0.0, 0.0, 0.0, 0.6 * skirt);
+        }"#,
+        };
+
+        engine.make_program(source)
+    }
+
+    fn measurement_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        let source = ProgramSource {
+            vertex_shader: r#"#version 300 es
+        in vec2 aPosition;
+        uniform float uCenterFreq;
+        uniform float uZoom;
+        uniform float uMeasurementFreq;
+        uniform float uMeasurementWidth;
+        void main() {
+            gl_Position = vec4(
+                uZoom * (aPosition.x * uMeasurementWidth + uMeasurementFreq - uCenterFreq),
+                aPosition.y, 0.0, 1.0);
+        }"#,
+            fragment_shader: r#"#version 300 es
+        precision highp float;
+        out vec4 color;
+        void main() {
+            color = vec4(0.25, 0.25, 0.25, 0.25);
+        }"#,
+        };
+
+        engine.make_program(source)
+    }
+
+    fn color_scale_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        let source = ProgramSource {
+            vertex_shader: &format!(
+                r#"#version 300 es
+        in vec2 aPosition;
+        uniform float uCanvasWidth;
+        out float vNormalizedPower;
+        void main() {{
+            float right = 1.0 - 2.0 * {margin:.3} / uCanvasWidth;
+            float left = right - 2.0 * {width:.3} / uCanvasWidth;
+            float normalizedPower = 0.5 * (aPosition.y + 1.0);
+            gl_Position = vec4(aPosition.x < 0.0 ? left : right,
+                               {bottom:.3} + normalizedPower * ({top:.3} - {bottom:.3}),
+                               0.0, 1.0);
+            vNormalizedPower = normalizedPower;
+        }}"#,
+                margin = Self::COLOR_SCALE_MARGIN_PX,
+                width = Self::COLOR_SCALE_WIDTH_PX,
+                bottom = Self::COLOR_SCALE_BOTTOM,
+                top = Self::COLOR_SCALE_TOP,
+            ),
             fragment_shader: r#"#version 300 es
         precision highp float;
-        in vec2 vTextureCoordinates;
-        uniform sampler2D uSampler;
+        in float vNormalizedPower;
+        uniform sampler2D uColormapSampler;
         out vec4 color;
         void main() {
-            color = texture(uSampler, vTextureCoordinates);
+            color = texture(uColormapSampler, vec2(vNormalizedPower, 0.0));
         }"#,
         };
         engine.make_program(source)
     }
 
-    fn horizontal_divisions_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+    fn color_scale_labels_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        // aValue is the power of the label in dB, and aTextWidth is the width
+        // of its text, which is used to align the labels to the right.
         let source = ProgramSource {
-            vertex_shader: r#"#version 300 es
-        in vec2 aPosition;
+            vertex_shader: &format!(
+                r#"#version 300 es
+        in float aValue;
+        in float aTextWidth;
+        in vec2 aTextureCoordinates;
         uniform float uWaterfallScaleAdd;
-        uniform float uWaterfallScaleAddFloor;
         uniform float uWaterfallScaleMult;
-        out float vAlpha;
-        void main() {
-            bool majorDivision = (gl_VertexID >> 1) % 10 == 0;
-            vAlpha = float(majorDivision) * 0.4 + 0.4;
-            // power is in units of 10 dB, because the conversion to power does not include
-            // the 10 factor in the 10*log10 formula
-            //
-            // subtracting uWaterfallScaleAddFloor + 1.0 ensures that power >= 0 for most of
-            // the horizontal divisions, so that few of them are hidden below the lower edge
-            // of the screen
-            float power = aPosition.y - uWaterfallScaleAddFloor - 1.0;
-            float normalizedPower = 2.0 * uWaterfallScaleMult * (power + uWaterfallScaleAdd) - 1.0;
-            gl_Position = vec4(aPosition.x, normalizedPower, 0.0, 1.0);
-        }"#,
+        uniform float uCanvasWidth;
+        uniform float uLabelWidth;
+        uniform float uLabelHeight;
+        uniform float uColorScaleStep;
+        out vec2 vTextureCoordinates;
+        void main() {{
+            vTextureCoordinates = aTextureCoordinates;
+            // the waterfall scale uses units of 10 dB
+            float normalizedPower = uWaterfallScaleMult * (0.1 * aValue + uWaterfallScaleAdd);
+            if (normalizedPower < 0.0 || normalizedPower > 1.0
+                || mod(aValue, uColorScaleStep) != 0.0) {{
+                // Hide the label by placing all its vertices outside of the
+                // clip space.
+                gl_Position = vec4(2.0, 2.0, 0.0, 1.0);
+                return;
+            }}
+            float right = 1.0 - 2.0 * ({margin:.3} + {width:.3} + {gap:.3}) / uCanvasWidth;
+            float center = right - 0.5 * aTextWidth;
+            float side_offset = (float(gl_VertexID & 1) - 0.5) * uLabelWidth;
+            float vertical_offset = (float((gl_VertexID & 2) >> 1) - 0.5) * uLabelHeight;
+            gl_Position = vec4(center + side_offset,
+                               {bottom:.3} + normalizedPower * ({top:.3} - {bottom:.3})
+                               + vertical_offset,
+                               0.0, 1.0);
+        }}"#,
+                margin = Self::COLOR_SCALE_MARGIN_PX,
+                width = Self::COLOR_SCALE_WIDTH_PX,
+                gap = Self::COLOR_SCALE_LABELS_GAP_PX,
+                bottom = Self::COLOR_SCALE_BOTTOM,
+                top = Self::COLOR_SCALE_TOP,
+            ),
             fragment_shader: r#"#version 300 es
         precision highp float;
-        in float vAlpha;
+        in vec2 vTextureCoordinates;
+        uniform sampler2D uSampler;
         out vec4 color;
         void main() {
-            color = vec4(vAlpha);
+            color = texture(uSampler, vTextureCoordinates);
         }"#,
         };
         engine.make_program(source)
     }
 
-    fn channel_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+    fn annotations_program(engine: &RenderEngine) -> Result<Rc<WebGlProgram>, JsValue> {
+        // The y coordinate of the vertices is the position of the line in the
+        // waterfall texture, measured in the same units as uTimeTranslation.
         let source = ProgramSource {
             vertex_shader: r#"#version 300 es
         in vec2 aPosition;
+        uniform float uTimeTranslation;
         uniform float uCenterFreq;
         uniform float uZoom;
-        uniform float uChannelFreq;
-        uniform float uChannelWidth;
         void main() {
             gl_Position = vec4(
-                uZoom * (aPosition.x * uChannelWidth + uChannelFreq - uCenterFreq),
-                aPosition.y, 0.0, 1.0);
+                uZoom * (aPosition.x - uCenterFreq),
+                -1.0 + uTimeTranslation - aPosition.y, 0.0, 1.0);
         }"#,
             fragment_shader: r#"#version 300 es
         precision highp float;
         out vec4 color;
         void main() {
-            color = vec4(0.0, 0.0, 0.0, 0.33);
+            color = vec4(1.0, 1.0, 1.0, 0.8);
         }"#,
         };
 
@@ -783,30 +1981,119 @@ impl Waterfall {
         Ok(vao)
     }
 
+    // Returns the step between the coarsest frequency labels and whether the
+    // next depth of labels uses radix 5, given the width of the bounding box
+    // of the labels.
+    fn initial_label_step(&self, width_boundingbox: f32) -> (f64, bool) {
+        let s = (self.samp_rate * 0.5 * width_boundingbox as f64).log10();
+        let s2 = s.ceil();
+        let s3 = s2 - 2.0_f64.log10();
+        if s3 >= s {
+            (10.0_f64.powf(s3), true)
+        } else {
+            (10.0_f64.powf(s2), false)
+        }
+    }
+
+    // Computes the minimum zoom at which each frequency label does not collide
+    // with the labels adjacent to it among the labels of its depth and of
+    // coarser depths.
+    //
+    // The labels are sorted by depth, and nfreqs gives the cumulative number
+    // of labels up to each depth. The widths of the labels are given in screen
+    // coordinates.
+    fn labels_min_zoom(
+        freqs: &[f64],
+        widths: &[f32],
+        nfreqs: &[usize],
+        samp_rate: f64,
+        margin_factor: f32,
+    ) -> Vec<f32> {
+        let mut min_zoom = vec![0.0_f32; freqs.len()];
+        let mut sorted = Vec::with_capacity(freqs.len());
+        let mut start = 0;
+        for &end in nfreqs {
+            sorted.extend(start..end);
+            sorted.sort_unstable_by(|&a, &b| freqs[a].total_cmp(&freqs[b]));
+            for pair in sorted.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                if a < start && b < start {
+                    continue;
+                }
+                // The distance between the centers of the labels in screen
+                // coordinates is zoom * 2 * (freqs[b] - freqs[a]) / samp_rate,
+                // and it must be at least the average of their widths.
+                let zoom = (f64::from(margin_factor * (widths[a] + widths[b])) * samp_rate
+                    / (4.0 * (freqs[b] - freqs[a]))) as f32;
+                for j in [a, b] {
+                    if j >= start {
+                        min_zoom[j] = min_zoom[j].max(zoom);
+                    }
+                }
+            }
+            start = end;
+        }
+        min_zoom
+    }
+
     fn frequency_labels_vao(
         &mut self,
         engine: &mut RenderEngine,
     ) -> Result<(Rc<WebGlVertexArrayObject>, Rc<WebGlVertexArrayObject>), JsValue> {
+        // The labels show the displayed frequencies. When the spectrum is
+        // inverted, the center_freq is still at the center of the screen (see
+        // actual_center_freq), because the half-bin shift is also inverted.
+        let center_freq = self.display_frequency(self.center_freq);
+        let minfreq = center_freq - 0.5 * self.samp_rate;
+        let maxfreq = center_freq + 0.5 * self.samp_rate;
+
         // Measure the width of a frequency label to determine the width of the
-        // bounding box for the labels. We use 0000.000 as a "template label", since
-        // we don't really know what labels we will use yet.
-        const TEXT_HEIGHT_PX: u32 = 16;
-        let boundingbox_margin_factor = 1.1;
-        let width_boundingbox = boundingbox_margin_factor
-            * engine.text_renderer_text_width("0000.000", TEXT_HEIGHT_PX)?;
+        // bounding box for the labels. We use the label of the edge frequency
+        // with the most digits, with all the digits replaced by 0, as a
+        // "template label", since we don't really know what labels we will
+        // use yet.
+        //
+        // The number of decimals of the labels depends on the step between
+        // the finest labels, which depends on the width of the bounding
+        // box. We start with a coarse resolution and recompute the width using
+        // the resolution given by the first estimate of the step. The second
+        // estimate can only give a larger step, so the bounding box is wide
+        // enough for the labels.
+        //
+        // The unit is chosen once for the whole axis, so that all the labels
+        // use the same unit.
+        self.freq_labels_unit = self
+            .frequency_unit
+            .resolve(minfreq.abs().max(maxfreq.abs()));
         let mut max_depth_labels = 4;
         let mut max_depth = max_depth_labels + 2;
+        let mut resolution = self.samp_rate;
+        let mut width_boundingbox = 0.0;
+        let mut initial_step = (0.0, false);
+        for _ in 0..2 {
+            let template_label = [minfreq, maxfreq]
+                .iter()
+                .map(|&f| {
+                    self.frequency_label(f, resolution)
+                        .replace(|c: char| c.is_ascii_digit(), "0")
+                })
+                .max_by_key(|label| label.len())
+                .unwrap();
+            width_boundingbox = Self::FREQUENCY_LABELS_MARGIN_FACTOR
+                * engine.text_renderer_text_width(
+                    &template_label,
+                    Self::FREQUENCY_LABELS_TEXT_HEIGHT_PX,
+                )?;
+            initial_step = self.initial_label_step(width_boundingbox);
+            let (mut step, mut radix5) = initial_step;
+            for _ in 1..max_depth_labels {
+                step /= if radix5 { 5.0 } else { 2.0 };
+                radix5 = !radix5;
+            }
+            resolution = step;
+        }
 
-        let s = (self.samp_rate * 0.5 * width_boundingbox as f64).log10();
-        let s2 = s.ceil();
-        let s3 = s2 - 2.0_f64.log10();
-        let (mut step, mut radix5) = if s3 >= s {
-            (10.0_f64.powf(s3), true)
-        } else {
-            (10.0_f64.powf(s2), false)
-        };
-        let minfreq = self.center_freq - 0.5 * self.samp_rate;
-        let maxfreq = self.center_freq + 0.5 * self.samp_rate;
+        let (mut step, mut radix5) = initial_step;
         let start = (minfreq / step).floor() as i32 - 1;
         let stop = (maxfreq / step).ceil() as i32 + 1;
         let mut freqs = (start..=stop).map(|k| k as f64 * step).collect::<Vec<_>>();
@@ -815,8 +2102,10 @@ impl Waterfall {
         let mut freq_radixes = Vec::with_capacity(max_depth);
         let step_factor = 0.5 * width_boundingbox as f64 * self.samp_rate;
         let mut zoom_levels = vec![(step_factor / step) as f32];
+        let mut steps = vec![step];
         for depth in 0..max_depth {
             step /= if radix5 { 5.0 } else { 2.0 };
+            steps.push(step);
             freq_radixes.push(if radix5 { 5 } else { 2 });
             for j in 0..freqs.len() {
                 let f = freqs[j];
@@ -863,8 +2152,7 @@ impl Waterfall {
                 .iter()
                 .copied()
                 .enumerate()
-                .filter(|&(_, n)| 2 * n <= 1 << 16)
-                .last()
+                .rfind(|&(_, n)| 2 * n <= 1 << 16)
                 .unwrap();
             freqs.truncate(ndepth);
             nfreqs.truncate(depth + 1);
@@ -881,20 +2169,62 @@ impl Waterfall {
         // u16.
         assert!(4 * freqs_labels.len() <= (1 << 16));
 
-        let y = -0.96;
-        let vertices_labels = freqs_labels
+        let vertices_ticks = freqs
             .iter()
             .flat_map(|f| {
-                let x = (2.0 * (f - self.center_freq) / self.samp_rate) as f32;
-                [x, y, x, y, x, y, x, y]
+                let x = (2.0 * (f - center_freq) / self.samp_rate) as f32;
+                [x, -1.0, x, -0.98]
             })
             .collect::<Vec<f32>>();
 
-        let vertices_ticks = freqs
+        let indices_ticks = (0..vertices_ticks.len())
+            .map(|x| x as u16)
+            .collect::<Vec<u16>>();
+
+        let vao_ticks = match self.vaos.frequency_ticks.take() {
+            Some(vao) => engine.modify_vao(vao),
+            None => engine.create_vao()?,
+        }
+        .create_array_buffer(
+            &self.programs.frequency_ticks,
+            "aPosition",
+            2,
+            &vertices_ticks,
+        )?
+        .create_element_array_buffer(&indices_ticks)?
+        .build();
+        self.vaos.frequency_ticks = Some(Rc::clone(&vao_ticks));
+
+        self.freqs_labels = freqs_labels.to_vec();
+        self.freq_steps = steps;
+        self.num_freqs = nfreqs;
+        self.freq_radixes = freq_radixes;
+        self.zoom_levels = zoom_levels;
+        // Update zoom-related variables.
+        self.set_zoom(self.get_zoom());
+        let vao_labels = self.frequency_labels_texts_vao(engine)?;
+
+        Ok((vao_labels, vao_ticks))
+    }
+
+    // Renders the texts of the frequency labels and builds the VAO of the
+    // labels. All the labels use the number of decimals required by the
+    // deepest labels that are drawn at the current zoom level, so that they
+    // have a consistent look and they are not longer than needed. This is
+    // called again by prepare_render when the zoom level changes this depth.
+    fn frequency_labels_texts_vao(
+        &mut self,
+        engine: &mut RenderEngine,
+    ) -> Result<Rc<WebGlVertexArrayObject>, JsValue> {
+        let center_freq = self.display_frequency(self.center_freq);
+        let freqs_labels = &self.freqs_labels;
+
+        let y = Self::FREQUENCY_LABELS_Y;
+        let vertices_labels = freqs_labels
             .iter()
             .flat_map(|f| {
-                let x = (2.0 * (f - self.center_freq) / self.samp_rate) as f32;
-                [x, -1.0, x, -0.98]
+                let x = (2.0 * (f - center_freq) / self.samp_rate) as f32;
+                [x, y, x, y, x, y, x, y]
             })
             .collect::<Vec<f32>>();
 
@@ -907,16 +2237,26 @@ impl Waterfall {
             })
             .collect::<Vec<u16>>();
 
-        let indices_ticks = (0..vertices_ticks.len())
-            .map(|x| x as u16)
-            .collect::<Vec<u16>>();
-
+        let resolution = self.freq_steps[self.labels_depth];
         let texture_texts = freqs_labels
             .iter()
-            .map(|f| format!("{:.03}", f * 1e-6))
+            .map(|&f| self.frequency_label(f, resolution))
             .collect::<Vec<_>>();
-        let texts_dimensions =
-            engine.render_texts_to_texture(&self.textures.text, &texture_texts, TEXT_HEIGHT_PX)?;
+        let texts_dimensions = engine.render_texts_to_texture(
+            &self.textures.text,
+            &texture_texts,
+            Self::FREQUENCY_LABELS_TEXT_HEIGHT_PX,
+        )?;
+        let min_zoom_labels = Self::labels_min_zoom(
+            freqs_labels,
+            &texts_dimensions.text_widths,
+            &self.num_freqs[..self.zoom_levels.len()],
+            self.samp_rate,
+            Self::FREQUENCY_LABELS_MARGIN_FACTOR,
+        )
+        .into_iter()
+        .flat_map(|z| [z; 4])
+        .collect::<Vec<f32>>();
 
         let vao_labels = match self.vaos.frequency_labels.take() {
             Some(vao) => engine.modify_vao(vao),
@@ -934,37 +2274,163 @@ impl Waterfall {
             2,
             &texts_dimensions.texture_coordinates,
         )?
+        .create_array_buffer(
+            &self.programs.frequency_labels,
+            "aMinZoom",
+            1,
+            &min_zoom_labels,
+        )?
         .create_element_array_buffer(&indices_labels)?
         .build();
         self.vaos.frequency_labels = Some(Rc::clone(&vao_labels));
 
-        let vao_ticks = match self.vaos.frequency_ticks.take() {
+        self.uniforms
+            .freq_labels_width
+            .set_data(texts_dimensions.text_width);
+        self.uniforms
+            .freq_labels_height
+            .set_data(texts_dimensions.text_height);
+        self.labels_texts_depth = Some(self.labels_depth);
+
+        Ok(vao_labels)
+    }
+
+    // Formats the label of a frequency using the unit of the frequency
+    // labels. The unit symbol is only included if the unit is chosen
+    // automatically.
+    fn frequency_label(&self, frequency: f64, resolution: f64) -> String {
+        let label = self.freq_labels_unit.format(frequency, resolution);
+        if self.frequency_unit == FrequencyUnit::Auto {
+            format!("{label} {}", self.freq_labels_unit)
+        } else {
+            label
+        }
+    }
+
+    fn color_scale_labels_vao(
+        &mut self,
+        engine: &mut RenderEngine,
+    ) -> Result<Rc<WebGlVertexArrayObject>, JsValue> {
+        let values = 0..=Self::COLOR_SCALE_MAX_LABEL;
+        let texture_texts = values.clone().map(|v| v.to_string()).collect::<Vec<_>>();
+        let texts_dimensions = engine.render_texts_to_texture(
+            &self.textures.color_scale_text,
+            &texture_texts,
+            Self::COLOR_SCALE_TEXT_HEIGHT_PX,
+        )?;
+        let vertices_values = values
+            .clone()
+            .flat_map(|v| [f32::from(v); 4])
+            .collect::<Vec<f32>>();
+        let vertices_widths = texts_dimensions
+            .text_widths
+            .iter()
+            .flat_map(|&w| [w; 4])
+            .collect::<Vec<f32>>();
+        let indices = values
+            .flat_map(|v| {
+                let a = 4 * v;
+                [a, a + 1, a + 2, a + 1, a + 2, a + 3]
+            })
+            .collect::<Vec<u16>>();
+
+        let vao = match self.vaos.color_scale_labels.take() {
             Some(vao) => engine.modify_vao(vao),
             None => engine.create_vao()?,
         }
         .create_array_buffer(
-            &self.programs.frequency_ticks,
-            "aPosition",
+            &self.programs.color_scale_labels,
+            "aValue",
+            1,
+            &vertices_values,
+        )?
+        .create_array_buffer(
+            &self.programs.color_scale_labels,
+            "aTextWidth",
+            1,
+            &vertices_widths,
+        )?
+        .create_array_buffer(
+            &self.programs.color_scale_labels,
+            "aTextureCoordinates",
             2,
-            &vertices_ticks,
+            &texts_dimensions.texture_coordinates,
         )?
-        .create_element_array_buffer(&indices_ticks)?
+        .create_element_array_buffer(&indices)?
         .build();
-        self.vaos.frequency_ticks = Some(Rc::clone(&vao_ticks));
+        self.vaos.color_scale_labels = Some(Rc::clone(&vao));
 
-        self.num_freqs = nfreqs;
-        self.freq_radixes = freq_radixes;
-        self.zoom_levels = zoom_levels;
-        // Update zoom-related variables.
-        self.set_zoom(self.get_zoom());
         self.uniforms
-            .freq_labels_width
+            .color_scale_labels_width
             .set_data(texts_dimensions.text_width);
         self.uniforms
-            .freq_labels_height
+            .color_scale_labels_height
             .set_data(texts_dimensions.text_height);
+        self.update_waterfall_scale();
 
-        Ok((vao_labels, vao_ticks))
+        Ok(vao)
+    }
+
+    fn annotations_vao(
+        &mut self,
+        engine: &mut RenderEngine,
+    ) -> Result<Rc<WebGlVertexArrayObject>, JsValue> {
+        // Ages and timestamps of the lines that have timestamps, sorted by
+        // increasing age (decreasing timestamp).
+        let timestamps = (0..Self::TEXTURE_HEIGHT)
+            .filter_map(|age| {
+                let line =
+                    (self.current_draw_line + Self::TEXTURE_HEIGHT - age) % Self::TEXTURE_HEIGHT;
+                Some((age as f64, self.line_timestamps[line]?))
+            })
+            .collect::<Vec<_>>();
+        // Age of the line at a given time, interpolated between the line
+        // timestamps and clamped to the newest and oldest lines.
+        let age_at = |time: f64| match timestamps.iter().position(|&(_, t)| t <= time) {
+            Some(0) => timestamps[0].0,
+            Some(j) => {
+                let (a0, t0) = timestamps[j - 1];
+                let (a1, t1) = timestamps[j];
+                a0 + (a1 - a0) * (t0 - time) / (t0 - t1)
+            }
+            None => timestamps.last().unwrap().0,
+        };
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        if let (Some(&(_, newest)), Some(&(_, oldest))) = (timestamps.first(), timestamps.last()) {
+            let (center_freq, samp_rate) = self.get_freq_samprate();
+            let x = |frequency: f64| {
+                (self.frequency_sign() * 2.0 * (frequency - center_freq) / samp_rate) as f32
+            };
+            let position = |age: f64| {
+                (4.0 * (self.current_draw_line as f64 - age) / Self::TEXTURE_HEIGHT as f64) as f32
+            };
+            for annotation in self
+                .annotations
+                .iter()
+                .filter(|a| a.end_time >= oldest && a.start_time <= newest)
+            {
+                let (x0, x1) = (x(annotation.frequency_min), x(annotation.frequency_max));
+                let (y0, y1) = (
+                    position(age_at(annotation.start_time)),
+                    position(age_at(annotation.end_time)),
+                );
+                let n = u16::try_from(vertices.len() / 2).unwrap();
+                vertices.extend_from_slice(&[x0, y0, x1, y0, x1, y1, x0, y1]);
+                indices.extend_from_slice(&[n, n + 1, n + 1, n + 2, n + 2, n + 3, n + 3, n]);
+            }
+        }
+        let vao = match self.vaos.annotations.take() {
+            Some(vao) => engine.modify_vao(vao),
+            None => engine.create_vao()?,
+        }
+        .create_array_buffer(&self.programs.annotations, "aPosition", 2, &vertices)?
+        .create_element_array_buffer(&indices)?
+        .build();
+        self.vaos.annotations = Some(Rc::clone(&vao));
+        self.annotations_num_idx.set(indices.len() as u32);
+        self.annotations_line = Some(self.current_draw_line);
+        Ok(vao)
     }
 
     fn horizontal_divisions_vao(
@@ -1040,7 +2506,15 @@ impl Waterfall {
                 break;
             }
         }
-        self.freq_num_idx.set(6 * self.num_freqs[k] as u32);
+        // The labels of the next depth are also drawn, since some of them
+        // might fit at this zoom level. The vertex shader hides the labels
+        // that collide with other labels.
+        let labels_depth = (k + 1).min(self.zoom_levels.len() - 1);
+        self.freq_num_idx
+            .set(6 * self.num_freqs[labels_depth] as u32);
+        // The texts of the labels are rendered again in prepare_render if the
+        // depth has changed.
+        self.labels_depth = labels_depth;
         let next = if self.freq_radixes[k] == 2 { k + 1 } else { k };
         self.freq_num_idx_ticks
             .set(2 * self.num_freqs[next + 1] as u32);
@@ -1089,6 +2563,29 @@ impl Waterfall {
         self.update_waterfall_scale();
     }
 
+    /// Returns the current waterfall minimum power value.
+    ///
+    /// The `value` is in dB units.
+    pub fn get_waterfall_min(&self) -> f32 {
+        self.waterfall_min
+    }
+
+    /// Returns the current waterfall maximum power value.
+    ///
+    /// The `value` is in dB units.
+    pub fn get_waterfall_max(&self) -> f32 {
+        self.waterfall_max
+    }
+
+    /// Returns the height of the frequency axis.
+    ///
+    /// The frequency axis is formed by the ticks and labels at the bottom of
+    /// the waterfall. The height is given in screen coordinates, in which the
+    /// full height of the canvas is 2.0.
+    pub fn frequency_axis_height(&self) -> f32 {
+        Self::FREQUENCY_LABELS_Y + 1.0 + self.uniforms.freq_labels_height.get_data()
+    }
+
     /// Returns the value of the uniform associated with the DDC channel
     /// frequency.
     pub fn get_channel_frequency_uniform(&self) -> f32 {
@@ -1112,6 +2609,17 @@ impl Waterfall {
         self.uniforms
             .waterfall_scale_mult
             .set_data(10.0 / (self.waterfall_max - self.waterfall_min));
+
+        // Use the smallest step between the color scale labels for which the
+        // labels do not overlap.
+        let scale_height = Self::COLOR_SCALE_TOP - Self::COLOR_SCALE_BOTTOM;
+        let min_spacing = 1.5 * self.uniforms.color_scale_labels_height.get_data();
+        let range = self.waterfall_max - self.waterfall_min;
+        let step = [1.0, 2.0, 5.0, 10.0, 20.0, 50.0]
+            .into_iter()
+            .find(|&step| step / range * scale_height >= min_spacing)
+            .unwrap_or(100.0);
+        self.uniforms.color_scale_step.set_data(step);
     }
 
     /// Sets the waterfall update rate.
@@ -1157,10 +2665,19 @@ impl Textures {
             .set_parameter(TextureParameter::WrapT(TextureWrap::ClampToEdge))
             .build();
 
+        let color_scale_text = engine
+            .create_texture()?
+            .set_parameter(TextureParameter::MagFilter(TextureMagFilter::Linear))
+            .set_parameter(TextureParameter::MinFilter(TextureMinFilter::Linear))
+            .set_parameter(TextureParameter::WrapS(TextureWrap::ClampToEdge))
+            .set_parameter(TextureParameter::WrapT(TextureWrap::ClampToEdge))
+            .build();
+
         Ok(Textures {
             waterfall,
             colormap,
             text,
+            color_scale_text,
         })
     }
 
@@ -1190,6 +2707,20 @@ impl Textures {
             Rc::clone(&self.text),
         )])
     }
+
+    fn color_scale_textures(&self) -> Box<[Texture]> {
+        Box::new([Texture::new(
+            String::from("uColormapSampler"),
+            Rc::clone(&self.colormap),
+        )])
+    }
+
+    fn color_scale_text_textures(&self) -> Box<[Texture]> {
+        Box::new([Texture::new(
+            String::from("uSampler"),
+            Rc::clone(&self.color_scale_text),
+        )])
+    }
 }
 
 impl Uniforms {
@@ -1221,6 +2752,18 @@ impl Uniforms {
             )),
             channel_freq: Rc::new(Uniform::new(String::from("uChannelFreq"), 0.0)),
             channel_width: Rc::new(Uniform::new(String::from("uChannelWidth"), 0.1)),
+            channel_transition: Rc::new(Uniform::new(String::from("uChannelTransition"), 0.05)),
+            measurement_freq: Rc::new(Uniform::new(String::from("uMeasurementFreq"), 0.0)),
+            measurement_width: Rc::new(Uniform::new(String::from("uMeasurementWidth"), 0.0)),
+            color_scale_labels_width: Rc::new(Uniform::new(
+                String::from("uLabelWidth"),
+                Default::default(),
+            )),
+            color_scale_labels_height: Rc::new(Uniform::new(
+                String::from("uLabelHeight"),
+                Default::default(),
+            )),
+            color_scale_step: Rc::new(Uniform::new(String::from("uColorScaleStep"), 10.0)),
         }
     }
 
@@ -1280,6 +2823,48 @@ impl Uniforms {
             Rc::clone(&self.channel_width) as _,
         ])
     }
+
+    fn channel_preview_uniforms(&self) -> Box<[Rc<dyn UniformValue>]> {
+        Box::new([
+            Rc::clone(&self.center_freq) as _,
+            Rc::clone(&self.zoom) as _,
+            Rc::clone(&self.channel_freq) as _,
+            Rc::clone(&self.channel_width) as _,
+            Rc::clone(&self.channel_transition) as _,
+        ])
+    }
+
+    fn measurement_uniforms(&self) -> Box<[Rc<dyn UniformValue>]> {
+        Box::new([
+            Rc::clone(&self.center_freq) as _,
+            Rc::clone(&self.zoom) as _,
+            Rc::clone(&self.measurement_freq) as _,
+            Rc::clone(&self.measurement_width) as _,
+        ])
+    }
+
+    fn annotations_uniforms(&self) -> Box<[Rc<dyn UniformValue>]> {
+        Box::new([
+            Rc::clone(&self.time_translation) as _,
+            Rc::clone(&self.center_freq) as _,
+            Rc::clone(&self.zoom) as _,
+        ])
+    }
+
+    fn color_scale_uniforms(&self) -> Box<[Rc<dyn UniformValue>]> {
+        Box::new([Rc::clone(&self.canvas_width) as _])
+    }
+
+    fn color_scale_labels_uniforms(&self) -> Box<[Rc<dyn UniformValue>]> {
+        Box::new([
+            Rc::clone(&self.waterfall_scale_add) as _,
+            Rc::clone(&self.waterfall_scale_mult) as _,
+            Rc::clone(&self.canvas_width) as _,
+            Rc::clone(&self.color_scale_labels_width) as _,
+            Rc::clone(&self.color_scale_labels_height) as _,
+            Rc::clone(&self.color_scale_step) as _,
+        ])
+    }
 }
 
 impl Default for Uniforms {
@@ -5,20 +5,39 @@
 
 use crate::pointer::{PointerGesture, PointerTracker};
 use crate::render::RenderEngine;
+#[cfg(feature = "app")]
 use crate::ui::Ui;
-use crate::waterfall::Waterfall;
+use crate::waterfall::{format_line_timestamp, Waterfall};
+use serde::{Deserialize, Serialize};
+#[cfg(not(feature = "app"))]
+use standalone::Ui;
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{HtmlCanvasElement, PointerEvent, WheelEvent, Window};
+use web_sys::{HtmlCanvasElement, HtmlElement, PointerEvent, WheelEvent, Window};
 
 /// Waterfall interaction controller.
 ///
 /// This registers events that act on the waterfall to perform the following functions:
-/// * Control of zoom via on-wheel events.
+/// * Control of zoom via on-wheel events. Over the frequency axis, the zoom is
+///   always anchored at the pointer frequency. Elsewhere, the anchor is given by
+///   the [`ZoomAnchor`] selected in the [`Ui`].
+/// * Control of the waterfall levels via on-wheel events with the Shift key
+///   (shifts the minimum and maximum) or the Alt key (changes the maximum only)
+///   pressed.
 /// * Control of zoom via pinch gestures generated by a [`PointerTracker`].
 /// * Control of center frequency via drag gestures generated by a `PointerTracker`.
+///   Depending on the [`DragMode`] selected in the [`Ui`], dragging pans the
+///   display, retunes the RX frequency continuously, selects a region of the
+///   recording to export, or adds an annotation to the recording. When the waterfall shows an offline spectrogram,
+///   dragging pans the display and scrolls the spectrogram in time.
+/// * Selection of the channel power measurement region via drag gestures with the
+///   Shift key pressed.
+/// * Display of the time of the spectrum line and of the label of the
+///   annotation under the pointer as the canvas tooltip.
+/// * Display of the frequency and power of the latest spectrum line at the
+///   pointer position in a small label that follows the pointer.
 /// * Control of the cursor style according to whether the pointer is hovering or clicking
 ///   on the waterfall.
 #[derive(Clone)]
@@ -31,6 +50,109 @@ pub struct WaterfallInteraction {
     ui: Rc<RefCell<Option<Ui>>>,
     center_freq_overflow: Rc<RefCell<f32>>,
     drag_series: Rc<Cell<Option<Drag>>>,
+    shift_pressed: Rc<Cell<bool>>,
+    measurement_start: Rc<Cell<f32>>,
+    tune_frequency: Rc<Cell<f64>>,
+    // Corners of the region selected for export or annotation, given as a
+    // frequency between -1 and 1 and a vertical position in screen coordinates.
+    export_region: Rc<Cell<[(f32, f32); 2]>>,
+    // Label that shows the frequency and power at the pointer position
+    spectrum_readout: Rc<HtmlElement>,
+}
+
+/// Zoom anchor.
+///
+/// This enum lists the points that can be kept fixed on the screen when the
+/// waterfall is zoomed in or out using the mouse wheel.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum ZoomAnchor {
+    /// The frequency under the pointer stays fixed.
+    #[default]
+    Pointer,
+    /// The frequency at the center of the screen stays fixed.
+    Center,
+}
+
+impl std::str::FromStr for ZoomAnchor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<ZoomAnchor, ()> {
+        Ok(match s {
+            "Pointer" => ZoomAnchor::Pointer,
+            "Center" => ZoomAnchor::Center,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl std::fmt::Display for ZoomAnchor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                ZoomAnchor::Pointer => "Pointer",
+                ZoomAnchor::Center => "Center",
+            }
+        )
+    }
+}
+
+/// Drag mode.
+///
+/// This enum lists the actions that can be performed by dragging the waterfall
+/// horizontally.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default, Serialize, Deserialize)]
+pub enum DragMode {
+    /// Dragging pans the display.
+    ///
+    /// The RX frequency is only changed when the display is dragged beyond its
+    /// edge.
+    #[default]
+    Pan,
+    /// Dragging retunes the RX frequency continuously.
+    ///
+    /// The spectrum moves together with the pointer, as in a panadapter.
+    Tune,
+    /// Dragging selects a time and frequency region of the recording.
+    ///
+    /// When the pointer is released, the region is extracted by maia-httpd
+    /// from the current recording and downloaded as a SigMF archive.
+    Export,
+    /// Dragging selects a time and frequency region to annotate.
+    ///
+    /// When the pointer is released, the user is asked for a label and the
+    /// region is added to the annotations of the current recording.
+    Annotate,
+}
+
+impl std::str::FromStr for DragMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<DragMode, ()> {
+        Ok(match s {
+            "Pan" => DragMode::Pan,
+            "Tune" => DragMode::Tune,
+            "Export" => DragMode::Export,
+            "Annotate" => DragMode::Annotate,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl std::fmt::Display for DragMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(
+            f,
+            "{}",
+            match self {
+                DragMode::Pan => "Pan",
+                DragMode::Tune => "Tune",
+                DragMode::Export => "Export",
+                DragMode::Annotate => "Annotate",
+            }
+        )
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -43,6 +165,10 @@ struct Drag {
 enum DragObject {
     Waterfall,
     Channel,
+    Measurement,
+    Tune,
+    Export,
+    Annotate,
 }
 
 impl WaterfallInteraction {
@@ -60,6 +186,7 @@ impl WaterfallInteraction {
         // Set the canvas default cursor to crosshair. This is overridden by
         // some of the methods below.
         canvas.style().set_property("cursor", "crosshair")?;
+        let spectrum_readout = Rc::new(Self::create_spectrum_readout(&window)?);
 
         let interaction = WaterfallInteraction {
             window,
@@ -70,6 +197,11 @@ impl WaterfallInteraction {
             ui: Rc::new(RefCell::new(None)),
             center_freq_overflow: Rc::new(RefCell::new(0.0)),
             drag_series: Rc::new(Cell::new(None)),
+            shift_pressed: Rc::new(Cell::new(false)),
+            measurement_start: Rc::new(Cell::new(0.0)),
+            tune_frequency: Rc::new(Cell::new(0.0)),
+            export_region: Rc::new(Cell::new([(0.0, 0.0); 2])),
+            spectrum_readout,
         };
         interaction.set_callbacks();
         Ok(interaction)
@@ -82,16 +214,49 @@ impl WaterfallInteraction {
     /// updating the frequency when the waterfall is dragged will not be
     /// supported (this is for the intended use case in which a `Ui` object is
     /// not available).
+    #[cfg(feature = "app")]
     pub fn set_ui(&mut self, ui: Ui) {
         self.ui.borrow_mut().replace(ui);
     }
 
+    // Creates the spectrum readout label. It is styled inline, so that it does
+    // not depend on the stylesheet of the page that contains the waterfall.
+    fn create_spectrum_readout(window: &Window) -> Result<HtmlElement, JsValue> {
+        let document = window.document().ok_or("unable to get document")?;
+        let readout: HtmlElement = document.create_element("div")?.dyn_into()?;
+        readout.set_class_name("spectrum_readout");
+        let style = readout.style();
+        for (property, value) in [
+            ("display", "none"),
+            ("position", "fixed"),
+            ("padding", "2px 5px"),
+            ("font-size", "0.875rem"),
+            ("white-space", "pre"),
+            ("color", "white"),
+            ("background-color", "rgba(0, 0, 0, 0.5)"),
+            ("pointer-events", "none"),
+        ] {
+            style.set_property(property, value)?;
+        }
+        document
+            .body()
+            .ok_or("unable to get document body")?
+            .append_child(&readout)?;
+        Ok(readout)
+    }
+
     fn set_callbacks(&self) {
         // We leak all the closures produced by self to prevent them from being
         // dropped immediately.
         self.resize_canvas()();
+        // An event listener is used instead of the onresize handler so that
+        // several waterfalls can be used in the same window.
         self.window
-            .set_onresize(Some(self.onresize().into_js_value().unchecked_ref()));
+            .add_event_listener_with_callback(
+                "resize",
+                self.onresize().into_js_value().unchecked_ref(),
+            )
+            .unwrap();
 
         self.canvas
             .set_onwheel(Some(self.onwheel().into_js_value().unchecked_ref()));
@@ -169,28 +334,166 @@ impl WaterfallInteraction {
     }
 
     fn onwheel(&self) -> Closure<dyn Fn(WheelEvent)> {
-        let render_engine = Rc::clone(&self.render_engine);
-        let waterfall = Rc::clone(&self.waterfall);
-        let canvas = Rc::clone(&self.canvas);
+        let interaction = self.clone();
         Closure::new(move |event: WheelEvent| {
             event.prevent_default();
+            if event.shift_key() || event.alt_key() {
+                if let Err(e) = interaction.wheel_levels(&event) {
+                    web_sys::console::error_1(&e);
+                }
+                return;
+            }
             let dilation = (-1e-3 * event.delta_y() as f32).exp();
-            let center = event.client_x();
+            let anchor = if interaction.is_over_frequency_axis(event.client_y()) {
+                ZoomAnchor::Pointer
+            } else {
+                interaction
+                    .ui
+                    .borrow()
+                    .as_ref()
+                    .map(|ui| ui.waterfall_zoom_anchor())
+                    .unwrap_or_default()
+            };
+            let center = match anchor {
+                ZoomAnchor::Pointer => event.client_x(),
+                ZoomAnchor::Center => {
+                    let rect = interaction.canvas.get_bounding_client_rect();
+                    (rect.x() + 0.5 * rect.width()).round() as i32
+                }
+            };
             Self::apply_dilation(
-                &canvas,
-                &render_engine.borrow(),
-                &mut waterfall.borrow_mut(),
+                &interaction.canvas,
+                &interaction.render_engine.borrow(),
+                &mut interaction.waterfall.borrow_mut(),
                 dilation,
                 center,
             );
         })
     }
 
+    // Converts client coordinates to screen coordinates, in which the canvas
+    // spans from -1 (bottom) to 1 (top).
+    fn screen_y(&self, client_y: i32) -> Option<f64> {
+        let rect = self.canvas.get_bounding_client_rect();
+        if rect.height() <= 0.0 {
+            return None;
+        }
+        Some(1.0 - 2.0 * (f64::from(client_y) - rect.y()) / rect.height())
+    }
+
+    fn is_over_frequency_axis(&self, client_y: i32) -> bool {
+        let Some(y) = self.screen_y(client_y) else {
+            return false;
+        };
+        let axis_height = self.waterfall.borrow().frequency_axis_height();
+        y <= -1.0 + f64::from(axis_height)
+    }
+
+    // Converts client coordinates to a frequency between -1 and 1, as used by
+    // Waterfall::set_center_frequency.
+    fn frequency_x(&self, client_x: i32) -> f32 {
+        let waterfall = self.waterfall.borrow();
+        let units_per_px = Self::units_per_px(&self.render_engine.borrow(), &waterfall);
+        let x = client_x - self.canvas.get_bounding_client_rect().x().round() as i32;
+        waterfall.get_center_frequency() + x as f32 * units_per_px - 1.0 / waterfall.get_zoom()
+    }
+
+    // Shows the time of the spectrum line and the label of the annotation
+    // under the pointer as the canvas tooltip.
+    fn update_time_tooltip(&self, client_x: i32, client_y: i32) {
+        let Some(y) = self.screen_y(client_y) else {
+            let _ = self.canvas.set_attribute("title", "");
+            return;
+        };
+        let x = self.frequency_x(client_x);
+        let waterfall = self.waterfall.borrow();
+        let mut title = waterfall
+            .line_timestamp_at(y as f32)
+            .map(format_line_timestamp)
+            .unwrap_or_default();
+        if let Some(annotation) = waterfall.annotation_at(x, y as f32) {
+            title.push('\n');
+            title.push_str(&annotation.label);
+        }
+        // if setting the title fails, there is not much we can do
+        let _ = self.canvas.set_attribute("title", &title);
+    }
+
+    // Shows the frequency and power of the latest spectrum line at the pointer
+    // position in the spectrum readout label.
+    fn update_spectrum_readout(&self, client_x: i32, client_y: i32) {
+        let x = self.frequency_x(client_x);
+        let waterfall = self.waterfall.borrow();
+        let Some(power) = waterfall.spectrum_power_at(x) else {
+            self.hide_spectrum_readout();
+            return;
+        };
+        let (fc, fs) = waterfall.get_freq_samprate();
+        let frequency = waterfall
+            .display_frequency(fc + Self::frequency_sign(&waterfall) * 0.5 * f64::from(x) * fs);
+        let unit = waterfall.frequency_unit().resolve(frequency);
+        // Use enough decimals to resolve the spectrum bins.
+        let bin_width = fs / Waterfall::SPECTRUM_POINTS as f64;
+        let decimals = (unit.scale() / bin_width).log10().ceil().clamp(0.0, 9.0) as usize;
+        self.spectrum_readout.set_text_content(Some(&format!(
+            "{:.*} {unit}\n{power:.1} dB",
+            decimals,
+            frequency / unit.scale()
+        )));
+        // Place the label below the pointer, towards the center of the window.
+        let offset = 12;
+        let width = self
+            .window
+            .inner_width()
+            .ok()
+            .and_then(|w| w.as_f64())
+            .unwrap_or_default();
+        let (left, transform) = if f64::from(client_x) > 0.5 * width {
+            (client_x - offset, "translateX(-100%)")
+        } else {
+            (client_x + offset, "none")
+        };
+        let style = self.spectrum_readout.style();
+        // if setting the style fails, there is not much we can do
+        let _ = style.set_property("left", &format!("{left}px"));
+        let _ = style.set_property("top", &format!("{}px", client_y + offset));
+        let _ = style.set_property("transform", transform);
+        let _ = style.set_property("display", "block");
+    }
+
+    fn hide_spectrum_readout(&self) {
+        let _ = self
+            .spectrum_readout
+            .style()
+            .set_property("display", "none");
+    }
+
+    fn wheel_levels(&self, event: &WheelEvent) -> Result<(), JsValue> {
+        let ui = self.ui.borrow();
+        let Some(ui) = ui.as_ref() else {
+            return Ok(());
+        };
+        // Some browsers report the Shift+wheel as a horizontal scroll.
+        let delta = event.delta_y() + event.delta_x();
+        if delta == 0.0 {
+            return Ok(());
+        }
+        // Each wheel event changes the levels by 1 dB.
+        let step = if delta < 0.0 { 1.0 } else { -1.0 };
+        if event.shift_key() {
+            ui.adjust_waterfall_levels(step, step)
+        } else {
+            ui.adjust_waterfall_levels(0.0, step)
+        }
+    }
+
     fn onpointerdown(&self) -> Closure<dyn Fn(PointerEvent)> {
         let canvas = Rc::clone(&self.canvas);
         let pointer_tracker = Rc::clone(&self.pointer_tracker);
+        let shift_pressed = Rc::clone(&self.shift_pressed);
         Closure::new(move |event: PointerEvent| {
             canvas.style().set_property("cursor", "col-resize").unwrap();
+            shift_pressed.set(event.shift_key());
             pointer_tracker.borrow_mut().on_pointer_down(event);
         })
     }
@@ -198,6 +501,11 @@ impl WaterfallInteraction {
     fn onpointerup(&self) -> Closure<dyn Fn(PointerEvent)> {
         let interaction = self.clone();
         Closure::new(move |event: PointerEvent| {
+            // The spectrum readout of a mouse stays visible after a click, but
+            // other pointers only give a readout while they touch the canvas.
+            if event.type_() != "pointerup" || event.pointer_type() != "mouse" {
+                interaction.hide_spectrum_readout();
+            }
             let mut pointer_tracker = interaction.pointer_tracker.borrow_mut();
             pointer_tracker.on_pointer_up(event);
             if !pointer_tracker.has_active_pointers() {
@@ -208,6 +516,19 @@ impl WaterfallInteraction {
                     .unwrap();
                 // Reset frequency overflow when we release.
                 *interaction.center_freq_overflow.borrow_mut() = 0.0;
+                if let Some(drag) = interaction.drag_series.get() {
+                    let result = match drag.object {
+                        DragObject::Export => Some(interaction.export_region()),
+                        DragObject::Annotate => Some(interaction.annotate_region()),
+                        _ => None,
+                    };
+                    if let Some(result) = result {
+                        interaction.drag_series.set(None);
+                        if let Err(e) = result {
+                            web_sys::console::error_1(&e);
+                        }
+                    }
+                }
             }
         })
     }
@@ -215,6 +536,8 @@ impl WaterfallInteraction {
     fn onpointermove(&self) -> Closure<dyn Fn(PointerEvent)> {
         let interaction = self.clone();
         Closure::new(move |event: PointerEvent| {
+            interaction.update_time_tooltip(event.client_x(), event.client_y());
+            interaction.update_spectrum_readout(event.client_x(), event.client_y());
             if let Some(gesture) = interaction
                 .pointer_tracker
                 .borrow_mut()
@@ -228,7 +551,11 @@ impl WaterfallInteraction {
     fn process_gesture(&self, gesture: PointerGesture) -> Result<(), JsValue> {
         match gesture {
             PointerGesture::Drag {
-                dx, x0, series_id, ..
+                dx,
+                dy,
+                x0,
+                y0,
+                series_id,
             } => {
                 let mut waterfall = self.waterfall.borrow_mut();
                 let units_per_px = Self::units_per_px(&self.render_engine.borrow(), &waterfall);
@@ -239,23 +566,40 @@ impl WaterfallInteraction {
                     .get()
                     .map(|drag| drag.series_id != series_id)
                     .unwrap_or(true);
+                // x0 uses client coordinates so we need to shift it according
+                // to the client coordinates for the canvas origin.
+                let x0 = x0 - self.canvas.get_bounding_client_rect().x().round() as i32;
+                let f0 = waterfall.get_center_frequency() + x0 as f32 * units_per_px
+                    - 1.0 / waterfall.get_zoom();
                 if new_drag {
-                    let object = if !waterfall.is_channel_visible() {
-                        DragObject::Waterfall
+                    // The offline spectrogram can only be panned, since
+                    // tuning, exporting and annotating act on the device.
+                    let drag_mode = if waterfall.is_offline() {
+                        DragMode::Pan
                     } else {
-                        // x0 uses client coordinates so we need to shift it according
-                        // to the client coordinates for the canvas origin.
-                        let x0 = x0 - self.canvas.get_bounding_client_rect().x().round() as i32;
-                        let freq = waterfall.get_center_frequency();
-                        let f0 = freq + x0 as f32 * units_per_px - 1.0 / waterfall.get_zoom();
-                        let chan_freq = waterfall.get_channel_frequency_uniform();
-                        let chan_width = waterfall.get_channel_width_uniform();
-                        let inside_channel = (f0 - chan_freq).abs() <= chan_width;
-                        if inside_channel {
-                            DragObject::Channel
+                        self.drag_mode()
+                    };
+                    let object = if self.shift_pressed.get() {
+                        self.measurement_start.set(f0);
+                        DragObject::Measurement
+                    } else if matches!(drag_mode, DragMode::Export | DragMode::Annotate) {
+                        let y = self.screen_y(y0).unwrap_or_default() as f32;
+                        self.export_region.set([(f0, y); 2]);
+                        if drag_mode == DragMode::Export {
+                            DragObject::Export
                         } else {
-                            DragObject::Waterfall
+                            DragObject::Annotate
                         }
+                    } else if waterfall.is_channel_visible()
+                        && (f0 - waterfall.get_channel_frequency_uniform()).abs()
+                            <= waterfall.get_channel_width_uniform()
+                    {
+                        DragObject::Channel
+                    } else if drag_mode == DragMode::Tune {
+                        self.tune_frequency.set(waterfall.get_freq_samprate().0);
+                        DragObject::Tune
+                    } else {
+                        DragObject::Waterfall
                     };
                     self.drag_series.set(Some(Drag { series_id, object }));
                 }
@@ -263,8 +607,23 @@ impl WaterfallInteraction {
                 let object = self.drag_series.get().unwrap().object;
                 match object {
                     DragObject::Channel => self.drag_channel(&mut waterfall, dx, units_per_px)?,
+                    DragObject::Measurement => {
+                        let f1 = (f0 + dx as f32 * units_per_px).clamp(-1.0, 1.0);
+                        let start = self.measurement_start.get().clamp(-1.0, 1.0);
+                        waterfall.set_measurement_region(Some((start, f1)));
+                    }
                     DragObject::Waterfall => {
-                        self.drag_waterfall(&mut waterfall, dx, units_per_px)?
+                        self.drag_waterfall(&mut waterfall, dx, dy, units_per_px)?
+                    }
+                    DragObject::Tune => self.drag_tune(&waterfall, dx, units_per_px)?,
+                    DragObject::Export | DragObject::Annotate => {
+                        let f1 = (f0 + dx as f32 * units_per_px).clamp(-1.0, 1.0);
+                        let y1 = self.screen_y(y0 + dy).unwrap_or_default() as f32;
+                        let [start, _] = self.export_region.get();
+                        self.export_region.set([start, (f1, y1)]);
+                        // The frequency range of the region is highlighted
+                        // using the measurement region.
+                        waterfall.set_measurement_region(Some((start.0, f1)));
                     }
                 }
             }
@@ -291,21 +650,107 @@ impl WaterfallInteraction {
         let ui = ui.as_ref().unwrap();
         let samp_rate = waterfall.get_freq_samprate().1;
         let freq = waterfall.get_channel_frequency()
-            + f64::from(dx) * f64::from(units_per_px) * 0.5 * samp_rate;
+            + Self::frequency_sign(waterfall)
+                * f64::from(dx)
+                * f64::from(units_per_px)
+                * 0.5
+                * samp_rate;
         let freq = freq.clamp(-0.5 * samp_rate, 0.5 * samp_rate);
         waterfall.set_channel_frequency(freq);
         ui.set_ddc_frequency(freq)?;
         Ok(())
     }
 
+    // Sign of the change of the RX frequency when moving from left to right
+    // in the waterfall.
+    fn frequency_sign(waterfall: &Waterfall) -> f64 {
+        if waterfall.is_spectrum_inverted() {
+            -1.0
+        } else {
+            1.0
+        }
+    }
+
+    fn drag_mode(&self) -> DragMode {
+        self.ui
+            .borrow()
+            .as_ref()
+            .map(|ui| ui.waterfall_drag_mode())
+            .unwrap_or_default()
+    }
+
+    fn drag_tune(&self, waterfall: &Waterfall, dx: i32, units_per_px: f32) -> Result<(), JsValue> {
+        let ui = self.ui.borrow();
+        let Some(ui) = ui.as_ref() else {
+            return Ok(());
+        };
+        // The frequency moves in the opposite direction to the pointer, so
+        // that the spectrum follows the pointer.
+        let samp_rate = waterfall.get_freq_samprate().1;
+        let freq = self.tune_frequency.get()
+            - Self::frequency_sign(waterfall)
+                * f64::from(dx)
+                * f64::from(units_per_px)
+                * 0.5
+                * samp_rate;
+        let freq = ui.tune_rx_frequency(freq)?;
+        self.tune_frequency.set(freq);
+        Ok(())
+    }
+
+    // Returns the time span and frequency range of the selected region, or
+    // None if the selected lines do not have timestamps.
+    fn selected_region(&self) -> Option<((f64, f64), (f64, f64))> {
+        let waterfall = self.waterfall.borrow();
+        let [start, end] = self.export_region.get();
+        let start_time = waterfall.line_timestamp_at(start.1)?;
+        let end_time = waterfall.line_timestamp_at(end.1)?;
+        let (fc, fs) = waterfall.get_freq_samprate();
+        let frequency = |f: f32| fc + Self::frequency_sign(&waterfall) * 0.5 * f64::from(f) * fs;
+        Some((
+            (start_time, end_time),
+            (frequency(start.0), frequency(end.0)),
+        ))
+    }
+
+    fn export_region(&self) -> Result<(), JsValue> {
+        let ui = self.ui.borrow();
+        let Some(ui) = ui.as_ref() else {
+            return Ok(());
+        };
+        let Some((time_span, frequency_range)) = self.selected_region() else {
+            return ui.alert("The selected waterfall lines do not have timestamps");
+        };
+        ui.export_recording_region(time_span, frequency_range)
+    }
+
+    fn annotate_region(&self) -> Result<(), JsValue> {
+        let ui = self.ui.borrow();
+        let Some(ui) = ui.as_ref() else {
+            return Ok(());
+        };
+        let Some((time_span, frequency_range)) = self.selected_region() else {
+            return ui.alert("The selected waterfall lines do not have timestamps");
+        };
+        ui.add_recording_annotation(time_span, frequency_range)
+    }
+
     fn drag_waterfall(
         &self,
         waterfall: &mut Waterfall,
         dx: i32,
+        dy: i32,
         units_per_px: f32,
     ) -> Result<(), JsValue> {
         let freq = waterfall.get_center_frequency() - dx as f32 * units_per_px;
         let clamped = Self::clamp_center_frequency(freq, waterfall.get_zoom());
+        if waterfall.is_offline() {
+            // The offline spectrogram is also scrolled vertically. The
+            // vertical screen coordinates increase upwards.
+            waterfall.set_center_frequency(clamped);
+            waterfall.scroll_offline(-2.0 * dy as f32 / self.canvas.client_height() as f32);
+            return Ok(());
+        }
         let mut overflow = self.center_freq_overflow.borrow_mut();
         *overflow += freq - clamped;
         let shift_threshold = 0.25;
@@ -315,7 +760,7 @@ impl WaterfallInteraction {
                 let shift = shift_threshold.copysign(*overflow);
                 *overflow -= shift;
                 let (fc, fs) = waterfall.get_freq_samprate();
-                let new_fc = fc + 0.5 * f64::from(shift) * fs;
+                let new_fc = fc + Self::frequency_sign(waterfall) * 0.5 * f64::from(shift) * fs;
                 ui.set_rx_frequency(new_fc as u64)?;
             }
             _ => waterfall.set_center_frequency(clamped),
@@ -323,3 +768,57 @@ impl WaterfallInteraction {
         Ok(())
     }
 }
+
+// Without the app feature there is no Ui, so this uninhabited type is used in
+// its place. Since a value of this type cannot exist, the interaction behaves
+// as when no Ui has been set.
+#[cfg(not(feature = "app"))]
+mod standalone {
+    use super::{DragMode, ZoomAnchor};
+    use wasm_bindgen::JsValue;
+
+    #[derive(Clone)]
+    pub enum Ui {}
+
+    impl Ui {
+        pub fn waterfall_zoom_anchor(&self) -> ZoomAnchor {
+            match *self {}
+        }
+
+        pub fn waterfall_drag_mode(&self) -> DragMode {
+            match *self {}
+        }
+
+        pub fn adjust_waterfall_levels(&self, _: f32, _: f32) -> Result<(), JsValue> {
+            match *self {}
+        }
+
+        pub fn set_ddc_frequency(&self, _: f64) -> Result<(), JsValue> {
+            match *self {}
+        }
+
+        pub fn set_rx_frequency(&self, _: u64) -> Result<(), JsValue> {
+            match *self {}
+        }
+
+        pub fn tune_rx_frequency(&self, _: f64) -> Result<f64, JsValue> {
+            match *self {}
+        }
+
+        pub fn alert(&self, _: &str) -> Result<(), JsValue> {
+            match *self {}
+        }
+
+        pub fn export_recording_region(&self, _: (f64, f64), _: (f64, f64)) -> Result<(), JsValue> {
+            match *self {}
+        }
+
+        pub fn add_recording_annotation(
+            &self,
+            _: (f64, f64),
+            _: (f64, f64),
+        ) -> Result<(), JsValue> {
+            match *self {}
+        }
+    }
+}
@@ -1,5 +1,7 @@
 //! WebSocket client for waterfall data.
 
+use js_sys::ArrayBuffer;
+use maia_json::{WaterfallFrameHeader, WaterfallMessageHeader, WaterfallMessageType};
 use std::cell::RefCell;
 use std::rc::Rc;
 use wasm_bindgen::prelude::*;
@@ -12,7 +14,19 @@ use crate::waterfall::Waterfall;
 ///
 /// Implements a WebSocket client that receives messages containing waterfall
 /// data and submits the data to the waterfall by calling
-/// [Waterfall::put_waterfall_spectrum].
+/// [Waterfall::put_waterfall_spectrum]. Notifications of dropped lines are
+/// submitted by calling [Waterfall::put_dropped_lines], and line timestamps
+/// by calling [Waterfall::put_line_timestamp].
+///
+/// The client requests the v4 protocol, in which each message starts with a
+/// [`WaterfallMessageHeader`] that gives its type. Spectrum lines are preceded
+/// by a [`WaterfallFrameHeader`] that carries their sequence number and
+/// timestamp. The sequence numbers are given to
+/// [Waterfall::put_line_sequence] to detect dropped lines. Alerts are logged to
+/// the console, and messages of other types are ignored. If the server does not
+/// support the v4 protocol, the client falls back to the v2 protocol, which
+/// only has spectrum lines with the same frame header, or to the v1 protocol,
+/// in which binary messages contain only the spectrum line.
 pub struct WebSocketClient {}
 
 struct WebSocketData {
@@ -44,8 +58,21 @@ impl WebSocketClient {
         };
         let hostname = location.hostname()?;
         let port = location.port()?;
+        Self::start_with_url(
+            &format!("{protocol}://{hostname}:{port}/waterfall"),
+            waterfall,
+        )
+    }
+
+    /// Starts the WebSocket client connecting to a given URL.
+    ///
+    /// This function is similar to [`WebSocketClient::start`], but instead of
+    /// connecting to the `/waterfall` endpoint of the server from which the
+    /// page has been loaded, it connects to the WebSocket `url`. It can be used
+    /// to receive waterfall data from other Maia SDR devices.
+    pub fn start_with_url(url: &str, waterfall: Rc<RefCell<Waterfall>>) -> Result<(), JsValue> {
         let data = Rc::new(WebSocketData {
-            url: format!("{protocol}://{hostname}:{port}/waterfall"),
+            url: url.to_string(),
             onmessage: onmessage(waterfall).into_js_value(),
             onclose: RefCell::new(None),
         });
@@ -58,6 +85,23 @@ impl WebSocketClient {
 
 fn onmessage(waterfall: Rc<RefCell<Waterfall>>) -> Closure<dyn Fn(MessageEvent)> {
     Closure::new(move |event: MessageEvent| {
+        // Text messages notify about dropped lines or give the timestamp of
+        // the next line
+        if let Some(text) = event.data().as_string() {
+            if let Ok(timestamp) = serde_json::from_str::<maia_json::WaterfallTimestamp>(&text) {
+                waterfall
+                    .borrow_mut()
+                    .put_line_timestamp(timestamp.timestamp);
+                return;
+            }
+            match serde_json::from_str::<maia_json::WaterfallDropped>(&text) {
+                Ok(dropped) => waterfall
+                    .borrow_mut()
+                    .put_dropped_lines(dropped.dropped_lines),
+                Err(e) => web_sys::console::error_1(&e.to_string().into()),
+            }
+            return;
+        }
         let data = match event.data().dyn_into::<js_sys::ArrayBuffer>() {
             Ok(x) => x,
             Err(e) => {
@@ -65,15 +109,119 @@ fn onmessage(waterfall: Rc<RefCell<Waterfall>>) -> Closure<dyn Fn(MessageEvent)>
                 return;
             }
         };
-        waterfall
-            .borrow_mut()
-            .put_waterfall_spectrum(&js_sys::Float32Array::new(&data));
+        let protocol = event
+            .target()
+            .and_then(|t| t.dyn_into::<WebSocket>().ok())
+            .map(|ws| ws.protocol())
+            .unwrap_or_default();
+        let mut waterfall = waterfall.borrow_mut();
+        if protocol == maia_json::WATERFALL_PROTOCOL_V4 {
+            put_message(&mut waterfall, &data);
+        } else if protocol == maia_json::WATERFALL_PROTOCOL_V2 {
+            // The binary message starts with the frame header
+            put_frame(&mut waterfall, &data, 0, WaterfallFrameHeader::SIZE);
+        } else {
+            waterfall.put_waterfall_spectrum(&js_sys::Float32Array::new(&data));
+        }
     })
 }
 
+// Handles a message of the v4 protocol.
+fn put_message(waterfall: &mut Waterfall, data: &ArrayBuffer) {
+    let Some(header) = read_bytes(data, 0, WaterfallMessageHeader::SIZE)
+        .and_then(|bytes| WaterfallMessageHeader::from_bytes(&bytes))
+    else {
+        web_sys::console::error_1(&"waterfall message is too short".into());
+        return;
+    };
+    let payload_offset = usize::from(header.payload_offset);
+    match header.message_type() {
+        Some(WaterfallMessageType::SpectrumLine) => put_frame(
+            waterfall,
+            data,
+            WaterfallMessageHeader::SIZE,
+            payload_offset,
+        ),
+        Some(WaterfallMessageType::ConfigChange) => {
+            if let Some(change) =
+                read_json::<maia_json::WaterfallConfigChange>(data, payload_offset)
+            {
+                web_sys::console::debug_1(
+                    &format!("configuration changed: {}", change.path).into(),
+                );
+            }
+        }
+        Some(WaterfallMessageType::Alert) => {
+            if let Some(alert) = read_json::<maia_json::WaterfallAlert>(data, payload_offset) {
+                web_sys::console::warn_1(&alert.message.into());
+            }
+        }
+        // IQ chunks are not used by maia-wasm, and unknown message types have
+        // been added in newer versions of the protocol.
+        Some(WaterfallMessageType::IqChunk) | None => {}
+    }
+}
+
+// Submits a spectrum line to the waterfall. The frame header is at
+// header_offset and the spectrum data at spectrum_offset.
+fn put_frame(
+    waterfall: &mut Waterfall,
+    data: &ArrayBuffer,
+    header_offset: usize,
+    spectrum_offset: usize,
+) {
+    let Some(header) = read_bytes(data, header_offset, WaterfallFrameHeader::SIZE)
+        .and_then(|bytes| WaterfallFrameHeader::from_bytes(&bytes))
+    else {
+        web_sys::console::error_1(&"waterfall frame is too short".into());
+        return;
+    };
+    waterfall.put_line_sequence(header.sequence);
+    waterfall.put_line_timestamp(header.timestamp);
+    let spectrum = if spectrum_offset.is_multiple_of(std::mem::size_of::<f32>()) {
+        js_sys::Float32Array::new_with_byte_offset(data, spectrum_offset as u32)
+    } else {
+        // A Float32Array can only be created at an aligned offset, so the
+        // spectrum is copied.
+        js_sys::Float32Array::new(&data.slice(spectrum_offset as u32))
+    };
+    waterfall.put_waterfall_spectrum(&spectrum);
+}
+
+// Copies len bytes starting at offset from an ArrayBuffer. Returns None if the
+// ArrayBuffer is too short.
+fn read_bytes(data: &ArrayBuffer, offset: usize, len: usize) -> Option<Vec<u8>> {
+    if offset + len > data.byte_length() as usize {
+        return None;
+    }
+    let mut bytes = vec![0; len];
+    js_sys::Uint8Array::new_with_byte_offset_and_length(data, offset as u32, len as u32)
+        .copy_to(&mut bytes);
+    Some(bytes)
+}
+
+// Parses the JSON payload that starts at offset.
+fn read_json<T: serde::de::DeserializeOwned>(data: &ArrayBuffer, offset: usize) -> Option<T> {
+    let len = (data.byte_length() as usize).checked_sub(offset)?;
+    match serde_json::from_slice(&read_bytes(data, offset, len)?) {
+        Ok(json) => Some(json),
+        Err(e) => {
+            web_sys::console::error_1(&e.to_string().into());
+            None
+        }
+    }
+}
+
 impl WebSocketData {
     fn connect(&self) -> Result<(), JsValue> {
-        let ws = WebSocket::new(&self.url)?;
+        // Request the v4 and v2 protocols. The server selects the newest one
+        // that it supports. Servers that do not support any of them accept the
+        // connection without selecting any protocol, and use the v1 protocol.
+        let protocols = js_sys::Array::of2(
+            &maia_json::WATERFALL_PROTOCOL_V4.into(),
+            &maia_json::WATERFALL_PROTOCOL_V2.into(),
+        );
+        let ws = WebSocket::new_with_str_sequence(&self.url, &protocols)?;
         ws.set_binary_type(web_sys::BinaryType::Arraybuffer);
         ws.set_onmessage(Some(self.onmessage.unchecked_ref()));
         // by this point onclose shouldn't be None
@@ -0,0 +1,138 @@
+//! Tests of the API state synchronization logic.
+//!
+//! These tests run under wasm-bindgen-test with `wasm-pack test --node`, and
+//! as regular tests with `cargo test`, since the synchronization logic does not
+//! depend on the DOM.
+
+#![cfg(feature = "app")]
+
+use maia_json::{Ad9361GainMode, Hertz, PatchSpectrometer, RecorderState, SpectrometerInput};
+use maia_wasm::ui::{
+    raster::ChannelRaster,
+    sync::{
+        agc_toggle_mode, complete_spectrometer_patch, recording_finished, snap_to_channel_raster,
+        RxFrequencyChange, Tuning,
+    },
+};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn ddc_tuning() -> Tuning {
+    Tuning {
+        spectrometer_input: SpectrometerInput::DDC,
+        ad9361_sampling_frequency: 10e6,
+        ad9361_rx_lo_frequency: 100e6,
+        ddc_output_sampling_frequency: 1e6,
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn recording_finished_transitions() {
+    use RecorderState::*;
+    for previous in [Running, Stopping] {
+        assert!(recording_finished(previous, Stopped));
+        assert!(!recording_finished(previous, Running));
+    }
+    assert!(!recording_finished(Stopped, Stopped));
+    assert!(!recording_finished(Stopped, Running));
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn rx_frequency_change_within_band() {
+    let tuning = ddc_tuning();
+    // The usable band of the DDC is 100 MHz +/- 3.75 MHz
+    for (frequency, ddc) in [
+        (100_000_000, 0.0),
+        (96_250_000, -3.75e6),
+        (103_750_000, 3.75e6),
+    ] {
+        let change = RxFrequencyChange::new(&tuning, frequency);
+        assert_eq!(change.ad9361_frequency, None);
+        assert_eq!(change.ddc_frequency, Some(ddc));
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn rx_frequency_change_outside_band() {
+    let tuning = ddc_tuning();
+    // Below the band, the DDC ends up at the upper edge
+    let change = RxFrequencyChange::new(&tuning, 80_000_000);
+    assert_eq!(change.ad9361_frequency, Some(76_250_000));
+    assert_eq!(change.ddc_frequency, Some(3.75e6));
+    // Above the band, the DDC ends up at the lower edge
+    let change = RxFrequencyChange::new(&tuning, 120_000_000);
+    assert_eq!(change.ad9361_frequency, Some(123_750_000));
+    assert_eq!(change.ddc_frequency, Some(-3.75e6));
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn rx_frequency_change_ad9361_input() {
+    let tuning = Tuning {
+        spectrometer_input: SpectrometerInput::AD9361,
+        ..ddc_tuning()
+    };
+    let change = RxFrequencyChange::new(&tuning, 120_000_000);
+    assert_eq!(change.ad9361_frequency, Some(120_000_000));
+    assert_eq!(change.ddc_frequency, None);
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn channel_raster_snapping() {
+    // No raster
+    assert_eq!(
+        snap_to_channel_raster(ChannelRaster::Off, 0.0, false, 446_011_000.0),
+        446_011_000.0
+    );
+    let raster = ChannelRaster::Khz12_5;
+    // Upconverter with a 125 MHz LO
+    assert_eq!(
+        snap_to_channel_raster(raster, -125e6, false, 146_011_000.0),
+        146_012_500.0
+    );
+    // A frequency already on the raster is not changed
+    assert_eq!(
+        snap_to_channel_raster(raster, 10e9, true, 1_000_012_500.0),
+        1_000_012_500.0
+    );
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn agc_toggle() {
+    for last_agc in [
+        Ad9361GainMode::SlowAttack,
+        Ad9361GainMode::FastAttack,
+        Ad9361GainMode::Hybrid,
+    ] {
+        assert_eq!(agc_toggle_mode(Ad9361GainMode::Manual, last_agc), last_agc);
+        assert_eq!(
+            agc_toggle_mode(Ad9361GainMode::SlowAttack, last_agc),
+            Ad9361GainMode::Manual
+        );
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), test)]
+#[wasm_bindgen_test]
+fn spectrometer_patch_completion() {
+    // The output sampling frequency is only added when the input changes
+    let mut patch = PatchSpectrometer {
+        mode: Some(maia_json::SpectrometerMode::PeakDetect),
+        ..Default::default()
+    };
+    complete_spectrometer_patch(&mut patch, Some(Hertz(20.0)));
+    assert_eq!(patch.output_sampling_frequency, None);
+
+    let mut patch = PatchSpectrometer {
+        input: Some(SpectrometerInput::AD9361),
+        ..Default::default()
+    };
+    complete_spectrometer_patch(&mut patch, None);
+    assert_eq!(patch.output_sampling_frequency, None);
+    complete_spectrometer_patch(&mut patch, Some(Hertz(5.0)));
+    assert_eq!(patch.output_sampling_frequency, Some(Hertz(5.0)));
+}